@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use toy_dns_lib::packet::Packet;
+
+fuzz_target!(|packet: Packet| {
+    toy_dns_lib::fuzz::round_trip(packet);
+});
@@ -0,0 +1,118 @@
+use super::resolve::read_batch;
+use super::{parse_record_type, parse_timeout};
+use std::io::Write;
+use std::time::Duration;
+use toy_dns_lib::bench::BenchConfig;
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::{Upstream, DEFAULT_UDP_PORT};
+use toy_dns_lib::record::RecordType;
+
+#[cfg(test)]
+use crate::{parse_args, Args, Command};
+#[cfg(test)]
+use clap::Parser;
+
+/// Arguments for `toy_dns bench`
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// Server to send the queries to over UDP, e.g. 127.0.0.1 or [::1]:5353
+    #[arg(long, value_name = "SERVER")]
+    pub server: String,
+
+    /// Number of queries to send per second, whether or not the server keeps up
+    #[arg(long, value_name = "N", default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub qps: u32,
+
+    /// How long to send queries for, e.g. 10s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = parse_timeout)]
+    pub duration: Duration,
+
+    /// Query the names listed in FILE (- for stdin) in turn, one `name [type]` per line as with
+    /// --batch
+    #[arg(long, value_name = "FILE")]
+    pub names: String,
+
+    /// Type of the records to query for names listed without one
+    #[arg(short = 't', long = "type", value_name = "TYPE", default_value = "A", value_parser = parse_record_type)]
+    pub record_type: RecordType,
+
+    /// How long to wait for each response before counting the query as timed out
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+}
+
+/// Load a server with queries and print how it fared.
+///
+/// # Arguments
+/// * `args`: Arguments of the `bench` subcommand.
+/// * `stdout`: Where to print the report.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn bench(args: BenchArgs, stdout: &mut impl Write) -> i32 {
+    let server = match Upstream::parse_server(&args.server, DEFAULT_UDP_PORT) {
+        Ok(Upstream::Udp(address)) => address,
+        _ => {
+            eprintln!(
+                "Could not benchmark {}: expected an address to reach over UDP",
+                args.server
+            );
+            return DnsError::InvalidUpstream.exit_code();
+        }
+    };
+    let questions = match read_batch(&args.names, args.record_type) {
+        Ok(questions) => questions,
+        Err(message) => {
+            eprintln!("{}", message);
+            return DnsError::Batch.exit_code();
+        }
+    };
+
+    let config = BenchConfig {
+        server,
+        queries_per_second: args.qps,
+        duration: args.duration,
+        timeout: args.timeout,
+        questions,
+    };
+    match toy_dns_lib::bench::run(&config) {
+        Ok(report) => {
+            _ = write!(stdout, "{}", report.render());
+            0
+        }
+        Err(error) => {
+            eprintln!("Could not benchmark {}: {}", server, error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Validate parsing of the bench subcommand and its failures before any query is sent.
+#[test]
+fn test_parsing_bench_subcommand() {
+    let bench_with = |args: &[&str]| -> i32 {
+        let args = Args::parse_from(["toy_dns", "bench"].iter().chain(args));
+        let Command::Bench(bench_args) = args.command else {
+            panic!("Expected the bench subcommand");
+        };
+        bench(bench_args, &mut Vec::new())
+    };
+
+    let args = Args::parse_from(["toy_dns", "bench", "--server", "::1", "--names", "-"]);
+    let Command::Bench(bench_args) = args.command else {
+        panic!("Expected the bench subcommand");
+    };
+    assert_eq!(bench_args.qps, 100);
+    assert_eq!(bench_args.duration, Duration::from_secs(10));
+    assert_eq!(bench_args.timeout, Duration::from_secs(2));
+    assert_eq!(bench_args.record_type, RecordType::A);
+
+    let args = ["--server", "127.0.0.1", "--names", "/nonexistent/names.txt"];
+    assert_eq!(bench_with(&args), DnsError::Batch.exit_code());
+    let args = ["--server", "tls://127.0.0.1", "--names", "-"];
+    assert_eq!(bench_with(&args), DnsError::InvalidUpstream.exit_code());
+    assert!(parse_args(["toy_dns", "bench", "--names", "-"]).is_err());
+    assert!(
+        parse_args(["toy_dns", "bench", "--server", "::1", "--names", "-", "--qps", "0"]).is_err()
+    );
+}
@@ -0,0 +1,126 @@
+use clap::Subcommand;
+use std::io::Write;
+use toy_dns_lib::control::{self, ControlReply, DEFAULT_CONTROL_ADDRESS};
+use toy_dns_lib::errors::DnsError;
+
+#[cfg(test)]
+use crate::{parse_args, Args, Command};
+#[cfg(test)]
+use clap::Parser;
+#[cfg(test)]
+use std::net::TcpListener;
+#[cfg(test)]
+use std::sync::Arc;
+#[cfg(test)]
+use toy_dns_lib::cache::{Cache, CacheConfig};
+#[cfg(test)]
+use toy_dns_lib::control::Control;
+
+/// Arguments for `toy_dns cache`
+#[derive(clap::Args, Debug)]
+pub struct CacheArgs {
+    /// Address of the control socket of the server, as given to serve --control
+    #[arg(long, value_name = "ADDRESS", default_value = DEFAULT_CONTROL_ADDRESS)]
+    pub control: String,
+
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+/// Commands of `toy_dns cache`
+#[derive(Subcommand, Debug, PartialEq)]
+pub enum CacheCommand {
+    /// Print the cached responses, with the time each has left
+    Dump,
+
+    /// Print the cached responses for a name, whatever their record type
+    Get {
+        /// Domain name, e.g. example.com
+        name: String,
+    },
+
+    /// Remove the cached responses for a name, or every cached response
+    Flush {
+        /// Domain name, e.g. example.com
+        name: Option<String>,
+    },
+}
+
+/// Send a cache command to the control socket of a running server and print its output.
+///
+/// # Arguments
+/// * `args`: Arguments of the `cache` subcommand.
+/// * `stdout`: Where to print the output.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn cache(args: CacheArgs, stdout: &mut impl Write) -> i32 {
+    let command = match &args.command {
+        CacheCommand::Dump => "cache dump".to_owned(),
+        CacheCommand::Get { name } => format!("cache get {}", name),
+        CacheCommand::Flush { name: None } => "cache flush".to_owned(),
+        CacheCommand::Flush { name: Some(name) } => format!("cache flush {}", name),
+    };
+    match control::send_command(&args.control, &command) {
+        Ok(ControlReply::Done(output)) => {
+            _ = write!(stdout, "{}", output);
+            0
+        }
+        Ok(ControlReply::Refused(reason)) => {
+            eprintln!("The server refused {:?}: {}", command, reason);
+            DnsError::Control.exit_code()
+        }
+        Err(error) => {
+            eprintln!("Could not reach the server at {}: {}", args.control, error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Validate parsing of the cache subcommand and of --control, and that commands are sent to the
+/// control socket.
+#[test]
+fn test_parsing_cache_subcommand() {
+    let args = Args::parse_from(["toy_dns", "cache", "flush", "example.com"]);
+    let Command::Cache(cache_args) = args.command else {
+        panic!("Expected the cache subcommand");
+    };
+    assert_eq!(cache_args.control, DEFAULT_CONTROL_ADDRESS);
+    assert_eq!(
+        cache_args.command,
+        CacheCommand::Flush {
+            name: Some("example.com".to_owned())
+        }
+    );
+    assert!(parse_args(["toy_dns", "cache", "get"]).is_err());
+    assert!(parse_args(["toy_dns", "cache"]).is_err());
+
+    let args = Args::parse_from(["toy_dns", "serve", "--forward", "192.0.2.1", "--control"]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.control.as_deref(), Some(DEFAULT_CONTROL_ADDRESS));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let control = Control::new(vec![Arc::new(Cache::new(CacheConfig::default()))]);
+    std::thread::spawn(move || control.serve(listener));
+    let cache_with = |command: &[&str], stdout: &mut Vec<u8>| -> i32 {
+        let args = ["toy_dns", "cache", "--control", &address];
+        let args = Args::parse_from(args.iter().chain(command));
+        let Command::Cache(cache_args) = args.command else {
+            panic!("Expected the cache subcommand");
+        };
+        cache(cache_args, stdout)
+    };
+
+    let mut stdout = Vec::new();
+    assert_eq!(cache_with(&["dump"], &mut stdout), 0);
+    assert_eq!(String::from_utf8_lossy(&stdout), "; the cache is empty\n");
+    let mut stdout = Vec::new();
+    assert_eq!(cache_with(&["get", "example.com"], &mut stdout), 0);
+    assert_eq!(
+        String::from_utf8_lossy(&stdout),
+        "; example.com is not cached\n"
+    );
+}
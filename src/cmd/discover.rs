@@ -0,0 +1,234 @@
+use super::{parse_timeout, upstream_socket};
+use log::error;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::time::Duration;
+use toy_dns_lib::acl::Cidr;
+use toy_dns_lib::dnssd;
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::DEFAULT_UDP_PORT;
+use toy_dns_lib::open_resolver;
+use toy_dns_lib::resolver::Resolver;
+use toy_dns_lib::resolver_config::ResolverConfig;
+use toy_dns_lib::socket::DnsTransport;
+use toy_dns_lib::subdomains;
+use toy_dns_lib::wildcard;
+
+/// Arguments for `toy_dns browse`
+#[derive(clap::Args, Debug)]
+pub struct BrowseArgs {
+    /// Service type to browse, e.g. _ipp._tcp or _http._tcp.local
+    pub service_type: String,
+}
+
+/// Browse the local link for instances of a service and print them.
+///
+/// # Arguments
+/// * `args`: Arguments of the `browse` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the instances.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn browse(args: BrowseArgs, socket: &mut dyn DnsTransport, stdout: &mut impl Write) -> i32 {
+    match dnssd::browse(socket, &args.service_type, None) {
+        Ok(instances) => {
+            for instance in instances {
+                _ = write!(stdout, "{}", instance);
+            }
+            0
+        }
+        Err(error) => {
+            eprintln!("Could not browse {}: {}", args.service_type, error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Arguments for `toy_dns scan-resolvers`
+#[derive(clap::Args, Debug)]
+pub struct ScanResolversArgs {
+    /// Hosts to probe, as addresses or blocks in CIDR notation, e.g. 192.0.2.1 or 192.0.2.0/24
+    #[arg(required = true, value_parser = Cidr::parse)]
+    pub targets: Vec<Cidr>,
+
+    /// Port to send the queries to
+    #[arg(long, value_name = "PORT", default_value_t = DEFAULT_UDP_PORT)]
+    pub port: u16,
+
+    /// Name to ask the hosts to resolve
+    #[arg(long, value_name = "NAME", default_value = open_resolver::DEFAULT_PROBE_NAME)]
+    pub name: String,
+
+    /// Probe at most RATE hosts per second
+    #[arg(long, value_name = "RATE", default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate: u32,
+
+    /// How long to wait for each host to respond, e.g. 2s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "2s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Print every host probed, not only the open resolvers
+    #[arg(long, default_value_t = false)]
+    pub all: bool,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Most addresses `scan-resolvers` probes in one run, as many as in a /16 IPv4 block.
+const MAX_SCAN_ADDRESSES: u128 = 65_536;
+
+/// Probe hosts for open resolvers and print those found, or every host with `--all`.
+///
+/// # Arguments
+/// * `args`: Arguments of the `scan-resolvers` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the results.
+///
+/// # Return
+/// Returns the process exit code, 1 if open resolvers were found.
+pub fn scan_resolvers(
+    args: ScanResolversArgs,
+    socket: &mut dyn DnsTransport,
+    stdout: &mut impl Write,
+) -> i32 {
+    let count = args.targets.iter().fold(0u128, |count, target| {
+        count.saturating_add(target.address_count())
+    });
+    if count > MAX_SCAN_ADDRESSES {
+        eprintln!(
+            "Could not scan {} addresses: at most {} may be scanned at once",
+            count, MAX_SCAN_ADDRESSES
+        );
+        return DnsError::InvalidCidr.exit_code();
+    }
+
+    let addresses = args
+        .targets
+        .iter()
+        .flat_map(Cidr::addresses)
+        .map(|address| SocketAddr::new(address, args.port));
+    let results =
+        open_resolver::scan_resolvers(socket, addresses, &args.name, args.rate, args.rand_seed);
+    for result in results.iter().filter(|result| args.all || result.is_open()) {
+        _ = writeln!(stdout, "{}", result);
+    }
+    let open = results.iter().filter(|result| result.is_open()).count();
+    _ = writeln!(
+        stdout,
+        "{} of {} hosts answered recursively",
+        open,
+        results.len()
+    );
+    match open {
+        0 => 0,
+        _ => 1,
+    }
+}
+
+/// Arguments for `toy_dns enum`
+#[derive(clap::Args, Debug)]
+pub struct EnumArgs {
+    /// Domain to find names under, e.g. example.com
+    pub domain_name: String,
+
+    /// Try the labels listed in FILE (- for stdin), one per line, e.g. www
+    #[arg(long, value_name = "FILE")]
+    pub wordlist: String,
+
+    /// Number of names resolved at once
+    #[arg(long, value_name = "N", default_value_t = 10, value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: u16,
+
+    /// List names resolving to the addresses of the wildcard of the domain too
+    #[arg(long, default_value_t = false)]
+    pub no_wildcard_check: bool,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Find names under a domain from a wordlist and print their records.
+///
+/// # Arguments
+/// * `args`: Arguments of the `enum` subcommand.
+/// * `stdout`: Where to print the names found.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn enumerate(args: EnumArgs, stdout: &mut impl Write) -> i32 {
+    let contents = match args.wordlist.as_str() {
+        "-" => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map(|_| contents)
+        }
+        path => std::fs::read_to_string(path),
+    };
+    let Ok(contents) = contents else {
+        eprintln!("Could not read {}", args.wordlist);
+        return DnsError::Batch.exit_code();
+    };
+    let words = subdomains::parse_wordlist(&contents);
+
+    let mut sockets = Vec::with_capacity(args.concurrency as usize);
+    for _ in 0..args.concurrency {
+        match upstream_socket(None, Some(args.timeout)) {
+            Ok(socket) => sockets.push(socket),
+            Err(error) => {
+                error!("Failed to bind UDP socket to a local port. {}", error);
+                return error.exit_code();
+            }
+        }
+    }
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let wildcard = match args.no_wildcard_check {
+        true => None,
+        false => match wildcard::detect_wildcard(
+            &resolver,
+            &mut sockets[0],
+            &args.domain_name,
+            args.rand_seed,
+        ) {
+            Ok(wildcard) => wildcard,
+            Err(error) => {
+                eprintln!(
+                    "Could not probe {} for a wildcard: {}",
+                    args.domain_name, error
+                );
+                return error.exit_code();
+            }
+        },
+    };
+    if let Some(wildcard) = &wildcard {
+        _ = writeln!(
+            stdout,
+            "Wildcard {}, names only resolving to these are left out\n",
+            wildcard
+        );
+    }
+
+    let found = subdomains::enumerate_subdomains(
+        &resolver,
+        sockets,
+        &args.domain_name,
+        &words,
+        wildcard.as_ref(),
+        args.rand_seed,
+    );
+    for subdomain in &found {
+        _ = write!(stdout, "{}", subdomain);
+    }
+    _ = writeln!(stdout, "\n{} of {} names found", found.len(), words.len());
+    0
+}
@@ -0,0 +1,65 @@
+use super::parse_timeout;
+use std::io::Write;
+use std::time::Duration;
+use toy_dns_lib::mailcheck;
+use toy_dns_lib::resolver::Resolver;
+use toy_dns_lib::resolver_config::ResolverConfig;
+use toy_dns_lib::socket::DnsTransport;
+
+/// Arguments for `toy_dns mailcheck`
+#[derive(clap::Args, Debug)]
+pub struct MailcheckArgs {
+    /// Domain whose mail setup to check, e.g. example.com
+    pub domain_name: String,
+
+    /// Also check the DKIM key of this selector, e.g. mail for mail._domainkey.example.com
+    #[arg(long, value_name = "SELECTOR")]
+    pub dkim_selector: Option<String>,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Check the mail setup of a domain and print the report.
+///
+/// # Arguments
+/// * `args`: Arguments of the `mailcheck` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the report.
+///
+/// # Return
+/// Returns the process exit code, 1 if a check failed.
+pub fn mailcheck(
+    args: MailcheckArgs,
+    socket: &mut dyn DnsTransport,
+    stdout: &mut impl Write,
+) -> i32 {
+    let resolver = Resolver::new(ResolverConfig::default());
+    match mailcheck::check_mail(
+        &resolver,
+        socket,
+        &args.domain_name,
+        args.dkim_selector.as_deref(),
+        args.rand_seed,
+    ) {
+        Ok(report) => {
+            _ = write!(stdout, "{}", report.render());
+            match report.passed() {
+                true => 0,
+                false => 1,
+            }
+        }
+        Err(error) => {
+            eprintln!(
+                "Could not check the mail setup of {}: {}",
+                args.domain_name, error
+            );
+            error.exit_code()
+        }
+    }
+}
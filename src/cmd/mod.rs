@@ -0,0 +1,146 @@
+pub mod bench;
+pub mod cache;
+pub mod discover;
+pub mod mailcheck;
+pub mod parse;
+pub mod resolve;
+pub mod serve;
+pub mod zone;
+
+use log::error;
+use std::net::SocketAddr;
+use std::time::Duration;
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::{Upstream, UpstreamSocket};
+use toy_dns_lib::query_log::{QueryLog, QueryLogFormat};
+use toy_dns_lib::record::RecordType;
+use toy_dns_lib::socket::local_address;
+use toy_dns_lib::upstream_pool::PoolStrategy;
+
+/// How `--upstream-strategy` chooses the upstream to ask first
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum UpstreamStrategy {
+    /// The first one that is responding, in the order given
+    Failover,
+
+    /// Each one in turn
+    RoundRobin,
+
+    /// The one that has been responding the fastest
+    Fastest,
+}
+
+impl From<UpstreamStrategy> for PoolStrategy {
+    fn from(strategy: UpstreamStrategy) -> Self {
+        match strategy {
+            UpstreamStrategy::Failover => PoolStrategy::Failover,
+            UpstreamStrategy::RoundRobin => PoolStrategy::RoundRobin,
+            UpstreamStrategy::Fastest => PoolStrategy::Fastest,
+        }
+    }
+}
+
+/// How entries of `--query-log` are written
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// One line of text per query
+    Text,
+
+    /// One JSON object per line
+    Json,
+}
+
+/// Open the query log given with `--query-log`.
+///
+/// # Arguments
+/// * `path`: The path of the log file, or `-` for stderr.
+/// * `format`: How to write the entries.
+pub fn open_query_log(path: &str, format: LogFormat) -> Result<QueryLog, DnsError> {
+    let format = match format {
+        LogFormat::Text => QueryLogFormat::Text,
+        LogFormat::Json => QueryLogFormat::Json,
+    };
+    QueryLog::open(path, format)
+}
+
+/// The address of a server reached over plain DNS, e.g. given with `--server`, or `None` for
+/// servers reached over TLS or HTTPS and servers which do not parse.
+///
+/// # Arguments
+/// * `server`: The server, if any.
+/// * `port`: The port of servers given without one.
+pub fn server_address(server: Option<&str>, port: u16) -> Option<SocketAddr> {
+    match Upstream::parse_server(server?, port) {
+        Ok(Upstream::Udp(address)) => Some(address),
+        _ => None,
+    }
+}
+
+/// Bind a socket to send queries to the given server from, on the address family of the server,
+/// and set how long it waits for responses.
+///
+/// # Arguments
+/// * `server`: The server queries are sent to, if known up front.
+/// * `timeout`: How long to wait for a server to respond, or `None` for the default.
+pub fn upstream_socket(
+    server: Option<SocketAddr>,
+    timeout: Option<Duration>,
+) -> Result<UpstreamSocket, DnsError> {
+    let mut socket = UpstreamSocket::bind(local_address(server))?;
+    if let Some(timeout) = timeout {
+        socket.set_timeout(timeout)?;
+    }
+    Ok(socket)
+}
+
+/// Run a subcommand with a socket from `upstream_socket()`.
+///
+/// # Arguments
+/// * `server`: The server queries are sent to, if known up front.
+/// * `timeout`: How long to wait for a server to respond, or `None` for the default.
+/// * `command`: The subcommand, returning the process exit code.
+///
+/// # Return
+/// Returns the exit code of the subcommand, or of the error binding the socket.
+pub fn with_upstream_socket(
+    server: Option<SocketAddr>,
+    timeout: Option<Duration>,
+    command: impl FnOnce(&mut UpstreamSocket) -> i32,
+) -> i32 {
+    match upstream_socket(server, timeout) {
+        Ok(mut socket) => command(&mut socket),
+        Err(error) => {
+            error!("Failed to bind UDP socket to a local port. {}", error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Parse the record type given with `--type`.
+///
+/// # Argument
+/// * `name`: The mnemonic of the record type, e.g. "AAAA".
+pub fn parse_record_type(name: &str) -> Result<RecordType, String> {
+    match RecordType::from_name(name) {
+        Some(RecordType::OPT) | None => Err(format!("unknown record type {}", name)),
+        Some(record_type) => Ok(record_type),
+    }
+}
+
+/// Parse the duration given with `--timeout`: a number of seconds or milliseconds, e.g. "3s",
+/// "1.5s" or "500ms". Seconds are assumed without a unit.
+///
+/// # Argument
+/// * `duration`: The duration to parse.
+pub fn parse_timeout(duration: &str) -> Result<Duration, String> {
+    let (value, seconds_per_unit) = match duration.strip_suffix("ms") {
+        Some(milliseconds) => (milliseconds, 0.001),
+        None => (duration.strip_suffix('s').unwrap_or(duration), 1.0),
+    };
+    match value.parse::<f64>() {
+        Ok(value) if value > 0.0 && value.is_finite() => {
+            Ok(Duration::from_secs_f64(value * seconds_per_unit))
+        }
+        _ => Err(format!("invalid duration {}", duration)),
+    }
+}
@@ -0,0 +1,205 @@
+use clap::ArgGroup;
+use data_encoding::{BASE64_NOPAD, HEXLOWER_PERMISSIVE};
+use std::io::{Read, Write};
+use toy_dns_lib::dig::{self, Sections};
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::json;
+use toy_dns_lib::packet::Packet;
+use toy_dns_lib::pcap;
+
+#[cfg(test)]
+use crate::{parse_args, Args, Command};
+#[cfg(test)]
+use clap::Parser;
+
+/// Arguments for `toy_dns parse`
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("dump").required(true).args(["hex", "base64", "file", "pcap"])))]
+pub struct ParseArgs {
+    /// The message in hexadecimal, e.g. as copied from Wireshark. Whitespace and colons between
+    /// the octets are ignored
+    #[arg(long, value_name = "BYTES")]
+    pub hex: Option<String>,
+
+    /// The message in base64, with the standard or the URL-safe alphabet as in the dns parameter
+    /// of DNS over HTTPS. Whitespace and padding are ignored
+    #[arg(long, value_name = "BYTES")]
+    pub base64: Option<String>,
+
+    /// Read the message as raw octets from FILE (- for stdin)
+    #[arg(long, value_name = "FILE")]
+    pub file: Option<String>,
+
+    /// Decode every message sent to or from port 53 over UDP or TCP in FILE, a capture in the
+    /// pcap or pcapng format (- for stdin)
+    #[arg(long, value_name = "FILE")]
+    pub pcap: Option<String>,
+
+    /// Print the message as a JSON object instead, one line per message of a capture
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+}
+
+/// Read the octets of the message given to `toy_dns parse`.
+///
+/// # Argument
+/// * `args`: Arguments of the `parse` subcommand.
+fn read_dump(args: &ParseArgs) -> Result<Vec<u8>, String> {
+    let compact = |text: &str, ignored: &[char]| -> String {
+        text.chars()
+            .filter(|character| !character.is_whitespace() && !ignored.contains(character))
+            .collect()
+    };
+    if let Some(hex) = &args.hex {
+        return HEXLOWER_PERMISSIVE
+            .decode(compact(hex, &[':']).as_bytes())
+            .map_err(|error| format!("Invalid hexadecimal: {}", error));
+    }
+    if let Some(base64) = &args.base64 {
+        let base64 = compact(base64, &['=']).replace('-', "+").replace('_', "/");
+        return BASE64_NOPAD
+            .decode(base64.as_bytes())
+            .map_err(|error| format!("Invalid base64: {}", error));
+    }
+    let path = args.file.as_deref().or(args.pcap.as_deref()).unwrap_or("-");
+    let mut dump = Vec::new();
+    let result = match path {
+        "-" => std::io::stdin().read_to_end(&mut dump),
+        _ => std::fs::File::open(path).and_then(|mut file| file.read_to_end(&mut dump)),
+    };
+    match result {
+        Ok(_) => Ok(dump),
+        Err(error) => Err(format!("Could not read {}: {}", path, error)),
+    }
+}
+
+/// Decode a captured message and print it like dig would, or as JSON.
+///
+/// # Arguments
+/// * `args`: Arguments of the `parse` subcommand.
+/// * `stdout`: Where to print the message.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn parse(args: ParseArgs, stdout: &mut impl Write) -> i32 {
+    let dump = match read_dump(&args) {
+        Ok(dump) => dump,
+        Err(message) => {
+            eprintln!("{}", message);
+            return DnsError::Dump.exit_code();
+        }
+    };
+    if args.pcap.is_some() {
+        return parse_capture(&dump, args.json, stdout);
+    }
+    let packet = match Packet::parse(&dump) {
+        Ok(packet) => packet,
+        Err(error) => {
+            eprintln!("Could not decode the message: {}", error);
+            return error.exit_code();
+        }
+    };
+    if args.json {
+        _ = writeln!(stdout, "{}", json::packet_to_json(&packet));
+        return 0;
+    }
+    _ = write!(stdout, "{}", dig::render(&packet, &Sections::default()));
+    _ = writeln!(stdout, "\n;; MSG SIZE  rcvd: {}", dump.len());
+    0
+}
+
+/// Decode the messages found in a capture and print each along with where and when it was
+/// captured.
+///
+/// # Arguments
+/// * `capture`: The contents of the capture file.
+/// * `json_output`: Whether to print the messages as JSON objects.
+/// * `stdout`: Where to print the messages.
+///
+/// # Return
+/// Returns the process exit code. 0 on success, or else the exit code of the first message which
+/// could not be decoded.
+fn parse_capture(capture: &[u8], json_output: bool, stdout: &mut impl Write) -> i32 {
+    let messages = match pcap::read_messages(capture) {
+        Ok(messages) => messages,
+        Err(error) => {
+            eprintln!("Could not read the capture: {}", error);
+            return error.exit_code();
+        }
+    };
+
+    let mut exit_code = 0;
+    for (index, message) in messages.iter().enumerate() {
+        let result = Packet::parse(&message.data);
+        if json_output {
+            _ = writeln!(stdout, "{}", json::captured_message(message, &result));
+        } else {
+            if index > 0 {
+                _ = writeln!(stdout);
+            }
+            _ = writeln!(
+                stdout,
+                ";; {} {} -> {} over {}",
+                message.captured_at(),
+                message.source,
+                message.destination,
+                message.transport
+            );
+            match &result {
+                Ok(packet) => {
+                    _ = write!(stdout, "{}", dig::render(packet, &Sections::default()));
+                    _ = writeln!(stdout, "\n;; MSG SIZE  rcvd: {}", message.data.len());
+                }
+                Err(error) => _ = writeln!(stdout, ";; Could not decode the message: {}", error),
+            }
+        }
+        if let (Err(error), 0) = (&result, exit_code) {
+            exit_code = error.exit_code();
+        }
+    }
+    exit_code
+}
+
+/// Validate decoding of captured messages given in hexadecimal or base64.
+#[test]
+fn test_parsing_message_dumps() {
+    let parse_with = |args: &[&str]| -> (i32, String) {
+        let args = Args::parse_from(["toy_dns", "parse"].iter().chain(args));
+        let Command::Parse(parse_args) = args.command else {
+            panic!("Expected the parse subcommand");
+        };
+        let mut stdout: Vec<u8> = Vec::new();
+        let exit_code = parse(parse_args, &mut stdout);
+        (exit_code, String::from_utf8(stdout).unwrap())
+    };
+
+    let expected = "\
+;; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660
+;; flags: qr rd ra; QUERY: 1, ANSWER: 1, AUTHORITY: 0, ADDITIONAL: 0
+
+;; QUESTION SECTION:
+;example.com. IN A
+
+;; ANSWER SECTION:
+example.com. 3600 IN A 192.0.2.1
+
+;; MSG SIZE  rcvd: 45
+";
+    let hex = "12:34:81:80:00:01:00:01:00:00:00:00 076578616d706c6503636f6d00 00010001 \
+               C00C 0001 0001 00000e10 0004 c0000201";
+    assert_eq!(parse_with(&["--hex", hex]), (0, expected.to_owned()));
+    let base64 = "EjSBgAABAAEAAAAAB2V4YW1wbGUDY29tAAABAAHADAABAAEAAA4QAATAAAIB";
+    assert_eq!(parse_with(&["--base64", base64]), (0, expected.to_owned()));
+
+    let (exit_code, output) = parse_with(&["--json", "--base64", base64]);
+    assert_eq!(exit_code, 0);
+    assert!(output.starts_with(r#"{"id":4660,"flags":["qr","rd","ra"],"rcode":"NOERROR","#));
+
+    assert_eq!(parse_with(&["--hex", "zz"]).0, DnsError::Dump.exit_code());
+    assert_eq!(
+        parse_with(&["--hex", "1234"]).0,
+        DnsError::ParseFlag(None).exit_code()
+    );
+    assert!(parse_args(["toy_dns", "parse"]).is_err());
+    assert!(parse_args(["toy_dns", "parse", "--hex", "12", "--base64", "EjQ="]).is_err());
+}
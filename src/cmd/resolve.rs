@@ -0,0 +1,1026 @@
+use super::{
+    open_query_log, parse_record_type, parse_timeout, server_address, LogFormat, UpstreamStrategy,
+};
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use toy_dns_lib::dig::{self, Sections};
+use toy_dns_lib::edns::PaddingPolicy;
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::{Upstream, DEFAULT_UDP_PORT};
+use toy_dns_lib::graphviz;
+use toy_dns_lib::header::RCODE_NAME_ERROR;
+use toy_dns_lib::hosts::{HostsFile, DEFAULT_HOSTS_PATH};
+use toy_dns_lib::json;
+use toy_dns_lib::packet::Packet;
+use toy_dns_lib::query::QueryOptions;
+use toy_dns_lib::query_log::{QueryLog, QueryLogEntry};
+use toy_dns_lib::record::RecordType;
+use toy_dns_lib::resolver::{reverse_name, Resolver};
+use toy_dns_lib::resolver_config::ResolverConfig;
+use toy_dns_lib::socket::{self, DnsTransport};
+use toy_dns_lib::throttle::{QueryThrottle, ThrottleConfig};
+use toy_dns_lib::trace::Trace;
+use toy_dns_lib::upstream_pool::UpstreamPool;
+
+#[cfg(test)]
+use crate::{parse_args, Command};
+#[cfg(test)]
+use toy_dns_lib::mock_data;
+#[cfg(test)]
+use toy_dns_lib::socket::MockSocket;
+
+/// Arguments for `toy_dns resolve` and `toy_dns trace`
+#[derive(clap::Args, Debug)]
+pub struct ResolveArgs {
+    /// Domain names to query, each resolved in turn
+    #[arg(required_unless_present_any = ["batch", "reverse"])]
+    pub domain_names: Vec<String>,
+
+    /// Look up the host names of ADDRESS with a PTR query for its in-addr.arpa or ip6.arpa name
+    /// (may be repeated)
+    #[arg(short = 'x', value_name = "ADDRESS")]
+    pub reverse: Vec<IpAddr>,
+
+    /// Also resolve the names listed in FILE (- for stdin), one `name [type]` per line. Empty
+    /// lines and lines starting with # are skipped
+    #[arg(long, value_name = "FILE")]
+    pub batch: Option<String>,
+
+    /// Type of the records to query, e.g. AAAA, MX, ANY or TYPE65
+    #[arg(short = 't', long = "type", value_name = "TYPE", default_value = "A", value_parser = parse_record_type)]
+    pub record_type: RecordType,
+
+    /// Send the query to SERVER, e.g. 8.8.8.8 or dns.google:853 for DNS over TLS, instead of
+    /// resolving it from the root servers (also accepted as @SERVER)
+    #[arg(long, value_name = "SERVER")]
+    pub server: Option<String>,
+
+    /// Send the query to the name servers of the system resolver configuration with the RD bit
+    /// set, leaving the resolution to them
+    #[arg(long, default_value_t = false, conflicts_with = "server")]
+    pub stub: bool,
+
+    /// How to choose which name server to ask first with --stub. Name servers which stop
+    /// responding are skipped
+    #[arg(long, value_enum, default_value_t = UpstreamStrategy::Failover, requires = "stub")]
+    pub upstream_strategy: UpstreamStrategy,
+
+    /// Port to send queries to, for servers given with --server without one and the name servers
+    /// of --stub
+    #[arg(short, long, default_value_t = DEFAULT_UDP_PORT)]
+    pub port: u16,
+
+    /// Send queries over TCP rather than UDP, keeping connections open while servers allow (also
+    /// accepted as +tcp)
+    #[arg(long, default_value_t = false)]
+    pub tcp: bool,
+
+    /// Send queries over IPv6: bind to [::]:0, start resolutions at the IPv6 addresses of the root
+    /// servers, falling back to their IPv4 addresses when there is no IPv6 route, and prefer AAAA
+    /// glue. IPv4 servers remain reachable at their IPv4-mapped addresses
+    #[arg(short = '6', long = "ipv6-transport", default_value_t = false)]
+    pub ipv6_transport: bool,
+
+    /// Send queries from ADDRESS, a local IP address with an optional port, e.g. 192.0.2.10 or
+    /// [2001:db8::10]:5353, to choose the path queries take on multi-homed hosts. Only applies to
+    /// UDP
+    #[arg(short = 'b', long = "source", value_name = "ADDRESS", value_parser = parse_source_address)]
+    pub source: Option<SocketAddr>,
+
+    /// Send queries through the network interface NAME, e.g. tun0 for a VPN, whatever the routing
+    /// table says. Only applies to UDP, and is only supported on Linux
+    #[arg(long, value_name = "NAME")]
+    pub interface: Option<String>,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// How many more times to send a query to a server which does not respond
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    pub retries: u8,
+
+    /// Send at most RATE queries per second to each server on average, holding queries back
+    /// until their turn, e.g. to spare the servers asked by a large --batch
+    #[arg(long, value_name = "RATE", value_parser = clap::value_parser!(u32).range(1..))]
+    pub query_rate: Option<u32>,
+
+    /// Record every message sent to and received from servers in FILE, a capture in the pcap
+    /// format to inspect later, e.g. with Wireshark or `toy_dns parse --pcap`
+    #[arg(long, value_name = "FILE")]
+    pub capture: Option<String>,
+
+    /// Record every exchange with servers in FILE, as a fixture for mock sockets in tests, or as
+    /// Rust source if FILE ends with .rs. Replaying the exchanges takes the same --rand-seed
+    #[arg(long, value_name = "FILE")]
+    pub record_fixture: Option<String>,
+
+    /// Log each resolution to FILE (- for stderr), with its response code, number of answers,
+    /// how long it took and the servers queried
+    #[arg(long, value_name = "FILE")]
+    pub query_log: Option<String>,
+
+    /// Format of the entries of the query log
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, requires = "query_log")]
+    pub query_log_format: LogFormat,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+
+    /// Apply the search list and ndots option of the system resolver configuration
+    #[arg(long, default_value_t = false)]
+    pub search: bool,
+
+    /// Answer names found in a hosts file (/etc/hosts unless given) without querying the network
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = DEFAULT_HOSTS_PATH)]
+    pub hosts: Option<String>,
+
+    /// Set the DO bit to request DNSSEC records (also accepted as +dnssec)
+    #[arg(long, default_value_t = false)]
+    pub dnssec: bool,
+
+    /// Set the CD bit to disable DNSSEC validation upstream (also accepted as +cdflag)
+    #[arg(long, default_value_t = false)]
+    pub cdflag: bool,
+
+    /// Ask the answering server to identify itself with NSID (also accepted as +nsid)
+    #[arg(long, default_value_t = false)]
+    pub nsid: bool,
+
+    /// Pad queries over TLS and HTTPS to a multiple of BLOCK octets, 128 unless given (also
+    /// accepted as +padding). Queries over UDP and TCP are never padded
+    #[arg(long, value_name = "BLOCK", num_args = 0..=1, default_missing_value = "128")]
+    pub padding: Option<u16>,
+
+    /// Print the result of each name as a JSON object on its own line, with the header flags,
+    /// all four sections, typed record data, timings and the servers queried
+    #[arg(long, default_value_t = false)]
+    pub json: bool,
+
+    /// Print only the data of the answers, one per line, e.g. just the addresses (also accepted
+    /// as +short)
+    #[arg(long, default_value_t = false, conflicts_with = "json")]
+    pub short: bool,
+
+    /// Print each server queried along the way, with the referral or answer records it returned
+    /// and how long it took, instead of the response (also accepted as +trace)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["json", "short"])]
+    pub trace: bool,
+
+    /// Print the path of each resolution as a JSON object on its own line: every server queried,
+    /// what it was asked and the referral or answer it returned
+    #[arg(long, default_value_t = false, conflicts_with_all = ["json", "short", "trace"])]
+    pub trace_json: bool,
+
+    /// Print the path of each resolution as a graph in the DOT language of Graphviz, from the
+    /// root servers to the answer, e.g. to render with `dot -Tsvg`
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["json", "short", "trace", "trace_json"]
+    )]
+    pub trace_dot: bool,
+
+    /// Print statistics after each result: the queries sent, the bytes they put on the wire, the
+    /// round-trip times of each server, cache hits and the total time (also accepted as +stats)
+    #[arg(long, default_value_t = false, conflicts_with_all = ["json", "short", "trace_json", "trace_dot"])]
+    pub stats: bool,
+
+    /// Print internationalized names in Unicode rather than as the xn-- A-labels sent on the wire
+    /// (also accepted as +idnout)
+    #[arg(long, default_value_t = false)]
+    pub idnout: bool,
+
+    /// How a failed resolution is reported on stderr
+    #[arg(long, value_enum, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// Turn a part of the output on or off, applied in order: all, comments, question, answer,
+    /// authority or additional, prefixed with "no" to turn it off (also accepted as +noall,
+    /// +answer and so on)
+    #[arg(long = "section", value_name = "TOGGLE", value_parser = parse_section_toggle)]
+    pub sections: Vec<String>,
+}
+
+/// How failures are reported with `--error-format`
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    /// A sentence naming the domain and the error
+    Text,
+
+    /// A JSON object with the error code, message, server, stage and whether it may be retried
+    Json,
+}
+
+/// Parse the address given with `--source`: an IP address, with a port or without one to let the
+/// system pick it.
+///
+/// # Argument
+/// * `address`: The address to parse, e.g. "192.0.2.10" or "[2001:db8::10]:5353".
+fn parse_source_address(address: &str) -> Result<SocketAddr, String> {
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 0));
+    }
+    match address.parse::<SocketAddr>() {
+        Ok(address) => Ok(address),
+        Err(_) => Err(format!("invalid source address {}", address)),
+    }
+}
+
+/// Parse a toggle given with `--section`.
+///
+/// # Argument
+/// * `toggle`: The toggle, e.g. "noall" or "answer".
+fn parse_section_toggle(toggle: &str) -> Result<String, String> {
+    match Sections::is_toggle(toggle) {
+        true => Ok(toggle.to_owned()),
+        false => Err(format!("unknown section toggle {}", toggle)),
+    }
+}
+
+/// Run toy_dns with given arguments and logging level.
+///
+/// # Argument
+/// * `args`: CLI arguments.
+/// * `socket`: The socket (in a `Box`) to run toy_dns queries through.
+/// * `stdout`: stdout to write to.
+///
+/// # Return
+/// Returns the process exit code. 0 on success, or else the exit code of the first domain name
+/// which could not be resolved.
+pub fn run(args: ResolveArgs, socket: &mut dyn DnsTransport, stdout: &mut impl Write) -> i32 {
+    let config = match args.search || args.stub {
+        true => match ResolverConfig::from_system() {
+            Ok(config) if args.search => config,
+            // Only the name servers matter to a stub resolver without the search list.
+            Ok(config) => ResolverConfig {
+                name_servers: config.name_servers,
+                ..Default::default()
+            },
+            Err(error) => {
+                eprintln!(
+                    "Could not read the system resolver configuration: {}",
+                    error
+                );
+                return error.exit_code();
+            }
+        },
+        false => ResolverConfig::default(),
+    };
+
+    let mut resolver = match args.stub {
+        true => Resolver::stub(config, args.port),
+        false => Resolver::new(config),
+    };
+    if args.stub {
+        resolver.pool = Some(Arc::new(UpstreamPool::new(args.upstream_strategy.into())));
+    }
+    resolver.query_options = QueryOptions {
+        dnssec_ok: args.dnssec,
+        checking_disabled: args.cdflag,
+        nsid: args.nsid,
+        padding: match args.padding {
+            Some(block_length) => PaddingPolicy::BlockLength(block_length),
+            None => PaddingPolicy::Disabled,
+        },
+        retries: args.retries,
+        tcp_keepalive: args.tcp,
+        throttle: args
+            .query_rate
+            .map(|rate| QueryThrottle::new(ThrottleConfig::new(rate))),
+        ipv6: args.ipv6_transport,
+        ..resolver.query_options.clone()
+    };
+    if let Some(server) = &args.server {
+        match Upstream::parse_server(server, args.port) {
+            Ok(upstream) => resolver.servers = vec![upstream.to_string()],
+            Err(error) => {
+                eprintln!("Could not query {}: {}", server, error);
+                return error.exit_code();
+            }
+        }
+        // Like dig, ask the server to do the resolution.
+        resolver.query_options.recursion_desired = true;
+    }
+    if args.json || args.trace || args.trace_json || args.trace_dot || args.query_log.is_some() {
+        resolver.query_options.trace = Some(Trace::default());
+    }
+    let query_log = match &args.query_log {
+        Some(path) => match open_query_log(path, args.query_log_format) {
+            Ok(query_log) => Some(query_log),
+            Err(error) => {
+                eprintln!("Could not open {}: {}", path, error);
+                return error.exit_code();
+            }
+        },
+        None => None,
+    };
+    if let Some(path) = &args.hosts {
+        match HostsFile::from_file(path) {
+            Ok(hosts) => resolver.hosts = Some(hosts),
+            Err(error) => {
+                eprintln!("Could not read {}: {}", path, error);
+                return error.exit_code();
+            }
+        }
+    }
+
+    // clap requires a domain name, an address or a batch file unless a subcommand is given.
+    let mut questions: Vec<(String, RecordType)> = args
+        .domain_names
+        .iter()
+        .map(|domain_name| (domain_name.clone(), args.record_type))
+        .chain(
+            args.reverse
+                .iter()
+                .map(|address| (reverse_name(*address), RecordType::PTR)),
+        )
+        .collect();
+    if let Some(path) = &args.batch {
+        match read_batch(path, args.record_type) {
+            Ok(batch) => questions.extend(batch),
+            Err(message) => {
+                eprintln!("{}", message);
+                return DnsError::Batch.exit_code();
+            }
+        }
+    }
+
+    let mut exit_code = 0;
+    for (index, (domain_name, record_type)) in questions.iter().enumerate() {
+        // Results are only told apart when there are several of them, and JSON and short
+        // results are meant for scripts.
+        if questions.len() > 1 && !args.json && !args.trace_json && !args.trace_dot && !args.short {
+            if index > 0 {
+                _ = writeln!(stdout);
+            }
+            _ = writeln!(stdout, "Results for {}:", domain_name);
+        }
+        let result = lookup(
+            &args,
+            &resolver,
+            socket,
+            domain_name,
+            *record_type,
+            query_log.as_ref(),
+            stdout,
+        );
+        if exit_code == 0 {
+            exit_code = result;
+        }
+    }
+    exit_code
+}
+
+/// Read and parse a batch file, see `parse_batch()`.
+///
+/// # Arguments
+/// * `path`: The path of the batch file, or `-` for stdin.
+/// * `default_type`: The record type of names given without one.
+pub fn read_batch(
+    path: &str,
+    default_type: RecordType,
+) -> Result<Vec<(String, RecordType)>, String> {
+    let contents = match path {
+        "-" => {
+            let mut contents = String::new();
+            std::io::stdin()
+                .read_to_string(&mut contents)
+                .map(|_| contents)
+        }
+        _ => std::fs::read_to_string(path),
+    };
+    let Ok(contents) = contents else {
+        return Err(format!("Could not read {}", path));
+    };
+    parse_batch(&contents, default_type)
+        .map_err(|message| format!("Could not parse {}: {}", path, message))
+}
+
+/// Parse the contents of a batch file: one domain name per line, optionally followed by the type
+/// of the records to query. Empty lines and lines starting with `#` are skipped.
+///
+/// # Arguments
+/// * `contents`: The contents of the batch file.
+/// * `default_type`: The record type of names given without one.
+fn parse_batch(
+    contents: &str,
+    default_type: RecordType,
+) -> Result<Vec<(String, RecordType)>, String> {
+    let mut questions = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let mut fields = line.split_whitespace();
+        let Some(domain_name) = fields.next().filter(|field| !field.starts_with('#')) else {
+            continue;
+        };
+        let record_type = match fields.next() {
+            Some(name) => parse_record_type(name)
+                .map_err(|message| format!("line {}: {}", index + 1, message))?,
+            None => default_type,
+        };
+        if fields.next().is_some() {
+            return Err(format!("line {}: expected a name and a type", index + 1));
+        }
+        questions.push((domain_name.to_owned(), record_type));
+    }
+    Ok(questions)
+}
+
+/// Resolve a single domain name and print the answers.
+///
+/// # Arguments
+/// * `args`: CLI arguments.
+/// * `resolver`: The resolver to resolve the name with.
+/// * `socket`: The socket (in a `Box`) to run toy_dns queries through.
+/// * `domain_name`: The domain name to resolve.
+/// * `record_type`: The type of the records to query.
+/// * `query_log`: Where to log the resolution, if anywhere.
+/// * `stdout`: stdout to write to.
+///
+/// # Return
+/// Returns the exit code for the domain name. 0 on success.
+fn lookup(
+    args: &ResolveArgs,
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    record_type: RecordType,
+    query_log: Option<&QueryLog>,
+    stdout: &mut impl Write,
+) -> i32 {
+    let (result, stats) =
+        resolver.resolve_with_stats(socket, domain_name, record_type, args.rand_seed);
+    let elapsed = stats.elapsed;
+    let exchanges = match &resolver.query_options.trace {
+        Some(trace) => trace.take(),
+        None => Vec::new(),
+    };
+    if let Some(query_log) = query_log {
+        query_log.log(&QueryLogEntry::new(
+            domain_name,
+            record_type,
+            result.as_ref(),
+            elapsed,
+            &exchanges,
+        ));
+    }
+
+    if args.json || args.trace_json {
+        let rendered = match args.json {
+            true => json::resolution(domain_name, record_type, &result, &exchanges, elapsed),
+            false => json::delegation_path(domain_name, record_type, &result, &exchanges, elapsed),
+        };
+        _ = writeln!(stdout, "{}", rendered);
+        return exit_code(&result);
+    }
+
+    if args.trace_dot {
+        _ = write!(
+            stdout,
+            "{}",
+            graphviz::delegation_graph(domain_name, record_type, &result, &exchanges)
+        );
+        return exit_code(&result);
+    }
+
+    let idn_out = |output: String| match args.idnout {
+        true => dig::idn_out(&output),
+        false => output,
+    };
+    if args.trace {
+        let rendered = dig::render_trace(domain_name, record_type, &exchanges);
+        _ = write!(stdout, "{}", idn_out(rendered));
+    }
+    let exit_code = exit_code(&result);
+    match result {
+        Ok(_) if args.trace => (),
+        Ok(packet) if args.short => {
+            _ = write!(stdout, "{}", idn_out(dig::render_short(&packet)));
+        }
+        Ok(packet) => {
+            let mut sections = Sections::default();
+            for toggle in &args.sections {
+                sections.toggle(toggle);
+            }
+            _ = write!(stdout, "{}", idn_out(dig::render(&packet, &sections)));
+        }
+        Err(error) => match args.error_format {
+            ErrorFormat::Text => {
+                eprintln!("DNS request for {} failed with {}", domain_name, error)
+            }
+            ErrorFormat::Json => {
+                eprintln!("{}", json::error(domain_name, record_type, &error))
+            }
+        },
+    }
+    if args.stats {
+        _ = write!(stdout, "\n{}", stats.render());
+    }
+    exit_code
+}
+
+/// The local address to send queries from: the one given with --source, or else the unspecified
+/// address of the family of the server given with --server, or of IPv6 with --ipv6-transport.
+///
+/// # Argument
+/// * `args`: Arguments of the `resolve` subcommand.
+pub fn local_address(args: &ResolveArgs) -> String {
+    if let Some(source) = args.source {
+        return source.to_string();
+    }
+    match args.ipv6_transport {
+        true => "[::]:0".to_owned(),
+        false => {
+            socket::local_address(server_address(args.server.as_deref(), args.port)).to_owned()
+        }
+    }
+}
+
+/// The exit code of a lookup. A response telling that the name does not exist is printed like any
+/// other, e.g. from a server given with --server, but fails the lookup just as an
+/// `UnknownDomainName` error from a resolution does.
+///
+/// # Argument
+/// * `result`: The outcome of the lookup.
+fn exit_code(result: &Result<Packet, DnsError>) -> i32 {
+    match result {
+        Ok(packet) if packet.rcode() == RCODE_NAME_ERROR => DnsError::UnknownDomainName.exit_code(),
+        Ok(_) => 0,
+        Err(error) => error.exit_code(),
+    }
+}
+
+/// Build the arguments of the resolve subcommand from a command line, as a test would give them.
+/// Options left out take their defaults, and the subcommand may be left implicit.
+///
+/// # Argument
+/// * `args`: The command line, program name first.
+#[cfg(test)]
+pub fn resolve_args<const N: usize>(args: [&str; N]) -> ResolveArgs {
+    match parse_args(args).unwrap().command {
+        Command::Resolve(args) => *args,
+        command => panic!("Expected the resolve subcommand, got {:?}", command),
+    }
+}
+
+/// Validate running the program with twitter.com
+#[test]
+fn test_running_toy_dns() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "twitter.com"]);
+
+    let data = mock_data::captured_data_for_twitter();
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 15212\n\
+         ;; flags: qr aa; QUERY: 1, ANSWER: 1, AUTHORITY: 8, ADDITIONAL: 0\n\
+         \n\
+         ;; QUESTION SECTION:\n\
+         ;twitter.com. IN A\n\
+         \n\
+         ;; ANSWER SECTION:\n\
+         twitter.com. 1800 IN A 104.244.42.193\n\
+         \n\
+         ;; AUTHORITY SECTION:\n\
+         twitter.com. 13999 IN NS a.r06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS a.u06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS b.r06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS b.u06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS c.r06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS c.u06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS d.r06.twtrdns.net.\n\
+         twitter.com. 13999 IN NS d.u06.twtrdns.net.\n"
+    );
+
+    Ok(())
+}
+
+/// Validate running the program with an invalid CLI argument results in an error.
+#[test]
+fn test_running_toy_dns_with_invalid_domain_name() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "�"]);
+
+    let mut socket = MockSocket::bind("")?;
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    let result = run(args, &mut socket, &mut stdout);
+    assert_eq!(result, DnsError::QuerySerialization.exit_code());
+
+    Ok(())
+}
+
+/// Validate that several domain names are resolved in separate blocks, and that the exit code
+/// tells of the first failure. Only the answer section is printed, as with +noall +answer.
+#[test]
+fn test_running_toy_dns_with_several_domain_names() -> Result<(), DnsError> {
+    let args = resolve_args([
+        "toy_dns",
+        "--rand-seed",
+        "0",
+        "--section",
+        "noall",
+        "--section",
+        "answer",
+        "twitter.com",
+        "�",
+        "twitter.com",
+    ]);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    let result = run(args, &mut socket, &mut stdout);
+    assert_eq!(result, DnsError::QuerySerialization.exit_code());
+
+    let answer = "twitter.com. 1800 IN A 104.244.42.193\n";
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        format!(
+            "Results for twitter.com:\n{}\nResults for �:\n\nResults for twitter.com:\n{}",
+            answer, answer
+        )
+    );
+    Ok(())
+}
+
+/// Validate that results are printed as JSON, one line per domain name.
+#[test]
+fn test_running_toy_dns_with_json() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "--json", "twitter.com", "�"]);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    let result = run(args, &mut socket, &mut stdout);
+    assert_eq!(result, DnsError::QuerySerialization.exit_code());
+
+    let stdout = String::from_utf8(stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].starts_with(r#"{"name":"twitter.com","type":"A","elapsed_ms":"#));
+    assert!(lines[0].contains(
+        r#""answer":[{"name":"twitter.com","type":"A","class":"IN","ttl":1800,"data":"104.244.42.193","rdata":{"address":"104.244.42.193"}}]"#
+    ));
+    assert!(lines[0].contains(r#""servers":[{"address":"#));
+    assert!(lines[1].contains(r#""error":"QuerySerialization: "#));
+    assert!(lines[1].ends_with(r#""response":null,"servers":[]}"#));
+    Ok(())
+}
+
+/// Validate that each resolution is logged with --query-log, along with the servers queried.
+#[test]
+fn test_running_toy_dns_with_query_log() -> Result<(), DnsError> {
+    let path = std::env::temp_dir().join(format!("toy_dns_query_log_{}", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let args = resolve_args([
+        "toy_dns",
+        "--rand-seed",
+        "0",
+        "--query-log",
+        &path,
+        "--query-log-format",
+        "json",
+        "twitter.com",
+        "�",
+    ]);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    let result = run(args, &mut socket, &mut stdout);
+    assert_eq!(result, DnsError::QuerySerialization.exit_code());
+    let contents = std::fs::read_to_string(&path).map_err(|_| DnsError::QueryLog)?;
+    _ = std::fs::remove_file(&path);
+
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(r#""name":"twitter.com","type":"A","rcode":"NOERROR","answers":1,"#));
+    assert!(lines[0].contains(r#""servers":[""#));
+    assert!(lines[0].ends_with(r#""error":null}"#));
+    assert!(lines[1].contains(r#""rcode":null,"answers":0,"#));
+    assert!(lines[1].contains(r#""servers":[],"error":"QuerySerialization: "#));
+
+    assert!(parse_args(["toy_dns", "--query-log-format", "json", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate that the path of the resolution is printed as JSON with --trace-json, even with the
+/// trace subcommand.
+#[test]
+fn test_running_toy_dns_with_trace_json() -> Result<(), DnsError> {
+    let Command::Trace(args) = parse_args([
+        "toy_dns",
+        "trace",
+        "--rand-seed",
+        "0",
+        "--trace-json",
+        "twitter.com",
+    ])
+    .unwrap()
+    .command
+    else {
+        panic!("Expected the trace subcommand");
+    };
+    let args = ResolveArgs {
+        trace: !args.trace_json && !args.trace_dot,
+        ..*args
+    };
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with(r#"{"name":"twitter.com","type":"A","elapsed_ms":"#));
+    assert!(lines[0].contains(r#""error":null,"path":[{"server":"#));
+    assert!(lines[0].contains(r#""outcome":"referral","referral":{"zone":"com","#));
+    assert!(
+        lines[0].contains(r#""outcome":"answer","referral":null,"answer":[{"name":"twitter.com""#)
+    );
+
+    assert!(parse_args(["toy_dns", "--trace-json", "+trace", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate that the path of the resolution is printed as a Graphviz graph with --trace-dot.
+#[test]
+fn test_running_toy_dns_with_trace_dot() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "--trace-dot", "twitter.com"]);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.starts_with("digraph delegation {\n"));
+    assert!(stdout.contains(" [label=\"com\"];\n"));
+    assert!(
+        stdout.contains("    result [shape=box, label=\"twitter.com A\\nA 104.244.42.193\"];\n")
+    );
+    assert!(stdout.ends_with(" -> result;\n}\n"));
+
+    assert!(parse_args(["toy_dns", "--trace-dot", "--json", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate that statistics about the resolution follow the response with +stats.
+#[test]
+fn test_running_toy_dns_with_stats() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "+stats", "twitter.com"]);
+    assert!(args.stats);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    let (response, stats) = stdout.split_once("\n\n;; Queries sent: ").unwrap();
+    assert!(response.contains("104.244.42.193"));
+    assert!(stats.contains(" (0 unanswered)\n;; Bytes on the wire: "));
+    assert!(stats.contains("\n;; Cache: 0 hits, 0 misses\n;; Total time: "));
+    assert!(stats.contains(" responses, 0 timeouts, rtt min/avg/max "));
+
+    assert!(parse_args(["toy_dns", "--stats", "--json", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate that only the data of the answers is printed with --short, without telling the
+/// results of several domain names apart.
+#[test]
+fn test_running_toy_dns_with_short() -> Result<(), DnsError> {
+    let args = resolve_args([
+        "toy_dns",
+        "--rand-seed",
+        "0",
+        "+short",
+        "twitter.com",
+        "twitter.com",
+    ]);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+    assert_eq!(
+        String::from_utf8(stdout).unwrap(),
+        "104.244.42.193\n104.244.42.193\n"
+    );
+    assert!(parse_args(["toy_dns", "--short", "--json", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate that the servers queried along the way are printed with --trace.
+#[test]
+fn test_running_toy_dns_with_trace() -> Result<(), DnsError> {
+    let args = resolve_args(["toy_dns", "--rand-seed", "0", "+trace", "twitter.com"]);
+    assert!(args.trace);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(run(args, &mut socket, &mut stdout), 0);
+    let stdout = String::from_utf8(stdout).unwrap();
+    // The root server refers to the com servers, which refer to the twitter.com servers, whose
+    // own address has to be resolved before they can answer.
+    assert!(stdout.starts_with("com. 172800 IN NS e.gtld-servers.net.\n"));
+    assert!(stdout.contains(";; Received 1024 bytes from 192.58.128.30:53(j.root-servers.net) in "));
+    assert!(stdout.contains(";; Looking up a.r06.twtrdns.net A to reach the next name server\n"));
+    assert!(stdout.contains("twitter.com. 1800 IN A 104.244.42.193\n"));
+    assert!(
+        stdout.contains(";; Received 1024 bytes from 205.251.192.179:53(a.r06.twtrdns.net) in ")
+    );
+    assert!(!stdout.contains("HEADER"));
+    assert!(parse_args(["toy_dns", "--trace", "--short", "example.com"]).is_err());
+    Ok(())
+}
+
+/// Validate parsing of the timeout and retries.
+#[test]
+fn test_parsing_timeout_and_retries() {
+    assert_eq!(parse_timeout("3s"), Ok(Duration::from_secs(3)));
+    assert_eq!(parse_timeout("3"), Ok(Duration::from_secs(3)));
+    assert_eq!(parse_timeout("1.5s"), Ok(Duration::from_millis(1500)));
+    assert_eq!(parse_timeout("500ms"), Ok(Duration::from_millis(500)));
+    assert!(parse_timeout("0s").is_err());
+    assert!(parse_timeout("-1").is_err());
+    assert!(parse_timeout("5m").is_err());
+
+    let args = resolve_args(["toy_dns", "example.com"]);
+    assert_eq!(args.timeout, Duration::from_secs(5));
+    assert_eq!(args.retries, 2);
+    let args = resolve_args([
+        "toy_dns",
+        "--timeout",
+        "3s",
+        "--retries",
+        "0",
+        "example.com",
+    ]);
+    assert_eq!(args.timeout, Duration::from_secs(3));
+    assert_eq!(args.retries, 0);
+    assert!(!args.tcp);
+    assert_eq!(args.query_rate, None);
+    let args = resolve_args(["toy_dns", "--query-rate", "20", "example.com"]);
+    assert_eq!(args.query_rate, Some(20));
+    assert!(parse_args(["toy_dns", "--query-rate", "0", "example.com"]).is_err());
+    assert!(parse_args(["toy_dns", "--query-rate", "fast", "example.com"]).is_err());
+
+    let args = resolve_args(["toy_dns", "+tcp", "example.com"]);
+    assert!(args.tcp);
+    assert!(!args.ipv6_transport);
+
+    let args = resolve_args(["toy_dns", "-6", "example.com"]);
+    assert!(args.ipv6_transport);
+
+    let args = resolve_args([
+        "toy_dns",
+        "-b",
+        "192.0.2.10",
+        "--interface",
+        "tun0",
+        "example.com",
+    ]);
+    assert_eq!(args.source, Some("192.0.2.10:0".parse().unwrap()));
+    assert_eq!(args.interface.as_deref(), Some("tun0"));
+    let args = resolve_args(["toy_dns", "--source", "[2001:db8::10]:5353", "example.com"]);
+    assert_eq!(args.source, Some("[2001:db8::10]:5353".parse().unwrap()));
+    assert!(parse_args(["toy_dns", "-b", "example.org", "example.com"]).is_err());
+
+    let args = resolve_args(["toy_dns", "--record-fixture", "twitter.txt", "twitter.com"]);
+    assert_eq!(args.record_fixture.as_deref(), Some("twitter.txt"));
+}
+
+/// Validate parsing of batch files.
+#[test]
+fn test_parsing_batch() {
+    assert_eq!(
+        parse_batch(
+            "# Names to check\nexample.com\n\n  example.org  mx\nexample.net TYPE65280\n",
+            RecordType::AAAA
+        ),
+        Ok(vec![
+            ("example.com".to_owned(), RecordType::AAAA),
+            ("example.org".to_owned(), RecordType::MX),
+            ("example.net".to_owned(), RecordType::Unknown(65280)),
+        ])
+    );
+    assert_eq!(
+        parse_batch("example.com\nexample.org BOGUS\n", RecordType::A),
+        Err("line 2: unknown record type BOGUS".to_owned())
+    );
+    assert!(parse_batch("example.com A extra\n", RecordType::A).is_err());
+
+    let args = resolve_args(["toy_dns", "--batch", "-"]);
+    assert_eq!(args.batch.as_deref(), Some("-"));
+    assert!(args.domain_names.is_empty());
+
+    let args = resolve_args(["toy_dns", "-x", "192.0.2.1", "-x", "2001:db8::1"]);
+    assert_eq!(
+        args.reverse,
+        [
+            "192.0.2.1".parse::<IpAddr>().unwrap(),
+            "2001:db8::1".parse::<IpAddr>().unwrap()
+        ]
+    );
+    assert!(args.domain_names.is_empty());
+    assert!(parse_args(["toy_dns", "-x", "example.com"]).is_err());
+}
+
+/// Validate that queries are sent from an address of the family of the server given with
+/// --server, unless a source address is given.
+#[test]
+fn test_local_address() {
+    let args = resolve_args(["toy_dns", "example.com"]);
+    assert_eq!(local_address(&args), "0.0.0.0:0");
+    let args = resolve_args(["toy_dns", "@192.0.2.53", "example.com"]);
+    assert_eq!(local_address(&args), "0.0.0.0:0");
+    let args = resolve_args(["toy_dns", "@[2001:db8::53]", "example.com"]);
+    assert_eq!(local_address(&args), "[::]:0");
+    let args = resolve_args(["toy_dns", "@[2001:db8::53]:5353", "example.com"]);
+    assert_eq!(local_address(&args), "[::]:0");
+    let args = resolve_args(["toy_dns", "-6", "example.com"]);
+    assert_eq!(local_address(&args), "[::]:0");
+    let args = resolve_args([
+        "toy_dns",
+        "-b",
+        "192.0.2.10",
+        "@[2001:db8::53]",
+        "example.com",
+    ]);
+    assert_eq!(local_address(&args), "192.0.2.10:0");
+}
+
+/// Validate that the NXDOMAIN response of a server given with --server is printed with its
+/// status, and that the lookup fails all the same.
+#[test]
+fn test_running_toy_dns_with_unknown_name_at_server() {
+    use std::cell::RefCell;
+    use toy_dns_lib::header::FLAG_RESPONSE;
+
+    /// Answers every query with NXDOMAIN, from the address it was sent to.
+    struct NxdomainSocket(RefCell<Option<(Vec<u8>, SocketAddr)>>);
+
+    impl DnsTransport for NxdomainSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+            let mut response = Packet::parse(buf)?;
+            response.header.flags |= FLAG_RESPONSE | RCODE_NAME_ERROR;
+            *self.0.borrow_mut() = Some((response.serialize()?, addr.parse().unwrap()));
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            let Some((data, source)) = self.0.borrow_mut().take() else {
+                return Err(DnsError::SocketRead(None));
+            };
+            buf[..data.len()].copy_from_slice(&data);
+            Ok((data.len(), source))
+        }
+    }
+
+    let args = resolve_args([
+        "toy_dns",
+        "--rand-seed",
+        "0",
+        "@192.0.2.53",
+        "missing.example",
+    ]);
+    let mut socket = NxdomainSocket(RefCell::new(None));
+    let mut stdout: Vec<u8> = Vec::new();
+
+    assert_eq!(
+        run(args, &mut socket, &mut stdout),
+        DnsError::UnknownDomainName.exit_code()
+    );
+    let stdout = String::from_utf8(stdout).unwrap();
+    assert!(stdout.starts_with(";; ->>HEADER<<- opcode: QUERY, status: NXDOMAIN, id: "));
+    assert!(stdout.contains(";missing.example. IN A\n"));
+}
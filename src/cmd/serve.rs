@@ -0,0 +1,721 @@
+use super::{open_query_log, upstream_socket, LogFormat, UpstreamStrategy};
+use clap::ArgGroup;
+use std::net::{TcpListener, UdpSocket};
+use std::sync::Arc;
+use toy_dns_lib::acl::{AccessList, Cidr, ListenerAcl};
+use toy_dns_lib::blocklist::{BlockAction, Blocklist};
+use toy_dns_lib::cache::{Cache, CacheConfig};
+use toy_dns_lib::control::{Control, DEFAULT_CONTROL_ADDRESS};
+use toy_dns_lib::dns64::{Nat64Prefix, WELL_KNOWN_PREFIX};
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::{Forwarder, Upstream};
+use toy_dns_lib::in_flight::InFlight;
+use toy_dns_lib::metrics::Metrics;
+use toy_dns_lib::resolver::Resolver;
+use toy_dns_lib::resolver_config::ResolverConfig;
+use toy_dns_lib::rrl::{RateLimitConfig, RateLimiter};
+use toy_dns_lib::server::{Listener, Server, DEFAULT_LISTEN_ADDRESS};
+use toy_dns_lib::throttle::{QueryThrottle, ThrottleConfig};
+use toy_dns_lib::upstream_pool::UpstreamPool;
+
+#[cfg(test)]
+use super::resolve::resolve_args;
+#[cfg(test)]
+use crate::{parse_args, Args, Command};
+#[cfg(test)]
+use clap::Parser;
+#[cfg(test)]
+use std::time::Duration;
+#[cfg(test)]
+use toy_dns_lib::record::RecordType;
+#[cfg(test)]
+use toy_dns_lib::zone_walk;
+
+/// Value of `--dns64` asking for the NAT64 prefix to be discovered.
+const DISCOVER_NAT64_PREFIX: &str = "discover";
+
+/// Arguments for `toy_dns serve`
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("upstream").args(["recursion", "forward"])))]
+pub struct ServeArgs {
+    /// Zone file to serve, with $ORIGIN set or absolute names (may be repeated, reloaded when
+    /// changed)
+    #[arg(
+        long = "zone",
+        value_name = "FILE",
+        required_unless_present = "upstream"
+    )]
+    pub zones: Vec<String>,
+
+    /// Address and port to listen on over UDP and TCP (may be repeated)
+    #[arg(long, value_name = "ADDRESS", default_value = DEFAULT_LISTEN_ADDRESS)]
+    pub listen: Vec<String>,
+
+    /// Only answer clients in CIDR, refusing others (may be repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub allow_query: Vec<String>,
+
+    /// Refuse clients in CIDR, even if allowed (may be repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub deny_query: Vec<String>,
+
+    /// Only resolve or forward names outside of the served zones for clients in CIDR, rather than
+    /// for those on the loopback and private networks; 0.0.0.0/0 and ::/0 open recursion to
+    /// everyone (may be repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub allow_recursion: Vec<String>,
+
+    /// Never resolve or forward names for clients in CIDR, even if allowed (may be repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub deny_recursion: Vec<String>,
+
+    /// Allow zone transfers to clients in CIDR, which are refused to everyone by default (may be
+    /// repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub allow_transfer: Vec<String>,
+
+    /// Refuse zone transfers to clients in CIDR, even if allowed (may be repeated)
+    #[arg(long, value_name = "CIDR")]
+    pub deny_transfer: Vec<String>,
+
+    /// Resolve names outside of the served zones for clients which set the RD bit
+    #[arg(long, default_value_t = false)]
+    pub recursion: bool,
+
+    /// Forward names outside of the served zones to UPSTREAM, e.g. 192.0.2.1, tls://1.1.1.1 or
+    /// https://dns.google/dns-query (may be repeated, tried in order)
+    #[arg(long, value_name = "UPSTREAM", conflicts_with = "recursion")]
+    pub forward: Vec<String>,
+
+    /// How to choose which upstream to forward to first. Upstreams which stop responding are
+    /// ejected until a health probe finds them responding again
+    #[arg(long, value_enum, default_value_t = UpstreamStrategy::Failover, requires = "forward")]
+    pub upstream_strategy: UpstreamStrategy,
+
+    /// Send at most RATE queries per second to each upstream or name server on average, holding
+    /// queries back until their turn
+    #[arg(
+        long,
+        value_name = "RATE",
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "upstream"
+    )]
+    pub query_rate: Option<u32>,
+
+    /// Synthesize AAAA records from A records in PREFIX, 64:ff9b::/96 unless given, for names
+    /// resolved or forwarded without any (DNS64). With "discover", the prefix is discovered by
+    /// asking the upstream about ipv4only.arpa
+    #[arg(
+        long,
+        value_name = "PREFIX",
+        num_args = 0..=1,
+        default_missing_value = WELL_KNOWN_PREFIX,
+        requires = "upstream"
+    )]
+    pub dns64: Option<String>,
+
+    /// Refresh cached answers accessed with less than PERCENT of their TTL remaining
+    #[arg(long, value_name = "PERCENT", requires = "upstream")]
+    pub prefetch: Option<u8>,
+
+    /// Cache and serve records with a TTL of at least SECONDS, e.g. 5
+    #[arg(long, value_name = "SECONDS", requires = "upstream")]
+    pub min_ttl: Option<u32>,
+
+    /// Cache and serve records with a TTL of at most SECONDS, e.g. 86400
+    #[arg(long, value_name = "SECONDS", requires = "upstream")]
+    pub max_ttl: Option<u32>,
+
+    /// Cache at most ENTRIES responses, evicting those closest to their expiry [default: 10000]
+    #[arg(long, value_name = "ENTRIES", requires = "upstream")]
+    pub cache_size: Option<usize>,
+
+    /// Keep expired answers SECONDS longer, to serve when they cannot be refreshed, e.g. 86400
+    #[arg(long, value_name = "SECONDS", requires = "upstream")]
+    pub serve_stale: Option<u32>,
+
+    /// Block the names listed in FILE, in hosts, domain list or adblock format (may be repeated,
+    /// reloaded when changed)
+    #[arg(long, value_name = "FILE")]
+    pub blocklist: Vec<String>,
+
+    /// How to answer blocked names
+    #[arg(long, value_enum, default_value_t = BlockWith::Nxdomain, requires = "blocklist")]
+    pub block_with: BlockWith,
+
+    /// Limit identical responses to each client network to RATE per second
+    #[arg(long, value_name = "RATE")]
+    pub rate_limit: Option<u32>,
+
+    /// Send every Nth response above the rate limit truncated instead of dropping it, or drop all
+    /// with 0
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "rate_limit")]
+    pub rate_limit_slip: u32,
+
+    /// Keep address records in zone order instead of rotating them from one response to the next
+    #[arg(long, default_value_t = false)]
+    pub no_rotate: bool,
+
+    /// Number of requests handled concurrently
+    #[arg(long, default_value_t = 4)]
+    pub workers: usize,
+
+    /// Serve Prometheus metrics over HTTP at ADDRESS under /metrics, e.g. 127.0.0.1:9153
+    #[arg(long, value_name = "ADDRESS")]
+    pub metrics: Option<String>,
+
+    /// Accept control commands over TCP at ADDRESS, 127.0.0.1:8953 unless given, e.g. from
+    /// toy_dns cache. Anyone who can connect may flush the cache, so keep it local
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        num_args = 0..=1,
+        default_missing_value = DEFAULT_CONTROL_ADDRESS
+    )]
+    pub control: Option<String>,
+
+    /// Log each query answered to FILE (- for stderr), with its response code, number of
+    /// answers, how long it took and the upstreams contacted
+    #[arg(long, value_name = "FILE")]
+    pub query_log: Option<String>,
+
+    /// Format of the entries of the query log
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, requires = "query_log")]
+    pub query_log_format: LogFormat,
+}
+
+/// How `toy_dns serve` answers blocked names
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum BlockWith {
+    /// Answer that the name does not exist
+    Nxdomain,
+
+    /// Answer with 0.0.0.0 or ::
+    Null,
+}
+
+/// Build the access lists of the listeners from the `--allow-*` and `--deny-*` arguments.
+///
+/// # Argument
+/// * `args`: The arguments of `toy_dns serve`.
+fn listener_acl(args: &ServeArgs) -> Result<ListenerAcl, DnsError> {
+    let parse = |cidrs: &[String]| -> Result<Vec<Cidr>, DnsError> {
+        cidrs.iter().map(|cidr| Cidr::parse(cidr)).collect()
+    };
+    let mut acl = ListenerAcl {
+        query: AccessList {
+            allow: parse(&args.allow_query)?,
+            deny: parse(&args.deny_query)?,
+        },
+        ..Default::default()
+    };
+    if !args.allow_recursion.is_empty() {
+        acl.recursion.allow = parse(&args.allow_recursion)?;
+    }
+    acl.recursion.deny = parse(&args.deny_recursion)?;
+    if !args.allow_transfer.is_empty() || !args.deny_transfer.is_empty() {
+        acl.transfer = AccessList {
+            allow: parse(&args.allow_transfer)?,
+            deny: parse(&args.deny_transfer)?,
+        };
+    }
+    Ok(acl)
+}
+
+/// Serve the given zones until the socket fails.
+///
+/// # Argument
+/// * `args`: Arguments of the `serve` subcommand.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn serve(args: ServeArgs) -> i32 {
+    let mut server = match Server::from_files(&args.zones) {
+        Ok(server) => server,
+        Err(error) => {
+            eprintln!("Could not serve the zones: {}", error);
+            return error.exit_code();
+        }
+    };
+
+    let acl = match listener_acl(&args) {
+        Ok(acl) => acl,
+        Err(error) => {
+            eprintln!("Could not parse the access lists: {}", error);
+            return error.exit_code();
+        }
+    };
+
+    let mut listeners = Vec::with_capacity(args.listen.len());
+    for address in &args.listen {
+        match UdpSocket::bind(address).and_then(|socket| Ok((socket, TcpListener::bind(address)?)))
+        {
+            Ok((socket, tcp)) => listeners.push(Listener {
+                socket,
+                tcp: Some(tcp),
+                acl: acl.clone(),
+            }),
+            Err(error) => {
+                eprintln!("Could not listen on {}: {}", address, error);
+                return DnsError::SocketBind(None).exit_code();
+            }
+        }
+    }
+
+    let throttle = args
+        .query_rate
+        .map(|rate| QueryThrottle::new(ThrottleConfig::new(rate)));
+    if args.recursion {
+        let mut resolver = Resolver::new(ResolverConfig::default());
+        resolver.query_options.throttle = throttle.clone();
+        resolver.cache = Some(Arc::new(Cache::new(CacheConfig {
+            prefetch_percent: args.prefetch,
+            min_ttl: args.min_ttl,
+            max_ttl: args.max_ttl,
+            max_entries: args.cache_size,
+            serve_stale: args.serve_stale,
+        })));
+        resolver.in_flight = Some(Arc::new(InFlight::default()));
+        server.recursion = Some(resolver);
+    }
+
+    if !args.forward.is_empty() {
+        let mut upstreams = Vec::with_capacity(args.forward.len());
+        for upstream in &args.forward {
+            match Upstream::parse(upstream) {
+                Ok(upstream) => upstreams.push(upstream),
+                Err(error) => {
+                    eprintln!("Could not forward to {}: {}", upstream, error);
+                    return error.exit_code();
+                }
+            }
+        }
+        let mut forwarder = Forwarder::new(upstreams);
+        forwarder.pool = Some(Arc::new(UpstreamPool::new(args.upstream_strategy.into())));
+        forwarder.cache = Some(Arc::new(Cache::new(CacheConfig {
+            prefetch_percent: args.prefetch,
+            min_ttl: args.min_ttl,
+            max_ttl: args.max_ttl,
+            max_entries: args.cache_size,
+            serve_stale: args.serve_stale,
+        })));
+        forwarder.in_flight = Some(Arc::new(InFlight::default()));
+        forwarder.throttle = throttle;
+        server.forwarding = Some(forwarder);
+    }
+
+    if let Some(prefix) = &args.dns64 {
+        let prefix = match prefix.as_str() {
+            DISCOVER_NAT64_PREFIX => upstream_socket(server.upstream_address(), None)
+                .and_then(|mut upstream| server.discover_nat64_prefix(&mut upstream)),
+            _ => Nat64Prefix::parse(prefix),
+        };
+        match prefix {
+            Ok(prefix) => server.dns64 = Some(prefix),
+            Err(error) => {
+                eprintln!("Could not determine the NAT64 prefix: {}", error);
+                return error.exit_code();
+            }
+        }
+    }
+
+    if !args.blocklist.is_empty() {
+        let action = match args.block_with {
+            BlockWith::Nxdomain => BlockAction::NameError,
+            BlockWith::Null => BlockAction::NullAddress,
+        };
+        match Blocklist::from_files(&args.blocklist, action) {
+            Ok(blocklist) => server.blocklist = Some(blocklist),
+            Err(error) => {
+                eprintln!("Could not load the blocklist: {}", error);
+                return error.exit_code();
+            }
+        }
+    }
+
+    if let Some(responses_per_second) = args.rate_limit {
+        server.rate_limiter = Some(RateLimiter::new(RateLimitConfig {
+            responses_per_second,
+            slip: args.rate_limit_slip,
+            ..Default::default()
+        }));
+    }
+
+    server.rotate_answers = !args.no_rotate;
+
+    if let Some(address) = &args.metrics {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!("Could not serve metrics on {}: {}", address, error);
+                return DnsError::SocketBind(None).exit_code();
+            }
+        };
+        let metrics = Metrics::default();
+        server.set_metrics(metrics.clone());
+        std::thread::spawn(move || metrics.serve(listener));
+    }
+
+    if let Some(address) = &args.control {
+        let listener = match TcpListener::bind(address) {
+            Ok(listener) => listener,
+            Err(error) => {
+                eprintln!(
+                    "Could not serve the control socket on {}: {}",
+                    address, error
+                );
+                return DnsError::SocketBind(None).exit_code();
+            }
+        };
+        let recursion_cache = server
+            .recursion
+            .as_ref()
+            .and_then(|resolver| resolver.cache.clone());
+        let forwarding_cache = server
+            .forwarding
+            .as_ref()
+            .and_then(|forwarder| forwarder.cache.clone());
+        let control = Control::new(
+            recursion_cache
+                .into_iter()
+                .chain(forwarding_cache)
+                .collect(),
+        );
+        std::thread::spawn(move || control.serve(listener));
+    }
+
+    if let Some(path) = &args.query_log {
+        match open_query_log(path, args.query_log_format) {
+            Ok(query_log) => server.query_log = Some(query_log),
+            Err(error) => {
+                eprintln!("Could not open {}: {}", path, error);
+                return error.exit_code();
+            }
+        }
+    }
+
+    match Arc::new(server).serve_concurrently(listeners, args.workers) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("Stopped serving: {}", error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Validate parsing of the serve subcommand alongside the default resolve behavior.
+#[test]
+fn test_parsing_serve_subcommand() {
+    let args = Args::parse_from(["toy_dns", "serve", "--zone", "a.zone", "--zone", "b.zone"]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.zones, vec!["a.zone", "b.zone"]);
+    assert_eq!(serve_args.listen, [DEFAULT_LISTEN_ADDRESS]);
+    assert!(!serve_args.recursion);
+
+    let args = Args::parse_from(["toy_dns", "serve", "--recursion", "--prefetch", "10"]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert!(serve_args.zones.is_empty());
+    assert_eq!(serve_args.prefetch, Some(10));
+    assert_eq!(serve_args.dns64, None);
+    assert_eq!((serve_args.min_ttl, serve_args.max_ttl), (None, None));
+
+    let args = [
+        "toy_dns",
+        "serve",
+        "--forward",
+        "192.0.2.1",
+        "--min-ttl",
+        "5",
+        "--max-ttl",
+        "86400",
+        "--cache-size",
+        "500",
+        "--serve-stale",
+        "86400",
+    ];
+    let Command::Serve(serve_args) = Args::parse_from(args).command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(
+        (serve_args.min_ttl, serve_args.max_ttl),
+        (Some(5), Some(86400))
+    );
+    assert_eq!(serve_args.cache_size, Some(500));
+    assert_eq!(serve_args.serve_stale, Some(86400));
+    assert!(parse_args(["toy_dns", "serve", "--zone", "a.zone", "--max-ttl", "60"]).is_err());
+
+    let args = Args::parse_from(["toy_dns", "serve", "--recursion", "--dns64"]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.dns64.as_deref(), Some(WELL_KNOWN_PREFIX));
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--forward",
+        "192.0.2.1",
+        "--dns64",
+        "discover",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.dns64.as_deref(), Some(DISCOVER_NAT64_PREFIX));
+    assert_eq!(serve_args.upstream_strategy, UpstreamStrategy::Failover);
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--forward",
+        "192.0.2.1",
+        "--forward",
+        "192.0.2.2",
+        "--upstream-strategy",
+        "round-robin",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.upstream_strategy, UpstreamStrategy::RoundRobin);
+    assert!(Args::try_parse_from([
+        "toy_dns",
+        "serve",
+        "--zone",
+        "a.zone",
+        "--upstream-strategy",
+        "fastest"
+    ])
+    .is_err());
+    assert!(Args::try_parse_from(["toy_dns", "serve", "--zone", "a.zone", "--dns64"]).is_err());
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--forward",
+        "tls://1.1.1.1",
+        "--forward",
+        "192.0.2.1",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.forward, ["tls://1.1.1.1", "192.0.2.1"]);
+    assert!(serve_args.blocklist.is_empty());
+    assert!(
+        Args::try_parse_from(["toy_dns", "serve", "--forward", "192.0.2.1", "--recursion"])
+            .is_err()
+    );
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--forward",
+        "192.0.2.1",
+        "--blocklist",
+        "ads.txt",
+        "--block-with",
+        "null",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.blocklist, ["ads.txt"]);
+    assert_eq!(serve_args.block_with, BlockWith::Null);
+    assert_eq!(serve_args.rate_limit, None);
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--zone",
+        "example.com.zone",
+        "--rate-limit",
+        "10",
+        "--rate-limit-slip",
+        "0",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.rate_limit, Some(10));
+    assert_eq!(serve_args.rate_limit_slip, 0);
+    assert!(!serve_args.no_rotate);
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "serve",
+        "--zone",
+        "example.com.zone",
+        "--listen",
+        "127.0.0.1:5353",
+        "--listen",
+        "[::1]:5353",
+        "--allow-query",
+        "127.0.0.0/8",
+        "--deny-recursion",
+        "0.0.0.0/0",
+        "--allow-transfer",
+        "192.0.2.53",
+        "--no-rotate",
+    ]);
+    let Command::Serve(serve_args) = args.command else {
+        panic!("Expected the serve subcommand");
+    };
+    assert_eq!(serve_args.listen, ["127.0.0.1:5353", "[::1]:5353"]);
+    assert!(serve_args.no_rotate);
+    let acl = listener_acl(&serve_args).unwrap();
+    assert!(acl.query.permits("127.0.0.1".parse().unwrap()));
+    assert!(!acl.query.permits("192.0.2.1".parse().unwrap()));
+    assert!(!acl.recursion.permits("127.0.0.1".parse().unwrap()));
+    assert!(acl.transfer.permits("192.0.2.53".parse().unwrap()));
+    assert!(!acl.transfer.permits("192.0.2.54".parse().unwrap()));
+
+    let args = Args::parse_from(["toy_dns", "browse", "_ipp._tcp"]);
+    let Command::Browse(browse_args) = args.command else {
+        panic!("Expected the browse subcommand");
+    };
+    assert_eq!(browse_args.service_type, "_ipp._tcp");
+
+    let args = Args::parse_from(["toy_dns", "check-delegation", "example.com", "-r", "0"]);
+    let Command::CheckDelegation(check_args) = args.command else {
+        panic!("Expected the check-delegation subcommand");
+    };
+    assert_eq!(check_args.domain_name, "example.com");
+    assert_eq!(check_args.rand_seed, Some(0));
+    assert_eq!(check_args.timeout, Duration::from_secs(5));
+
+    let args = Args::parse_from(["toy_dns", "lint", "--zone", "example.com.zone"]);
+    let Command::Lint(lint_args) = args.command else {
+        panic!("Expected the lint subcommand");
+    };
+    assert_eq!(lint_args.zone.as_deref(), Some("example.com.zone"));
+    assert!(!lint_args.no_resolve);
+    let args = Args::parse_from([
+        "toy_dns",
+        "lint",
+        "--transfer",
+        "example.com",
+        "--server",
+        "192.0.2.53",
+        "--no-resolve",
+    ]);
+    let Command::Lint(lint_args) = args.command else {
+        panic!("Expected the lint subcommand");
+    };
+    assert_eq!(lint_args.transfer.as_deref(), Some("example.com"));
+    assert_eq!(lint_args.server.as_deref(), Some("192.0.2.53"));
+    assert!(lint_args.no_resolve);
+    assert!(Args::try_parse_from(["toy_dns", "lint", "--transfer", "example.com"]).is_err());
+    assert!(Args::try_parse_from(["toy_dns", "lint"]).is_err());
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "mailcheck",
+        "example.com",
+        "--dkim-selector",
+        "mail",
+    ]);
+    let Command::Mailcheck(mailcheck_args) = args.command else {
+        panic!("Expected the mailcheck subcommand");
+    };
+    assert_eq!(mailcheck_args.domain_name, "example.com");
+    assert_eq!(mailcheck_args.dkim_selector.as_deref(), Some("mail"));
+
+    let args = Args::parse_from(["toy_dns", "wildcard", "example.com"]);
+    let Command::Wildcard(wildcard_args) = args.command else {
+        panic!("Expected the wildcard subcommand");
+    };
+    assert_eq!(wildcard_args.domain_name, "example.com");
+    assert_eq!(wildcard_args.rand_seed, None);
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "scan-resolvers",
+        "192.0.2.0/30",
+        "198.51.100.7",
+        "--rate",
+        "5",
+    ]);
+    let Command::ScanResolvers(scan_args) = args.command else {
+        panic!("Expected the scan-resolvers subcommand");
+    };
+    assert_eq!(
+        scan_args.targets,
+        [
+            Cidr::parse("192.0.2.0/30").unwrap(),
+            Cidr::parse("198.51.100.7").unwrap()
+        ]
+    );
+    assert_eq!(scan_args.port, 53);
+    assert_eq!(scan_args.name, "example.com");
+    assert_eq!(scan_args.rate, 5);
+    assert!(Args::try_parse_from(["toy_dns", "scan-resolvers", "192.0.2.0/33"]).is_err());
+    assert!(Args::try_parse_from(["toy_dns", "scan-resolvers"]).is_err());
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "enum",
+        "example.com",
+        "--wordlist",
+        "words.txt",
+        "--concurrency",
+        "50",
+    ]);
+    let Command::Enum(enum_args) = args.command else {
+        panic!("Expected the enum subcommand");
+    };
+    assert_eq!(enum_args.domain_name, "example.com");
+    assert_eq!(enum_args.wordlist, "words.txt");
+    assert_eq!(enum_args.concurrency, 50);
+    assert!(!enum_args.no_wildcard_check);
+    assert!(Args::try_parse_from(["toy_dns", "enum", "example.com"]).is_err());
+
+    let args = Args::parse_from([
+        "toy_dns",
+        "walk-zone",
+        "example.com",
+        "--server",
+        "192.0.2.53",
+        "--max-queries",
+        "20",
+    ]);
+    let Command::WalkZone(walk_args) = args.command else {
+        panic!("Expected the walk-zone subcommand");
+    };
+    assert_eq!(walk_args.zone_name, "example.com");
+    assert_eq!(walk_args.server.as_deref(), Some("192.0.2.53"));
+    assert_eq!(walk_args.max_queries, 20);
+    let args = Args::parse_from(["toy_dns", "walk-zone", "example.com"]);
+    let Command::WalkZone(walk_args) = args.command else {
+        panic!("Expected the walk-zone subcommand");
+    };
+    assert_eq!(walk_args.server, None);
+    assert_eq!(walk_args.max_queries, zone_walk::DEFAULT_MAX_QUERIES);
+
+    let args = resolve_args(["toy_dns", "example.com"]);
+    assert_eq!(args.domain_names, ["example.com"]);
+    assert!(!args.trace);
+    let args = resolve_args(["toy_dns", "resolve", "-t", "MX", "example.com"]);
+    assert_eq!(args.domain_names, ["example.com"]);
+    assert_eq!(args.record_type, RecordType::MX);
+    let Command::Trace(args) = parse_args(["toy_dns", "trace", "example.com"])
+        .unwrap()
+        .command
+    else {
+        panic!("Expected the trace subcommand");
+    };
+    assert_eq!(args.domain_names, ["example.com"]);
+    assert_eq!(args.record_type, RecordType::A);
+
+    let args = resolve_args(["toy_dns", "--type", "any", "example.com"]);
+    assert_eq!(args.record_type, RecordType::ANY);
+    let args = resolve_args(["toy_dns", "-t", "TYPE65280", "example.com"]);
+    assert_eq!(args.record_type, RecordType::Unknown(65280));
+    assert!(parse_args(["toy_dns", "--type", "BOGUS", "example.com"]).is_err());
+    assert!(parse_args(["toy_dns", "--type", "OPT", "example.com"]).is_err());
+
+    assert!(parse_args(["toy_dns", "serve"]).is_err());
+    assert!(parse_args(["toy_dns", "--dnssec"]).is_err());
+}
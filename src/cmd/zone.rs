@@ -0,0 +1,298 @@
+use super::parse_timeout;
+use clap::ArgGroup;
+use std::io::Write;
+use std::time::Duration;
+use toy_dns_lib::axfr;
+use toy_dns_lib::delegation;
+use toy_dns_lib::errors::DnsError;
+use toy_dns_lib::forwarder::{Upstream, DEFAULT_UDP_PORT};
+use toy_dns_lib::lint;
+use toy_dns_lib::resolver::Resolver;
+use toy_dns_lib::resolver_config::ResolverConfig;
+use toy_dns_lib::socket::DnsTransport;
+use toy_dns_lib::tcp::TcpTransport;
+use toy_dns_lib::wildcard;
+use toy_dns_lib::zone::Zone;
+use toy_dns_lib::zone_walk;
+
+/// Arguments for `toy_dns check-delegation`
+#[derive(clap::Args, Debug)]
+pub struct CheckDelegationArgs {
+    /// Zone whose delegation to check, e.g. example.com
+    pub domain_name: String,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Check the delegation of a zone and print the report.
+///
+/// # Arguments
+/// * `args`: Arguments of the `check-delegation` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the report.
+///
+/// # Return
+/// Returns the process exit code, 1 if problems were found.
+pub fn check_delegation(
+    args: CheckDelegationArgs,
+    socket: &mut dyn DnsTransport,
+    stdout: &mut impl Write,
+) -> i32 {
+    let resolver = Resolver::new(ResolverConfig::default());
+    match delegation::check_delegation(&resolver, socket, &args.domain_name, args.rand_seed) {
+        Ok(report) => {
+            _ = write!(stdout, "{}", report.render());
+            match report.is_consistent() {
+                true => 0,
+                false => 1,
+            }
+        }
+        Err(error) => {
+            eprintln!(
+                "Could not check the delegation of {}: {}",
+                args.domain_name, error
+            );
+            error.exit_code()
+        }
+    }
+}
+
+/// Arguments for `toy_dns lint`
+#[derive(clap::Args, Debug)]
+#[command(group(ArgGroup::new("source").required(true).args(["zone", "transfer"])))]
+pub struct LintArgs {
+    /// Zone file to check, with $ORIGIN set or absolute names
+    #[arg(long, value_name = "FILE")]
+    pub zone: Option<String>,
+
+    /// Transfer the zone ZONE from --server with AXFR and check it
+    #[arg(long, value_name = "ZONE", requires = "server")]
+    pub transfer: Option<String>,
+
+    /// Server to transfer the zone from over TCP, e.g. 192.0.2.53 or [::1]:5353
+    #[arg(long, value_name = "SERVER")]
+    pub server: Option<String>,
+
+    /// Only check the zone on its own, without resolving the names outside of it which it points
+    /// at
+    #[arg(long, default_value_t = false)]
+    pub no_resolve: bool,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Load the zone to lint, from its file or by transferring it over TCP.
+///
+/// # Argument
+/// * `args`: Arguments of the `lint` subcommand.
+///
+/// # Return
+/// Returns the zone, or the process exit code if it could not be loaded.
+fn load_lint_zone(args: &LintArgs) -> Result<Zone, i32> {
+    if let Some(path) = &args.zone {
+        return Zone::from_file(path, "").map_err(|error| {
+            eprintln!("Could not load {}: {}", path, error);
+            error.exit_code()
+        });
+    }
+    let (Some(zone_name), Some(server)) = (&args.transfer, &args.server) else {
+        return Err(DnsError::InvalidUpstream.exit_code());
+    };
+    let address = match Upstream::parse_server(server, DEFAULT_UDP_PORT) {
+        Ok(Upstream::Udp(address)) => address,
+        _ => {
+            eprintln!(
+                "Could not transfer {}: expected the address of a server, not {}",
+                zone_name, server
+            );
+            return Err(DnsError::InvalidUpstream.exit_code());
+        }
+    };
+    let mut tcp = TcpTransport::default();
+    tcp.set_timeout(args.timeout);
+    axfr::transfer_zone(&mut tcp, &address.to_string(), zone_name, args.rand_seed).map_err(
+        |error| {
+            eprintln!(
+                "Could not transfer {} from {}: {}",
+                zone_name, server, error
+            );
+            error.exit_code()
+        },
+    )
+}
+
+/// Check a zone for mistakes and print the report.
+///
+/// # Arguments
+/// * `args`: Arguments of the `lint` subcommand.
+/// * `socket`: The socket to resolve the names outside of the zone on.
+/// * `stdout`: Where to print the report.
+///
+/// # Return
+/// Returns the process exit code, 1 if errors were found.
+pub fn lint(args: LintArgs, socket: &mut dyn DnsTransport, stdout: &mut impl Write) -> i32 {
+    let zone = match load_lint_zone(&args) {
+        Ok(zone) => zone,
+        Err(exit_code) => return exit_code,
+    };
+    let mut report = lint::lint_zone(&zone);
+    if !args.no_resolve {
+        let resolver = Resolver::new(ResolverConfig::default());
+        report.findings.extend(lint::lint_external_names(
+            &zone,
+            &resolver,
+            socket,
+            args.rand_seed,
+        ));
+    }
+    _ = write!(stdout, "{}", report.render());
+    match report.has_errors() {
+        true => 1,
+        false => 0,
+    }
+}
+
+/// Arguments for `toy_dns wildcard`
+#[derive(clap::Args, Debug)]
+pub struct WildcardArgs {
+    /// Domain to probe random names under, e.g. example.com
+    pub domain_name: String,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Probe a domain for a wildcard and print the addresses it synthesizes, if any.
+///
+/// # Arguments
+/// * `args`: Arguments of the `wildcard` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the result.
+///
+/// # Return
+/// Returns the process exit code.
+pub fn wildcard(args: WildcardArgs, socket: &mut dyn DnsTransport, stdout: &mut impl Write) -> i32 {
+    let resolver = Resolver::new(ResolverConfig::default());
+    match wildcard::detect_wildcard(&resolver, socket, &args.domain_name, args.rand_seed) {
+        Ok(Some(wildcard)) => {
+            _ = writeln!(stdout, "{}", wildcard);
+            0
+        }
+        Ok(None) => {
+            _ = writeln!(
+                stdout,
+                "{}: no wildcard",
+                args.domain_name.trim_end_matches('.')
+            );
+            0
+        }
+        Err(error) => {
+            eprintln!("Could not probe {}: {}", args.domain_name, error);
+            error.exit_code()
+        }
+    }
+}
+
+/// Arguments for `toy_dns walk-zone`
+#[derive(clap::Args, Debug)]
+pub struct WalkZoneArgs {
+    /// Zone to walk, e.g. example.com
+    pub zone_name: String,
+
+    /// Authoritative server of the zone to walk it at, e.g. 192.0.2.53 or [::1]:5353. By default
+    /// the first name server of the zone found
+    #[arg(long, value_name = "SERVER")]
+    pub server: Option<String>,
+
+    /// Send at most N queries
+    #[arg(long, value_name = "N", default_value_t = zone_walk::DEFAULT_MAX_QUERIES)]
+    pub max_queries: usize,
+
+    /// How long to wait for a server to respond, e.g. 3s or 500ms
+    #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = parse_timeout)]
+    pub timeout: Duration,
+
+    /// Random generator seed
+    #[arg(short, long)]
+    pub rand_seed: Option<usize>,
+}
+
+/// Walk the NSEC or NSEC3 chain of a zone and print the names or hashes collected.
+///
+/// # Arguments
+/// * `args`: Arguments of the `walk-zone` subcommand.
+/// * `socket`: The socket to send queries on.
+/// * `stdout`: Where to print the walk.
+///
+/// # Return
+/// Returns the process exit code, 1 if the chain could not be followed to its end.
+pub fn walk_zone(
+    args: WalkZoneArgs,
+    socket: &mut dyn DnsTransport,
+    stdout: &mut impl Write,
+) -> i32 {
+    let address = match &args.server {
+        Some(server) => match Upstream::parse_server(server, DEFAULT_UDP_PORT) {
+            Ok(Upstream::Udp(address)) => address,
+            _ => {
+                eprintln!(
+                    "Could not walk {}: expected the address of a server, not {}",
+                    args.zone_name, server
+                );
+                return DnsError::InvalidUpstream.exit_code();
+            }
+        },
+        None => {
+            let resolver = Resolver::new(ResolverConfig::default());
+            match zone_walk::find_name_server(&resolver, socket, &args.zone_name, args.rand_seed) {
+                Ok(address) => address,
+                Err(error) => {
+                    eprintln!(
+                        "Could not find a name server of {}: {}",
+                        args.zone_name, error
+                    );
+                    return error.exit_code();
+                }
+            }
+        }
+    };
+    match zone_walk::walk_zone(
+        socket,
+        &address.to_string(),
+        &args.zone_name,
+        args.max_queries,
+        args.rand_seed,
+    ) {
+        Ok(walk) => {
+            _ = write!(stdout, "{}", walk.render());
+            match walk.complete {
+                true => 0,
+                false => 1,
+            }
+        }
+        Err(error) => {
+            eprintln!(
+                "Could not walk {} at {}: {}",
+                args.zone_name, address, error
+            );
+            error.exit_code()
+        }
+    }
+}
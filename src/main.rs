@@ -1,152 +1,403 @@
-use clap::Parser;
-use env_logger::Builder;
-use log::{error, LevelFilter};
-use std::io::{stdout, Write};
-use std::net::UdpSocket;
+mod cmd;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use cmd::bench::{bench, BenchArgs};
+use cmd::cache::{cache, CacheArgs};
+use cmd::discover::{browse, enumerate, scan_resolvers, BrowseArgs, EnumArgs, ScanResolversArgs};
+use cmd::mailcheck::{mailcheck, MailcheckArgs};
+use cmd::parse::{parse, ParseArgs};
+use cmd::resolve::{local_address, run, ResolveArgs};
+use cmd::serve::{serve, ServeArgs};
+use cmd::zone::{
+    check_delegation, lint, walk_zone, wildcard, CheckDelegationArgs, LintArgs, WalkZoneArgs,
+    WildcardArgs,
+};
+use cmd::{server_address, with_upstream_socket};
+use log::error;
+use std::io::{stdout, IsTerminal};
+use std::net::SocketAddr;
+use toy_dns_lib::dig::Sections;
 use toy_dns_lib::errors::DnsError;
-use toy_dns_lib::query::Query;
-use toy_dns_lib::record::RecordType;
-use toy_dns_lib::socket::Socket;
+use toy_dns_lib::forwarder::{UpstreamSocket, DEFAULT_UDP_PORT};
+use toy_dns_lib::mock_data;
+use toy_dns_lib::pcap::PcapWriter;
+use toy_dns_lib::socket::{DnsTransport, RecordingSocket};
+use tracing_subscriber::filter::LevelFilter;
 
 /// Arguments for toy_dns
 #[derive(Parser, Debug)]
 #[command(version, arg_required_else_help(true))]
 struct Args {
+    #[command(subcommand)]
+    command: Command,
+
     /// Name of the person to greet
-    #[arg(short, long, default_value_t = false)]
+    #[arg(short, long, global = true, default_value_t = false)]
     verbose: bool,
+}
+
+/// Subcommands of toy_dns. Without one, toy_dns resolves the given domain names.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Resolve domain names and print the responses (the default)
+    Resolve(Box<ResolveArgs>),
+
+    /// Resolve domain names and print each server queried along the way (same as resolve --trace)
+    Trace(Box<ResolveArgs>),
+
+    /// Answer queries authoritatively from zone files
+    Serve(Box<ServeArgs>),
+
+    /// Discover instances of a service on the local link with multicast DNS
+    Browse(BrowseArgs),
+
+    /// Decode a captured DNS message and print it
+    Parse(ParseArgs),
+
+    /// Send queries to a server at a steady rate and report its latency, timeouts and RCODEs
+    Bench(BenchArgs),
+
+    /// Inspect or flush the cache of a running server through its control socket
+    Cache(CacheArgs),
 
-    /// Domain name to query
-    domain_name: String,
+    /// Compare the name servers and glue a parent zone delegates a zone with to the NS records at
+    /// its apex, and report mismatches and lame name servers
+    CheckDelegation(CheckDelegationArgs),
 
-    /// Random generator seed
-    #[arg(short, long)]
-    rand_seed: Option<usize>,
+    /// Check a zone file or a zone transferred from a server for mistakes: SOA timers, CNAME
+    /// conflicts, dangling names, missing glue and TTL anomalies
+    Lint(LintArgs),
+
+    /// Check that a domain can receive mail and authenticates what it sends: its MX records and
+    /// their addresses, and its SPF, DMARC and optionally DKIM records
+    Mailcheck(MailcheckArgs),
+
+    /// Detect whether names which do not exist under a domain resolve anyway, because of a
+    /// wildcard record or a catch-all server, and print the addresses they resolve to
+    Wildcard(WildcardArgs),
+
+    /// Ask hosts to resolve a name recursively and report those which do, to find accidental
+    /// open resolvers on networks you operate
+    ScanResolvers(ScanResolversArgs),
+
+    /// Find names under a domain by resolving the labels of a wordlist concurrently, leaving out
+    /// those which only resolve to the addresses of its wildcard
+    Enum(EnumArgs),
+
+    /// List the names of a DNSSEC-signed zone by following its NSEC chain, or collect the hashes
+    /// of its names when it is signed with NSEC3
+    WalkZone(WalkZoneArgs),
+}
+
+/// The first operand among the arguments, which names the subcommand if they have one. `-` counts
+/// as an operand, as it stands for standard input.
+///
+/// # Argument
+/// * `args`: The command line arguments, program name first.
+fn first_operand(args: &[String]) -> Option<&str> {
+    args.iter()
+        .skip(1)
+        .map(String::as_str)
+        .find(|arg| *arg == "-" || !arg.starts_with('-'))
+}
+
+/// Whether the given operand names a subcommand.
+///
+/// # Argument
+/// * `operand`: The operand, e.g. `serve` or `example.com`.
+fn is_subcommand(operand: &str) -> bool {
+    operand == "help"
+        || Args::command()
+            .get_subcommands()
+            .any(|subcommand| subcommand.get_name() == operand)
+}
+
+/// Translate dig-style `+option` and `@server` arguments into their `--option` equivalents so
+/// that clap can parse them. Only the arguments of `resolve` and `trace`, named or not, are
+/// translated; those of other subcommands are passed through unchanged.
+///
+/// # Argument
+/// * `args`: The raw command line arguments, program name first.
+fn translate_dig_options(args: Vec<String>) -> Vec<String> {
+    if first_operand(&args)
+        .is_some_and(|operand| is_subcommand(operand) && !matches!(operand, "resolve" | "trace"))
+    {
+        return args;
+    }
+    args.into_iter()
+        .map(|arg| {
+            if let Some(server) = arg.strip_prefix('@').filter(|server| !server.is_empty()) {
+                return format!("--server={}", server);
+            }
+            match arg.strip_prefix('+') {
+                Some(toggle) if Sections::is_toggle(toggle) => format!("--section={}", toggle),
+                Some(option) if !option.is_empty() => format!("--{}", option),
+                _ => arg,
+            }
+        })
+        .collect()
+}
+
+/// Prepend the `resolve` subcommand to the arguments unless they already name a subcommand, so
+/// that `toy_dns example.com` keeps resolving example.com. Arguments without any operand (e.g.
+/// just `--help`) are left alone.
+///
+/// # Argument
+/// * `args`: The command line arguments, program name first.
+fn default_to_resolve(mut args: Vec<String>) -> Vec<String> {
+    if first_operand(&args).is_some_and(|operand| !is_subcommand(operand)) {
+        args.insert(1, "resolve".to_owned());
+    }
+    args
+}
+
+/// Parse the command line, translating dig-style options and defaulting to the `resolve`
+/// subcommand.
+///
+/// # Argument
+/// * `args`: The command line arguments, program name first.
+fn parse_args<I, S>(args: I) -> Result<Args, clap::Error>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    let args = translate_dig_options(args.into_iter().map(Into::into).collect());
+    Args::try_parse_from(default_to_resolve(args))
 }
 
 fn main() {
-    let args = Args::parse();
+    let args = parse_args(std::env::args()).unwrap_or_else(|error| error.exit());
 
     let logging_level = match args.verbose {
-        true => LevelFilter::Info,
-        false => LevelFilter::Off,
+        true => LevelFilter::INFO,
+        false => LevelFilter::OFF,
     };
 
-    Builder::new()
-        .format(|buf, record| {
-            writeln!(
-                buf,
-                "[{}] {}",
-                record.level(),
-                // record.target(),
-                record.args()
-            )
-        })
-        .filter(None, logging_level)
+    // Log records from the library are routed through the subscriber as well, so they show up
+    // within the spans of the resolution they belong to.
+    tracing_subscriber::fmt()
+        .with_max_level(logging_level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false)
+        .without_time()
         .init();
 
-    let socket = match UdpSocket::bind("0.0.0.0:0") {
+    let args = match args.command {
+        Command::Serve(serve_args) => std::process::exit(serve(*serve_args)),
+        Command::Browse(browse_args) => {
+            std::process::exit(with_upstream_socket(None, None, |socket| {
+                browse(browse_args, socket, &mut stdout())
+            }))
+        }
+        Command::Parse(parse_args) => std::process::exit(parse(parse_args, &mut stdout())),
+        Command::Bench(bench_args) => std::process::exit(bench(bench_args, &mut stdout())),
+        Command::Cache(cache_args) => std::process::exit(cache(cache_args, &mut stdout())),
+        Command::CheckDelegation(check_args) => std::process::exit(with_upstream_socket(
+            None,
+            Some(check_args.timeout),
+            |socket| check_delegation(check_args, socket, &mut stdout()),
+        )),
+        Command::Lint(lint_args) => std::process::exit(with_upstream_socket(
+            server_address(lint_args.server.as_deref(), DEFAULT_UDP_PORT),
+            Some(lint_args.timeout),
+            |socket| lint(lint_args, socket, &mut stdout()),
+        )),
+        Command::Mailcheck(mailcheck_args) => std::process::exit(with_upstream_socket(
+            None,
+            Some(mailcheck_args.timeout),
+            |socket| mailcheck(mailcheck_args, socket, &mut stdout()),
+        )),
+        Command::Wildcard(wildcard_args) => std::process::exit(with_upstream_socket(
+            None,
+            Some(wildcard_args.timeout),
+            |socket| wildcard(wildcard_args, socket, &mut stdout()),
+        )),
+        Command::ScanResolvers(scan_args) => {
+            // The socket is of the family of the first host, as one socket cannot reach both.
+            let first_host = scan_args
+                .targets
+                .first()
+                .and_then(|target| target.addresses().next())
+                .map(|address| SocketAddr::new(address, scan_args.port));
+            std::process::exit(with_upstream_socket(
+                first_host,
+                Some(scan_args.timeout),
+                |socket| scan_resolvers(scan_args, socket, &mut stdout()),
+            ))
+        }
+        Command::Enum(enum_args) => std::process::exit(enumerate(enum_args, &mut stdout())),
+        Command::WalkZone(walk_args) => std::process::exit(with_upstream_socket(
+            server_address(walk_args.server.as_deref(), DEFAULT_UDP_PORT),
+            Some(walk_args.timeout),
+            |socket| walk_zone(walk_args, socket, &mut stdout()),
+        )),
+        Command::Resolve(args) => *args,
+        Command::Trace(args) => ResolveArgs {
+            trace: !args.trace_json && !args.trace_dot,
+            ..*args
+        },
+    };
+
+    // Upstream sockets reach servers over UDP, TLS or HTTPS depending on how they are addressed.
+    // Reads over UDP time out, as multicast DNS queries for .local names may go unanswered.
+    let mut socket = match UpstreamSocket::bind(&local_address(&args)).and_then(|mut socket| {
+        socket.set_timeout(args.timeout)?;
+        socket.set_tcp(args.tcp);
+        if let Some(interface) = &args.interface {
+            socket.bind_to_device(interface)?;
+        }
+        Ok(socket)
+    }) {
         Ok(socket) => socket,
         Err(error) => {
             error!("Failed to bind UDP socket to a local port. {}", error);
-            std::process::exit(DnsError::SocketBind.exit_code());
+            std::process::exit(error.exit_code());
         }
     };
-
-    let mut boxed_socket: Box<dyn Socket<UdpSocket>> = Box::new(socket);
-    std::process::exit(run::<UdpSocket>(args, &mut boxed_socket, &mut stdout()));
-}
-
-/// Run toy_dns with given arguments and logging level.
-///
-/// # Argument
-/// * `args`: CLI arguments.
-/// * `socket`: The socket (in a `Box`) to run toy_dns queries through.
-/// * `stdout`: stdout to write to.
-///
-/// # Return
-/// Returns the process exit code. 0 on success.
-fn run<T>(args: Args, socket: &mut Box<dyn Socket<T>>, stdout: &mut impl Write) -> i32 {
-    let query = Query {
-        domain_name: &args.domain_name,
-        record_type: RecordType::A,
-    };
-
-    match query.resolve(socket, args.rand_seed) {
-        Ok(packet) => {
-            _ = writeln!(stdout, "Answer:");
-            _ = writeln!(stdout, "");
-            for answer in packet.answers {
-                let Ok(name) = std::str::from_utf8(&answer.name) else {
-                    eprintln!("Could not decode record name in UTF8.");
-                    return DnsError::InvalidByteInName.exit_code();
-                };
-                let address = answer.ip_address();
-                _ = writeln!(
-                    stdout,
-                    "Found {} record for {} with address {} set to expire in {}",
-                    answer.r_type, name, address, answer.ttl
-                );
+    if let Some(path) = &args.capture {
+        let capture = std::fs::File::create(path)
+            .map_err(|_| DnsError::WriteCapture)
+            .and_then(PcapWriter::new);
+        match capture {
+            Ok(capture) => socket.set_capture(capture),
+            Err(error) => {
+                eprintln!("Could not create {}: {}", path, error);
+                std::process::exit(error.exit_code());
             }
-            return 0;
-        }
-        Err(error) => {
-            eprintln!("DNS request failed with {}", error);
-            return error.exit_code();
         }
     }
+
+    let Some(path) = args.record_fixture.clone() else {
+        std::process::exit(run(args, &mut socket, &mut stdout()));
+    };
+    let mut socket = RecordingSocket::new(socket);
+    let exit_code = run(args, &mut socket, &mut stdout());
+    let exchanges = socket.into_exchanges();
+    let fixture = match path.ends_with(".rs") {
+        true => mock_data::to_rust_source("CAPTURED_DATA", &exchanges),
+        false => mock_data::to_fixture(&exchanges),
+    };
+    if std::fs::write(&path, fixture).is_err() {
+        eprintln!("Could not write {}", path);
+        std::process::exit(1);
+    }
+    std::process::exit(exit_code);
 }
 
 #[cfg(test)]
-use toy_dns_lib::socket::MockSocket;
-
+use cmd::resolve::{resolve_args, ErrorFormat};
 #[cfg(test)]
-use toy_dns_lib::mock_data;
+use cmd::UpstreamStrategy;
 
-/// Validate running the program with twitter.com
+/// Validate that dig-style +options are translated into long options.
 #[test]
-fn test_running_toy_dns() -> Result<(), DnsError> {
-    let args = Args {
-        verbose: false,
-        domain_name: "twitter.com".to_owned(),
-        rand_seed: Some(0),
-    };
-
-    let data = mock_data::CAPTURED_DATA_FOR_TWITTER;
-
-    let mut socket = MockSocket::bind("")?;
-    socket.register_response_data(data);
-
-    let mut stdout: Vec<u8> = Vec::new();
+fn test_translating_dig_options() {
+    let args = ["toy_dns", "+dnssec", "example.com", "+cdflag", "+"].map(String::from);
+    assert_eq!(
+        translate_dig_options(args.into()),
+        vec!["toy_dns", "--dnssec", "example.com", "--cdflag", "+"]
+    );
+    let args = ["toy_dns", "example.com", "@dns.google:853", "@"].map(String::from);
+    assert_eq!(
+        translate_dig_options(args.into()),
+        vec!["toy_dns", "example.com", "--server=dns.google:853", "@"]
+    );
 
-    let mut boxed_socket: Box<dyn Socket<MockSocket>> = Box::new(socket);
+    let args = resolve_args(["toy_dns", "example.com", "@[2001:4860:4860::8888]"]);
+    assert_eq!(args.server.as_deref(), Some("[2001:4860:4860::8888]"));
 
-    assert_eq!(run::<MockSocket>(args, &mut boxed_socket, &mut stdout), 0);
+    let args = resolve_args(["toy_dns", "+dnssec", "example.com"]);
+    assert!(args.dnssec);
+    assert!(!args.cdflag);
 
+    let args = ["toy_dns", "trace", "example.com", "@192.0.2.53", "+dnssec"].map(String::from);
     assert_eq!(
-        String::from_utf8(stdout).unwrap(),
-        "Answer:\n\nFound A record for twitter.com with address 104.244.42.193 set to expire in 1800\n"
+        translate_dig_options(args.into()),
+        vec![
+            "toy_dns",
+            "trace",
+            "example.com",
+            "--server=192.0.2.53",
+            "--dnssec"
+        ]
     );
+}
 
-    Ok(())
+/// Validate that the arguments of subcommands other than resolve and trace are passed through
+/// unchanged, even when they look like dig-style options.
+#[test]
+fn test_dig_options_only_translated_for_resolve() {
+    let args = ["toy_dns", "serve", "--zone", "@example.zone", "+dnssec"].map(String::from);
+    assert_eq!(translate_dig_options(args.clone().into()), args);
+    let args = ["toy_dns", "--verbose", "parse", "@capture.pcap"].map(String::from);
+    assert_eq!(translate_dig_options(args.clone().into()), args);
+    let args = ["toy_dns", "help", "+trace"].map(String::from);
+    assert_eq!(translate_dig_options(args.clone().into()), args);
 }
 
-/// Validate running the program with an invalid CLI argument results in an error.
+/// Validate that --stub sends queries to the system's resolvers, and cannot be combined with a
+/// server of its own.
 #[test]
-fn test_running_toy_dns_with_invalid_domain_name() -> Result<(), DnsError> {
-    let args = Args {
-        verbose: true,
-        domain_name: "❌".to_owned(),
-        rand_seed: Some(0),
-    };
+fn test_stub_option() {
+    assert!(!resolve_args(["toy_dns", "example.com"]).stub);
+    let args = resolve_args(["toy_dns", "--stub", "example.com"]);
+    assert!(args.stub);
+    assert_eq!(args.port, DEFAULT_UDP_PORT);
+    assert!(parse_args(["toy_dns", "--stub", "--server", "8.8.8.8", "example.com"]).is_err());
+}
 
-    let socket = MockSocket::bind("")?;
+/// Validate that -p sets the port of the server queried.
+#[test]
+fn test_port_option() {
+    let args = resolve_args(["toy_dns", "@127.0.0.1", "-p", "5300", "example.com"]);
+    assert_eq!(args.server.as_deref(), Some("127.0.0.1"));
+    assert_eq!(args.port, 5300);
+}
+
+/// Validate that --upstream-strategy is parsed, and only accepted along with --stub.
+#[test]
+fn test_upstream_strategy_option() {
+    let args = resolve_args([
+        "toy_dns",
+        "--stub",
+        "--upstream-strategy",
+        "fastest",
+        "example.com",
+    ]);
+    assert_eq!(args.upstream_strategy, UpstreamStrategy::Fastest);
+    assert!(parse_args(["toy_dns", "--upstream-strategy", "fastest", "example.com"]).is_err());
+}
 
-    let mut stdout: Vec<u8> = Vec::new();
-    let mut boxed_socket: Box<dyn Socket<MockSocket>> = Box::new(socket);
+/// Validate that +idnout is off unless given.
+#[test]
+fn test_idnout_option() {
+    assert!(!resolve_args(["toy_dns", "example.com"]).idnout);
+    assert!(resolve_args(["toy_dns", "+idnout", "example.com"]).idnout);
+}
 
-    let result = run::<MockSocket>(args, &mut boxed_socket, &mut stdout);
-    assert_eq!(result, DnsError::QuerySerialization.exit_code());
+/// Validate that --error-format defaults to text and rejects unknown formats.
+#[test]
+fn test_error_format_option() {
+    let args = resolve_args(["toy_dns", "example.com"]);
+    assert_eq!(args.error_format, ErrorFormat::Text);
+    let args = resolve_args(["toy_dns", "--error-format", "json", "example.com"]);
+    assert_eq!(args.error_format, ErrorFormat::Json);
+    assert!(parse_args(["toy_dns", "--error-format", "yaml", "example.com"]).is_err());
+}
 
-    Ok(())
+/// Validate that dig-style section toggles such as +noall and +answer are collected in order,
+/// and that unknown sections are rejected.
+#[test]
+fn test_section_options() {
+    let args = resolve_args([
+        "toy_dns",
+        "+noall",
+        "example.com",
+        "+answer",
+        "+noadditional",
+    ]);
+    assert_eq!(args.sections, ["noall", "answer", "noadditional"]);
+    assert!(!args.short);
+    assert!(parse_args(["toy_dns", "--section", "bogus", "example.com"]).is_err());
 }
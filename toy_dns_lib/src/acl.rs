@@ -0,0 +1,278 @@
+use crate::errors::DnsError;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// A block of IP addresses in CIDR notation, e.g. `192.0.2.0/24` or `2001:db8::/32`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Cidr {
+    /// The first address of the block.
+    address: IpAddr,
+
+    /// The number of leading bits shared by all addresses of the block.
+    prefix_length: u8,
+}
+
+impl Cidr {
+    /// Parse a block given in CIDR notation. A lone address is a block of that single address.
+    ///
+    /// # Argument
+    /// * `cidr`: The block to parse.
+    pub fn parse(cidr: &str) -> Result<Cidr, DnsError> {
+        let (address, prefix_length) = match cidr.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (cidr, None),
+        };
+        let Ok(address) = address.parse::<IpAddr>() else {
+            return Err(DnsError::InvalidCidr);
+        };
+
+        let max_length = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix_length = match prefix_length.map(|length| length.parse::<u8>()) {
+            None => max_length,
+            Some(Ok(length)) if length <= max_length => length,
+            Some(_) => return Err(DnsError::InvalidCidr),
+        };
+
+        Ok(Cidr {
+            address: mask(address, prefix_length),
+            prefix_length,
+        })
+    }
+
+    /// Whether the address belongs to the block. IPv4-mapped IPv6 addresses belong to the IPv4
+    /// blocks they map.
+    ///
+    /// # Argument
+    /// * `address`: The address to check.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+            IpAddr::V4(_) => address,
+        };
+        address.is_ipv4() == self.address.is_ipv4()
+            && mask(address, self.prefix_length) == self.address
+    }
+
+    /// The first and last addresses of the block, as numbers.
+    fn bounds(&self) -> (u128, u128) {
+        let length = self.prefix_length as u32;
+        match self.address {
+            IpAddr::V4(v4) => {
+                let first = u32::from(v4);
+                let last = first | u32::MAX.checked_shr(length).unwrap_or(0);
+                (first as u128, last as u128)
+            }
+            IpAddr::V6(v6) => {
+                let first = u128::from(v6);
+                (first, first | u128::MAX.checked_shr(length).unwrap_or(0))
+            }
+        }
+    }
+
+    /// The number of addresses in the block, or `u128::MAX` for all IPv6 addresses.
+    pub fn address_count(&self) -> u128 {
+        let (first, last) = self.bounds();
+        (last - first).saturating_add(1)
+    }
+
+    /// The addresses of the block, in ascending order.
+    pub fn addresses(&self) -> impl Iterator<Item = IpAddr> {
+        let (first, last) = self.bounds();
+        let is_ipv4 = self.address.is_ipv4();
+        (first..=last).map(move |address| match is_ipv4 {
+            true => IpAddr::V4((address as u32).into()),
+            false => IpAddr::V6(address.into()),
+        })
+    }
+}
+
+/// Keep the leading bits of the address and clear the others.
+///
+/// # Arguments
+/// * `address`: The address to mask.
+/// * `prefix_length`: The number of leading bits to keep.
+fn mask(address: IpAddr, prefix_length: u8) -> IpAddr {
+    match address {
+        IpAddr::V4(v4) => {
+            let mask = u32::MAX
+                .checked_shl(32 - prefix_length.min(32) as u32)
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let mask = u128::MAX
+                .checked_shl(128 - prefix_length.min(128) as u32)
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & mask).into())
+        }
+    }
+}
+
+/// Which clients may do something. A client in a denied block never may. Otherwise, a client may
+/// if it is in an allowed block or if no block is allowed explicitly.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AccessList {
+    /// Blocks of clients which may.
+    pub allow: Vec<Cidr>,
+
+    /// Blocks of clients which may not, whether allowed or not.
+    pub deny: Vec<Cidr>,
+}
+
+impl AccessList {
+    /// An access list which denies everyone.
+    pub fn deny_all() -> AccessList {
+        AccessList {
+            allow: vec![],
+            deny: vec![
+                Cidr {
+                    address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    prefix_length: 0,
+                },
+                Cidr {
+                    address: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                    prefix_length: 0,
+                },
+            ],
+        }
+    }
+
+    /// An access list which only allows the loopback and private networks: 127.0.0.0/8 and ::1,
+    /// the private IPv4 blocks of RFC 1918 and the unique local IPv6 addresses of RFC 4193.
+    pub fn private_networks() -> AccessList {
+        let allow = [
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "::1/128",
+            "fc00::/7",
+        ];
+        AccessList {
+            allow: allow
+                .iter()
+                .filter_map(|cidr| Cidr::parse(cidr).ok())
+                .collect(),
+            deny: vec![],
+        }
+    }
+
+    /// Whether the client may.
+    ///
+    /// # Argument
+    /// * `client`: The address of the client.
+    pub fn permits(&self, client: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(client)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(client))
+    }
+}
+
+/// What the clients of a listener may do. By default, anyone may query, only clients on the
+/// loopback and private networks may ask for recursion, lest the server be an open resolver
+/// abused for amplification attacks, and no one may transfer zones.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ListenerAcl {
+    /// Clients which may query at all. Other clients are refused without their request being
+    /// processed any further.
+    pub query: AccessList,
+
+    /// Clients whose queries for names outside of the served zones are resolved or forwarded.
+    pub recursion: AccessList,
+
+    /// Clients which may request zone transfers (AXFR and IXFR).
+    pub transfer: AccessList,
+}
+
+impl Default for ListenerAcl {
+    fn default() -> Self {
+        ListenerAcl {
+            query: AccessList::default(),
+            recursion: AccessList::private_networks(),
+            transfer: AccessList::deny_all(),
+        }
+    }
+}
+
+/// Validate parsing of CIDR blocks and matching addresses against them.
+#[test]
+fn test_cidr() -> Result<(), DnsError> {
+    let block = Cidr::parse("192.0.2.77/24")?;
+    assert!(block.contains("192.0.2.1".parse().unwrap()));
+    assert!(block.contains("::ffff:192.0.2.1".parse().unwrap()));
+    assert!(!block.contains("192.0.3.1".parse().unwrap()));
+    assert!(!block.contains("2001:db8::1".parse().unwrap()));
+
+    let block = Cidr::parse("2001:db8::/32")?;
+    assert!(block.contains("2001:db8:ffff::1".parse().unwrap()));
+    assert!(!block.contains("2001:db9::1".parse().unwrap()));
+
+    let block = Cidr::parse("198.51.100.7")?;
+    assert!(block.contains("198.51.100.7".parse().unwrap()));
+    assert!(!block.contains("198.51.100.8".parse().unwrap()));
+
+    assert!(Cidr::parse("0.0.0.0/0")?.contains("203.0.113.1".parse().unwrap()));
+    assert_eq!(Cidr::parse("0.0.0.0/0")?.address_count(), 1 << 32);
+    assert_eq!(Cidr::parse("::/0")?.address_count(), u128::MAX);
+
+    let block = Cidr::parse("192.0.2.77/30")?;
+    assert_eq!(block.address_count(), 4);
+    let addresses: Vec<IpAddr> = block.addresses().collect();
+    assert_eq!(addresses.first(), Some(&"192.0.2.76".parse().unwrap()));
+    assert_eq!(addresses.last(), Some(&"192.0.2.79".parse().unwrap()));
+    let addresses: Vec<IpAddr> = Cidr::parse("2001:db8::1")?.addresses().collect();
+    assert_eq!(addresses, ["2001:db8::1".parse::<IpAddr>().unwrap()]);
+    assert_eq!(Cidr::parse("192.0.2.0/33"), Err(DnsError::InvalidCidr));
+    assert_eq!(Cidr::parse("example.com/8"), Err(DnsError::InvalidCidr));
+    Ok(())
+}
+
+/// Validate that denied blocks take precedence over allowed ones.
+#[test]
+fn test_access_list() -> Result<(), DnsError> {
+    let client = "10.1.2.3".parse().unwrap();
+    assert!(AccessList::default().permits(client));
+    assert!(!AccessList::deny_all().permits(client));
+    assert!(!AccessList::deny_all().permits("2001:db8::1".parse().unwrap()));
+
+    let list = AccessList {
+        allow: vec![Cidr::parse("10.0.0.0/8")?],
+        deny: vec![Cidr::parse("10.1.0.0/16")?],
+    };
+    assert!(list.permits("10.2.0.1".parse().unwrap()));
+    assert!(!list.permits(client));
+    assert!(!list.permits("192.0.2.1".parse().unwrap()));
+    Ok(())
+}
+
+/// Validate that by default, recursion is only allowed to the loopback and private networks.
+#[test]
+fn test_listener_acl_default() {
+    let acl = ListenerAcl::default();
+    for client in [
+        "127.0.0.1",
+        "::1",
+        "10.1.2.3",
+        "172.31.0.1",
+        "192.168.1.1",
+        "fd00::1",
+    ] {
+        let client = client.parse().unwrap();
+        assert!(acl.query.permits(client));
+        assert!(acl.recursion.permits(client));
+        assert!(!acl.transfer.permits(client));
+    }
+    for client in [
+        "192.0.2.1",
+        "172.32.0.1",
+        "2001:db8::1",
+        "::ffff:203.0.113.1",
+    ] {
+        let client = client.parse().unwrap();
+        assert!(acl.query.permits(client));
+        assert!(!acl.recursion.permits(client));
+    }
+}
@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// Scope of link-local addresses, including loopback ones (RFC 6724, section 3.1).
+const SCOPE_LINK_LOCAL: u8 = 0x2;
+
+/// Scope of site-local addresses, deprecated but still ranked.
+const SCOPE_SITE_LOCAL: u8 = 0x5;
+
+/// Scope of global addresses, including private IPv4 ones.
+const SCOPE_GLOBAL: u8 = 0xe;
+
+/// The default policy table of RFC 6724, section 2.1: prefix, prefix length, precedence and
+/// label. IPv4 addresses are looked up as IPv4-mapped IPv6 addresses.
+const POLICY_TABLE: [(u128, u32, u8, u8); 9] = [
+    (0x0000_0000_0000_0000_0000_0000_0000_0001, 128, 50, 0), // ::1/128
+    (0x0000_0000_0000_0000_0000_ffff_0000_0000, 96, 35, 4),  // ::ffff:0:0/96
+    (0x0000_0000_0000_0000_0000_0000_0000_0000, 96, 1, 3),   // ::/96
+    (0x2001_0000_0000_0000_0000_0000_0000_0000, 32, 5, 5),   // 2001::/32
+    (0x2002_0000_0000_0000_0000_0000_0000_0000, 16, 30, 2),  // 2002::/16
+    (0x3ffe_0000_0000_0000_0000_0000_0000_0000, 16, 1, 12),  // 3ffe::/16
+    (0xfec0_0000_0000_0000_0000_0000_0000_0000, 10, 1, 11),  // fec0::/10
+    (0xfc00_0000_0000_0000_0000_0000_0000_0000, 7, 3, 13),   // fc00::/7
+    (0x0000_0000_0000_0000_0000_0000_0000_0000, 0, 40, 1),   // ::/0
+];
+
+/// An address as an IPv6 address, IPv4 ones being mapped (`::ffff:a.b.c.d`).
+///
+/// # Argument
+/// * `address`: The address.
+fn to_ipv6(address: IpAddr) -> Ipv6Addr {
+    match address {
+        IpAddr::V4(address) => address.to_ipv6_mapped(),
+        IpAddr::V6(address) => address,
+    }
+}
+
+/// The precedence and label of an address in the default policy table.
+///
+/// # Argument
+/// * `address`: The address.
+fn policy(address: IpAddr) -> (u8, u8) {
+    let bits = u128::from(to_ipv6(address));
+    POLICY_TABLE
+        .iter()
+        .find(|(prefix, length, _, _)| {
+            *length == 0 || bits >> (128 - length) == prefix >> (128 - length)
+        })
+        .map(|(_, _, precedence, label)| (*precedence, *label))
+        .unwrap_or((40, 1))
+}
+
+/// The scope of an address (RFC 6724, section 3.1).
+///
+/// # Argument
+/// * `address`: The address.
+fn scope(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(address) if address.is_loopback() || address.is_link_local() => SCOPE_LINK_LOCAL,
+        IpAddr::V4(_) => SCOPE_GLOBAL,
+        IpAddr::V6(address) if address.is_multicast() => address.octets()[1] & 0xf,
+        IpAddr::V6(address) if address.is_loopback() => SCOPE_LINK_LOCAL,
+        IpAddr::V6(address) => match address.segments()[0] & 0xffc0 {
+            0xfe80 => SCOPE_LINK_LOCAL,
+            0xfec0 => SCOPE_SITE_LOCAL,
+            _ => SCOPE_GLOBAL,
+        },
+    }
+}
+
+/// The number of leading bits two addresses of the same family share, up to the 64 bits of the
+/// usual IPv6 prefix.
+///
+/// # Arguments
+/// * `source`: The source address.
+/// * `destination`: The destination address.
+fn common_prefix_length(source: IpAddr, destination: IpAddr) -> u32 {
+    match (source, destination) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            (u32::from(source) ^ u32::from(destination)).leading_zeros()
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            let differing = u128::from(source) ^ u128::from(destination);
+            differing.leading_zeros().min(64)
+        }
+        _ => 0,
+    }
+}
+
+/// The source address the system would use to reach a destination, as told by connecting a UDP
+/// socket to it, which sends nothing. `None` when the destination is unreachable, e.g. an IPv6
+/// address on a host without IPv6 connectivity.
+///
+/// # Argument
+/// * `destination`: The destination address.
+pub fn source_address(destination: IpAddr) -> Option<IpAddr> {
+    let local_address = match destination {
+        IpAddr::V4(_) => "0.0.0.0:0",
+        IpAddr::V6(_) => "[::]:0",
+    };
+    let socket = UdpSocket::bind(local_address).ok()?;
+    // Any port does, as nothing is sent.
+    socket.connect(SocketAddr::new(destination, 9)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+/// Compare two destinations along with their source addresses by the rules of RFC 6724,
+/// section 6, the preferred one first. Rules 3, 4 and 7 are left out as they need to know about
+/// deprecated, home and tunnelled addresses.
+///
+/// # Arguments
+/// * `a`: The first destination and the source address to reach it with.
+/// * `b`: The second destination and the source address to reach it with.
+fn compare(a: &(IpAddr, Option<IpAddr>), b: &(IpAddr, Option<IpAddr>)) -> Ordering {
+    let (destination_a, destination_b) = (a.0, b.0);
+    let (Some(source_a), Some(source_b)) = (a.1, b.1) else {
+        // Rule 1: Avoid unusable destinations.
+        return b.1.is_some().cmp(&a.1.is_some());
+    };
+    let (precedence_a, label_a) = policy(destination_a);
+    let (precedence_b, label_b) = policy(destination_b);
+
+    // Rule 2: Prefer matching scope.
+    let scope_matches_a = scope(destination_a) == scope(source_a);
+    let scope_matches_b = scope(destination_b) == scope(source_b);
+    // Rule 5: Prefer matching label.
+    let label_matches_a = label_a == policy(source_a).1;
+    let label_matches_b = label_b == policy(source_b).1;
+    scope_matches_b
+        .cmp(&scope_matches_a)
+        .then(label_matches_b.cmp(&label_matches_a))
+        // Rule 6: Prefer higher precedence.
+        .then(precedence_b.cmp(&precedence_a))
+        // Rule 8: Prefer smaller scope.
+        .then(scope(destination_a).cmp(&scope(destination_b)))
+        // Rule 9: Use longest matching prefix, within the same family.
+        .then(match destination_a.is_ipv4() == destination_b.is_ipv4() {
+            true => common_prefix_length(source_b, destination_b)
+                .cmp(&common_prefix_length(source_a, destination_a)),
+            false => Ordering::Equal,
+        })
+    // Rule 10: Otherwise, leave the order unchanged.
+}
+
+/// Sort destination addresses in the order they should be tried, as specified in RFC 6724:
+/// reachable ones first, then those whose scope and kind match their source address, then by the
+/// precedence of the default policy table (native IPv6, then IPv4, then transition mechanisms)
+/// and by the length of the prefix shared with their source address. The sort is stable.
+///
+/// # Arguments
+/// * `destinations`: The addresses to sort, e.g. the A and AAAA records of a name.
+/// * `source_address`: The source address to reach a destination with, if reachable, e.g.
+///   `source_address()`.
+pub fn sort_destinations(
+    destinations: &mut [IpAddr],
+    source_address: impl Fn(IpAddr) -> Option<IpAddr>,
+) {
+    let mut candidates: Vec<(IpAddr, Option<IpAddr>)> = destinations
+        .iter()
+        .map(|destination| (*destination, source_address(*destination)))
+        .collect();
+    candidates.sort_by(compare);
+    for (destination, (candidate, _)) in destinations.iter_mut().zip(candidates) {
+        *destination = candidate;
+    }
+}
+
+/// Sort the destinations with the given source addresses, those without one being unreachable.
+#[cfg(test)]
+fn sorted(destinations: &[&str], sources: &[(&str, &str)]) -> Vec<String> {
+    let mut destinations: Vec<IpAddr> = destinations
+        .iter()
+        .map(|address| address.parse().unwrap())
+        .collect();
+    sort_destinations(&mut destinations, |destination| {
+        sources
+            .iter()
+            .find(|(to, _)| to.parse() == Ok(destination))
+            .map(|(_, from)| from.parse().unwrap())
+    });
+    destinations.iter().map(IpAddr::to_string).collect()
+}
+
+/// Validate the scopes and policies addresses are given.
+#[test]
+fn test_address_scope_and_policy() {
+    let address = |address: &str| address.parse::<IpAddr>().unwrap();
+    assert_eq!(scope(address("127.0.0.1")), SCOPE_LINK_LOCAL);
+    assert_eq!(scope(address("169.254.1.1")), SCOPE_LINK_LOCAL);
+    assert_eq!(scope(address("10.0.0.1")), SCOPE_GLOBAL);
+    assert_eq!(scope(address("fe80::1")), SCOPE_LINK_LOCAL);
+    assert_eq!(scope(address("fec0::1")), SCOPE_SITE_LOCAL);
+    assert_eq!(scope(address("ff05::1")), SCOPE_SITE_LOCAL);
+    assert_eq!(scope(address("2001:db8::1")), SCOPE_GLOBAL);
+
+    assert_eq!(policy(address("::1")), (50, 0));
+    assert_eq!(policy(address("2001:db8::1")), (40, 1));
+    assert_eq!(policy(address("198.51.100.1")), (35, 4));
+    assert_eq!(policy(address("2002:c633:6401::1")), (30, 2));
+    assert_eq!(policy(address("2001:0::1")), (5, 5));
+    assert_eq!(policy(address("fd00::1")), (3, 13));
+}
+
+/// Validate the examples of RFC 6724, section 10.2.
+#[test]
+fn test_sorting_destinations() {
+    // Prefer matching scope.
+    assert_eq!(
+        sorted(
+            &["2001:db8:1::1", "198.51.100.121"],
+            &[
+                ("2001:db8:1::1", "fe80::1"),
+                ("198.51.100.121", "198.51.100.117")
+            ]
+        ),
+        ["198.51.100.121", "2001:db8:1::1"]
+    );
+    // Prefer higher precedence.
+    assert_eq!(
+        sorted(
+            &["198.51.100.121", "2001:db8:1::1"],
+            &[
+                ("2001:db8:1::1", "2001:db8:1::2"),
+                ("198.51.100.121", "169.254.13.78")
+            ]
+        ),
+        ["2001:db8:1::1", "198.51.100.121"]
+    );
+    // Prefer smaller scope.
+    assert_eq!(
+        sorted(
+            &["2001:db8:1::1", "fe80::1"],
+            &[("2001:db8:1::1", "2001:db8:1::2"), ("fe80::1", "fe80::2")]
+        ),
+        ["fe80::1", "2001:db8:1::1"]
+    );
+    // Use longest matching prefix.
+    assert_eq!(
+        sorted(
+            &["2001:db8:1::1", "2001:db8:3ffe::1"],
+            &[
+                ("2001:db8:1::1", "2001:db8:1::2"),
+                ("2001:db8:3ffe::1", "2001:db8:3f44::2")
+            ]
+        ),
+        ["2001:db8:1::1", "2001:db8:3ffe::1"]
+    );
+    // Prefer matching label, then native transport over 6to4.
+    assert_eq!(
+        sorted(
+            &["2001:db8:1::1", "2002:c633:6401::1"],
+            &[
+                ("2001:db8:1::1", "2002:c633:6401::2"),
+                ("2002:c633:6401::1", "2002:c633:6401::2")
+            ]
+        ),
+        ["2002:c633:6401::1", "2001:db8:1::1"]
+    );
+    assert_eq!(
+        sorted(
+            &["2002:c633:6401::1", "2001:db8:1::1"],
+            &[
+                ("2001:db8:1::1", "2001:db8:1::2"),
+                ("2002:c633:6401::1", "2002:c633:6401::2")
+            ]
+        ),
+        ["2001:db8:1::1", "2002:c633:6401::1"]
+    );
+    // Avoid unusable destinations, and otherwise leave the order unchanged.
+    assert_eq!(
+        sorted(
+            &["2001:db8:1::1", "198.51.100.121", "198.51.100.122"],
+            &[
+                ("198.51.100.121", "198.51.100.117"),
+                ("198.51.100.122", "198.51.100.117")
+            ]
+        ),
+        ["198.51.100.121", "198.51.100.122", "2001:db8:1::1"]
+    );
+}
@@ -0,0 +1,292 @@
+use crate::errors::DnsError;
+use crate::socket::{DnsTransport, MockSocket};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use tokio::net::UdpSocket;
+
+/// A future returned by the methods of `AsyncDnsTransport`.
+pub type TransportFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, DnsError>> + 'a>>;
+
+/// The asynchronous counterpart of `DnsTransport`: sending and receiving return futures instead
+/// of blocking, so that transports can be driven by tokio. Like `DnsTransport`, the trait is
+/// object safe, so that code can take any transport as `&mut dyn AsyncDnsTransport`.
+pub trait AsyncDnsTransport {
+    /// Send the given buffer to the provided address. Upon success the future yields the size of
+    /// the sent buffer.
+    ///
+    /// # Arguments
+    /// * `buf`: The buffer to send.
+    /// * `addr`: The address to send `buf` to.
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &'a str) -> TransportFuture<'a, usize>;
+
+    /// Wait for data on the transport. Upon success the future yields the size of the received
+    /// data and where it came from.
+    ///
+    /// # Argument
+    /// * `buf`: The buffer to populate when data is received.
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)>;
+}
+
+/// Bind a tokio UDP socket to the provided address. Must be called from within a tokio runtime.
+///
+/// # Argument
+/// * `addr`: The (local) address to bind to.
+pub async fn bind_udp(addr: &str) -> Result<UdpSocket, DnsError> {
+    match UdpSocket::bind(addr).await {
+        Ok(socket) => Ok(socket),
+        Err(error) => Err(DnsError::SocketBind(Some(error))),
+    }
+}
+
+impl AsyncDnsTransport for UdpSocket {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &'a str) -> TransportFuture<'a, usize> {
+        Box::pin(async move {
+            // As with the blocking socket, a socket bound to an IPv6 address only reaches IPv4
+            // servers at their IPv4-mapped addresses.
+            let sent = match (addr.parse::<SocketAddr>(), self.local_addr()) {
+                (Ok(SocketAddr::V4(target)), Ok(SocketAddr::V6(_))) => {
+                    let mapped =
+                        SocketAddr::new(IpAddr::V6(target.ip().to_ipv6_mapped()), target.port());
+                    self.send_to(buf, mapped).await
+                }
+                _ => self.send_to(buf, addr).await,
+            };
+            match sent {
+                Ok(size) => Ok(size),
+                Err(error) => Err(DnsError::SocketSend(Some(error))),
+            }
+        })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(async move {
+            match UdpSocket::recv_from(self, buf).await {
+                Ok(size_and_addr) => Ok(size_and_addr),
+                Err(error) => Err(DnsError::SocketRead(Some(error))),
+            }
+        })
+    }
+}
+
+/// The mock socket vendors its preconfigured responses asynchronously too, so that code written
+/// against `AsyncDnsTransport` is tested the same way as code written against `DnsTransport`.
+/// Latency injected with `MockFault::Latency` is waited out on the tokio timer rather than by
+/// blocking the thread.
+impl AsyncDnsTransport for MockSocket {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &'a str) -> TransportFuture<'a, usize> {
+        Box::pin(async move { DnsTransport::send(self, buf, addr) })
+    }
+
+    fn recv_from<'a>(&'a self, buf: &'a mut [u8]) -> TransportFuture<'a, (usize, SocketAddr)> {
+        Box::pin(async move {
+            let reply = self.take_reply()?;
+            tokio::time::sleep(reply.latency).await;
+            reply.deliver(buf)
+        })
+    }
+}
+
+/// Run a future to completion on a tokio runtime of the current thread, for tests.
+///
+/// # Argument
+/// * `future`: The future to run.
+#[cfg(test)]
+fn block_on<F: Future>(future: F) -> F::Output {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(future)
+}
+
+/// Validate that the mock socket vendors its preconfigured responses asynchronously.
+#[test]
+fn test_async_mock_socket() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey};
+
+    block_on(async {
+        let data = [(
+            MockKey {
+                query_bytes: vec![12, 34],
+                server_ip: "1.2.3.4:0".to_owned(),
+            },
+            MockData {
+                data: vec![0xAB; 4],
+            },
+        )];
+        let mut socket = MockSocket::default();
+        socket.register_response_data(data);
+        let transport: &mut dyn AsyncDnsTransport = &mut socket;
+
+        assert_eq!(transport.send(&[12, 34], "1.2.3.4:0").await?, 2);
+        let mut buf = [0; 4];
+        let (size, source) = transport.recv_from(&mut buf).await?;
+        assert_eq!(size, 4);
+        assert_eq!(source, "1.2.3.4:0".parse().unwrap());
+        assert_eq!(buf, [0xAB; 4]);
+
+        // Like a datagram, the response is only received once.
+        assert_eq!(
+            transport.recv_from(&mut buf).await.err(),
+            Some(DnsError::SocketRead(None))
+        );
+        assert!(transport.send(&[56, 78], "1.2.3.4:0").await.is_err());
+        Ok(())
+    })
+}
+
+/// Validate that the mock socket injects faults into the responses it vendors asynchronously.
+#[test]
+fn test_async_mock_socket_faults() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockFault, MockKey};
+    use std::time::{Duration, Instant};
+
+    block_on(async {
+        let key = MockKey {
+            query_bytes: vec![12, 34],
+            server_ip: "1.2.3.4:0".to_owned(),
+        };
+        let mut socket = MockSocket::default();
+        socket.register_faulty_response(
+            key.clone(),
+            MockData {
+                data: vec![0xAB; 4],
+            },
+            vec![
+                MockFault::Latency(Duration::from_millis(20)),
+                MockFault::Truncate(2),
+            ],
+        );
+        socket.register_faulty_response(
+            key.clone(),
+            MockData {
+                data: vec![0xAB; 4],
+            },
+            vec![MockFault::Drop],
+        );
+
+        AsyncDnsTransport::send(&mut socket, &[12, 34], "1.2.3.4:0").await?;
+        let started_at = Instant::now();
+        let mut buf = [0; 4];
+        assert_eq!(AsyncDnsTransport::recv_from(&socket, &mut buf).await?.0, 2);
+        assert!(started_at.elapsed() >= Duration::from_millis(20));
+        assert_eq!(buf[..2], [0xAB; 2]);
+
+        AsyncDnsTransport::send(&mut socket, &[12, 34], "1.2.3.4:0").await?;
+        assert!(AsyncDnsTransport::recv_from(&socket, &mut buf)
+            .await
+            .is_err());
+        assert_eq!(socket.send_count(&key), 2);
+        Ok(())
+    })
+}
+
+/// Validate exchanging a message over a tokio UDP socket with a server answering from another
+/// thread.
+#[test]
+fn test_async_udp_socket() -> Result<(), DnsError> {
+    block_on(async {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0")
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        let server_address = server
+            .local_addr()
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        let echo = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (size, client) = server.recv_from(&mut buf).unwrap();
+            server.send_to(&buf[..size], client).unwrap();
+        });
+
+        let mut socket = bind_udp("127.0.0.1:0").await?;
+        let address = server_address.to_string();
+        assert_eq!(
+            AsyncDnsTransport::send(&mut socket, b"query", &address).await?,
+            5
+        );
+        let mut buf = [0; 512];
+        let (size, from) = AsyncDnsTransport::recv_from(&socket, &mut buf).await?;
+        assert_eq!(&buf[..size], b"query");
+        assert_eq!(from, server_address);
+        echo.join().unwrap();
+        Ok(())
+    })
+}
+
+/// Validate asking a server for a name over a tokio UDP socket, ignoring a datagram forged ahead
+/// of the response.
+#[test]
+fn test_async_ask() -> Result<(), DnsError> {
+    use crate::header::FLAG_RESPONSE;
+    use crate::packet::Packet;
+    use crate::query::Query;
+    use crate::record::RecordType;
+
+    block_on(async {
+        let server = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_address = server.local_addr().unwrap().to_string();
+        let spoofer = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut socket = bind_udp("127.0.0.1:0").await?;
+        let responder = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (size, client) = server.recv_from(&mut buf).unwrap();
+            let mut response = Packet::parse(&buf[..size]).unwrap();
+            response.header.flags |= FLAG_RESPONSE;
+            let mut forged = response.clone();
+            forged.header.id = forged.header.id.wrapping_add(1);
+            spoofer
+                .send_to(&forged.serialize().unwrap(), client)
+                .unwrap();
+            server
+                .send_to(&forged.serialize().unwrap(), client)
+                .unwrap();
+            server
+                .send_to(&response.serialize().unwrap(), client)
+                .unwrap();
+        });
+
+        let query = Query::new("example.com", RecordType::A);
+        let response = query
+            .ask_async(&mut socket, &server_address, Some(0))
+            .await?;
+        assert!(response.header.has_flag(FLAG_RESPONSE));
+        assert_eq!(response.questions[0].name, "example.com");
+        responder.join().unwrap();
+        Ok(())
+    })
+}
+
+/// Validate asking for a name over the mock socket, as the synchronous resolver is tested.
+#[test]
+fn test_async_ask_mock_socket() -> Result<(), DnsError> {
+    use crate::header::FLAG_RESPONSE;
+    use crate::packet::Packet;
+    use crate::query::Query;
+    use crate::record::RecordType;
+    use crate::socket::{MockData, MockKey};
+
+    block_on(async {
+        let query = Query::new("example.com", RecordType::A);
+        let query_bytes = query.serialize(Some(0))?;
+        let mut response = Packet::parse(&query_bytes)?;
+        response.header.flags |= FLAG_RESPONSE;
+        let mut response_bytes = response.serialize()?;
+        response_bytes.resize(1024, 0);
+        let mut socket = MockSocket::default();
+        socket.register_response(
+            MockKey {
+                query_bytes,
+                server_ip: "192.0.2.1:53".to_owned(),
+            },
+            MockData {
+                data: response_bytes,
+            },
+        );
+
+        let received = query
+            .ask_async(&mut socket, "192.0.2.1:53", Some(0))
+            .await?;
+        assert_eq!(received, response);
+        Ok(())
+    })
+}
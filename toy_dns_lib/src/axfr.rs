@@ -0,0 +1,102 @@
+use crate::errors::DnsError;
+use crate::header::RCODE_NO_ERROR;
+use crate::packet::Packet;
+use crate::query::Query;
+use crate::record::RecordType;
+use crate::socket::DnsTransport;
+use crate::zone::Zone;
+use log::info;
+
+/// Query type of full zone transfers, see RFC 5936.
+const QUERY_TYPE_AXFR: u16 = 252;
+
+/// The largest message which can be sent over TCP, whose length prefix is 16 bits.
+const MAX_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+/// Transfer a zone from a server with AXFR (RFC 5936). The records are sent in one or more
+/// messages, starting and ending with the SOA record of the zone, so the socket must be
+/// connection-oriented (e.g. `TcpTransport`) for zones which do not fit in a single message.
+///
+/// # Arguments
+/// * `socket`: The socket to transfer the zone on.
+/// * `server_address`: The address of the server, e.g. `192.0.2.53:53`.
+/// * `zone_name`: The name of the zone, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn transfer_zone(
+    socket: &mut dyn DnsTransport,
+    server_address: &str,
+    zone_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Zone, DnsError> {
+    let zone_name = zone_name.trim_end_matches('.');
+    let query = Query::new(zone_name, RecordType::Unknown(QUERY_TYPE_AXFR));
+    let Ok(query_bytes) = query.serialize(rand_seed) else {
+        return Err(DnsError::QuerySerialization);
+    };
+    info!("Transferring {} from {}", zone_name, server_address);
+    socket.send(&query_bytes, server_address)?;
+
+    let mut records = Vec::new();
+    let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+    loop {
+        let (size, _) = socket.recv_from(&mut buf)?;
+        let packet = Packet::parse(&buf[..size])?;
+        if packet.header.id != u16::from_be_bytes([query_bytes[0], query_bytes[1]])
+            || packet.rcode() != RCODE_NO_ERROR
+            || packet.answers.is_empty()
+        {
+            return Err(DnsError::ZoneTransfer);
+        }
+        for record in packet.answers {
+            let is_soa = record.r_type == RecordType::SOA;
+            match (records.is_empty(), is_soa) {
+                (true, false) => return Err(DnsError::ZoneTransfer),
+                // The SOA record is repeated at the end of the transfer.
+                (false, true) => {
+                    info!("Transferred {} records of {}", records.len(), zone_name);
+                    return Ok(Zone::new(zone_name, records));
+                }
+                _ => records.push(record),
+            }
+        }
+    }
+}
+
+/// Validate transferring a zone sent in a single message, and refusing a transfer which does not
+/// start with the SOA record.
+#[test]
+fn test_transferring_zone() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let axfr = RecordType::Unknown(QUERY_TYPE_AXFR);
+    let exchanges = vec![
+        MockResolution::new("example.com", axfr, 0)
+            .at("192.0.2.53")
+            .answer(
+                "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 1 3600 900 604800 60
+                 example.com. 3600 IN NS ns1.example.com.
+                 ns1.example.com. 3600 IN A 192.0.2.53
+                 example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 1 3600 900 604800 60",
+            )
+            .build()?,
+        MockResolution::new("example.org", axfr, 0)
+            .at("192.0.2.53")
+            .answer("example.org. 3600 IN NS ns1.example.org.")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let zone = transfer_zone(&mut socket, "192.0.2.53:53", "example.com.", Some(0))?;
+    assert_eq!(zone.origin, "example.com");
+    let types: Vec<RecordType> = zone.records.iter().map(|record| record.r_type).collect();
+    assert_eq!(types, [RecordType::SOA, RecordType::NS, RecordType::A]);
+    assert!(zone.soa().is_some());
+
+    assert_eq!(
+        transfer_zone(&mut socket, "192.0.2.53:53", "example.org", Some(0)).err(),
+        Some(DnsError::ZoneTransfer)
+    );
+    Ok(())
+}
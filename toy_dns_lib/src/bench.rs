@@ -0,0 +1,323 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::header::{rcode_name, Header, FLAG_RECURSION_DESIRED, FLAG_RESPONSE};
+use crate::packet::Packet;
+use crate::query::CLASS_IN;
+use crate::question::Question;
+use crate::record::RecordType;
+use log::warn;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest response read from the server. Only the header is looked at.
+const RECEIVE_BUFFER_SIZE: usize = 4096;
+
+/// How often the receiving thread checks whether the run is over.
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How to load a server with queries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    /// The server to send queries to over UDP.
+    pub server: SocketAddr,
+
+    /// How many queries to send per second.
+    pub queries_per_second: u32,
+
+    /// How long to send queries for.
+    pub duration: Duration,
+
+    /// How long to wait for a response before counting the query as timed out.
+    pub timeout: Duration,
+
+    /// The questions to ask, in turn.
+    pub questions: Vec<(String, RecordType)>,
+}
+
+/// The outcome of loading a server with queries.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BenchReport {
+    /// How many queries were sent.
+    pub sent: usize,
+
+    /// How many queries could not be sent.
+    pub send_errors: usize,
+
+    /// How long each response took to arrive, in increasing order.
+    pub latencies: Vec<Duration>,
+
+    /// How many queries went unanswered within the timeout.
+    pub timeouts: usize,
+
+    /// How many responses carried each RCODE.
+    pub rcodes: BTreeMap<u16, usize>,
+
+    /// How long queries were sent for.
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    /// The latency under which the given percentage of responses arrived, using the nearest-rank
+    /// method. `None` without any response.
+    ///
+    /// # Argument
+    /// * `percent`: The percentile, e.g. 99.0.
+    pub fn percentile(&self, percent: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, self.latencies.len()) - 1])
+    }
+
+    /// Render the report as dig-style comment lines: the rate achieved, the responses and
+    /// timeouts, the latency percentiles and how many responses carried each RCODE.
+    pub fn render(&self) -> String {
+        let seconds = self.elapsed.as_secs_f64();
+        let rate = match seconds > 0.0 {
+            true => self.sent as f64 / seconds,
+            false => 0.0,
+        };
+        let timeout_percent = match self.sent {
+            0 => 0.0,
+            sent => self.timeouts as f64 * 100.0 / sent as f64,
+        };
+        let mut output = format!(
+            ";; Sent {} queries in {:.1} s ({:.1} qps), {} could not be sent\n\
+             ;; Responses: {}, timeouts: {} ({:.1}%)\n",
+            self.sent,
+            seconds,
+            rate,
+            self.send_errors,
+            self.latencies.len(),
+            self.timeouts,
+            timeout_percent
+        );
+        if let (Some(min), Some(max)) = (self.latencies.first(), self.latencies.last()) {
+            let milliseconds = |latency: Duration| latency.as_secs_f64() * 1000.0;
+            let percentile = |percent| milliseconds(self.percentile(percent).unwrap_or_default());
+            output.push_str(&format!(
+                ";; Latency: min {:.2} ms, p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms\n",
+                milliseconds(*min),
+                percentile(50.0),
+                percentile(90.0),
+                percentile(99.0),
+                milliseconds(*max)
+            ));
+        }
+        for (rcode, count) in &self.rcodes {
+            output.push_str(&format!(";; {}: {}\n", rcode_name(*rcode), count));
+        }
+        output
+    }
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Entries are only ever
+/// added or removed whole, so the map remains usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Serialize a query for the given question with the RD bit set.
+///
+/// # Arguments
+/// * `id`: The ID of the query.
+/// * `domain_name`: The name in question.
+/// * `record_type`: The type in question.
+fn query(id: u16, domain_name: &str, record_type: RecordType) -> Result<Vec<u8>, DnsError> {
+    Packet {
+        header: Header {
+            id,
+            flags: FLAG_RECURSION_DESIRED,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from(domain_name),
+            q_type: record_type,
+            q_class: CLASS_IN,
+        }],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()
+}
+
+/// Send queries to a server at a steady rate for a while, regardless of how fast it responds,
+/// and report how long the responses took, which RCODE they carried and how many never came.
+/// Responses are told apart by their ID, so that no more than 65536 queries may be outstanding
+/// at once.
+///
+/// # Argument
+/// * `config`: How to load the server.
+pub fn run(config: &BenchConfig) -> Result<BenchReport, DnsError> {
+    if config.questions.is_empty() || config.queries_per_second == 0 {
+        return Err(DnsError::Bench);
+    }
+    let local_address: SocketAddr = match config.server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket =
+        UdpSocket::bind(local_address).map_err(|error| DnsError::SocketBind(Some(error)))?;
+    if socket.connect(config.server).is_err() {
+        return Err(DnsError::SocketConnect(None));
+    }
+    let receiving_socket = socket
+        .try_clone()
+        .map_err(|error| DnsError::SocketBind(Some(error)))?;
+    if receiving_socket
+        .set_read_timeout(Some(RECEIVE_POLL_INTERVAL))
+        .is_err()
+    {
+        return Err(DnsError::SocketBind(None));
+    }
+
+    // Queries awaiting a response, by ID, with when they were sent.
+    let pending: Arc<Mutex<HashMap<u16, Instant>>> = Arc::default();
+    let done = Arc::new(AtomicBool::new(false));
+    let receiver = {
+        let pending = pending.clone();
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut responses: Vec<(Duration, u16)> = Vec::new();
+            let mut buf = [0u8; RECEIVE_BUFFER_SIZE];
+            while !done.load(Ordering::Relaxed) {
+                let Ok(size) = receiving_socket.recv(&mut buf) else {
+                    continue;
+                };
+                let Ok(header) = Header::read_and_advance(&mut Cursor::new(&buf[..size])) else {
+                    continue;
+                };
+                if !header.has_flag(FLAG_RESPONSE) {
+                    continue;
+                }
+                if let Some(sent_at) = lock(&pending).remove(&header.id) {
+                    responses.push((sent_at.elapsed(), header.rcode()));
+                }
+            }
+            responses
+        })
+    };
+
+    let mut report = BenchReport::default();
+    let interval = Duration::from_secs(1) / config.queries_per_second;
+    let started_at = Instant::now();
+    for (index, (domain_name, record_type)) in config.questions.iter().cycle().enumerate() {
+        let scheduled = interval * index as u32;
+        if scheduled >= config.duration {
+            break;
+        }
+        if let Some(wait) = scheduled.checked_sub(started_at.elapsed()) {
+            thread::sleep(wait);
+        }
+
+        let id = index as u16;
+        let bytes = query(id, domain_name, *record_type)?;
+        // A query still awaiting a response under the same ID is given up on.
+        if lock(&pending).insert(id, Instant::now()).is_some() {
+            report.timeouts += 1;
+        }
+        match socket.send(&bytes) {
+            Ok(_) => report.sent += 1,
+            Err(error) => {
+                warn!("Could not send a query to {}: {}", config.server, error);
+                lock(&pending).remove(&id);
+                report.send_errors += 1;
+            }
+        }
+    }
+    report.elapsed = started_at.elapsed();
+
+    // Give the last queries as long to be answered as the others.
+    let deadline = Instant::now() + config.timeout;
+    while Instant::now() < deadline && !lock(&pending).is_empty() {
+        thread::sleep(RECEIVE_POLL_INTERVAL.min(config.timeout));
+    }
+    done.store(true, Ordering::Relaxed);
+    let Ok(responses) = receiver.join() else {
+        return Err(DnsError::SocketRead(None));
+    };
+
+    // Responses which took longer than the timeout count as timeouts all the same.
+    report.timeouts += lock(&pending).len();
+    for (latency, rcode) in responses {
+        if latency > config.timeout {
+            report.timeouts += 1;
+            continue;
+        }
+        report.latencies.push(latency);
+        *report.rcodes.entry(rcode).or_default() += 1;
+    }
+    report.latencies.sort();
+    Ok(report)
+}
+
+/// Validate percentiles and the rendering of a report.
+#[test]
+fn test_bench_report() {
+    let report = BenchReport {
+        sent: 10,
+        send_errors: 0,
+        latencies: (1..=8).map(Duration::from_millis).collect(),
+        timeouts: 2,
+        rcodes: BTreeMap::from([(0, 7), (3, 1)]),
+        elapsed: Duration::from_secs(2),
+    };
+    assert_eq!(report.percentile(50.0), Some(Duration::from_millis(4)));
+    assert_eq!(report.percentile(99.0), Some(Duration::from_millis(8)));
+    assert_eq!(report.percentile(0.0), Some(Duration::from_millis(1)));
+    assert_eq!(BenchReport::default().percentile(50.0), None);
+    assert_eq!(
+        report.render(),
+        ";; Sent 10 queries in 2.0 s (5.0 qps), 0 could not be sent\n\
+         ;; Responses: 8, timeouts: 2 (20.0%)\n\
+         ;; Latency: min 1.00 ms, p50 4.00 ms, p90 8.00 ms, p99 8.00 ms, max 8.00 ms\n\
+         ;; NOERROR: 7\n\
+         ;; NXDOMAIN: 1\n"
+    );
+}
+
+/// Validate loading a server on the loopback interface which answers every other query.
+#[test]
+fn test_bench_run() -> Result<(), DnsError> {
+    let server =
+        UdpSocket::bind("127.0.0.1:0").map_err(|error| DnsError::SocketBind(Some(error)))?;
+    let address = server
+        .local_addr()
+        .map_err(|error| DnsError::SocketBind(Some(error)))?;
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut answer = false;
+        while let Ok((size, client)) = server.recv_from(&mut buf) {
+            answer = !answer;
+            if answer {
+                buf[2] |= 0x80;
+                buf[3] = (buf[3] & 0xf0) | 3;
+                _ = server.send_to(&buf[..size], client);
+            }
+        }
+    });
+
+    let report = run(&BenchConfig {
+        server: address,
+        queries_per_second: 100,
+        duration: Duration::from_millis(100),
+        timeout: Duration::from_millis(200),
+        questions: vec![("example.com".to_owned(), RecordType::A)],
+    })?;
+    assert_eq!(report.sent, 10);
+    assert_eq!(report.latencies.len(), 5);
+    assert_eq!(report.timeouts, 5);
+    assert_eq!(report.rcodes, BTreeMap::from([(3, 5)]));
+    assert!(report.elapsed >= Duration::from_millis(90));
+    Ok(())
+}
@@ -0,0 +1,336 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::query::CLASS_IN;
+use crate::record::{Record, RecordType};
+use crate::ttl::Ttl;
+use log::info;
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+
+/// TTL of the null addresses answered for blocked names.
+const BLOCKED_TTL: u32 = 60;
+
+/// Names found in hosts-format blocklists which map the local host rather than block anything.
+const LOCAL_HOST_NAMES: [&str; 5] = [
+    "localhost",
+    "localhost.localdomain",
+    "local",
+    "broadcasthost",
+    "ip6-localhost",
+];
+
+/// How blocked names are answered.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum BlockAction {
+    /// Pretend the name does not exist.
+    #[default]
+    NameError,
+
+    /// Answer A queries with 0.0.0.0 and AAAA queries with ::, and any other type with no data.
+    NullAddress,
+}
+
+/// The blocked domains along with where they were read from.
+#[derive(Default)]
+struct BlocklistState {
+    /// Lowercased domains without a trailing dot. Their subdomains are blocked too.
+    domains: HashSet<String>,
+
+    /// When each file was last modified at the time it was read.
+    modified: Vec<Option<SystemTime>>,
+}
+
+/// A thread-safe set of blocked domains read from hosts-format lists (`0.0.0.0 ads.example`),
+/// plain domain lists (`ads.example`) or adblock-style lists (`||ads.example^`). A domain blocks
+/// its subdomains as well. Files can be reloaded at runtime when they change.
+#[derive(Default)]
+pub struct Blocklist {
+    /// How blocked names are answered.
+    pub action: BlockAction,
+
+    /// The files the blocked domains are read from.
+    paths: Vec<String>,
+
+    /// The blocked domains.
+    state: RwLock<BlocklistState>,
+}
+
+/// Lock the state for reading even if another thread panicked while holding it. The state is only
+/// ever replaced as a whole, so it remains usable.
+fn read(lock: &RwLock<BlocklistState>) -> RwLockReadGuard<'_, BlocklistState> {
+    match lock.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Lock the state for writing. See `read()`.
+fn write(lock: &RwLock<BlocklistState>) -> RwLockWriteGuard<'_, BlocklistState> {
+    match lock.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl Blocklist {
+    /// Read the blocklists at the given paths.
+    ///
+    /// # Arguments
+    /// * `paths`: Paths to the blocklists.
+    /// * `action`: How blocked names are answered.
+    pub fn from_files(paths: &[String], action: BlockAction) -> Result<Blocklist, DnsError> {
+        let blocklist = Blocklist {
+            action,
+            paths: paths.to_vec(),
+            ..Default::default()
+        };
+        *write(&blocklist.state) = blocklist.read_files()?;
+        Ok(blocklist)
+    }
+
+    /// Parse the contents of a blocklist. Hosts-format lines, bare domains and adblock-style
+    /// `||domain^` rules are understood. Everything after a `#` is a comment, as are lines
+    /// starting with `!`. Any other line is ignored.
+    ///
+    /// # Arguments
+    /// * `contents`: The contents of a blocklist.
+    /// * `action`: How blocked names are answered.
+    pub fn parse(contents: &str, action: BlockAction) -> Blocklist {
+        let mut domains = HashSet::new();
+        parse_domains(contents, &mut domains);
+        Blocklist {
+            action,
+            paths: vec![],
+            state: RwLock::new(BlocklistState {
+                domains,
+                modified: vec![],
+            }),
+        }
+    }
+
+    /// The number of blocked domains, not counting their subdomains.
+    pub fn len(&self) -> usize {
+        read(&self.state).domains.len()
+    }
+
+    /// Whether no domain is blocked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The entry which blocks the given name, if any: the name itself or the closest of its
+    /// parent domains on the list.
+    ///
+    /// # Argument
+    /// * `domain_name`: The name to check.
+    pub fn blocked_by(&self, domain_name: &str) -> Option<String> {
+        let domain_name = domain_name.trim_end_matches('.').to_ascii_lowercase();
+        let state = read(&self.state);
+
+        let mut candidate = domain_name.as_str();
+        loop {
+            if state.domains.contains(candidate) {
+                return Some(candidate.to_owned());
+            }
+            let (_, parent) = candidate.split_once('.')?;
+            candidate = parent;
+        }
+    }
+
+    /// The answers to a query for a blocked name. A name error is expressed as no answers, just
+    /// like no data.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The blocked name.
+    /// * `record_type`: The record type being queried.
+    pub fn answers(&self, domain_name: &str, record_type: RecordType) -> Vec<Record> {
+        let data = match (self.action, record_type) {
+            (BlockAction::NullAddress, RecordType::A) => Ipv4Addr::UNSPECIFIED.octets().to_vec(),
+            (BlockAction::NullAddress, RecordType::AAAA) => Ipv6Addr::UNSPECIFIED.octets().to_vec(),
+            _ => return vec![],
+        };
+        vec![Record {
+            name: DnsName::from(domain_name),
+            r_type: record_type,
+            r_class: CLASS_IN,
+            ttl: Ttl::from(BLOCKED_TTL),
+            data,
+        }]
+    }
+
+    /// Read the files again if any of them was modified since they were last read. On failure,
+    /// the domains read before remain blocked. Returns whether the files were reloaded.
+    pub fn reload_if_changed(&self) -> Result<bool, DnsError> {
+        let modified: Vec<Option<SystemTime>> =
+            self.paths.iter().map(|path| modified(path)).collect();
+        if read(&self.state).modified == modified {
+            return Ok(false);
+        }
+
+        let state = self.read_files()?;
+        info!("Reloaded {} blocked domains", state.domains.len());
+        *write(&self.state) = state;
+        Ok(true)
+    }
+
+    /// Read the blocked domains from all files.
+    fn read_files(&self) -> Result<BlocklistState, DnsError> {
+        let mut state = BlocklistState::default();
+        for path in &self.paths {
+            // The modification time is taken first so that a change made while reading is picked
+            // up by the next reload.
+            state.modified.push(modified(path));
+            let Ok(contents) = std::fs::read_to_string(path) else {
+                return Err(DnsError::Blocklist);
+            };
+            parse_domains(&contents, &mut state.domains);
+        }
+        Ok(state)
+    }
+}
+
+/// When the file at the given path was last modified, if that can be told.
+///
+/// # Argument
+/// * `path`: Path to the file.
+pub(crate) fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Add the domains listed in a blocklist to the set. See `Blocklist::parse()`.
+///
+/// # Arguments
+/// * `contents`: The contents of a blocklist.
+/// * `domains`: The set to add the domains to.
+fn parse_domains(contents: &str, domains: &mut HashSet<String>) {
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.starts_with('!') {
+            continue;
+        }
+
+        if let Some(rule) = line.strip_prefix("||") {
+            // Only rules blocking a whole domain are supported, not ones with paths or options.
+            if let Some(domain) = rule.strip_suffix('^') {
+                insert_domain(domain, domains);
+            }
+            continue;
+        }
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            [domain] => insert_domain(domain, domains),
+            [address, names @ ..] if address.parse::<std::net::IpAddr>().is_ok() => {
+                for name in names {
+                    insert_domain(name, domains);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Add a domain to the set unless it is not a domain at all or names the local host.
+///
+/// # Arguments
+/// * `domain`: The domain as listed.
+/// * `domains`: The set to add the domain to.
+fn insert_domain(domain: &str, domains: &mut HashSet<String>) {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    if domain.is_empty()
+        || domain.parse::<std::net::IpAddr>().is_ok()
+        || LOCAL_HOST_NAMES.contains(&domain.as_str())
+        || !domain
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || b"-_.".contains(&byte))
+    {
+        return;
+    }
+    domains.insert(domain);
+}
+
+/// Validate parsing of hosts-format, domain-list and adblock-style blocklists.
+#[test]
+fn test_parsing_blocklist() {
+    let blocklist = Blocklist::parse(
+        "
+# Hosts format
+127.0.0.1 localhost
+0.0.0.0 0.0.0.0
+0.0.0.0 ads.example.com tracker.example.net # trailing comment
+# Domain list
+Telemetry.Example.
+# Adblock style
+! Title: test list
+||doubleclick.example^
+||example.org/banner.png
+",
+        BlockAction::NameError,
+    );
+
+    assert_eq!(blocklist.len(), 4);
+    assert_eq!(
+        blocklist.blocked_by("ads.example.com"),
+        Some("ads.example.com".to_owned())
+    );
+    assert_eq!(
+        blocklist.blocked_by("eu.TELEMETRY.example."),
+        Some("telemetry.example".to_owned())
+    );
+    assert_eq!(
+        blocklist.blocked_by("x.y.doubleclick.example"),
+        Some("doubleclick.example".to_owned())
+    );
+    assert_eq!(blocklist.blocked_by("example.com"), None);
+    assert_eq!(blocklist.blocked_by("localhost"), None);
+    assert_eq!(blocklist.blocked_by("example.org"), None);
+}
+
+/// Validate the answers to blocked names for each action.
+#[test]
+fn test_blocklist_answers() {
+    let blocklist = Blocklist::parse("ads.example\n", BlockAction::NullAddress);
+    assert_eq!(
+        blocklist.answers("ads.example", RecordType::A)[0].data,
+        [0, 0, 0, 0]
+    );
+    assert_eq!(
+        blocklist.answers("ads.example", RecordType::AAAA)[0].data,
+        [0; 16]
+    );
+    assert!(blocklist.answers("ads.example", RecordType::MX).is_empty());
+
+    let blocklist = Blocklist::parse("ads.example\n", BlockAction::NameError);
+    assert!(blocklist.answers("ads.example", RecordType::A).is_empty());
+}
+
+/// Validate that blocklist files are reloaded only once they change.
+#[test]
+fn test_blocklist_reload() -> Result<(), DnsError> {
+    let path = std::env::temp_dir().join(format!("toy_dns_blocklist_{}", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    std::fs::write(&path, "ads.example\n").unwrap();
+
+    let blocklist = Blocklist::from_files(std::slice::from_ref(&path), BlockAction::NameError)?;
+    assert!(blocklist.blocked_by("ads.example").is_some());
+    assert!(!blocklist.reload_if_changed()?);
+
+    // Modification times may be coarse, so the change is dated well into the future.
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    std::fs::write(&path, "tracker.example\n").unwrap();
+    file.set_modified(SystemTime::now() + std::time::Duration::from_secs(60))
+        .unwrap();
+    assert!(blocklist.reload_if_changed()?);
+    assert!(blocklist.blocked_by("ads.example").is_none());
+    assert!(blocklist.blocked_by("tracker.example").is_some());
+
+    // A blocklist which cannot be read leaves the domains read before blocked.
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(blocklist.reload_if_changed(), Err(DnsError::Blocklist));
+    assert!(blocklist.blocked_by("tracker.example").is_some());
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::errors::DnsError;
+use crate::packet::Packet;
+use crate::record::RecordType;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// Resolve many questions concurrently with the same resolver. Each socket is handed to a worker
+/// thread of its own, which resolves the next question no worker has taken yet until none is
+/// left, so the number of sockets is the number of resolutions in progress at once. The results
+/// are in the order of the questions.
+///
+/// # Arguments
+/// * `resolver`: The resolver to resolve the questions with, shared by the workers.
+/// * `sockets`: The sockets of the workers, at least one.
+/// * `questions`: The names to resolve and the type of records to resolve them to.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn resolve_bulk<S: DnsTransport + Send>(
+    resolver: &Resolver,
+    sockets: Vec<S>,
+    questions: &[(String, RecordType)],
+    rand_seed: Option<usize>,
+) -> Vec<Result<Packet, DnsError>> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<(usize, Result<Packet, DnsError>)> = thread::scope(|scope| {
+        let workers: Vec<_> = sockets
+            .into_iter()
+            .map(|mut socket| {
+                let next = &next;
+                scope.spawn(move || {
+                    let mut results = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((domain_name, record_type)) = questions.get(index) else {
+                            return results;
+                        };
+                        let result =
+                            resolver.resolve(&mut socket, domain_name, *record_type, rand_seed);
+                        results.push((index, result));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| panic::resume_unwind(panic))
+            })
+            .collect()
+    });
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Validate that questions spread over several workers are answered in order.
+#[test]
+fn test_resolving_in_bulk() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let mut sockets = Vec::new();
+    for _ in 0..3 {
+        let mut exchanges = Vec::new();
+        for host in 1..=5 {
+            let name = format!("host{}.example.com", host);
+            exchanges.push(
+                MockResolution::new(&name, RecordType::A, 0)
+                    .answer(&format!("{}. 300 IN A 192.0.2.{}", name, host))
+                    .build()?,
+            );
+        }
+        exchanges.push(
+            MockResolution::new("gone.example.com", RecordType::A, 0)
+                .name_error()
+                .build()?,
+        );
+        let mut socket = MockSocket::bind("")?;
+        socket.register_response_data(exchanges.into_iter().flatten());
+        sockets.push(socket);
+    }
+
+    let mut questions: Vec<(String, RecordType)> = (1..=5)
+        .map(|host| (format!("host{}.example.com", host), RecordType::A))
+        .collect();
+    questions.insert(2, ("gone.example.com".to_owned(), RecordType::A));
+    let resolver = Resolver::new(ResolverConfig::default());
+    let results = resolve_bulk(&resolver, sockets, &questions, Some(0));
+    assert_eq!(results.len(), 6);
+    assert_eq!(
+        results[2].as_ref().err(),
+        Some(&DnsError::UnknownDomainName)
+    );
+    let addresses: Vec<String> = results
+        .iter()
+        .filter_map(|result| result.as_ref().ok())
+        .map(|packet| packet.answers[0].data_to_presentation())
+        .collect();
+    assert_eq!(
+        addresses,
+        [
+            "192.0.2.1",
+            "192.0.2.2",
+            "192.0.2.3",
+            "192.0.2.4",
+            "192.0.2.5"
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,343 @@
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use std::fmt;
+
+/// The flag marking a property that CAs must understand to issue (RFC 8659, section 4.1).
+const FLAG_CRITICAL: u8 = 0x80;
+
+/// The property tags defined in RFC 8659, section 4.2. A CA must not issue when a critical
+/// property has any other tag.
+const KNOWN_TAGS: [&str; 3] = ["issue", "issuewild", "iodef"];
+
+/// A CAA record: a property of the names in its domain (RFC 8659, section 4.1).
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaaProperty {
+    /// The flags, of which only the critical flag is defined.
+    pub flags: u8,
+
+    /// The tag, e.g. `issue`, in lower case.
+    pub tag: String,
+
+    /// The value, e.g. `ca.example.net; account=230123`.
+    pub value: String,
+}
+
+impl CaaProperty {
+    /// Parse the data of a CAA record.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<CaaProperty, DnsError> {
+        let (Some(&flags), Some(&tag_length)) = (data.first(), data.get(1)) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let Some(tag) = data.get(2..2 + tag_length as usize) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        Ok(CaaProperty {
+            flags,
+            tag: String::from_utf8_lossy(tag).to_ascii_lowercase(),
+            value: String::from_utf8_lossy(&data[2 + tag.len()..]).into_owned(),
+        })
+    }
+
+    /// Whether CAs must understand the property to issue.
+    pub fn is_critical(&self) -> bool {
+        self.flags & FLAG_CRITICAL != 0
+    }
+}
+
+impl fmt::Display for CaaProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} \"{}\"", self.flags, self.tag, self.value)
+    }
+}
+
+/// A CA allowed to issue by an `issue` or `issuewild` property (RFC 8659, section 4.2).
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaaIssuer {
+    /// The domain name of the CA, e.g. `ca.example.net`. `None` when the property allows no CA.
+    pub domain_name: Option<String>,
+
+    /// The parameters for the CA, e.g. `account=230123`.
+    pub parameters: Vec<(String, String)>,
+}
+
+impl CaaIssuer {
+    /// Parse the value of an `issue` or `issuewild` property. `None` if it is malformed, in which
+    /// case it allows no CA but still restricts issuance.
+    ///
+    /// # Argument
+    /// * `value`: The value, e.g. `ca.example.net; account=230123`.
+    pub fn parse(value: &str) -> Option<CaaIssuer> {
+        let mut parts = value.split(';').map(str::trim);
+        let domain_name = parts.next().unwrap_or_default();
+        let mut parameters = Vec::new();
+        for parameter in parts.filter(|parameter| !parameter.is_empty()) {
+            let (tag, value) = parameter.split_once('=')?;
+            parameters.push((tag.trim().to_owned(), value.trim().to_owned()));
+        }
+        Some(CaaIssuer {
+            domain_name: match domain_name.is_empty() {
+                true => None,
+                false => Some(domain_name.trim_end_matches('.').to_ascii_lowercase()),
+            },
+            parameters,
+        })
+    }
+}
+
+impl fmt::Display for CaaIssuer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.domain_name.as_deref().unwrap_or("no CA"))?;
+        for (tag, value) in &self.parameters {
+            write!(f, "; {}={}", tag, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The CAA policy in effect for a name: the CAA records of the closest name at or above it which
+/// has any, as found by CAs (RFC 8659, section 3).
+#[derive(Debug, PartialEq, Clone)]
+pub struct CaaPolicy {
+    /// The name the records were found at, e.g. `example.com` for `www.example.com`.
+    pub domain_name: String,
+
+    /// The properties, in the order they were received.
+    pub properties: Vec<CaaProperty>,
+}
+
+impl CaaPolicy {
+    /// The values of the properties with a tag.
+    ///
+    /// # Argument
+    /// * `tag`: The tag, e.g. `iodef`.
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+        let tag = tag.to_owned();
+        self.properties
+            .iter()
+            .filter(move |property| property.tag == tag)
+            .map(|property| property.value.as_str())
+    }
+
+    /// The CAs allowed to issue certificates, or `None` when any CA may. Wildcard certificates
+    /// are governed by the `issuewild` properties if there are any and by the `issue` properties
+    /// otherwise (RFC 8659, section 4.3). Malformed properties are left out.
+    ///
+    /// # Argument
+    /// * `wildcard`: Whether the certificate is for a wildcard name, e.g. `*.example.com`.
+    pub fn issuers(&self, wildcard: bool) -> Option<Vec<CaaIssuer>> {
+        let tag = match wildcard && self.values("issuewild").next().is_some() {
+            true => "issuewild",
+            false => "issue",
+        };
+        // No property with the tag leaves issuance unrestricted.
+        self.values(tag).next()?;
+        Some(self.values(tag).filter_map(CaaIssuer::parse).collect())
+    }
+
+    /// Where CAs are asked to report requests which violate the policy (`iodef`), e.g.
+    /// `mailto:security@example.com`.
+    pub fn iodef(&self) -> Vec<&str> {
+        self.values("iodef").collect()
+    }
+
+    /// The critical properties CAs do not know, which forbid any issuance.
+    pub fn unknown_critical(&self) -> Vec<&CaaProperty> {
+        self.properties
+            .iter()
+            .filter(|property| property.is_critical() && !KNOWN_TAGS.contains(&&*property.tag))
+            .collect()
+    }
+
+    /// Whether a CA may issue certificates under the policy.
+    ///
+    /// # Arguments
+    /// * `issuer_domain_name`: The domain name the CA identifies itself with, e.g.
+    ///   `ca.example.net`.
+    /// * `wildcard`: Whether the certificate is for a wildcard name.
+    pub fn permits(&self, issuer_domain_name: &str, wildcard: bool) -> bool {
+        if !self.unknown_critical().is_empty() {
+            return false;
+        }
+        let issuer_domain_name = issuer_domain_name.trim_end_matches('.');
+        match self.issuers(wildcard) {
+            None => true,
+            Some(issuers) => issuers.iter().any(|issuer| {
+                issuer
+                    .domain_name
+                    .as_deref()
+                    .is_some_and(|domain_name| domain_name.eq_ignore_ascii_case(issuer_domain_name))
+            }),
+        }
+    }
+
+    /// Render the effective `issue`, `issuewild` and `iodef` policy, one per line, under the
+    /// name the records were found at.
+    pub fn render(&self) -> String {
+        let issuers = |wildcard: bool| match self.issuers(wildcard) {
+            None => "any CA".to_owned(),
+            Some(issuers) if issuers.is_empty() => "no CA".to_owned(),
+            Some(issuers) => issuers
+                .iter()
+                .map(CaaIssuer::to_string)
+                .collect::<Vec<String>>()
+                .join(", "),
+        };
+        let mut output = format!("{}\n", self.domain_name);
+        output.push_str(&format!("  issue: {}\n", issuers(false)));
+        output.push_str(&format!("  issuewild: {}\n", issuers(true)));
+        if !self.iodef().is_empty() {
+            output.push_str(&format!("  iodef: {}\n", self.iodef().join(", ")));
+        }
+        for property in self.unknown_critical() {
+            output.push_str(&format!("  unknown critical property: {}\n", property));
+        }
+        output
+    }
+}
+
+/// Find the CAA policy in effect for a name by climbing the hierarchy as CAs do: the CAA records
+/// of the name itself, else those of its parent, and so on up to the top-level domain. `None`
+/// when no name on the way has any, so that any CA may issue.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the CAA records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The name certificates are for, e.g. `www.example.com` or `*.example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_caa(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Option<CaaPolicy>, DnsError> {
+    let domain_name = domain_name.trim_end_matches('.');
+    let domain_name = domain_name.strip_prefix("*.").unwrap_or(domain_name);
+    let labels: Vec<&str> = domain_name.split('.').collect();
+
+    for start in 0..labels.len() {
+        let candidate = labels[start..].join(".");
+        let packet = match resolver.resolve(socket, &candidate, RecordType::CAA, rand_seed) {
+            Ok(packet) => packet,
+            // A name which does not exist has no records, like one without any CAA record.
+            Err(DnsError::UnknownDomainName) => continue,
+            Err(error) => return Err(error),
+        };
+        let properties = packet
+            .answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::CAA)
+            .map(|record| CaaProperty::from_data(&record.data))
+            .collect::<Result<Vec<CaaProperty>, DnsError>>()?;
+        if !properties.is_empty() {
+            return Ok(Some(CaaPolicy {
+                domain_name: candidate,
+                properties,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Build a policy out of CAA records in presentation format.
+#[cfg(test)]
+fn policy(records: &[(u8, &str, &str)]) -> CaaPolicy {
+    CaaPolicy {
+        domain_name: "example.com".to_owned(),
+        properties: records
+            .iter()
+            .map(|(flags, tag, value)| CaaProperty {
+                flags: *flags,
+                tag: tag.to_string(),
+                value: value.to_string(),
+            })
+            .collect(),
+    }
+}
+
+/// Validate parsing of CAA record data and issuer values.
+#[test]
+fn test_parsing_caa_property() -> Result<(), DnsError> {
+    let property = CaaProperty::from_data(b"\x80\x05ISSUEca.example.net")?;
+    assert!(property.is_critical());
+    assert_eq!(property.tag, "issue");
+    assert_eq!(property.to_string(), "128 issue \"ca.example.net\"");
+    assert!(CaaProperty::from_data(b"\x00\x05iss").is_err());
+
+    assert_eq!(
+        CaaIssuer::parse(" ca.example.net. ; account=230123 ;"),
+        Some(CaaIssuer {
+            domain_name: Some("ca.example.net".to_owned()),
+            parameters: vec![("account".to_owned(), "230123".to_owned())],
+        })
+    );
+    assert_eq!(CaaIssuer::parse(";").unwrap().domain_name, None);
+    assert_eq!(CaaIssuer::parse("ca.example.net; account"), None);
+    Ok(())
+}
+
+/// Validate which CAs a policy permits, for plain and wildcard names.
+#[test]
+fn test_caa_policy_permits() {
+    let any = policy(&[(0, "iodef", "mailto:security@example.com")]);
+    assert!(any.permits("ca.example.net", true));
+    assert_eq!(any.issuers(false), None);
+    assert_eq!(any.iodef(), ["mailto:security@example.com"]);
+
+    let restricted = policy(&[(0, "issue", "ca.example.net"), (0, "issuewild", ";")]);
+    assert!(restricted.permits("CA.example.net.", false));
+    assert!(!restricted.permits("other.example.org", false));
+    assert!(!restricted.permits("ca.example.net", true));
+    assert_eq!(
+        restricted.render(),
+        "example.com\n  issue: ca.example.net\n  issuewild: no CA\n"
+    );
+
+    // Without issuewild, wildcard names fall back to issue.
+    let fallback = policy(&[(0, "issue", "ca.example.net"), (0, "issue", "malformed; x")]);
+    assert!(fallback.permits("ca.example.net", true));
+    assert_eq!(fallback.issuers(true).map(|issuers| issuers.len()), Some(1));
+
+    let critical = policy(&[(0, "issue", "ca.example.net"), (128, "tbs", "x")]);
+    assert!(!critical.permits("ca.example.net", false));
+    assert!(critical
+        .render()
+        .ends_with("  unknown critical property: 128 tbs \"x\"\n"));
+}
+
+/// Validate that the policy is looked up at the closest name with CAA records, climbing past
+/// names without any and names which do not exist.
+#[test]
+fn test_looking_up_caa_policy() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let exchanges = vec![
+        MockResolution::new("host.www.example.com", RecordType::CAA, 0)
+            .name_error()
+            .build()?,
+        MockResolution::new("www.example.com", RecordType::CAA, 0)
+            .answer("")
+            .build()?,
+        MockResolution::new("example.com", RecordType::CAA, 0)
+            .answer(
+                "example.com. 300 IN CAA 0 issue \"ca.example.net\"
+                 example.com. 300 IN CAA 0 iodef \"mailto:security@example.com\"",
+            )
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let policy = lookup_caa(&resolver, &mut socket, "*.host.www.example.com", Some(0))?.unwrap();
+    assert_eq!(policy.domain_name, "example.com");
+    assert!(policy.permits("ca.example.net", true));
+    assert_eq!(policy.iodef(), ["mailto:security@example.com"]);
+    Ok(())
+}
@@ -0,0 +1,906 @@
+use crate::errors::{DnsError, ErrorCategory};
+use crate::header::{FLAG_TRUNCATED, RCODE_NAME_ERROR, RCODE_NO_ERROR};
+use crate::metrics::Metrics;
+use crate::packet::Packet;
+use crate::record::{Record, RecordType};
+use crate::ttl::Ttl;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Identifies a cached response.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub struct CacheKey {
+    /// Lowercased domain name of the question.
+    pub domain_name: String,
+
+    /// Record type of the question.
+    pub record_type: RecordType,
+}
+
+impl CacheKey {
+    /// Create a key for the given question. Names are compared case-insensitively.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The domain name of the question.
+    /// * `record_type`: The record type of the question.
+    pub fn new(domain_name: &str, record_type: RecordType) -> CacheKey {
+        CacheKey {
+            domain_name: CacheKey::normalize(domain_name),
+            record_type,
+        }
+    }
+
+    /// The name as it appears in keys: lowercased, without the trailing dot.
+    ///
+    /// # Argument
+    /// * `domain_name`: The domain name to normalize.
+    pub fn normalize(domain_name: &str) -> String {
+        domain_name.trim_end_matches('.').to_ascii_lowercase()
+    }
+}
+
+/// How many entries a cache holds unless configured otherwise.
+pub const DEFAULT_MAX_ENTRIES: usize = 10_000;
+
+/// The TTL of the records of an expired entry when it is served stale, as recommended by RFC 8767,
+/// section 4.
+const STALE_TTL: u32 = 30;
+
+/// Tunables of a `Cache`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CacheConfig {
+    /// When set, an entry accessed with less than this percentage of its original TTL remaining
+    /// is queued to be refreshed ahead of its expiry.
+    pub prefetch_percent: Option<u8>,
+
+    /// When set, records are cached and served with a TTL of at least this many seconds, e.g. to
+    /// smooth over zones with pathologically low TTLs. Records with a TTL of 0 are still not
+    /// cached.
+    pub min_ttl: Option<u32>,
+
+    /// When set, records are cached and served with a TTL of at most this many seconds, so that
+    /// changes to zones with very long TTLs are picked up in time.
+    pub max_ttl: Option<u32>,
+
+    /// The most entries the cache holds, `DEFAULT_MAX_ENTRIES` if unset. Once full, the entry
+    /// closest to its expiry makes room for the new one.
+    pub max_entries: Option<usize>,
+
+    /// When set, expired entries are kept for this many more seconds, to be served when the
+    /// servers which would refresh them cannot be reached (RFC 8767). See `Cache::or_stale()`.
+    pub serve_stale: Option<u32>,
+}
+
+/// Counters of the lookups in a `Cache` and of the entries it dropped, for embedders to monitor
+/// how effective it is. See `Cache::stats()`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+
+    /// Lookups the cache could not answer, including those of expired entries.
+    pub misses: u64,
+
+    /// Entries dropped because they expired or to make room for others.
+    pub evictions: u64,
+
+    /// Lookups answered from the cache that the name does not exist or has no records of the
+    /// type. They are counted among the hits too.
+    pub negative_hits: u64,
+
+    /// Expired entries served as the servers which would refresh them could not be reached.
+    pub stale_serves: u64,
+}
+
+/// A cached response along with its lifetime.
+struct CacheEntry {
+    /// The cached response.
+    packet: Packet,
+
+    /// When the response was stored.
+    stored_at: Instant,
+
+    /// The TTL the response was stored with.
+    ttl: Duration,
+
+    /// How long the entry is kept once expired, to be served stale.
+    stale_for: Duration,
+
+    /// Whether the entry is already waiting in the prefetch queue.
+    prefetch_queued: bool,
+
+    /// The position of the entry in `Entries::by_expiry`.
+    expiry: (Instant, u64),
+}
+
+/// The cached responses, along with the order in which they expire so that expired entries can
+/// be swept, and the entry closest to its expiry evicted, without going through all of them.
+#[derive(Default)]
+struct Entries {
+    /// The entries by question.
+    by_key: HashMap<CacheKey, CacheEntry>,
+
+    /// The questions of the entries by when they are dropped, i.e. once expired and no longer
+    /// served stale, ties broken by order of insertion.
+    by_expiry: BTreeMap<(Instant, u64), CacheKey>,
+
+    /// The number of entries inserted so far, to break ties in `by_expiry`.
+    inserted: u64,
+}
+
+impl Entries {
+    /// Add an entry, replacing any entry of the same question.
+    ///
+    /// # Arguments
+    /// * `key`: The question the response answers.
+    /// * `entry`: The entry, whose position in `by_expiry` is set here.
+    fn insert(&mut self, key: CacheKey, mut entry: CacheEntry) {
+        self.remove(&key);
+        entry.expiry = (entry.stored_at + entry.ttl + entry.stale_for, self.inserted);
+        self.inserted += 1;
+        self.by_expiry.insert(entry.expiry, key.clone());
+        self.by_key.insert(key, entry);
+    }
+
+    /// Remove the entry of the given question, if any.
+    ///
+    /// # Argument
+    /// * `key`: The question to remove the entry of.
+    fn remove(&mut self, key: &CacheKey) -> Option<CacheEntry> {
+        let entry = self.by_key.remove(key)?;
+        self.by_expiry.remove(&entry.expiry);
+        Some(entry)
+    }
+
+    /// Remove the entry closest to its expiry, returning whether there was one.
+    fn remove_first(&mut self) -> bool {
+        let Some((_, key)) = self.by_expiry.pop_first() else {
+            return false;
+        };
+        self.by_key.remove(&key);
+        true
+    }
+
+    /// Remove the entries which have expired and may no longer be served stale, returning how
+    /// many there were.
+    ///
+    /// # Argument
+    /// * `now`: The current time.
+    fn sweep(&mut self, now: Instant) -> usize {
+        let mut swept = 0;
+        while self
+            .by_expiry
+            .first_key_value()
+            .is_some_and(|((expires_at, _), _)| *expires_at <= now)
+        {
+            self.remove_first();
+            swept += 1;
+        }
+        swept
+    }
+}
+
+/// A thread-safe cache of responses, keyed by question. Entries live for the lowest TTL among
+/// their answers, or for negative answers as long as their SOA record allows, and are swept once
+/// expired whenever a response is stored. The number of entries is bounded, see
+/// `CacheConfig::max_entries`.
+#[derive(Default)]
+pub struct Cache {
+    /// Tunables of the cache.
+    config: CacheConfig,
+
+    /// The cached responses.
+    entries: Mutex<Entries>,
+
+    /// Keys of near-expiry entries which should be refreshed off the request path.
+    prefetch_queue: Mutex<Vec<CacheKey>>,
+
+    /// Counters of the lookups and of the dropped entries.
+    stats: Mutex<CacheStats>,
+
+    /// Where the counters are also reported, if anywhere. See `set_metrics()`.
+    metrics: Mutex<Option<Metrics>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. The cache only ever holds
+/// complete entries, so its contents remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl CacheEntry {
+    /// The response, with the TTLs of its records counted down by the time elapsed since it was
+    /// stored.
+    ///
+    /// # Argument
+    /// * `elapsed`: The time elapsed since the response was stored.
+    fn aged(&self, elapsed: Duration) -> Packet {
+        let elapsed_secs = elapsed.as_secs() as u32;
+        let mut packet = self.packet.clone();
+        for record in records_mut(&mut packet) {
+            record.ttl = record.ttl.saturating_sub(elapsed_secs);
+        }
+        packet
+    }
+}
+
+/// How long a negative answer, i.e. NXDOMAIN or NODATA, may be cached: the lower of the TTL of
+/// the SOA record in its authority section and the minimum field of that record (RFC 2308,
+/// section 5). Responses without an SOA record are not cached.
+///
+/// # Argument
+/// * `packet`: The response.
+fn negative_ttl(packet: &Packet) -> Option<Ttl> {
+    if !packet.answers.is_empty()
+        || !matches!(packet.header.rcode(), RCODE_NO_ERROR | RCODE_NAME_ERROR)
+    {
+        return None;
+    }
+    let soa = packet
+        .authorities
+        .iter()
+        .find(|record| record.r_type == RecordType::SOA)?;
+    let minimum = soa.data.get(soa.data.len().checked_sub(4)?..)?;
+    let minimum = u32::from_be_bytes(minimum.try_into().ok()?);
+    Some(soa.ttl.min(Ttl::from(minimum)))
+}
+
+/// The records of a response which have a TTL, leaving out the OPT record whose TTL field holds
+/// flags instead.
+///
+/// # Argument
+/// * `packet`: The response.
+fn records_mut(packet: &mut Packet) -> impl Iterator<Item = &mut Record> {
+    packet
+        .answers
+        .iter_mut()
+        .chain(packet.authorities.iter_mut())
+        .chain(packet.additionals.iter_mut())
+        .filter(|record| record.r_type != RecordType::OPT)
+}
+
+impl Cache {
+    /// Create an empty cache.
+    ///
+    /// # Argument
+    /// * `config`: Tunables of the cache.
+    pub fn new(config: CacheConfig) -> Cache {
+        Cache {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Store a response, with its TTLs brought within the configured bounds, after sweeping the
+    /// expired entries. When the cache is still full, the entry closest to its expiry is evicted.
+    /// Responses without answers, unless they are negative answers, with a TTL of 0 or truncated
+    /// are not cached.
+    ///
+    /// # Arguments
+    /// * `key`: The question the response answers.
+    /// * `packet`: The response.
+    /// * `now`: The current time.
+    pub fn insert(&self, key: CacheKey, packet: &Packet, now: Instant) {
+        if packet.header.has_flag(FLAG_TRUNCATED) {
+            return;
+        }
+        let Some(ttl) = packet
+            .answers
+            .iter()
+            .map(|answer| answer.ttl)
+            .min()
+            .or_else(|| negative_ttl(packet))
+        else {
+            return;
+        };
+        if ttl == 0 {
+            return;
+        }
+
+        let mut packet = packet.clone();
+        for record in records_mut(&mut packet) {
+            record.ttl = self.clamp_ttl(record.ttl);
+        }
+        let mut entries = lock(&self.entries);
+        let mut evicted = entries.sweep(now);
+        let max_entries = self.config.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES);
+        while !entries.by_key.contains_key(&key)
+            && entries.by_key.len() >= max_entries
+            && entries.remove_first()
+        {
+            evicted += 1;
+        }
+        entries.insert(
+            key,
+            CacheEntry {
+                packet,
+                stored_at: now,
+                ttl: self.clamp_ttl(ttl).duration(),
+                stale_for: self.stale_for(),
+                prefetch_queued: false,
+                expiry: (now, 0),
+            },
+        );
+        drop(entries);
+        for _ in 0..evicted {
+            self.record_eviction();
+        }
+    }
+
+    /// Retrieve a response which has not yet expired. The TTLs of the returned records reflect the
+    /// time remaining. When prefetching is enabled and the entry is close to expiry, its key is
+    /// queued for `take_prefetch_queue()`. Expired entries are dropped, unless they may still be
+    /// served stale.
+    ///
+    /// # Arguments
+    /// * `key`: The question to look up.
+    /// * `now`: The current time.
+    pub fn get(&self, key: &CacheKey, now: Instant) -> Option<Packet> {
+        let mut entries = lock(&self.entries);
+        let Some(entry) = entries.by_key.get_mut(key) else {
+            self.record_lookup(false);
+            return None;
+        };
+
+        let elapsed = now.saturating_duration_since(entry.stored_at);
+        if elapsed >= entry.ttl {
+            if elapsed >= entry.ttl + entry.stale_for {
+                entries.remove(key);
+                self.record_eviction();
+            }
+            self.record_lookup(false);
+            return None;
+        }
+
+        let remaining = entry.ttl - elapsed;
+        if let Some(percent) = self.config.prefetch_percent {
+            if !entry.prefetch_queued && remaining < entry.ttl * percent as u32 / 100 {
+                entry.prefetch_queued = true;
+                lock(&self.prefetch_queue).push(key.clone());
+            }
+        }
+
+        self.record_lookup(true);
+        if entry.packet.answers.is_empty() {
+            self.record_negative_hit();
+        }
+        Some(entry.aged(elapsed))
+    }
+
+    /// The outcome of resolving a question, or when no server could be reached, the expired
+    /// entry of the question if it may still be served stale. Its records are served with a TTL
+    /// of 30 seconds, so that clients soon ask again (RFC 8767, section 4).
+    ///
+    /// # Arguments
+    /// * `key`: The question resolved.
+    /// * `result`: The response to the question, or the reason there is none.
+    /// * `now`: The current time.
+    pub fn or_stale(
+        &self,
+        key: &CacheKey,
+        result: Result<Packet, DnsError>,
+        now: Instant,
+    ) -> Result<Packet, DnsError> {
+        let error = match result {
+            Err(error) if error.category() == ErrorCategory::Transient => error,
+            result => return result,
+        };
+        let entries = lock(&self.entries);
+        let Some(entry) = entries.by_key.get(key) else {
+            return Err(error);
+        };
+        let elapsed = now.saturating_duration_since(entry.stored_at);
+        if elapsed < entry.ttl || elapsed >= entry.ttl + entry.stale_for {
+            return Err(error);
+        }
+        let mut packet = entry.packet.clone();
+        drop(entries);
+        for record in records_mut(&mut packet) {
+            record.ttl = Ttl::from(STALE_TTL);
+        }
+        self.record_stale_serve();
+        Ok(packet)
+    }
+
+    /// Report the counters to the given metrics as well from now on, e.g. those of the server the
+    /// cache belongs to.
+    ///
+    /// # Argument
+    /// * `metrics`: The metrics to add to.
+    pub fn set_metrics(&self, metrics: Metrics) {
+        *lock(&self.metrics) = Some(metrics);
+    }
+
+    /// The counters of the lookups made so far and of the entries dropped.
+    pub fn stats(&self) -> CacheStats {
+        *lock(&self.stats)
+    }
+
+    /// How long entries are kept once expired, to be served stale.
+    fn stale_for(&self) -> Duration {
+        Duration::from_secs(self.config.serve_stale.unwrap_or(0).into())
+    }
+
+    /// Bring a TTL within the configured bounds.
+    ///
+    /// # Argument
+    /// * `ttl`: The TTL of a record.
+    fn clamp_ttl(&self, ttl: Ttl) -> Ttl {
+        ttl.clamped(
+            self.config.min_ttl.unwrap_or(0),
+            self.config.max_ttl.unwrap_or(u32::MAX),
+        )
+    }
+
+    /// Count a lookup.
+    ///
+    /// # Argument
+    /// * `hit`: Whether the cache had the answer.
+    fn record_lookup(&self, hit: bool) {
+        let mut stats = lock(&self.stats);
+        match hit {
+            true => stats.hits += 1,
+            false => stats.misses += 1,
+        }
+        if let Some(metrics) = &*lock(&self.metrics) {
+            metrics.record_cache_lookup(hit);
+        }
+    }
+
+    /// Count an entry dropped because it expired or to make room.
+    fn record_eviction(&self) {
+        lock(&self.stats).evictions += 1;
+        if let Some(metrics) = &*lock(&self.metrics) {
+            metrics.record_cache_eviction();
+        }
+    }
+
+    /// Count a lookup answered with a negative answer.
+    fn record_negative_hit(&self) {
+        lock(&self.stats).negative_hits += 1;
+        if let Some(metrics) = &*lock(&self.metrics) {
+            metrics.record_cache_negative_hit();
+        }
+    }
+
+    /// Count an expired entry served stale.
+    fn record_stale_serve(&self) {
+        lock(&self.stats).stale_serves += 1;
+        if let Some(metrics) = &*lock(&self.metrics) {
+            metrics.record_cache_stale_serve();
+        }
+    }
+
+    /// The entries which have not yet expired, sorted by name and record type, along with the time
+    /// they have left. The TTLs of the records reflect the time remaining. Unlike `get()`, this
+    /// leaves the entries as they are, e.g. for an operator to inspect.
+    ///
+    /// # Argument
+    /// * `now`: The current time.
+    pub fn dump(&self, now: Instant) -> Vec<(CacheKey, Duration, Packet)> {
+        let mut entries: Vec<(CacheKey, Duration, Packet)> = lock(&self.entries)
+            .by_key
+            .iter()
+            .filter_map(|(key, entry)| {
+                let elapsed = now.saturating_duration_since(entry.stored_at);
+                let remaining = entry
+                    .ttl
+                    .checked_sub(elapsed)
+                    .filter(|left| !left.is_zero())?;
+                Some((key.clone(), remaining, entry.aged(elapsed)))
+            })
+            .collect();
+        entries.sort_by_key(|(key, _, _)| {
+            (key.domain_name.clone(), RecordType::value(key.record_type))
+        });
+        entries
+    }
+
+    /// Remove the entries of the given name, whatever their record type, or every entry. Returns
+    /// the number of entries removed.
+    ///
+    /// # Argument
+    /// * `domain_name`: The name whose entries to remove, or `None` to empty the cache.
+    pub fn flush(&self, domain_name: Option<&str>) -> usize {
+        let mut entries = lock(&self.entries);
+        let before = entries.by_key.len();
+        match domain_name {
+            Some(domain_name) => {
+                let domain_name = CacheKey::normalize(domain_name);
+                let keys: Vec<CacheKey> = entries
+                    .by_key
+                    .keys()
+                    .filter(|key| key.domain_name == domain_name)
+                    .cloned()
+                    .collect();
+                for key in keys {
+                    entries.remove(&key);
+                }
+            }
+            None => *entries = Entries::default(),
+        }
+        before - entries.by_key.len()
+    }
+
+    /// Take the keys of all entries that are due to be refreshed, leaving the queue empty.
+    pub fn take_prefetch_queue(&self) -> Vec<CacheKey> {
+        std::mem::take(&mut *lock(&self.prefetch_queue))
+    }
+
+    /// The number of entries in the cache, including ones which have expired but have not yet been
+    /// swept or looked up since.
+    pub fn len(&self) -> usize {
+        lock(&self.entries).by_key.len()
+    }
+
+    /// Whether the cache has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+fn test_packet(ttl: u32) -> Packet {
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+
+    Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(ttl),
+            data: vec![93, 184, 216, 34],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    }
+}
+
+/// Validate that cached entries are served with decreasing TTLs until they expire.
+#[test]
+fn test_cache_expiry() {
+    let cache = Cache::new(CacheConfig::default());
+    let key = CacheKey::new("Example.com.", RecordType::A);
+    let now = Instant::now();
+
+    cache.insert(key.clone(), &test_packet(100), now);
+
+    let packet = cache.get(
+        &CacheKey::new("example.com", RecordType::A),
+        now + Duration::from_secs(40),
+    );
+    assert_eq!(packet.unwrap().answers[0].ttl, 60);
+
+    assert!(cache.get(&key, now + Duration::from_secs(100)).is_none());
+    assert!(cache.is_empty());
+}
+
+/// Validate that responses without answers or with a zero TTL are not cached.
+#[test]
+fn test_cache_skips_uncacheable_responses() {
+    let cache = Cache::new(CacheConfig::default());
+    let now = Instant::now();
+
+    cache.insert(
+        CacheKey::new("example.com", RecordType::A),
+        &test_packet(0),
+        now,
+    );
+
+    let mut empty = test_packet(100);
+    empty.answers.clear();
+    cache.insert(CacheKey::new("example.com", RecordType::AAAA), &empty, now);
+
+    assert!(cache.is_empty());
+}
+
+/// Validate that near-expiry entries are queued for prefetching exactly once.
+#[test]
+fn test_cache_prefetch_queue() {
+    let cache = Cache::new(CacheConfig {
+        prefetch_percent: Some(10),
+        ..Default::default()
+    });
+    let key = CacheKey::new("example.com", RecordType::A);
+    let now = Instant::now();
+
+    cache.insert(key.clone(), &test_packet(100), now);
+
+    // Plenty of TTL remains, so nothing should be queued.
+    assert!(cache.get(&key, now + Duration::from_secs(50)).is_some());
+    assert!(cache.take_prefetch_queue().is_empty());
+
+    // Within the last 10% of the TTL, the entry is still served but queued for a refresh.
+    assert!(cache.get(&key, now + Duration::from_secs(95)).is_some());
+    assert!(cache.get(&key, now + Duration::from_secs(96)).is_some());
+    assert_eq!(cache.take_prefetch_queue(), vec![key.clone()]);
+    assert!(cache.take_prefetch_queue().is_empty());
+}
+
+/// Validate that prefetching is off unless configured.
+#[test]
+fn test_cache_without_prefetch() {
+    let cache = Cache::new(CacheConfig::default());
+    let key = CacheKey::new("example.com", RecordType::A);
+    let now = Instant::now();
+
+    cache.insert(key.clone(), &test_packet(100), now);
+    assert!(cache.get(&key, now + Duration::from_secs(99)).is_some());
+    assert!(cache.take_prefetch_queue().is_empty());
+}
+
+/// Validate that dumping lists the live entries with their remaining time, leaving them as they
+/// are, and that entries are flushed by name or all at once.
+#[test]
+fn test_cache_dump_and_flush() {
+    let cache = Cache::new(CacheConfig {
+        prefetch_percent: Some(50),
+        ..Default::default()
+    });
+    let now = Instant::now();
+    cache.insert(
+        CacheKey::new("example.com", RecordType::AAAA),
+        &test_packet(100),
+        now,
+    );
+    cache.insert(
+        CacheKey::new("example.com", RecordType::A),
+        &test_packet(100),
+        now,
+    );
+    cache.insert(
+        CacheKey::new("example.org", RecordType::A),
+        &test_packet(10),
+        now,
+    );
+
+    let later = now + Duration::from_secs(60);
+    let entries = cache.dump(later);
+    let keys: Vec<(&str, RecordType, Duration)> = entries
+        .iter()
+        .map(|(key, remaining, _)| (key.domain_name.as_str(), key.record_type, *remaining))
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            ("example.com", RecordType::A, Duration::from_secs(40)),
+            ("example.com", RecordType::AAAA, Duration::from_secs(40))
+        ]
+    );
+    assert_eq!(entries[0].2.answers[0].ttl, 40);
+    assert!(cache.take_prefetch_queue().is_empty());
+    assert_eq!(cache.len(), 3);
+
+    assert_eq!(cache.flush(Some("EXAMPLE.com.")), 2);
+    assert_eq!(cache.flush(Some("example.com")), 0);
+    assert_eq!(cache.flush(None), 1);
+    assert!(cache.is_empty());
+}
+
+/// Validate that hits, misses and expired entries are counted, and reported to the metrics set.
+#[test]
+fn test_cache_stats() {
+    let cache = Cache::new(CacheConfig::default());
+    let metrics = Metrics::default();
+    cache.set_metrics(metrics.clone());
+    let key = CacheKey::new("example.com", RecordType::A);
+    let now = Instant::now();
+
+    assert!(cache.get(&key, now).is_none());
+    cache.insert(key.clone(), &test_packet(100), now);
+    assert!(cache.get(&key, now + Duration::from_secs(10)).is_some());
+    assert!(cache.get(&key, now + Duration::from_secs(20)).is_some());
+    assert!(cache.get(&key, now + Duration::from_secs(100)).is_none());
+    assert!(cache.get(&key, now + Duration::from_secs(110)).is_none());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 2,
+            misses: 3,
+            evictions: 1,
+            ..Default::default()
+        }
+    );
+    let output = metrics.render();
+    for line in [
+        "toy_dns_cache_hits_total 2",
+        "toy_dns_cache_misses_total 3",
+        "toy_dns_cache_evictions_total 1",
+    ] {
+        assert!(output.lines().any(|rendered| rendered == line), "{}", line);
+    }
+}
+
+/// Validate that negative answers are cached for as long as their SOA record allows, and counted
+/// when served.
+#[test]
+fn test_cache_negative_answers() {
+    use crate::header::RCODE_NAME_ERROR;
+    use crate::zone::Zone;
+
+    let cache = Cache::new(CacheConfig::default());
+    let metrics = Metrics::default();
+    cache.set_metrics(metrics.clone());
+    let key = CacheKey::new("missing.example.com", RecordType::A);
+    let now = Instant::now();
+
+    let zone = Zone::parse(
+        "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 1 3600 900 604800 60",
+        ".",
+    )
+    .unwrap();
+    let mut packet = test_packet(100);
+    packet.answers.clear();
+    packet.header.flags |= RCODE_NAME_ERROR;
+    packet.authorities = zone.records;
+    cache.insert(key.clone(), &packet, now);
+
+    // The SOA minimum is lower than its TTL, so it bounds how long the answer is cached.
+    let cached = cache.get(&key, now + Duration::from_secs(30)).unwrap();
+    assert_eq!(cached.header.rcode(), RCODE_NAME_ERROR);
+    assert!(cache.get(&key, now + Duration::from_secs(60)).is_none());
+
+    // Without an SOA record, a response without answers is still not cached.
+    packet.authorities.clear();
+    cache.insert(key.clone(), &packet, now);
+    assert!(cache.is_empty());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 1,
+            misses: 1,
+            evictions: 1,
+            negative_hits: 1,
+            stale_serves: 0,
+        }
+    );
+    let output = metrics.render();
+    assert!(output
+        .lines()
+        .any(|line| line == "toy_dns_cache_negative_hits_total 1"));
+}
+
+/// Validate that expired entries are kept as configured, and served stale only when the question
+/// could not be resolved as no server was reached.
+#[test]
+fn test_cache_serve_stale() {
+    let cache = Cache::new(CacheConfig {
+        serve_stale: Some(3600),
+        ..Default::default()
+    });
+    let metrics = Metrics::default();
+    cache.set_metrics(metrics.clone());
+    let key = CacheKey::new("example.com", RecordType::A);
+    let now = Instant::now();
+    let timeout = || Err(DnsError::SocketRead(None));
+
+    cache.insert(key.clone(), &test_packet(100), now);
+
+    // A live entry is not served stale, nor is anything once the question is resolved.
+    let live = now + Duration::from_secs(50);
+    assert_eq!(
+        cache.or_stale(&key, timeout(), live),
+        Err(DnsError::SocketRead(None))
+    );
+
+    // Once expired, the entry is a miss but it is kept.
+    let expired = now + Duration::from_secs(200);
+    assert!(cache.get(&key, expired).is_none());
+    assert_eq!(cache.len(), 1);
+    let stale = cache.or_stale(&key, timeout(), expired).unwrap();
+    assert_eq!(stale.answers[0].ttl, STALE_TTL);
+    let fresh = test_packet(300);
+    assert_eq!(cache.or_stale(&key, Ok(fresh.clone()), expired), Ok(fresh));
+    assert_eq!(
+        cache.or_stale(&key, Err(DnsError::UnknownDomainName), expired),
+        Err(DnsError::UnknownDomainName)
+    );
+
+    // Past the stale period, the entry is dropped.
+    let gone = now + Duration::from_secs(3700);
+    assert!(cache.or_stale(&key, timeout(), gone).is_err());
+    assert!(cache.get(&key, gone).is_none());
+    assert!(cache.is_empty());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            hits: 0,
+            misses: 2,
+            evictions: 1,
+            negative_hits: 0,
+            stale_serves: 1,
+        }
+    );
+    let output = metrics.render();
+    assert!(output
+        .lines()
+        .any(|line| line == "toy_dns_cache_stale_serves_total 1"));
+}
+
+/// Validate that TTLs are brought within the configured bounds, both for how long entries live and
+/// for the records served, leaving the OPT record alone.
+#[test]
+fn test_cache_ttl_clamping() {
+    use crate::edns::Edns;
+
+    let cache = Cache::new(CacheConfig {
+        min_ttl: Some(5),
+        max_ttl: Some(86400),
+        ..Default::default()
+    });
+    let now = Instant::now();
+
+    let short = CacheKey::new("example.com", RecordType::A);
+    cache.insert(short.clone(), &test_packet(1), now);
+    let packet = cache.get(&short, now + Duration::from_secs(3)).unwrap();
+    assert_eq!(packet.answers[0].ttl, 2);
+    assert!(cache.get(&short, now + Duration::from_secs(5)).is_none());
+
+    let long = CacheKey::new("example.com", RecordType::AAAA);
+    let mut packet = test_packet(604800);
+    let opt = Edns {
+        dnssec_ok: true,
+        ..Edns::new(1232)
+    };
+    packet.additionals.push(opt.to_record().unwrap());
+    cache.insert(long.clone(), &packet, now);
+    let packet = cache.get(&long, now + Duration::from_secs(100)).unwrap();
+    assert_eq!(packet.answers[0].ttl, 86300);
+    assert_eq!(packet.edns(), Ok(Some(opt)));
+    assert!(cache.get(&long, now + Duration::from_secs(86400)).is_none());
+
+    let zero = CacheKey::new("example.org", RecordType::A);
+    cache.insert(zero.clone(), &test_packet(0), now);
+    assert!(cache.get(&zero, now).is_none());
+}
+
+/// Validate that the cache stays within its capacity, sweeping expired entries first and then
+/// evicting those closest to their expiry.
+#[test]
+fn test_cache_capacity() {
+    let cache = Cache::new(CacheConfig {
+        max_entries: Some(2),
+        ..Default::default()
+    });
+    let now = Instant::now();
+    let short = CacheKey::new("short.example.com", RecordType::A);
+    let long = CacheKey::new("long.example.com", RecordType::A);
+    let new = CacheKey::new("new.example.com", RecordType::A);
+
+    cache.insert(short.clone(), &test_packet(10), now);
+    cache.insert(long.clone(), &test_packet(100), now);
+    cache.insert(new.clone(), &test_packet(50), now);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&short, now).is_none());
+    assert!(cache.get(&long, now).is_some());
+
+    // Replacing an entry makes no room.
+    cache.insert(long.clone(), &test_packet(100), now);
+    assert!(cache.get(&new, now).is_some());
+
+    // Expired entries are swept whatever the capacity.
+    cache.insert(
+        short.clone(),
+        &test_packet(10),
+        now + Duration::from_secs(60),
+    );
+    assert_eq!(cache.len(), 2);
+    assert!(cache.get(&new, now + Duration::from_secs(60)).is_none());
+    assert_eq!(cache.stats().evictions, 2);
+
+    for index in 0..100 {
+        let key = CacheKey::new(&format!("{}.example.com", index), RecordType::A);
+        cache.insert(key, &test_packet(100 + index), now);
+        assert!(cache.len() <= 2);
+    }
+}
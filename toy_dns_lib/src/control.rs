@@ -0,0 +1,270 @@
+use crate::cache::{Cache, CacheKey};
+use crate::errors::DnsError;
+use log::{info, warn};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Address the control socket listens on unless configured otherwise, the port of unbound's.
+pub const DEFAULT_CONTROL_ADDRESS: &str = "127.0.0.1:8953";
+
+/// Largest command read from an operator.
+const MAX_COMMAND_SIZE: u64 = 1024;
+
+/// How long an operator may take to send a command, and the server to reply.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the server made of a control command.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ControlReply {
+    /// The command was carried out, with the given output.
+    Done(String),
+
+    /// The command was not carried out, for the given reason.
+    Refused(String),
+}
+
+/// Lets operators inspect a running server over a TCP connection, one command per connection,
+/// e.g. `cache dump`, `cache get example.com` or `cache flush example.com`. The server replies
+/// `ok` followed by the output, or `error:` followed by the reason. The socket has no
+/// authentication, so it should only be reachable from the host itself.
+pub struct Control {
+    /// The caches of the server, shared with its resolver and its forwarder.
+    caches: Vec<Arc<Cache>>,
+}
+
+impl Control {
+    /// Create a control socket handler for the given caches.
+    ///
+    /// # Argument
+    /// * `caches`: The caches of the server.
+    pub fn new(caches: Vec<Arc<Cache>>) -> Control {
+        Control { caches }
+    }
+
+    /// Carry out a command:
+    /// * `cache dump`: List the cached responses, with the time each has left.
+    /// * `cache get NAME`: List the cached responses for NAME, whatever their record type.
+    /// * `cache flush [NAME]`: Remove the cached responses for NAME, or every cached response.
+    ///
+    /// # Arguments
+    /// * `command`: The command, as sent by the operator.
+    /// * `now`: The current time.
+    pub fn execute(&self, command: &str, now: Instant) -> ControlReply {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        if words.first() != Some(&"cache") {
+            return ControlReply::Refused(format!("unknown command \"{}\"", command.trim()));
+        }
+        if self.caches.is_empty() {
+            return ControlReply::Refused("the server has no cache".to_owned());
+        }
+
+        match words[1..] {
+            ["dump"] => ControlReply::Done(self.render_entries(None, now)),
+            ["get", domain_name] => ControlReply::Done(self.render_entries(Some(domain_name), now)),
+            ["flush"] | ["flush", _] => {
+                let domain_name = words.get(2).copied();
+                let flushed: usize = self
+                    .caches
+                    .iter()
+                    .map(|cache| cache.flush(domain_name))
+                    .sum();
+                info!("Flushed {} cache entries", flushed);
+                ControlReply::Done(format!("; flushed {} entries\n", flushed))
+            }
+            _ => ControlReply::Refused(format!("unknown command \"{}\"", command.trim())),
+        }
+    }
+
+    /// Render the live cache entries, each introduced by a comment with its question and the time
+    /// it has left, followed by its records in presentation format.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name whose entries to render, or `None` for every entry.
+    /// * `now`: The current time.
+    fn render_entries(&self, domain_name: Option<&str>, now: Instant) -> String {
+        let wanted = domain_name.map(CacheKey::normalize);
+        let mut output = String::new();
+        for (key, remaining, packet) in self.caches.iter().flat_map(|cache| cache.dump(now)) {
+            if wanted
+                .as_ref()
+                .is_some_and(|wanted| *wanted != key.domain_name)
+            {
+                continue;
+            }
+            _ = writeln!(
+                output,
+                "; {} {}, expires in {}s",
+                key.domain_name,
+                key.record_type,
+                remaining.as_secs()
+            );
+            for record in packet
+                .answers
+                .iter()
+                .chain(&packet.authorities)
+                .chain(&packet.additionals)
+            {
+                _ = writeln!(output, "{}", record);
+            }
+        }
+        if output.is_empty() {
+            match domain_name {
+                Some(domain_name) => _ = writeln!(output, "; {} is not cached", domain_name),
+                None => output.push_str("; the cache is empty\n"),
+            }
+        }
+        output
+    }
+
+    /// Answer commands on the listener until accepting connections fails. Commands are handled
+    /// one at a time, which is plenty for operators.
+    ///
+    /// # Argument
+    /// * `listener`: The bound listener to serve on.
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(accept_error) => {
+                    warn!("Stopped serving the control socket: {}", accept_error);
+                    return;
+                }
+            };
+            if let Err(respond_error) = self.respond(stream) {
+                info!("Could not answer a control command: {}", respond_error);
+            }
+        }
+    }
+
+    /// Read a command from an operator and reply to it.
+    ///
+    /// # Argument
+    /// * `stream`: The connection to the operator.
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+        let mut command = String::new();
+        BufReader::new((&stream).take(MAX_COMMAND_SIZE)).read_line(&mut command)?;
+        info!("Control command: {}", command.trim());
+        let reply = match self.execute(&command, Instant::now()) {
+            ControlReply::Done(output) => format!("ok\n{}", output),
+            ControlReply::Refused(reason) => format!("error: {}\n", reason),
+        };
+        stream.write_all(reply.as_bytes())
+    }
+}
+
+/// Send a command to the control socket of a running server and read its reply.
+///
+/// # Arguments
+/// * `address`: The address of the control socket, e.g. `DEFAULT_CONTROL_ADDRESS`.
+/// * `command`: The command, e.g. `cache dump`. See `Control::execute()`.
+pub fn send_command(address: &str, command: &str) -> Result<ControlReply, DnsError> {
+    let mut stream =
+        TcpStream::connect(address).map_err(|error| DnsError::SocketConnect(Some(error)))?;
+    stream
+        .set_read_timeout(Some(COMMAND_TIMEOUT))
+        .map_err(|error| DnsError::SocketConnect(Some(error)))?;
+    stream
+        .write_all(format!("{}\n", command).as_bytes())
+        .and_then(|_| stream.shutdown(Shutdown::Write))
+        .map_err(|error| DnsError::SocketSend(Some(error)))?;
+    let mut reply = String::new();
+    stream
+        .read_to_string(&mut reply)
+        .map_err(|error| DnsError::SocketRead(Some(error)))?;
+
+    if let Some(output) = reply.strip_prefix("ok\n") {
+        return Ok(ControlReply::Done(output.to_owned()));
+    }
+    match reply.strip_prefix("error: ") {
+        Some(reason) => Ok(ControlReply::Refused(reason.trim_end().to_owned())),
+        None => Err(DnsError::Control),
+    }
+}
+
+/// Validate the output of cache commands.
+#[test]
+fn test_control_cache_commands() {
+    use crate::cache::CacheConfig;
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+    use crate::packet::Packet;
+    use crate::record::{Record, RecordType};
+    use crate::ttl::Ttl;
+
+    let cache = Arc::new(Cache::new(CacheConfig::default()));
+    let control = Control::new(vec![Arc::clone(&cache)]);
+    let now = Instant::now();
+    assert_eq!(
+        control.execute("cache dump", now),
+        ControlReply::Done("; the cache is empty\n".to_owned())
+    );
+
+    let packet = Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![192, 0, 2, 1],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    };
+    cache.insert(CacheKey::new("example.com", RecordType::A), &packet, now);
+
+    let later = now + Duration::from_secs(100);
+    let expected = "; example.com A, expires in 200s\nexample.com. 200 IN A 192.0.2.1\n";
+    assert_eq!(
+        control.execute("cache dump\n", later),
+        ControlReply::Done(expected.to_owned())
+    );
+    assert_eq!(
+        control.execute("cache get Example.com.", later),
+        ControlReply::Done(expected.to_owned())
+    );
+    assert_eq!(
+        control.execute("cache get example.org", later),
+        ControlReply::Done("; example.org is not cached\n".to_owned())
+    );
+    assert_eq!(
+        control.execute("cache flush example.com", later),
+        ControlReply::Done("; flushed 1 entries\n".to_owned())
+    );
+    assert!(cache.is_empty());
+
+    assert_eq!(
+        control.execute("cache purge", later),
+        ControlReply::Refused("unknown command \"cache purge\"".to_owned())
+    );
+    assert_eq!(
+        Control::new(vec![]).execute("cache dump", later),
+        ControlReply::Refused("the server has no cache".to_owned())
+    );
+}
+
+/// Validate that commands sent to the control socket are answered over TCP.
+#[test]
+fn test_control_socket() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let control = Control::new(vec![Arc::new(Cache::new(CacheConfig::default()))]);
+    std::thread::spawn(move || control.serve(listener));
+
+    assert_eq!(
+        send_command(&address, "cache flush")?,
+        ControlReply::Done("; flushed 0 entries\n".to_owned())
+    );
+    assert_eq!(
+        send_command(&address, "stop")?,
+        ControlReply::Refused("unknown command \"stop\"".to_owned())
+    );
+    Ok(())
+}
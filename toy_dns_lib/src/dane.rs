@@ -0,0 +1,502 @@
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use data_encoding::HEXUPPER;
+use ring::digest::{digest, SHA256, SHA512};
+use std::fmt;
+
+/// The DER tag of a SEQUENCE.
+const DER_SEQUENCE: u8 = 0x30;
+
+/// The DER tag of the explicit version field of a certificate, `[0]`.
+const DER_VERSION: u8 = 0xa0;
+
+/// Certificate usage: a CA certificate of the chain, which must also pass PKIX validation
+/// (RFC 6698, section 2.1.1).
+pub const USAGE_PKIX_TA: u8 = 0;
+
+/// Certificate usage: the server certificate, which must also pass PKIX validation.
+pub const USAGE_PKIX_EE: u8 = 1;
+
+/// Certificate usage: a CA certificate of the chain, trusted as is.
+pub const USAGE_DANE_TA: u8 = 2;
+
+/// Certificate usage: the server certificate, trusted as is.
+pub const USAGE_DANE_EE: u8 = 3;
+
+/// Selector: the whole certificate is matched (RFC 6698, section 2.1.2).
+pub const SELECTOR_CERTIFICATE: u8 = 0;
+
+/// Selector: the SubjectPublicKeyInfo of the certificate is matched.
+pub const SELECTOR_PUBLIC_KEY: u8 = 1;
+
+/// Matching type: the selected part is matched as is (RFC 6698, section 2.1.3).
+pub const MATCHING_EXACT: u8 = 0;
+
+/// Matching type: the SHA-256 digest of the selected part is matched.
+pub const MATCHING_SHA256: u8 = 1;
+
+/// Matching type: the SHA-512 digest of the selected part is matched.
+pub const MATCHING_SHA512: u8 = 2;
+
+/// A DER element split off the data it starts.
+struct DerElement<'a> {
+    /// The tag of the element, e.g. `DER_SEQUENCE`.
+    tag: u8,
+
+    /// The contents of the element, without its tag and length.
+    contents: &'a [u8],
+
+    /// The whole element, with its tag and length.
+    encoded: &'a [u8],
+
+    /// The data following the element.
+    rest: &'a [u8],
+}
+
+/// Split the DER element at the start of the data off the rest. Only tags which fit in a single
+/// octet are supported, which is all a certificate needs up to its public key.
+///
+/// # Argument
+/// * `data`: The data starting with the element.
+fn der_element(data: &[u8]) -> Result<DerElement<'_>, DnsError> {
+    let (Some(&tag), Some(&length)) = (data.first(), data.get(1)) else {
+        return Err(DnsError::Certificate);
+    };
+    if tag & 0x1f == 0x1f {
+        return Err(DnsError::Certificate);
+    }
+
+    // Lengths from 128 on are given in as many octets as the low bits of the first one say.
+    let (header_length, length) = match length {
+        0..=0x7f => (2, length as usize),
+        0x81..=0x84 => {
+            let octet_count = (length & 0x7f) as usize;
+            let Some(octets) = data.get(2..2 + octet_count) else {
+                return Err(DnsError::Certificate);
+            };
+            let length = octets
+                .iter()
+                .fold(0usize, |length, octet| length << 8 | *octet as usize);
+            (2 + octet_count, length)
+        }
+        _ => return Err(DnsError::Certificate),
+    };
+    let Some(encoded) = data.get(..header_length + length) else {
+        return Err(DnsError::Certificate);
+    };
+    Ok(DerElement {
+        tag,
+        contents: &encoded[header_length..],
+        encoded,
+        rest: &data[encoded.len()..],
+    })
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of a certificate, which TLSA records with selector 1
+/// match against (RFC 5280, section 4.1).
+///
+/// # Argument
+/// * `certificate`: The DER-encoded certificate.
+pub fn subject_public_key_info(certificate: &[u8]) -> Result<&[u8], DnsError> {
+    let certificate = der_element(certificate)?;
+    if certificate.tag != DER_SEQUENCE || !certificate.rest.is_empty() {
+        return Err(DnsError::Certificate);
+    }
+    let tbs_certificate = der_element(certificate.contents)?;
+    if tbs_certificate.tag != DER_SEQUENCE {
+        return Err(DnsError::Certificate);
+    }
+
+    let mut fields = tbs_certificate.contents;
+    let version = der_element(fields)?;
+    if version.tag == DER_VERSION {
+        fields = version.rest;
+    }
+    // The serial number, signature algorithm, issuer, validity and subject come first.
+    for _ in 0..5 {
+        fields = der_element(fields)?.rest;
+    }
+    let public_key = der_element(fields)?;
+    if public_key.tag != DER_SEQUENCE {
+        return Err(DnsError::Certificate);
+    }
+    Ok(public_key.encoded)
+}
+
+/// The name holding the TLSA records of a TCP service: `_port._tcp.domain`.
+///
+/// # Arguments
+/// * `domain_name`: The host name of the server, e.g. `mail.example.com`.
+/// * `port`: The port of the service, e.g. 25.
+pub fn tlsa_name(domain_name: &str, port: u16) -> String {
+    format!("_{}._tcp.{}", port, domain_name.trim_end_matches('.'))
+}
+
+/// A TLSA record: a certificate association of a service (RFC 6698, section 2.1).
+#[derive(Debug, PartialEq, Clone)]
+pub struct TlsaRecord {
+    /// How the certificate is to be used, e.g. `USAGE_DANE_EE`.
+    pub usage: u8,
+
+    /// Which part of the certificate is matched, e.g. `SELECTOR_PUBLIC_KEY`.
+    pub selector: u8,
+
+    /// How the part is matched, e.g. `MATCHING_SHA256`.
+    pub matching_type: u8,
+
+    /// What the part, or its digest, must be.
+    pub data: Vec<u8>,
+}
+
+impl TlsaRecord {
+    /// Parse the data of a TLSA record.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<TlsaRecord, DnsError> {
+        let [usage, selector, matching_type, association @ ..] = data else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        Ok(TlsaRecord {
+            usage: *usage,
+            selector: *selector,
+            matching_type: *matching_type,
+            data: association.to_vec(),
+        })
+    }
+
+    /// How the record associates with a certificate. Records with a usage, selector or matching
+    /// type RFC 6698 does not define are unusable.
+    ///
+    /// # Arguments
+    /// * `certificate`: The DER-encoded certificate.
+    /// * `public_key`: Its SubjectPublicKeyInfo, see `subject_public_key_info()`.
+    fn associate(&self, certificate: &[u8], public_key: &[u8]) -> Association {
+        let selected = match self.selector {
+            SELECTOR_CERTIFICATE => certificate,
+            SELECTOR_PUBLIC_KEY => public_key,
+            _ => return Association::Unusable,
+        };
+        let matched = match self.matching_type {
+            MATCHING_EXACT => selected.to_vec(),
+            MATCHING_SHA256 => digest(&SHA256, selected).as_ref().to_vec(),
+            MATCHING_SHA512 => digest(&SHA512, selected).as_ref().to_vec(),
+            _ => return Association::Unusable,
+        };
+        match (self.usage <= USAGE_DANE_EE, matched == self.data) {
+            (false, _) => Association::Unusable,
+            (true, true) => Association::Match,
+            (true, false) => Association::Mismatch,
+        }
+    }
+}
+
+impl fmt::Display for TlsaRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.usage,
+            self.selector,
+            self.matching_type,
+            HEXUPPER.encode(&self.data)
+        )
+    }
+}
+
+/// How a TLSA record associates with a certificate.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Association {
+    /// The certificate matches the record.
+    Match,
+
+    /// The certificate does not match the record.
+    Mismatch,
+
+    /// The record uses parameters toy_dns does not know, so it cannot be matched.
+    Unusable,
+}
+
+impl fmt::Display for Association {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Association::Match => "match",
+            Association::Mismatch => "mismatch",
+            Association::Unusable => "unusable",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The outcome of checking a certificate against the TLSA records of a service.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DaneVerdict {
+    /// The name the TLSA records were looked up at, e.g. `_443._tcp.example.com`.
+    pub tlsa_name: String,
+
+    /// Each TLSA record and how it associates with the certificate.
+    pub associations: Vec<(TlsaRecord, Association)>,
+}
+
+impl DaneVerdict {
+    /// Whether the certificate matches any of the records.
+    pub fn is_match(&self) -> bool {
+        self.associations
+            .iter()
+            .any(|(_, association)| *association == Association::Match)
+    }
+
+    /// Whether the service uses DANE: it has records which can be matched. Clients fall back to
+    /// plain PKIX validation otherwise (RFC 6698, section 4.1).
+    pub fn is_usable(&self) -> bool {
+        self.associations
+            .iter()
+            .any(|(_, association)| *association != Association::Unusable)
+    }
+
+    /// Render the verdict: each record and how it associates, then the overall outcome.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for (record, association) in &self.associations {
+            output.push_str(&format!(
+                "{} TLSA {}: {}\n",
+                self.tlsa_name, record, association
+            ));
+        }
+        let outcome = match (self.is_usable(), self.is_match()) {
+            (false, _) => "no usable TLSA records",
+            (true, true) => "the certificate matches",
+            (true, false) => "the certificate matches no TLSA record",
+        };
+        output.push_str(&format!("{}: {}\n", self.tlsa_name, outcome));
+        output
+    }
+}
+
+/// Check a certificate against TLSA records, trying every record whatever its usage. Records
+/// with a trust anchor usage (PKIX-TA, DANE-TA) are meant to match a CA certificate of the chain
+/// rather than the server certificate, and PKIX usages additionally call for PKIX validation,
+/// which is left to the caller.
+///
+/// # Arguments
+/// * `tlsa_name`: The name the records were found at.
+/// * `records`: The TLSA records.
+/// * `certificate`: The DER-encoded certificate.
+pub fn verify(
+    tlsa_name: &str,
+    records: Vec<TlsaRecord>,
+    certificate: &[u8],
+) -> Result<DaneVerdict, DnsError> {
+    let public_key = subject_public_key_info(certificate)?;
+    Ok(DaneVerdict {
+        tlsa_name: tlsa_name.to_owned(),
+        associations: records
+            .into_iter()
+            .map(|record| {
+                let association = record.associate(certificate, public_key);
+                (record, association)
+            })
+            .collect(),
+    })
+}
+
+/// Look up the TLSA records of a TCP service and check a certificate against them, see
+/// `verify()`. DANE relies on the records being signed, but the resolver does not validate
+/// DNSSEC, so a match only shows the records and the certificate agree.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TLSA records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The host name of the server, e.g. `mail.example.com`.
+/// * `port`: The port of the service, e.g. 25.
+/// * `certificate`: The DER-encoded certificate the server presented.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn verify_dane(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    port: u16,
+    certificate: &[u8],
+    rand_seed: Option<usize>,
+) -> Result<DaneVerdict, DnsError> {
+    let name = tlsa_name(domain_name, port);
+    let records = match resolver.resolve(socket, &name, RecordType::TLSA, rand_seed) {
+        Ok(packet) => packet
+            .answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::TLSA)
+            .map(|record| TlsaRecord::from_data(&record.data))
+            .collect::<Result<Vec<TlsaRecord>, DnsError>>()?,
+        // A service without a name of its own has no records, like one without any TLSA record.
+        Err(DnsError::UnknownDomainName) => vec![],
+        Err(error) => return Err(error),
+    };
+    verify(&name, records, certificate)
+}
+
+/// A DER element with the given tag and contents, whose length fits in a single octet.
+#[cfg(test)]
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    [&[tag, contents.len() as u8][..], contents].concat()
+}
+
+/// A certificate with empty fields up to its public key, which is all parsing looks at.
+#[cfg(test)]
+fn certificate(public_key: &[u8]) -> Vec<u8> {
+    let tbs_certificate = [
+        der(DER_VERSION, &der(0x02, &[2])),
+        der(0x02, &[1]),
+        der(DER_SEQUENCE, &[]),
+        der(DER_SEQUENCE, &[]),
+        der(DER_SEQUENCE, &[]),
+        der(DER_SEQUENCE, &[]),
+        der(DER_SEQUENCE, public_key),
+    ]
+    .concat();
+    let certificate = [
+        der(DER_SEQUENCE, &tbs_certificate),
+        der(DER_SEQUENCE, &[]),
+        der(0x03, &[0]),
+    ]
+    .concat();
+    der(DER_SEQUENCE, &certificate)
+}
+
+/// Validate finding the public key of a certificate, with short and long lengths.
+#[test]
+fn test_subject_public_key_info() -> Result<(), DnsError> {
+    let certificate = certificate(&[1, 2, 3]);
+    assert_eq!(subject_public_key_info(&certificate)?, [0x30, 3, 1, 2, 3]);
+
+    let element = [&[0x04, 0x81, 0x80][..], &[7; 0x80][..], &[0xff][..]].concat();
+    let element = der_element(&element)?;
+    assert_eq!(element.contents, [7; 0x80]);
+    assert_eq!(element.rest, [0xff]);
+
+    assert!(subject_public_key_info(&certificate[..certificate.len() - 1]).is_err());
+    assert!(subject_public_key_info(&der(DER_SEQUENCE, &der(DER_SEQUENCE, &[]))).is_err());
+    assert!(der_element(&[0x04, 0x85, 0, 0, 0, 0, 1]).is_err());
+    Ok(())
+}
+
+/// Validate matching a certificate against every selector and matching type.
+#[test]
+fn test_verifying_certificate() -> Result<(), DnsError> {
+    let certificate = certificate(&[1, 2, 3]);
+    let public_key = subject_public_key_info(&certificate)?.to_vec();
+    let record = |usage: u8, selector: u8, matching_type: u8, data: &[u8]| TlsaRecord {
+        usage,
+        selector,
+        matching_type,
+        data: data.to_vec(),
+    };
+
+    let records = vec![
+        record(
+            USAGE_DANE_EE,
+            SELECTOR_CERTIFICATE,
+            MATCHING_EXACT,
+            &certificate,
+        ),
+        record(
+            USAGE_PKIX_EE,
+            SELECTOR_CERTIFICATE,
+            MATCHING_SHA512,
+            digest(&SHA512, &certificate).as_ref(),
+        ),
+        record(
+            USAGE_DANE_TA,
+            SELECTOR_PUBLIC_KEY,
+            MATCHING_SHA256,
+            digest(&SHA256, &public_key).as_ref(),
+        ),
+        record(USAGE_DANE_EE, SELECTOR_PUBLIC_KEY, MATCHING_EXACT, &[0x30]),
+        record(4, SELECTOR_PUBLIC_KEY, MATCHING_EXACT, &public_key),
+        record(USAGE_DANE_EE, 2, MATCHING_EXACT, &public_key),
+        record(USAGE_DANE_EE, SELECTOR_PUBLIC_KEY, 3, &public_key),
+    ];
+    let verdict = verify("_443._tcp.example.com", records, &certificate)?;
+    let associations: Vec<Association> = verdict
+        .associations
+        .iter()
+        .map(|(_, association)| *association)
+        .collect();
+    assert_eq!(
+        associations,
+        [
+            Association::Match,
+            Association::Match,
+            Association::Match,
+            Association::Mismatch,
+            Association::Unusable,
+            Association::Unusable,
+            Association::Unusable
+        ]
+    );
+    assert!(verdict.is_match() && verdict.is_usable());
+
+    let records = vec![record(
+        USAGE_DANE_EE,
+        SELECTOR_PUBLIC_KEY,
+        MATCHING_EXACT,
+        &[0x30],
+    )];
+    let verdict = verify("_443._tcp.example.com", records, &certificate)?;
+    assert_eq!(
+        verdict.render(),
+        "_443._tcp.example.com TLSA 3 1 0 30: mismatch\n\
+         _443._tcp.example.com: the certificate matches no TLSA record\n"
+    );
+    assert!(verify("_443._tcp.example.com", vec![], &[0x30, 0]).is_err());
+    Ok(())
+}
+
+/// Validate looking up the TLSA records of a service to check a certificate.
+#[test]
+fn test_verifying_dane() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    assert_eq!(tlsa_name("example.com.", 443), "_443._tcp.example.com");
+    let certificate = certificate(&[1, 2, 3]);
+    let public_key = subject_public_key_info(&certificate)?;
+    let answer = format!(
+        "_443._tcp.example.com. 300 IN TLSA 3 1 1 {}",
+        HEXUPPER.encode(digest(&SHA256, public_key).as_ref())
+    );
+    let exchanges = vec![
+        MockResolution::new("_443._tcp.example.com", RecordType::TLSA, 0)
+            .answer(&answer)
+            .build()?,
+        MockResolution::new("_25._tcp.example.com", RecordType::TLSA, 0)
+            .name_error()
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let verdict = verify_dane(
+        &resolver,
+        &mut socket,
+        "example.com",
+        443,
+        &certificate,
+        Some(0),
+    )?;
+    assert!(verdict.is_match());
+
+    let verdict = verify_dane(
+        &resolver,
+        &mut socket,
+        "example.com",
+        25,
+        &certificate,
+        Some(0),
+    )?;
+    assert!(!verdict.is_usable());
+    Ok(())
+}
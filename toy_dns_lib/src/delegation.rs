@@ -0,0 +1,469 @@
+use crate::errors::DnsError;
+use crate::header::{rcode_name, FLAG_AUTHORITATIVE, RCODE_NO_ERROR};
+use crate::query::{server_address, Query};
+use crate::record::RecordType;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use crate::trace::{Outcome, Trace};
+use std::fmt;
+use std::net::IpAddr;
+
+/// How a name server of a zone responded when asked for the NS records of its apex.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ServerResponse {
+    /// The server answered authoritatively, with the given name servers.
+    Authoritative(Vec<String>),
+
+    /// The server did not answer authoritatively for the zone, for the given reason, e.g. `not
+    /// authoritative` or `REFUSED`.
+    Lame(String),
+}
+
+impl fmt::Display for ServerResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Authoritative(_) => write!(f, "authoritative"),
+            Self::Lame(reason) => write!(f, "lame, {}", reason),
+        }
+    }
+}
+
+/// What was found about one of the name servers of a zone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NameServerCheck {
+    /// The name of the name server, e.g. `ns1.example.com`.
+    pub name: String,
+
+    /// The addresses the parent zone gave as glue for the name server, if any.
+    pub glue: Vec<IpAddr>,
+
+    /// The addresses the name of the name server resolves to.
+    pub addresses: Vec<IpAddr>,
+
+    /// How the name server responded at each of its addresses, glue included.
+    pub responses: Vec<(IpAddr, ServerResponse)>,
+}
+
+/// An inconsistency between a delegation and the zone it delegates to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Problem {
+    /// The parent zone lists a name server which the apex of the zone does not.
+    MissingFromChild(String),
+
+    /// The apex of the zone lists a name server which the parent zone does not.
+    MissingFromParent(String),
+
+    /// The glue of a name server differs from the addresses its name resolves to.
+    GlueMismatch {
+        name: String,
+        glue: Vec<IpAddr>,
+        addresses: Vec<IpAddr>,
+    },
+
+    /// A name server has neither glue nor addresses.
+    Unresolvable(String),
+
+    /// A name server does not answer authoritatively for the zone at one of its addresses.
+    Lame {
+        name: String,
+        address: IpAddr,
+        reason: String,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingFromChild(name) => {
+                write!(f, "{} is listed by the parent but not at the apex", name)
+            }
+            Self::MissingFromParent(name) => {
+                write!(f, "{} is listed at the apex but not by the parent", name)
+            }
+            Self::GlueMismatch {
+                name,
+                glue,
+                addresses,
+            } => write!(
+                f,
+                "the glue of {} ({}) does not match its addresses ({})",
+                name,
+                join(glue),
+                join(addresses)
+            ),
+            Self::Unresolvable(name) => write!(f, "{} has no address", name),
+            Self::Lame {
+                name,
+                address,
+                reason,
+            } => write!(f, "{} ({}) is lame: {}", name, address, reason),
+        }
+    }
+}
+
+/// The delegation of a zone by its parent, compared with the zone itself.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DelegationReport {
+    /// The name of the zone, e.g. `example.com`.
+    pub domain_name: String,
+
+    /// The address of the server of the parent zone which referred to the zone.
+    pub parent_server: String,
+
+    /// The name servers of the zone according to the parent zone, sorted.
+    pub parent_name_servers: Vec<String>,
+
+    /// The name servers of the zone according to its apex, as answered by any of its name
+    /// servers, sorted.
+    pub child_name_servers: Vec<String>,
+
+    /// What was found about each name server listed by either side.
+    pub name_servers: Vec<NameServerCheck>,
+}
+
+impl DelegationReport {
+    /// The inconsistencies found: name servers listed by one side only, then for each name
+    /// server its glue, its addresses and how it responded.
+    pub fn problems(&self) -> Vec<Problem> {
+        let mut problems: Vec<Problem> = self
+            .parent_name_servers
+            .iter()
+            .filter(|name| !self.child_name_servers.contains(name))
+            .map(|name| Problem::MissingFromChild(name.clone()))
+            .collect();
+        problems.extend(
+            self.child_name_servers
+                .iter()
+                .filter(|name| !self.parent_name_servers.contains(name))
+                .map(|name| Problem::MissingFromParent(name.clone())),
+        );
+
+        for check in &self.name_servers {
+            if !check.glue.is_empty()
+                && !check.addresses.is_empty()
+                && check.glue != check.addresses
+            {
+                problems.push(Problem::GlueMismatch {
+                    name: check.name.clone(),
+                    glue: check.glue.clone(),
+                    addresses: check.addresses.clone(),
+                });
+            }
+            if check.glue.is_empty() && check.addresses.is_empty() {
+                problems.push(Problem::Unresolvable(check.name.clone()));
+            }
+            for (address, response) in &check.responses {
+                if let ServerResponse::Lame(reason) = response {
+                    problems.push(Problem::Lame {
+                        name: check.name.clone(),
+                        address: *address,
+                        reason: reason.clone(),
+                    });
+                }
+            }
+        }
+        problems
+    }
+
+    /// Whether the delegation and the zone agree, and every name server answers for the zone.
+    pub fn is_consistent(&self) -> bool {
+        self.problems().is_empty()
+    }
+
+    /// Render the report: the name servers according to each side, how each of them responded,
+    /// then the problems found.
+    pub fn render(&self) -> String {
+        let mut output = format!(
+            "{}: the parent ({}) lists {}\n{}: the apex lists {}\n",
+            self.domain_name,
+            self.parent_server,
+            self.parent_name_servers.join(", "),
+            self.domain_name,
+            match self.child_name_servers.is_empty() {
+                true => "nothing".to_owned(),
+                false => self.child_name_servers.join(", "),
+            }
+        );
+        for check in &self.name_servers {
+            for (address, response) in &check.responses {
+                output.push_str(&format!("{} ({}): {}\n", check.name, address, response));
+            }
+        }
+        let problems = self.problems();
+        match problems.len() {
+            0 => output.push_str(&format!("{}: no problems found\n", self.domain_name)),
+            1 => output.push_str(&format!("{}: 1 problem found\n", self.domain_name)),
+            count => output.push_str(&format!("{}: {} problems found\n", self.domain_name, count)),
+        }
+        for problem in problems {
+            output.push_str(&format!("  {}\n", problem));
+        }
+        output
+    }
+}
+
+/// A name as names are compared: in lowercase, without a trailing dot.
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Addresses separated by commas, e.g. for a problem.
+fn join(addresses: &[IpAddr]) -> String {
+    let addresses: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+    addresses.join(", ")
+}
+
+/// Ask a name server for the NS records of the apex of a zone, without recursion.
+///
+/// # Arguments
+/// * `resolver`: The resolver whose query options to send the query with.
+/// * `socket`: The socket on which to perform the DNS query.
+/// * `domain_name`: The name of the zone.
+/// * `address`: The address of the name server.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn ask_apex(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    address: IpAddr,
+    rand_seed: Option<usize>,
+) -> ServerResponse {
+    let query = Query {
+        domain_name: domain_name.into(),
+        record_type: RecordType::NS,
+        options: resolver.query_options.clone(),
+    };
+    let packet = match query.ask(socket, &server_address(&address.to_string()), rand_seed) {
+        Ok(packet) => packet,
+        Err(error) => return ServerResponse::Lame(error.to_string()),
+    };
+    if packet.rcode() != RCODE_NO_ERROR {
+        return ServerResponse::Lame(rcode_name(packet.rcode()));
+    }
+    if !packet.header.has_flag(FLAG_AUTHORITATIVE) {
+        return ServerResponse::Lame("not authoritative".to_owned());
+    }
+    let mut name_servers: Vec<String> = packet
+        .answers
+        .iter()
+        .filter(|record| record.r_type == RecordType::NS)
+        .map(|record| normalize(&record.data_to_presentation()))
+        .collect();
+    if name_servers.is_empty() {
+        return ServerResponse::Lame("no NS records at the apex".to_owned());
+    }
+    name_servers.sort();
+    name_servers.dedup();
+    ServerResponse::Authoritative(name_servers)
+}
+
+/// Check the delegation of a zone: the NS records and glue which a server of the parent zone
+/// refers to it with are compared with the NS records at its apex and the addresses of its name
+/// servers, and every name server listed by either side is asked for the apex at each of its
+/// addresses. The parent server is the one met while resolving the NS records of the zone from the
+/// root servers, so the resolver must resolve from the root rather than with a cache or through
+/// other servers.
+///
+/// # Arguments
+/// * `resolver`: The resolver to resolve the zone and the names of its name servers with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The name of the zone, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn check_delegation(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<DelegationReport, DnsError> {
+    let domain_name = normalize(domain_name);
+    let trace = Trace::default();
+    let resolution = resolver.resolve_traced(
+        socket,
+        &domain_name,
+        RecordType::NS,
+        rand_seed,
+        Some(&trace),
+    );
+    let referral = trace
+        .take()
+        .into_iter()
+        .rev()
+        .find_map(|exchange| match exchange.outcome() {
+            Outcome::Referral {
+                zone,
+                name_servers,
+                glue,
+            } if normalize(&zone) == domain_name => {
+                Some((exchange.server_address, name_servers, glue))
+            }
+            _ => None,
+        });
+    let Some((parent_server, parent_name_servers, parent_glue)) = referral else {
+        return Err(resolution.err().unwrap_or(DnsError::Delegation));
+    };
+
+    let mut parent_name_servers: Vec<String> = parent_name_servers
+        .iter()
+        .map(|name| normalize(name))
+        .collect();
+    parent_name_servers.sort();
+    parent_name_servers.dedup();
+
+    // Name servers which only the apex lists are checked as well, once they are found.
+    let mut names = parent_name_servers.clone();
+    let mut name_servers = Vec::new();
+    while let Some(name) = names.get(name_servers.len()).cloned() {
+        let mut glue: Vec<IpAddr> = parent_glue
+            .iter()
+            .filter(|glue| normalize(&glue.name) == name)
+            .filter_map(|glue| glue.address.parse().ok())
+            .collect();
+        glue.sort();
+        let mut addresses = resolver
+            .lookup_ip(socket, &name, rand_seed)
+            .unwrap_or_default();
+        addresses.sort();
+
+        let mut asked: Vec<IpAddr> = glue.iter().chain(&addresses).copied().collect();
+        asked.sort();
+        asked.dedup();
+        let mut responses = Vec::new();
+        for address in asked {
+            let response = ask_apex(resolver, socket, &domain_name, address, rand_seed);
+            if let ServerResponse::Authoritative(apex_name_servers) = &response {
+                for apex_name_server in apex_name_servers {
+                    if !names.contains(apex_name_server) {
+                        names.push(apex_name_server.clone());
+                    }
+                }
+            }
+            responses.push((address, response));
+        }
+        name_servers.push(NameServerCheck {
+            name,
+            glue,
+            addresses,
+            responses,
+        });
+    }
+
+    let mut child_name_servers: Vec<String> = name_servers
+        .iter()
+        .flat_map(|check| &check.responses)
+        .filter_map(|(_, response)| match response {
+            ServerResponse::Authoritative(name_servers) => Some(name_servers.clone()),
+            ServerResponse::Lame(_) => None,
+        })
+        .flatten()
+        .collect();
+    child_name_servers.sort();
+    child_name_servers.dedup();
+
+    Ok(DelegationReport {
+        domain_name,
+        parent_server,
+        parent_name_servers,
+        child_name_servers,
+        name_servers,
+    })
+}
+
+/// Validate checking a delegation whose parent and apex disagree, with stale glue, a lame name
+/// server and one without any address.
+#[test]
+fn test_checking_delegation() -> Result<(), DnsError> {
+    use crate::hosts::HostsFile;
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let apex = "example.com. 3600 IN NS ns1.example.com.
+                example.com. 3600 IN NS ns3.example.com.";
+    let exchanges = vec![MockResolution::new("example.com", RecordType::NS, 0)
+        .referral("com", "a.gtld-servers.net", "192.5.6.30")
+        .delegation(
+            "example.com. 172800 IN NS ns1.example.com.
+                 example.com. 172800 IN NS NS2.example.net.
+                 ns1.example.com. 172800 IN A 192.0.2.53",
+        )
+        .at("192.0.2.53")
+        .answer(apex)
+        .at("192.0.2.99")
+        .answer(apex)
+        .at("192.0.2.54")
+        .referral("example.com", "ns1.example.com", "192.0.2.53")
+        .build()?];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse(
+        "192.0.2.99 ns1.example.com\n192.0.2.54 ns2.example.net\n",
+    ));
+    let report = check_delegation(&resolver, &mut socket, "Example.com.", Some(0))?;
+    assert_eq!(report.parent_server, "192.5.6.30:53");
+    assert_eq!(
+        report.parent_name_servers,
+        ["ns1.example.com", "ns2.example.net"]
+    );
+    assert_eq!(
+        report.child_name_servers,
+        ["ns1.example.com", "ns3.example.com"]
+    );
+    let names: Vec<&str> = report
+        .name_servers
+        .iter()
+        .map(|check| check.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["ns1.example.com", "ns2.example.net", "ns3.example.com"]
+    );
+
+    let address = |address: &str| address.parse::<IpAddr>().unwrap();
+    assert_eq!(
+        report.problems(),
+        [
+            Problem::MissingFromChild("ns2.example.net".to_owned()),
+            Problem::MissingFromParent("ns3.example.com".to_owned()),
+            Problem::GlueMismatch {
+                name: "ns1.example.com".to_owned(),
+                glue: vec![address("192.0.2.53")],
+                addresses: vec![address("192.0.2.99")],
+            },
+            Problem::Lame {
+                name: "ns2.example.net".to_owned(),
+                address: address("192.0.2.54"),
+                reason: "not authoritative".to_owned(),
+            },
+            Problem::Unresolvable("ns3.example.com".to_owned()),
+        ]
+    );
+    assert!(!report.is_consistent());
+    assert!(report
+        .render()
+        .contains("ns1.example.com (192.0.2.53): authoritative\n"));
+    assert!(report.render().contains("example.com: 5 problems found\n"));
+    Ok(())
+}
+
+/// Validate that a name which its parent does not refer to is not checked.
+#[test]
+fn test_checking_undelegated_name() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let exchanges = MockResolution::new("example.com", RecordType::NS, 0)
+        .answer("example.com. 3600 IN NS ns1.example.com.")
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    assert_eq!(
+        check_delegation(&resolver, &mut socket, "example.com", Some(0)),
+        Err(DnsError::Delegation)
+    );
+    Ok(())
+}
@@ -0,0 +1,447 @@
+use crate::edns::format_nsid;
+use crate::header::{rcode_name, OPCODE_MASK};
+use crate::packet::Packet;
+use crate::record::{Record, RecordType};
+use crate::trace::Exchange;
+use crate::zone::{class_name, write_records};
+
+/// Toggle turning every part of the output on (or, as `noall`, off).
+const ALL: &str = "all";
+
+/// The parts of a response to print, turned on and off with dig-style toggles such as `noall` and
+/// `answer`. Every part is printed by default.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Sections {
+    /// The header lines, the OPT pseudo-section and the headings of the sections.
+    pub comments: bool,
+
+    /// The question section.
+    pub question: bool,
+
+    /// The answer section.
+    pub answer: bool,
+
+    /// The authority section.
+    pub authority: bool,
+
+    /// The additional section, except for the OPT pseudo-record.
+    pub additional: bool,
+}
+
+impl Default for Sections {
+    fn default() -> Self {
+        Sections {
+            comments: true,
+            question: true,
+            answer: true,
+            authority: true,
+            additional: true,
+        }
+    }
+}
+
+impl Sections {
+    /// The part of the output named by a toggle, without its `no` prefix.
+    fn part(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "comments" => Some(&mut self.comments),
+            "question" => Some(&mut self.question),
+            "answer" => Some(&mut self.answer),
+            "authority" => Some(&mut self.authority),
+            "additional" => Some(&mut self.additional),
+            _ => None,
+        }
+    }
+
+    /// Whether the given name is a toggle, e.g. `answer`, `noanswer` or `noall`.
+    ///
+    /// # Argument
+    /// * `toggle`: The name to check.
+    pub fn is_toggle(toggle: &str) -> bool {
+        let name = toggle.strip_prefix("no").unwrap_or(toggle);
+        name == ALL || Sections::default().part(name).is_some()
+    }
+
+    /// Apply a toggle: `name` prints the named part and `noname` hides it, with `all` standing
+    /// for every part. Toggles apply in order, so that `noall` followed by `answer` only prints
+    /// the answer section. Names which are not toggles are ignored.
+    ///
+    /// # Argument
+    /// * `toggle`: The toggle to apply.
+    pub fn toggle(&mut self, toggle: &str) {
+        let (name, on) = match toggle.strip_prefix("no") {
+            Some(name) => (name, false),
+            None => (toggle, true),
+        };
+        if name == ALL {
+            *self = Sections {
+                comments: on,
+                question: on,
+                answer: on,
+                authority: on,
+                additional: on,
+            };
+        } else if let Some(part) = self.part(name) {
+            *part = on;
+        }
+    }
+}
+
+/// The mnemonic of an opcode, as dig prints it.
+///
+/// # Argument
+/// * `opcode`: The opcode, shifted down from the flags.
+fn opcode_name(opcode: u16) -> String {
+    let name = match opcode {
+        0 => "QUERY",
+        1 => "IQUERY",
+        2 => "STATUS",
+        4 => "NOTIFY",
+        5 => "UPDATE",
+        6 => "DSO",
+        _ => return format!("RESERVED{}", opcode),
+    };
+    name.to_owned()
+}
+
+/// Render a response the way dig does: the header with its flags and response code, then the
+/// question, answer, authority and additional sections with records in presentation format.
+///
+/// # Arguments
+/// * `packet`: The response to render.
+/// * `sections`: The parts of the response to print.
+pub fn render(packet: &Packet, sections: &Sections) -> String {
+    render_sections(packet, sections, false)
+}
+
+/// Render a message in full, like `render()` with every part printed, but keeping the OPT
+/// pseudo-record in the additional section as well. Everything but the records is a comment, so
+/// the output can be read back as a master file with `Zone::parse()` to yield the same records.
+///
+/// # Argument
+/// * `packet`: The message to render.
+pub(crate) fn render_lossless(packet: &Packet) -> String {
+    render_sections(packet, &Sections::default(), true)
+}
+
+/// Render the parts of a message, for `render()` and `render_lossless()`.
+///
+/// # Arguments
+/// * `packet`: The message to render.
+/// * `sections`: The parts of the message to print.
+/// * `keep_opt`: Whether to also print the OPT pseudo-record in the additional section.
+fn render_sections(packet: &Packet, sections: &Sections, keep_opt: bool) -> String {
+    let mut output = String::new();
+    if sections.comments {
+        output.push_str(&format!(
+            ";; ->>HEADER<<- opcode: {}, status: {}, id: {}\n",
+            opcode_name((packet.header.flags & OPCODE_MASK) >> OPCODE_MASK.trailing_zeros()),
+            rcode_name(packet.rcode()),
+            packet.header.id
+        ));
+        output.push_str(&format!(
+            ";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}\n",
+            packet.header.flag_names().join(" "),
+            packet.questions.len(),
+            packet.answers.len(),
+            packet.authorities.len(),
+            packet.additionals.len()
+        ));
+        if let Ok(Some(edns)) = packet.edns() {
+            output.push_str("\n;; OPT PSEUDOSECTION:\n");
+            output.push_str(&format!(
+                "; EDNS: version: {}, flags:{}; udp: {}\n",
+                edns.version,
+                match edns.dnssec_ok {
+                    true => " do",
+                    false => "",
+                },
+                edns.udp_payload_size
+            ));
+            if let Some(nsid) = edns.nsid() {
+                output.push_str(&format!("; NSID: {}\n", format_nsid(nsid)));
+            }
+        }
+    }
+
+    if sections.question && !packet.questions.is_empty() {
+        if sections.comments {
+            output.push_str("\n;; QUESTION SECTION:\n");
+        }
+        for question in &packet.questions {
+            output.push_str(&format!(
+                ";{}. {} {}\n",
+                String::from_utf8_lossy(&question.name).trim_end_matches('.'),
+                class_name(question.q_class),
+                question.q_type
+            ));
+        }
+    }
+
+    // The OPT pseudo-record is not a record of the additional section, but the pseudo-section
+    // above.
+    let additionals: Vec<Record> = packet
+        .additionals
+        .iter()
+        .filter(|record| keep_opt || record.r_type != RecordType::OPT)
+        .cloned()
+        .collect();
+    let record_sections = [
+        (sections.answer, "ANSWER", &packet.answers),
+        (sections.authority, "AUTHORITY", &packet.authorities),
+        (sections.additional, "ADDITIONAL", &additionals),
+    ];
+    for (shown, title, records) in record_sections {
+        if !shown || records.is_empty() {
+            continue;
+        }
+        if sections.comments {
+            output.push_str(&format!("\n;; {} SECTION:\n", title));
+        }
+        output.push_str(&write_records(records));
+    }
+    output
+}
+
+/// Convert the A-labels of the names in rendered output to their Unicode form, the way dig does
+/// with `+idnout`, e.g. `xn--bcher-kva.example.` to `bücher.example.`. Quoted strings, e.g. the
+/// data of TXT records, are left as they are. Without the `idna` feature, the output is returned
+/// as is.
+///
+/// # Argument
+/// * `output`: The rendered output, e.g. of `render()`.
+pub fn idn_out(output: &str) -> String {
+    #[cfg(feature = "idna")]
+    return crate::idna::labels_to_unicode(output);
+    #[cfg(not(feature = "idna"))]
+    return output.to_owned();
+}
+
+/// Render only the data of the answers, one per line, the way `dig +short` does, e.g. the
+/// addresses of the name along with the targets of the CNAME records leading to them.
+///
+/// # Argument
+/// * `packet`: The response to render.
+pub fn render_short(packet: &Packet) -> String {
+    let mut output = String::new();
+    for answer in &packet.answers {
+        output.push_str(&answer.data_to_presentation());
+        output.push('\n');
+    }
+    output
+}
+
+/// Render the path of a resolution the way `dig +trace` does: for each server queried in turn,
+/// the answer and referral records it returned, followed by where they came from and how long
+/// they took. Servers queried to resolve the name of the next name server are introduced by the
+/// name they were asked about.
+///
+/// # Arguments
+/// * `domain_name`: The name which was resolved.
+/// * `record_type`: The type which was resolved.
+/// * `exchanges`: The exchanges made with servers along the way, in order.
+pub fn render_trace(domain_name: &str, record_type: RecordType, exchanges: &[Exchange]) -> String {
+    let mut output = String::new();
+    for exchange in exchanges {
+        if exchange.domain_name != domain_name || exchange.record_type != record_type {
+            output.push_str(&format!(
+                ";; Looking up {} {} to reach the next name server\n",
+                exchange.domain_name, exchange.record_type
+            ));
+        }
+        output.push_str(&write_records(&exchange.response.answers));
+        output.push_str(&write_records(&exchange.response.authorities));
+        output.push_str(&format!(
+            ";; Received {} bytes from {}",
+            exchange.received_bytes, exchange.server_address
+        ));
+        if !exchange.server_name.is_empty() {
+            output.push_str(&format!("({})", exchange.server_name));
+        }
+        output.push_str(&format!(" in {} ms\n\n", exchange.elapsed.as_millis()));
+    }
+    output
+}
+
+/// Validate applying toggles in order.
+#[test]
+fn test_toggling_sections() {
+    let mut sections = Sections::default();
+    sections.toggle("noall");
+    sections.toggle("answer");
+    assert_eq!(
+        sections,
+        Sections {
+            comments: false,
+            question: false,
+            answer: true,
+            authority: false,
+            additional: false,
+        }
+    );
+
+    sections.toggle("all");
+    sections.toggle("noauthority");
+    sections.toggle("bogus");
+    assert_eq!(
+        sections,
+        Sections {
+            authority: false,
+            ..Default::default()
+        }
+    );
+
+    assert!(Sections::is_toggle("noall"));
+    assert!(Sections::is_toggle("additional"));
+    assert!(Sections::is_toggle("nocomments"));
+    assert!(!Sections::is_toggle("dnssec"));
+    assert!(!Sections::is_toggle("no"));
+}
+
+/// Validate rendering a response in full and with only some of its sections.
+#[test]
+fn test_rendering_sections() {
+    use crate::dns_name::DnsName;
+    use crate::edns::{Edns, EdnsOption};
+    use crate::header::{Header, FLAG_RECURSION_AVAILABLE, FLAG_RECURSION_DESIRED, FLAG_RESPONSE};
+    use crate::question::Question;
+    use crate::ttl::Ttl;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+    let mut edns = Edns::new(1232);
+    edns.options.push(EdnsOption::Nsid(b"ns1".to_vec()));
+    let packet = Packet {
+        header: Header {
+            id: 15212,
+            flags: FLAG_RESPONSE | FLAG_RECURSION_DESIRED | FLAG_RECURSION_AVAILABLE,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("www.example.com"),
+            q_type: RecordType::A,
+            q_class: 1,
+        }],
+        answers: vec![
+            record(
+                "www.example.com",
+                RecordType::CNAME,
+                b"\x07example\x03com\x00",
+            ),
+            record("example.com", RecordType::A, &[192, 0, 2, 1]),
+        ],
+        authorities: vec![record("example.com", RecordType::NS, b"\x02ns\x00")],
+        additionals: vec![edns.to_record().unwrap()],
+    };
+
+    assert_eq!(
+        render(&packet, &Sections::default()),
+        ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 15212\n\
+         ;; flags: qr rd ra; QUERY: 1, ANSWER: 2, AUTHORITY: 1, ADDITIONAL: 1\n\
+         \n\
+         ;; OPT PSEUDOSECTION:\n\
+         ; EDNS: version: 0, flags:; udp: 1232\n\
+         ; NSID: 6e 73 31 (\"ns1\")\n\
+         \n\
+         ;; QUESTION SECTION:\n\
+         ;www.example.com. IN A\n\
+         \n\
+         ;; ANSWER SECTION:\n\
+         www.example.com. 300 IN CNAME example.com.\n\
+         example.com. 300 IN A 192.0.2.1\n\
+         \n\
+         ;; AUTHORITY SECTION:\n\
+         example.com. 300 IN NS ns.\n"
+    );
+
+    let mut sections = Sections::default();
+    sections.toggle("noall");
+    sections.toggle("answer");
+    assert_eq!(
+        render(&packet, &sections),
+        "www.example.com. 300 IN CNAME example.com.\nexample.com. 300 IN A 192.0.2.1\n"
+    );
+    assert_eq!(render_short(&packet), "example.com.\n192.0.2.1\n");
+}
+
+/// Validate rendering the path of a resolution, including a detour to resolve a name server.
+#[test]
+fn test_rendering_trace() {
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+    use crate::ttl::Ttl;
+    use std::time::Duration;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(172800),
+        data: data.to_vec(),
+    };
+    let exchange = |server_address: &str,
+                    server_name: &str,
+                    domain_name: &str,
+                    answers: Vec<Record>,
+                    authorities: Vec<Record>| Exchange {
+        server_address: server_address.to_owned(),
+        server_name: server_name.to_owned(),
+        domain_name: domain_name.to_owned(),
+        record_type: RecordType::A,
+        response: Packet {
+            header: Header::default(),
+            questions: vec![],
+            answers,
+            authorities,
+            additionals: vec![],
+        },
+        received_bytes: 512,
+        elapsed: Duration::from_millis(12),
+    };
+    let exchanges = [
+        exchange(
+            "198.41.0.4:53",
+            "a.root-servers.net",
+            "example.com",
+            vec![],
+            vec![record(
+                "com",
+                RecordType::NS,
+                b"\x01a\x0cgtld-servers\x03net\x00",
+            )],
+        ),
+        exchange(
+            "192.5.6.30:53",
+            "",
+            "ns.example.net",
+            vec![record("ns.example.net", RecordType::A, &[192, 0, 2, 53])],
+            vec![],
+        ),
+        exchange(
+            "192.0.2.53:53",
+            "ns.example.net",
+            "example.com",
+            vec![record("example.com", RecordType::A, &[192, 0, 2, 1])],
+            vec![],
+        ),
+    ];
+
+    assert_eq!(
+        render_trace("example.com", RecordType::A, &exchanges),
+        "com. 172800 IN NS a.gtld-servers.net.\n\
+         ;; Received 512 bytes from 198.41.0.4:53(a.root-servers.net) in 12 ms\n\
+         \n\
+         ;; Looking up ns.example.net A to reach the next name server\n\
+         ns.example.net. 172800 IN A 192.0.2.53\n\
+         ;; Received 512 bytes from 192.5.6.30:53 in 12 ms\n\
+         \n\
+         example.com. 172800 IN A 192.0.2.1\n\
+         ;; Received 512 bytes from 192.0.2.53:53(ns.example.net) in 12 ms\n\
+         \n"
+    );
+}
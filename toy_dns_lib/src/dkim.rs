@@ -0,0 +1,235 @@
+use crate::errors::DnsError;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use data_encoding::BASE64;
+
+/// The version of DKIM key records.
+const DKIM_VERSION: &str = "DKIM1";
+
+/// The tag=value pairs of a record in the tag list format of RFC 6376, section 3.2, shared by DKIM
+/// and DMARC records, in order. Whitespace around tags and values is ignored, as is a trailing
+/// `;`. `None` if a tag has no `=`, has an invalid name or is repeated.
+///
+/// # Argument
+/// * `text`: The text of the record, e.g. `v=DMARC1; p=none`.
+pub(crate) fn parse_tag_list(text: &str) -> Option<Vec<(&str, &str)>> {
+    let mut tags: Vec<(&str, &str)> = Vec::new();
+    for spec in text.trim().trim_end_matches(';').split(';') {
+        let (tag, value) = spec.split_once('=')?;
+        let tag = tag.trim();
+        let valid = tag.starts_with(|character: char| character.is_ascii_alphabetic())
+            && tag
+                .chars()
+                .all(|character| character.is_ascii_alphanumeric() || character == '_');
+        if !valid || tags.iter().any(|(seen, _)| *seen == tag) {
+            return None;
+        }
+        tags.push((tag, value.trim()));
+    }
+    Some(tags)
+}
+
+/// The colon-separated items of a tag value, e.g. `sha1:sha256`.
+///
+/// # Argument
+/// * `value`: The value of the tag.
+fn colon_separated(value: &str) -> Vec<String> {
+    value
+        .split(':')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// The name holding the key of a DKIM selector: `selector._domainkey.domain`.
+///
+/// # Arguments
+/// * `selector`: The selector, as in the `s=` tag of a signature, e.g. `mail`.
+/// * `domain_name`: The signing domain, as in the `d=` tag of a signature.
+pub fn dkim_name(selector: &str, domain_name: &str) -> String {
+    format!(
+        "{}._domainkey.{}",
+        selector.trim_end_matches('.'),
+        domain_name.trim_end_matches('.')
+    )
+}
+
+/// A DKIM public key record (RFC 6376, section 3.6.1).
+#[derive(Debug, PartialEq, Clone)]
+pub struct DkimKey {
+    /// The hash algorithms the key may be used with (`h=`), e.g. `sha256`. Empty when any may.
+    pub hash_algorithms: Vec<String>,
+
+    /// The type of the key (`k=`), `rsa` unless given.
+    pub key_type: String,
+
+    /// Notes for administrators (`n=`).
+    pub notes: Option<String>,
+
+    /// The public key (`p=`), decoded from base64. Empty when the key was revoked.
+    pub public_key: Vec<u8>,
+
+    /// The service types the key applies to (`s=`), `*` for all unless given.
+    pub service_types: Vec<String>,
+
+    /// The flags (`t=`), e.g. `y` while the domain is testing DKIM.
+    pub flags: Vec<String>,
+}
+
+impl DkimKey {
+    /// Parse a DKIM key record.
+    ///
+    /// # Argument
+    /// * `text`: The text of the record, e.g. `v=DKIM1; k=rsa; p=MIGfMA0...`.
+    pub fn parse(text: &str) -> Result<DkimKey, DnsError> {
+        let tags = parse_tag_list(text).ok_or(DnsError::Dkim)?;
+        if let Some(position) = tags.iter().position(|(tag, _)| *tag == "v") {
+            if position != 0 || tags[0].1 != DKIM_VERSION {
+                return Err(DnsError::Dkim);
+            }
+        }
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| *tag == name)
+                .map(|(_, value)| *value)
+        };
+
+        let public_key: String = tag("p")
+            .ok_or(DnsError::Dkim)?
+            .chars()
+            .filter(|character| !character.is_ascii_whitespace())
+            .collect();
+        Ok(DkimKey {
+            hash_algorithms: tag("h").map(colon_separated).unwrap_or_default(),
+            key_type: tag("k").unwrap_or("rsa").to_owned(),
+            notes: tag("n").map(str::to_owned),
+            public_key: BASE64
+                .decode(public_key.as_bytes())
+                .map_err(|_| DnsError::Dkim)?,
+            service_types: colon_separated(tag("s").unwrap_or("*")),
+            flags: tag("t").map(colon_separated).unwrap_or_default(),
+        })
+    }
+
+    /// The key record among the TXT records of a selector: the first which parses. A selector
+    /// with records of which none parse is an error.
+    ///
+    /// # Argument
+    /// * `texts`: The TXT records, e.g. from `Resolver::lookup_txt()`.
+    pub fn find(texts: &[String]) -> Result<Option<DkimKey>, DnsError> {
+        let mut failure = None;
+        for text in texts {
+            match DkimKey::parse(text) {
+                Ok(key) => return Ok(Some(key)),
+                Err(error) => failure = failure.or(Some(error)),
+            }
+        }
+        match failure {
+            Some(error) => Err(error),
+            None => Ok(None),
+        }
+    }
+
+    /// Whether the key was revoked, its public key being empty.
+    pub fn is_revoked(&self) -> bool {
+        self.public_key.is_empty()
+    }
+
+    /// Whether the domain is testing DKIM (`t=y`), so that failures should not be held against
+    /// its mail.
+    pub fn is_testing(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "y")
+    }
+}
+
+/// Look up the DKIM key of a selector.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TXT records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `selector`: The selector, e.g. `mail`.
+/// * `domain_name`: The signing domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_dkim(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    selector: &str,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Option<DkimKey>, DnsError> {
+    let texts = resolver.lookup_txt(socket, &dkim_name(selector, domain_name), rand_seed)?;
+    DkimKey::find(&texts)
+}
+
+/// Validate parsing of tag lists.
+#[test]
+fn test_parsing_tag_list() {
+    assert_eq!(
+        parse_tag_list(" v=DMARC1 ;p = reject; rua=mailto:a@example.com; "),
+        Some(vec![
+            ("v", "DMARC1"),
+            ("p", "reject"),
+            ("rua", "mailto:a@example.com")
+        ])
+    );
+    assert_eq!(parse_tag_list("p=a=b"), Some(vec![("p", "a=b")]));
+    assert_eq!(parse_tag_list("v=DKIM1; p"), None);
+    assert_eq!(parse_tag_list("p=a; p=b"), None);
+    assert_eq!(parse_tag_list("1p=a"), None);
+}
+
+/// Validate parsing of DKIM key records.
+#[test]
+fn test_parsing_dkim_key() -> Result<(), DnsError> {
+    let key = DkimKey::parse("v=DKIM1; h=sha1:sha256; k=rsa; t=y:s; p=MFww AQID")?;
+    assert_eq!(key.hash_algorithms, ["sha1", "sha256"]);
+    assert_eq!(key.key_type, "rsa");
+    assert_eq!(key.public_key, [0x30, 0x5c, 0x30, 1, 2, 3]);
+    assert_eq!(key.service_types, ["*"]);
+    assert!(key.is_testing());
+    assert!(!key.is_revoked());
+
+    let key = DkimKey::parse("k=ed25519; p=")?;
+    assert_eq!(key.key_type, "ed25519");
+    assert!(key.is_revoked());
+    assert!(!key.is_testing());
+
+    assert_eq!(DkimKey::parse("k=rsa"), Err(DnsError::Dkim));
+    assert_eq!(DkimKey::parse("p=MFww; v=DKIM1"), Err(DnsError::Dkim));
+    assert_eq!(DkimKey::parse("v=DKIM2; p=MFww"), Err(DnsError::Dkim));
+    assert_eq!(DkimKey::parse("p=not base64"), Err(DnsError::Dkim));
+
+    let texts = ["unrelated".to_owned(), "p=MFww".to_owned()];
+    assert_eq!(
+        DkimKey::find(&texts)?.unwrap().public_key,
+        [0x30, 0x5c, 0x30]
+    );
+    assert_eq!(DkimKey::find(&texts[..1]), Err(DnsError::Dkim));
+    assert_eq!(DkimKey::find(&[]), Ok(None));
+    Ok(())
+}
+
+/// Validate looking up the DKIM key of a selector.
+#[test]
+fn test_looking_up_dkim_key() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    assert_eq!(
+        dkim_name("mail", "example.com."),
+        "mail._domainkey.example.com"
+    );
+    let exchanges = MockResolution::new("mail._domainkey.example.com", RecordType::TXT, 0)
+        .answer("mail._domainkey.example.com. 300 IN TXT \"v=DKIM1; k=rsa; \" \"p=MFww\"")
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let key = lookup_dkim(&resolver, &mut socket, "mail", "example.com", Some(0))?;
+    assert_eq!(key.unwrap().public_key, [0x30, 0x5c, 0x30]);
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use crate::dkim::parse_tag_list;
+use crate::errors::DnsError;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use std::time::Duration;
+
+/// The version DMARC records start with.
+const DMARC_VERSION: &str = "DMARC1";
+
+/// What receivers are asked to do with mail failing DMARC (RFC 7489, section 6.3).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Disposition {
+    /// Deliver it as usual, only reporting it.
+    None,
+
+    /// Treat it as suspicious, e.g. deliver it to the spam folder.
+    Quarantine,
+
+    /// Reject it.
+    Reject,
+}
+
+impl Disposition {
+    /// Parse a disposition as found in the `p=` and `sp=` tags.
+    ///
+    /// # Argument
+    /// * `value`: The value of the tag, e.g. `quarantine`.
+    fn parse(value: &str) -> Result<Disposition, DnsError> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Ok(Disposition::None),
+            "quarantine" => Ok(Disposition::Quarantine),
+            "reject" => Ok(Disposition::Reject),
+            _ => Err(DnsError::Dmarc),
+        }
+    }
+}
+
+/// How closely the domain authenticated by DKIM or SPF must match the domain of the sender.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Alignment {
+    /// The domains must share their organizational domain, e.g. `mail.example.com` and
+    /// `example.com`.
+    Relaxed,
+
+    /// The domains must be the same.
+    Strict,
+}
+
+impl Alignment {
+    /// Parse an alignment mode as found in the `adkim=` and `aspf=` tags, relaxed unless given.
+    ///
+    /// # Argument
+    /// * `value`: The value of the tag, if any, e.g. `s`.
+    fn parse(value: Option<&str>) -> Result<Alignment, DnsError> {
+        match value {
+            None | Some("r") => Ok(Alignment::Relaxed),
+            Some("s") => Ok(Alignment::Strict),
+            _ => Err(DnsError::Dmarc),
+        }
+    }
+}
+
+/// The name holding the DMARC policy of a domain: `_dmarc.domain`.
+///
+/// # Argument
+/// * `domain_name`: The domain of the sender, e.g. `example.com`.
+pub fn dmarc_name(domain_name: &str) -> String {
+    format!("_dmarc.{}", domain_name.trim_end_matches('.'))
+}
+
+/// A DMARC policy record (RFC 7489, section 6.3).
+#[derive(Debug, PartialEq, Clone)]
+pub struct DmarcPolicy {
+    /// What to do with failing mail from the domain (`p=`).
+    pub policy: Disposition,
+
+    /// What to do with failing mail from subdomains (`sp=`), the same as for the domain unless
+    /// given.
+    pub subdomain_policy: Disposition,
+
+    /// How DKIM identifiers must be aligned (`adkim=`).
+    pub dkim_alignment: Alignment,
+
+    /// How SPF identifiers must be aligned (`aspf=`).
+    pub spf_alignment: Alignment,
+
+    /// The percentage of failing mail the policy applies to (`pct=`), 100 unless given.
+    pub percent: u8,
+
+    /// Where to send aggregate reports (`rua=`), e.g. `mailto:dmarc@example.com`.
+    pub aggregate_reports: Vec<String>,
+
+    /// Where to send failure reports (`ruf=`).
+    pub failure_reports: Vec<String>,
+
+    /// When to send failure reports (`fo=`), e.g. `1` for any failure, `0` unless given.
+    pub failure_options: String,
+
+    /// How often aggregate reports are asked for (`ri=`), daily unless given.
+    pub report_interval: Duration,
+}
+
+impl DmarcPolicy {
+    /// Parse a DMARC record.
+    ///
+    /// # Argument
+    /// * `text`: The text of the record, e.g. `v=DMARC1; p=reject; rua=mailto:d@example.com`.
+    pub fn parse(text: &str) -> Result<DmarcPolicy, DnsError> {
+        let tags = parse_tag_list(text).ok_or(DnsError::Dmarc)?;
+        if tags.first() != Some(&("v", DMARC_VERSION)) {
+            return Err(DnsError::Dmarc);
+        }
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(tag, _)| *tag == name)
+                .map(|(_, value)| *value)
+        };
+        let uris = |name: &str| -> Vec<String> {
+            tag(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|uri| !uri.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+
+        let policy = Disposition::parse(tag("p").ok_or(DnsError::Dmarc)?)?;
+        let percent = match tag("pct") {
+            Some(percent) => percent
+                .parse::<u8>()
+                .ok()
+                .filter(|percent| *percent <= 100)
+                .ok_or(DnsError::Dmarc)?,
+            None => 100,
+        };
+        let report_interval = match tag("ri") {
+            Some(interval) => interval.parse::<u64>().map_err(|_| DnsError::Dmarc)?,
+            None => 86400,
+        };
+        Ok(DmarcPolicy {
+            policy,
+            subdomain_policy: match tag("sp") {
+                Some(subdomain_policy) => Disposition::parse(subdomain_policy)?,
+                None => policy,
+            },
+            dkim_alignment: Alignment::parse(tag("adkim"))?,
+            spf_alignment: Alignment::parse(tag("aspf"))?,
+            percent,
+            aggregate_reports: uris("rua"),
+            failure_reports: uris("ruf"),
+            failure_options: tag("fo").unwrap_or("0").to_owned(),
+            report_interval: Duration::from_secs(report_interval),
+        })
+    }
+
+    /// The DMARC record among the TXT records of a domain, if any: those starting with
+    /// `v=DMARC1`. More than one is an error, as receivers then apply no policy.
+    ///
+    /// # Argument
+    /// * `texts`: The TXT records, e.g. from `Resolver::lookup_txt()`.
+    pub fn find(texts: &[String]) -> Result<Option<DmarcPolicy>, DnsError> {
+        let mut records = texts.iter().filter(|text| {
+            let version = text.split(';').next().unwrap_or_default();
+            version.split('=').map(str::trim).eq(["v", DMARC_VERSION])
+        });
+        match (records.next(), records.next()) {
+            (None, _) => Ok(None),
+            (Some(text), None) => Ok(Some(DmarcPolicy::parse(text)?)),
+            (Some(_), Some(_)) => Err(DnsError::Dmarc),
+        }
+    }
+}
+
+/// Look up the DMARC policy of a domain. The policy of its organizational domain, which applies
+/// when the domain has none, is not looked up.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TXT records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain of the sender, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_dmarc(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Option<DmarcPolicy>, DnsError> {
+    let texts = resolver.lookup_txt(socket, &dmarc_name(domain_name), rand_seed)?;
+    DmarcPolicy::find(&texts)
+}
+
+/// Validate parsing of DMARC records, with and without defaults.
+#[test]
+fn test_parsing_dmarc_policy() -> Result<(), DnsError> {
+    let policy = DmarcPolicy::parse(
+        "v=DMARC1; p=quarantine; sp=reject; adkim=s; pct=50; \
+         rua=mailto:a@example.com, mailto:b@example.net; fo=1; ri=3600",
+    )?;
+    assert_eq!(
+        policy,
+        DmarcPolicy {
+            policy: Disposition::Quarantine,
+            subdomain_policy: Disposition::Reject,
+            dkim_alignment: Alignment::Strict,
+            spf_alignment: Alignment::Relaxed,
+            percent: 50,
+            aggregate_reports: vec![
+                "mailto:a@example.com".to_owned(),
+                "mailto:b@example.net".to_owned()
+            ],
+            failure_reports: vec![],
+            failure_options: "1".to_owned(),
+            report_interval: Duration::from_secs(3600),
+        }
+    );
+
+    let policy = DmarcPolicy::parse("v=DMARC1; p=none")?;
+    assert_eq!(policy.subdomain_policy, Disposition::None);
+    assert_eq!(policy.percent, 100);
+    assert_eq!(policy.report_interval, Duration::from_secs(86400));
+
+    assert_eq!(DmarcPolicy::parse("p=none"), Err(DnsError::Dmarc));
+    assert_eq!(DmarcPolicy::parse("p=none; v=DMARC1"), Err(DnsError::Dmarc));
+    assert_eq!(DmarcPolicy::parse("v=DMARC1"), Err(DnsError::Dmarc));
+    assert_eq!(DmarcPolicy::parse("v=DMARC1; p=drop"), Err(DnsError::Dmarc));
+    assert_eq!(
+        DmarcPolicy::parse("v=DMARC1; p=none; pct=101"),
+        Err(DnsError::Dmarc)
+    );
+    Ok(())
+}
+
+/// Validate looking up the DMARC policy of a domain among its TXT records.
+#[test]
+fn test_looking_up_dmarc_policy() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    assert_eq!(dmarc_name("example.com."), "_dmarc.example.com");
+    let exchanges = MockResolution::new("_dmarc.example.com", RecordType::TXT, 0)
+        .answer(
+            "_dmarc.example.com. 300 IN TXT \"unrelated\"
+             _dmarc.example.com. 300 IN TXT \"v=DMARC1; p=reject\"",
+        )
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let policy = lookup_dmarc(&resolver, &mut socket, "example.com", Some(0))?;
+    assert_eq!(policy.unwrap().policy, Disposition::Reject);
+
+    let texts = [
+        "v=DMARC1; p=none".to_owned(),
+        "v=DMARC1; p=reject".to_owned(),
+    ];
+    assert_eq!(DmarcPolicy::find(&texts), Err(DnsError::Dmarc));
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use crate::errors::DnsError;
+use crate::header::RCODE_NO_ERROR;
+use crate::packet::Packet;
+use crate::record::{Record, RecordType};
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The Well-Known Prefix reserved for NAT64, as specified in RFC 6052, section 2.1.
+pub const WELL_KNOWN_PREFIX: &str = "64:ff9b::/96";
+
+/// Name whose AAAA records reveal the NAT64 prefix of the network, as specified in RFC 7050,
+/// section 2.
+pub const IPV4ONLY_ARPA: &str = "ipv4only.arpa";
+
+/// The addresses of the A records of `ipv4only.arpa`, which DNS64 servers embed in the AAAA records
+/// they synthesize for it.
+const IPV4ONLY_ADDRESSES: [Ipv4Addr; 2] =
+    [Ipv4Addr::new(192, 0, 0, 170), Ipv4Addr::new(192, 0, 0, 171)];
+
+/// Prefix lengths IPv4 addresses can be embedded after, as specified in RFC 6052, section 2.2.
+const PREFIX_LENGTHS: [u8; 6] = [32, 40, 48, 56, 64, 96];
+
+/// Index of the octet of an IPv6 address which is left zero when embedding IPv4 addresses (the
+/// "u" octet, bits 64 to 71).
+const U_OCTET: usize = 8;
+
+/// The IPv6 prefix a NAT64 translator maps the IPv4 address space into, e.g. `64:ff9b::/96`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Nat64Prefix {
+    /// The prefix, with every bit past its length cleared.
+    prefix: Ipv6Addr,
+
+    /// The length of the prefix: 32, 40, 48, 56, 64 or 96.
+    length: u8,
+}
+
+impl Nat64Prefix {
+    /// Parse a prefix given as `address/length`.
+    ///
+    /// # Argument
+    /// * `prefix`: The prefix to parse, e.g. `64:ff9b::/96`.
+    pub fn parse(prefix: &str) -> Result<Nat64Prefix, DnsError> {
+        let Some((address, length)) = prefix.split_once('/') else {
+            return Err(DnsError::InvalidNat64Prefix);
+        };
+        let (Ok(address), Ok(length)) = (address.parse::<Ipv6Addr>(), length.parse::<u8>()) else {
+            return Err(DnsError::InvalidNat64Prefix);
+        };
+        Self::new(address, length)
+    }
+
+    /// Create a prefix from an address and a length. Bits of the address past the length are
+    /// ignored.
+    ///
+    /// # Arguments
+    /// * `address`: An address within the prefix.
+    /// * `length`: The length of the prefix.
+    pub fn new(address: Ipv6Addr, length: u8) -> Result<Nat64Prefix, DnsError> {
+        if !PREFIX_LENGTHS.contains(&length) {
+            return Err(DnsError::InvalidNat64Prefix);
+        }
+        let mask = u128::MAX << (128 - length as u32);
+        Ok(Nat64Prefix {
+            prefix: Ipv6Addr::from(u128::from(address) & mask),
+            length,
+        })
+    }
+
+    /// The IPv6 address the IPv4 address is reachable at through the translator, as specified in
+    /// RFC 6052, section 2.2. The octets of the IPv4 address follow the prefix, skipping the "u"
+    /// octet.
+    ///
+    /// # Argument
+    /// * `address`: The IPv4 address to embed.
+    pub fn embed(&self, address: Ipv4Addr) -> Ipv6Addr {
+        let mut octets = self.prefix.octets();
+        let positions = (self.length as usize / 8..16).filter(|index| *index != U_OCTET);
+        for (index, octet) in positions.zip(address.octets()) {
+            octets[index] = octet;
+        }
+        Ipv6Addr::from(octets)
+    }
+
+    /// Discover the prefix from the AAAA records a DNS64 server synthesized for `ipv4only.arpa`,
+    /// as specified in RFC 7050, section 3. The prefix is the one the well-known IPv4 addresses
+    /// of the name are embedded in. There is none without a DNS64 server on the path.
+    ///
+    /// # Argument
+    /// * `answers`: The answers to the AAAA query for `ipv4only.arpa`.
+    pub fn discover(answers: &[Record]) -> Option<Nat64Prefix> {
+        answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::AAAA)
+            .filter_map(|record| <[u8; 16]>::try_from(&record.data[..]).ok())
+            .map(Ipv6Addr::from)
+            .find_map(|address| {
+                // Longer prefixes are tried first, as an address embedded after a short prefix
+                // cannot be mistaken for one embedded after a longer prefix with a zero suffix.
+                PREFIX_LENGTHS.iter().rev().find_map(|length| {
+                    let prefix = Nat64Prefix::new(address, *length).ok()?;
+                    IPV4ONLY_ADDRESSES
+                        .iter()
+                        .any(|embedded| prefix.embed(*embedded) == address)
+                        .then_some(prefix)
+                })
+            })
+    }
+}
+
+impl fmt::Display for Nat64Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.prefix, self.length)
+    }
+}
+
+/// Whether a response to a AAAA query calls for synthesis: the name exists but has no AAAA
+/// record, as specified in RFC 6147, section 5.1.2. Names which do not exist are not synthesized.
+///
+/// # Argument
+/// * `response`: The response to the AAAA query.
+pub fn needs_synthesis(response: &Packet) -> bool {
+    response.rcode() == RCODE_NO_ERROR
+        && !response
+            .answers
+            .iter()
+            .any(|record| record.r_type == RecordType::AAAA)
+}
+
+/// Turn a response to an A query into a response to the AAAA query for the same name, as
+/// specified in RFC 6147, section 5.1.7. A records become AAAA records of the addresses embedded
+/// in the prefix, and other records, such as the CNAME records leading to them, are kept.
+///
+/// # Arguments
+/// * `prefix`: The NAT64 prefix to embed the addresses in.
+/// * `response`: The response to the A query.
+pub fn synthesize(prefix: &Nat64Prefix, mut response: Packet) -> Packet {
+    for record in response.answers.iter_mut() {
+        if record.r_type != RecordType::A {
+            continue;
+        }
+        let Ok(octets) = <[u8; 4]>::try_from(&record.data[..]) else {
+            continue;
+        };
+        *record = Record {
+            r_type: RecordType::AAAA,
+            data: prefix.embed(Ipv4Addr::from(octets)).octets().to_vec(),
+            ..record.clone()
+        };
+    }
+    response
+}
+
+/// Validate parsing of prefixes and embedding of IPv4 addresses at every prefix length.
+#[test]
+fn test_nat64_prefix() -> Result<(), DnsError> {
+    let address: Ipv4Addr = "192.0.2.33".parse().unwrap();
+    let expected = [
+        ("2001:db8::/32", "2001:db8:c000:221::"),
+        ("2001:db8:100::/40", "2001:db8:1c0:2:21::"),
+        ("2001:db8:122::/48", "2001:db8:122:c000:2:2100::"),
+        ("2001:db8:122:300::/56", "2001:db8:122:3c0:0:221::"),
+        ("2001:db8:122:344::/64", "2001:db8:122:344:c0:2:2100:0"),
+        ("64:ff9b::/96", "64:ff9b::c000:221"),
+    ];
+    for (prefix, embedded) in expected {
+        let prefix = Nat64Prefix::parse(prefix)?;
+        assert_eq!(prefix.embed(address), embedded.parse::<Ipv6Addr>().unwrap());
+    }
+
+    assert_eq!(
+        Nat64Prefix::parse("64:ff9b::1/96")?.to_string(),
+        WELL_KNOWN_PREFIX
+    );
+    assert_eq!(
+        Nat64Prefix::parse("64:ff9b::/80"),
+        Err(DnsError::InvalidNat64Prefix)
+    );
+    assert_eq!(
+        Nat64Prefix::parse("192.0.2.0/96"),
+        Err(DnsError::InvalidNat64Prefix)
+    );
+    Ok(())
+}
+
+/// Validate that A records are synthesized into AAAA records, keeping CNAME records.
+#[test]
+fn test_synthesize() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::header::{Header, RCODE_NAME_ERROR};
+    use crate::ttl::Ttl;
+
+    let record = |r_type: RecordType, data: Vec<u8>| Record {
+        name: DnsName::from("www.example.com"),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data,
+    };
+    let mut response = Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    };
+    assert!(needs_synthesis(&response));
+
+    response.answers = vec![
+        record(RecordType::CNAME, vec![0]),
+        record(RecordType::A, vec![192, 0, 2, 1]),
+    ];
+    let synthesized = synthesize(&Nat64Prefix::parse(WELL_KNOWN_PREFIX)?, response.clone());
+    assert_eq!(synthesized.answers[0], response.answers[0]);
+    assert_eq!(synthesized.answers[1].r_type, RecordType::AAAA);
+    assert_eq!(
+        synthesized.answers[1].data,
+        "64:ff9b::c000:201".parse::<Ipv6Addr>().unwrap().octets()
+    );
+    assert!(!needs_synthesis(&synthesized));
+
+    response.answers.clear();
+    response.header.flags = RCODE_NAME_ERROR;
+    assert!(!needs_synthesis(&response));
+    Ok(())
+}
+
+/// Validate discovery of the prefix from the AAAA records of ipv4only.arpa.
+#[test]
+fn test_discover_prefix() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::ttl::Ttl;
+    let record = |address: &str| Record {
+        name: DnsName::from(IPV4ONLY_ARPA),
+        r_type: RecordType::AAAA,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: address.parse::<Ipv6Addr>().unwrap().octets().to_vec(),
+    };
+
+    let answers = [record("64:ff9b::c000:aa"), record("64:ff9b::c000:ab")];
+    assert_eq!(
+        Nat64Prefix::discover(&answers),
+        Some(Nat64Prefix::parse(WELL_KNOWN_PREFIX)?)
+    );
+    assert_eq!(
+        Nat64Prefix::discover(&[record("2001:db8:122:344:c0:0:aa00:0")]),
+        Some(Nat64Prefix::parse("2001:db8:122:344::/64")?)
+    );
+    assert_eq!(
+        Nat64Prefix::discover(&[record("2001:db8:c000:ab::")]),
+        Some(Nat64Prefix::parse("2001:db8::/32")?)
+    );
+
+    // Addresses which do not embed the well-known addresses are not from a DNS64 server.
+    assert_eq!(Nat64Prefix::discover(&[record("2001:db8::1")]), None);
+    assert_eq!(Nat64Prefix::discover(&[]), None);
+    Ok(())
+}
@@ -0,0 +1,375 @@
+use crate::errors::DnsError;
+use crate::record_name::{MAX_LABEL_LENGTH, MAX_NAME_LENGTH};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+
+/// A domain name in dotted form without a trailing dot, e.g. `www.example.com`, with the root
+/// being the empty name. Names are compared and hashed without regard to ASCII case, as
+/// specified in RFC 4343, so that they can be matched, cached and checked against a bailiwick
+/// however a server chose to spell them. With the `serde` feature, names are serialized as
+/// strings.
+#[derive(Clone, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "String", into = "String")
+)]
+pub struct DnsName {
+    name: Vec<u8>,
+}
+
+impl DnsName {
+    /// The root name.
+    pub fn root() -> DnsName {
+        DnsName::default()
+    }
+
+    /// Whether this is the root name.
+    pub fn is_root(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// The name in dotted form, without a trailing dot.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// The labels of the name, from the leftmost, e.g. `www`, `example` and `com`. The root has
+    /// none.
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> {
+        self.name
+            .split(|&byte| byte == b'.')
+            .filter(|_| !self.is_root())
+    }
+
+    /// The number of labels of the name, 0 for the root.
+    pub fn label_count(&self) -> usize {
+        self.labels().count()
+    }
+
+    /// The name without its leftmost label, e.g. `example.com` for `www.example.com`. `None` for
+    /// the root.
+    pub fn parent(&self) -> Option<DnsName> {
+        if self.is_root() {
+            return None;
+        }
+        let name = match self.name.iter().position(|&byte| byte == b'.') {
+            Some(index) => self.name[index + 1..].to_vec(),
+            None => Vec::new(),
+        };
+        Some(DnsName { name })
+    }
+
+    /// Whether the name equals the given name or lies below it, e.g. `www.example.com` within
+    /// `example.com` but not within `ample.com`. Every name lies within the root.
+    ///
+    /// # Argument
+    /// * `ancestor`: The name to check against.
+    pub fn is_subdomain_of(&self, ancestor: &DnsName) -> bool {
+        if ancestor.is_root() || self == ancestor {
+            return true;
+        }
+        let Some(prefix_length) = self.name.len().checked_sub(ancestor.name.len() + 1) else {
+            return false;
+        };
+        self.name[prefix_length] == b'.'
+            && self.name[prefix_length + 1..].eq_ignore_ascii_case(&ancestor.name)
+    }
+
+    /// Whether the name lies exactly one label below the given name, e.g. `www.example.com`
+    /// below `example.com`.
+    ///
+    /// # Argument
+    /// * `parent`: The name to check against.
+    pub fn is_child_of(&self, parent: &DnsName) -> bool {
+        self.parent().is_some_and(|name| name == *parent)
+    }
+
+    /// Whether the given name lies exactly one label below this one.
+    ///
+    /// # Argument
+    /// * `child`: The name to check against.
+    pub fn is_parent_of(&self, child: &DnsName) -> bool {
+        child.is_child_of(self)
+    }
+
+    /// Encode the name in the canonical wire form of RFC 4034, section 6.2: uncompressed, with
+    /// every ASCII letter lowercased, e.g. `\x07example\x03com\x00` for `Example.COM`.
+    pub fn to_canonical_wire(&self) -> Result<Vec<u8>, DnsError> {
+        let mut bytes = Vec::with_capacity(self.name.len() + 2);
+        for label in self.labels() {
+            if label.is_empty() {
+                return Err(DnsError::InvalidByteInName);
+            }
+            let Ok(length) = u8::try_from(label.len()) else {
+                return Err(DnsError::LabelTooLong);
+            };
+            if length > MAX_LABEL_LENGTH {
+                return Err(DnsError::LabelTooLong);
+            }
+            bytes.push(length);
+            bytes.extend(label.iter().map(u8::to_ascii_lowercase));
+        }
+        bytes.push(0);
+        if bytes.len() > MAX_NAME_LENGTH {
+            return Err(DnsError::NameTooLong);
+        }
+        Ok(bytes)
+    }
+}
+
+impl From<Vec<u8>> for DnsName {
+    /// Take a name in dotted form. A trailing dot is optional, and `.` alone is the root.
+    fn from(mut name: Vec<u8>) -> Self {
+        if name.last() == Some(&b'.') {
+            name.pop();
+        }
+        DnsName { name }
+    }
+}
+
+impl From<&[u8]> for DnsName {
+    fn from(name: &[u8]) -> Self {
+        DnsName::from(name.to_vec())
+    }
+}
+
+impl From<&str> for DnsName {
+    fn from(name: &str) -> Self {
+        DnsName::from(name.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for DnsName {
+    fn from(name: String) -> Self {
+        DnsName::from(name.into_bytes())
+    }
+}
+
+impl From<DnsName> for String {
+    /// The name in dotted form without a trailing dot, replacing invalid UTF-8. Names read from
+    /// messages are valid UTF-8 already.
+    fn from(name: DnsName) -> Self {
+        match String::from_utf8(name.name) {
+            Ok(name) => name,
+            Err(error) => String::from_utf8_lossy(error.as_bytes()).into_owned(),
+        }
+    }
+}
+
+/// The characters arbitrary names are made of. Dots would split labels, so they are left out.
+#[cfg(feature = "arbitrary")]
+const ARBITRARY_LABEL_CHARACTERS: &[u8] =
+    b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-_*";
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for DnsName {
+    /// A name of up to 3 labels of up to 63 octets, which keeps it within 255 octets, so that it
+    /// can be encoded. The root has no labels.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut labels = Vec::new();
+        for _ in 0..u.int_in_range(0..=3)? {
+            let mut label = Vec::new();
+            for _ in 0..u.int_in_range(1..=MAX_LABEL_LENGTH)? {
+                label.push(*u.choose(ARBITRARY_LABEL_CHARACTERS)?);
+            }
+            labels.push(label);
+        }
+        Ok(DnsName {
+            name: labels.join(&b'.'),
+        })
+    }
+}
+
+impl Deref for DnsName {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+impl AsRef<[u8]> for DnsName {
+    fn as_ref(&self) -> &[u8] {
+        &self.name
+    }
+}
+
+impl PartialEq for DnsName {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.eq_ignore_ascii_case(&other.name)
+    }
+}
+
+impl Eq for DnsName {}
+
+impl PartialEq<str> for DnsName {
+    /// Compare with a name in dotted form, with or without a trailing dot.
+    fn eq(&self, other: &str) -> bool {
+        let other = other.as_bytes();
+        let other = other.strip_suffix(b".").unwrap_or(other);
+        self.name.eq_ignore_ascii_case(other)
+    }
+}
+
+impl PartialEq<&str> for DnsName {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl Hash for DnsName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for byte in &self.name {
+            state.write_u8(byte.to_ascii_lowercase());
+        }
+        state.write_usize(self.name.len());
+    }
+}
+
+impl Ord for DnsName {
+    /// Order names canonically as specified in RFC 4034, section 6.1: label by label from the
+    /// rightmost, each label compared as a lowercased octet string, so that a name sorts directly
+    /// after its parent, e.g. `example` < `a.example` < `Z.a.example` < `z.example`.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let mut labels = self.labels().rev();
+        let mut other_labels = other.labels().rev();
+        loop {
+            let (label, other_label) = match (labels.next(), other_labels.next()) {
+                (None, None) => return Ordering::Equal,
+                (None, Some(_)) => return Ordering::Less,
+                (Some(_), None) => return Ordering::Greater,
+                (Some(label), Some(other_label)) => (label, other_label),
+            };
+            let ordering = label
+                .iter()
+                .map(u8::to_ascii_lowercase)
+                .cmp(other_label.iter().map(u8::to_ascii_lowercase));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+    }
+}
+
+impl PartialOrd for DnsName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for DnsName {
+    /// Write the name in dotted form without a trailing dot, replacing invalid UTF-8.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.name))
+    }
+}
+
+impl fmt::Debug for DnsName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DnsName({:?})", String::from_utf8_lossy(&self.name))
+    }
+}
+
+/// Validate that names compare and hash without regard to case or a trailing dot.
+#[test]
+fn test_dns_name_equality() {
+    use std::collections::HashSet;
+
+    assert_eq!(
+        DnsName::from("WWW.Example.com."),
+        DnsName::from("www.example.com")
+    );
+    assert_ne!(
+        DnsName::from("www.example.com"),
+        DnsName::from("www.example.org")
+    );
+    assert_eq!(DnsName::from("Example.COM"), "example.com.");
+    assert_eq!(DnsName::from("."), DnsName::root());
+
+    let names: HashSet<DnsName> = ["example.com", "EXAMPLE.com.", "example.org"]
+        .into_iter()
+        .map(DnsName::from)
+        .collect();
+    assert_eq!(names.len(), 2);
+    assert_eq!(format!("{}", DnsName::from("example.com.")), "example.com");
+}
+
+/// Validate label iteration and the relations between names.
+#[test]
+fn test_dns_name_hierarchy() {
+    let name = DnsName::from("www.Example.com");
+    let labels: Vec<&[u8]> = name.labels().collect();
+    assert_eq!(labels, [b"www".as_slice(), b"Example", b"com"]);
+    assert_eq!(name.label_count(), 3);
+    assert_eq!(DnsName::root().label_count(), 0);
+
+    assert_eq!(name.parent(), Some(DnsName::from("example.com")));
+    assert_eq!(DnsName::from("com").parent(), Some(DnsName::root()));
+    assert_eq!(DnsName::root().parent(), None);
+
+    assert!(name.is_subdomain_of(&DnsName::from("EXAMPLE.com")));
+    assert!(name.is_subdomain_of(&name));
+    assert!(name.is_subdomain_of(&DnsName::root()));
+    assert!(!name.is_subdomain_of(&DnsName::from("ample.com")));
+    assert!(!DnsName::from("com").is_subdomain_of(&DnsName::from("example.com")));
+
+    assert!(name.is_child_of(&DnsName::from("example.com")));
+    assert!(!name.is_child_of(&DnsName::from("com")));
+    assert!(DnsName::from("com").is_parent_of(&DnsName::from("example.com")));
+}
+
+/// Validate the canonical wire form of names, and that overlong labels and names are rejected.
+#[test]
+fn test_dns_name_canonical_wire() {
+    assert_eq!(
+        DnsName::from("WWW.Example.com.").to_canonical_wire(),
+        Ok(b"\x03www\x07example\x03com\x00".to_vec())
+    );
+    assert_eq!(DnsName::root().to_canonical_wire(), Ok(vec![0]));
+    assert_eq!(
+        DnsName::from("a..com").to_canonical_wire(),
+        Err(DnsError::InvalidByteInName)
+    );
+
+    let long_label = "a".repeat(64);
+    assert_eq!(
+        DnsName::from(long_label.as_str()).to_canonical_wire(),
+        Err(DnsError::LabelTooLong)
+    );
+    let long_name = vec!["a".repeat(63); 4].join(".");
+    assert_eq!(
+        DnsName::from(long_name.as_str()).to_canonical_wire(),
+        Err(DnsError::NameTooLong)
+    );
+}
+
+/// Validate canonical ordering against the example of RFC 4034, section 6.1.
+#[test]
+fn test_dns_name_canonical_order() {
+    let ordered: Vec<DnsName> = [
+        b"example".as_slice(),
+        b"a.example",
+        b"yljkjljk.a.example",
+        b"Z.a.example",
+        b"zABC.a.EXAMPLE",
+        b"z.example",
+        b"\x01.z.example",
+        b"*.z.example",
+        b"\xc8.z.example",
+    ]
+    .into_iter()
+    .map(DnsName::from)
+    .collect();
+
+    let mut names = ordered.clone();
+    names.reverse();
+    names.sort();
+    assert_eq!(names, ordered);
+    assert_eq!(
+        DnsName::from("A.example").cmp(&DnsName::from("a.EXAMPLE")),
+        Ordering::Equal
+    );
+}
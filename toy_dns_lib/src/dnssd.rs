@@ -0,0 +1,351 @@
+use crate::errors::DnsError;
+use crate::query::{is_mdns_name, read_multicast_response, Query, QueryOptions};
+use crate::record::{Record, RecordType};
+use crate::record_name::RecordName;
+use crate::socket::DnsTransport;
+use byteorder::{BigEndian, ReadBytesExt};
+use log::info;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Most responses read while browsing. Responders answer within a second, so the socket's read
+/// timeout normally ends browsing well before.
+const MAX_BROWSE_RESPONSES: usize = 64;
+
+/// An instance of a service discovered on the local link with DNS-Based Service Discovery
+/// (RFC 6763), e.g. a printer advertising `_ipp._tcp`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ServiceInstance {
+    /// Full name of the instance, e.g. `Office Printer._ipp._tcp.local`.
+    pub name: String,
+
+    /// The host providing the service, e.g. `printer.local`. Empty if it could not be resolved.
+    pub host: String,
+
+    /// The port the service listens on.
+    pub port: u16,
+
+    /// The `key=value` attributes the instance advertises in its TXT record.
+    pub attributes: Vec<String>,
+
+    /// The addresses of the host.
+    pub addresses: Vec<IpAddr>,
+}
+
+impl fmt::Display for ServiceInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        writeln!(f, "    host {}:{}", self.host, self.port)?;
+        for address in &self.addresses {
+            writeln!(f, "    address {}", address)?;
+        }
+        for attribute in &self.attributes {
+            writeln!(f, "    txt {}", attribute)?;
+        }
+        Ok(())
+    }
+}
+
+/// Browse the local link for instances of a service with multicast DNS, as specified in RFC 6763,
+/// section 4. The PTR records of the service type name the instances, whose SRV, TXT and address
+/// records are then resolved. Responders usually include those records with the PTR records, so
+/// further queries are only sent for records which are missing.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS queries. It should have a read timeout, as
+///   browsing reads responses until none arrives anymore.
+/// * `service_type`: The service type to browse, e.g. `_ipp._tcp` or `_ipp._tcp.local`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn browse(
+    socket: &mut dyn DnsTransport,
+    service_type: &str,
+    rand_seed: Option<usize>,
+) -> Result<Vec<ServiceInstance>, DnsError> {
+    let service_type = service_type.trim_end_matches('.');
+    let service_type = match is_mdns_name(service_type) {
+        true => service_type.to_owned(),
+        false => format!("{}.local", service_type),
+    };
+
+    let query = Query {
+        domain_name: service_type.as_str().into(),
+        record_type: RecordType::PTR,
+        options: QueryOptions::default(),
+    };
+    let query_bytes = query.send_multicast(socket, rand_seed)?;
+
+    let mut records: Vec<Record> = Vec::new();
+    for _ in 0..MAX_BROWSE_RESPONSES {
+        let Ok(packet) = read_multicast_response(socket, &query_bytes) else {
+            break;
+        };
+        add_records(
+            &mut records,
+            packet.answers.into_iter().chain(packet.additionals),
+        );
+    }
+
+    let mut instance_names: Vec<String> = Vec::new();
+    for record in &records {
+        if record.r_type != RecordType::PTR || record.name != service_type.as_str() {
+            continue;
+        }
+        let Ok(name) = read_name(&mut Cursor::new(&record.data[..])) else {
+            continue;
+        };
+        if !instance_names.contains(&name) {
+            instance_names.push(name);
+        }
+    }
+    info!(
+        "Found {} instances of {}",
+        instance_names.len(),
+        service_type
+    );
+
+    let mut instances = Vec::with_capacity(instance_names.len());
+    for name in instance_names {
+        let mut instance = ServiceInstance {
+            name,
+            ..Default::default()
+        };
+
+        if let Some(srv) = lookup(
+            socket,
+            &mut records,
+            &instance.name,
+            RecordType::SRV,
+            rand_seed,
+        )
+        .first()
+        {
+            let (port, host) = parse_srv(&srv.data)?;
+            instance.port = port;
+            instance.host = host;
+        }
+        for txt in lookup(
+            socket,
+            &mut records,
+            &instance.name,
+            RecordType::TXT,
+            rand_seed,
+        ) {
+            instance.attributes.extend(parse_txt(&txt.data)?);
+        }
+        if !instance.host.is_empty() {
+            for record_type in [RecordType::A, RecordType::AAAA] {
+                for record in lookup(socket, &mut records, &instance.host, record_type, rand_seed) {
+                    if let Some(address) = parse_address(&record.data) {
+                        instance.addresses.push(address);
+                    }
+                }
+            }
+        }
+        instances.push(instance);
+    }
+    Ok(instances)
+}
+
+/// The records of the given name and type, queried with multicast DNS unless they are among the
+/// records received so far. Records received in response to the query are kept for later.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `records`: The records received so far.
+/// * `domain_name`: The name of the records.
+/// * `record_type`: The type of the records.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn lookup(
+    socket: &mut dyn DnsTransport,
+    records: &mut Vec<Record>,
+    domain_name: &str,
+    record_type: RecordType,
+    rand_seed: Option<usize>,
+) -> Vec<Record> {
+    let matching = |records: &[Record]| -> Vec<Record> {
+        records
+            .iter()
+            .filter(|record| record.r_type == record_type && record.name == domain_name)
+            .cloned()
+            .collect()
+    };
+    let found = matching(records);
+    if !found.is_empty() {
+        return found;
+    }
+
+    let query = Query {
+        domain_name: domain_name.into(),
+        record_type,
+        options: QueryOptions::default(),
+    };
+    match query.resolve(socket, rand_seed) {
+        Ok(packet) => add_records(
+            records,
+            packet.answers.into_iter().chain(packet.additionals),
+        ),
+        Err(error) => info!(
+            "Could not resolve {} {}: {}",
+            domain_name, record_type, error
+        ),
+    }
+    matching(records)
+}
+
+/// Add records to the list unless they are already on it, as responders repeat themselves.
+///
+/// # Arguments
+/// * `records`: The list of records.
+/// * `new_records`: The records to add.
+fn add_records(records: &mut Vec<Record>, new_records: impl Iterator<Item = Record>) {
+    for record in new_records {
+        if !records.contains(&record) {
+            records.push(record);
+        }
+    }
+}
+
+/// Read an uncompressed name.
+fn read_name(cursor: &mut Cursor<&[u8]>) -> Result<String, DnsError> {
+    let name = RecordName::read_and_advance(cursor)?;
+    let Ok(name) = String::from_utf8(name) else {
+        return Err(DnsError::InvalidByteInName);
+    };
+    Ok(name)
+}
+
+/// The port and target host of SRV record data, as specified in RFC 2782.
+///
+/// # Argument
+/// * `data`: The uncompressed record data.
+fn parse_srv(data: &[u8]) -> Result<(u16, String), DnsError> {
+    let mut cursor = Cursor::new(data);
+    // The priority and weight only matter when choosing among several SRV records.
+    cursor.set_position(4);
+    let port = cursor
+        .read_u16::<BigEndian>()
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    Ok((port, read_name(&mut cursor)?))
+}
+
+/// The character strings of TXT record data. A single empty string means no attributes, as
+/// specified in RFC 6763, section 6.1.
+///
+/// # Argument
+/// * `data`: The record data.
+fn parse_txt(data: &[u8]) -> Result<Vec<String>, DnsError> {
+    let mut cursor = Cursor::new(data);
+    let mut strings = Vec::new();
+    while (cursor.position() as usize) < data.len() {
+        let length = cursor
+            .read_u8()
+            .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+        let mut string = vec![0u8; length as usize];
+        cursor
+            .read_exact(&mut string)
+            .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+        if !string.is_empty() {
+            strings.push(String::from_utf8_lossy(&string).into_owned());
+        }
+    }
+    Ok(strings)
+}
+
+/// The address held by A or AAAA record data.
+///
+/// # Argument
+/// * `data`: The record data.
+fn parse_address(data: &[u8]) -> Option<IpAddr> {
+    if let Ok(octets) = <[u8; 4]>::try_from(data) {
+        return Some(IpAddr::V4(Ipv4Addr::from(octets)));
+    }
+    let octets = <[u8; 16]>::try_from(data).ok()?;
+    Some(IpAddr::V6(Ipv6Addr::from(octets)))
+}
+
+/// Validate browsing a service whose responder includes the records of its instance.
+#[test]
+fn test_browse() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::header::{Header, FLAG_AUTHORITATIVE, FLAG_RESPONSE};
+    use crate::packet::Packet;
+    use crate::query::CLASS_IN;
+    use crate::question::Question;
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    let record = |name: &str, r_type: RecordType, data: Vec<u8>| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 0x8001,
+        ttl: Ttl::from(120),
+        data,
+    };
+    let instance = "Office Printer._ipp._tcp.local";
+    let mut srv = vec![0, 0, 0, 0, 2, 119];
+    srv.extend(
+        RecordName {
+            name: "printer.local",
+        }
+        .encode()?,
+    );
+    let query = Query {
+        domain_name: "_ipp._tcp.local".into(),
+        record_type: RecordType::PTR,
+        options: QueryOptions::default(),
+    };
+    let query_bytes = query.serialize(Some(0))?;
+    let mut response = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("_ipp._tcp.local"),
+            q_type: RecordType::PTR,
+            q_class: CLASS_IN,
+        }],
+        answers: vec![record(
+            "_ipp._tcp.local",
+            RecordType::PTR,
+            RecordName { name: instance }.encode()?,
+        )],
+        authorities: vec![],
+        additionals: vec![
+            record(instance, RecordType::SRV, srv),
+            record(
+                instance,
+                RecordType::TXT,
+                b"\x0arp=printer\x06note=3".to_vec(),
+            ),
+            record("printer.local", RecordType::A, vec![192, 168, 1, 20]),
+        ],
+    }
+    .serialize()?;
+    response.resize(1024, 0);
+
+    let data = [(
+        MockKey {
+            query_bytes,
+            server_ip: "224.0.0.251:5353".to_owned(),
+        },
+        MockData { data: response },
+    )];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let instances = browse(&mut socket, "_ipp._tcp", Some(0))?;
+    assert_eq!(
+        instances,
+        [ServiceInstance {
+            name: instance.to_owned(),
+            host: "printer.local".to_owned(),
+            port: 631,
+            attributes: vec!["rp=printer".to_owned(), "note=3".to_owned()],
+            addresses: vec!["192.168.1.20".parse().unwrap()],
+        }]
+    );
+    Ok(())
+}
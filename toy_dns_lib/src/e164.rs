@@ -0,0 +1,164 @@
+use crate::errors::DnsError;
+use crate::naptr::{sort_naptr_records, NaptrRecord};
+use crate::record::RecordType;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use log::warn;
+
+/// The domain ENUM names live under (RFC 6116, section 2).
+const ENUM_DOMAIN: &str = "e164.arpa";
+
+/// The prefix of the services of ENUM rules, e.g. `E2U+sip`.
+const ENUM_SERVICE_PREFIX: &str = "E2U";
+
+/// The most digits an E.164 number may have.
+const MAX_E164_DIGITS: usize = 15;
+
+/// A URI an ENUM lookup yielded for a telephone number.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EnumUri {
+    /// The services of the rule which yielded the URI, e.g. `E2U+sip` or `E2U+email:mailto`.
+    pub services: String,
+
+    /// The URI, e.g. `sip:info@example.com`.
+    pub uri: String,
+}
+
+/// A telephone number in the form ENUM rules are applied to: `+` followed by its digits.
+/// Spaces, dashes, dots and parentheses between the digits are dropped.
+///
+/// # Argument
+/// * `number`: The telephone number in international format, e.g. `+44 1632 960083`.
+pub fn e164_number(number: &str) -> Result<String, DnsError> {
+    let Some(digits) = number.trim().strip_prefix('+') else {
+        return Err(DnsError::E164);
+    };
+    let digits: String = digits
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+        .collect();
+    if digits.is_empty()
+        || digits.len() > MAX_E164_DIGITS
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(DnsError::E164);
+    }
+    Ok(format!("+{}", digits))
+}
+
+/// The ENUM name of a telephone number: its digits in reverse order, one label each, under
+/// `e164.arpa` (RFC 6116, section 2.4).
+///
+/// # Argument
+/// * `number`: The telephone number in international format, e.g. `+44 1632 960083`.
+pub fn e164_name(number: &str) -> Result<String, DnsError> {
+    let number = e164_number(number)?;
+    let mut labels: Vec<String> = number[1..].chars().rev().map(String::from).collect();
+    labels.push(ENUM_DOMAIN.to_owned());
+    Ok(labels.join("."))
+}
+
+/// Look up the URIs of a telephone number with ENUM: the NAPTR records of its `e164.arpa` name
+/// whose services are ENUM services, in the order of their rules, each rewriting the number into
+/// a URI with its substitution expression. Rules which are not terminal, pointing at another
+/// name to look up, are not followed. Malformed rules are left out.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the NAPTR records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `number`: The telephone number in international format, e.g. `+44 1632 960083`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_enum(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    number: &str,
+    rand_seed: Option<usize>,
+) -> Result<Vec<EnumUri>, DnsError> {
+    let name = e164_name(number)?;
+    let number = e164_number(number)?;
+    let packet = resolver.resolve(socket, &name, RecordType::NAPTR, rand_seed)?;
+    let mut records = packet
+        .answers
+        .iter()
+        .filter(|record| record.r_type == RecordType::NAPTR)
+        .map(|record| NaptrRecord::from_data(&record.data))
+        .collect::<Result<Vec<NaptrRecord>, DnsError>>()?;
+    sort_naptr_records(&mut records);
+
+    let mut uris = Vec::new();
+    for record in records {
+        let is_enum_service = record
+            .services
+            .get(..ENUM_SERVICE_PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(ENUM_SERVICE_PREFIX));
+        if !is_enum_service || !record.is_terminal() {
+            continue;
+        }
+        match record.rewrite(&number) {
+            Ok(Some(uri)) => uris.push(EnumUri {
+                services: record.services,
+                uri,
+            }),
+            Ok(None) => {}
+            Err(error) => warn!(
+                "Skipping the rule {:?} of {}: {}",
+                record.regexp, name, error
+            ),
+        }
+    }
+    Ok(uris)
+}
+
+/// Validate turning telephone numbers into ENUM names.
+#[test]
+fn test_e164_name() -> Result<(), DnsError> {
+    assert_eq!(
+        e164_name("+44 (1632) 960-083")?,
+        "3.8.0.0.6.9.2.3.6.1.4.4.e164.arpa"
+    );
+    assert_eq!(e164_number(" +1.555.0100 ")?, "+15550100");
+    assert_eq!(e164_name("441632960083"), Err(DnsError::E164));
+    assert_eq!(e164_name("+44 1632 96008x"), Err(DnsError::E164));
+    assert_eq!(e164_name("+"), Err(DnsError::E164));
+    assert_eq!(e164_name("+1234567890123456"), Err(DnsError::E164));
+    Ok(())
+}
+
+/// Validate looking up the URIs of a telephone number, in the order of the rules.
+#[test]
+fn test_looking_up_enum() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let name = "3.8.0.0.6.9.2.3.6.1.4.4.e164.arpa";
+    let exchanges = MockResolution::new(name, RecordType::NAPTR, 0)
+        .answer(&format!(
+            r#"{name}. 300 IN NAPTR 100 20 "u" "E2U+email:mailto" "!^.*$!mailto:info@example.com!" .
+               {name}. 300 IN NAPTR 100 10 "u" "E2U+sip" "!^\\+44(.*)$!sip:\\1@example.com!" .
+               {name}. 300 IN NAPTR 100 30 "" "E2U+sip" "" other.example.com.
+               {name}. 300 IN NAPTR 100 40 "u" "x-other" "!^.*$!http://example.com/!" .
+               {name}. 300 IN NAPTR 100 50 "u" "E2U+sip" "!^(!sip:x!" ."#,
+            name = name
+        ))
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let uris = lookup_enum(&resolver, &mut socket, "+44 1632 960083", Some(0))?;
+    assert_eq!(
+        uris,
+        [
+            EnumUri {
+                services: "E2U+sip".to_owned(),
+                uri: "sip:1632960083@example.com".to_owned(),
+            },
+            EnumUri {
+                services: "E2U+email:mailto".to_owned(),
+                uri: "mailto:info@example.com".to_owned(),
+            }
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,366 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::record::{Record, RecordType};
+use crate::ttl::Ttl;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{Cursor, Read};
+use std::time::Duration;
+
+/// The DO bit within the flags that the OPT record carries in its TTL field.
+const FLAG_DNSSEC_OK: u32 = 0b1000_0000_0000_0000;
+
+/// Option code of the name server identifier option. See RFC 5001.
+const OPTION_CODE_NSID: u16 = 3;
+
+/// Option code of the edns-tcp-keepalive option. See RFC 7828.
+const OPTION_CODE_TCP_KEEPALIVE: u16 = 11;
+
+/// Option code of the padding option. See RFC 7830.
+const OPTION_CODE_PADDING: u16 = 12;
+
+/// Size of the code and length fields which precede the data of every option.
+const OPTION_HEADER_LENGTH: usize = 4;
+
+/// The block length RFC 8467 recommends for padding queries.
+pub const RECOMMENDED_QUERY_BLOCK_LENGTH: u16 = 128;
+
+/// How queries are padded with the padding option to resist traffic analysis. Padding only
+/// makes sense on encrypted transports (DNS-over-TLS/HTTPS), where it hides the length of the
+/// name being queried. See RFC 8467.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PaddingPolicy {
+    /// Do not pad.
+    #[default]
+    Disabled,
+
+    /// Pad the message to a multiple of the given number of octets.
+    BlockLength(u16),
+}
+
+impl PaddingPolicy {
+    /// The number of padding octets needed to bring a message to the next block boundary once a
+    /// padding option has been added to it.
+    ///
+    /// # Argument
+    /// * `message_length`: The length of the message before the padding option is added.
+    pub fn padding_length(&self, message_length: usize) -> Option<u16> {
+        let PaddingPolicy::BlockLength(block_length) = *self else {
+            return None;
+        };
+        if block_length == 0 {
+            return None;
+        }
+
+        let block_length = block_length as usize;
+        let padded_length = message_length + OPTION_HEADER_LENGTH;
+        Some(((block_length - padded_length % block_length) % block_length) as u16)
+    }
+}
+
+/// An option carried in the data of an OPT record. See RFC 6891 for the wire format.
+#[derive(Debug, PartialEq, Clone)]
+pub enum EdnsOption {
+    /// The name server identifier (RFC 5001). Queries carry it empty to request the identifier,
+    /// responses carry the identifier of the answering server instance.
+    Nsid(Vec<u8>),
+
+    /// The idle timeout of a TCP connection in units of 100 milliseconds (RFC 7828). Clients send
+    /// the option without a timeout; servers reply with the timeout they are willing to honor.
+    TcpKeepalive(Option<u16>),
+
+    /// The given number of zero octets of padding (RFC 7830).
+    Padding(u16),
+
+    /// An option that toy_dns does not interpret.
+    Unknown { code: u16, data: Vec<u8> },
+}
+
+impl EdnsOption {
+    /// Read an option from the OPT record data at the given cursor.
+    ///
+    /// # Argument
+    /// * `cursor`: A cursor over the data of an OPT record.
+    fn read_and_advance(cursor: &mut Cursor<&[u8]>) -> Result<EdnsOption, DnsError> {
+        let Ok(code) = cursor.read_u16::<BigEndian>() else {
+            return Err(DnsError::ParseEdnsOption);
+        };
+        let Ok(length) = cursor.read_u16::<BigEndian>() else {
+            return Err(DnsError::ParseEdnsOption);
+        };
+        let mut data = vec![0u8; length as usize];
+        let Ok(_) = cursor.read_exact(&mut data) else {
+            return Err(DnsError::ParseEdnsOption);
+        };
+
+        Ok(match code {
+            OPTION_CODE_NSID => EdnsOption::Nsid(data),
+            OPTION_CODE_TCP_KEEPALIVE => match data.as_slice() {
+                [] => EdnsOption::TcpKeepalive(None),
+                [high, low] => EdnsOption::TcpKeepalive(Some(u16::from_be_bytes([*high, *low]))),
+                _ => return Err(DnsError::ParseEdnsOption),
+            },
+            OPTION_CODE_PADDING => EdnsOption::Padding(data.len() as u16),
+            _ => EdnsOption::Unknown { code, data },
+        })
+    }
+
+    /// Serialize the option onto the given buffer.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized option to.
+    fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        let padding;
+        let timeout;
+        let (code, data) = match self {
+            EdnsOption::Nsid(data) => (OPTION_CODE_NSID, data.as_slice()),
+            EdnsOption::TcpKeepalive(None) => (OPTION_CODE_TCP_KEEPALIVE, [].as_slice()),
+            EdnsOption::TcpKeepalive(Some(value)) => {
+                timeout = value.to_be_bytes();
+                (OPTION_CODE_TCP_KEEPALIVE, timeout.as_slice())
+            }
+            EdnsOption::Padding(length) => {
+                padding = vec![0u8; *length as usize];
+                (OPTION_CODE_PADDING, padding.as_slice())
+            }
+            EdnsOption::Unknown { code, data } => (*code, data.as_slice()),
+        };
+
+        let Ok(length) = u16::try_from(data.len()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(code) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(length) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        bytes.extend(data);
+        Ok(())
+    }
+}
+
+/// The EDNS(0) parameters of a message, carried in the OPT pseudo-record of the additional
+/// section. See RFC 6891.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Edns {
+    /// The largest UDP payload the sender is able to receive.
+    pub udp_payload_size: u16,
+
+    /// The upper 8 bits of the 12-bit response code.
+    pub extended_rcode: u8,
+
+    /// The EDNS version. Only version 0 exists.
+    pub version: u8,
+
+    /// The DO bit: whether the sender wants DNSSEC records in the response (RFC 3225).
+    pub dnssec_ok: bool,
+
+    /// The options carried in the OPT record.
+    pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+    /// Create EDNS(0) parameters advertising the given UDP payload size.
+    ///
+    /// # Argument
+    /// * `udp_payload_size`: The largest UDP payload the sender is able to receive.
+    pub fn new(udp_payload_size: u16) -> Edns {
+        Edns {
+            udp_payload_size,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+            options: vec![],
+        }
+    }
+
+    /// The name server identifier carried in the options, if any.
+    pub fn nsid(&self) -> Option<&[u8]> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::Nsid(data) => Some(data.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// The TCP idle timeout advertised with the edns-tcp-keepalive option, if any.
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        self.options.iter().find_map(|option| match option {
+            EdnsOption::TcpKeepalive(Some(timeout)) => {
+                Some(Duration::from_millis(*timeout as u64 * 100))
+            }
+            _ => None,
+        })
+    }
+
+    /// Build the OPT pseudo-record carrying these parameters.
+    pub fn to_record(&self) -> Result<Record, DnsError> {
+        let mut data = Vec::new();
+        for option in &self.options {
+            option.serialize(&mut data)?;
+        }
+
+        let mut ttl = (self.extended_rcode as u32) << 24 | (self.version as u32) << 16;
+        if self.dnssec_ok {
+            ttl |= FLAG_DNSSEC_OK;
+        }
+
+        Ok(Record {
+            // The OPT record is always owned by the root.
+            name: DnsName::root(),
+            r_type: RecordType::OPT,
+            r_class: self.udp_payload_size,
+            ttl: Ttl::from(ttl),
+            data,
+        })
+    }
+
+    /// Extract the EDNS parameters from an OPT pseudo-record.
+    ///
+    /// # Argument
+    /// * `record`: An OPT record.
+    pub fn from_record(record: &Record) -> Result<Edns, DnsError> {
+        let mut options = Vec::new();
+        let mut cursor = Cursor::new(record.data.as_slice());
+        while (cursor.position() as usize) < record.data.len() {
+            options.push(EdnsOption::read_and_advance(&mut cursor)?);
+        }
+
+        Ok(Edns {
+            udp_payload_size: record.r_class,
+            extended_rcode: (record.ttl.as_secs() >> 24) as u8,
+            version: (record.ttl.as_secs() >> 16) as u8,
+            dnssec_ok: record.ttl.as_secs() & FLAG_DNSSEC_OK != 0,
+            options,
+        })
+    }
+}
+
+/// Validate that EDNS parameters survive a round trip through an OPT record.
+#[test]
+fn test_edns_record_round_trip() -> Result<(), DnsError> {
+    let edns = Edns {
+        udp_payload_size: 1232,
+        extended_rcode: 1,
+        version: 0,
+        dnssec_ok: true,
+        options: vec![EdnsOption::Unknown {
+            code: 65001,
+            data: vec![1, 2, 3],
+        }],
+    };
+
+    let record = edns.to_record()?;
+    assert_eq!(record.r_type, RecordType::OPT);
+    assert_eq!(record.r_class, 1232);
+    assert_eq!(record.ttl, 0x0100_8000);
+    assert_eq!(record.data, vec![0xFD, 0xE9, 0, 3, 1, 2, 3]);
+
+    assert_eq!(Edns::from_record(&record)?, edns);
+    Ok(())
+}
+
+/// Validate parsing of the NSID option returned by a server.
+#[test]
+fn test_edns_nsid() -> Result<(), DnsError> {
+    let record = Record {
+        r_type: RecordType::OPT,
+        r_class: 1232,
+        data: vec![0, 3, 0, 4, b'k', b'1', b'.', b'x'],
+        ..Default::default()
+    };
+
+    let edns = Edns::from_record(&record)?;
+    assert_eq!(edns.options, vec![EdnsOption::Nsid(b"k1.x".to_vec())]);
+    assert_eq!(edns.nsid(), Some(b"k1.x".as_slice()));
+    assert_eq!(edns.to_record()?, record);
+    Ok(())
+}
+
+/// Validate the padding needed to reach a block boundary, including the option's own header.
+#[test]
+fn test_padding_length() {
+    let policy = PaddingPolicy::BlockLength(128);
+    assert_eq!(policy.padding_length(40), Some(84));
+    assert_eq!(policy.padding_length(124), Some(0));
+    assert_eq!(policy.padding_length(125), Some(127));
+    assert_eq!(PaddingPolicy::Disabled.padding_length(40), None);
+    assert_eq!(PaddingPolicy::BlockLength(0).padding_length(40), None);
+}
+
+/// Validate the wire format of the padding option.
+#[test]
+fn test_edns_padding_option() -> Result<(), DnsError> {
+    let mut edns = Edns::new(1232);
+    edns.options.push(EdnsOption::Padding(3));
+
+    let record = edns.to_record()?;
+    assert_eq!(record.data, vec![0, 12, 0, 3, 0, 0, 0]);
+    assert_eq!(Edns::from_record(&record)?, edns);
+    Ok(())
+}
+
+/// Validate the wire format of the edns-tcp-keepalive option in queries and responses.
+#[test]
+fn test_edns_tcp_keepalive_option() -> Result<(), DnsError> {
+    let mut query = Edns::new(1232);
+    query.options.push(EdnsOption::TcpKeepalive(None));
+    assert_eq!(query.to_record()?.data, vec![0, 11, 0, 0]);
+    assert_eq!(query.tcp_keepalive(), None);
+
+    let mut response = Edns::new(1232);
+    response.options.push(EdnsOption::TcpKeepalive(Some(1200)));
+    let record = response.to_record()?;
+    assert_eq!(record.data, vec![0, 11, 0, 2, 4, 176]);
+    assert_eq!(
+        Edns::from_record(&record)?.tcp_keepalive(),
+        Some(Duration::from_secs(120))
+    );
+
+    let malformed = Record {
+        r_type: RecordType::OPT,
+        data: vec![0, 11, 0, 1, 4],
+        ..Default::default()
+    };
+    assert_eq!(
+        Edns::from_record(&malformed),
+        Err(DnsError::ParseEdnsOption)
+    );
+    Ok(())
+}
+
+/// Validate that a truncated option in an OPT record fails to parse.
+#[test]
+fn test_edns_truncated_option() {
+    let record = Record {
+        r_type: RecordType::OPT,
+        data: vec![0, 3, 0, 4, 1],
+        ..Default::default()
+    };
+    assert_eq!(Edns::from_record(&record), Err(DnsError::ParseEdnsOption));
+}
+
+/// Render a name server identifier the way dig does: as hex followed by its printable form.
+///
+/// # Argument
+/// * `nsid`: The raw name server identifier.
+pub fn format_nsid(nsid: &[u8]) -> String {
+    let hex: Vec<String> = nsid.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let printable: String = nsid
+        .iter()
+        .map(|&byte| match byte.is_ascii_graphic() || byte == b' ' {
+            true => byte as char,
+            false => '.',
+        })
+        .collect();
+    format!("{} (\"{}\")", hex.join(" "), printable)
+}
+
+/// Validate the rendering of a name server identifier.
+#[test]
+fn test_format_nsid() {
+    assert_eq!(
+        format_nsid(b"gpdns-sfo"),
+        "67 70 64 6e 73 2d 73 66 6f (\"gpdns-sfo\")"
+    );
+    assert_eq!(format_nsid(&[0x61, 0x00]), "61 00 (\"a.\")");
+}
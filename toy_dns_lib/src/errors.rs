@@ -1,111 +1,607 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, io};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum DnsError {
     // Parse Errors
     ParseResponse,
-    ParseId,
-    ParseFlag,
-    ParseQuestionCount,
-    ParseAnswerCount,
-    ParseAuthorityCount,
-    ParseAdditionalCount,
-    ReadByte,
-    ReadLength,
-    ReadQuestionType,
-    ReadQuestionClass,
-    ReadRecordType,
-    ReadRecordClass,
-    ReadRecordTTL,
-    ReadRecordDataLength,
-    ReadRecordData,
+    ParseId(Option<io::Error>),
+    ParseFlag(Option<io::Error>),
+    ParseQuestionCount(Option<io::Error>),
+    ParseAnswerCount(Option<io::Error>),
+    ParseAuthorityCount(Option<io::Error>),
+    ParseAdditionalCount(Option<io::Error>),
+    ReadByte(Option<io::Error>),
+    ReadLength(Option<io::Error>),
+    ReadQuestionType(Option<io::Error>),
+    ReadQuestionClass(Option<io::Error>),
+    ReadRecordType(Option<io::Error>),
+    ReadRecordClass(Option<io::Error>),
+    ReadRecordTTL(Option<io::Error>),
+    ReadRecordDataLength(Option<io::Error>),
+    ReadRecordData(Option<io::Error>),
+    ParseEdnsOption,
 
     // Record Errors
     InvalidByteInName,
     UnrecognizedRecordType,
 
     // Socket Errors
-    SocketBind,
-    SocketSend,
-    SocketRead,
+    SocketBind(Option<io::Error>),
+    SocketSend(Option<io::Error>),
+    SocketRead(Option<io::Error>),
+    SocketConnect(Option<io::Error>),
 
     // Decompress Errors
-    DecompressReadByte,
-    DecompressSkip,
-    DecompressRestore,
+    DecompressReadByte(Option<io::Error>),
+    DecompressSkip(Option<io::Error>),
+    DecompressRestore(Option<io::Error>),
 
     // Serialization Errors
     QuerySerialization,
 
     // Additional Nameservers Not Found
     UnknownDomainName,
+    TooManyReferrals,
+
+    // Configuration Errors
+    SystemConfig,
+    HostsFile,
+    ParseZone,
+    InvalidUpstream,
+    Blocklist,
+    InvalidCidr,
+    InvalidNat64Prefix,
+    Batch,
+    Dump,
+    ParseCapture,
+    WriteCapture,
+    QueryLog,
+    Bench,
+    CompressionLoop,
+    LabelTooLong,
+    NameTooLong,
+    ForwardPointer,
+    BindToDevice,
+    Fixture,
+    Control,
+    Spf,
+    Dkim,
+    Dmarc,
+    Certificate,
+    Naptr,
+    E164,
+    Svcb,
+    Delegation,
+    ZoneTransfer,
+    ZoneWalk,
+
+    /// One of the errors above, along with where it happened.
+    Context(Box<DnsError>, Box<ErrorContext>),
+}
+
+/// What an error says about whether trying again may help.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorCategory {
+    /// The network failed on the way, e.g. a timeout. The query may succeed if sent again, or
+    /// sent to another server.
+    Transient,
+
+    /// A server sent something malformed or unusable, or settled that the name does not exist.
+    /// Asking the same server again will not help, though another server may do better.
+    Protocol,
+
+    /// The query or the configuration is wrong, e.g. an invalid name or an unreadable zone file.
+    /// Nothing will succeed until it is changed.
+    Input,
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Transient => "transient",
+            Self::Protocol => "protocol",
+            Self::Input => "input",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The stage of an exchange with a server at which an error happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Serialize,
+    Send,
+    Receive,
+    Parse,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Serialize => "serialize",
+            Self::Send => "send",
+            Self::Receive => "receive",
+            Self::Parse => "parse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where an error happened, so that e.g. a malformed response can be traced to the server which
+/// sent it and the octet it went wrong at. Each part is known to different code on the way up,
+/// so parts are filled in as the error passes through, see `DnsError::with_context()`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ErrorContext {
+    /// The position of the cursor in the message when the error happened.
+    pub offset: Option<u64>,
+
+    /// The part of the message being read, e.g. "header" or "answer".
+    pub section: Option<&'static str>,
+
+    /// The index of the entry being read within its section, from 0.
+    pub index: Option<usize>,
+
+    /// The address of the server being exchanged with.
+    pub server: Option<String>,
+
+    /// The stage of the exchange with the server.
+    pub stage: Option<Stage>,
+}
+
+impl ErrorContext {
+    /// The context of an exchange with a server.
+    ///
+    /// # Arguments
+    /// * `server`: The address of the server.
+    /// * `stage`: The stage of the exchange.
+    pub fn exchange(server: &str, stage: Stage) -> ErrorContext {
+        ErrorContext {
+            server: Some(server.to_owned()),
+            stage: Some(stage),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    /// Write the known parts, e.g. `stage: parse, server: 192.0.2.1:53, offset: 42, section:
+    /// answer, index: 1`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let parts = [
+            self.stage.map(|stage| format!("stage: {}", stage)),
+            self.server
+                .as_ref()
+                .map(|server| format!("server: {}", server)),
+            self.offset.map(|offset| format!("offset: {}", offset)),
+            self.section.map(|section| format!("section: {}", section)),
+            self.index.map(|index| format!("index: {}", index)),
+        ];
+        let parts: Vec<String> = parts.into_iter().flatten().collect();
+        write!(f, "{}", parts.join(", "))
+    }
 }
 
 impl DnsError {
+    /// The error without its context, e.g. `ReadRecordData`.
+    pub fn kind(&self) -> &DnsError {
+        match self {
+            Self::Context(error, _) => error.kind(),
+            _ => self,
+        }
+    }
+
+    /// The name of the kind of error, e.g. "ReadRecordData".
+    pub fn name(&self) -> String {
+        let name = format!("{:?}", self.kind());
+        // Drop the I/O error the variant may carry.
+        match name.split_once('(') {
+            Some((name, _)) => name.to_owned(),
+            None => name,
+        }
+    }
+
+    /// Where the error happened, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Self::Context(_, context) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Attach the given context to the error. Parts of the context the error already carries are
+    /// kept, as they were filled in closer to where the error happened.
+    ///
+    /// # Argument
+    /// * `context`: Where the error happened.
+    pub fn with_context(self, context: ErrorContext) -> DnsError {
+        let (error, known) = match self {
+            Self::Context(error, known) => (error, *known),
+            error => (Box::new(error), ErrorContext::default()),
+        };
+        let context = ErrorContext {
+            offset: known.offset.or(context.offset),
+            section: known.section.or(context.section),
+            index: known.index.or(context.index),
+            server: known.server.or(context.server),
+            stage: known.stage.or(context.stage),
+        };
+        Self::Context(error, Box::new(context))
+    }
+
+    /// The I/O error behind a socket or cursor failure, e.g. a refused connection, if there is
+    /// one. Failures with context are looked through.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            Self::Context(error, _) => error.io_error(),
+            Self::ParseId(error)
+            | Self::ParseFlag(error)
+            | Self::ParseQuestionCount(error)
+            | Self::ParseAnswerCount(error)
+            | Self::ParseAuthorityCount(error)
+            | Self::ParseAdditionalCount(error)
+            | Self::ReadByte(error)
+            | Self::ReadLength(error)
+            | Self::ReadQuestionType(error)
+            | Self::ReadQuestionClass(error)
+            | Self::ReadRecordType(error)
+            | Self::ReadRecordClass(error)
+            | Self::ReadRecordTTL(error)
+            | Self::ReadRecordDataLength(error)
+            | Self::ReadRecordData(error)
+            | Self::SocketBind(error)
+            | Self::SocketSend(error)
+            | Self::SocketRead(error)
+            | Self::SocketConnect(error)
+            | Self::DecompressReadByte(error)
+            | Self::DecompressSkip(error)
+            | Self::DecompressRestore(error) => error.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// What the error says about whether trying again may help. Names which are too long or hold
+    /// invalid bytes are the fault of the input, unless they were read from a message.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Context(error, context) => {
+                let read = context.stage == Some(Stage::Parse) || context.section.is_some();
+                match error.kind() {
+                    Self::InvalidByteInName | Self::LabelTooLong | Self::NameTooLong if read => {
+                        ErrorCategory::Protocol
+                    }
+                    _ => error.category(),
+                }
+            }
+            Self::SocketSend(_) | Self::SocketRead(_) | Self::SocketConnect(_) => {
+                ErrorCategory::Transient
+            }
+            Self::ParseResponse
+            | Self::ParseId(_)
+            | Self::ParseFlag(_)
+            | Self::ParseQuestionCount(_)
+            | Self::ParseAnswerCount(_)
+            | Self::ParseAuthorityCount(_)
+            | Self::ParseAdditionalCount(_)
+            | Self::ReadByte(_)
+            | Self::ReadLength(_)
+            | Self::ReadQuestionType(_)
+            | Self::ReadQuestionClass(_)
+            | Self::ReadRecordType(_)
+            | Self::ReadRecordClass(_)
+            | Self::ReadRecordTTL(_)
+            | Self::ReadRecordDataLength(_)
+            | Self::ReadRecordData(_)
+            | Self::ParseEdnsOption
+            | Self::UnrecognizedRecordType
+            | Self::DecompressReadByte(_)
+            | Self::DecompressSkip(_)
+            | Self::DecompressRestore(_)
+            | Self::UnknownDomainName
+            | Self::TooManyReferrals
+            | Self::CompressionLoop
+            | Self::ForwardPointer
+            | Self::Spf
+            | Self::Dkim
+            | Self::Dmarc
+            | Self::Naptr
+            | Self::Svcb
+            | Self::Delegation
+            | Self::ZoneTransfer
+            | Self::ZoneWalk => ErrorCategory::Protocol,
+            Self::InvalidByteInName
+            | Self::LabelTooLong
+            | Self::NameTooLong
+            | Self::SocketBind(_)
+            | Self::QuerySerialization
+            | Self::SystemConfig
+            | Self::HostsFile
+            | Self::ParseZone
+            | Self::InvalidUpstream
+            | Self::Blocklist
+            | Self::InvalidCidr
+            | Self::InvalidNat64Prefix
+            | Self::Batch
+            | Self::Dump
+            | Self::ParseCapture
+            | Self::WriteCapture
+            | Self::QueryLog
+            | Self::Bench
+            | Self::BindToDevice
+            | Self::Fixture
+            | Self::Control
+            | Self::Certificate
+            | Self::E164 => ErrorCategory::Input,
+        }
+    }
+
+    /// Whether sending the same query to the same server again may succeed: the network failed
+    /// on the way, but the server was not found to refuse queries or to be out of reach.
+    pub fn is_retryable(&self) -> bool {
+        if self.category() != ErrorCategory::Transient {
+            return false;
+        }
+        let Some(error) = self.io_error() else {
+            return true;
+        };
+        !matches!(
+            error.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::PermissionDenied
+                | io::ErrorKind::AddrNotAvailable
+                | io::ErrorKind::NetworkUnreachable
+                | io::ErrorKind::HostUnreachable
+                | io::ErrorKind::InvalidInput
+                | io::ErrorKind::Unsupported
+        )
+    }
+
+    /// Whether the error is a read which timed out waiting for a response, rather than one which
+    /// failed, e.g. as the server refused the query.
+    pub fn is_timeout(&self) -> bool {
+        *self == Self::SocketRead(None)
+            && self.io_error().is_some_and(|error| {
+                matches!(
+                    error.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                )
+            })
+    }
+
     pub fn exit_code(&self) -> i32 {
         match self {
             Self::ParseResponse => 2,
-            Self::ParseId => 3,
-            Self::ParseFlag => 4,
-            Self::ParseQuestionCount => 5,
-            Self::ParseAnswerCount => 6,
-            Self::ParseAuthorityCount => 7,
-            Self::ParseAdditionalCount => 8,
-            Self::ReadByte => 9,
-            Self::ReadLength => 10,
-            Self::ReadQuestionType => 11,
-            Self::ReadQuestionClass => 12,
-            Self::ReadRecordType => 13,
-            Self::ReadRecordClass => 14,
-            Self::ReadRecordTTL => 15,
-            Self::ReadRecordDataLength => 16,
-            Self::ReadRecordData => 17,
-            Self::SocketBind => 18,
-            Self::SocketSend => 19,
-            Self::SocketRead => 20,
-            Self::DecompressReadByte => 21,
-            Self::DecompressSkip => 22,
-            Self::DecompressRestore => 23,
+            Self::ParseId(_) => 3,
+            Self::ParseFlag(_) => 4,
+            Self::ParseQuestionCount(_) => 5,
+            Self::ParseAnswerCount(_) => 6,
+            Self::ParseAuthorityCount(_) => 7,
+            Self::ParseAdditionalCount(_) => 8,
+            Self::ReadByte(_) => 9,
+            Self::ReadLength(_) => 10,
+            Self::ReadQuestionType(_) => 11,
+            Self::ReadQuestionClass(_) => 12,
+            Self::ReadRecordType(_) => 13,
+            Self::ReadRecordClass(_) => 14,
+            Self::ReadRecordTTL(_) => 15,
+            Self::ReadRecordDataLength(_) => 16,
+            Self::ReadRecordData(_) => 17,
+            Self::SocketBind(_) => 18,
+            Self::SocketSend(_) => 19,
+            Self::SocketRead(_) => 20,
+            Self::DecompressReadByte(_) => 21,
+            Self::DecompressSkip(_) => 22,
+            Self::DecompressRestore(_) => 23,
             Self::QuerySerialization => 24,
             Self::UnrecognizedRecordType => 25,
             Self::InvalidByteInName => 26,
             Self::UnknownDomainName => 27,
+            Self::SystemConfig => 28,
+            Self::HostsFile => 29,
+            Self::ParseEdnsOption => 30,
+            Self::SocketConnect(_) => 31,
+            Self::ParseZone => 32,
+            Self::InvalidUpstream => 33,
+            Self::Blocklist => 34,
+            Self::InvalidCidr => 35,
+            Self::InvalidNat64Prefix => 36,
+            Self::Batch => 37,
+            Self::Dump => 38,
+            Self::ParseCapture => 39,
+            Self::WriteCapture => 40,
+            Self::QueryLog => 41,
+            Self::Bench => 42,
+            Self::CompressionLoop => 43,
+            Self::LabelTooLong => 44,
+            Self::NameTooLong => 45,
+            Self::ForwardPointer => 46,
+            Self::BindToDevice => 47,
+            Self::Fixture => 48,
+            Self::Control => 49,
+            Self::Spf => 50,
+            Self::Dkim => 51,
+            Self::Dmarc => 52,
+            Self::Certificate => 53,
+            Self::Naptr => 54,
+            Self::E164 => 55,
+            Self::Svcb => 56,
+            Self::Delegation => 57,
+            Self::ZoneTransfer => 58,
+            Self::ZoneWalk => 59,
+            Self::TooManyReferrals => 60,
+            Self::Context(error, _) => error.exit_code(),
         }
     }
 }
 
-impl Error for DnsError {}
+impl PartialEq for DnsError {
+    /// Errors compare by their kind alone, so that an error with context or an I/O error still
+    /// equals the bare variant, e.g. `DnsError::SocketRead(None)`.
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self.kind()) == std::mem::discriminant(other.kind())
+    }
+}
+
+impl Error for DnsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Context(error, _) => Some(error.as_ref()),
+            _ => self.io_error().map(|error| error as &(dyn Error + 'static)),
+        }
+    }
+}
 
 impl fmt::Display for DnsError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let description = match self {
             Self::ParseResponse => "Could not parse DNS response",
-            Self::ParseId => "Could not parse ID in header",
-            Self::ParseFlag => "Could not parse flag in header",
-            Self::ParseQuestionCount => "Could not parse number of questions",
-            Self::ParseAnswerCount => "Could not parse number of answers",
-            Self::ParseAuthorityCount => "Could not parse number of authorities",
-            Self::ParseAdditionalCount => "Could not parse number of additionals",
-            Self::ReadByte => "Could not read the next byte",
-            Self::ReadLength => "Could not read length in string buffer",
-            Self::ReadQuestionType => "Could not read type in question",
-            Self::ReadQuestionClass => "Could not read class in question",
-            Self::ReadRecordType => "Could not read type in record",
-            Self::ReadRecordClass => "Could not read class in record",
-            Self::ReadRecordTTL => "Could not read TTL in record",
-            Self::ReadRecordDataLength => "Could not read length of data in record",
-            Self::ReadRecordData => "Could not read data in record",
-            Self::SocketBind => "Could not bind to socket",
-            Self::SocketSend => "Could not send data through socket",
-            Self::SocketRead => "Could not read data from socket",
-            Self::DecompressReadByte => "Could not read additional byte to read skip offset",
-            Self::DecompressSkip => "Skip failed, most likely was out of bounds",
-            Self::DecompressRestore => "Could not restore cursor to previous position",
+            Self::ParseId(_) => "Could not parse ID in header",
+            Self::ParseFlag(_) => "Could not parse flag in header",
+            Self::ParseQuestionCount(_) => "Could not parse number of questions",
+            Self::ParseAnswerCount(_) => "Could not parse number of answers",
+            Self::ParseAuthorityCount(_) => "Could not parse number of authorities",
+            Self::ParseAdditionalCount(_) => "Could not parse number of additionals",
+            Self::ReadByte(_) => "Could not read the next byte",
+            Self::ReadLength(_) => "Could not read length in string buffer",
+            Self::ReadQuestionType(_) => "Could not read type in question",
+            Self::ReadQuestionClass(_) => "Could not read class in question",
+            Self::ReadRecordType(_) => "Could not read type in record",
+            Self::ReadRecordClass(_) => "Could not read class in record",
+            Self::ReadRecordTTL(_) => "Could not read TTL in record",
+            Self::ReadRecordDataLength(_) => "Could not read length of data in record",
+            Self::ReadRecordData(_) => "Could not read data in record",
+            Self::ParseEdnsOption => "Could not parse option in OPT record",
+            Self::SocketBind(_) => "Could not bind to socket",
+            Self::SocketSend(_) => "Could not send data through socket",
+            Self::SocketRead(_) => "Could not read data from socket",
+            Self::SocketConnect(_) => "Could not connect to the server",
+            Self::DecompressReadByte(_) => "Could not read additional byte to read skip offset",
+            Self::DecompressSkip(_) => "Skip failed, most likely was out of bounds",
+            Self::DecompressRestore(_) => "Could not restore cursor to previous position",
             Self::QuerySerialization => "Could not serialize DNS query",
             Self::UnrecognizedRecordType => "Did not recognize the record type value",
             Self::InvalidByteInName => "Found invalid byte in record name",
             Self::UnknownDomainName => "No nameservers are aware of the given domain name",
+            Self::TooManyReferrals => "The name servers referred the query too many times",
+            Self::SystemConfig => "Could not read the system resolver configuration",
+            Self::HostsFile => "Could not read the hosts file",
+            Self::ParseZone => "Could not read or parse the zone file",
+            Self::InvalidUpstream => "Could not parse the address of the upstream server",
+            Self::Blocklist => "Could not read the blocklist",
+            Self::InvalidCidr => "Could not parse the CIDR block",
+            Self::InvalidNat64Prefix => "Could not parse the NAT64 prefix",
+            Self::Batch => "Could not read or parse the batch file",
+            Self::Dump => "Could not read or decode the message dump",
+            Self::ParseCapture => "Could not parse the pcap or pcapng capture",
+            Self::WriteCapture => "Could not write the capture",
+            Self::QueryLog => "Could not open the query log",
+            Self::Bench => "The benchmark needs names to query and a rate above zero",
+            Self::CompressionLoop => "Compression pointers in a name loop back on themselves",
+            Self::LabelTooLong => "A label of a name is longer than 63 octets",
+            Self::NameTooLong => "A name is longer than 255 octets",
+            Self::ForwardPointer => "A compression pointer points at or past itself",
+            Self::BindToDevice => "Could not bind the socket to the network interface",
+            Self::Fixture => "Could not parse the mock fixture",
+            Self::Control => "The server did not carry out the control command",
+            Self::Spf => "The SPF policy is missing, malformed or needs too many lookups",
+            Self::Dkim => "The DKIM key record is malformed",
+            Self::Dmarc => "The DMARC policy is malformed or ambiguous",
+            Self::Certificate => "Could not parse the certificate",
+            Self::Naptr => "The NAPTR regular expression is malformed",
+            Self::E164 => "The telephone number is not in E.164 format",
+            Self::Svcb => "The HTTPS or SVCB aliases are too long a chain",
+            Self::Delegation => "The name is not delegated by a parent zone",
+            Self::ZoneTransfer => "The zone transfer was refused or cut short",
+            Self::ZoneWalk => "The zone has no NSEC or NSEC3 records to walk",
+            Self::Context(error, context) => return write!(f, "{} ({})", error, context),
         };
-        write!(f, "{:?}: {}", self, description)
+        match self.io_error() {
+            Some(error) => write!(f, "{}: {}: {}", self.name(), description, error),
+            None => write!(f, "{}: {}", self.name(), description),
+        }
     }
 }
+
+/// Validate which errors are worth retrying, and what the others are the fault of.
+#[test]
+fn test_error_categories() {
+    let timeout = DnsError::SocketRead(Some(io::ErrorKind::TimedOut.into()));
+    assert_eq!(timeout.category(), ErrorCategory::Transient);
+    assert!(timeout.is_retryable());
+    assert!(DnsError::SocketRead(None).is_retryable());
+    assert!(timeout.is_timeout());
+    assert!(!DnsError::SocketRead(Some(io::ErrorKind::ConnectionRefused.into())).is_timeout());
+    assert!(!DnsError::SocketSend(Some(io::ErrorKind::TimedOut.into())).is_timeout());
+
+    let refused = DnsError::SocketConnect(Some(io::ErrorKind::ConnectionRefused.into()))
+        .with_context(ErrorContext::exchange("192.0.2.53:53", Stage::Send));
+    assert_eq!(refused.category(), ErrorCategory::Transient);
+    assert!(!refused.is_retryable());
+
+    assert_eq!(
+        DnsError::ReadRecordData(None).category(),
+        ErrorCategory::Protocol
+    );
+    assert_eq!(
+        DnsError::UnknownDomainName.category(),
+        ErrorCategory::Protocol
+    );
+    assert_eq!(DnsError::ParseZone.category(), ErrorCategory::Input);
+    assert!(!DnsError::ParseZone.is_retryable());
+
+    // Names are the fault of the input, unless they were read from a message.
+    assert_eq!(DnsError::LabelTooLong.category(), ErrorCategory::Input);
+    let read = DnsError::LabelTooLong.with_context(ErrorContext {
+        section: Some("answer"),
+        ..Default::default()
+    });
+    assert_eq!(read.category(), ErrorCategory::Protocol);
+}
+
+/// Validate that an error with context describes the error along with where it happened, and that
+/// the context does not change its exit code or hide what caused it.
+#[test]
+fn test_error_context() {
+    let timeout = DnsError::SocketRead(Some(io::ErrorKind::TimedOut.into()))
+        .with_context(ErrorContext::exchange("192.0.2.53:53", Stage::Receive));
+    assert_eq!(
+        timeout.to_string(),
+        format!(
+            "SocketRead: Could not read data from socket: {} (stage: receive, server: 192.0.2.53:53)",
+            io::Error::from(io::ErrorKind::TimedOut)
+        )
+    );
+    assert_eq!(timeout.exit_code(), DnsError::SocketRead(None).exit_code());
+
+    // The error with context is caused by the error itself, which is caused by the I/O error.
+    let error = timeout.source().unwrap();
+    assert_eq!(
+        error.to_string(),
+        DnsError::SocketRead(Some(io::ErrorKind::TimedOut.into())).to_string()
+    );
+    let io_error = error.source().unwrap();
+    assert_eq!(
+        io_error.downcast_ref::<io::Error>().map(io::Error::kind),
+        Some(io::ErrorKind::TimedOut)
+    );
+    assert!(io_error.source().is_none());
+
+    // Context attached on the way up is merged, keeping the parts filled in first.
+    let read = DnsError::ReadRecordData(None)
+        .with_context(ErrorContext {
+            offset: Some(42),
+            section: Some("answer"),
+            index: Some(1),
+            ..Default::default()
+        })
+        .with_context(ErrorContext {
+            offset: Some(0),
+            ..ErrorContext::exchange("192.0.2.53:53", Stage::Parse)
+        });
+    assert_eq!(
+        read.to_string(),
+        "ReadRecordData: Could not read data in record \
+         (stage: parse, server: 192.0.2.53:53, offset: 42, section: answer, index: 1)"
+    );
+    assert_eq!(read.exit_code(), DnsError::ReadRecordData(None).exit_code());
+    assert!(read.source().unwrap().source().is_none());
+}
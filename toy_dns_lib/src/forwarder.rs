@@ -0,0 +1,1063 @@
+use crate::cache::{Cache, CacheKey};
+use crate::dns_name::DnsName;
+use crate::edns::Edns;
+use crate::errors::{DnsError, ErrorContext, Stage};
+use crate::header::{
+    Header, FLAG_RECURSION_DESIRED, FLAG_TRUNCATED, RCODE_REFUSED, RCODE_SERVER_FAILURE,
+};
+use crate::https::{DohUrl, HttpsTransport};
+use crate::in_flight::InFlight;
+use crate::metrics::Metrics;
+use crate::packet::Packet;
+use crate::pcap::PcapWriter;
+use crate::query::{receive_buffer_size, receive_response, retry_over_tcp, CLASS_IN};
+use crate::question::Question;
+use crate::record::RecordType;
+use crate::socket::{bind_to_device, bind_udp, local_address, DnsTransport};
+use crate::tcp::TcpTransport;
+use crate::throttle::QueryThrottle;
+use crate::tls::{TlsTransport, DEFAULT_TLS_PORT};
+use crate::trace::{Exchange, Trace};
+use crate::upstream_pool::UpstreamPool;
+use log::{info, warn};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+use std::fs::File;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Port DNS servers listen on over UDP.
+pub const DEFAULT_UDP_PORT: u16 = 53;
+
+/// UDP payload size advertised to upstreams with EDNS. See `MAX_EDNS_RESPONSE_SIZE` of the server.
+const ADVERTISED_PAYLOAD_SIZE: u16 = 1232;
+
+/// Largest response read from an upstream.
+const RECEIVE_BUFFER_SIZE: usize = 4096;
+
+/// How long to wait for an upstream over UDP or TCP before moving on to the next one.
+const UDP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An upstream server queries are forwarded to, along with the protocol to reach it with.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Upstream {
+    /// Plain DNS over UDP.
+    Udp(SocketAddr),
+
+    /// DNS over TLS, at the given host and port. The host may be a name or an IP address and the
+    /// server's certificate must be valid for it.
+    Tls(String),
+
+    /// DNS over HTTPS, at the given URL.
+    Https(String),
+}
+
+impl Upstream {
+    /// Parse an upstream given as `192.0.2.1`, `udp://[2001:db8::1]:5353`, `tls://dns.quad9.net`,
+    /// `tls://1.1.1.1:853` or `https://dns.google/dns-query`. The port defaults to the standard
+    /// port of the protocol.
+    ///
+    /// # Argument
+    /// * `upstream`: The upstream to parse.
+    pub fn parse(upstream: &str) -> Result<Upstream, DnsError> {
+        if upstream.starts_with("https://") {
+            DohUrl::parse(upstream)?;
+            return Ok(Upstream::Https(upstream.to_owned()));
+        }
+
+        if let Some(host) = upstream.strip_prefix("tls://") {
+            if host.is_empty() || host.contains('/') {
+                return Err(DnsError::InvalidUpstream);
+            }
+            if let Ok(address) = host.parse::<SocketAddr>() {
+                return Ok(Upstream::Tls(address.to_string()));
+            }
+            if let Ok(ip) = host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+            {
+                return Ok(Upstream::Tls(
+                    SocketAddr::new(ip, DEFAULT_TLS_PORT).to_string(),
+                ));
+            }
+            if host.contains(':') {
+                return Ok(Upstream::Tls(host.to_owned()));
+            }
+            return Ok(Upstream::Tls(format!("{}:{}", host, DEFAULT_TLS_PORT)));
+        }
+
+        let address = upstream.strip_prefix("udp://").unwrap_or(upstream);
+        if let Ok(address) = address.parse::<SocketAddr>() {
+            return Ok(Upstream::Udp(address));
+        }
+        let Ok(ip) = address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        else {
+            return Err(DnsError::InvalidUpstream);
+        };
+        Ok(Upstream::Udp(SocketAddr::new(ip, DEFAULT_UDP_PORT)))
+    }
+
+    /// Parse a server given the way dig takes it after `@`, e.g. `8.8.8.8`,
+    /// `[2001:4860:4860::8888]`, `dns.google` or `dns.google:853`. Servers on the DNS over TLS
+    /// port are reached over TLS. Other host names are looked up with the system resolver. Any
+    /// upstream accepted by `parse()` is accepted as well.
+    ///
+    /// # Arguments
+    /// * `server`: The server to parse.
+    /// * `default_port`: The port of servers given without one, normally `DEFAULT_UDP_PORT`.
+    pub fn parse_server(server: &str, default_port: u16) -> Result<Upstream, DnsError> {
+        if server.contains("://") {
+            return Self::parse(server);
+        }
+        let address = match server
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            Ok(ip) => Some(SocketAddr::new(ip, default_port)),
+            Err(_) => server.parse::<SocketAddr>().ok(),
+        };
+        if let Some(address) = address {
+            return match address.port() {
+                DEFAULT_TLS_PORT => Ok(Upstream::Tls(address.to_string())),
+                _ => Ok(Upstream::Udp(address)),
+            };
+        }
+
+        let (host, port) = match server.rsplit_once(':') {
+            Some((host, port)) => {
+                let Ok(port) = port.parse::<u16>() else {
+                    return Err(DnsError::InvalidUpstream);
+                };
+                (host, port)
+            }
+            None => (server, default_port),
+        };
+        if host.is_empty() {
+            return Err(DnsError::InvalidUpstream);
+        }
+        if port == DEFAULT_TLS_PORT {
+            return Ok(Upstream::Tls(format!("{}:{}", host, port)));
+        }
+        let Some(address) = (host, port)
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+        else {
+            return Err(DnsError::InvalidUpstream);
+        };
+        Ok(Upstream::Udp(address))
+    }
+}
+
+impl fmt::Display for Upstream {
+    /// The address of the upstream as understood by `UpstreamSocket`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Udp(address) => write!(f, "{}", address),
+            Self::Tls(address) => write!(f, "tls://{}", address),
+            Self::Https(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// The transport an `UpstreamSocket` last sent a message with.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Transport {
+    Udp,
+    Tcp,
+    Tls,
+    Https,
+}
+
+/// A socket which picks the transport by the address messages are sent to: `tls://host:port`
+/// goes over TLS, `https://` URLs over HTTPS and anything else over UDP, or TCP if forced with
+/// `set_tcp()`. It lets recursion and forwarding over any protocol share a single socket.
+pub struct UpstreamSocket {
+    /// Socket for plain DNS.
+    udp: UdpSocket,
+
+    /// The address the socket for plain DNS is bound to, as given to `bind()`.
+    local_address: String,
+
+    /// Whether each query over UDP is sent from a fresh port, as the socket was bound to port 0.
+    fresh_ports: bool,
+
+    /// How long to wait for a server before giving up on it.
+    timeout: Duration,
+
+    /// The network interface datagrams are sent through, if any.
+    device: Option<String>,
+
+    /// Transport for plain DNS over TCP.
+    tcp: TcpTransport,
+
+    /// Whether plain DNS goes over TCP rather than UDP.
+    force_tcp: bool,
+
+    /// Transport for DNS over TLS.
+    tls: TlsTransport,
+
+    /// Transport for DNS over HTTPS.
+    https: HttpsTransport,
+
+    /// The transport to read the next response from.
+    last_transport: Transport,
+
+    /// Where to record the messages sent and received, if anywhere.
+    capture: Option<Mutex<PcapWriter<File>>>,
+}
+
+impl UpstreamSocket {
+    /// Bind the transport to the provided address.
+    ///
+    /// # Argument
+    /// * `addr`: The (local) address to bind to.
+    pub fn bind(addr: &str) -> Result<UpstreamSocket, DnsError> {
+        let udp = bind_udp(addr)?;
+        udp.set_read_timeout(Some(UDP_TIMEOUT))
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        let mut tcp = TcpTransport::bind(addr)?;
+        tcp.set_timeout(UDP_TIMEOUT);
+        let fresh_ports = addr
+            .parse::<SocketAddr>()
+            .is_ok_and(|local| local.port() == 0);
+        Ok(UpstreamSocket {
+            udp,
+            local_address: addr.to_owned(),
+            fresh_ports,
+            timeout: UDP_TIMEOUT,
+            device: None,
+            tcp,
+            force_tcp: false,
+            tls: TlsTransport::bind(addr)?,
+            https: HttpsTransport::bind(addr)?,
+            last_transport: Transport::Udp,
+            capture: None,
+        })
+    }
+
+    /// Set how long to wait for a server before giving up on it, over any transport.
+    ///
+    /// # Argument
+    /// * `timeout`: The timeout. It must not be zero.
+    pub fn set_timeout(&mut self, timeout: Duration) -> Result<(), DnsError> {
+        self.timeout = timeout;
+        self.udp
+            .set_read_timeout(Some(timeout))
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        self.tcp.set_timeout(timeout);
+        self.tls.set_timeout(timeout);
+        self.https.set_timeout(timeout);
+        Ok(())
+    }
+
+    /// Send datagrams through the given network interface, whatever the routing table says. Only
+    /// UDP is affected: connections over TCP, TLS and HTTPS follow the routing table.
+    ///
+    /// # Argument
+    /// * `device`: The name of the interface, e.g. "eth0".
+    pub fn bind_to_device(&mut self, device: &str) -> Result<(), DnsError> {
+        bind_to_device(&self.udp, device)?;
+        self.device = Some(device.to_owned());
+        Ok(())
+    }
+
+    /// Replace the socket for plain DNS with one bound to a fresh port, picked at random by the
+    /// system, so that forging a response takes guessing the port along with the ID of the query
+    /// (RFC 5452, section 9.2). Responses to earlier queries are left behind with the old socket.
+    /// A socket bound to an unspecified address is bound again on the address family of the
+    /// server, so that one socket reaches servers over IPv4 and IPv6 alike.
+    ///
+    /// # Argument
+    /// * `server`: The server the next query is sent to, if its address is known.
+    fn rebind_udp(&mut self, server: Option<SocketAddr>) -> Result<(), DnsError> {
+        let unspecified = self
+            .local_address
+            .parse::<SocketAddr>()
+            .is_ok_and(|local| local.ip().is_unspecified());
+        let udp = match (unspecified, server) {
+            (true, Some(_)) => bind_udp(local_address(server))?,
+            _ => bind_udp(&self.local_address)?,
+        };
+        udp.set_read_timeout(Some(self.timeout))
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        if let Some(device) = &self.device {
+            bind_to_device(&udp, device)?;
+        }
+        self.udp = udp;
+        Ok(())
+    }
+
+    /// Record every message sent and received from now on in a capture, as UDP datagrams between
+    /// the local port and the server whatever the transport. Queries to servers given by name, as
+    /// over HTTPS, are only recorded by their responses.
+    ///
+    /// # Argument
+    /// * `capture`: The capture to write to.
+    pub fn set_capture(&mut self, capture: PcapWriter<File>) {
+        self.capture = Some(Mutex::new(capture));
+    }
+
+    /// Record a message in the capture, if any. Failing to do so does not fail the exchange.
+    ///
+    /// # Arguments
+    /// * `source`: Where the message was sent from, or `None` for the local port.
+    /// * `destination`: Where the message was sent to, or `None` for the local port.
+    /// * `data`: The message.
+    fn record(&self, source: Option<SocketAddr>, destination: Option<SocketAddr>, data: &[u8]) {
+        let Some(capture) = &self.capture else {
+            return;
+        };
+        let local = self
+            .udp
+            .local_addr()
+            .unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        let mut capture = match capture.lock() {
+            Ok(capture) => capture,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let result =
+            capture.write_datagram(source.unwrap_or(local), destination.unwrap_or(local), data);
+        if let Err(error) = result {
+            warn!("Could not record the message: {}", error);
+        }
+    }
+
+    /// Send the given buffer over the transport picked by the address.
+    ///
+    /// # Arguments
+    /// * `buf`: The buffer to send.
+    /// * `addr`: The address to send `buf` to.
+    fn send_on_transport(&mut self, buf: &[u8], addr: &str) -> Result<usize, DnsError> {
+        if let Some(host) = addr.strip_prefix("tls://") {
+            self.last_transport = Transport::Tls;
+            return self.tls.send(buf, host);
+        }
+        if addr.starts_with("https://") {
+            self.last_transport = Transport::Https;
+            return self.https.send(buf, addr);
+        }
+        if self.force_tcp {
+            self.last_transport = Transport::Tcp;
+            return self.tcp.send(buf, addr);
+        }
+        self.last_transport = Transport::Udp;
+        if self.fresh_ports {
+            self.rebind_udp(addr.parse::<SocketAddr>().ok())?;
+        }
+        DnsTransport::send(&mut self.udp, buf, addr)
+    }
+}
+
+impl DnsTransport for UpstreamSocket {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        let size = self.send_on_transport(buf, addr)?;
+        let server = addr.strip_prefix("tls://").unwrap_or(addr);
+        if let Ok(server) = server.parse::<SocketAddr>() {
+            self.record(None, Some(server), buf);
+        }
+        Ok(size)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        let (size, server) = match self.last_transport {
+            Transport::Udp => DnsTransport::recv_from(&self.udp, buf),
+            Transport::Tcp => self.tcp.recv_from(buf),
+            Transport::Tls => self.tls.recv_from(buf),
+            Transport::Https => self.https.recv_from(buf),
+        }?;
+        self.record(Some(server), None, &buf[..size.min(buf.len())]);
+        Ok((size, server))
+    }
+
+    fn is_connection_oriented(&self) -> bool {
+        self.force_tcp
+    }
+
+    fn set_idle_timeout(&mut self, addr: &str, timeout: Option<Duration>) {
+        if self.last_transport == Transport::Tcp {
+            self.tcp.set_idle_timeout(addr, timeout);
+        }
+    }
+
+    /// Send plain DNS over TCP rather than UDP, e.g. to get past middleboxes which drop UDP or to
+    /// retry a query whose response was truncated.
+    fn set_tcp(&mut self, tcp: bool) {
+        self.force_tcp = tcp;
+    }
+}
+
+/// Relays queries to upstream resolvers and caches their responses, so that a server can act as
+/// a local DNS proxy. Upstreams are tried in order until one of them answers, the order being up
+/// to the pool if there is one.
+pub struct Forwarder {
+    /// The upstreams to forward queries to, in order of preference.
+    pub upstreams: Vec<Upstream>,
+
+    /// Cache of previous responses. It can be shared with other forwarders.
+    pub cache: Option<Arc<Cache>>,
+
+    /// Count cache lookups, the responses of upstreams, how long they took and the queries they
+    /// left unanswered.
+    pub metrics: Option<Metrics>,
+
+    /// Health of the upstreams, to skip those which stopped responding and choose which one to
+    /// ask first. Without a pool, upstreams are asked in order.
+    pub pool: Option<Arc<UpstreamPool>>,
+
+    /// Queries on their way, so that identical queries made concurrently on a cache miss are
+    /// forwarded once. It can be shared with other forwarders.
+    pub in_flight: Option<Arc<InFlight>>,
+
+    /// Limit the rate of queries forwarded to each upstream, holding queries back until their
+    /// turn.
+    pub throttle: Option<QueryThrottle>,
+}
+
+impl Forwarder {
+    /// Create a forwarder relaying queries to the given upstreams.
+    ///
+    /// # Argument
+    /// * `upstreams`: The upstreams to forward queries to, in order of preference.
+    pub fn new(upstreams: Vec<Upstream>) -> Forwarder {
+        Forwarder {
+            upstreams,
+            cache: None,
+            metrics: None,
+            pool: None,
+            in_flight: None,
+            throttle: None,
+        }
+    }
+
+    /// Answer the given name and record type from the cache, or else from the first upstream which
+    /// responds with anything but a server failure or a refusal. When none responds, an expired
+    /// answer may be served stale, see `CacheConfig::serve_stale`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `domain_name`: The name to resolve.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn forward(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        self.forward_traced(socket, domain_name, record_type, rand_seed, None)
+    }
+
+    /// Like `forward()`, but the exchange with the upstream which answered, if any, is added to
+    /// the given trace. Answers from the cache, or from an identical query made concurrently, add
+    /// nothing.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `domain_name`: The name to resolve.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `trace`: Where to add the exchanges, if anywhere.
+    pub fn forward_traced(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+        trace: Option<&Trace>,
+    ) -> Result<Packet, DnsError> {
+        let key = CacheKey::new(domain_name, record_type);
+        let cached = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.get(&key, Instant::now()));
+        if let Some(Some(packet)) = cached {
+            info!("Answering {} from the cache", domain_name);
+            return Ok(packet);
+        }
+
+        let result = match &self.in_flight {
+            Some(in_flight) => in_flight.run(&key, || {
+                self.query_upstreams(socket, &key, rand_seed, trace)
+            }),
+            None => self.query_upstreams(socket, &key, rand_seed, trace),
+        };
+        let Some(cache) = &self.cache else {
+            return result;
+        };
+        match result {
+            Ok(packet) => {
+                cache.insert(key, &packet, Instant::now());
+                Ok(packet)
+            }
+            result => cache.or_stale(&key, result, Instant::now()),
+        }
+    }
+
+    /// Refresh the cache entries which were queued for prefetching. See `Resolver::prefetch`.
+    /// Returns the number of refreshed entries.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn prefetch(&self, socket: &mut dyn DnsTransport, rand_seed: Option<usize>) -> usize {
+        let Some(cache) = &self.cache else {
+            return 0;
+        };
+
+        let mut refreshed = 0;
+        for key in cache.take_prefetch_queue() {
+            match self.query_upstreams(socket, &key, rand_seed, None) {
+                Ok(packet) => {
+                    info!("Prefetched {} {}", key.record_type, key.domain_name);
+                    cache.insert(key, &packet, Instant::now());
+                    refreshed += 1;
+                }
+                Err(error) => info!("Could not prefetch {}: {}", key.domain_name, error),
+            }
+        }
+        refreshed
+    }
+
+    /// Probe the upstreams which are due a health probe, if there is a pool. See
+    /// `UpstreamPool::probe()`. Returns the number of upstreams which responded.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn probe(&self, socket: &mut dyn DnsTransport, rand_seed: Option<usize>) -> usize {
+        match &self.pool {
+            Some(pool) => pool.probe(socket, &self.upstreams, rand_seed),
+            None => 0,
+        }
+    }
+
+    /// Send the question to each upstream in turn until one of them answers it.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `key`: The question to ask.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `trace`: Where to add the exchanges with upstreams which responded, if anywhere.
+    fn query_upstreams(
+        &self,
+        socket: &mut dyn DnsTransport,
+        key: &CacheKey,
+        rand_seed: Option<usize>,
+        trace: Option<&Trace>,
+    ) -> Result<Packet, DnsError> {
+        let id = match rand_seed {
+            None => rand::thread_rng().gen_range(0..=u16::MAX),
+            Some(value) => ChaCha8Rng::seed_from_u64(value as u64).gen_range(0..=u16::MAX),
+        };
+        let query = Packet {
+            header: Header {
+                id,
+                flags: FLAG_RECURSION_DESIRED,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name: DnsName::from(key.domain_name.as_str()),
+                q_type: key.record_type,
+                q_class: CLASS_IN,
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![Edns::new(ADVERTISED_PAYLOAD_SIZE).to_record()?],
+        }
+        .serialize()?;
+
+        // The last failure is reported if no upstream answers. A server failure or a refusal still
+        // beats not having any response at all.
+        let upstreams = match &self.pool {
+            Some(pool) => pool.order(&self.upstreams),
+            None => self.upstreams.iter().collect(),
+        };
+        let mut result = Err(DnsError::SocketConnect(None));
+        for upstream in upstreams {
+            info!(
+                "Forwarding {} {} to {}",
+                key.record_type, key.domain_name, upstream
+            );
+            if let Some(throttle) = &self.throttle {
+                throttle.wait(&upstream.to_string());
+            }
+            let sent_at = Instant::now();
+            let exchanged = Self::exchange(socket, &query, &upstream.to_string());
+            match (&exchanged, &self.metrics) {
+                (Ok(_), Some(metrics)) => metrics.record_upstream_response(sent_at.elapsed()),
+                (Err(error), Some(metrics)) if error.is_timeout() => {
+                    metrics.record_upstream_timeout()
+                }
+                _ => (),
+            }
+            match (&exchanged, &self.pool) {
+                (Ok(_), Some(pool)) => {
+                    pool.record_response(&upstream.to_string(), sent_at.elapsed())
+                }
+                (Err(_), Some(pool)) => pool.record_failure(&upstream.to_string()),
+                _ => (),
+            }
+            let (packet, received_bytes) = match exchanged {
+                Ok(exchanged) => exchanged,
+                Err(error) => {
+                    warn!("No response from {}: {}", upstream, error);
+                    result = Err(error);
+                    continue;
+                }
+            };
+            if let Some(trace) = trace {
+                trace.push(Exchange {
+                    server_address: upstream.to_string(),
+                    server_name: "".to_owned(),
+                    domain_name: key.domain_name.clone(),
+                    record_type: key.record_type,
+                    response: packet.clone(),
+                    received_bytes,
+                    elapsed: sent_at.elapsed(),
+                });
+            }
+            let rcode = packet.rcode();
+            if rcode != RCODE_SERVER_FAILURE && rcode != RCODE_REFUSED {
+                return Ok(packet);
+            }
+            warn!("{} failed to answer with RCODE {}", upstream, rcode);
+            result = Ok(packet);
+        }
+        result
+    }
+
+    /// Send the query to an upstream and read its response, along with its size in octets.
+    /// Responses which do not come from the upstream, or do not match the query, are ignored; see
+    /// `receive_response()`. A truncated response is retried over TCP when the socket can. Errors
+    /// tell the upstream and the stage of the exchange they happened at.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstream.
+    /// * `query`: The query in wire format.
+    /// * `addr`: The address of the upstream.
+    fn exchange(
+        socket: &mut dyn DnsTransport,
+        query: &[u8],
+        addr: &str,
+    ) -> Result<(Packet, usize), DnsError> {
+        socket
+            .send(query, addr)
+            .map_err(|error| error.with_context(ErrorContext::exchange(addr, Stage::Send)))?;
+        let mut buf = vec![0u8; receive_buffer_size(socket, RECEIVE_BUFFER_SIZE)];
+        let (mut received_bytes, mut packet) = receive_response(socket, &mut buf, query, addr)?;
+        if packet.header.has_flag(FLAG_TRUNCATED) {
+            if let Some(retried) = retry_over_tcp(socket, &mut buf, query, addr)? {
+                (received_bytes, packet) = retried;
+            }
+        }
+        Ok((packet, received_bytes))
+    }
+}
+
+#[cfg(test)]
+pub(crate) const TEST_UPSTREAM: &str = "192.0.2.53:53";
+
+/// A mock socket on which `TEST_UPSTREAM` answers twitter.com A with the given RCODE.
+#[cfg(test)]
+pub(crate) fn test_upstream(rcode: u16) -> crate::socket::MockSocket {
+    use crate::record::Record;
+    use crate::ttl::Ttl;
+
+    let answer = Record {
+        name: DnsName::from("twitter.com"),
+        r_type: RecordType::A,
+        r_class: CLASS_IN,
+        ttl: Ttl::from(1800),
+        data: vec![104, 244, 42, 193],
+    };
+    test_upstream_answering(vec![(
+        CacheKey::new("twitter.com", RecordType::A),
+        rcode,
+        vec![answer],
+    )])
+}
+
+/// A mock socket on which `TEST_UPSTREAM` answers each question with the given RCODE and
+/// records.
+#[cfg(test)]
+pub(crate) fn test_upstream_answering(
+    responses: Vec<(CacheKey, u16, Vec<crate::record::Record>)>,
+) -> crate::socket::MockSocket {
+    let mut socket = crate::socket::MockSocket::default();
+    socket.register_response_data(test_upstream_data(responses));
+    socket
+}
+
+/// The exchanges in which `TEST_UPSTREAM` answers each question with the given RCODE and
+/// records. See `test_upstream_answering()`.
+#[cfg(test)]
+fn test_upstream_data(
+    responses: Vec<(CacheKey, u16, Vec<crate::record::Record>)>,
+) -> Vec<(crate::socket::MockKey, crate::socket::MockData)> {
+    use crate::header::FLAG_RESPONSE;
+    use crate::socket::{MockData, MockKey};
+
+    let mut data: Vec<(MockKey, MockData)> = Vec::with_capacity(responses.len());
+    for (key, rcode, answers) in responses {
+        let query = Packet {
+            header: Header {
+                id: 15212,
+                flags: FLAG_RECURSION_DESIRED,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name: DnsName::from(key.domain_name.as_str()),
+                q_type: key.record_type,
+                q_class: CLASS_IN,
+            }],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![Edns::new(ADVERTISED_PAYLOAD_SIZE).to_record().unwrap()],
+        };
+        let mut response = query.clone();
+        response.header.flags |= FLAG_RESPONSE | rcode;
+        response.answers = answers;
+        // The response fills the whole receive buffer, as the mock socket expects.
+        let mut response = response.serialize().unwrap();
+        response.resize(RECEIVE_BUFFER_SIZE, 0);
+
+        data.push((
+            MockKey {
+                query_bytes: query.serialize().unwrap(),
+                server_ip: TEST_UPSTREAM.to_owned(),
+            },
+            MockData { data: response },
+        ));
+    }
+    data
+}
+
+/// Validate parsing of upstreams for each protocol, with and without ports.
+#[test]
+fn test_parsing_upstreams() -> Result<(), DnsError> {
+    assert_eq!(
+        Upstream::parse("192.0.2.1")?,
+        Upstream::Udp("192.0.2.1:53".parse().unwrap())
+    );
+    assert_eq!(
+        Upstream::parse("udp://[2001:db8::1]:5353")?,
+        Upstream::Udp("[2001:db8::1]:5353".parse().unwrap())
+    );
+    assert_eq!(
+        Upstream::parse("2001:db8::1")?.to_string(),
+        "[2001:db8::1]:53"
+    );
+    assert_eq!(
+        Upstream::parse("tls://1.1.1.1")?.to_string(),
+        "tls://1.1.1.1:853"
+    );
+    assert_eq!(
+        Upstream::parse("tls://dns.quad9.net")?,
+        Upstream::Tls("dns.quad9.net:853".to_owned())
+    );
+    assert_eq!(
+        Upstream::parse("https://dns.google/dns-query")?.to_string(),
+        "https://dns.google/dns-query"
+    );
+    assert_eq!(
+        Upstream::parse("dns.google"),
+        Err(DnsError::InvalidUpstream)
+    );
+    assert_eq!(Upstream::parse("tls://"), Err(DnsError::InvalidUpstream));
+    Ok(())
+}
+
+/// Validate parsing of servers given dig-style.
+#[test]
+fn test_parsing_servers() -> Result<(), DnsError> {
+    assert_eq!(
+        Upstream::parse_server("8.8.8.8", DEFAULT_UDP_PORT)?,
+        Upstream::Udp("8.8.8.8:53".parse().unwrap())
+    );
+    assert_eq!(
+        Upstream::parse_server("[2001:4860:4860::8888]", DEFAULT_UDP_PORT)?.to_string(),
+        "[2001:4860:4860::8888]:53"
+    );
+    assert_eq!(
+        Upstream::parse_server("1.1.1.1:853", DEFAULT_UDP_PORT)?,
+        Upstream::Tls("1.1.1.1:853".to_owned())
+    );
+    assert_eq!(
+        Upstream::parse_server("dns.google:853", DEFAULT_UDP_PORT)?,
+        Upstream::Tls("dns.google:853".to_owned())
+    );
+    assert_eq!(
+        Upstream::parse_server("https://dns.google/dns-query", DEFAULT_UDP_PORT)?,
+        Upstream::Https("https://dns.google/dns-query".to_owned())
+    );
+    assert_eq!(
+        Upstream::parse_server(":853", DEFAULT_UDP_PORT),
+        Err(DnsError::InvalidUpstream)
+    );
+    assert_eq!(
+        Upstream::parse_server("dns.google:dns", DEFAULT_UDP_PORT),
+        Err(DnsError::InvalidUpstream)
+    );
+
+    // The default port only applies to servers given without one.
+    assert_eq!(
+        Upstream::parse_server("127.0.0.1", 5300)?,
+        Upstream::Udp("127.0.0.1:5300".parse().unwrap())
+    );
+    assert_eq!(
+        Upstream::parse_server("[::1]", 8600)?.to_string(),
+        "[::1]:8600"
+    );
+    assert_eq!(
+        Upstream::parse_server("127.0.0.1:53", 5300)?,
+        Upstream::Udp("127.0.0.1:53".parse().unwrap())
+    );
+    Ok(())
+}
+
+/// Validate that forwarded responses are cached and answered from the cache afterwards.
+#[test]
+fn test_forwarding_with_cache() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+    use crate::socket::MockSocket;
+
+    let mut forwarder = Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]);
+    forwarder.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+
+    let mut upstream = test_upstream(0);
+    let packet = forwarder.forward(&mut upstream, "twitter.com", RecordType::A, Some(0))?;
+    assert_eq!(packet.answers[0].data, [104, 244, 42, 193]);
+
+    // The upstream is gone, yet the name is still answered.
+    let mut upstream = MockSocket::default();
+    let packet = forwarder.forward(&mut upstream, "TWITTER.com", RecordType::A, Some(0))?;
+    assert_eq!(packet.answers[0].data, [104, 244, 42, 193]);
+    assert!(forwarder
+        .forward(&mut upstream, "twitter.com", RecordType::AAAA, Some(0))
+        .is_err());
+    Ok(())
+}
+
+/// Validate that truncated responses are retried over TCP, and neither cached nor retried when
+/// the socket cannot switch to TCP.
+#[test]
+fn test_forwarding_truncated_response() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+    use crate::header::FLAG_RESPONSE;
+    use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let mut forwarder = Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]);
+    forwarder.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+    let mut upstream = test_upstream(FLAG_TRUNCATED);
+    let packet = forwarder.forward(&mut upstream, "twitter.com", RecordType::A, Some(0))?;
+    assert!(packet.header.has_flag(FLAG_TRUNCATED));
+    let key = CacheKey::new("twitter.com", RecordType::A);
+    assert!(forwarder
+        .cache
+        .as_ref()
+        .unwrap()
+        .get(&key, Instant::now())
+        .is_none());
+
+    // The upstream answers over UDP with a truncated response, and in full over TCP.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let udp = UdpSocket::bind(addr).unwrap();
+    let respond = |query: &[u8], flags: u16, answers: usize| {
+        let mut response = Packet::parse(query).unwrap();
+        response.header.flags |= FLAG_RESPONSE | flags;
+        for index in 0..answers {
+            response.answers.push(crate::record::Record {
+                name: DnsName::from("twitter.com"),
+                r_type: RecordType::A,
+                r_class: CLASS_IN,
+                ttl: crate::ttl::Ttl::from(60),
+                data: vec![192, 0, 2, index as u8],
+            });
+        }
+        response.serialize().unwrap()
+    };
+    let server = std::thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let (size, client) = udp.recv_from(&mut buf).unwrap();
+        udp.send_to(&respond(&buf[..size], FLAG_TRUNCATED, 0), client)
+            .unwrap();
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut query = vec![0u8; stream.read_u16::<BigEndian>().unwrap() as usize];
+        stream.read_exact(&mut query).unwrap();
+        let response = respond(&query, 0, 40);
+        stream
+            .write_u16::<BigEndian>(response.len() as u16)
+            .unwrap();
+        stream.write_all(&response).unwrap();
+    });
+
+    let forwarder = Forwarder::new(vec![Upstream::parse(&addr.to_string())?]);
+    let mut socket = UpstreamSocket::bind("127.0.0.1:0")?;
+    let packet = forwarder.forward(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    assert!(!packet.header.has_flag(FLAG_TRUNCATED));
+    assert_eq!(packet.answers.len(), 40);
+    assert!(!socket.is_connection_oriented());
+    server.join().unwrap();
+    Ok(())
+}
+
+/// Validate that a response from another address than the upstream's is ignored rather than
+/// forwarded or cached.
+#[test]
+fn test_forwarding_ignores_wrong_source() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+    use crate::socket::{MockFault, MockSocket};
+
+    let mut forwarder = Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]);
+    forwarder.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+    let mut upstream = MockSocket::default();
+    let forger = "203.0.113.66:53".parse().unwrap();
+    let responses = vec![(CacheKey::new("twitter.com", RecordType::A), 0, vec![])];
+    for (key, data) in test_upstream_data(responses) {
+        upstream.register_faulty_response(key, data, vec![MockFault::WrongSource(forger)]);
+    }
+
+    let forwarded = forwarder.forward(&mut upstream, "twitter.com", RecordType::A, Some(0));
+    assert_eq!(forwarded.err(), Some(DnsError::SocketRead(None)));
+    assert!(forwarder.cache.as_ref().unwrap().is_empty());
+    Ok(())
+}
+
+/// Validate that upstreams failing to answer are skipped in favor of the next one.
+#[test]
+fn test_forwarding_fails_over() -> Result<(), DnsError> {
+    let forwarder = Forwarder::new(vec![
+        Upstream::parse("192.0.2.1")?,
+        Upstream::parse(TEST_UPSTREAM)?,
+    ]);
+    let packet = forwarder.forward(&mut test_upstream(0), "twitter.com", RecordType::A, Some(0))?;
+    assert_eq!(packet.answers[0].data, [104, 244, 42, 193]);
+
+    // A server failure is passed on when no upstream does any better.
+    let packet = forwarder.forward(
+        &mut test_upstream(RCODE_SERVER_FAILURE),
+        "twitter.com",
+        RecordType::A,
+        Some(0),
+    )?;
+    assert_eq!(packet.header.rcode(), RCODE_SERVER_FAILURE);
+    Ok(())
+}
+
+/// Validate that upstreams which keep failing to respond are ejected from the pool, so that
+/// queries go straight to the upstreams which respond.
+#[test]
+fn test_forwarding_through_pool() -> Result<(), DnsError> {
+    use crate::upstream_pool::PoolStrategy;
+
+    let mut forwarder = Forwarder::new(vec![
+        Upstream::parse("192.0.2.1")?,
+        Upstream::parse(TEST_UPSTREAM)?,
+    ]);
+    let pool = Arc::new(UpstreamPool::new(PoolStrategy::Failover));
+    forwarder.pool = Some(Arc::clone(&pool));
+
+    for _ in 0..3 {
+        forwarder.forward(&mut test_upstream(0), "twitter.com", RecordType::A, Some(0))?;
+    }
+    assert!(pool.is_ejected("192.0.2.1:53"));
+    assert!(!pool.is_ejected(TEST_UPSTREAM));
+    assert_eq!(
+        pool.order(&forwarder.upstreams),
+        [&Upstream::parse(TEST_UPSTREAM)?]
+    );
+    Ok(())
+}
+
+/// Validate that each query over UDP leaves from a port of its own.
+#[test]
+fn test_upstream_socket_uses_fresh_ports() -> Result<(), DnsError> {
+    let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap().to_string();
+
+    let mut socket = UpstreamSocket::bind("127.0.0.1:0")?;
+    let mut ports = Vec::new();
+    let mut buf = [0u8; 16];
+    for message in [[1, 2], [3, 4], [5, 6]] {
+        socket.send(&message, &addr)?;
+        let (size, client) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(buf[..size], message);
+        ports.push(client.port());
+    }
+    ports.dedup();
+    assert_eq!(ports.len(), 3);
+    Ok(())
+}
+
+/// Validate that a socket bound to an unspecified address reaches servers over IPv4 and IPv6
+/// alike, binding each fresh port on the family of the server.
+#[test]
+fn test_upstream_socket_follows_the_family_of_the_server() -> Result<(), DnsError> {
+    let mut socket = UpstreamSocket::bind("0.0.0.0:0")?;
+    let mut buf = [0u8; 16];
+    for local in ["[::1]:0", "127.0.0.1:0"] {
+        let server = UdpSocket::bind(local).unwrap();
+        let addr = server.local_addr().unwrap();
+        socket.send(&[1, 2], &addr.to_string())?;
+        let (size, client) = server.recv_from(&mut buf).unwrap();
+        assert_eq!(buf[..size], [1, 2]);
+        assert_eq!(client.is_ipv6(), addr.is_ipv6());
+    }
+    Ok(())
+}
+
+/// Validate that plain DNS goes over TCP once forced, keeping the connection open while the
+/// server allows.
+#[test]
+fn test_upstream_socket_over_tcp() -> Result<(), DnsError> {
+    use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    // The server echoes every message back on a single connection.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut messages = 0;
+        while let Ok(length) = stream.read_u16::<BigEndian>() {
+            let mut message = vec![0u8; length as usize];
+            stream.read_exact(&mut message).unwrap();
+            stream.write_u16::<BigEndian>(length).unwrap();
+            stream.write_all(&message).unwrap();
+            messages += 1;
+        }
+        messages
+    });
+
+    let mut socket = UpstreamSocket::bind("127.0.0.1:0")?;
+    assert!(!socket.is_connection_oriented());
+    socket.set_tcp(true);
+    assert!(socket.is_connection_oriented());
+
+    let mut buf = [0u8; 16];
+    for message in [[1, 2], [3, 4]] {
+        socket.send(&message, &addr)?;
+        let (size, _) = socket.recv_from(&mut buf)?;
+        assert_eq!(buf[..size], message);
+        socket.set_idle_timeout(&addr, Some(Duration::from_secs(60)));
+    }
+    socket.set_idle_timeout(&addr, None);
+
+    assert_eq!(server.join().unwrap(), 2);
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Checks run by the cargo-fuzz targets in `fuzz/`. The parser target takes raw input, while
+//! the serialization target takes messages built from it with the `arbitrary` feature, which
+//! implements `Arbitrary` for `Packet` and what it is made of. Both are plain functions, so that
+//! they also run as a test without a fuzzer.
+
+use crate::packet::Packet;
+
+/// Fuzz target for the parser: parse the input as a message and, if it parses, put the message
+/// through everything done with messages received from servers. Must not panic or hang, whatever
+/// the input.
+///
+/// # Argument
+/// * `data`: The fuzzer input.
+pub fn parse(data: &[u8]) {
+    let Ok(packet) = Packet::parse(data) else {
+        return;
+    };
+    _ = format!("{}", packet);
+    _ = packet.serialize();
+    _ = packet.edns();
+    _ = packet.diff(&packet);
+    for record in packet
+        .answers
+        .iter()
+        .chain(&packet.authorities)
+        .chain(&packet.additionals)
+    {
+        _ = record.to_presentation();
+        _ = record.serialize_canonical(&mut vec![]);
+    }
+}
+
+/// Fuzz target for serialization: serialize the given message with and without name
+/// compression, and parse it back. Panics if a message parsed differs from the one serialized.
+///
+/// # Argument
+/// * `packet`: The message. Its section counts are ignored, as serializing counts the sections.
+pub fn round_trip(mut packet: Packet) {
+    let bytes = packet.serialize().unwrap();
+    let parsed = Packet::parse(&bytes).unwrap();
+    packet.header.num_questions = packet.questions.len() as u16;
+    packet.header.num_answers = packet.answers.len() as u16;
+    packet.header.num_authorities = packet.authorities.len() as u16;
+    packet.header.num_additionals = packet.additionals.len() as u16;
+    assert_eq!(parsed, packet);
+    let bytes = packet.serialize_compressed().unwrap();
+    assert_eq!(Packet::parse(&bytes).unwrap(), packet);
+}
+
+/// Validate the fuzz targets against mutations of captured responses and random input, as a
+/// fuzzer would run them.
+#[test]
+fn test_fuzz_targets() {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let responses: Vec<Vec<u8>> = crate::mock_data::captured_data_for_twitter()
+        .into_iter()
+        .map(|(_, response)| response.data)
+        .collect();
+    for index in 0..2000 {
+        let mut data = responses[index % responses.len()].clone();
+        data.truncate(rng.gen_range(0..=data.len()));
+        for _ in 0..rng.gen_range(1..8) {
+            if !data.is_empty() {
+                let position = rng.gen_range(0..data.len());
+                data[position] = rng.gen();
+            }
+        }
+        parse(&data);
+    }
+
+    for _ in 0..500 {
+        let data: Vec<u8> = (0..rng.gen_range(0..2048)).map(|_| rng.gen()).collect();
+        parse(&data);
+    }
+
+    for response in &responses {
+        round_trip(Packet::parse(response).unwrap());
+    }
+    round_trip(Packet {
+        header: Default::default(),
+        questions: vec![],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    });
+}
+
+/// Validate the serialization target against messages built from random input with the
+/// `arbitrary` feature, as the round_trip fuzz target builds them.
+#[cfg(feature = "arbitrary")]
+#[test]
+fn test_round_trip_arbitrary_packets() {
+    use arbitrary::{Arbitrary, Unstructured};
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut records = 0;
+    for _ in 0..500 {
+        let data: Vec<u8> = (0..rng.gen_range(0..4096)).map(|_| rng.gen()).collect();
+        // Like the fuzzer, skip input too short to build a message from.
+        let Ok(packet) = Packet::arbitrary(&mut Unstructured::new(&data)) else {
+            continue;
+        };
+        records += packet.answers.len();
+        round_trip(packet);
+    }
+    assert!(records > 0);
+}
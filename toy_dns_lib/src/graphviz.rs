@@ -0,0 +1,202 @@
+use crate::errors::DnsError;
+use crate::packet::Packet;
+use crate::record::RecordType;
+use crate::trace::{Exchange, Outcome};
+
+/// Render the path of a resolution as a directed graph in the DOT language of Graphviz, e.g. to
+/// render it with `dot -Tsvg`. Each server queried is a node, from the root servers down to the
+/// authoritative servers, and each query leads from the server which referred to it to the
+/// server it was sent to. Referrals are labelled with the zone they delegate, and queries made
+/// to resolve the name of a name server are dashed and labelled with what they asked. The
+/// answer, or the error which prevented one, is the last node.
+///
+/// # Arguments
+/// * `domain_name`: The name which was resolved.
+/// * `record_type`: The type which was resolved.
+/// * `result`: The response to the query, or the error which prevented one.
+/// * `exchanges`: The exchanges made with servers along the way, in order.
+pub fn delegation_graph(
+    domain_name: &str,
+    record_type: RecordType,
+    result: &Result<Packet, DnsError>,
+    exchanges: &[Exchange],
+) -> String {
+    let mut output =
+        String::from("digraph delegation {\n    rankdir=LR;\n    node [shape=ellipse];\n");
+
+    let mut servers: Vec<&str> = Vec::new();
+    for exchange in exchanges {
+        if servers.contains(&exchange.server_address.as_str()) {
+            continue;
+        }
+        servers.push(&exchange.server_address);
+        let label = match exchange.server_name.is_empty() {
+            true => exchange.server_address.clone(),
+            false => format!("{}\n{}", exchange.server_name, exchange.server_address),
+        };
+        output.push_str(&format!(
+            "    {} [label={}];\n",
+            quote(&exchange.server_address),
+            quote(&label)
+        ));
+    }
+
+    let mut answer = format!("{} {}", domain_name, record_type);
+    match result {
+        Ok(packet) => {
+            for record in &packet.answers {
+                answer.push_str(&format!(
+                    "\n{} {}",
+                    record.r_type,
+                    record.data_to_presentation()
+                ));
+            }
+        }
+        Err(error) => answer.push_str(&format!("\n{:?}", error)),
+    }
+    output.push_str(&format!(
+        "    result [shape=box, label={}];\n",
+        quote(&answer)
+    ));
+
+    for (exchange, next) in exchanges.iter().zip(exchanges.iter().skip(1)) {
+        let attributes = if next.domain_name != exchange.domain_name
+            || next.record_type != exchange.record_type
+        {
+            format!(
+                " [label={}, style=dashed]",
+                quote(&format!("{} {}", next.domain_name, next.record_type))
+            )
+        } else if let Outcome::Referral { zone, .. } = exchange.outcome() {
+            format!(" [label={}]", quote(&zone))
+        } else {
+            String::new()
+        };
+        output.push_str(&format!(
+            "    {} -> {}{};\n",
+            quote(&exchange.server_address),
+            quote(&next.server_address),
+            attributes
+        ));
+    }
+    if let Some(last) = exchanges.last() {
+        output.push_str(&format!("    {} -> result;\n", quote(&last.server_address)));
+    }
+
+    output.push_str("}\n");
+    output
+}
+
+/// Quote a string as a DOT identifier, escaping quotes and line breaks.
+///
+/// # Argument
+/// * `value`: The string to quote.
+fn quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// Validate rendering of a resolution which had to look up the address of a name server.
+#[test]
+fn test_delegation_graph() {
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+    use crate::record::Record;
+    use crate::ttl::Ttl;
+    use std::time::Duration;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+    let exchange =
+        |server_address: &str, server_name: &str, domain_name: &str, response| Exchange {
+            server_address: server_address.to_owned(),
+            server_name: server_name.to_owned(),
+            domain_name: domain_name.to_owned(),
+            record_type: RecordType::A,
+            response,
+            received_bytes: 100,
+            elapsed: Duration::from_millis(5),
+        };
+    let referral = |zone: &str, name_server: &[u8]| Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![],
+        authorities: vec![record(zone, RecordType::NS, name_server)],
+        additionals: vec![],
+    };
+    let answer = |name: &str, address: [u8; 4]| Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![record(name, RecordType::A, &address)],
+        authorities: vec![],
+        additionals: vec![],
+    };
+    let exchanges = [
+        exchange(
+            "198.41.0.4:53",
+            "a.root-servers.net",
+            "example.com",
+            referral("com", b"\x02ns\x03net\x00"),
+        ),
+        exchange(
+            "198.41.0.4:53",
+            "a.root-servers.net",
+            "ns.net",
+            answer("ns.net", [192, 0, 2, 53]),
+        ),
+        exchange(
+            "192.0.2.53:53",
+            "ns.net",
+            "example.com",
+            answer("example.com", [192, 0, 2, 1]),
+        ),
+    ];
+
+    assert_eq!(
+        delegation_graph(
+            "example.com",
+            RecordType::A,
+            &Ok(answer("example.com", [192, 0, 2, 1])),
+            &exchanges
+        ),
+        "digraph delegation {\n\
+         \x20   rankdir=LR;\n\
+         \x20   node [shape=ellipse];\n\
+         \x20   \"198.41.0.4:53\" [label=\"a.root-servers.net\\n198.41.0.4:53\"];\n\
+         \x20   \"192.0.2.53:53\" [label=\"ns.net\\n192.0.2.53:53\"];\n\
+         \x20   result [shape=box, label=\"example.com A\\nA 192.0.2.1\"];\n\
+         \x20   \"198.41.0.4:53\" -> \"198.41.0.4:53\" [label=\"ns.net A\", style=dashed];\n\
+         \x20   \"198.41.0.4:53\" -> \"192.0.2.53:53\" [label=\"example.com A\", style=dashed];\n\
+         \x20   \"192.0.2.53:53\" -> result;\n\
+         }\n"
+    );
+
+    let direct = delegation_graph(
+        "example.com",
+        RecordType::A,
+        &Ok(answer("example.com", [192, 0, 2, 1])),
+        &[exchanges[0].clone(), exchanges[2].clone()],
+    );
+    assert!(direct.contains("    \"198.41.0.4:53\" -> \"192.0.2.53:53\" [label=\"com\"];\n"));
+
+    let failed = delegation_graph(
+        "example.com",
+        RecordType::A,
+        &Err(DnsError::UnknownDomainName),
+        &[],
+    );
+    assert!(
+        failed.contains("    result [shape=box, label=\"example.com A\\nUnknownDomainName\"];\n")
+    );
+    assert!(!failed.contains("->"));
+}
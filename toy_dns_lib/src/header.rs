@@ -1,9 +1,95 @@
 use crate::errors::DnsError;
-use byteorder::{BigEndian, ReadBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
 
+/// The QR bit, set when the message is a response.
+pub const FLAG_RESPONSE: u16 = 0b1000_0000_0000_0000;
+
+/// The AA bit, set when the responding server is an authority for the name in question.
+pub const FLAG_AUTHORITATIVE: u16 = 0b0000_0100_0000_0000;
+
+/// The TC bit, set when the message was truncated to fit the transport.
+pub const FLAG_TRUNCATED: u16 = 0b0000_0010_0000_0000;
+
+/// The RD bit, set when the client wants the server to pursue the query recursively.
+pub const FLAG_RECURSION_DESIRED: u16 = 0b0000_0001_0000_0000;
+
+/// The RA bit, set when the server supports recursive queries.
+pub const FLAG_RECURSION_AVAILABLE: u16 = 0b0000_0000_1000_0000;
+
+/// The AD bit, set when the server has validated all records in the response with DNSSEC.
+/// See RFC 4035.
+pub const FLAG_AUTHENTIC_DATA: u16 = 0b0000_0000_0010_0000;
+
+/// The CD bit, set when the client wants the server to skip DNSSEC validation. See RFC 4035.
+pub const FLAG_CHECKING_DISABLED: u16 = 0b0000_0000_0001_0000;
+
+/// Bits of the flags holding the opcode, the kind of query.
+pub const OPCODE_MASK: u16 = 0b0111_1000_0000_0000;
+
+/// Bits of the flags holding the response code.
+pub const RCODE_MASK: u16 = 0b0000_0000_0000_1111;
+
+/// Response code for a successful query.
+pub const RCODE_NO_ERROR: u16 = 0;
+
+/// Response code for a query the server could not interpret.
+pub const RCODE_FORMAT_ERROR: u16 = 1;
+
+/// Response code for a query the server failed to process.
+pub const RCODE_SERVER_FAILURE: u16 = 2;
+
+/// Response code for a name which does not exist (NXDOMAIN).
+pub const RCODE_NAME_ERROR: u16 = 3;
+
+/// Response code for a kind of query the server does not support.
+pub const RCODE_NOT_IMPLEMENTED: u16 = 4;
+
+/// Response code for a query the server refuses to answer.
+pub const RCODE_REFUSED: u16 = 5;
+
+/// Extended response code for a query with an EDNS version the server does not implement
+/// (RFC 6891). Only fits in a message along with an OPT record.
+pub const RCODE_BAD_VERSION: u16 = 16;
+
+/// Extended response code for a query with a malformed or invalid server cookie (RFC 7873).
+pub const RCODE_BAD_COOKIE: u16 = 23;
+
+/// The flags in the order dig prints them, along with their names.
+const FLAG_NAMES: [(u16, &str); 7] = [
+    (FLAG_RESPONSE, "qr"),
+    (FLAG_AUTHORITATIVE, "aa"),
+    (FLAG_TRUNCATED, "tc"),
+    (FLAG_RECURSION_DESIRED, "rd"),
+    (FLAG_RECURSION_AVAILABLE, "ra"),
+    (FLAG_AUTHENTIC_DATA, "ad"),
+    (FLAG_CHECKING_DISABLED, "cd"),
+];
+
+/// The mnemonic of a response code as registered with IANA, e.g. `NXDOMAIN`, or `RCODE` followed
+/// by the value for codes without one.
+///
+/// # Argument
+/// * `rcode`: The response code.
+pub fn rcode_name(rcode: u16) -> String {
+    let name = match rcode {
+        RCODE_NO_ERROR => "NOERROR",
+        RCODE_FORMAT_ERROR => "FORMERR",
+        RCODE_SERVER_FAILURE => "SERVFAIL",
+        RCODE_NAME_ERROR => "NXDOMAIN",
+        RCODE_NOT_IMPLEMENTED => "NOTIMP",
+        RCODE_REFUSED => "REFUSED",
+        RCODE_BAD_VERSION => "BADVERS",
+        RCODE_BAD_COOKIE => "BADCOOKIE",
+        _ => return format!("RCODE{}", rcode),
+    };
+    name.to_owned()
+}
+
 /// A DNS header. See RFC 1035 for specifications on headers of DNS messages.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Header {
     /// ID of the DNS message.
     pub id: u16,
@@ -24,44 +110,97 @@ pub struct Header {
     pub num_additionals: u16,
 }
 
-impl Default for Header {
-    fn default() -> Self {
-        Header {
-            id: 0,
-            flags: 0,
-            num_questions: 0,
-            num_answers: 0,
-            num_authorities: 0,
-            num_additionals: 0,
+impl Header {
+    /// Whether the given flag (one of the `FLAG_*` constants) is set.
+    ///
+    /// # Argument
+    /// * `flag`: The flag to check.
+    pub fn has_flag(&self, flag: u16) -> bool {
+        self.flags & flag == flag
+    }
+
+    /// The lower 4 bits of the response code. See `Packet::rcode()` for the full code.
+    pub fn rcode(&self) -> u16 {
+        self.flags & RCODE_MASK
+    }
+
+    /// The names of the flags which are set, e.g. `["qr", "rd", "ra"]`.
+    pub fn flag_names(&self) -> Vec<&'static str> {
+        FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has_flag(*flag))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    /// Serialize the header in wire format onto the given buffer.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized header to.
+    pub fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        for value in [
+            self.id,
+            self.flags,
+            self.num_questions,
+            self.num_answers,
+            self.num_authorities,
+            self.num_additionals,
+        ] {
+            let Ok(_) = bytes.write_u16::<BigEndian>(value) else {
+                return Err(DnsError::QuerySerialization);
+            };
         }
+        Ok(())
     }
-}
 
-impl Header {
     /// Read a DNS message header at the given cursor. Cursor will advance (even if the function
     /// fails) up to the last successful byte read.
     ///
     /// # Arguments
     /// * `cursor`: A cursor pointing at the byte buffer to attempt parsing from
     pub fn read_and_advance(cursor: &mut Cursor<&[u8]>) -> Result<Header, DnsError> {
-        let Ok(id) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseId) };
-        let Ok(flags) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseFlag) };
-        let Ok(num_questions) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseQuestionCount) };
-        let Ok(num_answers) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseAnswerCount) };
-        let Ok(num_authorities) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseAuthorityCount) };
-        let Ok(num_additionals) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ParseAdditionalCount) };
-
-        return Ok(Header {
-            id: id,
-            flags: flags,
-            num_questions: num_questions,
-            num_answers: num_answers,
-            num_authorities: num_authorities,
-            num_additionals: num_additionals,
-        });
+        let id = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseId(Some(error)))?;
+        let flags = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseFlag(Some(error)))?;
+        let num_questions = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseQuestionCount(Some(error)))?;
+        let num_answers = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseAnswerCount(Some(error)))?;
+        let num_authorities = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseAuthorityCount(Some(error)))?;
+        let num_additionals = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ParseAdditionalCount(Some(error)))?;
+
+        Ok(Header {
+            id,
+            flags,
+            num_questions,
+            num_answers,
+            num_authorities,
+            num_additionals,
+        })
     }
 }
 
+/// Validate the names of flags and response codes.
+#[test]
+fn test_flag_and_rcode_names() {
+    let header = Header {
+        flags: FLAG_RESPONSE | FLAG_RECURSION_DESIRED | FLAG_RECURSION_AVAILABLE | RCODE_NAME_ERROR,
+        ..Default::default()
+    };
+    assert_eq!(header.flag_names(), ["qr", "rd", "ra"]);
+    assert_eq!(rcode_name(header.rcode()), "NXDOMAIN");
+    assert_eq!(rcode_name(9), "RCODE9");
+}
+
 /// Validate parsing of a zeroed buffer. This is technically a valid header although it doesn't
 /// make much sense to us.
 #[test]
@@ -88,6 +227,44 @@ fn test_parsing_incomplete_header() {
     assert!(Header::read_and_advance(&mut cursor).is_err())
 }
 
+/// Validate checking of individual flags.
+#[test]
+fn test_header_flags() {
+    let header = Header {
+        flags: 0x81A0,
+        ..Default::default()
+    };
+
+    assert!(header.has_flag(FLAG_RESPONSE));
+    assert!(header.has_flag(FLAG_RECURSION_DESIRED));
+    assert!(header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert!(header.has_flag(FLAG_AUTHENTIC_DATA));
+    assert!(!header.has_flag(FLAG_AUTHORITATIVE));
+    assert!(!header.has_flag(FLAG_CHECKING_DISABLED));
+}
+
+/// Validate that a serialized header parses back into the same header.
+#[test]
+fn test_header_serialization_round_trip() -> Result<(), DnsError> {
+    let header = Header {
+        id: 0xcc47,
+        flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE | RCODE_NAME_ERROR,
+        num_questions: 1,
+        num_answers: 2,
+        num_authorities: 3,
+        num_additionals: 4,
+    };
+
+    let mut bytes = Vec::new();
+    header.serialize(&mut bytes)?;
+    assert_eq!(bytes, [204, 71, 132, 3, 0, 1, 0, 2, 0, 3, 0, 4]);
+
+    let parsed = Header::read_and_advance(&mut Cursor::new(bytes.as_slice()))?;
+    assert_eq!(parsed.rcode(), RCODE_NAME_ERROR);
+    assert_eq!(parsed, header);
+    Ok(())
+}
+
 /// Validate parsing of an empty buffer results in failure.
 #[test]
 fn test_parsing_empty_buffer_header() {
@@ -0,0 +1,187 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::header::{Header, FLAG_AUTHORITATIVE, FLAG_RESPONSE};
+use crate::packet::Packet;
+use crate::query::CLASS_IN;
+use crate::question::Question;
+use crate::record::{Record, RecordType};
+use crate::ttl::Ttl;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// Location of the hosts file on Unix-like systems.
+pub const DEFAULT_HOSTS_PATH: &str = "/etc/hosts";
+
+/// Static name-to-address mappings read from a hosts file.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct HostsFile {
+    /// Addresses of each name, keyed by the lowercased name without a trailing dot.
+    entries: HashMap<String, Vec<IpAddr>>,
+}
+
+impl HostsFile {
+    /// Read the hosts file at the given path.
+    ///
+    /// # Argument
+    /// * `path`: Path to the hosts file.
+    pub fn from_file(path: &str) -> Result<HostsFile, DnsError> {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Err(DnsError::HostsFile);
+        };
+        Ok(Self::parse(&contents))
+    }
+
+    /// Parse the contents of a hosts file. Each line holds an address followed by the canonical
+    /// name and any aliases. Everything after a `#` is a comment. Malformed lines are ignored.
+    ///
+    /// # Argument
+    /// * `contents`: The contents of a hosts file.
+    pub fn parse(contents: &str) -> HostsFile {
+        let mut entries: HashMap<String, Vec<IpAddr>> = HashMap::new();
+
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let mut tokens = line.split_whitespace();
+            let Some(Ok(address)) = tokens.next().map(|token| token.parse::<IpAddr>()) else {
+                continue;
+            };
+
+            for name in tokens {
+                let addresses = entries.entry(normalize_name(name)).or_default();
+                if !addresses.contains(&address) {
+                    addresses.push(address);
+                }
+            }
+        }
+
+        HostsFile { entries }
+    }
+
+    /// Look up the addresses of the given name which match the record type. Only A and AAAA
+    /// lookups can be answered from a hosts file.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name to look up.
+    /// * `record_type`: The record type being resolved.
+    pub fn lookup(&self, domain_name: &str, record_type: RecordType) -> Vec<IpAddr> {
+        let Some(addresses) = self.entries.get(&normalize_name(domain_name)) else {
+            return vec![];
+        };
+
+        addresses
+            .iter()
+            .filter(|address| match record_type {
+                RecordType::A => address.is_ipv4(),
+                RecordType::AAAA => address.is_ipv6(),
+                _ => false,
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Build a synthetic response packet for the given name from the hosts file, if the hosts
+    /// file has any matching addresses.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name to look up.
+    /// * `record_type`: The record type being resolved.
+    pub fn answer(&self, domain_name: &str, record_type: RecordType) -> Option<Packet> {
+        let addresses = self.lookup(domain_name, record_type);
+        if addresses.is_empty() {
+            return None;
+        }
+
+        let name = DnsName::from(normalize_name(domain_name));
+        let answers: Vec<Record> = addresses
+            .iter()
+            .map(|address| Record {
+                name: name.clone(),
+                r_type: record_type,
+                r_class: CLASS_IN,
+                // Entries of a hosts file are never cached, so there is no sensible TTL.
+                ttl: Ttl::from(0),
+                data: match address {
+                    IpAddr::V4(address) => address.octets().to_vec(),
+                    IpAddr::V6(address) => address.octets().to_vec(),
+                },
+            })
+            .collect();
+
+        Some(Packet {
+            header: Header {
+                flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+                num_questions: 1,
+                num_answers: answers.len() as u16,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name,
+                q_type: record_type,
+                q_class: CLASS_IN,
+            }],
+            answers,
+            authorities: vec![],
+            additionals: vec![],
+        })
+    }
+}
+
+/// Names in a hosts file are matched case-insensitively and without a trailing dot.
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+#[cfg(test)]
+const EXAMPLE_HOSTS_FILE: &str = "
+127.0.0.1   localhost
+::1         localhost ip6-localhost
+192.0.2.10  printer.lan printer   # The office printer
+192.0.2.11  Printer.LAN
+not-an-ip   broken.lan
+";
+
+/// Validate lookups of names and aliases in a hosts file.
+#[test]
+fn test_hosts_file_lookup() {
+    let hosts = HostsFile::parse(EXAMPLE_HOSTS_FILE);
+
+    assert_eq!(
+        hosts.lookup("printer", RecordType::A),
+        vec!["192.0.2.10".parse::<IpAddr>().unwrap()]
+    );
+    assert_eq!(
+        hosts.lookup("PRINTER.lan.", RecordType::A),
+        vec![
+            "192.0.2.10".parse::<IpAddr>().unwrap(),
+            "192.0.2.11".parse::<IpAddr>().unwrap()
+        ]
+    );
+    assert_eq!(
+        hosts.lookup("localhost", RecordType::AAAA),
+        vec!["::1".parse::<IpAddr>().unwrap()]
+    );
+    assert!(hosts.lookup("printer", RecordType::AAAA).is_empty());
+    assert!(hosts.lookup("broken.lan", RecordType::A).is_empty());
+    assert!(hosts.lookup("localhost", RecordType::NS).is_empty());
+}
+
+/// Validate the synthetic packet answering from a hosts file.
+#[test]
+fn test_hosts_file_answer() {
+    let hosts = HostsFile::parse(EXAMPLE_HOSTS_FILE);
+    let packet = hosts.answer("ip6-localhost", RecordType::AAAA).unwrap();
+
+    assert_eq!(packet.header.num_answers, 1);
+    assert_eq!(
+        packet.answers,
+        vec![Record {
+            name: DnsName::from("ip6-localhost"),
+            r_type: RecordType::AAAA,
+            r_class: 1,
+            ttl: Ttl::from(0),
+            data: vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        }]
+    );
+
+    assert!(hosts.answer("unknown.lan", RecordType::A).is_none());
+}
@@ -0,0 +1,331 @@
+use crate::errors::DnsError;
+use crate::socket::DnsTransport;
+use crate::tls::{client_config, connect, TlsStream, TLS_TIMEOUT};
+use log::info;
+use rustls::ClientConfig;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Port HTTPS servers listen on unless the URL says otherwise.
+const DEFAULT_HTTPS_PORT: u16 = 443;
+
+/// Media type of DNS messages exchanged over HTTPS, as specified in RFC 8484, section 6.
+const DNS_MESSAGE_MEDIA_TYPE: &str = "application/dns-message";
+
+/// Largest response body accepted from a server. DNS messages cannot be any larger.
+const MAX_BODY_SIZE: usize = u16::MAX as usize;
+
+/// The parts of a DoH URL needed to send a request.
+#[derive(Debug, PartialEq)]
+pub(crate) struct DohUrl {
+    /// Host and port to connect to.
+    authority: String,
+
+    /// Value of the Host header.
+    host: String,
+
+    /// Path of the resource, e.g. `/dns-query`.
+    path: String,
+}
+
+impl DohUrl {
+    /// Split an `https://` URL into the parts needed to send a request.
+    ///
+    /// # Argument
+    /// * `url`: The URL of the DoH resource, e.g. `https://dns.google/dns-query`.
+    pub(crate) fn parse(url: &str) -> Result<DohUrl, DnsError> {
+        let Some(rest) = url.strip_prefix("https://") else {
+            return Err(DnsError::InvalidUpstream);
+        };
+        let (host, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/dns-query"),
+        };
+        if host.is_empty() {
+            return Err(DnsError::InvalidUpstream);
+        }
+
+        // A port follows the last colon, unless that colon is part of a bracketed IPv6 address.
+        let has_port = host
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| !port.contains(']'));
+        let authority = if has_port {
+            host.to_owned()
+        } else {
+            format!("{}:{}", host, DEFAULT_HTTPS_PORT)
+        };
+
+        Ok(DohUrl {
+            authority,
+            host: host.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// Read an HTTP/1.1 response and return its body. Only successful responses carrying a DNS
+/// message are accepted. Bodies may be delimited by Content-Length or use chunked encoding.
+///
+/// # Argument
+/// * `reader`: The stream to read the response from.
+fn read_http_response<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, DnsError> {
+    let mut status_line = String::new();
+    reader
+        .read_line(&mut status_line)
+        .map_err(|error| DnsError::SocketRead(Some(error)))?;
+    let status = status_line.split_whitespace().nth(1);
+    if status != Some("200") {
+        info!("Server responded with {}", status_line.trim_end());
+        return Err(DnsError::SocketRead(None));
+    }
+
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut is_dns_message = false;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        let line = line.trim_end();
+        if read == 0 || line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse::<usize>().ok();
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        } else if name.eq_ignore_ascii_case("content-type") {
+            is_dns_message = value.starts_with(DNS_MESSAGE_MEDIA_TYPE);
+        }
+    }
+    if !is_dns_message {
+        return Err(DnsError::SocketRead(None));
+    }
+
+    let mut body = Vec::new();
+    if chunked {
+        loop {
+            let mut size_line = String::new();
+            reader
+                .read_line(&mut size_line)
+                .map_err(|error| DnsError::SocketRead(Some(error)))?;
+            let size = size_line.trim_end().split(';').next().unwrap_or("");
+            let Ok(size) = usize::from_str_radix(size, 16) else {
+                return Err(DnsError::SocketRead(None));
+            };
+            if body.len() + size > MAX_BODY_SIZE {
+                return Err(DnsError::SocketRead(None));
+            }
+
+            // Each chunk, including the last empty one, is followed by a line break.
+            let mut chunk = vec![0u8; size + 2];
+            reader
+                .read_exact(&mut chunk)
+                .map_err(|error| DnsError::SocketRead(Some(error)))?;
+            if size == 0 {
+                break;
+            }
+            body.extend(&chunk[..size]);
+        }
+    } else {
+        let Some(length) = content_length.filter(|length| *length <= MAX_BODY_SIZE) else {
+            return Err(DnsError::SocketRead(None));
+        };
+        body.resize(length, 0);
+        reader
+            .read_exact(&mut body)
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+    }
+    Ok(body)
+}
+
+/// A transport that sends DNS messages over HTTPS (DoH) as specified in RFC 8484. Messages are
+/// POSTed over HTTP/1.1 to the URL given as the address, e.g. `https://dns.google/dns-query`.
+/// Connections are kept alive and reused for subsequent messages to the same server.
+pub struct HttpsTransport {
+    /// The TLS configuration of new connections.
+    config: Arc<ClientConfig>,
+
+    /// Open connections keyed by authority.
+    sessions: HashMap<String, BufReader<TlsStream>>,
+
+    /// The response to the last message sent, waiting to be read.
+    response: RefCell<Option<(Vec<u8>, SocketAddr)>>,
+
+    /// How long new connections wait for the server.
+    timeout: Duration,
+}
+
+impl HttpsTransport {
+    /// Create the transport. Connections are made as messages are sent, so the address is
+    /// unused.
+    ///
+    /// # Argument
+    /// * `_addr`: The (local) address to bind to.
+    pub fn bind(_addr: &str) -> Result<HttpsTransport, DnsError> {
+        Ok(HttpsTransport {
+            config: client_config(&[b"http/1.1"])?,
+            sessions: HashMap::new(),
+            response: RefCell::new(None),
+            timeout: TLS_TIMEOUT,
+        })
+    }
+
+    /// Set how long new connections wait for the server to read or write anything.
+    ///
+    /// # Argument
+    /// * `timeout`: The timeout. It must not be zero.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// POST a message over the connection and read the response to it.
+    ///
+    /// # Arguments
+    /// * `session`: The connection to exchange the message over.
+    /// * `url`: The URL to POST the message to.
+    /// * `buf`: The message to send.
+    fn exchange(
+        session: &mut BufReader<TlsStream>,
+        url: &DohUrl,
+        buf: &[u8],
+    ) -> Result<Vec<u8>, DnsError> {
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nAccept: {}\r\nContent-Length: {}\r\n\r\n",
+            url.path,
+            url.host,
+            DNS_MESSAGE_MEDIA_TYPE,
+            DNS_MESSAGE_MEDIA_TYPE,
+            buf.len()
+        )
+        .into_bytes();
+        request.extend(buf);
+
+        let stream = session.get_mut();
+        let (Ok(_), Ok(_)) = (stream.write_all(&request), stream.flush()) else {
+            return Err(DnsError::SocketSend(None));
+        };
+        read_http_response(session)
+    }
+}
+
+impl DnsTransport for HttpsTransport {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        let url = DohUrl::parse(addr)?;
+
+        // The server may have closed an idle connection in the meantime, in which case a fresh
+        // connection is attempted.
+        if let Some(mut session) = self.sessions.remove(&url.authority) {
+            if let Ok(response) = Self::exchange(&mut session, &url, buf) {
+                info!("Reusing open HTTPS connection to {}", url.authority);
+                let peer = session
+                    .get_ref()
+                    .sock
+                    .peer_addr()
+                    .map_err(|error| DnsError::SocketRead(Some(error)))?;
+                self.response.replace(Some((response, peer)));
+                self.sessions.insert(url.authority, session);
+                return Ok(buf.len());
+            }
+        }
+
+        let mut session = BufReader::new(connect(&self.config, &url.authority, self.timeout)?);
+        let response = Self::exchange(&mut session, &url, buf)?;
+        let peer = session
+            .get_ref()
+            .sock
+            .peer_addr()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        self.response.replace(Some((response, peer)));
+        self.sessions.insert(url.authority, session);
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        let Some((message, peer)) = self.response.take() else {
+            return Err(DnsError::SocketRead(None));
+        };
+
+        // Messages larger than the buffer are truncated, just as a datagram would be.
+        let size = message.len().min(buf.len());
+        buf[..size].copy_from_slice(&message[..size]);
+        Ok((size, peer))
+    }
+
+    fn is_connection_oriented(&self) -> bool {
+        true
+    }
+}
+
+/// Validate splitting of DoH URLs, with and without a port and path.
+#[test]
+fn test_parsing_doh_url() -> Result<(), DnsError> {
+    assert_eq!(
+        DohUrl::parse("https://dns.google/dns-query")?,
+        DohUrl {
+            authority: "dns.google:443".to_owned(),
+            host: "dns.google".to_owned(),
+            path: "/dns-query".to_owned(),
+        }
+    );
+    assert_eq!(
+        DohUrl::parse("https://[2606:4700::1111]:8443")?,
+        DohUrl {
+            authority: "[2606:4700::1111]:8443".to_owned(),
+            host: "[2606:4700::1111]:8443".to_owned(),
+            path: "/dns-query".to_owned(),
+        }
+    );
+    assert_eq!(
+        DohUrl::parse("https://[2606:4700::1111]/q")?.authority,
+        "[2606:4700::1111]:443"
+    );
+    assert_eq!(
+        DohUrl::parse("http://dns.google/dns-query"),
+        Err(DnsError::InvalidUpstream)
+    );
+    Ok(())
+}
+
+/// Validate reading of responses delimited by Content-Length and by chunked encoding.
+#[test]
+fn test_reading_http_response() -> Result<(), DnsError> {
+    use std::io::Cursor;
+
+    let mut response = Cursor::new(
+        b"HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: 3\r\n\r\n\x01\x02\x03"
+            .to_vec(),
+    );
+    assert_eq!(read_http_response(&mut response)?, [1, 2, 3]);
+
+    let mut response = Cursor::new(
+        b"HTTP/1.1 200 OK\r\ncontent-type: application/dns-message\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n\x01\x02\r\n1;ext\r\n\x03\r\n0\r\n\r\n"
+            .to_vec(),
+    );
+    assert_eq!(read_http_response(&mut response)?, [1, 2, 3]);
+
+    // Anything but a DNS message in a successful response is rejected.
+    let mut response =
+        Cursor::new(b"HTTP/1.1 415 Unsupported Media Type\r\nContent-Length: 0\r\n\r\n".to_vec());
+    assert_eq!(
+        read_http_response(&mut response),
+        Err(DnsError::SocketRead(None))
+    );
+    let mut response = Cursor::new(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: 1\r\n\r\n<".to_vec(),
+    );
+    assert_eq!(
+        read_http_response(&mut response),
+        Err(DnsError::SocketRead(None))
+    );
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::errors::DnsError;
+use ::idna::punycode;
+use ::idna::AsciiDenyList;
+
+/// Prefix of the labels holding an internationalized label encoded with Punycode, known as
+/// A-labels, as specified in RFC 5890, section 2.3.2.1.
+pub const ACE_PREFIX: &str = "xn--";
+
+/// Convert a name which may hold Unicode labels, e.g. `bücher.example`, to the ASCII form sent on
+/// the wire, e.g. `xn--bcher-kva.example`, with the UTS #46 processing of the `idna` crate: the
+/// name is mapped, e.g. lowercased, and normalized to NFC, then each label is validated and the
+/// Unicode ones are encoded with Punycode. ASCII names are left as they are, keeping their case.
+/// The code points the URL standard forbids in domains, such as spaces, are rejected, whereas
+/// underscores are kept for service labels like `_443._tcp`.
+///
+/// # Argument
+/// * `domain_name`: The name to convert.
+pub fn to_ascii(domain_name: &str) -> Result<String, DnsError> {
+    if domain_name.is_ascii() {
+        return Ok(domain_name.to_owned());
+    }
+    match ::idna::domain_to_ascii_cow(domain_name.as_bytes(), AsciiDenyList::URL) {
+        Ok(ascii) => Ok(ascii.into_owned()),
+        Err(_) => Err(DnsError::InvalidByteInName),
+    }
+}
+
+/// Convert a name holding A-labels, e.g. `xn--bcher-kva.example`, to its Unicode form for
+/// display, e.g. `bücher.example`. Labels which are not A-labels, or which do not decode to a
+/// Unicode label, are left as they are.
+///
+/// # Argument
+/// * `domain_name`: The name to convert.
+pub fn to_unicode(domain_name: &str) -> String {
+    domain_name
+        .split('.')
+        .map(label_to_unicode)
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+/// Convert the A-labels of the names in rendered text, e.g. records in presentation format, to
+/// their Unicode form for display. Quoted strings, e.g. the data of TXT records, are left as they
+/// are.
+///
+/// # Argument
+/// * `text`: The text to convert.
+pub fn labels_to_unicode(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut label = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in text.chars() {
+        if !quoted && (c.is_ascii_alphanumeric() || c == '-') {
+            label.push(c);
+            continue;
+        }
+        output.push_str(&label_to_unicode(&label));
+        label.clear();
+        output.push(c);
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        }
+    }
+    output.push_str(&label_to_unicode(&label));
+    output
+}
+
+/// Convert a label to its Unicode form if it is an A-label, or leave it as it is.
+///
+/// # Argument
+/// * `label`: The label to convert.
+fn label_to_unicode(label: &str) -> String {
+    let is_a_label = label
+        .get(..ACE_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(ACE_PREFIX));
+    if !is_a_label {
+        return label.to_owned();
+    }
+    match punycode::decode_to_string(&label[ACE_PREFIX.len()..]) {
+        Some(decoded) if !decoded.is_ascii() => decoded,
+        _ => label.to_owned(),
+    }
+}
+
+/// Validate conversion of names to their ASCII form.
+#[test]
+fn test_to_ascii() -> Result<(), DnsError> {
+    assert_eq!(to_ascii("bücher.example")?, "xn--bcher-kva.example");
+    assert_eq!(to_ascii("BÜCHER.Example.")?, "xn--bcher-kva.example.");
+    assert_eq!(to_ascii("münchen。de")?, "xn--mnchen-3ya.de");
+    assert_eq!(to_ascii("Example.COM")?, "Example.COM");
+    assert_eq!(to_ascii("cafe\u{301}.example")?, "xn--caf-dma.example");
+    assert_eq!(to_ascii("ﬀ.bücher.example")?, "ff.xn--bcher-kva.example");
+    assert_eq!(
+        to_ascii("_443._tcp.bücher.example")?,
+        "_443._tcp.xn--bcher-kva.example"
+    );
+    assert_eq!(
+        to_ascii("bü cher.example"),
+        Err(DnsError::InvalidByteInName)
+    );
+    assert_eq!(
+        to_ascii("a\u{200d}ü.example"),
+        Err(DnsError::InvalidByteInName)
+    );
+    assert_eq!(to_ascii("xn--ü.example"), Err(DnsError::InvalidByteInName));
+    Ok(())
+}
+
+/// Validate conversion of names and rendered text to their Unicode form.
+#[test]
+fn test_to_unicode() {
+    assert_eq!(to_unicode("xn--bcher-kva.example."), "bücher.example.");
+    assert_eq!(to_unicode("XN--mnchen-3YA.de"), "münchen.de");
+    assert_eq!(to_unicode("xn--invalid!.example"), "xn--invalid!.example");
+    assert_eq!(to_unicode("xn--example-.com"), "xn--example-.com");
+
+    assert_eq!(
+        labels_to_unicode("xn--bcher-kva.example. 300 IN CNAME www.xn--mnchen-3ya.de.\n"),
+        "bücher.example. 300 IN CNAME www.münchen.de.\n"
+    );
+    assert_eq!(
+        labels_to_unicode(
+            "xn--bcher-kva.example. 300 IN TXT \"xn--bcher-kva \\\" xn--bcher-kva\"\n"
+        ),
+        "bücher.example. 300 IN TXT \"xn--bcher-kva \\\" xn--bcher-kva\"\n"
+    );
+}
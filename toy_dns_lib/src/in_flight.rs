@@ -0,0 +1,227 @@
+use crate::cache::CacheKey;
+use crate::errors::DnsError;
+use crate::packet::Packet;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+/// How a query ended, as handed to the identical queries which waited on it.
+#[derive(Clone)]
+enum Outcome {
+    /// A response came back.
+    Answered(Packet),
+
+    /// The name does not exist.
+    UnknownDomainName,
+
+    /// The query failed otherwise. Errors cannot be handed around, so each waiting query is sent
+    /// on its own.
+    Failed,
+}
+
+/// A query on its way, which identical queries wait on.
+#[derive(Default)]
+struct Pending {
+    /// How the query ended, once it did.
+    outcome: Mutex<Option<Outcome>>,
+
+    /// Notified when the query ends.
+    ended: Condvar,
+}
+
+/// Coalesces identical queries made concurrently, e.g. by the workers of a server on a cache
+/// miss, onto the first of them: the others wait for its response rather than sending queries of
+/// their own, so that a popular name expiring from the cache does not set off a burst of
+/// identical queries upstream.
+#[derive(Default)]
+pub struct InFlight {
+    /// The queries on their way, by question.
+    pending: Mutex<HashMap<CacheKey, Arc<Pending>>>,
+
+    /// How many queries waited on another rather than being sent.
+    coalesced: AtomicUsize,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Outcomes are only ever set
+/// whole, so they remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Ends the query it was made for when dropped, even if sending it panicked, so that the queries
+/// waiting on it are never left hanging.
+struct Leader<'a> {
+    /// Where the query is registered.
+    in_flight: &'a InFlight,
+
+    /// The question of the query.
+    key: &'a CacheKey,
+
+    /// The query the others wait on.
+    pending: Arc<Pending>,
+}
+
+impl Drop for Leader<'_> {
+    fn drop(&mut self) {
+        lock(&self.in_flight.pending).remove(self.key);
+        lock(&self.pending.outcome).get_or_insert(Outcome::Failed);
+        self.pending.ended.notify_all();
+    }
+}
+
+impl InFlight {
+    /// Send a query with the given function, unless an identical query is already on its way, in
+    /// which case its response is waited for and returned instead.
+    ///
+    /// # Arguments
+    /// * `key`: The question of the query.
+    /// * `send`: Sends the query and returns the response.
+    pub fn run(
+        &self,
+        key: &CacheKey,
+        send: impl FnOnce() -> Result<Packet, DnsError>,
+    ) -> Result<Packet, DnsError> {
+        let mut registry = lock(&self.pending);
+        if let Some(pending) = registry.get(key).cloned() {
+            drop(registry);
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+            let mut outcome = lock(&pending.outcome);
+            while outcome.is_none() {
+                outcome = match pending.ended.wait(outcome) {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+            }
+            return match outcome.clone() {
+                Some(Outcome::Answered(packet)) => Ok(packet),
+                Some(Outcome::UnknownDomainName) => Err(DnsError::UnknownDomainName),
+                _ => {
+                    drop(outcome);
+                    send()
+                }
+            };
+        }
+
+        let leader = Leader {
+            in_flight: self,
+            key,
+            pending: Arc::default(),
+        };
+        registry.insert(key.clone(), Arc::clone(&leader.pending));
+        drop(registry);
+
+        let result = send();
+        *lock(&leader.pending.outcome) = Some(match &result {
+            Ok(packet) => Outcome::Answered(packet.clone()),
+            Err(DnsError::UnknownDomainName) => Outcome::UnknownDomainName,
+            Err(_) => Outcome::Failed,
+        });
+        result
+    }
+
+    /// How many queries waited on an identical one rather than being sent.
+    pub fn coalesced(&self) -> usize {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+}
+
+/// An empty response with the given ID.
+#[cfg(test)]
+fn response(id: u16) -> Packet {
+    use crate::header::Header;
+
+    Packet {
+        header: Header {
+            id,
+            ..Default::default()
+        },
+        questions: vec![],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    }
+}
+
+/// Validate that identical concurrent queries are sent once and all get its response, while
+/// other queries are sent on their own.
+#[test]
+fn test_coalescing_identical_queries() {
+    use crate::record::RecordType;
+    use std::sync::mpsc;
+    use std::thread;
+
+    let in_flight = Arc::new(InFlight::default());
+    let sent = Arc::new(AtomicUsize::new(0));
+    let key = CacheKey::new("example.com", RecordType::A);
+    let (release, released) = mpsc::channel::<()>();
+
+    let send = |id: u16| {
+        let sent = Arc::clone(&sent);
+        move || {
+            sent.fetch_add(1, Ordering::Relaxed);
+            Ok(response(id))
+        }
+    };
+
+    // The first query holds on until every other one is waiting on it.
+    let leader = {
+        let in_flight = Arc::clone(&in_flight);
+        let key = key.clone();
+        let send = send(1);
+        thread::spawn(move || {
+            in_flight.run(&key, || {
+                released.recv().unwrap();
+                send()
+            })
+        })
+    };
+    while lock(&in_flight.pending).is_empty() {
+        thread::yield_now();
+    }
+    let followers: Vec<_> = (0..4)
+        .map(|_| {
+            let in_flight = Arc::clone(&in_flight);
+            let key = key.clone();
+            let send = send(2);
+            thread::spawn(move || in_flight.run(&key, send))
+        })
+        .collect();
+    while in_flight.coalesced() < 4 {
+        thread::yield_now();
+    }
+
+    let other = CacheKey::new("example.org", RecordType::A);
+    assert_eq!(in_flight.run(&other, send(3)).unwrap().header.id, 3);
+
+    release.send(()).unwrap();
+    assert_eq!(leader.join().unwrap().unwrap().header.id, 1);
+    for follower in followers {
+        assert_eq!(follower.join().unwrap().unwrap().header.id, 1);
+    }
+    assert_eq!(sent.load(Ordering::Relaxed), 2);
+    assert!(lock(&in_flight.pending).is_empty());
+}
+
+/// Validate that queries waiting on one which failed are sent on their own, except when the name
+/// does not exist.
+#[test]
+fn test_coalesced_queries_after_failure() {
+    use crate::record::RecordType;
+
+    let in_flight = InFlight::default();
+    let key = CacheKey::new("example.com", RecordType::A);
+    let pending = Arc::new(Pending::default());
+    lock(&in_flight.pending).insert(key.clone(), Arc::clone(&pending));
+
+    *lock(&pending.outcome) = Some(Outcome::Failed);
+    let result = in_flight.run(&key, || Err(DnsError::SocketRead(None)));
+    assert_eq!(result, Err(DnsError::SocketRead(None)));
+
+    *lock(&pending.outcome) = Some(Outcome::UnknownDomainName);
+    let result = in_flight.run(&key, || Ok(response(1)));
+    assert_eq!(result, Err(DnsError::UnknownDomainName));
+    assert_eq!(in_flight.coalesced(), 2);
+}
@@ -0,0 +1,585 @@
+use crate::errors::DnsError;
+use crate::header::rcode_name;
+use crate::packet::Packet;
+use crate::pcap::CapturedMessage;
+use crate::query_log::QueryLogEntry;
+use crate::question::Question;
+use crate::record::{Record, RecordType};
+use crate::trace::{Exchange, Outcome};
+use crate::zone::class_name;
+use std::time::Duration;
+
+/// Names of the fields of record data in presentation format, for the types whose fields never
+/// contain whitespace.
+fn field_names(record_type: RecordType) -> &'static [&'static str] {
+    match record_type {
+        RecordType::A | RecordType::AAAA => &["address"],
+        RecordType::NS | RecordType::CNAME | RecordType::PTR => &["target"],
+        RecordType::MX => &["preference", "exchange"],
+        RecordType::SRV => &["priority", "weight", "port", "target"],
+        RecordType::SOA => &[
+            "mname", "rname", "serial", "refresh", "retry", "expire", "minimum",
+        ],
+        RecordType::DS => &["key_tag", "algorithm", "digest_type", "digest"],
+        RecordType::DNSKEY => &["flags", "protocol", "algorithm", "public_key"],
+        RecordType::TLSA => &["usage", "selector", "matching_type", "data"],
+        _ => &[],
+    }
+}
+
+/// Render the outcome of resolving a name as a JSON object holding the response (or the error),
+/// the exchanges with the servers which led to it and the time it took.
+///
+/// # Arguments
+/// * `domain_name`: The name which was resolved.
+/// * `record_type`: The type which was resolved.
+/// * `result`: The response to the query, or the error which prevented one.
+/// * `exchanges`: The exchanges made with servers along the way, in order.
+/// * `elapsed`: How long the resolution took.
+pub fn resolution(
+    domain_name: &str,
+    record_type: RecordType,
+    result: &Result<Packet, DnsError>,
+    exchanges: &[Exchange],
+    elapsed: Duration,
+) -> String {
+    let (response, error) = match result {
+        Ok(packet) => (packet_to_json(packet), "null".to_owned()),
+        Err(error) => ("null".to_owned(), string(&error.to_string())),
+    };
+    let servers: Vec<String> = exchanges
+        .iter()
+        .map(|exchange| {
+            format!(
+                "{{\"address\":{},\"name\":{},\"question\":{},\"rcode\":{},\"elapsed_ms\":{}}}",
+                string(&exchange.server_address),
+                string(&exchange.server_name),
+                string(&format!(
+                    "{} {}",
+                    exchange.domain_name, exchange.record_type
+                )),
+                string(&rcode_name(exchange.response.rcode())),
+                exchange.elapsed.as_millis()
+            )
+        })
+        .collect();
+    format!(
+        "{{\"name\":{},\"type\":{},\"elapsed_ms\":{},\"error\":{},\"response\":{},\"servers\":[{}]}}",
+        string(domain_name),
+        string(&record_type.to_string()),
+        elapsed.as_millis(),
+        error,
+        response,
+        servers.join(",")
+    )
+}
+
+/// Render the path of a resolution as a JSON object holding, for every query sent in turn, the
+/// server it went to, what it asked, and whether it led to an answer or a referral (with the
+/// name servers and glue it referred to).
+///
+/// # Arguments
+/// * `domain_name`: The name which was resolved.
+/// * `record_type`: The type which was resolved.
+/// * `result`: The response to the query, or the error which prevented one.
+/// * `exchanges`: The exchanges made with servers along the way, in order.
+/// * `elapsed`: How long the resolution took.
+pub fn delegation_path(
+    domain_name: &str,
+    record_type: RecordType,
+    result: &Result<Packet, DnsError>,
+    exchanges: &[Exchange],
+    elapsed: Duration,
+) -> String {
+    let error = match result {
+        Ok(_) => "null".to_owned(),
+        Err(error) => string(&error.to_string()),
+    };
+    let path: Vec<String> = exchanges.iter().map(hop).collect();
+    format!(
+        "{{\"name\":{},\"type\":{},\"elapsed_ms\":{},\"error\":{},\"path\":[{}]}}",
+        string(domain_name),
+        string(&record_type.to_string()),
+        elapsed.as_millis(),
+        error,
+        path.join(",")
+    )
+}
+
+/// Render an exchange of a resolution as a JSON object, for `delegation_path()`.
+fn hop(exchange: &Exchange) -> String {
+    let (outcome, referral) = match exchange.outcome() {
+        Outcome::Answer => ("answer", "null".to_owned()),
+        Outcome::NameError => ("nxdomain", "null".to_owned()),
+        Outcome::NoData => ("nodata", "null".to_owned()),
+        Outcome::Failure(_) => ("failure", "null".to_owned()),
+        Outcome::Referral {
+            zone,
+            name_servers,
+            glue,
+        } => {
+            let name_servers: Vec<String> = name_servers.iter().map(|name| string(name)).collect();
+            let glue: Vec<String> = glue
+                .iter()
+                .map(|glue| {
+                    format!(
+                        "{{\"name\":{},\"address\":{}}}",
+                        string(&glue.name),
+                        string(&glue.address)
+                    )
+                })
+                .collect();
+            let referral = format!(
+                "{{\"zone\":{},\"name_servers\":[{}],\"glue\":[{}]}}",
+                string(&zone),
+                name_servers.join(","),
+                glue.join(",")
+            );
+            ("referral", referral)
+        }
+    };
+    format!(
+        "{{\"server\":{},\"server_name\":{},\"question\":{{\"name\":{},\"type\":{}}},\"rcode\":{},\"size\":{},\"elapsed_ms\":{},\"outcome\":{},\"referral\":{},\"answer\":[{}]}}",
+        string(&exchange.server_address),
+        string(&exchange.server_name),
+        string(&exchange.domain_name),
+        string(&exchange.record_type.to_string()),
+        string(&rcode_name(exchange.response.rcode())),
+        exchange.received_bytes,
+        exchange.elapsed.as_millis(),
+        string(outcome),
+        referral,
+        records(&exchange.response.answers)
+    )
+}
+
+/// Render the error which prevented resolving a name as a JSON object, with its exit code, what
+/// it says about retrying, and where it happened as far as known.
+///
+/// # Arguments
+/// * `domain_name`: The name which was resolved.
+/// * `record_type`: The type which was resolved.
+/// * `error`: The error which prevented resolving the name.
+pub fn error(domain_name: &str, record_type: RecordType, error: &DnsError) -> String {
+    let context = error.context().cloned().unwrap_or_default();
+    let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_owned());
+    format!(
+        "{{\"name\":{},\"type\":{},\"code\":{},\"error\":{},\"message\":{},\"category\":{},\"retryable\":{},\"server\":{},\"stage\":{},\"offset\":{},\"section\":{},\"index\":{}}}",
+        string(domain_name),
+        string(&record_type.to_string()),
+        error.exit_code(),
+        string(&error.name()),
+        string(&error.kind().to_string()),
+        string(&error.category().to_string()),
+        error.is_retryable(),
+        optional(context.server.map(|server| string(&server))),
+        optional(context.stage.map(|stage| string(&stage.to_string()))),
+        optional(context.offset.map(|offset| offset.to_string())),
+        optional(context.section.map(string)),
+        optional(context.index.map(|index| index.to_string())),
+    )
+}
+
+/// Render a message found in a capture as a JSON object holding where and when it was captured
+/// along with the decoded message (or the error which prevented decoding it).
+///
+/// # Arguments
+/// * `message`: The captured message.
+/// * `result`: The decoded message, or the error which prevented decoding it.
+pub fn captured_message(message: &CapturedMessage, result: &Result<Packet, DnsError>) -> String {
+    let (packet, error) = match result {
+        Ok(packet) => (packet_to_json(packet), "null".to_owned()),
+        Err(error) => ("null".to_owned(), string(&error.to_string())),
+    };
+    format!(
+        "{{\"timestamp\":{},\"source\":{},\"destination\":{},\"transport\":{},\"size\":{},\"error\":{},\"message\":{}}}",
+        string(&message.captured_at()),
+        string(&message.source.to_string()),
+        string(&message.destination.to_string()),
+        string(&message.transport.to_string()),
+        message.data.len(),
+        error,
+        packet
+    )
+}
+
+/// Render an entry of the query log as a JSON object. Resolutions without a response have a null
+/// response code.
+///
+/// # Argument
+/// * `entry`: The entry to render.
+pub fn query_log_entry(entry: &QueryLogEntry) -> String {
+    let rcode = match entry.rcode {
+        Some(rcode) => string(&rcode_name(rcode)),
+        None => "null".to_owned(),
+    };
+    let error = match &entry.error {
+        Some(error) => string(error),
+        None => "null".to_owned(),
+    };
+    let servers: Vec<String> = entry.servers.iter().map(|server| string(server)).collect();
+    format!(
+        "{{\"timestamp\":{},\"name\":{},\"type\":{},\"rcode\":{},\"answers\":{},\"elapsed_ms\":{},\"servers\":[{}],\"error\":{}}}",
+        string(&entry.started_at()),
+        string(&entry.domain_name),
+        string(&entry.record_type.to_string()),
+        rcode,
+        entry.answers,
+        entry.elapsed.as_millis(),
+        servers.join(","),
+        error
+    )
+}
+
+/// Render a DNS message as a JSON object with its header flags, response code and all four
+/// sections.
+///
+/// # Argument
+/// * `packet`: The message to render.
+pub fn packet_to_json(packet: &Packet) -> String {
+    let flags: Vec<String> = packet
+        .header
+        .flag_names()
+        .iter()
+        .map(|name| string(name))
+        .collect();
+    let questions: Vec<String> = packet.questions.iter().map(question).collect();
+    format!(
+        "{{\"id\":{},\"flags\":[{}],\"rcode\":{},\"question\":[{}],\"answer\":[{}],\"authority\":[{}],\"additional\":[{}]}}",
+        packet.header.id,
+        flags.join(","),
+        string(&rcode_name(packet.rcode())),
+        questions.join(","),
+        records(&packet.answers),
+        records(&packet.authorities),
+        records(&packet.additionals)
+    )
+}
+
+/// Render a question as a JSON object.
+fn question(question: &Question) -> String {
+    format!(
+        "{{\"name\":{},\"type\":{},\"class\":{}}}",
+        string(&String::from_utf8_lossy(&question.name)),
+        string(&question.q_type.to_string()),
+        string(&class_name(question.q_class))
+    )
+}
+
+/// Render a section of records as the elements of a JSON array.
+fn records(records: &[Record]) -> String {
+    let records: Vec<String> = records.iter().map(record).collect();
+    records.join(",")
+}
+
+/// Render a record as a JSON object. The data is given both in presentation format and, for the
+/// common types, split into typed fields.
+fn record(record: &Record) -> String {
+    format!(
+        "{{\"name\":{},\"type\":{},\"class\":{},\"ttl\":{},\"data\":{},\"rdata\":{}}}",
+        string(&String::from_utf8_lossy(&record.name)),
+        string(&record.r_type.to_string()),
+        string(&class_name(record.r_class)),
+        record.ttl,
+        string(&record.data_to_presentation()),
+        rdata(record)
+    )
+}
+
+/// Render the data of a record as a JSON object of typed fields. Numeric fields become numbers
+/// and the character strings of TXT and HINFO records an array of strings. Types without typed
+/// fields, or data which cannot be decoded, give an empty object.
+fn rdata(record: &Record) -> String {
+    if matches!(record.r_type, RecordType::TXT | RecordType::HINFO) {
+        let mut strings = Vec::new();
+        let mut rest = &record.data[..];
+        while let Some((&length, tail)) = rest.split_first() {
+            let Some(bytes) = tail.get(..length as usize) else {
+                return "{}".to_owned();
+            };
+            strings.push(string(&String::from_utf8_lossy(bytes)));
+            rest = &tail[length as usize..];
+        }
+        return format!("{{\"strings\":[{}]}}", strings.join(","));
+    }
+
+    let names = field_names(record.r_type);
+    let presentation = record.data_to_presentation();
+    let values: Vec<&str> = presentation.split(' ').collect();
+    if names.is_empty() || values.len() != names.len() {
+        return "{}".to_owned();
+    }
+    let fields: Vec<String> = names
+        .iter()
+        .zip(values)
+        .map(|(name, value)| match value.parse::<u32>() {
+            Ok(number) => format!("\"{}\":{}", name, number),
+            Err(_) => format!("\"{}\":{}", name, string(value)),
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Quote and escape a string for JSON, as specified in RFC 8259, section 7.
+///
+/// # Argument
+/// * `value`: The string to quote.
+fn string(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for character in value.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{0}'..='\u{1f}' => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            _ => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Validate rendering of a response along with the servers it came from.
+#[test]
+fn test_resolution_to_json() {
+    use crate::dns_name::DnsName;
+    use crate::header::{Header, FLAG_RECURSION_DESIRED, FLAG_RESPONSE};
+    use crate::ttl::Ttl;
+
+    let record = |r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from("example.com"),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+    let packet = Packet {
+        header: Header {
+            id: 1234,
+            flags: FLAG_RESPONSE | FLAG_RECURSION_DESIRED,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("example.com"),
+            q_type: RecordType::ANY,
+            q_class: 1,
+        }],
+        answers: vec![
+            record(RecordType::A, &[192, 0, 2, 1]),
+            record(RecordType::MX, b"\x00\x0a\x04mail\x07example\x03com\x00"),
+            record(RecordType::TXT, b"\x08\"quoted\""),
+        ],
+        authorities: vec![],
+        additionals: vec![record(RecordType::Unknown(65), &[1])],
+    };
+    let exchanges = [Exchange {
+        server_address: "192.0.2.53:53".to_owned(),
+        server_name: "".to_owned(),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::ANY,
+        response: packet.clone(),
+        received_bytes: 100,
+        elapsed: Duration::from_millis(7),
+    }];
+
+    assert_eq!(
+        resolution(
+            "example.com",
+            RecordType::ANY,
+            &Ok(packet),
+            &exchanges,
+            Duration::from_millis(8)
+        ),
+        concat!(
+            r#"{"name":"example.com","type":"ANY","elapsed_ms":8,"error":null,"response":"#,
+            r#"{"id":1234,"flags":["qr","rd"],"rcode":"NOERROR","#,
+            r#""question":[{"name":"example.com","type":"ANY","class":"IN"}],"answer":["#,
+            r#"{"name":"example.com","type":"A","class":"IN","ttl":300,"data":"192.0.2.1","#,
+            r#""rdata":{"address":"192.0.2.1"}},"#,
+            r#"{"name":"example.com","type":"MX","class":"IN","ttl":300,"#,
+            r#""data":"10 mail.example.com.","rdata":{"preference":10,"exchange":"mail.example.com."}},"#,
+            r#"{"name":"example.com","type":"TXT","class":"IN","ttl":300,"#,
+            r#""data":"\"\\\"quoted\\\"\"","rdata":{"strings":["\"quoted\""]}}],"#,
+            r#""authority":[],"additional":[{"name":"example.com","type":"TYPE65","class":"IN","#,
+            r#""ttl":300,"data":"\\# 1 01","rdata":{}}]},"#,
+            r#""servers":[{"address":"192.0.2.53:53","name":"","question":"example.com ANY","#,
+            r#""rcode":"NOERROR","elapsed_ms":7}]}"#,
+        )
+    );
+
+    assert_eq!(
+        resolution(
+            "example.com",
+            RecordType::A,
+            &Err(DnsError::UnknownDomainName),
+            &[],
+            Duration::ZERO
+        ),
+        concat!(
+            r#"{"name":"example.com","type":"A","elapsed_ms":0,"#,
+            r#""error":"UnknownDomainName: No nameservers are aware of the given domain name","#,
+            r#""response":null,"servers":[]}"#,
+        )
+    );
+}
+
+/// Validate rendering of the error which prevented resolving a name, along with where it happened.
+#[test]
+fn test_error_to_json() {
+    use crate::errors::{ErrorContext, Stage};
+
+    let failure = DnsError::ReadRecordData(None)
+        .with_context(ErrorContext {
+            offset: Some(42),
+            section: Some("answer"),
+            index: Some(1),
+            ..Default::default()
+        })
+        .with_context(ErrorContext::exchange("192.0.2.53:53", Stage::Parse));
+    assert_eq!(
+        error("example.com", RecordType::A, &failure),
+        concat!(
+            r#"{"name":"example.com","type":"A","code":17,"error":"ReadRecordData","#,
+            r#""message":"ReadRecordData: Could not read data in record","category":"protocol","#,
+            r#""retryable":false,"server":"192.0.2.53:53","stage":"parse","offset":42,"#,
+            r#""section":"answer","index":1}"#,
+        )
+    );
+    assert_eq!(
+        error("example.com", RecordType::A, &DnsError::SocketRead(None)),
+        concat!(
+            r#"{"name":"example.com","type":"A","code":20,"error":"SocketRead","#,
+            r#""message":"SocketRead: Could not read data from socket","category":"transient","#,
+            r#""retryable":true,"server":null,"stage":null,"offset":null,"section":null,"#,
+            r#""index":null}"#,
+        )
+    );
+}
+
+/// Validate rendering of a captured message which could not be decoded.
+#[test]
+fn test_captured_message_to_json() {
+    use crate::pcap::Transport;
+
+    let message = CapturedMessage {
+        timestamp: Duration::from_millis(1700000000250),
+        source: "[2001:db8::1]:40000".parse().unwrap(),
+        destination: "[2001:db8::53]:53".parse().unwrap(),
+        transport: Transport::Tcp,
+        data: vec![0x12, 0x34],
+    };
+    assert_eq!(
+        captured_message(&message, &Packet::parse(&message.data)),
+        concat!(
+            r#"{"timestamp":"2023-11-14T22:13:20.250000Z","source":"[2001:db8::1]:40000","#,
+            r#""destination":"[2001:db8::53]:53","transport":"TCP","size":2,"#,
+            r#""error":"ParseFlag: Could not parse flag in header: failed to fill whole buffer "#,
+            r#"(offset: 2, section: header)","message":null}"#,
+        )
+    );
+}
+
+/// Validate rendering of entries of the query log, with and without a response.
+#[test]
+fn test_query_log_entry_to_json() {
+    let mut entry = QueryLogEntry {
+        timestamp: Duration::from_millis(1_700_000_000_250),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::A,
+        rcode: Some(0),
+        answers: 2,
+        elapsed: Duration::from_millis(42),
+        servers: vec!["198.41.0.4:53".to_owned(), "192.5.6.30:53".to_owned()],
+        error: None,
+    };
+    assert_eq!(
+        query_log_entry(&entry),
+        concat!(
+            r#"{"timestamp":"2023-11-14T22:13:20.250000Z","name":"example.com","type":"A","#,
+            r#""rcode":"NOERROR","answers":2,"elapsed_ms":42,"#,
+            r#""servers":["198.41.0.4:53","192.5.6.30:53"],"error":null}"#,
+        )
+    );
+
+    entry.rcode = None;
+    entry.answers = 0;
+    entry.servers = vec![];
+    entry.error = Some("SocketRead: \"timed out\"".to_owned());
+    assert!(query_log_entry(&entry)
+        .ends_with(r#""rcode":null,"answers":0,"elapsed_ms":42,"servers":[],"error":"SocketRead: \"timed out\""}"#));
+}
+
+/// Validate rendering of the path of a resolution, with a referral and an answer.
+#[test]
+fn test_delegation_path_to_json() {
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+    use crate::ttl::Ttl;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+    let response =
+        |answers: Vec<Record>, authorities: Vec<Record>, additionals: Vec<Record>| Packet {
+            header: Header::default(),
+            questions: vec![],
+            answers,
+            authorities,
+            additionals,
+        };
+    let exchange = |server_address: &str, server_name: &str, response: Packet| Exchange {
+        server_address: server_address.to_owned(),
+        server_name: server_name.to_owned(),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::A,
+        response,
+        received_bytes: 100,
+        elapsed: Duration::from_millis(5),
+    };
+    let answer = response(
+        vec![record("example.com", RecordType::A, &[192, 0, 2, 1])],
+        vec![],
+        vec![],
+    );
+    let exchanges = [
+        exchange(
+            "198.41.0.4:53",
+            "a.root-servers.net",
+            response(
+                vec![],
+                vec![record("com", RecordType::NS, b"\x01a\x03net\x00")],
+                vec![record("a.net", RecordType::A, &[192, 5, 6, 30])],
+            ),
+        ),
+        exchange("192.5.6.30:53", "", answer.clone()),
+    ];
+
+    assert_eq!(
+        delegation_path(
+            "example.com",
+            RecordType::A,
+            &Ok(answer),
+            &exchanges,
+            Duration::from_millis(11)
+        ),
+        concat!(
+            r#"{"name":"example.com","type":"A","elapsed_ms":11,"error":null,"path":["#,
+            r#"{"server":"198.41.0.4:53","server_name":"a.root-servers.net","#,
+            r#""question":{"name":"example.com","type":"A"},"rcode":"NOERROR","size":100,"#,
+            r#""elapsed_ms":5,"outcome":"referral","referral":{"zone":"com","#,
+            r#""name_servers":["a.net."],"glue":[{"name":"a.net","address":"192.5.6.30"}]},"#,
+            r#""answer":[]},"#,
+            r#"{"server":"192.5.6.30:53","server_name":"","#,
+            r#""question":{"name":"example.com","type":"A"},"rcode":"NOERROR","size":100,"#,
+            r#""elapsed_ms":5,"outcome":"answer","referral":null,"answer":["#,
+            r#"{"name":"example.com","type":"A","class":"IN","ttl":300,"data":"192.0.2.1","#,
+            r#""rdata":{"address":"192.0.2.1"}}]}]}"#,
+        )
+    );
+}
@@ -1,14 +1,71 @@
 pub mod packet;
 pub mod query;
+pub mod question;
 pub mod record;
+pub mod resolver;
+pub mod resolver_config;
+pub mod rrl;
+pub mod server;
+pub mod zone;
 
+pub mod acl;
+pub mod address_sort;
+#[cfg(feature = "tokio")]
+pub mod async_transport;
+pub mod axfr;
+pub mod bench;
+pub mod blocklist;
+pub mod bulk;
+pub mod caa;
+pub mod cache;
+pub mod control;
+pub mod dane;
+pub mod delegation;
+pub mod dig;
+pub mod dkim;
+pub mod dmarc;
+pub mod dns64;
+pub mod dns_name;
+pub mod dnssd;
+pub mod e164;
+pub mod edns;
 pub mod errors;
-mod header;
-mod question;
+pub mod forwarder;
+pub mod fuzz;
+pub mod generator;
+pub mod graphviz;
+pub mod header;
+pub mod hosts;
+#[cfg(feature = "idna")]
+pub mod idna;
+pub mod in_flight;
+pub mod json;
+pub mod lint;
+pub mod mailcheck;
+pub mod metrics;
+pub mod naptr;
+pub mod open_resolver;
+pub mod pcap;
+pub mod query_log;
+pub mod rdata;
 mod record_name;
 mod root_servers;
+pub mod spf;
+pub mod srv;
+pub mod stats;
+pub mod subdomains;
+pub mod svcb;
+pub mod throttle;
+pub mod trace;
+pub mod ttl;
+pub mod upstream_pool;
+pub mod wildcard;
+pub mod zone_walk;
 
+pub mod https;
 pub mod socket;
+pub mod tcp;
+pub mod tls;
 
 // Normally, this should not be pub. However, I wanted to easily test main.rs using this mock data.
 // I would usually recommend a multi-pronged approach of unit-testing, integrated testing,
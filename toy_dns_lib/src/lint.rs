@@ -0,0 +1,977 @@
+use crate::errors::DnsError;
+use crate::record::{Record, RecordType};
+use crate::record_name::RecordName;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use crate::zone::{Zone, ZoneLookup};
+use std::fmt;
+use std::io::{Cursor, Read};
+
+/// TTLs above a week keep mistakes cached for too long.
+const MAX_TTL: u32 = 604_800;
+
+/// TTLs under a minute have resolvers asking again all the time.
+const MIN_TTL: u32 = 60;
+
+/// Resolvers cache negative answers for at most a day whatever the SOA says (RFC 2308, section 5).
+const MAX_NEGATIVE_TTL: u32 = 86_400;
+
+/// Secondaries which lose the primary for less than the expire time keep serving the zone. RFC
+/// 1912, section 2.2 recommends two to four weeks, so less than a week is cutting it close.
+const MIN_EXPIRE: u32 = 604_800;
+
+/// How serious a finding is.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    /// Worth knowing, but not necessarily wrong.
+    Info,
+
+    /// Likely to cause trouble, or against common recommendations.
+    Warning,
+
+    /// Broken: resolvers will fail or misbehave.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Something wrong or unusual about a zone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Finding {
+    /// How serious it is.
+    pub severity: Severity,
+
+    /// The owner of the records it is about, e.g. `www.example.com`.
+    pub name: String,
+
+    /// What is wrong, e.g. `CNAME alongside other data (A)`.
+    pub message: String,
+}
+
+impl Finding {
+    /// A finding about the records of the given name.
+    ///
+    /// # Arguments
+    /// * `severity`: How serious it is.
+    /// * `name`: The owner of the records it is about.
+    /// * `message`: What is wrong.
+    fn new(severity: Severity, name: impl fmt::Display, message: String) -> Finding {
+        Finding {
+            severity,
+            name: name.to_string(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.name, self.message)
+    }
+}
+
+/// The findings about a zone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LintReport {
+    /// The name of the zone.
+    pub origin: String,
+
+    /// The findings, in the order they were made.
+    pub findings: Vec<Finding>,
+}
+
+impl LintReport {
+    /// The number of findings of the given severity.
+    ///
+    /// # Argument
+    /// * `severity`: The severity to count.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    }
+
+    /// Whether any finding is an error.
+    pub fn has_errors(&self) -> bool {
+        self.count(Severity::Error) > 0
+    }
+
+    /// Render the report: the findings, most serious first, then their count by severity.
+    pub fn render(&self) -> String {
+        let mut findings: Vec<&Finding> = self.findings.iter().collect();
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.severity));
+        let mut output = String::new();
+        for finding in findings {
+            output.push_str(&format!("{}\n", finding));
+        }
+        output.push_str(&format!(
+            "{}: {} errors, {} warnings, {} info\n",
+            self.origin,
+            self.count(Severity::Error),
+            self.count(Severity::Warning),
+            self.count(Severity::Info)
+        ));
+        output
+    }
+}
+
+/// The fields of an SOA record (RFC 1035, section 3.3.13).
+#[derive(Debug, PartialEq, Clone)]
+pub struct SoaRecord {
+    /// The primary name server of the zone.
+    pub mname: String,
+
+    /// The mailbox of the person responsible for the zone, with its `@` as a dot.
+    pub rname: String,
+
+    /// The version of the zone.
+    pub serial: u32,
+
+    /// How often secondaries check for a new version, in seconds.
+    pub refresh: u32,
+
+    /// How long secondaries wait to check again after a failed check, in seconds.
+    pub retry: u32,
+
+    /// How long secondaries keep serving the zone without reaching the primary, in seconds.
+    pub expire: u32,
+
+    /// How long resolvers cache negative answers, in seconds (RFC 2308, section 4).
+    pub minimum: u32,
+}
+
+impl SoaRecord {
+    /// Parse the data of an SOA record.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<SoaRecord, DnsError> {
+        let mut cursor = Cursor::new(data);
+        let mut names = Vec::new();
+        for _ in 0..2 {
+            let name = RecordName::read_and_advance(&mut cursor)?;
+            let Ok(name) = String::from_utf8(name) else {
+                return Err(DnsError::InvalidByteInName);
+            };
+            names.push(name);
+        }
+        let mut numbers = [0u32; 5];
+        for number in &mut numbers {
+            let mut bytes = [0u8; 4];
+            cursor
+                .read_exact(&mut bytes)
+                .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+            *number = u32::from_be_bytes(bytes);
+        }
+        let [serial, refresh, retry, expire, minimum] = numbers;
+        let [mname, rname] = <[String; 2]>::try_from(names).unwrap_or_default();
+        Ok(SoaRecord {
+            mname,
+            rname,
+            serial,
+            refresh,
+            retry,
+            expire,
+            minimum,
+        })
+    }
+}
+
+/// The name a record points at: the target of NS and CNAME records, the exchange of MX records
+/// and the target of SRV records, in lowercase. `None` for other records and for the root, which
+/// MX and SRV records point at to say there is no such service.
+///
+/// # Argument
+/// * `record`: The record.
+fn target(record: &Record) -> Option<String> {
+    let offset = match record.r_type {
+        RecordType::NS | RecordType::CNAME => 0,
+        RecordType::MX => 2,
+        RecordType::SRV => 6,
+        _ => return None,
+    };
+    let mut cursor = Cursor::new(record.data.get(offset..)?);
+    let name = String::from_utf8(RecordName::read_and_advance(&mut cursor).ok()?).ok()?;
+    match name.is_empty() {
+        true => None,
+        false => Some(name.to_ascii_lowercase()),
+    }
+}
+
+/// The records of a zone grouped into RRsets, in the order each set first appears.
+///
+/// # Argument
+/// * `zone`: The zone.
+fn rrsets(zone: &Zone) -> Vec<Vec<&Record>> {
+    let mut rrsets: Vec<Vec<&Record>> = Vec::new();
+    for record in &zone.records {
+        match rrsets
+            .iter_mut()
+            .find(|rrset| rrset[0].name == record.name && rrset[0].r_type == record.r_type)
+        {
+            Some(rrset) => rrset.push(record),
+            None => rrsets.push(vec![record]),
+        }
+    }
+    rrsets
+}
+
+/// Check the SOA and NS records at the apex of a zone.
+///
+/// # Argument
+/// * `zone`: The zone.
+fn check_apex(zone: &Zone) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let apex = |record: &&Record| record.name == zone.origin.as_str();
+    let soa_count = zone
+        .records
+        .iter()
+        .filter(apex)
+        .filter(|record| record.r_type == RecordType::SOA)
+        .count();
+    let ns_count = zone
+        .records
+        .iter()
+        .filter(apex)
+        .filter(|record| record.r_type == RecordType::NS)
+        .count();
+    match soa_count {
+        0 => findings.push(Finding::new(
+            Severity::Error,
+            &zone.origin,
+            "no SOA record at the apex".to_owned(),
+        )),
+        1 => {}
+        count => findings.push(Finding::new(
+            Severity::Error,
+            &zone.origin,
+            format!("{} SOA records at the apex", count),
+        )),
+    }
+    match ns_count {
+        0 => findings.push(Finding::new(
+            Severity::Error,
+            &zone.origin,
+            "no NS records at the apex".to_owned(),
+        )),
+        1 => findings.push(Finding::new(
+            Severity::Warning,
+            &zone.origin,
+            "a single name server, leaving no redundancy".to_owned(),
+        )),
+        _ => {}
+    }
+
+    let Some(soa) = zone.soa() else {
+        return findings;
+    };
+    let soa = match SoaRecord::from_data(&soa.data) {
+        Ok(soa) => soa,
+        Err(error) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                &zone.origin,
+                format!("malformed SOA record: {}", error),
+            ));
+            return findings;
+        }
+    };
+    let mut soa_finding = |severity, message| {
+        findings.push(Finding::new(severity, &zone.origin, message));
+    };
+    if soa.retry >= soa.refresh {
+        soa_finding(
+            Severity::Warning,
+            format!(
+                "SOA retry ({}) is not shorter than refresh ({})",
+                soa.retry, soa.refresh
+            ),
+        );
+    }
+    if soa.expire <= soa.refresh {
+        soa_finding(
+            Severity::Error,
+            format!(
+                "SOA expire ({}) is not longer than refresh ({}), so secondaries expire the zone between checks",
+                soa.expire, soa.refresh
+            ),
+        );
+    } else if soa.expire < MIN_EXPIRE {
+        soa_finding(
+            Severity::Warning,
+            format!("SOA expire ({}) is under a week", soa.expire),
+        );
+    }
+    if soa.minimum > MAX_NEGATIVE_TTL {
+        soa_finding(
+            Severity::Warning,
+            format!(
+                "SOA minimum ({}) exceeds a day, the most resolvers cache negative answers for",
+                soa.minimum
+            ),
+        );
+    }
+    findings
+}
+
+/// Check CNAME records: they must be alone at their name (RFC 1034, section 3.6.2), and their
+/// targets within the zone must exist.
+///
+/// # Argument
+/// * `zone`: The zone.
+fn check_cnames(zone: &Zone) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let rrsets = rrsets(zone);
+    for rrset in rrsets
+        .iter()
+        .filter(|rrset| rrset[0].r_type == RecordType::CNAME)
+    {
+        let name = &rrset[0].name;
+        if rrset.len() > 1 {
+            findings.push(Finding::new(
+                Severity::Error,
+                name,
+                format!("{} CNAME records", rrset.len()),
+            ));
+        }
+        // DNSSEC records are the only ones allowed alongside a CNAME record (RFC 4035).
+        let others: Vec<String> = rrsets
+            .iter()
+            .filter(|other| other[0].name == *name)
+            .map(|other| other[0].r_type)
+            .filter(|r_type| {
+                !matches!(
+                    r_type,
+                    RecordType::CNAME | RecordType::RRSIG | RecordType::NSEC
+                )
+            })
+            .map(|r_type| r_type.to_string())
+            .collect();
+        if !others.is_empty() {
+            findings.push(Finding::new(
+                Severity::Error,
+                name,
+                format!("CNAME alongside other data ({})", others.join(", ")),
+            ));
+        }
+
+        for target in rrset.iter().filter_map(|record| target(record)) {
+            if zone.contains(&target)
+                && zone.lookup(&target, RecordType::ANY) == ZoneLookup::NameError
+            {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    name,
+                    format!("dangling CNAME: {} does not exist", target),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+/// Check the names NS, MX and SRV records point at within the zone: they must not be aliases
+/// (RFC 2181, section 10.3) and must have addresses, given as glue when they lie within a
+/// delegated child zone.
+///
+/// # Argument
+/// * `zone`: The zone.
+fn check_targets(zone: &Zone) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for record in &zone.records {
+        if !matches!(
+            record.r_type,
+            RecordType::NS | RecordType::MX | RecordType::SRV
+        ) {
+            continue;
+        }
+        let Some(target) = target(record) else {
+            continue;
+        };
+        if !zone.contains(&target) {
+            continue;
+        }
+
+        let records_at_target: Vec<&Record> = zone
+            .records
+            .iter()
+            .filter(|other| other.name == target.as_str())
+            .collect();
+        let has_addresses = records_at_target
+            .iter()
+            .any(|other| matches!(other.r_type, RecordType::A | RecordType::AAAA));
+        if records_at_target
+            .iter()
+            .any(|other| other.r_type == RecordType::CNAME)
+        {
+            findings.push(Finding::new(
+                Severity::Error,
+                &record.name,
+                format!("{} points at {}, which is an alias", record.r_type, target),
+            ));
+        } else if let ZoneLookup::Referral { .. } = zone.lookup(&target, RecordType::A) {
+            // Names within a child zone are resolved there, unless they are needed to reach
+            // the child zone in the first place.
+            if record.r_type == RecordType::NS && !has_addresses {
+                findings.push(Finding::new(
+                    Severity::Error,
+                    &record.name,
+                    format!("missing glue for {}", target),
+                ));
+            }
+        } else if !matches!(zone.lookup(&target, RecordType::A), ZoneLookup::Answer(_))
+            && !matches!(
+                zone.lookup(&target, RecordType::AAAA),
+                ZoneLookup::Answer(_)
+            )
+        {
+            findings.push(Finding::new(
+                Severity::Error,
+                &record.name,
+                format!(
+                    "{} points at {}, which has no address records",
+                    record.r_type, target
+                ),
+            ));
+        }
+    }
+    findings
+}
+
+/// Check the TTLs of the records: those of an RRset must be equal (RFC 2181, section 5.2), and
+/// unusually long or short ones are pointed out.
+///
+/// # Argument
+/// * `zone`: The zone.
+fn check_ttls(zone: &Zone) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for rrset in rrsets(zone) {
+        let (name, r_type) = (&rrset[0].name, rrset[0].r_type);
+        // Signatures of different types share an RRset, each with the TTL of what it signs.
+        if r_type == RecordType::RRSIG {
+            continue;
+        }
+        let ttls: Vec<u32> = rrset.iter().map(|record| record.ttl.as_secs()).collect();
+        let shortest = ttls.iter().copied().min().unwrap_or_default();
+        let longest = ttls.iter().copied().max().unwrap_or_default();
+        if shortest != longest {
+            findings.push(Finding::new(
+                Severity::Warning,
+                name,
+                format!(
+                    "the TTLs of the {} records differ ({} to {})",
+                    r_type, shortest, longest
+                ),
+            ));
+        }
+        if longest > MAX_TTL {
+            findings.push(Finding::new(
+                Severity::Warning,
+                name,
+                format!("the {} TTL ({}) exceeds a week", r_type, longest),
+            ));
+        }
+        if shortest < MIN_TTL {
+            findings.push(Finding::new(
+                Severity::Info,
+                name,
+                format!("the {} TTL ({}) is under a minute", r_type, shortest),
+            ));
+        }
+    }
+    findings
+}
+
+/// Check a zone on its own: SOA and NS records at the apex, CNAME records alone at their name
+/// and pointing at existing names, NS, MX and SRV records pointing at names with addresses
+/// rather than aliases, glue for delegations, and TTLs. Names outside the zone are left to
+/// `lint_external_names()`.
+///
+/// # Argument
+/// * `zone`: The zone to check.
+pub fn lint_zone(zone: &Zone) -> LintReport {
+    let mut findings = check_apex(zone);
+    findings.extend(check_cnames(zone));
+    findings.extend(check_targets(zone));
+    findings.extend(check_ttls(zone));
+    LintReport {
+        origin: zone.origin.clone(),
+        findings,
+    }
+}
+
+/// Check the names outside of a zone which its NS, MX, SRV and CNAME records point at, by
+/// resolving them. Each name is resolved once.
+///
+/// # Arguments
+/// * `zone`: The zone to check.
+/// * `resolver`: The resolver to resolve the names with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lint_external_names(
+    zone: &Zone,
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    rand_seed: Option<usize>,
+) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut resolved: Vec<(String, Result<usize, DnsError>)> = Vec::new();
+    for record in &zone.records {
+        let Some(target) = target(record) else {
+            continue;
+        };
+        if zone.contains(&target) {
+            continue;
+        }
+        let index = match resolved.iter().position(|(name, _)| *name == target) {
+            Some(index) => index,
+            None => {
+                let addresses = resolver
+                    .lookup_ip(socket, &target, rand_seed)
+                    .map(|addresses| addresses.len());
+                resolved.push((target.clone(), addresses));
+                resolved.len() - 1
+            }
+        };
+
+        let (severity, problem) = match (&resolved[index].1, record.r_type) {
+            // A CNAME record may point at a name without addresses, e.g. one with only TXT records.
+            (Ok(0), RecordType::NS | RecordType::MX | RecordType::SRV) => {
+                (Severity::Error, "has no address records".to_owned())
+            }
+            (Ok(_), _) => continue,
+            (Err(DnsError::UnknownDomainName), RecordType::CNAME) => (
+                Severity::Error,
+                "does not exist, leaving the CNAME dangling".to_owned(),
+            ),
+            (Err(DnsError::UnknownDomainName), _) => (Severity::Error, "does not exist".to_owned()),
+            (Err(error), _) => (
+                Severity::Warning,
+                format!("could not be resolved: {}", error),
+            ),
+        };
+        findings.push(Finding::new(
+            severity,
+            &record.name,
+            format!("{} points at {}, which {}", record.r_type, target, problem),
+        ));
+    }
+    findings
+}
+
+#[cfg(test)]
+const LINT_ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@       SOA ns1 hostmaster 1 3600 7200 86400 172800
+        NS  ns1
+        MX  10 mail
+        MX  20 alias
+ns1     A   192.0.2.1
+www     CNAME web
+www     TXT \"oops\"
+alias   CNAME ns1
+gone    CNAME nowhere
+dup     CNAME ns1
+dup     CNAME www
+mail    A   192.0.2.2
+mail    30 A 192.0.2.3
+_sip._udp SRV 0 0 5060 nobody
+child   NS  ns.child
+child   NS  ns.example.net.
+other   NS  ns.other
+ns.other A  192.0.2.4
+short   10 A 192.0.2.5
+long    1209600 TXT \"forever\"
+";
+
+/// Validate the checks of a zone on its own.
+#[test]
+fn test_linting_zone() -> Result<(), DnsError> {
+    let zone = Zone::parse(LINT_ZONE, "")?;
+    let report = lint_zone(&zone);
+    let findings: Vec<String> = report.findings.iter().map(Finding::to_string).collect();
+    assert_eq!(
+        findings,
+        [
+            "warning: example.com: a single name server, leaving no redundancy",
+            "warning: example.com: SOA retry (7200) is not shorter than refresh (3600)",
+            "warning: example.com: SOA expire (86400) is under a week",
+            "warning: example.com: SOA minimum (172800) exceeds a day, the most resolvers cache negative answers for",
+            "error: www.example.com: CNAME alongside other data (TXT)",
+            "error: www.example.com: dangling CNAME: web.example.com does not exist",
+            "error: gone.example.com: dangling CNAME: nowhere.example.com does not exist",
+            "error: dup.example.com: 2 CNAME records",
+            "error: example.com: MX points at alias.example.com, which is an alias",
+            "error: _sip._udp.example.com: SRV points at nobody.example.com, which has no address records",
+            "error: child.example.com: missing glue for ns.child.example.com",
+            "warning: mail.example.com: the TTLs of the A records differ (30 to 3600)",
+            "info: mail.example.com: the A TTL (30) is under a minute",
+            "info: short.example.com: the A TTL (10) is under a minute",
+            "warning: long.example.com: the TXT TTL (1209600) exceeds a week",
+        ]
+    );
+    assert!(report.has_errors());
+    assert_eq!(report.count(Severity::Error), 7);
+    let rendered = report.render();
+    assert!(rendered.starts_with("error: www.example.com: CNAME alongside other data"));
+    assert!(rendered.ends_with("example.com: 7 errors, 6 warnings, 2 info\n"));
+
+    let zone = Zone::parse("www.example.com. 3600 IN A 192.0.2.1", "example.com")?;
+    let findings: Vec<String> = lint_zone(&zone)
+        .findings
+        .iter()
+        .map(Finding::to_string)
+        .collect();
+    assert_eq!(
+        findings,
+        [
+            "error: example.com: no SOA record at the apex",
+            "error: example.com: no NS records at the apex",
+        ]
+    );
+    Ok(())
+}
+
+/// Validate checking the names a zone points at outside of itself.
+#[test]
+fn test_linting_external_names() -> Result<(), DnsError> {
+    use crate::hosts::HostsFile;
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let zone = Zone::parse(
+        "
+$ORIGIN example.com.
+@       3600 SOA ns1.example.net. hostmaster 1 3600 900 1209600 300
+        3600 NS  ns1.example.net.
+        3600 NS  ns2.example.net.
+        3600 MX  10 mx.example.org.
+www     3600 CNAME gone.example.org.
+",
+        "",
+    )?;
+    let exchanges = vec![
+        MockResolution::new("gone.example.org", RecordType::AAAA, 0)
+            .name_error()
+            .build()?,
+        MockResolution::new("gone.example.org", RecordType::A, 0)
+            .name_error()
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse(
+        "192.0.2.1 ns1.example.net\n192.0.2.25 mx.example.org\n",
+    ));
+    assert!(lint_zone(&zone).findings.is_empty());
+    let findings: Vec<String> = lint_external_names(&zone, &resolver, &mut socket, Some(0))
+        .iter()
+        .map(Finding::to_string)
+        .collect();
+    assert_eq!(findings.len(), 2);
+    assert!(findings[0].starts_with(
+        "warning: example.com: NS points at ns2.example.net, which could not be resolved"
+    ));
+    assert_eq!(
+        findings[1],
+        "error: www.example.com: CNAME points at gone.example.org, which does not exist, leaving the CNAME dangling"
+    );
+    Ok(())
+}
+
+/// A zone none of the checks find anything about, for the rule tests to add to or alter.
+#[cfg(test)]
+const CLEAN_ZONE: &str = "
+$ORIGIN example.com.
+$TTL 3600
+@       SOA ns1 hostmaster 1 3600 900 1209600 300
+        NS  ns1
+        NS  ns2
+ns1     A   192.0.2.1
+ns2     A   192.0.2.2
+";
+
+/// The findings about the given zone, rendered, e.g. for a rule test to look for its finding.
+///
+/// # Argument
+/// * `contents`: The zone file of example.com.
+#[cfg(test)]
+fn lint_findings(contents: &str) -> Vec<String> {
+    let zone = Zone::parse(contents, "example.com").unwrap();
+    lint_zone(&zone)
+        .findings
+        .iter()
+        .map(Finding::to_string)
+        .collect()
+}
+
+/// Validate the rule that a zone has exactly one SOA record at its apex.
+#[test]
+fn test_lint_rule_soa_count() {
+    assert_eq!(lint_findings(CLEAN_ZONE), Vec::<String>::new());
+    let missing = CLEAN_ZONE.replace("@       SOA ns1 hostmaster 1 3600 900 1209600 300\n", "@");
+    assert_eq!(
+        lint_findings(&missing),
+        ["error: example.com: no SOA record at the apex"]
+    );
+
+    let twice = format!(
+        "{}@ SOA ns2 hostmaster 2 3600 900 1209600 300\n",
+        CLEAN_ZONE
+    );
+    assert_eq!(
+        lint_findings(&twice),
+        ["error: example.com: 2 SOA records at the apex"]
+    );
+}
+
+/// Validate the rule that a zone has NS records at its apex, more than one of them.
+#[test]
+fn test_lint_rule_ns_count() {
+    let three = format!("{}@ NS ns.example.net.\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&three), Vec::<String>::new());
+    let single = CLEAN_ZONE.replace("        NS  ns2\n", "");
+    assert_eq!(
+        lint_findings(&single),
+        ["warning: example.com: a single name server, leaving no redundancy"]
+    );
+
+    let none = single.replace("        NS  ns1\n", "");
+    assert_eq!(
+        lint_findings(&none),
+        ["error: example.com: no NS records at the apex"]
+    );
+}
+
+/// Validate the rule that the SOA record of a zone is well formed.
+#[test]
+fn test_lint_rule_malformed_soa() {
+    let mut zone = Zone::parse(CLEAN_ZONE, "").unwrap();
+    assert!(lint_zone(&zone).findings.is_empty());
+    let soa = zone
+        .records
+        .iter_mut()
+        .find(|record| record.r_type == RecordType::SOA)
+        .unwrap();
+    soa.data.truncate(soa.data.len() - 4);
+    let findings = lint_zone(&zone).findings;
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0]
+        .to_string()
+        .starts_with("error: example.com: malformed SOA record: "));
+}
+
+/// Validate the rule that the SOA retry is shorter than the refresh.
+#[test]
+fn test_lint_rule_soa_retry() {
+    let zone = CLEAN_ZONE.replace("3600 900 1209600", "3600 3600 1209600");
+    assert_eq!(
+        lint_findings(&zone),
+        ["warning: example.com: SOA retry (3600) is not shorter than refresh (3600)"]
+    );
+    let zone = CLEAN_ZONE.replace("3600 900 1209600", "3600 3599 1209600");
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rules that the SOA expire is longer than the refresh, and at least a week.
+#[test]
+fn test_lint_rule_soa_expire() {
+    let zone = CLEAN_ZONE.replace("3600 900 1209600", "3600 900 3600");
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: example.com: SOA expire (3600) is not longer than refresh (3600), so secondaries expire the zone between checks"]
+    );
+
+    let zone = CLEAN_ZONE.replace("3600 900 1209600", "3600 900 86400");
+    assert_eq!(
+        lint_findings(&zone),
+        ["warning: example.com: SOA expire (86400) is under a week"]
+    );
+    let zone = CLEAN_ZONE.replace("3600 900 1209600", "3600 900 604800");
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that the SOA minimum, the TTL of negative answers, is at most a day.
+#[test]
+fn test_lint_rule_soa_minimum() {
+    let zone = CLEAN_ZONE.replace("1209600 300", "1209600 86401");
+    assert_eq!(
+        lint_findings(&zone),
+        ["warning: example.com: SOA minimum (86401) exceeds a day, the most resolvers cache negative answers for"]
+    );
+    let zone = CLEAN_ZONE.replace("1209600 300", "1209600 86400");
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that a name has at most one CNAME record.
+#[test]
+fn test_lint_rule_cname_count() {
+    let zone = format!("{}www CNAME ns1\nwww CNAME ns2\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: www.example.com: 2 CNAME records"]
+    );
+    let zone = format!("{}www CNAME ns1\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that a CNAME record is alone at its name.
+#[test]
+fn test_lint_rule_cname_alongside_data() {
+    let zone = format!(
+        "{}www CNAME ns1\nwww TXT \"oops\"\nwww MX 10 ns1\n",
+        CLEAN_ZONE
+    );
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: www.example.com: CNAME alongside other data (TXT, MX)"]
+    );
+    let zone = format!("{}www CNAME ns1\nweb TXT \"fine\"\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that a CNAME record within the zone points at a name which exists. Names
+/// outside the zone are left to `lint_external_names()`.
+#[test]
+fn test_lint_rule_dangling_cname() {
+    let zone = format!("{}www CNAME web\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: www.example.com: dangling CNAME: web.example.com does not exist"]
+    );
+    let zone = format!("{}www CNAME www.example.net.\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that NS, MX and SRV records do not point at aliases.
+#[test]
+fn test_lint_rule_target_alias() {
+    let zone = format!("{}@ MX 10 mail\nmail CNAME ns1\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: example.com: MX points at mail.example.com, which is an alias"]
+    );
+    let zone = format!("{}@ MX 10 ns1\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that a delegation to a name server within the child zone comes with glue.
+#[test]
+fn test_lint_rule_missing_glue() {
+    let zone = format!("{}child NS ns.child\nchild NS ns1\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: child.example.com: missing glue for ns.child.example.com"]
+    );
+    let zone = format!(
+        "{}child NS ns.child\nchild NS ns1\nns.child A 192.0.2.3\n",
+        CLEAN_ZONE
+    );
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that NS, MX and SRV records within the zone point at names with addresses.
+#[test]
+fn test_lint_rule_target_without_addresses() {
+    let zone = format!(
+        "{}_sip._udp SRV 0 0 5060 sip\nsip TXT \"none\"\n",
+        CLEAN_ZONE
+    );
+    assert_eq!(
+        lint_findings(&zone),
+        ["error: _sip._udp.example.com: SRV points at sip.example.com, which has no address records"]
+    );
+    let zone = format!(
+        "{}_sip._udp SRV 0 0 5060 sip\nsip AAAA 2001:db8::1\n",
+        CLEAN_ZONE
+    );
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that the records of an RRset share their TTL.
+#[test]
+fn test_lint_rule_rrset_ttls() {
+    let zone = format!("{}www 300 A 192.0.2.3\nwww 600 A 192.0.2.4\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["warning: www.example.com: the TTLs of the A records differ (300 to 600)"]
+    );
+    let zone = format!("{}www 300 A 192.0.2.3\nwww 600 TXT \"fine\"\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that TTLs are at most a week.
+#[test]
+fn test_lint_rule_long_ttl() {
+    let zone = format!("{}www 604801 A 192.0.2.3\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["warning: www.example.com: the A TTL (604801) exceeds a week"]
+    );
+    let zone = format!("{}www 604800 A 192.0.2.3\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that TTLs are at least a minute.
+#[test]
+fn test_lint_rule_short_ttl() {
+    let zone = format!("{}www 59 A 192.0.2.3\n", CLEAN_ZONE);
+    assert_eq!(
+        lint_findings(&zone),
+        ["info: www.example.com: the A TTL (59) is under a minute"]
+    );
+    let zone = format!("{}www 60 A 192.0.2.3\n", CLEAN_ZONE);
+    assert_eq!(lint_findings(&zone), Vec::<String>::new());
+}
+
+/// Validate the rule that the names outside the zone which NS, MX and SRV records point at have
+/// addresses, while a CNAME record may point at a name without any.
+#[test]
+fn test_lint_rule_external_target_without_addresses() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let mut exchanges = vec![];
+    for record_type in [RecordType::AAAA, RecordType::A] {
+        let resolution = MockResolution::new("txt.example.org", record_type, 0)
+            .recursion_desired()
+            .at("192.0.2.53")
+            .recursive_answer("txt.example.org. 300 IN TXT \"no addresses\"")
+            .build()?;
+        exchanges.extend(resolution);
+    }
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.query_options.recursion_desired = true;
+    resolver.servers = vec!["192.0.2.53:53".to_owned()];
+
+    let zone = Zone::parse(&format!("{}@ MX 10 txt.example.org.\n", CLEAN_ZONE), "")?;
+    let findings: Vec<String> = lint_external_names(&zone, &resolver, &mut socket, Some(0))
+        .iter()
+        .map(Finding::to_string)
+        .collect();
+    assert_eq!(
+        findings,
+        ["error: example.com: MX points at txt.example.org, which has no address records"]
+    );
+
+    let zone = Zone::parse(&format!("{}www CNAME txt.example.org.\n", CLEAN_ZONE), "")?;
+    assert!(lint_external_names(&zone, &resolver, &mut socket, Some(0)).is_empty());
+    Ok(())
+}
@@ -0,0 +1,550 @@
+use crate::dkim::{dkim_name, lookup_dkim};
+use crate::dmarc::{dmarc_name, lookup_dmarc, Disposition};
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::record_name::RecordName;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use crate::spf::{expand_spf, lookup_spf, Qualifier, SpfTerm, MAX_SPF_LOOKUPS};
+use std::fmt;
+use std::io::Cursor;
+use std::net::IpAddr;
+
+/// An MX record: a host accepting mail for a domain (RFC 1035, section 3.3.9).
+#[derive(Debug, PartialEq, Clone)]
+pub struct MxRecord {
+    /// The preference of the host, lower ones being tried first.
+    pub preference: u16,
+
+    /// The host name of the mail exchange, without a trailing dot. `.` stands for no host at all
+    /// (RFC 7505).
+    pub exchange: String,
+}
+
+impl MxRecord {
+    /// Parse the data of an MX record, as decompressed when the record was read.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<MxRecord, DnsError> {
+        let Some(preference) = data.get(..2) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let mut cursor = Cursor::new(&data[2..]);
+        let exchange = RecordName::read_and_advance(&mut cursor)?;
+        let Ok(exchange) = String::from_utf8(exchange) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        Ok(MxRecord {
+            preference: u16::from_be_bytes([preference[0], preference[1]]),
+            exchange: match exchange.is_empty() {
+                true => ".".to_owned(),
+                false => exchange,
+            },
+        })
+    }
+
+    /// Whether this is a null MX record, saying the domain accepts no mail at all (RFC 7505).
+    pub fn is_null(&self) -> bool {
+        self.exchange == "."
+    }
+}
+
+/// The outcome of a check of the mail setup of a domain.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Verdict {
+    /// The setup is as it should be.
+    Pass,
+
+    /// The setup works, but falls short of what receivers expect.
+    Warn,
+
+    /// The setup is missing or broken.
+    Fail,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Self::Pass => "pass",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A check of the mail setup of a domain.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MailCheck {
+    /// What was checked: `MX`, `SPF`, `DKIM` or `DMARC`.
+    pub subject: &'static str,
+
+    /// The outcome of the check.
+    pub verdict: Verdict,
+
+    /// What was found, e.g. `mail.example.com (10): 192.0.2.25`.
+    pub detail: String,
+}
+
+impl MailCheck {
+    /// A check of the given subject.
+    ///
+    /// # Arguments
+    /// * `subject`: What was checked.
+    /// * `verdict`: The outcome of the check.
+    /// * `detail`: What was found.
+    fn new(subject: &'static str, verdict: Verdict, detail: String) -> MailCheck {
+        MailCheck {
+            subject,
+            verdict,
+            detail,
+        }
+    }
+}
+
+impl fmt::Display for MailCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.verdict, self.subject, self.detail)
+    }
+}
+
+/// The checks of the mail setup of a domain.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MailReport {
+    /// The domain checked, e.g. `example.com`.
+    pub domain_name: String,
+
+    /// The checks, in the order they were made.
+    pub checks: Vec<MailCheck>,
+}
+
+impl MailReport {
+    /// The number of checks with the given outcome.
+    ///
+    /// # Argument
+    /// * `verdict`: The outcome to count.
+    pub fn count(&self, verdict: Verdict) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.verdict == verdict)
+            .count()
+    }
+
+    /// Whether no check failed.
+    pub fn passed(&self) -> bool {
+        self.count(Verdict::Fail) == 0
+    }
+
+    /// Render the report: the checks, then a summary line.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for check in &self.checks {
+            output.push_str(&format!("{}\n", check));
+        }
+        output.push_str(&format!(
+            "{}: {} ({} passed, {} warnings, {} failed)\n",
+            self.domain_name,
+            match self.passed() {
+                true => "pass",
+                false => "fail",
+            },
+            self.count(Verdict::Pass),
+            self.count(Verdict::Warn),
+            self.count(Verdict::Fail)
+        ));
+        output
+    }
+}
+
+/// Check the mail exchanges of a domain: each must have addresses. A domain without MX records
+/// receives mail at its own addresses (RFC 5321, section 5.1), which is a warning if it has any.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn check_exchanges(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Vec<MailCheck>, DnsError> {
+    let packet = resolver.resolve(socket, domain_name, RecordType::MX, rand_seed)?;
+    let mut exchanges = packet
+        .answers
+        .iter()
+        .filter(|record| record.r_type == RecordType::MX)
+        .map(|record| MxRecord::from_data(&record.data))
+        .collect::<Result<Vec<MxRecord>, DnsError>>()?;
+    exchanges.sort_by_key(|exchange| exchange.preference);
+
+    if exchanges.is_empty() {
+        let check = match resolver.lookup_ip(socket, domain_name, rand_seed) {
+            Ok(addresses) if !addresses.is_empty() => MailCheck::new(
+                "MX",
+                Verdict::Warn,
+                format!(
+                    "no MX records, so mail goes to the addresses of the domain: {}",
+                    join_addresses(&addresses)
+                ),
+            ),
+            _ => MailCheck::new(
+                "MX",
+                Verdict::Fail,
+                "no MX records and no addresses, so the domain cannot receive mail".to_owned(),
+            ),
+        };
+        return Ok(vec![check]);
+    }
+    if let [exchange] = exchanges.as_slice() {
+        if exchange.is_null() {
+            return Ok(vec![MailCheck::new(
+                "MX",
+                Verdict::Pass,
+                "null MX record, the domain accepts no mail".to_owned(),
+            )]);
+        }
+    }
+
+    let mut checks = Vec::new();
+    for exchange in exchanges {
+        let name = format!("{} ({})", exchange.exchange, exchange.preference);
+        if exchange.is_null() {
+            checks.push(MailCheck::new(
+                "MX",
+                Verdict::Fail,
+                format!("{}: null MX record alongside other MX records", name),
+            ));
+            continue;
+        }
+        let check = match resolver.lookup_ip(socket, &exchange.exchange, rand_seed) {
+            Ok(addresses) if !addresses.is_empty() => MailCheck::new(
+                "MX",
+                Verdict::Pass,
+                format!("{}: {}", name, join_addresses(&addresses)),
+            ),
+            Ok(_) => MailCheck::new(
+                "MX",
+                Verdict::Fail,
+                format!("{}: no A or AAAA records", name),
+            ),
+            Err(DnsError::UnknownDomainName) => {
+                MailCheck::new("MX", Verdict::Fail, format!("{}: no such host", name))
+            }
+            Err(error) => MailCheck::new(
+                "MX",
+                Verdict::Fail,
+                format!("{}: could not be resolved: {}", name, error),
+            ),
+        };
+        checks.push(check);
+    }
+    Ok(checks)
+}
+
+/// Join addresses with commas, e.g. `192.0.2.25, 2001:db8::25`.
+///
+/// # Argument
+/// * `addresses`: The addresses.
+fn join_addresses(addresses: &[IpAddr]) -> String {
+    let addresses: Vec<String> = addresses.iter().map(IpAddr::to_string).collect();
+    addresses.join(", ")
+}
+
+/// Check the SPF policy of a domain: it must exist, stay within `MAX_SPF_LOOKUPS` lookups with
+/// those it includes, and end by failing the hosts it does not list.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn check_spf(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> MailCheck {
+    let record = match lookup_spf(resolver, socket, domain_name, rand_seed) {
+        Ok(Some(record)) => record,
+        Ok(None) | Err(DnsError::UnknownDomainName) => {
+            return MailCheck::new("SPF", Verdict::Fail, "no SPF record".to_owned())
+        }
+        Err(DnsError::Spf) => {
+            return MailCheck::new(
+                "SPF",
+                Verdict::Fail,
+                "more than one SPF record, or one which does not parse".to_owned(),
+            )
+        }
+        Err(error) => {
+            return MailCheck::new(
+                "SPF",
+                Verdict::Fail,
+                format!("could not be looked up: {}", error),
+            )
+        }
+    };
+    // Policies without includes or redirects are already complete.
+    let expanded = match record.includes().is_empty() && record.redirect().is_none() {
+        true => Ok(()),
+        false => expand_spf(resolver, socket, domain_name, rand_seed).map(|_| ()),
+    };
+    if let Err(error) = expanded {
+        return MailCheck::new(
+            "SPF",
+            Verdict::Fail,
+            format!(
+                "{}: an included policy is missing or it needs more than {} lookups ({})",
+                record, MAX_SPF_LOOKUPS, error
+            ),
+        );
+    }
+
+    let all = record.terms.iter().find_map(|term| match term {
+        SpfTerm::Mechanism {
+            qualifier, name, ..
+        } if name == "all" => Some(*qualifier),
+        _ => None,
+    });
+    match all {
+        Some(Qualifier::Fail | Qualifier::SoftFail) => {
+            MailCheck::new("SPF", Verdict::Pass, record.to_string())
+        }
+        None if record.redirect().is_some() => {
+            MailCheck::new("SPF", Verdict::Pass, record.to_string())
+        }
+        Some(Qualifier::Pass) => MailCheck::new(
+            "SPF",
+            Verdict::Fail,
+            format!("{}: +all lets any host send mail", record),
+        ),
+        _ => MailCheck::new(
+            "SPF",
+            Verdict::Warn,
+            format!("{}: does not end with -all or ~all", record),
+        ),
+    }
+}
+
+/// Check the DKIM key of a selector: it must exist and not be revoked.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `selector`: The selector, e.g. `mail`.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn check_dkim(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    selector: &str,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> MailCheck {
+    let name = dkim_name(selector, domain_name);
+    match lookup_dkim(resolver, socket, selector, domain_name, rand_seed) {
+        Ok(Some(key)) if key.is_revoked() => {
+            MailCheck::new("DKIM", Verdict::Fail, format!("{}: revoked key", name))
+        }
+        Ok(Some(key)) if key.is_testing() => MailCheck::new(
+            "DKIM",
+            Verdict::Warn,
+            format!("{}: {} key in testing mode (t=y)", name, key.key_type),
+        ),
+        Ok(Some(key)) => MailCheck::new(
+            "DKIM",
+            Verdict::Pass,
+            format!("{}: {} key", name, key.key_type),
+        ),
+        Ok(None) | Err(DnsError::UnknownDomainName) => {
+            MailCheck::new("DKIM", Verdict::Fail, format!("{}: no key", name))
+        }
+        Err(DnsError::Dkim) => MailCheck::new(
+            "DKIM",
+            Verdict::Fail,
+            format!("{}: key record does not parse", name),
+        ),
+        Err(error) => MailCheck::new(
+            "DKIM",
+            Verdict::Fail,
+            format!("{}: could not be looked up: {}", name, error),
+        ),
+    }
+}
+
+/// Check the DMARC policy of a domain: it must exist, and should ask for failing mail to be
+/// quarantined or rejected.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn check_dmarc(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> MailCheck {
+    let name = dmarc_name(domain_name);
+    let policy = match lookup_dmarc(resolver, socket, domain_name, rand_seed) {
+        Ok(Some(policy)) => policy,
+        Ok(None) | Err(DnsError::UnknownDomainName) => {
+            return MailCheck::new("DMARC", Verdict::Fail, format!("{}: no policy", name))
+        }
+        Err(DnsError::Dmarc) => {
+            return MailCheck::new(
+                "DMARC",
+                Verdict::Fail,
+                format!(
+                    "{}: more than one policy, or one which does not parse",
+                    name
+                ),
+            )
+        }
+        Err(error) => {
+            return MailCheck::new(
+                "DMARC",
+                Verdict::Fail,
+                format!("{}: could not be looked up: {}", name, error),
+            )
+        }
+    };
+    let disposition = match policy.policy {
+        Disposition::None => "none",
+        Disposition::Quarantine => "quarantine",
+        Disposition::Reject => "reject",
+    };
+    match (policy.policy, policy.percent) {
+        (Disposition::None, _) => MailCheck::new(
+            "DMARC",
+            Verdict::Warn,
+            format!("{}: p=none only monitors failing mail", name),
+        ),
+        (_, 100) => MailCheck::new(
+            "DMARC",
+            Verdict::Pass,
+            format!("{}: p={}", name, disposition),
+        ),
+        (_, percent) => MailCheck::new(
+            "DMARC",
+            Verdict::Warn,
+            format!(
+                "{}: p={} applies to {}% of failing mail only",
+                name, disposition, percent
+            ),
+        ),
+    }
+}
+
+/// Check the mail setup of a domain: its mail exchanges and their addresses, its SPF policy, its
+/// DMARC policy and, if a selector is given, its DKIM key. DKIM selectors cannot be discovered,
+/// so DKIM is left out otherwise.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `dkim_selector`: The DKIM selector to check the key of, if any, e.g. `mail`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn check_mail(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    dkim_selector: Option<&str>,
+    rand_seed: Option<usize>,
+) -> Result<MailReport, DnsError> {
+    let domain_name = domain_name.trim_end_matches('.');
+    let mut checks = check_exchanges(resolver, socket, domain_name, rand_seed)?;
+    checks.push(check_spf(resolver, socket, domain_name, rand_seed));
+    if let Some(selector) = dkim_selector {
+        checks.push(check_dkim(
+            resolver,
+            socket,
+            selector,
+            domain_name,
+            rand_seed,
+        ));
+    }
+    checks.push(check_dmarc(resolver, socket, domain_name, rand_seed));
+    Ok(MailReport {
+        domain_name: domain_name.to_owned(),
+        checks,
+    })
+}
+
+/// Validate parsing of MX records, including null ones.
+#[test]
+fn test_parsing_mx_record() -> Result<(), DnsError> {
+    let record = MxRecord::from_data(b"\x00\x0a\x04mail\x07example\x03com\x00")?;
+    assert_eq!(record.preference, 10);
+    assert_eq!(record.exchange, "mail.example.com");
+    assert!(!record.is_null());
+    assert!(MxRecord::from_data(b"\x00\x00\x00")?.is_null());
+    assert_eq!(
+        MxRecord::from_data(b"\x00"),
+        Err(DnsError::ReadRecordData(None))
+    );
+    Ok(())
+}
+
+/// Validate checking the mail setup of a domain with a broken exchange, a soft SPF policy, a
+/// DKIM key and a monitoring-only DMARC policy.
+#[test]
+fn test_checking_mail() -> Result<(), DnsError> {
+    use crate::hosts::HostsFile;
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let exchanges = vec![
+        MockResolution::new("example.com", RecordType::MX, 0)
+            .answer(
+                "example.com. 300 IN MX 20 gone.example.com.
+                 example.com. 300 IN MX 10 mail.example.com.",
+            )
+            .build()?,
+        MockResolution::new("gone.example.com", RecordType::AAAA, 0)
+            .name_error()
+            .build()?,
+        MockResolution::new("gone.example.com", RecordType::A, 0)
+            .name_error()
+            .build()?,
+        MockResolution::new("example.com", RecordType::TXT, 0)
+            .answer("example.com. 300 IN TXT \"v=spf1 mx ?all\"")
+            .build()?,
+        MockResolution::new("mail._domainkey.example.com", RecordType::TXT, 0)
+            .answer("mail._domainkey.example.com. 300 IN TXT \"v=DKIM1; k=ed25519; p=AQID\"")
+            .build()?,
+        MockResolution::new("_dmarc.example.com", RecordType::TXT, 0)
+            .answer("_dmarc.example.com. 300 IN TXT \"v=DMARC1; p=none\"")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse("192.0.2.25 mail.example.com\n"));
+    let report = check_mail(
+        &resolver,
+        &mut socket,
+        "example.com.",
+        Some("mail"),
+        Some(0),
+    )?;
+    assert_eq!(
+        report.render(),
+        "pass: MX: mail.example.com (10): 192.0.2.25
+fail: MX: gone.example.com (20): no such host
+warn: SPF: v=spf1 mx ?all: does not end with -all or ~all
+pass: DKIM: mail._domainkey.example.com: ed25519 key
+warn: DMARC: _dmarc.example.com: p=none only monitors failing mail
+example.com: fail (2 passed, 2 warnings, 1 failed)
+"
+    );
+    assert!(!report.passed());
+    Ok(())
+}
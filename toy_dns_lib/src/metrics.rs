@@ -0,0 +1,428 @@
+use crate::header::rcode_name;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Upper bounds of the buckets of latency histograms, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+];
+
+/// Largest HTTP request read from a scraper.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// How long a scraper may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A histogram of durations, with the buckets of `LATENCY_BUCKETS`.
+#[derive(Debug, Default)]
+struct Histogram {
+    /// How many observations fell in each bucket, but not in the previous one. The last count is
+    /// of those above every bucket.
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+
+    /// Sum of the observations, in seconds.
+    sum: f64,
+}
+
+impl Histogram {
+    /// Add an observation.
+    ///
+    /// # Argument
+    /// * `duration`: The observed duration.
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+
+    /// Render the histogram in the Prometheus text format, with cumulative buckets.
+    ///
+    /// # Arguments
+    /// * `output`: Where to render the histogram.
+    /// * `name`: The name of the metric.
+    /// * `help`: What the metric measures.
+    fn render(&self, output: &mut String, name: &str, help: &str) {
+        describe(output, name, "histogram", help);
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            _ = writeln!(output, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        cumulative += self.counts[LATENCY_BUCKETS.len()];
+        _ = writeln!(output, "{}_bucket{{le=\"+Inf\"}} {}", name, cumulative);
+        _ = writeln!(output, "{}_sum {}", name, self.sum);
+        _ = writeln!(output, "{}_count {}", name, cumulative);
+    }
+}
+
+/// Render the HELP and TYPE lines introducing a metric.
+///
+/// # Arguments
+/// * `output`: Where to render the lines.
+/// * `name`: The name of the metric.
+/// * `kind`: The type of the metric, e.g. `counter`.
+/// * `help`: What the metric measures.
+fn describe(output: &mut String, name: &str, kind: &str, help: &str) {
+    _ = writeln!(output, "# HELP {} {}", name, help);
+    _ = writeln!(output, "# TYPE {} {}", name, kind);
+}
+
+/// The values of every metric.
+#[derive(Debug, Default)]
+struct Registry {
+    /// Queries answered, by query type and response code.
+    queries: BTreeMap<(String, String), u64>,
+
+    /// Time taken to answer queries.
+    request_duration: Histogram,
+
+    /// Lookups answered from a cache.
+    cache_hits: u64,
+
+    /// Lookups a cache could not answer.
+    cache_misses: u64,
+
+    /// Entries a cache dropped because they expired.
+    cache_evictions: u64,
+
+    /// Lookups answered from a cache with a negative answer.
+    cache_negative_hits: u64,
+
+    /// Expired entries a cache served as they could not be refreshed.
+    cache_stale_serves: u64,
+
+    /// Time taken by servers to respond to queries sent upstream.
+    upstream_rtt: Histogram,
+
+    /// Queries sent upstream which went unanswered.
+    upstream_timeouts: u64,
+}
+
+/// Counters and histograms about the queries a server answers and those it sends upstream, to be
+/// scraped by Prometheus. Clones share the same values, so that the server, its resolver and its
+/// forwarder all add to them. See `Server::set_metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Values are only ever updated
+/// whole, so they remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl Metrics {
+    /// Count a query answered for a client.
+    ///
+    /// # Arguments
+    /// * `record_type`: The type in question, e.g. `AAAA`.
+    /// * `rcode`: The response code of the response.
+    /// * `elapsed`: How long it took to answer.
+    pub fn record_query(&self, record_type: &str, rcode: u16, elapsed: Duration) {
+        let mut registry = lock(&self.registry);
+        *registry
+            .queries
+            .entry((record_type.to_owned(), rcode_name(rcode)))
+            .or_default() += 1;
+        registry.request_duration.observe(elapsed);
+    }
+
+    /// Count a lookup in a cache.
+    ///
+    /// # Argument
+    /// * `hit`: Whether the cache had the answer.
+    pub fn record_cache_lookup(&self, hit: bool) {
+        let mut registry = lock(&self.registry);
+        match hit {
+            true => registry.cache_hits += 1,
+            false => registry.cache_misses += 1,
+        }
+    }
+
+    /// Count an entry a cache dropped because it expired.
+    pub fn record_cache_eviction(&self) {
+        lock(&self.registry).cache_evictions += 1;
+    }
+
+    /// Count a lookup a cache answered with a negative answer, i.e. NXDOMAIN or NODATA.
+    pub fn record_cache_negative_hit(&self) {
+        lock(&self.registry).cache_negative_hits += 1;
+    }
+
+    /// Count an expired entry a cache served as it could not be refreshed.
+    pub fn record_cache_stale_serve(&self) {
+        lock(&self.registry).cache_stale_serves += 1;
+    }
+
+    /// Count a response received from a server.
+    ///
+    /// # Argument
+    /// * `elapsed`: How long the server took to respond.
+    pub fn record_upstream_response(&self, elapsed: Duration) {
+        lock(&self.registry).upstream_rtt.observe(elapsed);
+    }
+
+    /// Count a query to a server which went unanswered.
+    pub fn record_upstream_timeout(&self) {
+        lock(&self.registry).upstream_timeouts += 1;
+    }
+
+    /// Render every metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let registry = lock(&self.registry);
+        let mut output = String::new();
+
+        describe(
+            &mut output,
+            "toy_dns_queries_total",
+            "counter",
+            "Queries answered, by type and RCODE.",
+        );
+        for ((record_type, rcode), count) in &registry.queries {
+            _ = writeln!(
+                output,
+                "toy_dns_queries_total{{type=\"{}\",rcode=\"{}\"}} {}",
+                record_type, rcode, count
+            );
+        }
+        registry.request_duration.render(
+            &mut output,
+            "toy_dns_request_duration_seconds",
+            "Time taken to answer queries.",
+        );
+
+        let lookups = registry.cache_hits + registry.cache_misses;
+        let ratio = match lookups {
+            0 => 0.0,
+            _ => registry.cache_hits as f64 / lookups as f64,
+        };
+        for (name, kind, help, value) in [
+            (
+                "toy_dns_cache_hits_total",
+                "counter",
+                "Lookups answered from the cache.",
+                registry.cache_hits as f64,
+            ),
+            (
+                "toy_dns_cache_misses_total",
+                "counter",
+                "Lookups the cache could not answer.",
+                registry.cache_misses as f64,
+            ),
+            (
+                "toy_dns_cache_evictions_total",
+                "counter",
+                "Cache entries dropped because they expired.",
+                registry.cache_evictions as f64,
+            ),
+            (
+                "toy_dns_cache_negative_hits_total",
+                "counter",
+                "Lookups answered from the cache with NXDOMAIN or NODATA.",
+                registry.cache_negative_hits as f64,
+            ),
+            (
+                "toy_dns_cache_stale_serves_total",
+                "counter",
+                "Expired cache entries served as they could not be refreshed.",
+                registry.cache_stale_serves as f64,
+            ),
+            (
+                "toy_dns_cache_hit_ratio",
+                "gauge",
+                "Share of lookups answered from the cache.",
+                ratio,
+            ),
+        ] {
+            describe(&mut output, name, kind, help);
+            _ = writeln!(output, "{} {}", name, value);
+        }
+
+        registry.upstream_rtt.render(
+            &mut output,
+            "toy_dns_upstream_rtt_seconds",
+            "Time taken by servers to respond to queries sent upstream.",
+        );
+        describe(
+            &mut output,
+            "toy_dns_upstream_timeouts_total",
+            "counter",
+            "Queries sent upstream which went unanswered.",
+        );
+        _ = writeln!(
+            output,
+            "toy_dns_upstream_timeouts_total {}",
+            registry.upstream_timeouts
+        );
+        output
+    }
+
+    /// Answer HTTP requests for `/metrics` on the listener until accepting connections fails.
+    /// Requests are handled one at a time, which is plenty for scrapers.
+    ///
+    /// # Argument
+    /// * `listener`: The bound listener to serve on.
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(accept_error) => {
+                    warn!("Stopped serving metrics: {}", accept_error);
+                    return;
+                }
+            };
+            if let Err(respond_error) = self.respond(stream) {
+                info!("Could not serve metrics: {}", respond_error);
+            }
+        }
+    }
+
+    /// Read an HTTP request from a scraper and respond to it.
+    ///
+    /// # Argument
+    /// * `stream`: The connection to the scraper.
+    fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let size = stream.read(&mut buf)?;
+            if size == 0 || request.len() + size > MAX_REQUEST_SIZE {
+                break;
+            }
+            request.extend_from_slice(&buf[..size]);
+        }
+        let (status, body) = response(&String::from_utf8_lossy(&request), self);
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes())
+    }
+}
+
+impl PartialEq for Metrics {
+    /// Metrics are equal when they share the same values.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.registry, &other.registry)
+    }
+}
+
+/// The status line and body of the response to an HTTP request.
+///
+/// # Arguments
+/// * `request`: The request, from its request line on.
+/// * `metrics`: The metrics to respond with.
+fn response(request: &str, metrics: &Metrics) -> (&'static str, String) {
+    let mut request_line = request.lines().next().unwrap_or_default().split(' ');
+    match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", metrics.render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_owned()),
+    }
+}
+
+/// Validate rendering of the metrics in the Prometheus text format.
+#[test]
+fn test_rendering_metrics() {
+    use crate::header::{RCODE_NAME_ERROR, RCODE_NO_ERROR};
+
+    let metrics = Metrics::default();
+    let shared = metrics.clone();
+    assert_eq!(metrics, shared);
+    assert_ne!(metrics, Metrics::default());
+
+    shared.record_query("A", RCODE_NO_ERROR, Duration::from_micros(700));
+    shared.record_query("A", RCODE_NO_ERROR, Duration::from_millis(30));
+    shared.record_query("AAAA", RCODE_NAME_ERROR, Duration::from_secs(3));
+    shared.record_cache_lookup(true);
+    shared.record_cache_lookup(true);
+    shared.record_cache_lookup(true);
+    shared.record_cache_lookup(false);
+    shared.record_cache_eviction();
+    shared.record_cache_negative_hit();
+    shared.record_cache_stale_serve();
+    shared.record_cache_stale_serve();
+    shared.record_upstream_response(Duration::from_millis(20));
+    shared.record_upstream_timeout();
+
+    let output = metrics.render();
+    for line in [
+        "# TYPE toy_dns_queries_total counter",
+        "toy_dns_queries_total{type=\"A\",rcode=\"NOERROR\"} 2",
+        "toy_dns_queries_total{type=\"AAAA\",rcode=\"NXDOMAIN\"} 1",
+        "# TYPE toy_dns_request_duration_seconds histogram",
+        "toy_dns_request_duration_seconds_bucket{le=\"0.0005\"} 0",
+        "toy_dns_request_duration_seconds_bucket{le=\"0.001\"} 1",
+        "toy_dns_request_duration_seconds_bucket{le=\"0.025\"} 1",
+        "toy_dns_request_duration_seconds_bucket{le=\"0.05\"} 2",
+        "toy_dns_request_duration_seconds_bucket{le=\"2.5\"} 2",
+        "toy_dns_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+        "toy_dns_request_duration_seconds_count 3",
+        "toy_dns_cache_hits_total 3",
+        "toy_dns_cache_misses_total 1",
+        "toy_dns_cache_evictions_total 1",
+        "toy_dns_cache_negative_hits_total 1",
+        "toy_dns_cache_stale_serves_total 2",
+        "toy_dns_cache_hit_ratio 0.75",
+        "toy_dns_upstream_rtt_seconds_bucket{le=\"0.025\"} 1",
+        "toy_dns_upstream_rtt_seconds_count 1",
+        "toy_dns_upstream_timeouts_total 1",
+    ] {
+        assert!(output.lines().any(|rendered| rendered == line), "{}", line);
+    }
+    assert!(output.contains("\ntoy_dns_request_duration_seconds_sum 3.03"));
+    assert_eq!(
+        Metrics::default()
+            .render()
+            .lines()
+            .find(|line| line.starts_with("toy_dns_cache_hit_ratio")),
+        Some("toy_dns_cache_hit_ratio 0")
+    );
+}
+
+/// Validate responses to scrapers.
+#[test]
+fn test_serving_metrics() -> std::io::Result<()> {
+    let metrics = Metrics::default();
+    metrics.record_upstream_timeout();
+    assert_eq!(
+        response("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n", &metrics),
+        ("200 OK", metrics.render())
+    );
+    assert_eq!(
+        response("GET / HTTP/1.1\r\n\r\n", &metrics).0,
+        "404 Not Found"
+    );
+    assert_eq!(
+        response("POST /metrics HTTP/1.1\r\n\r\n", &metrics).0,
+        "405 Method Not Allowed"
+    );
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let server = metrics.clone();
+    std::thread::spawn(move || server.serve(listener));
+    let mut stream = TcpStream::connect(address)?;
+    stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("toy_dns_upstream_timeouts_total 1\n"));
+    Ok(())
+}
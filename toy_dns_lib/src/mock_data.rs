@@ -1,332 +1,521 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::header::{
+    Header, FLAG_AUTHORITATIVE, FLAG_RECURSION_AVAILABLE, FLAG_RECURSION_DESIRED, FLAG_RESPONSE,
+    RCODE_NAME_ERROR, RCODE_REFUSED,
+};
+use crate::packet::Packet;
+use crate::query::{server_address, Query, QueryOptions, CLASS_IN};
+use crate::question::Question;
+use crate::record::{Record, RecordType};
+use crate::root_servers::RootServer;
 use crate::socket::{MockData, MockKey};
+use crate::zone::Zone;
+use data_encoding::HEXLOWER_PERMISSIVE;
+use std::fmt::Write;
+use std::net::IpAddr;
 
 /*
-Captured data in this file can be re-generated by runnig toy_dns with --verbose and transforming
-the output into the format below. All 1024 bytes should be kept for both simplicity of maintenance
-and the ability to retain the call to copy_from_slice() which writes to a buffer of 1024 length.
+Captured data lives in fixtures, in the format written by `to_fixture()`. Fixtures can be
+re-generated by running toy_dns with --record-fixture, which records every exchange with the
+servers queried. Responses are kept as received, in full.
 
 During capture, toy_dns was run with random seed of 0 which can be specified with --rand-seed 0.
  */
 
-pub static CAPTURED_DATA_FOR_TWITTER: &[(MockKey, MockData)] = &[
-    (
-        MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 7, 116, 119, 105, 116, 116, 101, 114, 3, 99,
-                111, 109, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "192.58.128.30:53",
-        },
-        MockData {
-            data: &[
-                59, 108, 130, 0, 0, 1, 0, 0, 0, 13, 0, 11, 7, 116, 119, 105, 116, 116, 101, 114, 3,
-                99, 111, 109, 0, 0, 1, 0, 1, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 20, 1, 101, 12,
-                103, 116, 108, 100, 45, 115, 101, 114, 118, 101, 114, 115, 3, 110, 101, 116, 0,
-                192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 98, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2,
-                163, 0, 0, 4, 1, 106, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 109,
-                192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 105, 192, 43, 192, 20, 0, 2,
-                0, 1, 0, 2, 163, 0, 0, 4, 1, 102, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4,
-                1, 97, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 103, 192, 43, 192, 20,
-                0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 104, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0,
-                0, 4, 1, 108, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 107, 192, 43,
-                192, 20, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 99, 192, 43, 192, 20, 0, 2, 0, 1, 0, 2,
-                163, 0, 0, 4, 1, 100, 192, 43, 192, 41, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 12,
-                94, 30, 192, 41, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 2, 28, 161, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 48, 192, 73, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 33, 14, 30,
-                192, 73, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 3, 35, 29, 0, 0, 0, 0, 0, 0,
-                0, 2, 0, 48, 192, 89, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 48, 79, 30, 192, 89, 0,
-                28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 2, 112, 148, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                48, 192, 105, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 55, 83, 30, 192, 105, 0, 28, 0,
-                1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 1, 177, 249, 0, 0, 0, 0, 0, 0, 0, 0, 0, 48, 192,
-                121, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 43, 172, 30, 192, 121, 0, 28, 0, 1, 0, 2,
-                163, 0, 0, 16, 32, 1, 5, 3, 57, 193, 0, 0, 0, 0, 0, 0, 0, 0, 0, 48, 192, 137, 0, 1,
-                0, 1, 0, 2, 163, 0, 0, 4, 192, 35, 51, 30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ],
-        },
-    ),
-    (
-        MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 7, 116, 119, 105, 116, 116, 101, 114, 3, 99,
-                111, 109, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "192.12.94.30:53",
-        },
-        MockData {
-            data: &[
-                59, 108, 128, 0, 0, 1, 0, 0, 0, 8, 0, 0, 7, 116, 119, 105, 116, 116, 101, 114, 3,
-                99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 19, 1, 97, 3,
-                114, 48, 54, 7, 116, 119, 116, 114, 100, 110, 115, 3, 110, 101, 116, 0, 192, 12, 0,
-                2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 98, 192, 43, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0,
-                4, 1, 99, 192, 43, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 100, 192, 43, 192,
-                12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 8, 1, 98, 3, 117, 48, 54, 192, 47, 192, 12, 0, 2,
-                0, 1, 0, 2, 163, 0, 0, 4, 1, 97, 192, 122, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4,
-                1, 99, 192, 122, 192, 12, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 100, 192, 122, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0,
-            ],
-        },
-    ),
-    (
-        MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "192.58.128.30:53",
-        },
-        MockData {
-            data: &[
-                59, 108, 130, 0, 0, 1, 0, 0, 0, 13, 0, 11, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1, 192, 26, 0, 2, 0, 1, 0, 2,
-                163, 0, 0, 17, 1, 101, 12, 103, 116, 108, 100, 45, 115, 101, 114, 118, 101, 114,
-                115, 192, 26, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 102, 192, 49, 192, 26, 0,
-                2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 109, 192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0,
-                4, 1, 105, 192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 106, 192, 49, 192,
-                26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 98, 192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163,
-                0, 0, 4, 1, 97, 192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 99, 192, 49,
-                192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 107, 192, 49, 192, 26, 0, 2, 0, 1, 0,
-                2, 163, 0, 0, 4, 1, 104, 192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 108,
-                192, 49, 192, 26, 0, 2, 0, 1, 0, 2, 163, 0, 0, 4, 1, 103, 192, 49, 192, 26, 0, 2,
-                0, 1, 0, 2, 163, 0, 0, 4, 1, 100, 192, 49, 192, 47, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4,
-                192, 12, 94, 30, 192, 47, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 2, 28, 161,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 48, 192, 76, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 35,
-                51, 30, 192, 76, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 3, 212, 20, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 48, 192, 92, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 55, 83, 30,
-                192, 92, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 1, 177, 249, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 48, 192, 108, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 43, 172, 30, 192, 108,
-                0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 3, 57, 193, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                48, 192, 124, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 48, 79, 30, 192, 124, 0, 28, 0,
-                1, 0, 2, 163, 0, 0, 16, 32, 1, 5, 2, 112, 148, 0, 0, 0, 0, 0, 0, 0, 0, 0, 48, 192,
-                140, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 192, 33, 14, 30, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ],
-        },
-    ),
-    (
-        MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "192.12.94.30:53",
-        },
-        MockData {
-            data: &[
-                59, 108, 128, 0, 0, 1, 0, 0, 0, 8, 0, 3, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1, 192, 18, 0, 2, 0, 1, 0, 2,
-                163, 0, 0, 22, 6, 110, 115, 45, 51, 55, 48, 9, 97, 119, 115, 100, 110, 115, 45, 52,
-                54, 3, 99, 111, 109, 0, 192, 18, 0, 2, 0, 1, 0, 2, 163, 0, 0, 19, 6, 110, 115, 45,
-                57, 55, 53, 9, 97, 119, 115, 100, 110, 115, 45, 53, 55, 192, 26, 192, 18, 0, 2, 0,
-                1, 0, 2, 163, 0, 0, 25, 7, 110, 115, 45, 49, 55, 48, 48, 9, 97, 119, 115, 100, 110,
-                115, 45, 50, 48, 2, 99, 111, 2, 117, 107, 0, 192, 18, 0, 2, 0, 1, 0, 2, 163, 0, 0,
-                23, 7, 110, 115, 45, 49, 52, 53, 48, 9, 97, 119, 115, 100, 110, 115, 45, 53, 51, 3,
-                111, 114, 103, 0, 192, 18, 0, 2, 0, 1, 0, 2, 163, 0, 0, 19, 7, 101, 100, 110, 115,
-                49, 48, 49, 8, 117, 108, 116, 114, 97, 100, 110, 115, 192, 26, 192, 18, 0, 2, 0, 1,
-                0, 2, 163, 0, 0, 19, 7, 101, 100, 110, 115, 49, 48, 49, 8, 117, 108, 116, 114, 97,
-                100, 110, 115, 192, 64, 192, 18, 0, 2, 0, 1, 0, 2, 163, 0, 0, 19, 7, 101, 100, 110,
-                115, 49, 48, 49, 8, 117, 108, 116, 114, 97, 100, 110, 115, 192, 167, 192, 18, 0, 2,
-                0, 1, 0, 2, 163, 0, 0, 22, 7, 101, 100, 110, 115, 49, 48, 49, 8, 117, 108, 116,
-                114, 97, 100, 110, 115, 3, 98, 105, 122, 0, 192, 81, 0, 1, 0, 1, 0, 2, 163, 0, 0,
-                4, 205, 251, 195, 207, 192, 184, 0, 1, 0, 1, 0, 2, 163, 0, 0, 4, 204, 74, 110, 101,
-                192, 184, 0, 28, 0, 1, 0, 2, 163, 0, 0, 16, 38, 16, 0, 161, 16, 20, 0, 0, 0, 0, 0,
-                0, 0, 0, 2, 101, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ],
-        },
-    ),
-    (
+/// Each query sent while resolving twitter.com, the server it was sent to and its response.
+const CAPTURED_DATA_FOR_TWITTER: &str = include_str!("../fixtures/twitter.txt");
+
+/// The exchanges of resolving twitter.com from the root servers, as mock socket responses.
+pub fn captured_data_for_twitter() -> Vec<(MockKey, MockData)> {
+    parse_fixture(CAPTURED_DATA_FOR_TWITTER).unwrap()
+}
+
+/// Builds the exchanges of a resolution from the root servers out of a description of each hop,
+/// so that tests need not spell out the bytes on the wire. The resolution starts at the root
+/// server picked by the seed, and each referral leads to the name server given as its glue, e.g.
+/// `.referral("com", "a.gtld-servers.net", "192.5.6.30").answer("example.com. 300 IN A
+/// 192.0.2.1")` describes the root referring to .com, whose name server then answers.
+pub struct MockResolution {
+    /// The query every server is sent, as it is serialized with the seed.
+    query_bytes: Result<Vec<u8>, DnsError>,
+
+    /// The seed the query is serialized with.
+    query_seed: Option<usize>,
+
+    /// The options the query is serialized with, e.g. set by `recursion_desired()`.
+    query_options: QueryOptions,
+
+    /// The question of the query, repeated in the responses.
+    question: Question,
+
+    /// The server the next hop is asked.
+    server: String,
+
+    /// The exchanges built so far.
+    exchanges: Vec<(MockKey, MockData)>,
+
+    /// The first error met while building the exchanges, returned by `build()`.
+    error: Option<DnsError>,
+}
+
+impl MockResolution {
+    /// Start describing the resolution of the given name and record type.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name to resolve.
+    /// * `record_type`: The type of records to resolve.
+    /// * `rand_seed`: The seed the resolution runs with, which picks the root server and the ID
+    ///   of the query.
+    pub fn new(domain_name: &str, record_type: RecordType, rand_seed: usize) -> MockResolution {
+        MockResolution {
+            query_bytes: Query::new(domain_name, record_type).serialize(Some(rand_seed)),
+            query_seed: Some(rand_seed),
+            query_options: QueryOptions::default(),
+            question: Question {
+                name: DnsName::from(domain_name),
+                q_type: record_type,
+                q_class: CLASS_IN,
+            },
+            server: server_address(RootServer::random(Some(rand_seed)).0),
+            exchanges: vec![],
+            error: None,
+        }
+    }
+
+    /// The server of the current hop refers the resolution to a name server of the given zone.
+    /// The next hop asks that name server, at the address given as its glue.
+    ///
+    /// # Arguments
+    /// * `zone`: The zone the resolution is referred to, e.g. "com".
+    /// * `name_server`: The name of the name server of the zone.
+    /// * `glue`: The IPv4 or IPv6 address of the name server.
+    pub fn referral(mut self, zone: &str, name_server: &str, glue: &str) -> MockResolution {
+        let zone = zone.trim_end_matches('.');
+        let name_server = name_server.trim_end_matches('.');
+        let glue_type = match glue.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => "AAAA",
+            _ => "A",
+        };
+        let records = format!(
+            "{}. 172800 IN NS {}.\n{}. 172800 IN {} {}\n",
+            zone, name_server, name_server, glue_type, glue
+        );
+        let server = server_address(glue);
+        match Zone::parse(&records, ".") {
+            Ok(zone) => {
+                let (name_servers, glue) = zone.records.split_at(1);
+                self.respond(0, vec![], name_servers.to_vec(), glue.to_vec());
+            }
+            Err(error) => self.fail(error),
+        }
+        self.server = server;
+        self
+    }
+
+    /// The server of the current hop refers the resolution to the name servers of a zone, given
+    /// as NS records along with the A and AAAA records of their glue, e.g. to refer to several
+    /// name servers at once. The server of the next hop is left to `at()`.
+    ///
+    /// # Argument
+    /// * `records`: The records in presentation format, one per line, with absolute names. Lines
+    ///   may be indented.
+    pub fn delegation(mut self, records: &str) -> MockResolution {
+        let records: Vec<&str> = records.lines().map(str::trim).collect();
+        match Zone::parse(&records.join("\n"), ".") {
+            Ok(zone) => {
+                let (name_servers, glue) = zone
+                    .records
+                    .into_iter()
+                    .partition(|record| record.r_type == RecordType::NS);
+                self.respond(0, vec![], name_servers, glue);
+            }
+            Err(error) => self.fail(error),
+        }
+        self
+    }
+
+    /// The next hop asks the server at the given address, e.g. to describe the response of a
+    /// server asked directly with `Query::ask()`.
+    ///
+    /// # Argument
+    /// * `address`: The IPv4 or IPv6 address of the server.
+    pub fn at(mut self, address: &str) -> MockResolution {
+        self.server = server_address(address);
+        self
+    }
+
+    /// The query is sent with the RD bit set, as it is to a recursive resolver, e.g. with
+    /// `Query::ask()` and `QueryOptions::recursion_desired`.
+    pub fn recursion_desired(mut self) -> MockResolution {
+        self.query_options.recursion_desired = true;
+        self.reserialize();
+        self
+    }
+
+    /// The query is sent with the DO bit set, as it is to have servers include DNSSEC records,
+    /// e.g. with `QueryOptions::dnssec_ok`.
+    pub fn dnssec_ok(mut self) -> MockResolution {
+        self.query_options.dnssec_ok = true;
+        self.reserialize();
+        self
+    }
+
+    /// The server of the current hop resolves the query on the client's behalf and answers with
+    /// the given records, as a recursive resolver does: with the RA bit set rather than AA.
+    ///
+    /// # Argument
+    /// * `records`: The records in presentation format, one per line, with absolute names. Lines
+    ///   may be indented.
+    pub fn recursive_answer(mut self, records: &str) -> MockResolution {
+        let records: Vec<&str> = records.lines().map(str::trim).collect();
+        match Zone::parse(&records.join("\n"), ".") {
+            Ok(zone) => self.respond(
+                FLAG_RECURSION_DESIRED | FLAG_RECURSION_AVAILABLE,
+                zone.records,
+                vec![],
+                vec![],
+            ),
+            Err(error) => self.fail(error),
+        }
+        self
+    }
+
+    /// The server of the current hop refuses the query (REFUSED).
+    pub fn refused(mut self) -> MockResolution {
+        self.respond(RCODE_REFUSED, vec![], vec![], vec![]);
+        self
+    }
+
+    /// The server of the current hop answers with the given records.
+    ///
+    /// # Argument
+    /// * `records`: The records in presentation format, one per line, with absolute names, e.g.
+    ///   "example.com. 300 IN A 192.0.2.1". Lines may be indented.
+    pub fn answer(mut self, records: &str) -> MockResolution {
+        let records: Vec<&str> = records.lines().map(str::trim).collect();
+        match Zone::parse(&records.join("\n"), ".") {
+            Ok(zone) => self.respond(FLAG_AUTHORITATIVE, zone.records, vec![], vec![]),
+            Err(error) => self.fail(error),
+        }
+        self
+    }
+
+    /// The server of the current hop answers that the name does not exist (NXDOMAIN).
+    pub fn name_error(mut self) -> MockResolution {
+        self.respond(
+            FLAG_AUTHORITATIVE | RCODE_NAME_ERROR,
+            vec![],
+            vec![],
+            vec![],
+        );
+        self
+    }
+
+    /// The server of the current hop answers that the name does not exist (NXDOMAIN), along with
+    /// the given records in the authority section, e.g. the NSEC or NSEC3 records proving it.
+    ///
+    /// # Argument
+    /// * `records`: The records in presentation format, one per line, with absolute names. Lines
+    ///   may be indented.
+    pub fn name_error_with(mut self, records: &str) -> MockResolution {
+        let records: Vec<&str> = records.lines().map(str::trim).collect();
+        match Zone::parse(&records.join("\n"), ".") {
+            Ok(zone) => self.respond(
+                FLAG_AUTHORITATIVE | RCODE_NAME_ERROR,
+                vec![],
+                zone.records,
+                vec![],
+            ),
+            Err(error) => self.fail(error),
+        }
+        self
+    }
+
+    /// The server of the current hop answers that the name has no records of the type (NODATA),
+    /// along with the given records in the authority section, e.g. the NSEC or NSEC3 record
+    /// proving it.
+    ///
+    /// # Argument
+    /// * `records`: The records in presentation format, one per line, with absolute names. Lines
+    ///   may be indented.
+    pub fn no_data_with(mut self, records: &str) -> MockResolution {
+        let records: Vec<&str> = records.lines().map(str::trim).collect();
+        match Zone::parse(&records.join("\n"), ".") {
+            Ok(zone) => self.respond(FLAG_AUTHORITATIVE, vec![], zone.records, vec![]),
+            Err(error) => self.fail(error),
+        }
+        self
+    }
+
+    /// The exchanges described, to register with a mock socket, or the first error met while
+    /// building them.
+    pub fn build(self) -> Result<Vec<(MockKey, MockData)>, DnsError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.exchanges),
+        }
+    }
+
+    /// Add the exchange of the current hop, in which the server responds with the given records.
+    ///
+    /// # Arguments
+    /// * `flags`: The flags of the response besides QR.
+    /// * `answers`: The records of the answer section.
+    /// * `authorities`: The records of the authority section.
+    /// * `additionals`: The records of the additional section.
+    fn respond(
+        &mut self,
+        flags: u16,
+        answers: Vec<Record>,
+        authorities: Vec<Record>,
+        additionals: Vec<Record>,
+    ) {
+        let query_bytes = match &self.query_bytes {
+            Ok(query_bytes) => query_bytes.clone(),
+            Err(_) => return self.fail(DnsError::QuerySerialization),
+        };
+        let response = Packet {
+            header: Header {
+                id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+                flags: FLAG_RESPONSE | flags,
+                ..Default::default()
+            },
+            questions: vec![self.question.clone()],
+            answers,
+            authorities,
+            additionals,
+        };
+        match response.serialize() {
+            Ok(data) => self.exchanges.push((
+                MockKey {
+                    query_bytes,
+                    server_ip: self.server.clone(),
+                },
+                MockData { data },
+            )),
+            Err(error) => self.fail(error),
+        }
+    }
+
+    /// Serialize the query again with the current options.
+    fn reserialize(&mut self) {
+        let query = Query {
+            domain_name: self.question.name.to_string().into(),
+            record_type: self.question.q_type,
+            options: self.query_options.clone(),
+        };
+        self.query_bytes = query.serialize(self.query_seed);
+    }
+
+    /// Keep the first error met, for `build()` to return.
+    ///
+    /// # Argument
+    /// * `error`: The error.
+    fn fail(&mut self, error: DnsError) {
+        self.error.get_or_insert(error);
+    }
+}
+
+/// Write exchanges as a fixture: one exchange per line, with the server, the query and the
+/// response in hex separated by spaces. Lost responses are written as `-`.
+///
+/// # Argument
+/// * `exchanges`: The exchanges, e.g. recorded with a `RecordingSocket`.
+pub fn to_fixture(exchanges: &[(MockKey, MockData)]) -> String {
+    let mut fixture =
+        "# Each line is an exchange: <server> <query in hex> <response in hex>.\n".to_owned();
+    for (key, value) in exchanges {
+        let response = match value.data.is_empty() {
+            true => "-".to_owned(),
+            false => HEXLOWER_PERMISSIVE.encode(&value.data),
+        };
+        _ = writeln!(
+            fixture,
+            "{} {} {}",
+            key.server_ip,
+            HEXLOWER_PERMISSIVE.encode(&key.query_bytes),
+            response
+        );
+    }
+    fixture
+}
+
+/// Read the exchanges of a fixture written by `to_fixture()`. Empty lines and lines starting with
+/// # are skipped.
+///
+/// # Argument
+/// * `fixture`: The fixture.
+pub fn parse_fixture(fixture: &str) -> Result<Vec<(MockKey, MockData)>, DnsError> {
+    let mut exchanges = vec![];
+    for line in fixture.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [server_ip, query, response] = fields[..] else {
+            return Err(DnsError::Fixture);
+        };
+        let Ok(query_bytes) = HEXLOWER_PERMISSIVE.decode(query.as_bytes()) else {
+            return Err(DnsError::Fixture);
+        };
+        let data = match response {
+            "-" => vec![],
+            response => match HEXLOWER_PERMISSIVE.decode(response.as_bytes()) {
+                Ok(data) => data,
+                Err(_) => return Err(DnsError::Fixture),
+            },
+        };
+        exchanges.push((
+            MockKey {
+                query_bytes,
+                server_ip: server_ip.to_owned(),
+            },
+            MockData { data },
+        ));
+    }
+    Ok(exchanges)
+}
+
+/// Write exchanges as the Rust source of a static array of (query, server, response) tuples, for
+/// tests which would rather embed their data than read a fixture. Lost responses are empty.
+///
+/// # Arguments
+/// * `name`: The name of the static.
+/// * `exchanges`: The exchanges, e.g. recorded with a `RecordingSocket`.
+pub fn to_rust_source(name: &str, exchanges: &[(MockKey, MockData)]) -> String {
+    /// Write bytes as the lines of an array literal.
+    fn write_bytes(source: &mut String, bytes: &[u8]) {
+        source.push_str("        &[\n");
+        for chunk in bytes.chunks(16) {
+            let chunk: Vec<String> = chunk.iter().map(u8::to_string).collect();
+            _ = writeln!(source, "            {},", chunk.join(", "));
+        }
+        source.push_str("        ],\n");
+    }
+
+    let mut source = format!("static {}: &[(&[u8], &str, &[u8])] = &[\n", name);
+    for (key, value) in exchanges {
+        source.push_str("    (\n");
+        write_bytes(&mut source, &key.query_bytes);
+        _ = writeln!(source, "        {:?},", key.server_ip);
+        write_bytes(&mut source, &value.data);
+        source.push_str("    ),\n");
+    }
+    source.push_str("];\n");
+    source
+}
+
+/// Validate that fixtures are read back as they were written, and that malformed lines are
+/// refused.
+#[test]
+fn test_fixture_round_trip() -> Result<(), DnsError> {
+    let exchanges = vec![
+        (
+            MockKey {
+                query_bytes: vec![59, 108, 0, 0],
+                server_ip: "192.0.2.53:53".to_owned(),
+            },
+            MockData { data: vec![] },
+        ),
+        (
+            MockKey {
+                query_bytes: vec![59, 108, 0, 0],
+                server_ip: "[2001:db8::53]:53".to_owned(),
+            },
+            MockData {
+                data: vec![59, 108, 128, 0],
+            },
+        ),
+    ];
+    let fixture = to_fixture(&exchanges);
+    assert!(fixture.ends_with("192.0.2.53:53 3b6c0000 -\n[2001:db8::53]:53 3b6c0000 3b6c8000\n"));
+    let parsed = parse_fixture(&fixture)?;
+    assert_eq!(parsed.len(), 2);
+    for ((key, value), (parsed_key, parsed_value)) in exchanges.iter().zip(&parsed) {
+        assert_eq!(key, parsed_key);
+        assert_eq!(value.data, parsed_value.data);
+    }
+
+    assert_eq!(
+        parse_fixture("192.0.2.53:53 3b6c0000").err(),
+        Some(DnsError::Fixture)
+    );
+    assert_eq!(
+        parse_fixture("192.0.2.53:53 3b6c0000 xyz").err(),
+        Some(DnsError::Fixture)
+    );
+    assert_eq!(captured_data_for_twitter().len(), 6);
+    Ok(())
+}
+
+/// Validate writing exchanges as Rust source.
+#[test]
+fn test_to_rust_source() {
+    let exchanges = [(
         MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "205.251.195.207:53",
+            query_bytes: vec![59, 108],
+            server_ip: "192.0.2.53:53".to_owned(),
         },
         MockData {
-            data: &[
-                59, 108, 132, 0, 0, 1, 0, 1, 0, 8, 0, 0, 1, 97, 3, 114, 48, 54, 7, 116, 119, 116,
-                114, 100, 110, 115, 3, 110, 101, 116, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 0, 0,
-                111, 0, 4, 205, 251, 192, 179, 192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 22, 7, 101,
-                100, 110, 115, 49, 48, 49, 8, 117, 108, 116, 114, 97, 100, 110, 115, 3, 98, 105,
-                122, 0, 192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 22, 7, 101, 100, 110, 115, 49, 48,
-                49, 8, 117, 108, 116, 114, 97, 100, 110, 115, 3, 99, 111, 109, 0, 192, 18, 0, 2, 0,
-                1, 0, 1, 81, 128, 0, 19, 7, 101, 100, 110, 115, 49, 48, 49, 8, 117, 108, 116, 114,
-                97, 100, 110, 115, 192, 26, 192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 22, 7, 101, 100,
-                110, 115, 49, 48, 49, 8, 117, 108, 116, 114, 97, 100, 110, 115, 3, 111, 114, 103,
-                0, 192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 20, 7, 110, 115, 45, 49, 52, 53, 48, 9,
-                97, 119, 115, 100, 110, 115, 45, 53, 51, 192, 179, 192, 18, 0, 2, 0, 1, 0, 1, 81,
-                128, 0, 25, 7, 110, 115, 45, 49, 55, 48, 48, 9, 97, 119, 115, 100, 110, 115, 45,
-                50, 48, 2, 99, 111, 2, 117, 107, 0, 192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 19, 6,
-                110, 115, 45, 51, 55, 48, 9, 97, 119, 115, 100, 110, 115, 45, 52, 54, 192, 114,
-                192, 18, 0, 2, 0, 1, 0, 1, 81, 128, 0, 19, 6, 110, 115, 45, 57, 55, 53, 9, 97, 119,
-                115, 100, 110, 115, 45, 53, 55, 192, 26, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ],
+            data: vec![59, 108, 128, 0],
         },
-    ),
+    )];
+    assert_eq!(
+        to_rust_source("CAPTURED", &exchanges),
+        "static CAPTURED: &[(&[u8], &str, &[u8])] = &[
     (
-        MockKey {
-            query_bytes: &[
-                59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 7, 116, 119, 105, 116, 116, 101, 114, 3, 99,
-                111, 109, 0, 0, 1, 0, 1,
-            ],
-            server_ip: "205.251.192.179:53",
-        },
-        MockData {
-            data: &[
-                59, 108, 132, 0, 0, 1, 0, 1, 0, 8, 0, 0, 7, 116, 119, 105, 116, 116, 101, 114, 3,
-                99, 111, 109, 0, 0, 1, 0, 1, 192, 12, 0, 1, 0, 1, 0, 0, 7, 8, 0, 4, 104, 244, 42,
-                193, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175, 0, 19, 1, 97, 3, 114, 48, 54, 7, 116, 119,
-                116, 114, 100, 110, 115, 3, 110, 101, 116, 0, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175,
-                0, 8, 1, 97, 3, 117, 48, 54, 192, 63, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175, 0, 4, 1,
-                98, 192, 59, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175, 0, 4, 1, 98, 192, 90, 192, 12, 0,
-                2, 0, 1, 0, 0, 54, 175, 0, 4, 1, 99, 192, 59, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175,
-                0, 4, 1, 99, 192, 90, 192, 12, 0, 2, 0, 1, 0, 0, 54, 175, 0, 4, 1, 100, 192, 59,
-                192, 12, 0, 2, 0, 1, 0, 0, 54, 175, 0, 4, 1, 100, 192, 90, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
-            ],
-        },
+        &[
+            59, 108,
+        ],
+        \"192.0.2.53:53\",
+        &[
+            59, 108, 128, 0,
+        ],
     ),
 ];
+"
+    );
+}
+
+/// Validate resolving a name through the referral chain built by `MockResolution`.
+#[test]
+fn test_mock_resolution() -> Result<(), DnsError> {
+    use crate::socket::MockSocket;
+
+    let exchanges = MockResolution::new("www.example.com", RecordType::A, 0)
+        .referral("com", "a.gtld-servers.net", "192.5.6.30")
+        .referral("example.com.", "ns1.example.com.", "192.0.2.53")
+        .answer(
+            "www.example.com. 300 IN CNAME example.com.
+             example.com. 300 IN A 192.0.2.1",
+        )
+        .build()?;
+    let servers: Vec<&str> = exchanges
+        .iter()
+        .map(|(key, _)| key.server_ip.as_str())
+        .collect();
+    assert_eq!(
+        servers,
+        ["192.58.128.30:53", "192.5.6.30:53", "192.0.2.53:53"]
+    );
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+    let query = Query::new("www.example.com", RecordType::A);
+    let packet = query.resolve(&mut socket, Some(0))?;
+    assert_eq!(packet.answers.len(), 2);
+    assert_eq!(
+        packet.answers[1].to_presentation(),
+        "example.com. 300 IN A 192.0.2.1"
+    );
+
+    assert_eq!(
+        MockResolution::new("example.com", RecordType::A, 0)
+            .answer("example.com. 300 IN A not-an-address")
+            .build()
+            .err(),
+        Some(DnsError::ParseZone)
+    );
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use crate::errors::DnsError;
+use crate::record_name::RecordName;
+use regex::RegexBuilder;
+use std::io::{Cursor, Read};
+
+/// A NAPTR record: a rule rewriting a string into a URI or a name to look up next, as used by
+/// ENUM and other DDDS applications (RFC 3403, section 4.1).
+#[derive(Debug, PartialEq, Clone)]
+pub struct NaptrRecord {
+    /// The order the rules must be processed in, lowest first.
+    pub order: u16,
+
+    /// The preference among rules of the same order, lowest first.
+    pub preference: u16,
+
+    /// The flags, e.g. `u` for a rule yielding a URI and ending the process.
+    pub flags: String,
+
+    /// The services the rule applies to, e.g. `E2U+sip`.
+    pub services: String,
+
+    /// The substitution expression, e.g. `!^.*$!sip:info@example.com!`. Empty when the
+    /// replacement applies instead.
+    pub regexp: String,
+
+    /// The name to look up next, without a trailing dot. `.` when the regexp applies instead.
+    pub replacement: String,
+}
+
+impl NaptrRecord {
+    /// Parse the data of a NAPTR record.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<NaptrRecord, DnsError> {
+        let Some(numbers) = data.get(..4) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let mut cursor = Cursor::new(&data[4..]);
+        let mut strings = Vec::new();
+        for _ in 0..3 {
+            let mut length = [0u8];
+            cursor
+                .read_exact(&mut length)
+                .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+            let mut string = vec![0u8; length[0] as usize];
+            cursor
+                .read_exact(&mut string)
+                .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+            strings.push(String::from_utf8_lossy(&string).into_owned());
+        }
+        let replacement = RecordName::read_and_advance(&mut cursor)?;
+        let Ok(replacement) = String::from_utf8(replacement) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        let Ok([flags, services, regexp]) = <[String; 3]>::try_from(strings) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        Ok(NaptrRecord {
+            order: u16::from_be_bytes([numbers[0], numbers[1]]),
+            preference: u16::from_be_bytes([numbers[2], numbers[3]]),
+            flags,
+            services,
+            regexp,
+            replacement: match replacement.is_empty() {
+                true => ".".to_owned(),
+                false => replacement,
+            },
+        })
+    }
+
+    /// Whether the rule yields a URI and ends the process (the `u` flag).
+    pub fn is_terminal(&self) -> bool {
+        self.flags
+            .chars()
+            .any(|flag| flag.eq_ignore_ascii_case(&'u'))
+    }
+
+    /// Apply the substitution expression to a string, as sed would: the part matching the
+    /// regular expression is replaced, with `\1` to `\9` standing for the groups it captured.
+    /// `None` when the rule has no expression or the string does not match.
+    ///
+    /// # Argument
+    /// * `input`: The string to rewrite, e.g. the telephone number `+441632960083` for ENUM.
+    pub fn rewrite(&self, input: &str) -> Result<Option<String>, DnsError> {
+        let Some((expression, replacement, case_insensitive)) = split_regexp(&self.regexp)? else {
+            return Ok(None);
+        };
+        let Ok(regex) = RegexBuilder::new(&expression)
+            .case_insensitive(case_insensitive)
+            .build()
+        else {
+            return Err(DnsError::Naptr);
+        };
+        let Some(captures) = regex.captures(input) else {
+            return Ok(None);
+        };
+
+        let matched = captures.get(0).map_or(0..0, |matched| matched.range());
+        let mut output = input[..matched.start].to_owned();
+        let mut chars = replacement.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                output.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    let group = captures.get(digit as usize - '0' as usize);
+                    output.push_str(group.map_or("", |group| group.as_str()));
+                }
+                Some(escaped) => output.push(escaped),
+                None => return Err(DnsError::Naptr),
+            }
+        }
+        output.push_str(&input[matched.end..]);
+        Ok(Some(output))
+    }
+}
+
+/// Split a substitution expression into its regular expression, its replacement and whether it
+/// is case-insensitive (RFC 3402, section 3.2). The first character delimits the parts, and is
+/// escaped with a backslash within them. `None` for an empty expression.
+///
+/// # Argument
+/// * `regexp`: The substitution expression, e.g. `!^\+44(.*)$!sip:\1@example.com!i`.
+fn split_regexp(regexp: &str) -> Result<Option<(String, String, bool)>, DnsError> {
+    let mut chars = regexp.chars();
+    let Some(delimiter) = chars.next() else {
+        return Ok(None);
+    };
+    if delimiter.is_ascii_digit() || delimiter == '\\' || delimiter == 'i' {
+        return Err(DnsError::Naptr);
+    }
+
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped == delimiter => part.push(escaped),
+                Some(escaped) => {
+                    part.push('\\');
+                    part.push(escaped);
+                }
+                None => return Err(DnsError::Naptr),
+            },
+            c if c == delimiter => parts.push(std::mem::take(&mut part)),
+            c => part.push(c),
+        }
+    }
+    // What follows the last delimiter is the flags.
+    parts.push(part);
+    let [expression, replacement, flags] =
+        <[String; 3]>::try_from(parts).map_err(|_| DnsError::Naptr)?;
+    match flags.as_str() {
+        "" => Ok(Some((expression, replacement, false))),
+        "i" => Ok(Some((expression, replacement, true))),
+        _ => Err(DnsError::Naptr),
+    }
+}
+
+/// Sort NAPTR records in the order their rules must be processed: by order, then preference.
+///
+/// # Argument
+/// * `records`: The records to sort.
+pub fn sort_naptr_records(records: &mut [NaptrRecord]) {
+    records.sort_by_key(|record| (record.order, record.preference));
+}
+
+/// A NAPTR record with the given order, preference, flags and substitution expression.
+#[cfg(test)]
+fn naptr(order: u16, preference: u16, flags: &str, regexp: &str) -> NaptrRecord {
+    NaptrRecord {
+        order,
+        preference,
+        flags: flags.to_owned(),
+        services: "E2U+sip".to_owned(),
+        regexp: regexp.to_owned(),
+        replacement: ".".to_owned(),
+    }
+}
+
+/// Validate parsing of NAPTR record data.
+#[test]
+fn test_parsing_naptr_record() -> Result<(), DnsError> {
+    use crate::rdata::from_presentation;
+    use crate::record::RecordType;
+
+    let data = from_presentation(
+        RecordType::NAPTR,
+        &[
+            "100",
+            "10",
+            "u",
+            "E2U+sip",
+            "!^.*$!sip:info@example.com!",
+            ".",
+        ],
+        "",
+    )?;
+    let record = NaptrRecord::from_data(&data)?;
+    assert_eq!(record, naptr(100, 10, "u", "!^.*$!sip:info@example.com!"));
+    assert!(record.is_terminal());
+    assert!(NaptrRecord::from_data(&data[..10]).is_err());
+
+    let mut records = vec![
+        naptr(20, 10, "u", ""),
+        naptr(10, 50, "", ""),
+        naptr(10, 20, "U", ""),
+    ];
+    sort_naptr_records(&mut records);
+    let keys: Vec<(u16, u16)> = records
+        .iter()
+        .map(|record| (record.order, record.preference))
+        .collect();
+    assert_eq!(keys, [(10, 20), (10, 50), (20, 10)]);
+    assert!(records[0].is_terminal() && !records[1].is_terminal());
+    Ok(())
+}
+
+/// Validate applying substitution expressions, with groups, escapes and flags.
+#[test]
+fn test_rewriting_with_naptr_record() -> Result<(), DnsError> {
+    let rewrite = |regexp: &str, input: &str| naptr(100, 10, "u", regexp).rewrite(input);
+
+    assert_eq!(
+        rewrite("!^.*$!sip:info@example.com!", "+441632960083")?,
+        Some("sip:info@example.com".to_owned())
+    );
+    assert_eq!(
+        rewrite(r"!^\+44(.*)$!sip:\1@example.com!", "+441632960083")?,
+        Some("sip:1632960083@example.com".to_owned())
+    );
+    assert_eq!(
+        rewrite(
+            r"/^\+44(16)(.*)$/mailto:\2\/\1@example.com/",
+            "+441632960083"
+        )?,
+        Some("mailto:32960083/16@example.com".to_owned())
+    );
+    assert_eq!(rewrite("!^X!Y!i", "xyz")?, Some("Yyz".to_owned()));
+    assert_eq!(rewrite(r"!^\+1!sip:x!", "+44")?, None);
+    assert_eq!(rewrite("", "+44")?, None);
+
+    assert_eq!(rewrite("!^.*$!sip:x", "+44"), Err(DnsError::Naptr));
+    assert_eq!(rewrite("!^.*$!sip:x!g", "+44"), Err(DnsError::Naptr));
+    assert_eq!(rewrite("1^.*$1sip:x1", "+44"), Err(DnsError::Naptr));
+    assert_eq!(rewrite("!(!sip:x!", "+44"), Err(DnsError::Naptr));
+    Ok(())
+}
@@ -0,0 +1,298 @@
+use crate::errors::DnsError;
+use crate::header::{rcode_name, FLAG_RECURSION_AVAILABLE, RCODE_NO_ERROR};
+use crate::query::{Query, QueryOptions};
+use crate::record::RecordType;
+use crate::socket::DnsTransport;
+use log::info;
+use std::fmt;
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The name asked of each host, which any resolver can resolve.
+pub const DEFAULT_PROBE_NAME: &str = "example.com";
+
+/// How a host responded to a query asking for recursion.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProbeOutcome {
+    /// The host resolved the name: it is an open resolver.
+    Open {
+        /// The number of records in the answer.
+        answers: usize,
+    },
+
+    /// The host responded without resolving the name, e.g. with a referral or an empty answer
+    /// and without the RA bit.
+    NoRecursion,
+
+    /// The host responded with an error, e.g. REFUSED.
+    Declined {
+        /// The RCODE of the response.
+        rcode: u16,
+    },
+
+    /// The host did not respond.
+    Silent,
+
+    /// The query could not be sent, or the response could not be read.
+    Failed(String),
+}
+
+impl fmt::Display for ProbeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Open { answers } => write!(f, "open resolver ({} answers)", answers),
+            Self::NoRecursion => write!(f, "no recursion"),
+            Self::Declined { rcode } => write!(f, "declined ({})", rcode_name(*rcode)),
+            Self::Silent => write!(f, "no response"),
+            Self::Failed(message) => write!(f, "failed ({})", message),
+        }
+    }
+}
+
+/// How a host of a scan responded.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ProbeResult {
+    /// The address the query was sent to.
+    pub address: SocketAddr,
+
+    /// How the host responded.
+    pub outcome: ProbeOutcome,
+}
+
+impl ProbeResult {
+    /// Whether the host is an open resolver.
+    pub fn is_open(&self) -> bool {
+        matches!(self.outcome, ProbeOutcome::Open { .. })
+    }
+}
+
+impl fmt::Display for ProbeResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.address, self.outcome)
+    }
+}
+
+/// Ask a host to resolve a name on the client's behalf, once, and tell whether it did. Only an
+/// answer with the RA bit set counts as recursion.
+///
+/// # Arguments
+/// * `socket`: The socket to send the query on. How long to wait for the host is up to it.
+/// * `address`: The address of the host.
+/// * `domain_name`: The name to ask for the A records of, e.g. `DEFAULT_PROBE_NAME`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn probe_resolver(
+    socket: &mut dyn DnsTransport,
+    address: SocketAddr,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> ProbeOutcome {
+    let query = Query {
+        domain_name: domain_name.into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            recursion_desired: true,
+            ..Default::default()
+        },
+    };
+    match query.ask(socket, &address.to_string(), rand_seed) {
+        Ok(packet) if packet.rcode() != RCODE_NO_ERROR => ProbeOutcome::Declined {
+            rcode: packet.rcode(),
+        },
+        Ok(packet)
+            if packet.header.has_flag(FLAG_RECURSION_AVAILABLE) && !packet.answers.is_empty() =>
+        {
+            ProbeOutcome::Open {
+                answers: packet.answers.len(),
+            }
+        }
+        Ok(_) => ProbeOutcome::NoRecursion,
+        Err(error) if matches!(error.kind(), DnsError::SocketRead(_)) => ProbeOutcome::Silent,
+        Err(error) => ProbeOutcome::Failed(error.to_string()),
+    }
+}
+
+/// Probe hosts one after the other with `probe_resolver()`, starting at most the given number of
+/// probes per second so as not to flood the network scanned.
+///
+/// # Arguments
+/// * `socket`: The socket to send the queries on. How long to wait for each host is up to it.
+/// * `addresses`: The addresses of the hosts, e.g. from `Cidr::addresses()`.
+/// * `domain_name`: The name to ask for the A records of, e.g. `DEFAULT_PROBE_NAME`.
+/// * `queries_per_second`: How many hosts to probe per second at most. At least 1.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn scan_resolvers(
+    socket: &mut dyn DnsTransport,
+    addresses: impl IntoIterator<Item = SocketAddr>,
+    domain_name: &str,
+    queries_per_second: u32,
+    rand_seed: Option<usize>,
+) -> Vec<ProbeResult> {
+    let interval = Duration::from_secs(1) / queries_per_second.max(1);
+    let started_at = Instant::now();
+    let mut results = Vec::new();
+    for (index, address) in addresses.into_iter().enumerate() {
+        let scheduled = interval * index as u32;
+        if let Some(wait) = scheduled.checked_sub(started_at.elapsed()) {
+            thread::sleep(wait);
+        }
+        let outcome = probe_resolver(socket, address, domain_name, rand_seed);
+        info!("{}: {}", address, outcome);
+        results.push(ProbeResult { address, outcome });
+    }
+    results
+}
+
+/// Validate telling open resolvers from hosts which refuse, do not recurse or do not respond.
+#[test]
+fn test_scanning_resolvers() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::{MockFault, MockSocket};
+
+    let probe = |address: &str| {
+        MockResolution::new(DEFAULT_PROBE_NAME, RecordType::A, 0)
+            .recursion_desired()
+            .at(address)
+    };
+    let exchanges = vec![
+        probe("192.0.2.1")
+            .recursive_answer("example.com. 300 IN A 192.0.2.80")
+            .build()?,
+        probe("192.0.2.2").refused().build()?,
+        probe("192.0.2.3")
+            .answer("example.com. 300 IN A 192.0.2.80")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+    let (key, data) = probe("192.0.2.4").refused().build()?.remove(0);
+    socket.register_faulty_response(key, data, vec![MockFault::Drop]);
+
+    let addresses = (1..=4).map(|host| SocketAddr::from(([192, 0, 2, host], 53)));
+    let results = scan_resolvers(&mut socket, addresses, DEFAULT_PROBE_NAME, 1000, Some(0));
+    let results: Vec<String> = results.iter().map(ProbeResult::to_string).collect();
+    assert_eq!(
+        results,
+        [
+            "192.0.2.1:53: open resolver (1 answers)",
+            "192.0.2.2:53: declined (REFUSED)",
+            "192.0.2.3:53: no recursion",
+            "192.0.2.4:53: no response",
+        ]
+    );
+    Ok(())
+}
+
+/// Validate each outcome of probing a single host, with transports standing in for hosts which
+/// resolve, refuse, do not respond or cannot be reached.
+#[test]
+fn test_probing_resolver_outcomes() -> Result<(), DnsError> {
+    use crate::header::RCODE_REFUSED;
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+    use std::io::ErrorKind;
+
+    /// A host which never responds: reads time out.
+    struct SilentSocket;
+
+    impl DnsTransport for SilentSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            Err(DnsError::SocketRead(Some(ErrorKind::WouldBlock.into())))
+        }
+    }
+
+    let address = SocketAddr::from(([192, 0, 2, 53], 53));
+    let probe = || {
+        MockResolution::new(DEFAULT_PROBE_NAME, RecordType::A, 0)
+            .recursion_desired()
+            .at("192.0.2.53")
+    };
+    let outcome = |exchanges| {
+        let mut socket = MockSocket::default();
+        socket.register_response_data(exchanges);
+        probe_resolver(&mut socket, address, DEFAULT_PROBE_NAME, Some(0))
+    };
+
+    let open = probe()
+        .recursive_answer(
+            "example.com. 300 IN A 192.0.2.80
+             example.com. 300 IN A 192.0.2.81",
+        )
+        .build()?;
+    assert_eq!(outcome(open), ProbeOutcome::Open { answers: 2 });
+
+    let refused = probe().refused().build()?;
+    assert_eq!(
+        outcome(refused),
+        ProbeOutcome::Declined {
+            rcode: RCODE_REFUSED
+        }
+    );
+
+    let authoritative = probe().answer("example.com. 300 IN A 192.0.2.80").build()?;
+    assert_eq!(outcome(authoritative), ProbeOutcome::NoRecursion);
+
+    let silent = probe_resolver(&mut SilentSocket, address, DEFAULT_PROBE_NAME, Some(0));
+    assert_eq!(silent, ProbeOutcome::Silent);
+    assert_eq!(silent.to_string(), "no response");
+
+    // Nothing answers at another address, so the query cannot even be sent.
+    let unreachable = probe_resolver(
+        &mut MockSocket::default(),
+        address,
+        DEFAULT_PROBE_NAME,
+        Some(0),
+    );
+    assert!(matches!(unreachable, ProbeOutcome::Failed(_)));
+    Ok(())
+}
+
+/// Validate scanning the hosts of a CIDR block in order, at the given rate.
+#[test]
+fn test_scanning_cidr_block() -> Result<(), DnsError> {
+    use crate::acl::Cidr;
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let probe = |address: &str| {
+        MockResolution::new(DEFAULT_PROBE_NAME, RecordType::A, 0)
+            .recursion_desired()
+            .at(address)
+    };
+    let exchanges = vec![
+        probe("192.0.2.1")
+            .recursive_answer("example.com. 300 IN A 192.0.2.80")
+            .build()?,
+        probe("192.0.2.2").refused().build()?,
+    ];
+    let mut socket = MockSocket::default();
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let block = Cidr::parse("192.0.2.0/30")?;
+    let addresses = block
+        .addresses()
+        .map(|address| SocketAddr::new(address, 53));
+    let started_at = Instant::now();
+    let results = scan_resolvers(&mut socket, addresses, DEFAULT_PROBE_NAME, 20, Some(0));
+
+    // Probes start a twentieth of a second apart: the last one 150 ms after the first.
+    assert!(started_at.elapsed() >= Duration::from_millis(150));
+    let scanned: Vec<SocketAddr> = results.iter().map(|result| result.address).collect();
+    assert_eq!(
+        scanned,
+        [0, 1, 2, 3].map(|host| SocketAddr::from(([192, 0, 2, host], 53)))
+    );
+    let open: Vec<bool> = results.iter().map(ProbeResult::is_open).collect();
+    assert_eq!(open, [false, true, false, false]);
+    assert_eq!(
+        results[2].outcome,
+        ProbeOutcome::Declined {
+            rcode: crate::header::RCODE_REFUSED
+        }
+    );
+    Ok(())
+}
@@ -1,10 +1,15 @@
-use crate::errors::DnsError;
-use crate::header::Header;
+use crate::dig;
+use crate::edns::Edns;
+use crate::errors::{DnsError, ErrorContext};
+use crate::header::{rcode_name, Header};
 use crate::question::Question;
-use crate::record::Record;
+use crate::record::{Record, RecordType};
+use crate::record_name::CompressionOffsets;
 use std::fmt;
 use std::io::Cursor;
 
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Packet {
     /// Header of a DNS packet.
     pub header: Header,
@@ -22,25 +27,91 @@ pub struct Packet {
     pub additionals: Vec<Record>,
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Packet {
+    /// A message of up to 4 questions and 8 records in each section, whose header counts its
+    /// sections.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let header = u.arbitrary::<Header>()?;
+        let mut questions = Vec::new();
+        for _ in 0..u.int_in_range(0..=4)? {
+            questions.push(u.arbitrary::<Question>()?);
+        }
+        let mut sections: [Vec<Record>; 3] = Default::default();
+        for section in &mut sections {
+            for _ in 0..u.int_in_range(0..=8)? {
+                section.push(u.arbitrary::<Record>()?);
+            }
+        }
+        let [answers, authorities, additionals] = sections;
+        Ok(Packet {
+            header: Header {
+                num_questions: questions.len() as u16,
+                num_answers: answers.len() as u16,
+                num_authorities: authorities.len() as u16,
+                num_additionals: additionals.len() as u16,
+                ..header
+            },
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+}
+
 impl fmt::Display for Packet {
+    /// Write the whole message the way dig does, see `dig::render()`: the header with its flags,
+    /// response code and section counts, then every section with its records in presentation
+    /// format, including the OPT pseudo-record. Everything but the records is a comment, so that
+    /// the records can be read back with `Zone::parse()`. With the alternate flag (`{:#}`), names
+    /// holding A-labels are written in their Unicode form, see `dig::idn_out()`.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (position, answer) in self.answers.iter().enumerate() {
-            let Ok(name) = std::str::from_utf8(&answer.name) else {return Err(fmt::Error)};
-            let address = answer.ip_address();
-            write!(
-                f,
-                "{}{}#{}@{}/{}",
-                if position == 0 { "" } else { "," },
-                answer.r_type,
-                name,
-                address,
-                answer.ttl
-            )?;
+        let output = dig::render_lossless(self);
+        match f.alternate() {
+            true => write!(f, "{}", dig::idn_out(&output)),
+            false => write!(f, "{}", output),
         }
-        Ok(())
     }
 }
 
+/// Read the entries of a section of a message. Errors are given the offset at which reading
+/// failed and the entry being read.
+///
+/// # Arguments
+/// * `cursor`: The byte buffer containing the full DNS message data.
+/// * `section`: The name of the section, e.g. "answer".
+/// * `count`: The number of entries given in the header.
+/// * `min_size`: The smallest size of an entry on the wire, e.g. a record with a root owner
+///   name and no data is 11 bytes. No more room is reserved than the remaining bytes can hold, so
+///   that a short message claiming many entries does not reserve much memory.
+/// * `read`: Reads an entry at the cursor.
+fn read_section<T>(
+    cursor: &mut Cursor<&[u8]>,
+    section: &'static str,
+    count: u16,
+    min_size: usize,
+    read: fn(&mut Cursor<&[u8]>) -> Result<T, DnsError>,
+) -> Result<Vec<T>, DnsError> {
+    let remaining = cursor
+        .get_ref()
+        .len()
+        .saturating_sub(cursor.position() as usize);
+    let mut entries = Vec::with_capacity((count as usize).min(remaining / min_size));
+    for index in 0..count as usize {
+        let entry = read(cursor).map_err(|error| {
+            error.with_context(ErrorContext {
+                offset: Some(cursor.position()),
+                section: Some(section),
+                index: Some(index),
+                ..Default::default()
+            })
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
 impl Packet {
     /// Parse a DNS packet from the given buffer.
     ///
@@ -48,46 +119,321 @@ impl Packet {
     /// * `buffer`: The byte buffer containing the full DNS message data.
     pub fn parse(buffer: &[u8]) -> Result<Packet, DnsError> {
         let mut cursor = Cursor::new(buffer);
-        let header = Header::read_and_advance(&mut cursor)?;
-        let mut questions = Vec::with_capacity(header.num_questions as usize);
+        let header = Header::read_and_advance(&mut cursor).map_err(|error| {
+            error.with_context(ErrorContext {
+                offset: Some(cursor.position()),
+                section: Some("header"),
+                ..Default::default()
+            })
+        })?;
+        let questions = read_section(
+            &mut cursor,
+            "question",
+            header.num_questions,
+            5,
+            Question::read_and_advance,
+        )?;
+        let answers = read_section(
+            &mut cursor,
+            "answer",
+            header.num_answers,
+            11,
+            Record::read_and_advance,
+        )?;
+        let authorities = read_section(
+            &mut cursor,
+            "authority",
+            header.num_authorities,
+            11,
+            Record::read_and_advance,
+        )?;
+        let additionals = read_section(
+            &mut cursor,
+            "additional",
+            header.num_additionals,
+            11,
+            Record::read_and_advance,
+        )?;
 
-        for _ in 0..header.num_questions {
-            let question = Question::read_and_advance(&mut cursor)?;
-            questions.push(question);
+        Ok(Packet {
+            header,
+            questions,
+            answers,
+            authorities,
+            additionals,
+        })
+    }
+
+    /// Serialize the packet in wire format. Names are not compressed. The section counts in the
+    /// header are taken from the sections themselves.
+    pub fn serialize(&self) -> Result<Vec<u8>, DnsError> {
+        let mut bytes = Vec::new();
+        self.serialize_header(&mut bytes)?;
+        for question in &self.questions {
+            question.serialize(&mut bytes)?;
+        }
+        for record in self.records() {
+            record.serialize(&mut bytes)?;
         }
+        Ok(bytes)
+    }
 
-        let mut answers = Vec::with_capacity(header.num_answers as usize);
-        for _ in 0..header.num_answers {
-            let answer = Record::read_and_advance(&mut cursor)?;
-            answers.push(answer);
+    /// Serialize the packet in wire format like `serialize()`, compressing names against the
+    /// names before them, as described in section 4.1.4 of RFC 1035.
+    pub fn serialize_compressed(&self) -> Result<Vec<u8>, DnsError> {
+        let mut bytes = Vec::new();
+        let mut offsets = CompressionOffsets::new();
+        self.serialize_header(&mut bytes)?;
+        for question in &self.questions {
+            question.serialize_compressed(&mut bytes, &mut offsets)?;
+        }
+        for record in self.records() {
+            record.serialize_compressed(&mut bytes, &mut offsets)?;
         }
+        Ok(bytes)
+    }
+
+    /// The records of the packet, section by section.
+    fn records(&self) -> impl Iterator<Item = &Record> {
+        self.answers
+            .iter()
+            .chain(self.authorities.iter())
+            .chain(self.additionals.iter())
+    }
 
-        let mut authorities = Vec::with_capacity(header.num_authorities as usize);
-        for _ in 0..header.num_authorities {
-            let authority = Record::read_and_advance(&mut cursor)?;
-            authorities.push(authority);
+    /// Serialize the header of the packet onto the given buffer, with the section counts taken
+    /// from the sections themselves.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized header to.
+    fn serialize_header(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        let mut header = self.header.clone();
+        let (Ok(num_questions), Ok(num_answers), Ok(num_authorities), Ok(num_additionals)) = (
+            u16::try_from(self.questions.len()),
+            u16::try_from(self.answers.len()),
+            u16::try_from(self.authorities.len()),
+            u16::try_from(self.additionals.len()),
+        ) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        header.num_questions = num_questions;
+        header.num_answers = num_answers;
+        header.num_authorities = num_authorities;
+        header.num_additionals = num_additionals;
+        header.serialize(bytes)
+    }
+
+    /// The EDNS parameters of the packet, if it carries an OPT record.
+    pub fn edns(&self) -> Result<Option<Edns>, DnsError> {
+        match self
+            .additionals
+            .iter()
+            .find(|record| record.r_type == RecordType::OPT)
+        {
+            Some(record) => Ok(Some(Edns::from_record(record)?)),
+            None => Ok(None),
         }
+    }
+
+    /// The response code (one of the `RCODE_*` constants), with its upper 8 bits taken from the
+    /// OPT record if there is one, so that extended codes such as BADVERS are not mistaken for
+    /// the codes sharing their lower 4 bits.
+    pub fn rcode(&self) -> u16 {
+        let extended_rcode = match self
+            .additionals
+            .iter()
+            .find(|record| record.r_type == RecordType::OPT)
+        {
+            Some(record) => (record.ttl.as_secs() >> 24) as u16,
+            None => 0,
+        };
+        extended_rcode << 4 | self.header.rcode()
+    }
 
-        let mut additionals = Vec::with_capacity(header.num_additionals as usize);
-        for _ in 0..header.num_additionals {
-            let record = Record::read_and_advance(&mut cursor)?;
-            additionals.push(record);
+    /// Compare the message with another, e.g. the answers of two servers to the same query, or of
+    /// one server before and after a zone change. The ID and the order of records are ignored.
+    ///
+    /// # Argument
+    /// * `other`: The message to compare against, taken as the newer one.
+    pub fn diff(&self, other: &Packet) -> PacketDiff {
+        let flags = self.header.flag_names();
+        let other_flags = other.header.flag_names();
+        PacketDiff {
+            flags_added: other_flags
+                .iter()
+                .filter(|flag| !flags.contains(flag))
+                .copied()
+                .collect(),
+            flags_removed: flags
+                .iter()
+                .filter(|flag| !other_flags.contains(flag))
+                .copied()
+                .collect(),
+            rcode: match self.rcode() == other.rcode() {
+                true => None,
+                false => Some((self.rcode(), other.rcode())),
+            },
+            answers: RecordsDiff::new(&self.answers, &other.answers),
+            authorities: RecordsDiff::new(&self.authorities, &other.authorities),
+            additionals: RecordsDiff::new(&self.additionals, &other.additionals),
         }
+    }
+}
 
-        Ok(Packet {
-            header: header,
-            questions: questions,
-            answers: answers,
-            authorities: authorities,
-            additionals: additionals,
-        })
+/// The differences between two messages, see `Packet::diff()`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PacketDiff {
+    /// Names of the header flags set only in the newer message, e.g. `["aa"]`.
+    pub flags_added: Vec<&'static str>,
+
+    /// Names of the header flags set only in the older message.
+    pub flags_removed: Vec<&'static str>,
+
+    /// The response codes of the older and the newer message, when they differ.
+    pub rcode: Option<(u16, u16)>,
+
+    /// The differences between the answer sections.
+    pub answers: RecordsDiff,
+
+    /// The differences between the authority sections.
+    pub authorities: RecordsDiff,
+
+    /// The differences between the additional sections.
+    pub additionals: RecordsDiff,
+}
+
+impl PacketDiff {
+    /// Whether the messages are the same, but for their IDs and the order of their records.
+    pub fn is_empty(&self) -> bool {
+        self.flags_added.is_empty()
+            && self.flags_removed.is_empty()
+            && self.rcode.is_none()
+            && self.answers.is_empty()
+            && self.authorities.is_empty()
+            && self.additionals.is_empty()
+    }
+}
+
+impl fmt::Display for PacketDiff {
+    /// Write the differences one per line, like a unified diff: `+` for what only the newer
+    /// message has and `-` for what only the older one has, with a changed record written as its
+    /// old and new form, e.g. `-example.com. 300 IN A 192.0.2.1` then
+    /// `+example.com. 300 IN A 192.0.2.2`. Nothing is written for identical messages.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.flags_removed.is_empty() || !self.flags_added.is_empty() {
+            writeln!(f, ";; flags:")?;
+            for flag in &self.flags_removed {
+                writeln!(f, "-{}", flag)?;
+            }
+            for flag in &self.flags_added {
+                writeln!(f, "+{}", flag)?;
+            }
+        }
+        if let Some((rcode, other_rcode)) = self.rcode {
+            writeln!(f, ";; status:")?;
+            writeln!(f, "-{}", rcode_name(rcode))?;
+            writeln!(f, "+{}", rcode_name(other_rcode))?;
+        }
+
+        let sections = [
+            ("ANSWER", &self.answers),
+            ("AUTHORITY", &self.authorities),
+            ("ADDITIONAL", &self.additionals),
+        ];
+        for (title, records) in sections {
+            if records.is_empty() {
+                continue;
+            }
+            writeln!(f, ";; {} SECTION:", title)?;
+            for record in &records.removed {
+                writeln!(f, "-{}", record)?;
+            }
+            for (record, other_record) in &records.changed {
+                writeln!(f, "-{}", record)?;
+                writeln!(f, "+{}", other_record)?;
+            }
+            for record in &records.added {
+                writeln!(f, "+{}", record)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The differences between the records of a section of two messages, see `Packet::diff()`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RecordsDiff {
+    /// Records only the newer message has.
+    pub added: Vec<Record>,
+
+    /// Records only the older message has.
+    pub removed: Vec<Record>,
+
+    /// Records of the same name, class and type whose TTL or data changed, as they are in the
+    /// older and in the newer message.
+    pub changed: Vec<(Record, Record)>,
+}
+
+impl RecordsDiff {
+    /// Compare the records of a section of two messages. Identical records are matched up first,
+    /// then records differing only in their TTL, and then what remains of each name, class and
+    /// type is paired up in order as changed.
+    ///
+    /// # Arguments
+    /// * `records`: The records of the older message.
+    /// * `other_records`: The records of the newer message.
+    fn new(records: &[Record], other_records: &[Record]) -> RecordsDiff {
+        let mut removed: Vec<&Record> = Vec::new();
+        let mut added: Vec<&Record> = other_records.iter().collect();
+        for record in records {
+            match added
+                .iter()
+                .position(|other_record| *other_record == record)
+            {
+                Some(index) => {
+                    added.remove(index);
+                }
+                None => removed.push(record),
+            }
+        }
+
+        let mut diff = RecordsDiff::default();
+        let matchers: [fn(&Record, &Record) -> bool; 2] = [
+            |record, other_record| record.data == other_record.data,
+            |_, _| true,
+        ];
+        for matcher in matchers {
+            removed.retain(|record| {
+                let Some(index) = added.iter().position(|other_record| {
+                    record.name == other_record.name
+                        && record.r_class == other_record.r_class
+                        && record.r_type == other_record.r_type
+                        && matcher(record, other_record)
+                }) else {
+                    return true;
+                };
+                diff.changed
+                    .push(((*record).clone(), added.remove(index).clone()));
+                false
+            });
+        }
+        diff.removed = removed.into_iter().cloned().collect();
+        diff.added = added.into_iter().cloned().collect();
+        diff
+    }
+
+    /// Whether the sections hold the same records, in any order.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
     }
 }
 
 /// Validate parsing of a simple, valid packet.
 #[test]
 fn test_parsing_simple_packet() {
+    use crate::dns_name::DnsName;
     use crate::record::RecordType;
+    use crate::ttl::Ttl;
 
     // A DNS packet that answers a query for www.example.com
     let data = [
@@ -116,7 +462,7 @@ fn test_parsing_simple_packet() {
         }
     );
 
-    let domain_name: Vec<u8> = "www.example.com".chars().map(|c| c as u8).collect();
+    let domain_name = DnsName::from("www.example.com");
 
     assert_eq!(
         packet.questions,
@@ -133,12 +479,51 @@ fn test_parsing_simple_packet() {
             name: domain_name,
             r_type: RecordType::A,
             r_class: 1,
-            ttl: 29 << 8 | 234,
+            ttl: Ttl::from(29 << 8 | 234),
             data: vec![93, 184, 216, 34]
         }]
     )
 }
 
+/// Validate extracting the EDNS parameters from a packet's OPT record.
+#[test]
+fn test_packet_edns() -> Result<(), DnsError> {
+    let data = [
+        // ID    Flags   Qs    Answ  Auth  Addl  Root Type   Size     Flags       Len
+        204, 71, 129, 160, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 41, 4, 208, 0, 0, 128, 0, 0, 0,
+    ];
+
+    let packet = Packet::parse(data.as_slice())?;
+    let edns = packet.edns()?.unwrap();
+    assert_eq!(edns.udp_payload_size, 1232);
+    assert!(edns.dnssec_ok);
+    Ok(())
+}
+
+/// Validate that the response code is assembled from the header and the OPT record.
+#[test]
+fn test_packet_extended_rcode() -> Result<(), DnsError> {
+    use crate::header::{RCODE_BAD_COOKIE, RCODE_BAD_VERSION, RCODE_NO_ERROR};
+
+    let data = [
+        // ID    Flags   Qs    Answ  Auth  Addl  Root Type   Size     Flags       Len
+        204, 71, 129, 128, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 41, 4, 208, 1, 0, 0, 0, 0, 0,
+    ];
+
+    let mut packet = Packet::parse(data.as_slice())?;
+    assert_eq!(packet.header.rcode(), RCODE_NO_ERROR);
+    assert_eq!(packet.rcode(), RCODE_BAD_VERSION);
+    assert_eq!(rcode_name(packet.rcode()), "BADVERS");
+
+    packet.header.flags |= 7;
+    assert_eq!(packet.rcode(), RCODE_BAD_COOKIE);
+    assert_eq!(rcode_name(packet.rcode()), "BADCOOKIE");
+
+    packet.additionals.clear();
+    assert_eq!(packet.rcode(), 7);
+    Ok(())
+}
+
 /// Validate parsing of a packet with only a header.
 #[test]
 fn test_parsing_packet_with_header() {
@@ -176,3 +561,351 @@ fn test_parsing_packet_with_missing_data_should_fail() {
 fn test_parsing_packet_with_no_data_should_fail() {
     assert!(Packet::parse([].as_slice()).is_err())
 }
+
+/// Validate that a serialized packet parses back into the same packet.
+#[test]
+fn test_packet_serialization_round_trip() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::ttl::Ttl;
+    let packet = Packet {
+        header: Header {
+            id: 4242,
+            flags: 0x8400,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("www.example.com"),
+            q_type: RecordType::A,
+            q_class: 1,
+        }],
+        answers: vec![Record {
+            name: DnsName::from("www.example.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![93, 184, 216, 34],
+        }],
+        authorities: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::NS,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![2, b'n', b's', 0],
+        }],
+        additionals: vec![],
+    };
+
+    let parsed = Packet::parse(&packet.serialize()?)?;
+    assert_eq!(parsed.header.num_answers, 1);
+    assert_eq!(parsed.header.num_authorities, 1);
+    assert_eq!(parsed.questions, packet.questions);
+    assert_eq!(parsed.answers, packet.answers);
+    assert_eq!(parsed.authorities, packet.authorities);
+    Ok(())
+}
+
+/// Validate that a packet serialized with serde deserializes back into the same packet, with
+/// names as strings and TTLs as seconds.
+#[cfg(feature = "serde")]
+#[test]
+fn test_packet_serde_round_trip() -> Result<(), serde_json::Error> {
+    use crate::dns_name::DnsName;
+    use crate::ttl::Ttl;
+    let packet = Packet {
+        header: Header {
+            id: 4242,
+            flags: 0x8400,
+            num_questions: 1,
+            num_answers: 2,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("www.example.com"),
+            q_type: RecordType::A,
+            q_class: 1,
+        }],
+        answers: vec![
+            Record {
+                name: DnsName::from("www.example.com"),
+                r_type: RecordType::A,
+                r_class: 1,
+                ttl: Ttl::from(300),
+                data: vec![93, 184, 216, 34],
+            },
+            Record {
+                name: DnsName::from("www.example.com"),
+                r_type: RecordType::Unknown(65280),
+                r_class: 1,
+                ttl: Ttl::from(60),
+                data: vec![1, 2, 3],
+            },
+        ],
+        authorities: vec![],
+        additionals: vec![],
+    };
+
+    let json = serde_json::to_string(&packet)?;
+    assert!(json.contains(r#""name":"www.example.com","r_type":"A","r_class":1,"ttl":300,"#));
+    assert_eq!(serde_json::from_str::<Packet>(&json)?, packet);
+    Ok(())
+}
+
+/// Validate that the alternate form of a packet displays the names of its answers in Unicode.
+#[cfg(feature = "idna")]
+#[test]
+fn test_packet_display_unicode_names() {
+    use crate::dns_name::DnsName;
+    use crate::ttl::Ttl;
+    let packet = Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![Record {
+            name: DnsName::from("xn--bcher-kva.example"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![192, 0, 2, 1],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    };
+    assert!(format!("{}", packet).ends_with("\nxn--bcher-kva.example. 300 IN A 192.0.2.1\n"));
+    assert!(format!("{:#}", packet).ends_with("\nbücher.example. 300 IN A 192.0.2.1\n"));
+}
+
+/// Validate that a packet displays every section, and that its records can be read back.
+#[test]
+fn test_packet_display_round_trip() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::header::{FLAG_RECURSION_DESIRED, FLAG_RESPONSE};
+    use crate::ttl::Ttl;
+    use crate::zone::Zone;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+    let mut edns = Edns::new(1232);
+    edns.dnssec_ok = true;
+    let packet = Packet {
+        header: Header {
+            id: 4660,
+            flags: FLAG_RESPONSE | FLAG_RECURSION_DESIRED,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("example.com"),
+            q_type: RecordType::MX,
+            q_class: 1,
+        }],
+        answers: vec![record(
+            "example.com",
+            RecordType::MX,
+            b"\x00\x0a\x04mail\x07example\x03com\x00",
+        )],
+        authorities: vec![record(
+            "example.com",
+            RecordType::NS,
+            b"\x02ns\x07example\x03com\x00",
+        )],
+        additionals: vec![
+            record("mail.example.com", RecordType::TXT, b"\x05a \"b\""),
+            // Neither can be read back in their own presentation format.
+            record("mail.example.com", RecordType::TXT, b""),
+            record("mail.example.com", RecordType::CAA, b"\x00\x02a-x"),
+            edns.to_record()?,
+        ],
+    };
+
+    let output = format!("{}", packet);
+    assert_eq!(
+        output,
+        ";; ->>HEADER<<- opcode: QUERY, status: NOERROR, id: 4660\n\
+         ;; flags: qr rd; QUERY: 1, ANSWER: 1, AUTHORITY: 1, ADDITIONAL: 4\n\
+         \n\
+         ;; OPT PSEUDOSECTION:\n\
+         ; EDNS: version: 0, flags: do; udp: 1232\n\
+         \n\
+         ;; QUESTION SECTION:\n\
+         ;example.com. IN MX\n\
+         \n\
+         ;; ANSWER SECTION:\n\
+         example.com. 300 IN MX 10 mail.example.com.\n\
+         \n\
+         ;; AUTHORITY SECTION:\n\
+         example.com. 300 IN NS ns.example.com.\n\
+         \n\
+         ;; ADDITIONAL SECTION:\n\
+         mail.example.com. 300 IN TXT \"a \\\"b\\\"\"\n\
+         mail.example.com. 300 IN TXT \\# 0\n\
+         mail.example.com. 300 IN CAA \\# 5 0002612D78\n\
+         . 32768 CLASS1232 OPT \\# 0\n"
+    );
+
+    let records: Vec<Record> = packet
+        .answers
+        .iter()
+        .chain(&packet.authorities)
+        .chain(&packet.additionals)
+        .cloned()
+        .collect();
+    assert_eq!(Zone::parse(&output, ".")?.records, records);
+    Ok(())
+}
+
+/// Validate comparing two messages, with records added, removed and changed in TTL and data.
+#[test]
+fn test_packet_diff() {
+    use crate::dns_name::DnsName;
+    use crate::header::{FLAG_AUTHORITATIVE, FLAG_RECURSION_AVAILABLE, RCODE_SERVER_FAILURE};
+    use crate::ttl::Ttl;
+
+    let record = |name: &str, r_type: RecordType, ttl: u32, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(ttl),
+        data: data.to_vec(),
+    };
+    let packet = Packet {
+        header: Header {
+            id: 1,
+            flags: FLAG_RECURSION_AVAILABLE,
+            ..Default::default()
+        },
+        questions: vec![],
+        answers: vec![
+            record("example.com", RecordType::A, 300, &[192, 0, 2, 1]),
+            record("example.com", RecordType::A, 300, &[192, 0, 2, 2]),
+            record("example.com", RecordType::TXT, 300, b"\x03old"),
+        ],
+        authorities: vec![record("example.com", RecordType::NS, 300, b"\x02ns\x00")],
+        additionals: vec![],
+    };
+
+    let mut other = packet.clone();
+    other.header.id = 2;
+    other.answers.reverse();
+    assert!(packet.diff(&other).is_empty());
+    assert_eq!(format!("{}", packet.diff(&other)), "");
+
+    other.header.flags = FLAG_AUTHORITATIVE | RCODE_SERVER_FAILURE;
+    other.answers = vec![
+        record("example.com", RecordType::TXT, 300, b"\x03new"),
+        record("EXAMPLE.com", RecordType::A, 60, &[192, 0, 2, 2]),
+        record("example.com", RecordType::AAAA, 300, &[0; 16]),
+    ];
+    let diff = packet.diff(&other);
+    assert_eq!(diff.flags_added, ["aa"]);
+    assert_eq!(diff.flags_removed, ["ra"]);
+    assert_eq!(diff.rcode, Some((0, RCODE_SERVER_FAILURE)));
+    assert_eq!(
+        diff.answers,
+        RecordsDiff {
+            added: vec![record("example.com", RecordType::AAAA, 300, &[0; 16])],
+            removed: vec![record("example.com", RecordType::A, 300, &[192, 0, 2, 1])],
+            changed: vec![
+                (
+                    record("example.com", RecordType::A, 300, &[192, 0, 2, 2]),
+                    record("EXAMPLE.com", RecordType::A, 60, &[192, 0, 2, 2]),
+                ),
+                (
+                    record("example.com", RecordType::TXT, 300, b"\x03old"),
+                    record("example.com", RecordType::TXT, 300, b"\x03new"),
+                ),
+            ],
+        }
+    );
+    assert!(diff.authorities.is_empty());
+    assert_eq!(
+        format!("{}", diff),
+        ";; flags:\n-ra\n+aa\n;; status:\n-NOERROR\n+SERVFAIL\n;; ANSWER SECTION:\n\
+         -example.com. 300 IN A 192.0.2.1\n\
+         -example.com. 300 IN A 192.0.2.2\n\
+         +EXAMPLE.com. 60 IN A 192.0.2.2\n\
+         -example.com. 300 IN TXT \"old\"\n\
+         +example.com. 300 IN TXT \"new\"\n\
+         +example.com. 300 IN AAAA ::\n"
+    );
+}
+
+/// Validate that names are compressed against the names before them, in the data of records too,
+/// and read back the same.
+#[test]
+fn test_packet_compressed_serialization() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::ttl::Ttl;
+    let packet = Packet {
+        header: Header {
+            id: 4242,
+            flags: 0x8400,
+            num_questions: 1,
+            num_answers: 1,
+            num_authorities: 1,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("www.example.com"),
+            q_type: RecordType::A,
+            q_class: 1,
+        }],
+        answers: vec![Record {
+            name: DnsName::from("www.example.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![93, 184, 216, 34],
+        }],
+        authorities: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::NS,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: b"\x02ns\x07example\x03com\x00".to_vec(),
+        }],
+        additionals: vec![],
+    };
+
+    let bytes = packet.serialize_compressed()?;
+    assert_eq!(bytes.len(), 66);
+    // The answer points to the question name, the authority to its ending.
+    assert_eq!(bytes[33..35], [0xC0, 12]);
+    assert_eq!(bytes[49..51], [0xC0, 16]);
+    assert_eq!(bytes[59..], [0, 5, 2, b'n', b's', 0xC0, 16]);
+    assert_eq!(Packet::parse(&bytes)?, packet);
+    assert!(bytes.len() < packet.serialize()?.len());
+    Ok(())
+}
+
+/// Ensure that a message claiming more entries than it has room for fails to parse, and that the
+/// error tells where.
+#[test]
+fn test_parse_overstated_counts() {
+    let mut message = vec![
+        0x12, 0x34, 0x81, 0x80, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    ];
+    let error = Packet::parse(&message[..4]).unwrap_err();
+    assert_eq!(error, DnsError::ParseQuestionCount(None));
+    assert_eq!(error.context().unwrap().section, Some("header"));
+
+    // Four questions for the root, then an answer cut short in its TTL, which runs out at the end
+    // of the message.
+    message[4..12].copy_from_slice(&[0, 4, 0, 1, 0, 0, 0, 0]);
+    message.extend([0; 20]);
+    message.extend([0, 0, 1, 0, 1, 0, 0]);
+    let error = Packet::parse(&message).unwrap_err();
+    assert_eq!(error, DnsError::ReadRecordTTL(None));
+    assert_eq!(
+        error.context(),
+        Some(&ErrorContext {
+            offset: Some(39),
+            section: Some("answer"),
+            index: Some(0),
+            ..Default::default()
+        })
+    );
+    assert_eq!(error.exit_code(), DnsError::ReadRecordTTL(None).exit_code());
+}
@@ -0,0 +1,780 @@
+use crate::errors::DnsError;
+use chrono::{DateTime, SecondsFormat};
+use std::fmt;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, SystemTime};
+
+/// The port DNS is served on over UDP and TCP.
+const DNS_PORT: u16 = 53;
+
+/// Magic numbers of the pcap format, with timestamps in microseconds or nanoseconds.
+const PCAP_MAGIC_MICROSECONDS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b23c4d;
+
+/// Block types of the pcapng format, and the magic number giving the byte order of a section.
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+/// Option of an interface description block giving the resolution of timestamps.
+const PCAPNG_OPTION_TSRESOL: u16 = 9;
+
+/// Link types of captured frames, as listed by tcpdump.org.
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LOOP: u16 = 108;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+
+/// EtherTypes of the frames which may carry DNS messages.
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// IP protocol numbers of the transports and of the IPv6 extension headers which may precede
+/// them.
+const PROTOCOL_HOP_BY_HOP: u8 = 0;
+const PROTOCOL_TCP: u8 = 6;
+const PROTOCOL_UDP: u8 = 17;
+const PROTOCOL_ROUTING: u8 = 43;
+const PROTOCOL_FRAGMENT: u8 = 44;
+const PROTOCOL_DESTINATION_OPTIONS: u8 = 60;
+
+/// The transport a captured message was carried over.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Udp => write!(f, "UDP"),
+            Self::Tcp => write!(f, "TCP"),
+        }
+    }
+}
+
+/// A DNS message found in a capture.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CapturedMessage {
+    /// When the frame carrying the message was captured, since the Unix epoch.
+    pub timestamp: Duration,
+
+    /// The address and port the message was sent from.
+    pub source: SocketAddr,
+
+    /// The address and port the message was sent to.
+    pub destination: SocketAddr,
+
+    /// The transport the message was carried over.
+    pub transport: Transport,
+
+    /// The message itself, without the length prefix of TCP.
+    pub data: Vec<u8>,
+}
+
+impl CapturedMessage {
+    /// When the message was captured, in the RFC 3339 format with microseconds.
+    pub fn captured_at(&self) -> String {
+        rfc3339(self.timestamp)
+    }
+}
+
+/// Format a time in the RFC 3339 format with microseconds, in UTC.
+///
+/// # Argument
+/// * `timestamp`: The time since the Unix epoch.
+pub(crate) fn rfc3339(timestamp: Duration) -> String {
+    match DateTime::from_timestamp(timestamp.as_secs() as i64, timestamp.subsec_nanos()) {
+        Some(time) => time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        None => format!("{:?}", timestamp),
+    }
+}
+
+/// Read the DNS messages sent to or from port 53 in a capture in the pcap or pcapng format, in the
+/// order they were captured. Frames of other protocols, fragmented datagrams and unknown link
+/// types are skipped, and a capture cut short ends at its last complete frame.
+///
+/// TCP streams are not reassembled: messages are only found in segments starting with their
+/// length prefix, and those spanning several segments are skipped.
+///
+/// # Argument
+/// * `capture`: The contents of the capture file.
+pub fn read_messages(capture: &[u8]) -> Result<Vec<CapturedMessage>, DnsError> {
+    let Some(magic) = read_u32(capture, 0, true) else {
+        return Err(DnsError::ParseCapture);
+    };
+    let mut messages = Vec::new();
+    match magic {
+        PCAPNG_SECTION_HEADER => read_pcapng(capture, &mut messages)?,
+        _ => read_pcap(capture, &mut messages)?,
+    }
+    Ok(messages)
+}
+
+/// Read the frames of a capture in the original pcap format.
+///
+/// # Arguments
+/// * `capture`: The contents of the capture file.
+/// * `messages`: Where to add the messages found.
+fn read_pcap(capture: &[u8], messages: &mut Vec<CapturedMessage>) -> Result<(), DnsError> {
+    let mut little_endian = true;
+    let ticks_per_second = match read_u32(capture, 0, little_endian) {
+        Some(PCAP_MAGIC_MICROSECONDS) => 1_000_000,
+        Some(PCAP_MAGIC_NANOSECONDS) => 1_000_000_000,
+        _ => {
+            little_endian = false;
+            match read_u32(capture, 0, little_endian) {
+                Some(PCAP_MAGIC_MICROSECONDS) => 1_000_000,
+                Some(PCAP_MAGIC_NANOSECONDS) => 1_000_000_000,
+                _ => return Err(DnsError::ParseCapture),
+            }
+        }
+    };
+    // The upper bits of the link type may carry the FCS length, which is of no interest here.
+    let Some(link_type) = read_u32(capture, 20, little_endian) else {
+        return Err(DnsError::ParseCapture);
+    };
+    let link_type = link_type as u16;
+
+    let mut offset = 24;
+    while let (Some(seconds), Some(fraction), Some(length)) = (
+        read_u32(capture, offset, little_endian),
+        read_u32(capture, offset + 4, little_endian),
+        read_u32(capture, offset + 8, little_endian),
+    ) {
+        let start = offset + 16;
+        let Some(frame) = capture.get(start..start + length as usize) else {
+            break;
+        };
+        let timestamp =
+            Duration::from_secs(seconds as u64) + timestamp(fraction as u64, ticks_per_second);
+        read_frame(frame, link_type, timestamp, messages);
+        offset = start + length as usize;
+    }
+    Ok(())
+}
+
+/// Read the blocks of a capture in the pcapng format. Sections may differ in byte order, and each
+/// starts its own list of interfaces.
+///
+/// # Arguments
+/// * `capture`: The contents of the capture file.
+/// * `messages`: Where to add the messages found.
+fn read_pcapng(capture: &[u8], messages: &mut Vec<CapturedMessage>) -> Result<(), DnsError> {
+    // Link type and timestamp resolution of each interface of the current section.
+    let mut interfaces: Vec<(u16, u64)> = Vec::new();
+    let mut little_endian = true;
+
+    let mut offset = 0;
+    while let Some(block_type) = read_u32(capture, offset, little_endian) {
+        if block_type == PCAPNG_SECTION_HEADER {
+            little_endian = match read_u32(capture, offset + 8, true) {
+                Some(PCAPNG_BYTE_ORDER_MAGIC) => true,
+                Some(_) => false,
+                None => return Err(DnsError::ParseCapture),
+            };
+            interfaces.clear();
+        }
+        let Some(length) = read_u32(capture, offset + 4, little_endian) else {
+            break;
+        };
+        let length = length as usize;
+        if length < 12 || !length.is_multiple_of(4) {
+            return Err(DnsError::ParseCapture);
+        }
+        let Some(body) = capture.get(offset + 8..offset + length - 4) else {
+            break;
+        };
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let Some(link_type) = read_u16(body, 0, little_endian) else {
+                    return Err(DnsError::ParseCapture);
+                };
+                let ticks_per_second = interface_resolution(body, little_endian);
+                interfaces.push((link_type, ticks_per_second));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let (Some(interface), Some(high), Some(low), Some(captured)) = (
+                    read_u32(body, 0, little_endian),
+                    read_u32(body, 4, little_endian),
+                    read_u32(body, 8, little_endian),
+                    read_u32(body, 12, little_endian),
+                ) else {
+                    return Err(DnsError::ParseCapture);
+                };
+                let Some(&(link_type, ticks_per_second)) = interfaces.get(interface as usize)
+                else {
+                    return Err(DnsError::ParseCapture);
+                };
+                let Some(frame) = body.get(20..20 + captured as usize) else {
+                    return Err(DnsError::ParseCapture);
+                };
+                let ticks = ((high as u64) << 32) | low as u64;
+                read_frame(
+                    frame,
+                    link_type,
+                    timestamp(ticks, ticks_per_second),
+                    messages,
+                );
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                // Simple packets have no timestamp, and are padded to 32 bits.
+                let (Some(&(link_type, _)), Some(original)) =
+                    (interfaces.first(), read_u32(body, 0, little_endian))
+                else {
+                    return Err(DnsError::ParseCapture);
+                };
+                let end = body.len().min(4 + original as usize);
+                read_frame(&body[4..end], link_type, Duration::ZERO, messages);
+            }
+            _ => (),
+        }
+        offset += length;
+    }
+    Ok(())
+}
+
+/// Timestamp resolution of an interface, in ticks per second, from the `if_tsresol` option of its
+/// description. Microseconds unless given otherwise.
+///
+/// # Arguments
+/// * `body`: The body of the interface description block.
+/// * `little_endian`: Whether the section is little-endian.
+fn interface_resolution(body: &[u8], little_endian: bool) -> u64 {
+    let mut offset = 8;
+    while let (Some(code), Some(length)) = (
+        read_u16(body, offset, little_endian),
+        read_u16(body, offset + 2, little_endian),
+    ) {
+        if code == PCAPNG_OPTION_TSRESOL && length == 1 {
+            let Some(&resolution) = body.get(offset + 4) else {
+                break;
+            };
+            // The high bit tells a power of two from a power of ten.
+            return match resolution & 0x80 {
+                0 => 10u64.pow((resolution as u32).min(19)),
+                _ => 1 << (resolution & 0x7f).min(63),
+            };
+        }
+        // Options end with code 0, and their values are padded to 32 bits.
+        if code == 0 {
+            break;
+        }
+        offset += 4 + (length as usize).div_ceil(4) * 4;
+    }
+    1_000_000
+}
+
+/// Convert a count of ticks into a duration.
+///
+/// # Arguments
+/// * `ticks`: The count of ticks.
+/// * `ticks_per_second`: How many ticks make a second.
+fn timestamp(ticks: u64, ticks_per_second: u64) -> Duration {
+    let nanoseconds = (ticks % ticks_per_second) as u128 * 1_000_000_000 / ticks_per_second as u128;
+    Duration::new(ticks / ticks_per_second, nanoseconds as u32)
+}
+
+/// Find the DNS messages in a captured frame.
+///
+/// # Arguments
+/// * `frame`: The captured octets of the frame.
+/// * `link_type`: The link type of the interface the frame was captured on.
+/// * `timestamp`: When the frame was captured.
+/// * `messages`: Where to add the messages found.
+fn read_frame(
+    frame: &[u8],
+    link_type: u16,
+    timestamp: Duration,
+    messages: &mut Vec<CapturedMessage>,
+) {
+    let packet = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ether_type = read_u16(frame, offset, false);
+            while ether_type == Some(ETHERTYPE_VLAN) {
+                offset += 4;
+                ether_type = read_u16(frame, offset, false);
+            }
+            match ether_type {
+                Some(ETHERTYPE_IPV4) | Some(ETHERTYPE_IPV6) => frame.get(offset + 2..),
+                _ => None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => match read_u16(frame, 14, false) {
+            Some(ETHERTYPE_IPV4) | Some(ETHERTYPE_IPV6) => frame.get(16..),
+            _ => None,
+        },
+        // The address family in the header of the loopback interfaces is of either byte order,
+        // so the version of the IP header tells the family instead.
+        LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => Some(frame),
+        _ => None,
+    };
+    let Some((source, destination, protocol, payload)) = packet.and_then(read_ip) else {
+        return;
+    };
+    let (Some(source_port), Some(destination_port)) =
+        (read_u16(payload, 0, false), read_u16(payload, 2, false))
+    else {
+        return;
+    };
+    if source_port != DNS_PORT && destination_port != DNS_PORT {
+        return;
+    }
+
+    let mut message = |transport: Transport, data: &[u8]| {
+        messages.push(CapturedMessage {
+            timestamp,
+            source: SocketAddr::new(source, source_port),
+            destination: SocketAddr::new(destination, destination_port),
+            transport,
+            data: data.to_vec(),
+        });
+    };
+    match protocol {
+        PROTOCOL_UDP => {
+            let Some(length) = read_u16(payload, 4, false) else {
+                return;
+            };
+            if let Some(data) = payload.get(8..length as usize) {
+                message(Transport::Udp, data);
+            }
+        }
+        PROTOCOL_TCP => {
+            let Some(&data_offset) = payload.get(12) else {
+                return;
+            };
+            let mut offset = (data_offset >> 4) as usize * 4;
+            while let Some(length) = read_u16(payload, offset, false) {
+                let Some(data) = payload.get(offset + 2..offset + 2 + length as usize) else {
+                    break;
+                };
+                message(Transport::Tcp, data);
+                offset += 2 + length as usize;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Read the addresses, the transport protocol and the payload of an IP packet. Fragments are
+/// skipped, as their payload is incomplete.
+///
+/// # Argument
+/// * `packet`: The IPv4 or IPv6 packet.
+fn read_ip(packet: &[u8]) -> Option<(IpAddr, IpAddr, u8, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_length = (packet[0] & 0x0f) as usize * 4;
+            let total_length = read_u16(packet, 2, false)? as usize;
+            // The more fragments flag and the fragment offset.
+            if read_u16(packet, 6, false)? & 0x3fff != 0 {
+                return None;
+            }
+            let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+            // Frames may be padded past the end of the packet.
+            let payload = packet.get(header_length..total_length.min(packet.len()))?;
+            Some((
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                packet[9],
+                payload,
+            ))
+        }
+        6 => {
+            let payload_length = read_u16(packet, 4, false)? as usize;
+            let mut next_header = *packet.get(6)?;
+            let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+            let mut payload = packet.get(40..(40 + payload_length).min(packet.len()))?;
+            while matches!(
+                next_header,
+                PROTOCOL_HOP_BY_HOP | PROTOCOL_ROUTING | PROTOCOL_DESTINATION_OPTIONS
+            ) {
+                next_header = *payload.first()?;
+                payload = payload.get((*payload.get(1)? as usize + 1) * 8..)?;
+            }
+            if next_header == PROTOCOL_FRAGMENT {
+                return None;
+            }
+            Some((
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                next_header,
+                payload,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Read a 16-bit integer at the given offset, if the data is long enough.
+fn read_u16(data: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    match little_endian {
+        true => Some(u16::from_le_bytes(bytes)),
+        false => Some(u16::from_be_bytes(bytes)),
+    }
+}
+
+/// Read a 32-bit integer at the given offset, if the data is long enough.
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    match little_endian {
+        true => Some(u32::from_le_bytes(bytes)),
+        false => Some(u32::from_be_bytes(bytes)),
+    }
+}
+
+/// Writes datagrams to a capture in the pcap format, as if captured on a raw IP interface. Each
+/// gets synthetic IP and UDP headers so that tools such as Wireshark decode it as DNS.
+pub struct PcapWriter<W: Write> {
+    /// Where the capture is written.
+    output: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Start a capture by writing its header.
+    ///
+    /// # Argument
+    /// * `output`: Where to write the capture, e.g. a file.
+    pub fn new(mut output: W) -> Result<PcapWriter<W>, DnsError> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC_MICROSECONDS.to_le_bytes());
+        // Version 2.4, with timestamps in UTC.
+        header.extend_from_slice(&[2, 0, 4, 0]);
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&(u16::MAX as u32).to_le_bytes());
+        header.extend_from_slice(&(LINKTYPE_RAW as u32).to_le_bytes());
+        let Ok(_) = output.write_all(&header) else {
+            return Err(DnsError::WriteCapture);
+        };
+        Ok(PcapWriter { output })
+    }
+
+    /// Add a datagram to the capture, timestamped with the current time. IPv4 addresses are
+    /// mapped to IPv6 when the other address is IPv6.
+    ///
+    /// # Arguments
+    /// * `source`: The address and port the datagram was sent from.
+    /// * `destination`: The address and port the datagram was sent to.
+    /// * `data`: The payload of the datagram.
+    pub fn write_datagram(
+        &mut self,
+        source: SocketAddr,
+        destination: SocketAddr,
+        data: &[u8],
+    ) -> Result<(), DnsError> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.write_datagram_at(timestamp, source, destination, data)
+    }
+
+    /// Add a datagram to the capture with the given timestamp.
+    ///
+    /// # Arguments
+    /// * `timestamp`: When the datagram was sent or received, since the Unix epoch.
+    /// * `source`: The address and port the datagram was sent from.
+    /// * `destination`: The address and port the datagram was sent to.
+    /// * `data`: The payload of the datagram.
+    fn write_datagram_at(
+        &mut self,
+        timestamp: Duration,
+        source: SocketAddr,
+        destination: SocketAddr,
+        data: &[u8],
+    ) -> Result<(), DnsError> {
+        let udp_length = 8 + data.len();
+        let mut udp = Vec::with_capacity(udp_length);
+        udp.extend_from_slice(&source.port().to_be_bytes());
+        udp.extend_from_slice(&destination.port().to_be_bytes());
+        udp.extend_from_slice(&(udp_length as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(data);
+
+        let mut packet = Vec::with_capacity(40 + udp_length);
+        let mut pseudo_header = Vec::with_capacity(40);
+        match (source.ip(), destination.ip()) {
+            (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                packet.extend_from_slice(&[0x45, 0]);
+                packet.extend_from_slice(&(20 + udp_length as u16).to_be_bytes());
+                packet.extend_from_slice(&[0, 0, 0x40, 0, 64, PROTOCOL_UDP, 0, 0]);
+                packet.extend_from_slice(&source.octets());
+                packet.extend_from_slice(&destination.octets());
+                let checksum = checksum(&[&packet]);
+                packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+                pseudo_header.extend_from_slice(&packet[12..20]);
+                pseudo_header.extend_from_slice(&[0, PROTOCOL_UDP]);
+                pseudo_header.extend_from_slice(&(udp_length as u16).to_be_bytes());
+            }
+            (source, destination) => {
+                let to_ipv6 = |address: IpAddr| match address {
+                    IpAddr::V4(address) => address.to_ipv6_mapped(),
+                    IpAddr::V6(address) => address,
+                };
+                packet.extend_from_slice(&[0x60, 0, 0, 0]);
+                packet.extend_from_slice(&(udp_length as u16).to_be_bytes());
+                packet.extend_from_slice(&[PROTOCOL_UDP, 64]);
+                packet.extend_from_slice(&to_ipv6(source).octets());
+                packet.extend_from_slice(&to_ipv6(destination).octets());
+                pseudo_header.extend_from_slice(&packet[8..40]);
+                pseudo_header.extend_from_slice(&(udp_length as u32).to_be_bytes());
+                pseudo_header.extend_from_slice(&[0, 0, 0, PROTOCOL_UDP]);
+            }
+        }
+        // A computed checksum of zero is sent as all ones, as zero means no checksum.
+        let checksum = match checksum(&[&pseudo_header, &udp]) {
+            0 => 0xffff,
+            checksum => checksum,
+        };
+        udp[6..8].copy_from_slice(&checksum.to_be_bytes());
+        packet.extend_from_slice(&udp);
+
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        record.extend_from_slice(&packet);
+        let (Ok(_), Ok(_)) = (self.output.write_all(&record), self.output.flush()) else {
+            return Err(DnsError::WriteCapture);
+        };
+        Ok(())
+    }
+
+    /// Finish the capture, giving back where it was written.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+/// The Internet checksum of RFC 1071 over the concatenation of the given parts, each of an even
+/// length but the last.
+///
+/// # Argument
+/// * `parts`: The data to sum.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for part in parts {
+        for word in part.chunks(2) {
+            let word = match word {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => u16::from_be_bytes([*high, 0]),
+                _ => 0,
+            };
+            sum += word as u32;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// A DNS query for example.com A, as sent over UDP.
+#[cfg(test)]
+const TEST_MESSAGE: &[u8] = b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\
+    \x07example\x03com\x00\x00\x01\x00\x01";
+
+/// Validate reading messages carried over UDP and IPv4 from a little-endian pcap capture of an
+/// Ethernet interface.
+#[test]
+fn test_reading_pcap() -> Result<(), DnsError> {
+    let mut frame = Vec::new();
+    // Ethernet header.
+    frame.extend_from_slice(&[0; 12]);
+    frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+    // IPv4 header, without fragmentation.
+    frame.extend_from_slice(&[0x45, 0]);
+    frame.extend_from_slice(&(20 + 8 + TEST_MESSAGE.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0, 0x40, 0, 64, PROTOCOL_UDP, 0, 0]);
+    frame.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 53]);
+    // UDP header.
+    frame.extend_from_slice(&40000u16.to_be_bytes());
+    frame.extend_from_slice(&DNS_PORT.to_be_bytes());
+    frame.extend_from_slice(&(8 + TEST_MESSAGE.len() as u16).to_be_bytes());
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(TEST_MESSAGE);
+    // Ethernet padding.
+    frame.extend_from_slice(&[0; 4]);
+
+    let mut capture = Vec::new();
+    capture.extend_from_slice(&PCAP_MAGIC_MICROSECONDS.to_le_bytes());
+    capture.extend_from_slice(&[2, 0, 4, 0]);
+    capture.extend_from_slice(&[0; 8]);
+    capture.extend_from_slice(&65535u32.to_le_bytes());
+    capture.extend_from_slice(&(LINKTYPE_ETHERNET as u32).to_le_bytes());
+    for _ in 0..2 {
+        capture.extend_from_slice(&1700000000u32.to_le_bytes());
+        capture.extend_from_slice(&250000u32.to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&frame);
+    }
+    // A frame cut short by the end of the capture.
+    let truncated = capture[24..40].to_vec();
+    capture.extend_from_slice(&truncated);
+
+    let message = CapturedMessage {
+        timestamp: Duration::from_millis(1700000000250),
+        source: "192.0.2.1:40000".parse().unwrap(),
+        destination: "192.0.2.53:53".parse().unwrap(),
+        transport: Transport::Udp,
+        data: TEST_MESSAGE.to_vec(),
+    };
+    assert_eq!(message.captured_at(), "2023-11-14T22:13:20.250000Z");
+    assert_eq!(read_messages(&capture)?, [message.clone(), message]);
+
+    // Other ports are skipped.
+    let mut other_port = capture.clone();
+    other_port[24 + 16 + 14 + 20 + 2..][..2].copy_from_slice(&5353u16.to_be_bytes());
+    assert_eq!(read_messages(&other_port)?.len(), 1);
+
+    assert_eq!(read_messages(b"bogus"), Err(DnsError::ParseCapture));
+    assert_eq!(read_messages(&[0; 24]), Err(DnsError::ParseCapture));
+    Ok(())
+}
+
+/// Validate reading messages carried over TCP and IPv6 from a big-endian pcapng capture of a raw
+/// IP interface with nanosecond timestamps.
+#[test]
+fn test_reading_pcapng() -> Result<(), DnsError> {
+    let mut packet = Vec::new();
+    // IPv6 header, followed by a destination options header.
+    let tcp_length = 20 + 2 * (2 + TEST_MESSAGE.len());
+    packet.extend_from_slice(&[0x60, 0, 0, 0]);
+    packet.extend_from_slice(&(8 + tcp_length as u16).to_be_bytes());
+    packet.extend_from_slice(&[PROTOCOL_DESTINATION_OPTIONS, 64]);
+    packet.extend_from_slice(&"2001:db8::53".parse::<Ipv6Addr>().unwrap().octets());
+    packet.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+    packet.extend_from_slice(&[PROTOCOL_TCP, 0, 1, 4, 0, 0, 0, 0]);
+    // TCP header, with two length-prefixed messages.
+    packet.extend_from_slice(&DNS_PORT.to_be_bytes());
+    packet.extend_from_slice(&40000u16.to_be_bytes());
+    packet.extend_from_slice(&[0; 8]);
+    packet.extend_from_slice(&[0x50, 0x18]);
+    packet.extend_from_slice(&[0; 6]);
+    for _ in 0..2 {
+        packet.extend_from_slice(&(TEST_MESSAGE.len() as u16).to_be_bytes());
+        packet.extend_from_slice(TEST_MESSAGE);
+    }
+
+    let block = |block_type: u32, body: &[u8]| -> Vec<u8> {
+        let length = 12 + body.len().div_ceil(4) * 4;
+        let mut block = Vec::new();
+        block.extend_from_slice(&block_type.to_be_bytes());
+        block.extend_from_slice(&(length as u32).to_be_bytes());
+        block.extend_from_slice(body);
+        block.resize(length - 4, 0);
+        block.extend_from_slice(&(length as u32).to_be_bytes());
+        block
+    };
+    let mut section = PCAPNG_BYTE_ORDER_MAGIC.to_be_bytes().to_vec();
+    section.extend_from_slice(&[0, 1, 0, 0]);
+    section.extend_from_slice(&[0xff; 8]);
+    let mut interface = LINKTYPE_RAW.to_be_bytes().to_vec();
+    interface.extend_from_slice(&[0; 6]);
+    interface.extend_from_slice(&PCAPNG_OPTION_TSRESOL.to_be_bytes());
+    interface.extend_from_slice(&[0, 1, 9, 0, 0, 0]);
+    interface.extend_from_slice(&[0; 4]);
+    let ticks: u64 = 1_700_000_000_123_456_789;
+    let mut enhanced = 0u32.to_be_bytes().to_vec();
+    enhanced.extend_from_slice(&((ticks >> 32) as u32).to_be_bytes());
+    enhanced.extend_from_slice(&(ticks as u32).to_be_bytes());
+    enhanced.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+    enhanced.extend_from_slice(&(packet.len() as u32).to_be_bytes());
+    enhanced.extend_from_slice(&packet);
+
+    let mut capture = block(PCAPNG_SECTION_HEADER, &section);
+    capture.extend(block(PCAPNG_INTERFACE_DESCRIPTION, &interface));
+    capture.extend(block(0xbad, &[1, 2, 3]));
+    capture.extend(block(PCAPNG_ENHANCED_PACKET, &enhanced));
+
+    let message = CapturedMessage {
+        timestamp: Duration::new(1700000000, 123456789),
+        source: "[2001:db8::53]:53".parse().unwrap(),
+        destination: "[2001:db8::1]:40000".parse().unwrap(),
+        transport: Transport::Tcp,
+        data: TEST_MESSAGE.to_vec(),
+    };
+    assert_eq!(read_messages(&capture)?, [message.clone(), message]);
+
+    // Packets of unknown interfaces make the capture invalid.
+    let mut capture = block(PCAPNG_SECTION_HEADER, &section);
+    capture.extend(block(PCAPNG_ENHANCED_PACKET, &enhanced));
+    assert_eq!(read_messages(&capture), Err(DnsError::ParseCapture));
+    Ok(())
+}
+
+/// Validate that datagrams written to a capture are read back, with valid checksums.
+#[test]
+fn test_writing_pcap() -> Result<(), DnsError> {
+    let mut writer = PcapWriter::new(Vec::new())?;
+    let query = CapturedMessage {
+        timestamp: Duration::new(1700000000, 250000000),
+        source: "192.0.2.1:40000".parse().unwrap(),
+        destination: "192.0.2.53:53".parse().unwrap(),
+        transport: Transport::Udp,
+        data: TEST_MESSAGE.to_vec(),
+    };
+    let response = CapturedMessage {
+        timestamp: Duration::new(1700000001, 0),
+        source: "[2001:db8::53]:53".parse().unwrap(),
+        destination: "0.0.0.0:40000".parse().unwrap(),
+        transport: Transport::Udp,
+        data: TEST_MESSAGE[..TEST_MESSAGE.len() - 1].to_vec(),
+    };
+    for message in [&query, &response] {
+        writer.write_datagram_at(
+            message.timestamp,
+            message.source,
+            message.destination,
+            &message.data,
+        )?;
+    }
+    let capture = writer.into_inner();
+
+    let mapped = CapturedMessage {
+        destination: "[::ffff:0.0.0.0]:40000".parse().unwrap(),
+        ..response
+    };
+    assert_eq!(read_messages(&capture)?, [query, mapped]);
+
+    // The checksums of the IPv4 header and of both UDP datagrams, pseudo-headers included, sum
+    // up to zero.
+    let ipv4 = &capture[24 + 16..][..20 + 8 + TEST_MESSAGE.len()];
+    assert_eq!(checksum(&[&ipv4[..20]]), 0);
+    assert_eq!(
+        checksum(&[
+            &ipv4[12..20],
+            &[0, PROTOCOL_UDP],
+            &ipv4[24..26],
+            &ipv4[20..]
+        ]),
+        0
+    );
+    let ipv6 = &capture[24 + 16 + ipv4.len() + 16..];
+    let udp_length = (ipv6.len() as u32 - 40).to_be_bytes();
+    assert_eq!(
+        checksum(&[
+            &ipv6[8..40],
+            &udp_length,
+            &[0, 0, 0, PROTOCOL_UDP],
+            &ipv6[40..]
+        ]),
+        0
+    );
+    Ok(())
+}
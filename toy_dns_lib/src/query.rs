@@ -1,85 +1,349 @@
-use crate::errors::DnsError;
-use crate::header::Header;
+use crate::dns_name::DnsName;
+use crate::edns::{format_nsid, Edns, EdnsOption, PaddingPolicy};
+use crate::errors::{DnsError, ErrorCategory, ErrorContext, Stage};
+use crate::forwarder::DEFAULT_UDP_PORT;
+use crate::header::{Header, FLAG_CHECKING_DISABLED, FLAG_RECURSION_DESIRED, FLAG_TRUNCATED};
+use crate::metrics::Metrics;
 use crate::packet::Packet;
 use crate::question::Question;
-use crate::record::{DnsRecordGetters, RecordType};
+use crate::record::{DnsRecordGetters, Record, RecordType};
 use crate::record_name::RecordName;
 use crate::root_servers::{RootServer, RootServerName};
-use crate::socket::Socket;
-use byteorder::{BigEndian, WriteBytesExt};
-use log::info;
+use crate::socket::DnsTransport;
+use crate::stats::StatsRecorder;
+use crate::throttle::QueryThrottle;
+use crate::trace::{Exchange, Trace};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
+use std::borrow::Cow;
 use std::io::Cursor;
 use std::mem::size_of;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+use tracing::{field, info, info_span, warn};
 
 // TODO: When toy_dns_lib supports more than CLASS_IN, this should become an enum.
-const CLASS_IN: u16 = 1;
+pub(crate) const CLASS_IN: u16 = 1;
 
-/// DNS Query
+// 1024 is a good rule of thumb max-size for a DNS answer. For a more serious DNS resolver,
+// this mechanism should be improved.
+const RECEIVE_BUFFER_SIZE: usize = 1024;
+
+/// Largest message TCP can carry, as its length prefix is two octets (RFC 1035, section 4.2.2).
+pub(crate) const TCP_RECEIVE_BUFFER_SIZE: usize = u16::MAX as usize;
+
+/// Address multicast DNS queries are sent to over IPv4, as specified in RFC 6762, section 3.
+const MDNS_IPV4_ADDRESS: &str = "224.0.0.251:5353";
+
+/// Address multicast DNS queries are sent to over IPv6, as specified in RFC 6762, section 3.
+const MDNS_IPV6_ADDRESS: &str = "[ff02::fb]:5353";
+
+/// Most servers a resolution from the root servers asks, counting those asked to resolve the
+/// names of name servers handed off to without glue, before giving up. Keeps a referral loop or a
+/// delegation to a name server within the zone it serves from going on forever.
+const MAX_HOPS: usize = 32;
+
+/// Top bit of the class of multicast DNS records, which tells caches to flush the records they
+/// hold for the name, as specified in RFC 6762, section 10.2.
+const MDNS_CACHE_FLUSH_BIT: u16 = 0x8000;
+
+/// Whether the name lies under `.local`, which is resolved with multicast DNS on the local link
+/// rather than with the global DNS, as specified in RFC 6762, section 3.
+///
+/// # Argument
+/// * `domain_name`: The name to check.
+pub fn is_mdns_name(domain_name: &str) -> bool {
+    let domain_name = domain_name.trim_end_matches('.').to_ascii_lowercase();
+    domain_name == "local" || domain_name.ends_with(".local")
+}
+
+/// Options controlling how a query is sent.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct QueryOptions {
+    /// Set the DO bit in EDNS so that servers include DNSSEC records in their responses.
+    pub dnssec_ok: bool,
+
+    /// Set the CD bit so that validating servers skip DNSSEC validation.
+    pub checking_disabled: bool,
+
+    /// Ask servers to identify themselves with the NSID option (RFC 5001).
+    pub nsid: bool,
+
+    /// How to pad queries. Only applied to queries sent over TLS or HTTPS, see `PaddingPolicy`.
+    pub padding: PaddingPolicy,
+
+    /// Ask servers how long a connection may stay idle with edns-tcp-keepalive (RFC 7828) and
+    /// keep connections open accordingly. Only sent over connection-oriented sockets.
+    pub tcp_keepalive: bool,
+
+    /// Set the RD bit so that the server resolves the name on the client's behalf. Only meant for
+    /// queries sent to a recursive resolver with `Query::ask()`.
+    pub recursion_desired: bool,
+
+    /// Record every exchange with a server, e.g. to show the path a resolution took.
+    pub trace: Option<Trace>,
+
+    /// Count the responses of servers, how long they took and the queries they left unanswered.
+    pub metrics: Option<Metrics>,
+
+    /// Collect how many queries a resolution sent, their sizes and how long each server took.
+    pub stats: Option<StatsRecorder>,
+
+    /// How many more times to send a query to a server which does not respond, before giving up
+    /// on it. How long to wait for a response is up to the socket.
+    pub retries: u8,
+
+    /// Reach name servers over IPv6 when resolving from the root: start at the IPv6 address of
+    /// the root server and prefer AAAA glue over A glue. The socket must be bound to an IPv6
+    /// address. Resolutions whose root server is unreachable over IPv6 carry on over IPv4.
+    pub ipv6: bool,
+
+    /// Limit the rate of queries sent to each server, holding queries back until their turn.
+    pub throttle: Option<QueryThrottle>,
+}
+
+impl QueryOptions {
+    /// The EDNS parameters to send with the query, if any of the options require EDNS.
+    fn edns(&self) -> Option<Edns> {
+        if !self.dnssec_ok
+            && !self.nsid
+            && self.padding == PaddingPolicy::Disabled
+            && !self.tcp_keepalive
+        {
+            return None;
+        }
+
+        let mut edns = Edns::new(RECEIVE_BUFFER_SIZE as u16);
+        edns.dnssec_ok = self.dnssec_ok;
+        if self.nsid {
+            edns.options.push(EdnsOption::Nsid(vec![]));
+        }
+        if self.tcp_keepalive {
+            edns.options.push(EdnsOption::TcpKeepalive(None));
+        }
+        Some(edns)
+    }
+}
+
+/// DNS Query. The domain name may be borrowed or owned; an owned query, e.g. from
+/// `Query::into_owned()`, can be kept or moved across threads without tying it to where the name
+/// came from.
+#[derive(Debug, Clone)]
 pub struct Query<'a> {
     /// Domain name for the query.
-    pub domain_name: &'a str,
+    pub domain_name: Cow<'a, str>,
 
     /// Record type for the query.
     pub record_type: RecordType,
+
+    /// Options controlling how the query is sent.
+    pub options: QueryOptions,
 }
 
-impl Query<'_> {
-    /// Recursively resolves a DNS query for the given domain name and record type.
+impl<'a> Query<'a> {
+    /// A query for the given name and record type, with default options.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name to look up, borrowed (`&str`) or owned (`String`).
+    /// * `record_type`: The type of records to look up.
+    pub fn new(domain_name: impl Into<Cow<'a, str>>, record_type: RecordType) -> Query<'a> {
+        Query {
+            domain_name: domain_name.into(),
+            record_type,
+            options: QueryOptions::default(),
+        }
+    }
+
+    /// The query with its domain name owned, so that it no longer borrows from anything.
+    pub fn into_owned(self) -> Query<'static> {
+        Query {
+            domain_name: Cow::Owned(self.domain_name.into_owned()),
+            record_type: self.record_type,
+            options: self.options,
+        }
+    }
+
+    /// Recursively resolves a DNS query for the given domain name and record type. Names under
+    /// `.local` are resolved with multicast DNS instead.
     ///
     /// # Argument
     /// * `socket`: The socket on which to perform the DNS query.
     /// * `rand_seed`: The seed for RNG, if desired.
-    pub fn resolve<T>(
+    pub fn resolve(
+        &self,
+        socket: &mut dyn DnsTransport,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        if is_mdns_name(&self.domain_name) {
+            return self.resolve_multicast(socket, rand_seed);
+        }
+        let mut hops_left = MAX_HOPS;
+        self.resolve_from_root(socket, rand_seed, &mut hops_left)
+    }
+
+    /// Sends the query once to the given server and returns its response instead of resolving it
+    /// from the root servers, the way dig queries a specific server. Set `recursion_desired` in
+    /// the options to have a recursive resolver do the resolution. The response is returned
+    /// whatever its RCODE, e.g. NXDOMAIN, for the caller to interpret.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS query.
+    /// * `server_address`: The address of the server as understood by the socket, e.g.
+    ///   `192.0.2.1:53` or `tls://dns.google:853`.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn ask(
+        &self,
+        socket: &mut dyn DnsTransport,
+        server_address: &str,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        let _span = info_span!("ask", server = server_address).entered();
+        self.perform(socket, server_address, "", rand_seed)
+    }
+
+    /// Like `ask()`, but over an asynchronous transport, so that the query can be driven by tokio
+    /// along with others. Responses which cannot be the one to the query are ignored, as with
+    /// `ask()`. The wait for the response is not bounded; wrap the future in
+    /// `tokio::time::timeout()` to give up on the server.
+    ///
+    /// # Arguments
+    /// * `transport`: The transport on which to perform the DNS query.
+    /// * `server_address`: The address of the server, e.g. `192.0.2.1:53`.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    #[cfg(feature = "tokio")]
+    pub async fn ask_async(
+        &self,
+        transport: &mut dyn crate::async_transport::AsyncDnsTransport,
+        server_address: &str,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        info!("Looking up {} at {}", self.domain_name, server_address);
+        let Ok(query_bytes) = self.serialize(rand_seed) else {
+            return Err(DnsError::QuerySerialization
+                .with_context(ErrorContext::exchange(server_address, Stage::Serialize)));
+        };
+        transport
+            .send(&query_bytes, server_address)
+            .await
+            .map_err(|error| {
+                error.with_context(ErrorContext::exchange(server_address, Stage::Send))
+            })?;
+        let mut buf = [0; RECEIVE_BUFFER_SIZE];
+        loop {
+            let (received_bytes, source) =
+                transport.recv_from(&mut buf).await.map_err(|error| {
+                    error.with_context(ErrorContext::exchange(server_address, Stage::Receive))
+                })?;
+            let received = &buf[..received_bytes.min(buf.len())];
+            if let Some(packet) = accept_response(received, source, &query_bytes, server_address)? {
+                return Ok(packet);
+            }
+        }
+    }
+
+    /// Resolves a DNS query with a one-shot multicast DNS query, as specified in RFC 6762,
+    /// section 5.1. The query is sent from a port other than 5353, so responders answer it
+    /// directly over unicast like a conventional DNS server would. IPv6 is only tried when the
+    /// socket cannot send over IPv4.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS query.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    fn resolve_multicast(
         &self,
-        socket: &mut Box<dyn Socket<T>>,
+        socket: &mut dyn DnsTransport,
         rand_seed: Option<usize>,
     ) -> Result<Packet, DnsError> {
-        self.resolve_with_depth(socket, 0, rand_seed)
+        let query_bytes = self.send_multicast(socket, rand_seed)?;
+        let packet = read_multicast_response(socket, &query_bytes)?;
+        if packet.answers.is_empty() {
+            return Err(DnsError::UnknownDomainName);
+        }
+        Ok(packet)
+    }
+
+    /// Sends the query to the multicast DNS group without waiting for responses, which may come
+    /// from several responders. Returns the query as sent, to match the responses against. See
+    /// `read_multicast_response()`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS query.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub(crate) fn send_multicast(
+        &self,
+        socket: &mut dyn DnsTransport,
+        rand_seed: Option<usize>,
+    ) -> Result<Vec<u8>, DnsError> {
+        let Ok(query_bytes) = self.serialize(rand_seed) else {
+            return Err(DnsError::QuerySerialization);
+        };
+
+        info!(
+            "Looking up {} {} with multicast DNS",
+            self.domain_name, self.record_type
+        );
+        if socket.send(&query_bytes, MDNS_IPV4_ADDRESS).is_err() {
+            socket.send(&query_bytes, MDNS_IPV6_ADDRESS)?;
+        }
+        Ok(query_bytes)
     }
 
     /// Serialize the query into bytes to send to a DNS server.
     ///
     /// # Argument
     /// * `rand_seed`: The seed for RNG, if desired.
-    fn serialize(&self, rand_seed: Option<usize>) -> Result<Vec<u8>, DnsError> {
+    pub(crate) fn serialize(&self, rand_seed: Option<usize>) -> Result<Vec<u8>, DnsError> {
         let random_id = match rand_seed {
             None => rand::thread_rng().gen_range(0..=u16::MAX),
             Some(value) => ChaCha8Rng::seed_from_u64(value as u64).gen_range(0..=u16::MAX),
         };
 
-        let header = Header {
+        let edns = self.options.edns();
+
+        let mut header = Header {
             id: random_id,
             num_questions: 1,
+            num_additionals: edns.is_some() as u16,
             ..Default::default()
         };
+        if self.options.checking_disabled {
+            header.flags |= FLAG_CHECKING_DISABLED;
+        }
+        if self.options.recursion_desired {
+            header.flags |= FLAG_RECURSION_DESIRED;
+        }
 
         let question = Question {
-            name: RecordName {
-                name: self.domain_name,
-            }
-            .encode()?,
+            name: DnsName::from(self.domain_name.as_ref()),
             q_type: self.record_type,
             q_class: CLASS_IN,
         };
 
         // Serialize the header & question
-        let mut bytes: Vec<u8> =
-            Vec::with_capacity(size_of::<Header>() + size_of::<Question>() + question.name.len());
+        let mut bytes: Vec<u8> = Vec::with_capacity(
+            size_of::<Header>() + size_of::<Question>() + question.name.len() + 2,
+        );
 
         // Serialize the header
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.id) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.flags) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.num_questions) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.num_answers) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.num_authorities) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(header.num_additionals) else { return Err(DnsError::QuerySerialization) };
+        header.serialize(&mut bytes)?;
 
         // Serialize the question
-        bytes.extend(question.name);
-        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(question.q_type)) else { return Err(DnsError::QuerySerialization) };
-        let Ok(_) = bytes.write_u16::<BigEndian>(question.q_class) else { return Err(DnsError::QuerySerialization) };
+        question.serialize(&mut bytes)?;
+
+        // Serialize the OPT pseudo-record, if any. Padding depends on the length of everything
+        // else in the message, so it is added last.
+        if let Some(mut edns) = edns {
+            let mut unpadded = Vec::new();
+            edns.to_record()?.serialize(&mut unpadded)?;
+            if let Some(length) = self
+                .options
+                .padding
+                .padding_length(bytes.len() + unpadded.len())
+            {
+                edns.options.push(EdnsOption::Padding(length));
+            }
+            edns.to_record()?.serialize(&mut bytes)?;
+        }
 
         Ok(bytes)
     }
@@ -87,87 +351,232 @@ impl Query<'_> {
     /// Serializes then sends a DNS query over the wire to the given DNS server.
     ///
     /// # Arguments
-    /// * `dns_server_ip`: The IP address of the DNS server to send the query to.
+    /// * `server_address`: The address of the DNS server to send the query to, as understood by the
+    ///   socket, e.g. `192.0.2.1:53`.
     /// * `dns_server_name`: The name of the DNS server if known. Only used for logging purposes.
-    /// * `recursion_depth`: The current level of recursion. Only used for logging purposes.
     /// * `rand_seed`: The seed for RNG, if desired.
-    fn perform<T>(
+    pub(crate) fn perform(
         &self,
-        socket: &mut Box<dyn Socket<T>>,
-        dns_server_ip: &str,
+        socket: &mut dyn DnsTransport,
+        server_address: &str,
         dns_server_name: &str,
-        recursion_depth: u16,
         rand_seed: Option<usize>,
     ) -> Result<Packet, DnsError> {
         info!(
-            "{}Looking up {} at {} {}",
-            " ".repeat((recursion_depth * 4).into()),
+            "Looking up {} at {} {}",
             self.domain_name,
-            dns_server_ip,
-            if dns_server_name != "" {
+            server_address,
+            if !dns_server_name.is_empty() {
                 format!("({})", dns_server_name)
             } else {
                 "".to_owned()
             }
         );
 
-        let Ok(query_bytes) = self.serialize(rand_seed) else {
-            return Err(DnsError::QuerySerialization);
+        // RFC 7828 forbids sending edns-tcp-keepalive over UDP. Padding only hides anything from
+        // observers of encrypted transports (RFC 8467), so cleartext queries are left unpadded.
+        let encrypted =
+            server_address.starts_with("tls://") || server_address.starts_with("https://");
+        let query = Query {
+            domain_name: Cow::Borrowed(&self.domain_name),
+            record_type: self.record_type,
+            options: QueryOptions {
+                tcp_keepalive: self.options.tcp_keepalive && socket.is_connection_oriented(),
+                padding: match encrypted {
+                    true => self.options.padding,
+                    false => PaddingPolicy::Disabled,
+                },
+                ..self.options.clone()
+            },
+        };
+        let Ok(query_bytes) = query.serialize(rand_seed) else {
+            return Err(DnsError::QuerySerialization
+                .with_context(ErrorContext::exchange(server_address, Stage::Serialize)));
         };
 
-        let Ok(_) = socket.send(&query_bytes, &format!("{}:53", dns_server_ip)) else {
-            return Err(DnsError::SocketSend);
+        // Queries which go unanswered are sent again, as UDP datagrams may be lost on the way. A
+        // server found to refuse queries or to be out of reach is not asked again.
+        let mut buf = vec![0; receive_buffer_size(socket, RECEIVE_BUFFER_SIZE)];
+        let mut attempts = 0;
+        let (received_bytes, packet, sent_at) = loop {
+            if let Some(throttle) = &self.options.throttle {
+                throttle.wait(server_address);
+            }
+            let sent_at = Instant::now();
+            if let Some(stats) = &self.options.stats {
+                stats.record_query(server_address, query_bytes.len());
+            }
+            let received = socket
+                .send(&query_bytes, server_address)
+                .map_err(|error| {
+                    error.with_context(ErrorContext::exchange(server_address, Stage::Send))
+                })
+                .and_then(|_| receive_response(socket, &mut buf, &query_bytes, server_address));
+            let timed_out = matches!(&received, Err(error) if error.is_timeout());
+            if let (true, Some(metrics)) = (timed_out, &self.options.metrics) {
+                metrics.record_upstream_timeout();
+            }
+            if let (true, Some(stats)) = (timed_out, &self.options.stats) {
+                stats.record_timeout(server_address);
+            }
+            match received {
+                Ok((received_bytes, packet)) => break (received_bytes, packet, sent_at),
+                Err(error) if attempts < self.options.retries && error.is_retryable() => {
+                    attempts += 1;
+                    info!(
+                        "No response from {} ({}), retrying ({} of {})",
+                        server_address, error, attempts, self.options.retries
+                    );
+                }
+                Err(error) => return Err(error),
+            }
+        };
+        let (received_bytes, packet) = if packet.header.has_flag(FLAG_TRUNCATED) {
+            retry_over_tcp(socket, &mut buf, &query_bytes, server_address)?
+                .unwrap_or((received_bytes, packet))
+        } else {
+            (received_bytes, packet)
         };
 
-        // 1024 is a good rule of thumb max-size for a DNS answer. For a more serious DNS resolver,
-        // this mechanism should be improved.
-        let mut buf = [0; 1024];
-        match (*socket).recv_from(&mut buf) {
-            Ok(_) => {
+        info!(
+            "Queried \"{:?}\" {} received: {:?}",
+            query_bytes,
+            server_address,
+            &buf[..received_bytes]
+        );
+        if let Some(metrics) = &self.options.metrics {
+            metrics.record_upstream_response(sent_at.elapsed());
+        }
+        if let Some(stats) = &self.options.stats {
+            stats.record_response(server_address, received_bytes, sent_at.elapsed());
+        }
+        if let Some(trace) = &self.options.trace {
+            trace.push(Exchange {
+                server_address: server_address.to_owned(),
+                server_name: dns_server_name.to_owned(),
+                domain_name: self.domain_name.to_string(),
+                record_type: self.record_type,
+                response: packet.clone(),
+                received_bytes,
+                elapsed: sent_at.elapsed(),
+            });
+        }
+        if socket.is_connection_oriented() {
+            let keepalive = match packet.edns() {
+                Ok(Some(edns)) if query.options.tcp_keepalive => edns.tcp_keepalive(),
+                _ => None,
+            };
+            socket.set_idle_timeout(server_address, keepalive);
+        }
+        if let Ok(Some(edns)) = packet.edns() {
+            if let Some(nsid) = edns.nsid() {
                 info!(
-                    "Queried \"{:?}\" {}:53 received: {:?}",
-                    query_bytes, dns_server_ip, buf
+                    "{} identified itself as {}",
+                    server_address,
+                    format_nsid(nsid)
                 );
-                return Packet::parse(&buf);
-            }
-            Err(_) => {
-                return Err(DnsError::SocketRead);
             }
+        }
+        Ok(packet)
+    }
+
+    /// Whether the response answers the query: it holds a record of the type in question, or any
+    /// record at all for ANY queries. Servers may answer ANY queries with whichever types they
+    /// like, even a single HINFO record, as specified in RFC 8482.
+    ///
+    /// # Argument
+    /// * `packet`: The response to the query.
+    fn is_answered_by(&self, packet: &Packet) -> bool {
+        packet
+            .answers
+            .iter()
+            .any(|record| self.record_type == RecordType::ANY || record.r_type == self.record_type)
+    }
+
+    /// The address of a name server among the given glue records. AAAA records are preferred over
+    /// A records when resolving over IPv6.
+    ///
+    /// # Argument
+    /// * `records`: The records to look for the address in.
+    fn glue_address(&self, records: &[Record]) -> Option<IpAddr> {
+        let ipv6_address = match self.options.ipv6 {
+            true => records.get_first_aaaa_record().and_then(Record::to_ip_addr),
+            false => None,
         };
+        ipv6_address.or_else(|| records.get_first_a_record().and_then(Record::to_ip_addr))
     }
 
-    /// Recursively resolves a DNS query for the given domain name and record type.
+    /// Recursively resolves a DNS query for the given domain name and record type, starting from
+    /// a root server. The resolution is recorded as a `resolve` span, with a `hop` span for each
+    /// server asked along the way. Resolving the name of a server a hop was handed off to happens
+    /// within that hop's span. Over IPv6, a root server which cannot be reached at its IPv6
+    /// address, e.g. as the host has no IPv6 route, is asked again at its IPv4 address, and the
+    /// resolution carries on over IPv4. Fails with `TooManyReferrals` once `hops_left` servers
+    /// have been asked.
     ///
     /// # Arguments
     /// * `socket`: The socket to perform network calls on.
-    /// * `recursion_depth`: The recursion depth. Used only for logging purposes.
     /// * `rand_seed`: The seed for RNG, if desired.
-    fn resolve_with_depth<T>(
+    /// * `hops_left`: How many more servers may be asked, shared with the resolutions this one
+    ///   starts.
+    fn resolve_from_root(
         &self,
-        socket: &mut Box<dyn Socket<T>>,
-        recursion_depth: u16,
+        socket: &mut dyn DnsTransport,
         rand_seed: Option<usize>,
+        hops_left: &mut usize,
     ) -> Result<Packet, DnsError> {
+        let _span = info_span!(
+            "resolve",
+            name = self.domain_name.as_ref(),
+            record_type = %self.record_type
+        )
+        .entered();
+        let mut zone = ".".to_owned();
         let root_server = RootServer::random(rand_seed);
-        let mut name_server_ip: String = (*root_server.0).to_owned();
+        let root_ipv6_address = root_server.1.ipv6_address().filter(|_| self.options.ipv6);
+        let mut name_server_ip: String = match root_ipv6_address {
+            Some(ipv6_address) => ipv6_address.to_owned(),
+            None => (*root_server.0).to_owned(),
+        };
         let mut name_server_host: String;
         let RootServerName(name_server_str) = *root_server.1;
         name_server_host = name_server_str.to_owned();
         loop {
-            match self.perform(
+            let Some(remaining) = hops_left.checked_sub(1) else {
+                warn!(
+                    "Gave up resolving {} after {} hops",
+                    self.domain_name, MAX_HOPS
+                );
+                return Err(DnsError::TooManyReferrals);
+            };
+            *hops_left = remaining;
+            let hop = info_span!(
+                "hop",
+                server = %name_server_ip,
+                name = %name_server_host,
+                zone = %zone,
+                elapsed_ms = field::Empty
+            )
+            .entered();
+            let started_at = Instant::now();
+            let result = self.perform(
                 socket,
-                &name_server_ip,
+                &server_address(&name_server_ip),
                 &name_server_host,
-                recursion_depth,
                 rand_seed,
-            ) {
+            );
+            hop.record("elapsed_ms", started_at.elapsed().as_millis() as u64);
+            match result {
                 Ok(packet) => {
-                    if packet.answers.get_first_a_record().is_some() {
+                    if let Some(ns_record) = packet.authorities.get_first_ns_record() {
+                        zone = String::from_utf8_lossy(&ns_record.name).into_owned();
+                    }
+                    if self.is_answered_by(&packet) {
                         return Ok(packet);
-                    } else if let Some(new_name_server) = packet.additionals.get_first_a_record() {
+                    } else if let Some(new_name_server) = self.glue_address(&packet.additionals) {
                         // There was no A record returned. The nameserver didn't have an A record
                         // for the domain. We'll have to try the next nameserver.
-                        name_server_ip = new_name_server.ip_address();
+                        name_server_ip = new_name_server.to_string();
                         name_server_host = "".to_owned();
                     } else if let Some(ns_record) = packet.authorities.get_first_ns_record() {
                         // At this point, the authority doesn't know which DNS server to point us to, so they're
@@ -176,41 +585,81 @@ impl Query<'_> {
                         // of the original DNS request will continue.
                         let mut cursor = Cursor::new(&ns_record.data[..]);
                         let nameserver_name_str_bytes = RecordName::read_and_advance(&mut cursor)?;
-                        let Ok(nameserver_name_str) = std::str::from_utf8(&nameserver_name_str_bytes) else {
+                        let Ok(nameserver_name_str) =
+                            std::str::from_utf8(&nameserver_name_str_bytes)
+                        else {
                             return Err(DnsError::InvalidByteInName);
                         };
 
                         info!(
-                            "{}{} handed us off to {}",
-                            " ".repeat(((recursion_depth) * 4).into()),
-                            name_server_ip,
-                            nameserver_name_str,
+                            "{} handed us off to {}",
+                            name_server_ip, nameserver_name_str,
                         );
 
-                        let new_query = Query {
-                            domain_name: nameserver_name_str,
-                            record_type: RecordType::A,
-                        };
-                        let name_server_resolved_packet =
-                            new_query.resolve_with_depth(socket, recursion_depth + 1, rand_seed)?;
-                        let Some(name_server_a_record) = name_server_resolved_packet.answers.get_first_a_record() else {
+                        // Over IPv6, the AAAA records of the name server are looked up first,
+                        // falling back to its A records when it has none.
+                        let mut name_server_address = None;
+                        if self.options.ipv6 {
+                            let new_query = Query {
+                                domain_name: nameserver_name_str.into(),
+                                record_type: RecordType::AAAA,
+                                options: self.options.clone(),
+                            };
+                            if let Ok(name_server_resolved_packet) =
+                                new_query.resolve_from_root(socket, rand_seed, hops_left)
+                            {
+                                name_server_address = name_server_resolved_packet
+                                    .answers
+                                    .get_first_aaaa_record()
+                                    .and_then(Record::to_ip_addr);
+                            }
+                        }
+                        if name_server_address.is_none() {
+                            let new_query = Query {
+                                domain_name: nameserver_name_str.into(),
+                                record_type: RecordType::A,
+                                options: self.options.clone(),
+                            };
+                            let name_server_resolved_packet =
+                                new_query.resolve_from_root(socket, rand_seed, hops_left)?;
+                            name_server_address = name_server_resolved_packet
+                                .answers
+                                .get_first_a_record()
+                                .and_then(Record::to_ip_addr);
+                        }
+                        let Some(name_server_address) = name_server_address else {
                             return Err(DnsError::UnknownDomainName);
                         };
 
                         name_server_host = nameserver_name_str.to_owned();
-                        name_server_ip = name_server_a_record.ip_address();
+                        name_server_ip = name_server_address.to_string();
 
-                        info!(
-                            "{}Resolved {} to {}",
-                            " ".repeat(((recursion_depth + 1) * 4).into()),
-                            nameserver_name_str,
-                            name_server_ip,
-                        )
+                        info!("Resolved {} to {}", nameserver_name_str, name_server_ip,)
                     } else {
                         return Err(DnsError::UnknownDomainName);
                     }
                 }
 
+                Err(error)
+                    if root_ipv6_address == Some(name_server_ip.as_str())
+                        && error.category() == ErrorCategory::Transient
+                        && !error.is_retryable() =>
+                {
+                    info!(
+                        "{} is unreachable, falling back to IPv4: {}",
+                        name_server_ip, error
+                    );
+                    let query = Query {
+                        domain_name: self.domain_name.clone(),
+                        record_type: self.record_type,
+                        options: QueryOptions {
+                            ipv6: false,
+                            ..self.options.clone()
+                        },
+                    };
+                    return query.resolve_from_root(socket, rand_seed, hops_left);
+                }
+
                 Err(error) => {
                     return Err(error);
                 }
@@ -219,12 +668,206 @@ impl Query<'_> {
     }
 }
 
+/// Wait for the response to a query sent to a server. Datagrams which cannot be it are ignored
+/// and the wait goes on: those from another address than the server's, and those which do not
+/// carry the ID and the question of the query. Such datagrams may be forged by an attacker
+/// racing the server to poison a cache (RFC 5452, section 9.1).
+///
+/// # Arguments
+/// * `socket`: The socket the query was sent on.
+/// * `buf`: The buffer to receive the response in.
+/// * `query_bytes`: The query as sent.
+/// * `server_address`: The address the query was sent to.
+pub(crate) fn receive_response(
+    socket: &mut dyn DnsTransport,
+    buf: &mut [u8],
+    query_bytes: &[u8],
+    server_address: &str,
+) -> Result<(usize, Packet), DnsError> {
+    loop {
+        let (received_bytes, source) = socket.recv_from(buf).map_err(|error| {
+            error.with_context(ErrorContext::exchange(server_address, Stage::Receive))
+        })?;
+        let received = &buf[..received_bytes.min(buf.len())];
+        if let Some(packet) = accept_response(received, source, query_bytes, server_address)? {
+            return Ok((received_bytes, packet));
+        }
+    }
+}
+
+/// Parse a datagram received in response to a query, or return `None` if it cannot be the
+/// response. See `receive_response()`.
+///
+/// # Arguments
+/// * `received`: The datagram.
+/// * `source`: Where the datagram came from.
+/// * `query_bytes`: The query as sent.
+/// * `server_address`: The address the query was sent to.
+pub(crate) fn accept_response(
+    received: &[u8],
+    source: SocketAddr,
+    query_bytes: &[u8],
+    server_address: &str,
+) -> Result<Option<Packet>, DnsError> {
+    // Servers given by name, as over TLS or HTTPS, are reached over a connection of their own.
+    let server = server_address.parse::<SocketAddr>().ok();
+    if server.is_some_and(|server| !is_same_address(server, source)) {
+        warn!(
+            "Ignored a response from {} rather than {}",
+            source, server_address
+        );
+        return Ok(None);
+    }
+    match_query(received, source, query_bytes, server_address)
+}
+
+/// Parse a response and check that it carries the ID and the question of the query, or return
+/// `None` if it does not. See `accept_response()`.
+///
+/// # Arguments
+/// * `received`: The response.
+/// * `source`: Where the response came from.
+/// * `query_bytes`: The query as sent.
+/// * `server_address`: The address the query was sent to, to tell in errors.
+fn match_query(
+    received: &[u8],
+    source: SocketAddr,
+    query_bytes: &[u8],
+    server_address: &str,
+) -> Result<Option<Packet>, DnsError> {
+    let mut cursor = Cursor::new(query_bytes);
+    let query_header = Header::read_and_advance(&mut cursor)?;
+    let question = Question::read_and_advance(&mut cursor)?;
+    let id = Header::read_and_advance(&mut Cursor::new(received)).map(|header| header.id);
+    if id.ok() != Some(query_header.id) {
+        warn!("Ignored a response from {} with the wrong ID", source);
+        return Ok(None);
+    }
+    let packet = Packet::parse(received).map_err(|error| {
+        error.with_context(ErrorContext::exchange(server_address, Stage::Parse))
+    })?;
+    if packet.questions != [question] {
+        warn!("Ignored a response from {} to another question", source);
+        return Ok(None);
+    }
+    Ok(Some(packet))
+}
+
+/// The size of the buffer to receive responses on the socket in. Over TCP, it fits the largest
+/// message there can be, so that large responses, the very reason to use TCP, are read whole.
+///
+/// # Arguments
+/// * `socket`: The socket responses are read from.
+/// * `udp_size`: The size of the buffer for datagrams.
+pub(crate) fn receive_buffer_size(socket: &dyn DnsTransport, udp_size: usize) -> usize {
+    match socket.is_connection_oriented() {
+        true => TCP_RECEIVE_BUFFER_SIZE,
+        false => udp_size,
+    }
+}
+
+/// Send a query again over TCP, as its response over UDP was truncated (RFC 7766, section 5), and
+/// wait for the whole response. The connection is closed once the response is in. Returns `None`
+/// when the socket cannot switch to TCP, the truncated response then being all there is.
+///
+/// # Arguments
+/// * `socket`: The socket the query was sent on over UDP.
+/// * `buf`: The buffer to receive the response in. It is grown to fit any message over TCP.
+/// * `query_bytes`: The query as sent.
+/// * `server_address`: The address the query was sent to.
+pub(crate) fn retry_over_tcp(
+    socket: &mut dyn DnsTransport,
+    buf: &mut Vec<u8>,
+    query_bytes: &[u8],
+    server_address: &str,
+) -> Result<Option<(usize, Packet)>, DnsError> {
+    if socket.is_connection_oriented() {
+        return Ok(None);
+    }
+    socket.set_tcp(true);
+    if !socket.is_connection_oriented() {
+        return Ok(None);
+    }
+    info!(
+        "The response from {} was truncated, retrying over TCP",
+        server_address
+    );
+    buf.resize(TCP_RECEIVE_BUFFER_SIZE, 0);
+    let received = socket
+        .send(query_bytes, server_address)
+        .map_err(|error| error.with_context(ErrorContext::exchange(server_address, Stage::Send)))
+        .and_then(|_| receive_response(socket, buf, query_bytes, server_address));
+    socket.set_idle_timeout(server_address, None);
+    socket.set_tcp(false);
+    received.map(Some)
+}
+
+/// Whether two socket addresses are the same, an IPv4 address being the same as its IPv4-mapped
+/// IPv6 address, as a socket bound to an IPv6 address sees IPv4 servers.
+///
+/// # Arguments
+/// * `a`: The first address.
+/// * `b`: The second address.
+fn is_same_address(a: SocketAddr, b: SocketAddr) -> bool {
+    a.ip().to_canonical() == b.ip().to_canonical() && a.port() == b.port()
+}
+
+/// The address to send queries to a name server at, given its IP address. IPv6 addresses are
+/// enclosed in brackets so that the port can be told apart.
+///
+/// # Argument
+/// * `ip`: The IP address of the name server.
+pub(crate) fn server_address(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, DEFAULT_UDP_PORT).to_string(),
+        Err(_) => format!("{}:{}", ip, DEFAULT_UDP_PORT),
+    }
+}
+
+/// Read the next response to a query sent to the multicast DNS group. Responses to a query sent
+/// from a port other than 5353 repeat its ID and question (RFC 6762, section 6.7), so those which
+/// do not are ignored. The cache-flush bit is cleared from the class of the records, as it is not
+/// part of the class as far as anyone else is concerned.
+///
+/// # Arguments
+/// * `socket`: The socket the query was sent on.
+/// * `query_bytes`: The query as sent.
+pub(crate) fn read_multicast_response(
+    socket: &mut dyn DnsTransport,
+    query_bytes: &[u8],
+) -> Result<Packet, DnsError> {
+    let mut buf = [0; RECEIVE_BUFFER_SIZE];
+    let (mut packet, responder) = loop {
+        let (size, responder) = socket.recv_from(&mut buf)?;
+        let received = &buf[..size.min(buf.len())];
+        let Some(packet) = match_query(received, responder, query_bytes, &responder.to_string())?
+        else {
+            continue;
+        };
+        break (packet, responder);
+    };
+    info!("Received a multicast DNS response from {}", responder);
+
+    for record in packet
+        .answers
+        .iter_mut()
+        .chain(packet.authorities.iter_mut())
+        .chain(packet.additionals.iter_mut())
+    {
+        if record.r_type != RecordType::OPT {
+            record.r_class &= !MDNS_CACHE_FLUSH_BIT;
+        }
+    }
+    Ok(packet)
+}
+
 /// Validate parsing of an incomplete header
 #[test]
 fn test_query_serialization() {
     let query = Query {
-        domain_name: "example.com",
+        domain_name: "example.com".into(),
         record_type: RecordType::A,
+        options: QueryOptions::default(),
     };
 
     let expected = [
@@ -243,24 +886,179 @@ fn test_query_serialization() {
     );
 }
 
+/// Validate that an owned query outlives the name it was made from and moves across threads.
+#[test]
+fn test_owned_query() -> Result<(), DnsError> {
+    let query = {
+        let domain_name = String::from("example.com");
+        Query::new(domain_name.as_str(), RecordType::A).into_owned()
+    };
+    let copy = query.clone();
+    let serialized = std::thread::spawn(move || query.serialize(Some(0)));
+    let Ok(serialized) = serialized.join() else {
+        panic!("Serializing the query panicked")
+    };
+    assert_eq!(serialized?, copy.serialize(Some(0))?);
+    assert_eq!(copy.domain_name, "example.com");
+    Ok(())
+}
+
+/// Validate serialization of a query with the DO and CD bits set.
+#[test]
+fn test_query_serialization_with_dnssec_options() {
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            dnssec_ok: true,
+            checking_disabled: true,
+            ..Default::default()
+        },
+    };
+
+    let expected = [
+        // Header                              Question...
+        // ID Flag     Qs    Answ  Auth  Addl  example.com
+        59, 108, 0, 16, 0, 1, 0, 0, 0, 0, 0, 1, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111,
+        //             ...Question  OPT
+        //             Type  Class  Root Type   Size    Flags       Len
+        109, 0, 0, 1, 0, 1, 0, 0, 41, 4, 0, 0, 0, 128, 0, 0, 0,
+    ];
+
+    assert_eq!(
+        query.serialize(Some(0)).unwrap_or_default().as_slice(),
+        expected
+    );
+}
+
+/// Validate serialization of a query requesting the NSID option.
+#[test]
+fn test_query_serialization_with_nsid() {
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            nsid: true,
+            ..Default::default()
+        },
+    };
+
+    let serialized = query.serialize(Some(0)).unwrap_or_default();
+    //                                       OPT
+    //                                       Root Type   Size    Flags       Len   NSID  Len
+    assert_eq!(
+        serialized[29..],
+        [0, 0, 41, 4, 0, 0, 0, 0, 0, 0, 4, 0, 3, 0, 0]
+    );
+}
+
+/// Validate that padded queries are a multiple of the block length.
+#[test]
+fn test_query_serialization_with_padding() {
+    for domain_name in [
+        "example.com",
+        "a.very.long.name.to.make.sure.padding.follows.example.com",
+    ] {
+        let query = Query {
+            domain_name: domain_name.into(),
+            record_type: RecordType::A,
+            options: QueryOptions {
+                padding: PaddingPolicy::BlockLength(128),
+                ..Default::default()
+            },
+        };
+
+        let serialized = query.serialize(Some(0)).unwrap_or_default();
+        assert_eq!(serialized.len(), 128);
+    }
+}
+
+/// Validate that queries are only padded when sent over an encrypted transport.
+#[test]
+fn test_querying_pads_only_encrypted_queries() {
+    use std::cell::RefCell;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+
+    /// A socket on which every response is lost, keeping the queries sent.
+    struct SilentSocket(Rc<RefCell<Vec<Vec<u8>>>>);
+
+    impl DnsTransport for SilentSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            self.0.borrow_mut().push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            Err(DnsError::SocketRead(None))
+        }
+    }
+
+    let sent = Rc::new(RefCell::new(vec![]));
+    let mut socket = SilentSocket(Rc::clone(&sent));
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            padding: PaddingPolicy::BlockLength(128),
+            ..Default::default()
+        },
+    };
+    for server in [
+        "192.0.2.53:53",
+        "tls://192.0.2.53:853",
+        "https://dns.example/dns-query",
+    ] {
+        assert!(query.ask(&mut socket, server, Some(0)).is_err());
+    }
+
+    // The query over UDP carries no OPT record, let alone a padding option.
+    let sent = sent.borrow();
+    assert_eq!(sent[0].len(), 29);
+    assert_eq!(sent[0][10..12], [0, 0]);
+    assert_eq!(sent[1].len(), 128);
+    assert_eq!(sent[2].len(), 128);
+}
+
+/// Validate that edns-tcp-keepalive is only requested when asked for.
+#[test]
+fn test_query_serialization_with_tcp_keepalive() {
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            tcp_keepalive: true,
+            ..Default::default()
+        },
+    };
+
+    let serialized = query.serialize(Some(0)).unwrap_or_default();
+    //                                       OPT
+    //                                       Root Type   Size    Flags       Len   Keep  Len
+    assert_eq!(
+        serialized[29..],
+        [0, 0, 41, 4, 0, 0, 0, 0, 0, 0, 4, 0, 11, 0, 0]
+    );
+}
+
 /// Validate the full flow of querying DNS with a mock socket.
 #[test]
 fn test_querying_domain_with_ns_delegation() -> Result<(), DnsError> {
     use crate::mock_data;
     use crate::socket::MockSocket;
 
-    let data = mock_data::CAPTURED_DATA_FOR_TWITTER;
+    let data = mock_data::captured_data_for_twitter();
 
     let mut socket = MockSocket::bind("")?;
     socket.register_response_data(data);
 
     let query = Query {
-        domain_name: "twitter.com",
+        domain_name: "twitter.com".into(),
         record_type: RecordType::A,
+        options: QueryOptions::default(),
     };
 
-    let mut boxed_socket: Box<dyn Socket<MockSocket>> = Box::new(socket);
-    let packet = query.resolve(&mut boxed_socket, Some(0))?;
+    let packet = query.resolve(&mut socket, Some(0))?;
 
     let a_record = packet.answers.get_first_a_record().unwrap();
     assert_eq!(a_record.ip_address(), "104.244.42.193");
@@ -269,3 +1067,821 @@ fn test_querying_domain_with_ns_delegation() -> Result<(), DnsError> {
     assert_eq!(a_record.r_type, RecordType::A);
     Ok(())
 }
+
+/// Validate that `.local` names are resolved with multicast DNS.
+#[test]
+fn test_querying_local_name_with_mdns() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    assert!(is_mdns_name("Printer.LOCAL."));
+    assert!(!is_mdns_name("notlocal"));
+
+    let query = Query {
+        domain_name: "printer.local".into(),
+        record_type: RecordType::A,
+        options: QueryOptions::default(),
+    };
+    let query_bytes = query.serialize(Some(0))?;
+    let mut response = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: crate::header::FLAG_RESPONSE | crate::header::FLAG_AUTHORITATIVE,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from("printer.local"),
+            q_type: RecordType::A,
+            q_class: CLASS_IN,
+        }],
+        answers: vec![Record {
+            name: DnsName::from("printer.local"),
+            r_type: RecordType::A,
+            r_class: MDNS_CACHE_FLUSH_BIT | CLASS_IN,
+            ttl: Ttl::from(120),
+            data: vec![192, 168, 1, 20],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()?;
+    response.resize(RECEIVE_BUFFER_SIZE, 0);
+
+    let data = [(
+        MockKey {
+            query_bytes,
+            server_ip: MDNS_IPV4_ADDRESS.to_owned(),
+        },
+        MockData { data: response },
+    )];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let packet = query.resolve(&mut socket, Some(0))?;
+    let a_record = packet.answers.get_first_a_record().unwrap();
+    assert_eq!(a_record.ip_address(), "192.168.1.20");
+    assert_eq!(a_record.r_class, CLASS_IN);
+    Ok(())
+}
+
+/// Validate that ANY queries are answered by records of any type, including minimal HINFO
+/// responses and types toy_dns does not know.
+#[test]
+fn test_querying_any() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::ANY,
+        options: QueryOptions::default(),
+    };
+    let record = |r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from("example.com"),
+        r_type,
+        r_class: CLASS_IN,
+        ttl: Ttl::from(3600),
+        data: data.to_vec(),
+    };
+    let answers = vec![
+        record(RecordType::HINFO, b"\x07RFC8482\x00"),
+        record(RecordType::Unknown(65280), &[0, 1, 0]),
+    ];
+
+    let query_bytes = query.serialize(Some(0))?;
+    let mut response = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: crate::header::FLAG_RESPONSE | crate::header::FLAG_AUTHORITATIVE,
+            ..Default::default()
+        },
+        questions: Packet::parse(&query_bytes)?.questions,
+        answers: answers.clone(),
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()?;
+    response.resize(RECEIVE_BUFFER_SIZE, 0);
+
+    let data = [(
+        MockKey {
+            query_bytes,
+            server_ip: "192.58.128.30:53".to_owned(),
+        },
+        MockData { data: response },
+    )];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let packet = query.resolve(&mut socket, Some(0))?;
+    assert_eq!(packet.answers, answers);
+    assert_eq!(
+        packet.answers[0].to_presentation(),
+        r#"example.com. 3600 IN HINFO "RFC8482" """#
+    );
+    assert_eq!(
+        packet.answers[1].to_presentation(),
+        r"example.com. 3600 IN TYPE65280 \# 3 000100"
+    );
+    Ok(())
+}
+
+/// Validate that a query is sent again when the server does not respond, until the retries run
+/// out.
+#[test]
+fn test_querying_with_retries() -> Result<(), DnsError> {
+    use std::cell::Cell;
+    use std::net::SocketAddr;
+    use std::rc::Rc;
+
+    /// A socket on which every response is lost, or refused, counting the queries sent.
+    struct LossySocket {
+        sent: Rc<Cell<usize>>,
+        refused: bool,
+    }
+
+    impl DnsTransport for LossySocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            self.sent.set(self.sent.get() + 1);
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            let refused = std::io::ErrorKind::ConnectionRefused;
+            match self.refused {
+                true => Err(DnsError::SocketRead(Some(refused.into()))),
+                false => Err(DnsError::SocketRead(None)),
+            }
+        }
+    }
+
+    let mut socket = LossySocket {
+        sent: Rc::new(Cell::new(0)),
+        refused: false,
+    };
+    let sent = Rc::clone(&socket.sent);
+
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            retries: 2,
+            ..Default::default()
+        },
+    };
+    assert_eq!(
+        query.ask(&mut socket, "192.0.2.53:53", Some(0)),
+        Err(DnsError::SocketRead(None))
+    );
+    assert_eq!(sent.get(), 3);
+
+    // A server which refuses queries is not asked again.
+    socket.refused = true;
+    sent.set(0);
+    assert!(query.ask(&mut socket, "192.0.2.53:53", Some(0)).is_err());
+    assert_eq!(sent.get(), 1);
+    Ok(())
+}
+
+/// Validate that only reads which time out count as timeouts, and not those which are refused.
+#[test]
+fn test_querying_counts_timeouts() {
+    use crate::stats::StatsRecorder;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+
+    /// A socket on which every read fails the same way.
+    struct FailingSocket(ErrorKind);
+
+    impl DnsTransport for FailingSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, _buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            Err(DnsError::SocketRead(Some(self.0.into())))
+        }
+    }
+
+    for (kind, timeouts) in [
+        (ErrorKind::WouldBlock, 1),
+        (ErrorKind::TimedOut, 1),
+        (ErrorKind::ConnectionRefused, 0),
+    ] {
+        let stats = StatsRecorder::default();
+        let query = Query {
+            domain_name: "example.com".into(),
+            record_type: RecordType::A,
+            options: QueryOptions {
+                stats: Some(stats.clone()),
+                ..Default::default()
+            },
+        };
+        assert!(query
+            .ask(&mut FailingSocket(kind), "192.0.2.53:53", Some(0))
+            .is_err());
+        assert_eq!(stats.take().servers[0].timeouts, timeouts);
+    }
+}
+
+/// Validate that a query whose first response is lost is answered by the retry.
+#[test]
+fn test_querying_with_lost_response() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            retries: 1,
+            ..Default::default()
+        },
+    };
+    let answers = vec![Record {
+        name: DnsName::from("example.com"),
+        r_type: RecordType::A,
+        r_class: CLASS_IN,
+        ttl: Ttl::from(3600),
+        data: vec![192, 0, 2, 1],
+    }];
+    let query_bytes = query.serialize(Some(0))?;
+    let mut response = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: crate::header::FLAG_RESPONSE,
+            ..Default::default()
+        },
+        questions: Packet::parse(&query_bytes)?.questions,
+        answers: answers.clone(),
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()?;
+    response.resize(RECEIVE_BUFFER_SIZE, 0);
+
+    let key = MockKey {
+        query_bytes,
+        server_ip: "192.0.2.53:53".to_owned(),
+    };
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data([
+        (key.clone(), MockData { data: vec![] }),
+        (key.clone(), MockData { data: response }),
+    ]);
+
+    let packet = query.ask(&mut socket, "192.0.2.53:53", Some(0))?;
+    assert_eq!(packet.answers, answers);
+    assert_eq!(socket.send_count(&key), 2);
+    Ok(())
+}
+
+/// Validate that errors tell which server the query failed at, at which stage, and where a
+/// malformed response went wrong.
+#[test]
+fn test_querying_error_context() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+
+    let query = Query::new("example.com", RecordType::A);
+    let query_bytes = query.serialize(Some(0))?;
+    // An answer whose owner name points forward, past itself.
+    let mut response = query_bytes.clone();
+    response[7] = 1;
+    response.extend([0xC0, 0xFF]);
+    response.resize(0x100, 0);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response(
+        MockKey {
+            query_bytes,
+            server_ip: "192.0.2.53:53".to_owned(),
+        },
+        MockData { data: response },
+    );
+
+    let error = query
+        .ask(&mut socket, "192.0.2.53:53", Some(0))
+        .unwrap_err();
+    assert_eq!(error, DnsError::ForwardPointer);
+    let context = error.context().unwrap();
+    assert_eq!(context.stage, Some(Stage::Parse));
+    assert_eq!(context.server.as_deref(), Some("192.0.2.53:53"));
+    assert_eq!(context.section, Some("answer"));
+    assert_eq!(context.index, Some(0));
+    // The header and the question take 29 octets, after which the pointer is read.
+    assert_eq!(context.offset, Some(31));
+
+    let error = query
+        .ask(&mut socket, "192.0.2.54:53", Some(0))
+        .unwrap_err();
+    assert_eq!(error, DnsError::SocketSend(None));
+    assert_eq!(
+        error.context(),
+        Some(&ErrorContext::exchange("192.0.2.54:53", Stage::Send))
+    );
+    Ok(())
+}
+
+/// Validate resolving over IPv6: the resolution starts at the IPv6 address of the root server
+/// and follows AAAA glue rather than A glue.
+#[test]
+fn test_querying_over_ipv6() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    let query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            ipv6: true,
+            ..Default::default()
+        },
+    };
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: CLASS_IN,
+        ttl: Ttl::from(3600),
+        data: data.to_vec(),
+    };
+    let answers = vec![record("example.com", RecordType::A, &[192, 0, 2, 1])];
+
+    let query_bytes = query.serialize(Some(0))?;
+    let response = |packet: Packet| -> Result<Vec<u8>, DnsError> {
+        let mut response = packet.serialize()?;
+        response.resize(RECEIVE_BUFFER_SIZE, 0);
+        Ok(response)
+    };
+    let header = Header {
+        id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+        flags: crate::header::FLAG_RESPONSE,
+        ..Default::default()
+    };
+    let referral = response(Packet {
+        header: header.clone(),
+        questions: Packet::parse(&query_bytes)?.questions,
+        answers: vec![],
+        authorities: vec![record("com", RecordType::NS, b"\x03ns1\x03com\x00")],
+        additionals: vec![
+            record("ns1.com", RecordType::A, &[192, 0, 2, 53]),
+            record(
+                "ns1.com",
+                RecordType::AAAA,
+                &[
+                    0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x53,
+                ],
+            ),
+        ],
+    })?;
+    let answer = response(Packet {
+        header,
+        questions: Packet::parse(&query_bytes)?.questions,
+        answers: answers.clone(),
+        authorities: vec![],
+        additionals: vec![],
+    })?;
+
+    let data = [
+        (
+            MockKey {
+                query_bytes: query_bytes.clone(),
+                server_ip: "[2001:503:c27::2:30]:53".to_owned(),
+            },
+            MockData { data: referral },
+        ),
+        (
+            MockKey {
+                query_bytes,
+                server_ip: "[2001:db8::53]:53".to_owned(),
+            },
+            MockData { data: answer },
+        ),
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let packet = query.resolve(&mut socket, Some(0))?;
+    assert_eq!(packet.answers, answers);
+    assert_eq!(server_address("192.0.2.1"), "192.0.2.1:53");
+    Ok(())
+}
+
+/// Validate that a resolution over IPv6 falls back to the IPv4 address of the root server when
+/// the host has no route to its IPv6 address.
+#[test]
+fn test_querying_over_ipv6_falls_back_to_ipv4() -> Result<(), DnsError> {
+    use crate::socket::{MockData, MockKey, MockSocket};
+    use crate::ttl::Ttl;
+
+    /// A socket without IPv6 connectivity.
+    struct Ipv4Socket(MockSocket);
+
+    impl DnsTransport for Ipv4Socket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+            if addr.starts_with('[') {
+                let unreachable = std::io::ErrorKind::NetworkUnreachable;
+                return Err(DnsError::SocketSend(Some(unreachable.into())));
+            }
+            self.0.send(buf, addr)
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            self.0.recv_from(buf)
+        }
+    }
+
+    let mut query = Query {
+        domain_name: "example.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions::default(),
+    };
+    let query_bytes = query.serialize(Some(0))?;
+    let mut answer = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: crate::header::FLAG_RESPONSE,
+            ..Default::default()
+        },
+        questions: Packet::parse(&query_bytes)?.questions,
+        answers: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::A,
+            r_class: CLASS_IN,
+            ttl: Ttl::from(3600),
+            data: vec![192, 0, 2, 1],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()?;
+    answer.resize(RECEIVE_BUFFER_SIZE, 0);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data([(
+        MockKey {
+            query_bytes,
+            server_ip: "192.58.128.30:53".to_owned(),
+        },
+        MockData { data: answer },
+    )]);
+    let mut socket = Ipv4Socket(socket);
+
+    query.options.ipv6 = true;
+    let packet = query.resolve(&mut socket, Some(0))?;
+    assert_eq!(packet.answers[0].data, [192, 0, 2, 1]);
+    Ok(())
+}
+
+/// Validate that a resolution gives up once it has asked as many servers as it may, when a name
+/// server keeps referring the query back to itself.
+#[test]
+fn test_resolving_gives_up_on_referral_loop() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::{MockKey, MockSocket};
+
+    let query = Query::new("example.com", RecordType::A);
+    let exchanges = MockResolution::new("example.com", RecordType::A, 0)
+        .referral("com", "a.gtld-servers.net", "192.0.2.30")
+        .referral("com", "a.gtld-servers.net", "192.0.2.30")
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    assert_eq!(
+        query.resolve(&mut socket, Some(0)),
+        Err(DnsError::TooManyReferrals)
+    );
+    let key = MockKey {
+        query_bytes: query.serialize(Some(0))?,
+        server_ip: "192.0.2.30:53".to_owned(),
+    };
+    assert_eq!(socket.send_count(&key), MAX_HOPS - 1);
+    Ok(())
+}
+
+/// Validate that a resolution gives up on a zone delegated without glue to a name server within
+/// it, as resolving the name server leads to the same delegation again.
+#[test]
+fn test_resolving_gives_up_on_self_referential_delegation() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let mut exchanges = vec![];
+    for name in ["www.example.com", "ns.example.com"] {
+        let resolution = MockResolution::new(name, RecordType::A, 0)
+            .delegation("example.com. 172800 IN NS ns.example.com.")
+            .build()?;
+        exchanges.extend(resolution);
+    }
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let query = Query::new("www.example.com", RecordType::A);
+    assert_eq!(
+        query.resolve(&mut socket, Some(0)),
+        Err(DnsError::TooManyReferrals)
+    );
+    Ok(())
+}
+
+/// Validate that responses which may be forged are ignored while waiting for the genuine one:
+/// those from another address than the server's, with another ID or to another question.
+#[test]
+fn test_querying_ignores_spoofed_responses() -> Result<(), DnsError> {
+    use crate::ttl::Ttl;
+    use std::cell::RefCell;
+
+    /// A socket on which the given datagrams arrive in turn, then nothing.
+    struct RacedSocket(RefCell<Vec<(Vec<u8>, SocketAddr)>>);
+
+    impl DnsTransport for RacedSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            let mut datagrams = self.0.borrow_mut();
+            if datagrams.is_empty() {
+                return Err(DnsError::SocketRead(None));
+            }
+            let (data, source) = datagrams.remove(0);
+            buf[..data.len()].copy_from_slice(&data);
+            Ok((data.len(), source))
+        }
+    }
+
+    let query = Query::new("example.com", RecordType::A);
+    let query_bytes = query.serialize(Some(0))?;
+    let id = u16::from_be_bytes([query_bytes[0], query_bytes[1]]);
+    let response = |id: u16, domain_name: &str, address: u8| -> Result<Vec<u8>, DnsError> {
+        Packet {
+            header: Header {
+                id,
+                flags: crate::header::FLAG_RESPONSE,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name: DnsName::from(domain_name),
+                q_type: RecordType::A,
+                q_class: CLASS_IN,
+            }],
+            answers: vec![Record {
+                name: DnsName::from(domain_name),
+                r_type: RecordType::A,
+                r_class: CLASS_IN,
+                ttl: Ttl::from(3600),
+                data: vec![192, 0, 2, address],
+            }],
+            authorities: vec![],
+            additionals: vec![],
+        }
+        .serialize()
+    };
+    let server: SocketAddr = "192.0.2.53:53".parse().unwrap();
+    let spoofed = vec![
+        (
+            response(id, "example.com", 66)?,
+            "198.51.100.1:53".parse().unwrap(),
+        ),
+        (
+            response(id, "example.com", 66)?,
+            "192.0.2.53:5353".parse().unwrap(),
+        ),
+        (response(id.wrapping_add(1), "example.com", 66)?, server),
+        (response(id, "example.org", 66)?, server),
+    ];
+
+    let mut datagrams = spoofed.clone();
+    datagrams.push((response(id, "EXAMPLE.com", 1)?, server));
+    let mut socket = RacedSocket(RefCell::new(datagrams));
+    let packet = query.ask(&mut socket, "192.0.2.53:53", Some(0))?;
+    assert_eq!(packet.answers[0].data, [192, 0, 2, 1]);
+
+    let mut socket = RacedSocket(RefCell::new(spoofed));
+    let error = query
+        .ask(&mut socket, "192.0.2.53:53", Some(0))
+        .unwrap_err();
+    assert_eq!(error, DnsError::SocketRead(None));
+    Ok(())
+}
+
+/// Validate that a response from another address than the server's is ignored during a
+/// resolution, the query being answered by its retry.
+#[test]
+fn test_resolving_ignores_wrong_source() -> Result<(), DnsError> {
+    use crate::mock_data;
+    use crate::socket::{MockFault, MockSocket};
+
+    let data = mock_data::captured_data_for_twitter();
+    let mut socket = MockSocket::bind("")?;
+    let (root_key, root_data) = data[0].clone();
+    let forger = "203.0.113.66:53".parse().unwrap();
+    socket.register_faulty_response(
+        root_key.clone(),
+        root_data,
+        vec![MockFault::WrongSource(forger)],
+    );
+    for (key, value) in data {
+        socket.register_response(key, value);
+    }
+
+    let query = Query {
+        domain_name: "twitter.com".into(),
+        record_type: RecordType::A,
+        options: QueryOptions {
+            retries: 1,
+            ..Default::default()
+        },
+    };
+    let packet = query.resolve(&mut socket, Some(0))?;
+    assert_eq!(
+        packet.answers.get_first_a_record().unwrap().ip_address(),
+        "104.244.42.193"
+    );
+    assert_eq!(socket.send_count(&root_key), 2);
+    Ok(())
+}
+
+/// A response to the given query holding enough A records to exceed the receive buffer for
+/// datagrams, along with its wire format.
+#[cfg(test)]
+fn large_response(query_bytes: &[u8]) -> Result<(Packet, Vec<u8>), DnsError> {
+    use crate::ttl::Ttl;
+
+    let mut response = Packet::parse(query_bytes)?;
+    response.header.flags |= crate::header::FLAG_RESPONSE;
+    response.additionals.clear();
+    response.answers = (0..100)
+        .map(|host| Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::A,
+            r_class: CLASS_IN,
+            ttl: Ttl::from(3600),
+            data: vec![192, 0, 2, host],
+        })
+        .collect();
+    let bytes = response.serialize()?;
+    assert!(bytes.len() > RECEIVE_BUFFER_SIZE);
+    Ok((response, bytes))
+}
+
+/// Validate that a response truncated over UDP is read whole once retried over TCP, even when it
+/// exceeds the receive buffer for datagrams.
+#[test]
+fn test_querying_retries_truncated_response_over_tcp() -> Result<(), DnsError> {
+    use std::cell::RefCell;
+
+    /// A socket on which the server answers over UDP with the truncated response, and over TCP
+    /// with the whole one.
+    struct TruncatingSocket {
+        tcp: bool,
+        truncated: Vec<u8>,
+        whole: Vec<u8>,
+        next: RefCell<Option<Vec<u8>>>,
+    }
+
+    impl DnsTransport for TruncatingSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            let response = match self.tcp {
+                true => self.whole.clone(),
+                false => self.truncated.clone(),
+            };
+            self.next.replace(Some(response));
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            let Some(response) = self.next.take() else {
+                return Err(DnsError::SocketRead(None));
+            };
+            let size = response.len().min(buf.len());
+            buf[..size].copy_from_slice(&response[..size]);
+            Ok((size, "192.0.2.53:53".parse().unwrap()))
+        }
+
+        fn is_connection_oriented(&self) -> bool {
+            self.tcp
+        }
+
+        fn set_tcp(&mut self, tcp: bool) {
+            self.tcp = tcp;
+        }
+    }
+
+    let query = Query::new("example.com", RecordType::A);
+    let (whole, whole_bytes) = large_response(&query.serialize(Some(0))?)?;
+    let mut truncated = whole.clone();
+    truncated.header.flags |= FLAG_TRUNCATED;
+    truncated.answers.clear();
+
+    let mut socket = TruncatingSocket {
+        tcp: false,
+        truncated: truncated.serialize()?,
+        whole: whole_bytes,
+        next: RefCell::new(None),
+    };
+    let packet = query.ask(&mut socket, "192.0.2.53:53", Some(0))?;
+    assert_eq!(packet.answers, whole.answers);
+    assert!(!socket.tcp);
+    Ok(())
+}
+
+/// Validate that a query forced over TCP, as with `--tcp`, reads a response exceeding the receive
+/// buffer for datagrams whole.
+#[test]
+fn test_querying_over_tcp_reads_large_response() -> Result<(), DnsError> {
+    use crate::forwarder::UpstreamSocket;
+    use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    // The server answers a single query with a large response.
+    let server = std::thread::spawn(move || -> Result<Packet, DnsError> {
+        let (mut stream, _) = listener.accept().unwrap();
+        let length = stream.read_u16::<BigEndian>().unwrap();
+        let mut query_bytes = vec![0u8; length as usize];
+        stream.read_exact(&mut query_bytes).unwrap();
+        let (response, bytes) = large_response(&query_bytes)?;
+        stream.write_u16::<BigEndian>(bytes.len() as u16).unwrap();
+        stream.write_all(&bytes).unwrap();
+        Ok(response)
+    });
+
+    let mut socket = UpstreamSocket::bind("127.0.0.1:0")?;
+    socket.set_tcp(true);
+    let packet = Query::new("example.com", RecordType::A).ask(&mut socket, &addr, Some(0))?;
+    assert_eq!(packet.answers, server.join().unwrap()?.answers);
+    Ok(())
+}
+
+/// Validate that multicast DNS responses which do not repeat the ID and the question of the query
+/// are ignored, and that a failure to read is reported as it happened.
+#[test]
+fn test_reading_multicast_response_ignores_other_queries() -> Result<(), DnsError> {
+    use crate::ttl::Ttl;
+    use std::cell::RefCell;
+    use std::io::ErrorKind;
+
+    /// A socket on which the given datagrams arrive in turn, then nothing.
+    struct MulticastSocket(RefCell<Vec<Vec<u8>>>);
+
+    impl DnsTransport for MulticastSocket {
+        fn send<'a>(&'a mut self, buf: &'a [u8], _addr: &str) -> Result<usize, DnsError> {
+            Ok(buf.len())
+        }
+
+        fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+            let mut datagrams = self.0.borrow_mut();
+            if datagrams.is_empty() {
+                return Err(DnsError::SocketRead(Some(ErrorKind::WouldBlock.into())));
+            }
+            let data = datagrams.remove(0);
+            buf[..data.len()].copy_from_slice(&data);
+            Ok((data.len(), "192.168.1.20:5353".parse().unwrap()))
+        }
+    }
+
+    let query = Query::new("printer.local", RecordType::A);
+    let query_bytes = query.serialize(Some(0))?;
+    let id = u16::from_be_bytes([query_bytes[0], query_bytes[1]]);
+    let response = |id: u16, domain_name: &str| -> Result<Vec<u8>, DnsError> {
+        Packet {
+            header: Header {
+                id,
+                flags: crate::header::FLAG_RESPONSE | crate::header::FLAG_AUTHORITATIVE,
+                ..Default::default()
+            },
+            questions: vec![Question {
+                name: DnsName::from(domain_name),
+                q_type: RecordType::A,
+                q_class: CLASS_IN,
+            }],
+            answers: vec![Record {
+                name: DnsName::from(domain_name),
+                r_type: RecordType::A,
+                r_class: CLASS_IN,
+                ttl: Ttl::from(120),
+                data: vec![192, 168, 1, 20],
+            }],
+            authorities: vec![],
+            additionals: vec![],
+        }
+        .serialize()
+    };
+
+    let mut socket = MulticastSocket(RefCell::new(vec![
+        response(id.wrapping_add(1), "printer.local")?,
+        response(id, "scanner.local")?,
+        response(id, "printer.local")?,
+    ]));
+    let packet = read_multicast_response(&mut socket, &query_bytes)?;
+    assert_eq!(packet.answers[0].name, "printer.local");
+    assert_eq!(packet.header.id, id);
+
+    let error = read_multicast_response(&mut socket, &query_bytes).unwrap_err();
+    assert!(error.is_timeout());
+    Ok(())
+}
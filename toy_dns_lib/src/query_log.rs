@@ -0,0 +1,311 @@
+use crate::errors::DnsError;
+use crate::header::{rcode_name, RCODE_NAME_ERROR};
+use crate::json;
+use crate::packet::Packet;
+use crate::pcap::rfc3339;
+use crate::record::RecordType;
+use crate::trace::Exchange;
+use log::warn;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How the entries of a query log are written.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueryLogFormat {
+    /// One line of space-separated fields per resolution, meant to be read or grepped.
+    Text,
+
+    /// One JSON object per line, meant for log processors.
+    Json,
+}
+
+/// A resolution as recorded in the query log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryLogEntry {
+    /// When the resolution started, since the Unix epoch.
+    pub timestamp: Duration,
+
+    /// The name which was resolved.
+    pub domain_name: String,
+
+    /// The type which was resolved.
+    pub record_type: RecordType,
+
+    /// The response code of the response, if there was one.
+    pub rcode: Option<u16>,
+
+    /// The number of records in the answer section of the response.
+    pub answers: usize,
+
+    /// How long the resolution took.
+    pub elapsed: Duration,
+
+    /// The servers queried along the way, in the order they were first contacted.
+    pub servers: Vec<String>,
+
+    /// Why there is no response, if there is none.
+    pub error: Option<String>,
+}
+
+impl QueryLogEntry {
+    /// Describe a resolution which has just completed. A name which is known not to exist is
+    /// logged as an NXDOMAIN response rather than an error.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name which was resolved.
+    /// * `record_type`: The type which was resolved.
+    /// * `result`: The response, or the error which prevented one.
+    /// * `elapsed`: How long the resolution took.
+    /// * `exchanges`: The exchanges made with servers along the way, in order.
+    pub fn new(
+        domain_name: &str,
+        record_type: RecordType,
+        result: Result<&Packet, &DnsError>,
+        elapsed: Duration,
+        exchanges: &[Exchange],
+    ) -> QueryLogEntry {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut servers: Vec<String> = Vec::new();
+        for exchange in exchanges {
+            if !servers.contains(&exchange.server_address) {
+                servers.push(exchange.server_address.clone());
+            }
+        }
+        let (rcode, answers, error) = match result {
+            Ok(packet) => (Some(packet.rcode()), packet.answers.len(), None),
+            Err(DnsError::UnknownDomainName) => (Some(RCODE_NAME_ERROR), 0, None),
+            Err(error) => (None, 0, Some(error.to_string())),
+        };
+        QueryLogEntry {
+            timestamp: now.saturating_sub(elapsed),
+            domain_name: domain_name.to_owned(),
+            record_type,
+            rcode,
+            answers,
+            elapsed,
+            servers,
+            error,
+        }
+    }
+
+    /// When the resolution started, in the RFC 3339 format with microseconds.
+    pub fn started_at(&self) -> String {
+        rfc3339(self.timestamp)
+    }
+
+    /// Render the entry as a line of text, e.g.
+    /// `2023-11-14T22:13:20.250000Z example.com A NOERROR answers=1 elapsed_ms=42 servers=198.41.0.4:53`.
+    /// Resolutions without a response have `ERROR` as their response code, followed by the error.
+    pub fn to_text(&self) -> String {
+        let rcode = match self.rcode {
+            Some(rcode) => rcode_name(rcode),
+            None => "ERROR".to_owned(),
+        };
+        let servers = match self.servers.is_empty() {
+            true => "-".to_owned(),
+            false => self.servers.join(","),
+        };
+        let mut line = format!(
+            "{} {} {} {} answers={} elapsed_ms={} servers={}",
+            self.started_at(),
+            self.domain_name,
+            self.record_type,
+            rcode,
+            self.answers,
+            self.elapsed.as_millis(),
+            servers
+        );
+        if let Some(error) = &self.error {
+            line.push_str(&format!(" error={}", error));
+        }
+        line
+    }
+}
+
+/// Where resolutions are logged, one entry per line. Clones share the same output, so that
+/// concurrent resolutions can log to it without their lines getting mixed up.
+#[derive(Clone)]
+pub struct QueryLog {
+    /// How entries are written.
+    pub format: QueryLogFormat,
+
+    /// Where entries are written.
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Entries are written whole, so
+/// the output remains usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl QueryLog {
+    /// Log to the given output.
+    ///
+    /// # Arguments
+    /// * `output`: Where to write the entries.
+    /// * `format`: How to write the entries.
+    pub fn new(output: Box<dyn Write + Send>, format: QueryLogFormat) -> QueryLog {
+        QueryLog {
+            format,
+            output: Arc::new(Mutex::new(output)),
+        }
+    }
+
+    /// Log to the file at the given path, appending to it if it exists, or to stderr with `-`.
+    ///
+    /// # Arguments
+    /// * `path`: The path of the file.
+    /// * `format`: How to write the entries.
+    pub fn open(path: &str, format: QueryLogFormat) -> Result<QueryLog, DnsError> {
+        if path == "-" {
+            return Ok(QueryLog::new(Box::new(std::io::stderr()), format));
+        }
+        let Ok(file) = OpenOptions::new().create(true).append(true).open(path) else {
+            return Err(DnsError::QueryLog);
+        };
+        Ok(QueryLog::new(Box::new(file), format))
+    }
+
+    /// Write an entry. Failing to do so is not worth failing the resolution for, so it is only
+    /// warned about.
+    ///
+    /// # Argument
+    /// * `entry`: The entry to write.
+    pub fn log(&self, entry: &QueryLogEntry) {
+        let line = match self.format {
+            QueryLogFormat::Text => entry.to_text(),
+            QueryLogFormat::Json => json::query_log_entry(entry),
+        };
+        let mut output = lock(&self.output);
+        if let Err(error) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            warn!("Could not write to the query log: {}", error);
+        }
+    }
+}
+
+impl std::fmt::Debug for QueryLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueryLog")
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+impl PartialEq for QueryLog {
+    /// Query logs are equal when they share the same output.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.output, &other.output)
+    }
+}
+
+/// Validate that resolutions are logged as text, with the servers queried and errors.
+#[test]
+fn test_query_log_text() -> Result<(), DnsError> {
+    use crate::dns_name::DnsName;
+    use crate::header::Header;
+    use crate::record::Record;
+    use crate::ttl::Ttl;
+
+    let packet = Packet {
+        header: Header::default(),
+        questions: vec![],
+        answers: vec![Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(300),
+            data: vec![192, 0, 2, 1],
+        }],
+        authorities: vec![],
+        additionals: vec![],
+    };
+    let exchange = |server_address: &str| Exchange {
+        server_address: server_address.to_owned(),
+        server_name: "".to_owned(),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::A,
+        response: packet.clone(),
+        received_bytes: 12,
+        elapsed: Duration::from_millis(3),
+    };
+    let exchanges = [
+        exchange("198.41.0.4:53"),
+        exchange("192.5.6.30:53"),
+        exchange("198.41.0.4:53"),
+    ];
+
+    let mut entry = QueryLogEntry::new(
+        "example.com",
+        RecordType::A,
+        Ok(&packet),
+        Duration::from_millis(42),
+        &exchanges,
+    );
+    assert_eq!(entry.servers, vec!["198.41.0.4:53", "192.5.6.30:53"]);
+    entry.timestamp = Duration::from_millis(1_700_000_000_250);
+    assert_eq!(
+        entry.to_text(),
+        "2023-11-14T22:13:20.250000Z example.com A NOERROR answers=1 elapsed_ms=42 \
+         servers=198.41.0.4:53,192.5.6.30:53"
+    );
+
+    let nxdomain = QueryLogEntry::new(
+        "missing.example",
+        RecordType::AAAA,
+        Err(&DnsError::UnknownDomainName),
+        Duration::ZERO,
+        &[],
+    );
+    assert_eq!(nxdomain.rcode, Some(RCODE_NAME_ERROR));
+    assert!(nxdomain
+        .to_text()
+        .ends_with(" missing.example AAAA NXDOMAIN answers=0 elapsed_ms=0 servers=-"));
+
+    let failure = QueryLogEntry::new(
+        "example.com",
+        RecordType::A,
+        Err(&DnsError::SocketRead(None)),
+        Duration::from_secs(5),
+        &exchanges[..1],
+    );
+    assert_eq!(failure.rcode, None);
+    assert!(failure.to_text().ends_with(&format!(
+        " example.com A ERROR answers=0 elapsed_ms=5000 servers=198.41.0.4:53 error={}",
+        DnsError::SocketRead(None)
+    )));
+    Ok(())
+}
+
+/// Validate that entries are appended to the log file, one per line.
+#[test]
+fn test_query_log_file() -> Result<(), DnsError> {
+    let path = std::env::temp_dir().join(format!("toy_dns_query_log_{}", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let entry = QueryLogEntry::new(
+        "example.com",
+        RecordType::A,
+        Err(&DnsError::UnknownDomainName),
+        Duration::ZERO,
+        &[],
+    );
+
+    let query_log = QueryLog::open(&path, QueryLogFormat::Json)?;
+    query_log.log(&entry);
+    query_log.clone().log(&entry);
+    let contents = std::fs::read_to_string(&path).map_err(|_| DnsError::QueryLog)?;
+    _ = std::fs::remove_file(&path);
+
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], json::query_log_entry(&entry));
+    assert!(QueryLog::open("/nonexistent/query.log", QueryLogFormat::Text).is_err());
+    Ok(())
+}
@@ -1,13 +1,16 @@
+use crate::dns_name::DnsName;
 use crate::errors::DnsError;
 use crate::record::RecordType;
-use crate::record_name::RecordName;
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::record_name::{CompressionOffsets, RecordName};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub struct Question {
     /// The domain name of interest in the question.
-    pub name: Vec<u8>,
+    pub name: DnsName,
 
     /// Type of the DNS question.
     pub q_type: RecordType,
@@ -23,14 +26,61 @@ impl Question {
     /// * `cursor`: The byte buffer containing the full DNS message data.
     pub fn read_and_advance(cursor: &mut Cursor<&[u8]>) -> Result<Question, DnsError> {
         let name = RecordName::read_and_advance(cursor)?;
-        let Ok(parsed_type) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ReadQuestionType) };
-        let Some(record_type) = RecordType::from(parsed_type) else { return Err(DnsError::UnrecognizedRecordType) };
-        let Ok(parsed_class) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ReadQuestionClass) };
-        return Ok(Question {
-            name: name,
+        let parsed_type = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ReadQuestionType(Some(error)))?;
+        let record_type = RecordType::from_wire(parsed_type);
+        let parsed_class = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ReadQuestionClass(Some(error)))?;
+        Ok(Question {
+            name: DnsName::from(name),
             q_type: record_type,
             q_class: parsed_class,
-        });
+        })
+    }
+
+    /// Serialize a parsed question, whose name is in dotted form, in wire format onto the given
+    /// buffer.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized question to.
+    pub fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        let Ok(name) = std::str::from_utf8(&self.name) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        bytes.extend(RecordName { name }.encode()?);
+        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(self.q_type)) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(self.q_class) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        Ok(())
+    }
+
+    /// Serialize the question in wire format onto the given message, compressing its name against
+    /// the names already in the message.
+    ///
+    /// # Arguments
+    /// * `bytes`: The message to append the serialized question to.
+    /// * `offsets`: The names written to the message so far.
+    pub(crate) fn serialize_compressed(
+        &self,
+        bytes: &mut Vec<u8>,
+        offsets: &mut CompressionOffsets,
+    ) -> Result<(), DnsError> {
+        let Ok(name) = std::str::from_utf8(&self.name) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        RecordName::compress_into(&RecordName { name }.encode()?, bytes, offsets);
+        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(self.q_type)) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(self.q_class) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        Ok(())
     }
 }
 
@@ -42,7 +92,7 @@ fn test_parsing_valid_question() {
         3u8, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 1, 0, 1,
     ];
 
-    let domain_name: Vec<u8> = "www.example.com".chars().map(|c| c as u8).collect();
+    let domain_name = DnsName::from("www.example.com");
     let expected = Question {
         name: domain_name,
         q_type: RecordType::A,
@@ -53,16 +103,17 @@ fn test_parsing_valid_question() {
     assert_eq!(Question::read_and_advance(&mut cursor).unwrap(), expected);
 }
 
-/// Validate parsing of a valid question
+/// Validate parsing of a question about a record type toy_dns does not know
 #[test]
-fn test_parsing_valid_question_invalid_record_type() {
+fn test_parsing_valid_question_unknown_record_type() {
     let data = [
         // www.example.com                                                           Type  Class
         3u8, 119, 119, 119, 7, 101, 120, 97, 109, 112, 108, 101, 3, 99, 111, 109, 0, 0, 44, 0, 1,
     ];
 
     let mut cursor = Cursor::new(data.as_slice());
-    assert!(Question::read_and_advance(&mut cursor).is_err());
+    let question = Question::read_and_advance(&mut cursor).unwrap();
+    assert_eq!(question.q_type, RecordType::Unknown(44));
 }
 
 /// Validate proper handling of a buffer too small to hold a question.
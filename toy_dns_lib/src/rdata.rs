@@ -0,0 +1,984 @@
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::record_name::RecordName;
+use crate::zone::{absolute_name, parse_ttl};
+use byteorder::{BigEndian, ReadBytesExt};
+use chrono::{DateTime, NaiveDateTime};
+use data_encoding::{BASE32HEX_NOPAD, BASE64, HEXUPPER, HEXUPPER_PERMISSIVE};
+use std::io::{Cursor, Read};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// The field introducing record data in the generic format of RFC 3597, section 5.
+const GENERIC_DATA_MARKER: &str = r"\#";
+
+/// The names of the SVCB and HTTPS parameters by key (RFC 9460, section 14.3.2). Other keys are
+/// named `key` followed by their value.
+const SVC_PARAM_KEYS: [&str; 7] = [
+    "mandatory",
+    "alpn",
+    "no-default-alpn",
+    "port",
+    "ipv4hint",
+    "ech",
+    "ipv6hint",
+];
+
+/// Encode the presentation format of record data, as found in zone files, into wire format. Names
+/// within the data are written uncompressed.
+///
+/// # Arguments
+/// * `record_type`: The type of the record the data belongs to.
+/// * `fields`: The whitespace-separated fields of the data. Quoted strings are a single field
+///   without the quotes but with their escapes intact.
+/// * `origin`: The origin relative names are completed with.
+pub fn from_presentation(
+    record_type: RecordType,
+    fields: &[&str],
+    origin: &str,
+) -> Result<Vec<u8>, DnsError> {
+    if fields.first() == Some(&GENERIC_DATA_MARKER) {
+        return from_generic_presentation(&fields[1..]);
+    }
+
+    let mut fields = fields.iter().copied();
+    let mut data = Vec::new();
+
+    match record_type {
+        RecordType::A => {
+            let address: Ipv4Addr = number(next(&mut fields)?)?;
+            data.extend(address.octets());
+        }
+        RecordType::AAAA => {
+            let address: Ipv6Addr = number(next(&mut fields)?)?;
+            data.extend(address.octets());
+        }
+        RecordType::NS | RecordType::CNAME | RecordType::PTR => {
+            push_name(&mut data, next(&mut fields)?, origin)?;
+        }
+        RecordType::SOA => {
+            push_name(&mut data, next(&mut fields)?, origin)?;
+            push_name(&mut data, next(&mut fields)?, origin)?;
+            data.extend(number::<u32>(next(&mut fields)?)?.to_be_bytes());
+            // The refresh, retry, expire and minimum fields are durations.
+            for _ in 0..4 {
+                let Some(seconds) = parse_ttl(next(&mut fields)?) else {
+                    return Err(DnsError::ParseZone);
+                };
+                data.extend(seconds.to_be_bytes());
+            }
+        }
+        RecordType::MX => {
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            push_name(&mut data, next(&mut fields)?, origin)?;
+        }
+        RecordType::HINFO => {
+            // The CPU and the operating system.
+            push_character_string(&mut data, next(&mut fields)?)?;
+            push_character_string(&mut data, next(&mut fields)?)?;
+        }
+        RecordType::TXT => {
+            push_character_string(&mut data, next(&mut fields)?)?;
+            for field in fields.by_ref() {
+                push_character_string(&mut data, field)?;
+            }
+        }
+        RecordType::SRV => {
+            for _ in 0..3 {
+                data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            }
+            push_name(&mut data, next(&mut fields)?, origin)?;
+        }
+        RecordType::NAPTR => {
+            // The order and preference, then the flags, services and regexp strings.
+            for _ in 0..2 {
+                data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            }
+            for _ in 0..3 {
+                push_character_string(&mut data, next(&mut fields)?)?;
+            }
+            push_name(&mut data, next(&mut fields)?, origin)?;
+        }
+        RecordType::SVCB | RecordType::HTTPS => {
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            push_name(&mut data, next(&mut fields)?, origin)?;
+            push_svc_params(&mut data, &mut fields)?;
+        }
+        RecordType::TLSA => {
+            // The certificate usage, selector and matching type, then the association data.
+            for _ in 0..3 {
+                data.push(number(next(&mut fields)?)?);
+            }
+            let Ok(association) = HEXUPPER_PERMISSIVE.decode(remainder(&mut fields).as_bytes())
+            else {
+                return Err(DnsError::ParseZone);
+            };
+            data.extend(association);
+        }
+        RecordType::DS => {
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            data.push(number(next(&mut fields)?)?);
+            data.push(number(next(&mut fields)?)?);
+            let Ok(digest) = HEXUPPER_PERMISSIVE.decode(remainder(&mut fields).as_bytes()) else {
+                return Err(DnsError::ParseZone);
+            };
+            data.extend(digest);
+        }
+        RecordType::DNSKEY => {
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            data.push(number(next(&mut fields)?)?);
+            data.push(number(next(&mut fields)?)?);
+            let Ok(key) = BASE64.decode(remainder(&mut fields).as_bytes()) else {
+                return Err(DnsError::ParseZone);
+            };
+            data.extend(key);
+        }
+        RecordType::RRSIG => {
+            let type_covered = record_type_by_name(next(&mut fields)?)?;
+            data.extend(RecordType::value(type_covered).to_be_bytes());
+            data.push(number(next(&mut fields)?)?);
+            data.push(number(next(&mut fields)?)?);
+            data.extend(number::<u32>(next(&mut fields)?)?.to_be_bytes());
+            data.extend(timestamp(next(&mut fields)?)?.to_be_bytes());
+            data.extend(timestamp(next(&mut fields)?)?.to_be_bytes());
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+            push_name(&mut data, next(&mut fields)?, origin)?;
+            let Ok(signature) = BASE64.decode(remainder(&mut fields).as_bytes()) else {
+                return Err(DnsError::ParseZone);
+            };
+            data.extend(signature);
+        }
+        RecordType::NSEC => {
+            push_name(&mut data, next(&mut fields)?, origin)?;
+            push_type_bitmap(&mut data, &mut fields)?;
+        }
+        RecordType::NSEC3 => {
+            data.push(number(next(&mut fields)?)?);
+            data.push(number(next(&mut fields)?)?);
+            data.extend(number::<u16>(next(&mut fields)?)?.to_be_bytes());
+
+            // A salt of "-" stands for an empty salt.
+            let salt = match next(&mut fields)? {
+                "-" => vec![],
+                salt => {
+                    let Ok(salt) = HEXUPPER_PERMISSIVE.decode(salt.as_bytes()) else {
+                        return Err(DnsError::ParseZone);
+                    };
+                    salt
+                }
+            };
+            push_length_prefixed(&mut data, &salt)?;
+
+            let next_hashed_owner = next(&mut fields)?.to_ascii_uppercase();
+            let Ok(next_hashed_owner) = BASE32HEX_NOPAD.decode(next_hashed_owner.as_bytes()) else {
+                return Err(DnsError::ParseZone);
+            };
+            push_length_prefixed(&mut data, &next_hashed_owner)?;
+            push_type_bitmap(&mut data, &mut fields)?;
+        }
+        RecordType::CAA => {
+            data.push(number(next(&mut fields)?)?);
+            let tag = next(&mut fields)?;
+            if tag.is_empty() || !tag.bytes().all(|byte| byte.is_ascii_alphanumeric()) {
+                return Err(DnsError::ParseZone);
+            }
+            push_length_prefixed(&mut data, tag.as_bytes())?;
+            // The value is not length-prefixed: it takes up the rest of the data.
+            data.extend(unescape(next(&mut fields)?)?);
+        }
+        // OPT pseudo-records only ever exist within a message and ANY only within questions. Data of
+        // unknown types can only be given in the generic format.
+        RecordType::OPT | RecordType::ANY | RecordType::Unknown(_) | RecordType::Invalid => {
+            return Err(DnsError::ParseZone)
+        }
+    }
+
+    if fields.next().is_some() {
+        return Err(DnsError::ParseZone);
+    }
+    Ok(data)
+}
+
+/// Decode record data given in the generic format of RFC 3597, section 5: its length followed by
+/// the data in hex.
+///
+/// # Argument
+/// * `fields`: The fields following the `\#` marker.
+fn from_generic_presentation(fields: &[&str]) -> Result<Vec<u8>, DnsError> {
+    let Some((length, hex)) = fields.split_first() else {
+        return Err(DnsError::ParseZone);
+    };
+    let length: usize = number(length)?;
+    let Ok(data) = HEXUPPER_PERMISSIVE.decode(hex.concat().as_bytes()) else {
+        return Err(DnsError::ParseZone);
+    };
+    if data.len() != length {
+        return Err(DnsError::ParseZone);
+    }
+    Ok(data)
+}
+
+/// Render record data in presentation format, the inverse of `from_presentation()`. Names are
+/// rendered absolute with a trailing dot. Data that `from_presentation()` could not read back,
+/// such as a TXT record without any string or a CAA record with an invalid tag, is an error, so
+/// that callers fall back to `generic_presentation()`.
+///
+/// # Arguments
+/// * `record_type`: The type of the record the data belongs to.
+/// * `data`: The uncompressed record data.
+pub fn to_presentation(record_type: RecordType, data: &[u8]) -> Result<String, DnsError> {
+    let mut cursor = Cursor::new(data);
+    let mut fields: Vec<String> = Vec::new();
+
+    match record_type {
+        RecordType::A => {
+            let Ok(octets) = <[u8; 4]>::try_from(data) else {
+                return Err(DnsError::ReadRecordData(None));
+            };
+            cursor.set_position(4);
+            fields.push(Ipv4Addr::from(octets).to_string());
+        }
+        RecordType::AAAA => {
+            let Ok(octets) = <[u8; 16]>::try_from(data) else {
+                return Err(DnsError::ReadRecordData(None));
+            };
+            cursor.set_position(16);
+            fields.push(Ipv6Addr::from(octets).to_string());
+        }
+        RecordType::NS | RecordType::CNAME | RecordType::PTR => {
+            fields.push(read_name(&mut cursor)?);
+        }
+        RecordType::SOA => {
+            fields.push(read_name(&mut cursor)?);
+            fields.push(read_name(&mut cursor)?);
+            for _ in 0..5 {
+                fields.push(read_u32(&mut cursor)?.to_string());
+            }
+        }
+        RecordType::MX => {
+            fields.push(read_u16(&mut cursor)?.to_string());
+            fields.push(read_name(&mut cursor)?);
+        }
+        RecordType::HINFO => {
+            for _ in 0..2 {
+                fields.push(quote_character_string(&read_length_prefixed(&mut cursor)?));
+            }
+        }
+        RecordType::TXT => {
+            if data.is_empty() {
+                return Err(DnsError::ReadRecordData(None));
+            }
+            while (cursor.position() as usize) < data.len() {
+                fields.push(quote_character_string(&read_length_prefixed(&mut cursor)?));
+            }
+        }
+        RecordType::SRV => {
+            for _ in 0..3 {
+                fields.push(read_u16(&mut cursor)?.to_string());
+            }
+            fields.push(read_name(&mut cursor)?);
+        }
+        RecordType::NAPTR => {
+            for _ in 0..2 {
+                fields.push(read_u16(&mut cursor)?.to_string());
+            }
+            for _ in 0..3 {
+                fields.push(quote_character_string(&read_length_prefixed(&mut cursor)?));
+            }
+            fields.push(read_name(&mut cursor)?);
+        }
+        RecordType::SVCB | RecordType::HTTPS => {
+            fields.push(read_u16(&mut cursor)?.to_string());
+            fields.push(read_name(&mut cursor)?);
+            while (cursor.position() as usize) < data.len() {
+                fields.push(read_svc_param(&mut cursor)?);
+            }
+        }
+        RecordType::TLSA => {
+            for _ in 0..3 {
+                fields.push(read_u8(&mut cursor)?.to_string());
+            }
+            fields.push(HEXUPPER.encode(&read_rest(&mut cursor)));
+        }
+        RecordType::DS => {
+            fields.push(read_u16(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(HEXUPPER.encode(&read_rest(&mut cursor)));
+        }
+        RecordType::DNSKEY => {
+            fields.push(read_u16(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(BASE64.encode(&read_rest(&mut cursor)));
+        }
+        RecordType::RRSIG => {
+            fields.push(type_name(read_u16(&mut cursor)?));
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u32(&mut cursor)?.to_string());
+            fields.push(format_timestamp(read_u32(&mut cursor)?));
+            fields.push(format_timestamp(read_u32(&mut cursor)?));
+            fields.push(read_u16(&mut cursor)?.to_string());
+            fields.push(read_name(&mut cursor)?);
+            fields.push(BASE64.encode(&read_rest(&mut cursor)));
+        }
+        RecordType::NSEC => {
+            fields.push(read_name(&mut cursor)?);
+            fields.extend(read_type_bitmap(&mut cursor)?);
+        }
+        RecordType::NSEC3 => {
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u8(&mut cursor)?.to_string());
+            fields.push(read_u16(&mut cursor)?.to_string());
+            let salt = read_length_prefixed(&mut cursor)?;
+            fields.push(match salt.is_empty() {
+                true => "-".to_owned(),
+                false => HEXUPPER.encode(&salt),
+            });
+            fields.push(BASE32HEX_NOPAD.encode(&read_length_prefixed(&mut cursor)?));
+            fields.extend(read_type_bitmap(&mut cursor)?);
+        }
+        RecordType::CAA => {
+            fields.push(read_u8(&mut cursor)?.to_string());
+            let tag = read_length_prefixed(&mut cursor)?;
+            if tag.is_empty() || !tag.iter().all(|byte| byte.is_ascii_alphanumeric()) {
+                return Err(DnsError::ReadRecordData(None));
+            }
+            fields.push(String::from_utf8_lossy(&tag).into_owned());
+            fields.push(quote_character_string(&read_rest(&mut cursor)));
+        }
+        RecordType::OPT | RecordType::ANY | RecordType::Unknown(_) | RecordType::Invalid => {
+            return Ok(generic_presentation(data))
+        }
+    }
+
+    if cursor.position() as usize != data.len() {
+        return Err(DnsError::ReadRecordData(None));
+    }
+    Ok(fields.join(" "))
+}
+
+/// The character strings of record data made of nothing else, such as that of TXT records.
+///
+/// # Argument
+/// * `data`: The record data.
+pub fn character_strings(data: &[u8]) -> Result<Vec<Vec<u8>>, DnsError> {
+    let mut cursor = Cursor::new(data);
+    let mut strings = Vec::new();
+    while (cursor.position() as usize) < data.len() {
+        strings.push(read_length_prefixed(&mut cursor)?);
+    }
+    Ok(strings)
+}
+
+/// Render record data in the generic format of RFC 3597, section 5, which works for any type.
+///
+/// # Argument
+/// * `data`: The record data.
+pub fn generic_presentation(data: &[u8]) -> String {
+    match data.is_empty() {
+        true => format!("{} 0", GENERIC_DATA_MARKER),
+        false => format!(
+            "{} {} {}",
+            GENERIC_DATA_MARKER,
+            data.len(),
+            HEXUPPER.encode(data)
+        ),
+    }
+}
+
+/// Read an uncompressed name and render it with a trailing dot.
+fn read_name(cursor: &mut Cursor<&[u8]>) -> Result<String, DnsError> {
+    let name = RecordName::read_and_advance(cursor)?;
+    let Ok(name) = String::from_utf8(name) else {
+        return Err(DnsError::InvalidByteInName);
+    };
+    Ok(format!("{}.", name))
+}
+
+fn read_u8(cursor: &mut Cursor<&[u8]>) -> Result<u8, DnsError> {
+    let value = cursor
+        .read_u8()
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    Ok(value)
+}
+
+fn read_u16(cursor: &mut Cursor<&[u8]>) -> Result<u16, DnsError> {
+    let value = cursor
+        .read_u16::<BigEndian>()
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    Ok(value)
+}
+
+fn read_u32(cursor: &mut Cursor<&[u8]>) -> Result<u32, DnsError> {
+    let value = cursor
+        .read_u32::<BigEndian>()
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    Ok(value)
+}
+
+/// Read bytes prefixed with their length in a single octet.
+fn read_length_prefixed(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, DnsError> {
+    let mut bytes = vec![0u8; read_u8(cursor)? as usize];
+    cursor
+        .read_exact(&mut bytes)
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    Ok(bytes)
+}
+
+/// Read everything up to the end of the data.
+fn read_rest(cursor: &mut Cursor<&[u8]>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    _ = cursor.read_to_end(&mut bytes);
+    bytes
+}
+
+/// The mnemonic of a record type value, or `TYPE` followed by the value for types toy_dns does not
+/// know, as specified in RFC 3597, section 5.
+fn type_name(value: u16) -> String {
+    match RecordType::from(value) {
+        Some(record_type) if record_type != RecordType::Invalid => record_type.to_string(),
+        _ => format!("TYPE{}", value),
+    }
+}
+
+/// Render an RRSIG timestamp as YYYYMMDDHHmmSS in UTC.
+fn format_timestamp(timestamp: u32) -> String {
+    match DateTime::from_timestamp(timestamp as i64, 0) {
+        Some(time) => time.format("%Y%m%d%H%M%S").to_string(),
+        None => timestamp.to_string(),
+    }
+}
+
+/// Quote a <character-string>, escaping quotes, backslashes and non-printable octets.
+fn quote_character_string(bytes: &[u8]) -> String {
+    let mut quoted = String::from('"');
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            0x20..=0x7e => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\{:03}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The name of an SVCB parameter key.
+fn svc_param_name(key: u16) -> String {
+    match SVC_PARAM_KEYS.get(key as usize) {
+        Some(name) => name.to_string(),
+        None => format!("key{}", key),
+    }
+}
+
+/// Read an SVCB parameter and render it as `name=value`, or as its name alone when it has no
+/// value.
+fn read_svc_param(cursor: &mut Cursor<&[u8]>) -> Result<String, DnsError> {
+    let key = read_u16(cursor)?;
+    let mut value = vec![0u8; read_u16(cursor)? as usize];
+    cursor
+        .read_exact(&mut value)
+        .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+    let malformed = || DnsError::ReadRecordData(None);
+
+    let rendered = match key {
+        0 => value
+            .chunks(2)
+            .map(|chunk| {
+                Ok(svc_param_name(u16::from_be_bytes(
+                    chunk.try_into().map_err(|_| malformed())?,
+                )))
+            })
+            .collect::<Result<Vec<String>, DnsError>>()?
+            .join(","),
+        1 => {
+            let mut value_cursor = Cursor::new(value.as_slice());
+            let mut protocols = Vec::new();
+            while (value_cursor.position() as usize) < value.len() {
+                let protocol = read_length_prefixed(&mut value_cursor)?;
+                protocols.push(String::from_utf8_lossy(&protocol).into_owned());
+            }
+            protocols.join(",")
+        }
+        2 if value.is_empty() => return Ok(svc_param_name(key)),
+        3 => u16::from_be_bytes(value.as_slice().try_into().map_err(|_| malformed())?).to_string(),
+        4 => value
+            .chunks(4)
+            .map(|chunk| {
+                Ok(
+                    Ipv4Addr::from(<[u8; 4]>::try_from(chunk).map_err(|_| malformed())?)
+                        .to_string(),
+                )
+            })
+            .collect::<Result<Vec<String>, DnsError>>()?
+            .join(","),
+        5 => BASE64.encode(&value),
+        6 => value
+            .chunks(16)
+            .map(|chunk| {
+                Ok(
+                    Ipv6Addr::from(<[u8; 16]>::try_from(chunk).map_err(|_| malformed())?)
+                        .to_string(),
+                )
+            })
+            .collect::<Result<Vec<String>, DnsError>>()?
+            .join(","),
+        // no-default-alpn takes no value.
+        2 => return Err(malformed()),
+        _ if value.is_empty() => return Ok(svc_param_name(key)),
+        _ => quote_character_string(&value),
+    };
+    Ok(format!("{}={}", svc_param_name(key), rendered))
+}
+
+/// Read the type bitmap of NSEC and NSEC3 records as specified in RFC 4034, section 4.1.2.
+fn read_type_bitmap(cursor: &mut Cursor<&[u8]>) -> Result<Vec<String>, DnsError> {
+    let mut types = Vec::new();
+    let end = cursor.get_ref().len() as u64;
+    while cursor.position() < end {
+        let window = read_u8(cursor)? as u16;
+        let bitmap = read_length_prefixed(cursor)?;
+        for (index, byte) in bitmap.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0x80 >> bit) != 0 {
+                    types.push(type_name(window << 8 | (index * 8 + bit) as u16));
+                }
+            }
+        }
+    }
+    Ok(types)
+}
+
+/// The next field of the record data, which must be present.
+fn next<'a>(fields: &mut impl Iterator<Item = &'a str>) -> Result<&'a str, DnsError> {
+    let Some(field) = fields.next() else {
+        return Err(DnsError::ParseZone);
+    };
+    Ok(field)
+}
+
+/// All remaining fields joined together. Binary data in base64 or hex may be split into several
+/// fields for readability.
+fn remainder<'a>(fields: &mut impl Iterator<Item = &'a str>) -> String {
+    fields.collect()
+}
+
+/// Parse a numeric (or otherwise `FromStr`) field.
+fn number<T: FromStr>(field: &str) -> Result<T, DnsError> {
+    let Ok(value) = field.parse::<T>() else {
+        return Err(DnsError::ParseZone);
+    };
+    Ok(value)
+}
+
+/// Look up a record type by its mnemonic.
+fn record_type_by_name(field: &str) -> Result<RecordType, DnsError> {
+    let Some(record_type) = RecordType::from_name(field) else {
+        return Err(DnsError::ParseZone);
+    };
+    Ok(record_type)
+}
+
+/// Parse an RRSIG timestamp, given either as YYYYMMDDHHmmSS in UTC or as seconds since the epoch.
+fn timestamp(field: &str) -> Result<u32, DnsError> {
+    if field.len() == 14 {
+        let Ok(time) = NaiveDateTime::parse_from_str(field, "%Y%m%d%H%M%S") else {
+            return Err(DnsError::ParseZone);
+        };
+        // Timestamps are serial numbers which wrap around in 2106.
+        return Ok(time.and_utc().timestamp() as u32);
+    }
+    number(field)
+}
+
+/// Append the wire format of a possibly relative name.
+fn push_name(data: &mut Vec<u8>, field: &str, origin: &str) -> Result<(), DnsError> {
+    let name = absolute_name(field, origin)?;
+    data.extend(RecordName { name: &name }.encode()?);
+    Ok(())
+}
+
+/// Append bytes prefixed with their length in a single octet.
+fn push_length_prefixed(data: &mut Vec<u8>, bytes: &[u8]) -> Result<(), DnsError> {
+    let Ok(length) = u8::try_from(bytes.len()) else {
+        return Err(DnsError::ParseZone);
+    };
+    data.push(length);
+    data.extend(bytes);
+    Ok(())
+}
+
+/// Append a <character-string> as specified in RFC 1035, section 3.3.
+fn push_character_string(data: &mut Vec<u8>, field: &str) -> Result<(), DnsError> {
+    push_length_prefixed(data, &unescape(field)?)
+}
+
+/// The bytes of a quoted field, with escapes of the form `\X` and `\DDD` resolved.
+fn unescape(field: &str) -> Result<Vec<u8>, DnsError> {
+    let mut bytes = Vec::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0u8; 4];
+            bytes.extend(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+
+        match chars.next() {
+            Some(digit) if digit.is_ascii_digit() => {
+                let digits: String = [Some(digit), chars.next(), chars.next()]
+                    .into_iter()
+                    .flatten()
+                    .collect();
+                let Ok(byte) = digits.parse::<u8>() else {
+                    return Err(DnsError::ParseZone);
+                };
+                if digits.len() != 3 {
+                    return Err(DnsError::ParseZone);
+                }
+                bytes.push(byte);
+            }
+            Some(escaped) if escaped.is_ascii() => bytes.push(escaped as u8),
+            _ => return Err(DnsError::ParseZone),
+        }
+    }
+    Ok(bytes)
+}
+
+/// The key of an SVCB parameter name, e.g. 3 for `port` or 667 for `key667`.
+fn svc_param_key(name: &str) -> Result<u16, DnsError> {
+    if let Some(key) = SVC_PARAM_KEYS.iter().position(|key| *key == name) {
+        return Ok(key as u16);
+    }
+    let Some(key) = name.strip_prefix("key") else {
+        return Err(DnsError::ParseZone);
+    };
+    number(key)
+}
+
+/// Append the SVCB parameters given as `name=value` fields, ordered by key as the wire format
+/// requires (RFC 9460, section 2.2). Lists are separated by commas, which cannot be escaped.
+fn push_svc_params<'a>(
+    data: &mut Vec<u8>,
+    fields: &mut impl Iterator<Item = &'a str>,
+) -> Result<(), DnsError> {
+    let mut params: Vec<(u16, Vec<u8>)> = Vec::new();
+    for field in fields {
+        let (name, value) = field.split_once('=').unwrap_or((field, ""));
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        let key = svc_param_key(name)?;
+        let list = value.split(',').filter(|item| !item.is_empty());
+
+        let mut encoded = Vec::new();
+        match key {
+            0 => {
+                for name in list {
+                    encoded.extend(svc_param_key(name)?.to_be_bytes());
+                }
+            }
+            1 => {
+                for protocol in list {
+                    push_character_string(&mut encoded, protocol)?;
+                }
+            }
+            2 if value.is_empty() => {}
+            3 => encoded.extend(number::<u16>(value)?.to_be_bytes()),
+            4 => {
+                for address in list {
+                    encoded.extend(number::<Ipv4Addr>(address)?.octets());
+                }
+            }
+            5 => {
+                let Ok(config) = BASE64.decode(value.as_bytes()) else {
+                    return Err(DnsError::ParseZone);
+                };
+                encoded.extend(config);
+            }
+            6 => {
+                for address in list {
+                    encoded.extend(number::<Ipv6Addr>(address)?.octets());
+                }
+            }
+            2 => return Err(DnsError::ParseZone),
+            _ => encoded.extend(unescape(value)?),
+        }
+        params.push((key, encoded));
+    }
+
+    params.sort_by_key(|(key, _)| *key);
+    if params.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+        return Err(DnsError::ParseZone);
+    }
+    for (key, value) in params {
+        let Ok(length) = u16::try_from(value.len()) else {
+            return Err(DnsError::ParseZone);
+        };
+        data.extend(key.to_be_bytes());
+        data.extend(length.to_be_bytes());
+        data.extend(value);
+    }
+    Ok(())
+}
+
+/// Append the type bitmap of NSEC and NSEC3 records as specified in RFC 4034, section 4.1.2.
+fn push_type_bitmap<'a>(
+    data: &mut Vec<u8>,
+    fields: &mut impl Iterator<Item = &'a str>,
+) -> Result<(), DnsError> {
+    let mut values = Vec::new();
+    for field in fields {
+        values.push(RecordType::value(record_type_by_name(field)?));
+    }
+    values.sort_unstable();
+    values.dedup();
+
+    // Types are grouped into windows of 256. Each window holds a bitmap just long enough to
+    // cover the highest type present in it.
+    let mut index = 0;
+    while index < values.len() {
+        let window = (values[index] >> 8) as u8;
+        let mut bitmap = [0u8; 32];
+        let mut bitmap_length = 0;
+        while index < values.len() && (values[index] >> 8) as u8 == window {
+            let low = (values[index] & 0xff) as usize;
+            bitmap[low / 8] |= 0x80 >> (low % 8);
+            bitmap_length = low / 8 + 1;
+            index += 1;
+        }
+        data.push(window);
+        data.push(bitmap_length as u8);
+        data.extend(&bitmap[..bitmap_length]);
+    }
+    Ok(())
+}
+
+/// Validate encoding of address and name-bearing record data.
+#[test]
+fn test_rdata_from_presentation_basic_types() -> Result<(), DnsError> {
+    assert_eq!(
+        from_presentation(RecordType::A, &["192.0.2.1"], "example.com")?,
+        [192, 0, 2, 1]
+    );
+    assert_eq!(
+        from_presentation(RecordType::AAAA, &["2001:db8::1"], "example.com")?,
+        [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]
+    );
+    assert_eq!(
+        from_presentation(RecordType::MX, &["10", "mail"], "example.com")?,
+        [
+            0, 10, 4, b'm', b'a', b'i', b'l', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c',
+            b'o', b'm', 0
+        ]
+    );
+    assert_eq!(
+        from_presentation(RecordType::CNAME, &["other.test."], "example.com")?,
+        [5, b'o', b't', b'h', b'e', b'r', 4, b't', b'e', b's', b't', 0]
+    );
+    assert_eq!(
+        from_presentation(
+            RecordType::SOA,
+            &[".", ".", "1", "1h", "15m", "1w", "60"],
+            ""
+        )?,
+        [0, 0, 0, 0, 0, 1, 0, 0, 14, 16, 0, 0, 3, 132, 0, 9, 58, 128, 0, 0, 0, 60]
+    );
+    Ok(())
+}
+
+/// Validate encoding of TXT strings with escapes.
+#[test]
+fn test_rdata_from_presentation_txt() -> Result<(), DnsError> {
+    assert_eq!(
+        from_presentation(RecordType::TXT, &["a b", r#"\"\065"#, ""], "")?,
+        [3, b'a', b' ', b'b', 2, b'"', b'A', 0]
+    );
+    assert!(from_presentation(RecordType::TXT, &[r"\25"], "").is_err());
+    assert!(from_presentation(RecordType::TXT, &[&"x".repeat(256)], "").is_err());
+    Ok(())
+}
+
+/// Validate encoding of DNSSEC record data.
+#[test]
+fn test_rdata_from_presentation_dnssec_types() -> Result<(), DnsError> {
+    assert_eq!(
+        from_presentation(RecordType::DS, &["60485", "5", "1", "2BB183AF", "5F22"], "")?,
+        [0xec, 0x45, 5, 1, 0x2b, 0xb1, 0x83, 0xaf, 0x5f, 0x22]
+    );
+    assert_eq!(
+        from_presentation(RecordType::DNSKEY, &["256", "3", "8", "AQID"], "")?,
+        [1, 0, 3, 8, 1, 2, 3]
+    );
+
+    // Types 1 (A), 15 (MX), 46 (RRSIG) and 47 (NSEC) in window 0.
+    assert_eq!(
+        from_presentation(
+            RecordType::NSEC,
+            &["host.example.com.", "A", "MX", "RRSIG", "NSEC"],
+            ""
+        )?,
+        [
+            4, b'h', b'o', b's', b't', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o',
+            b'm', 0, 0, 6, 0x40, 0x01, 0, 0, 0, 0x03
+        ]
+    );
+
+    let rrsig = from_presentation(
+        RecordType::RRSIG,
+        &[
+            "A",
+            "5",
+            "3",
+            "86400",
+            "20030322173103",
+            "20030220173103",
+            "2642",
+            "example.com.",
+            "AQID",
+        ],
+        "",
+    )?;
+    assert_eq!(rrsig[..4], [0, 1, 5, 3]);
+    assert_eq!(rrsig[8..12], 1048354263u32.to_be_bytes());
+    assert_eq!(rrsig[rrsig.len() - 3..], [1, 2, 3]);
+
+    assert_eq!(
+        from_presentation(RecordType::NSEC3, &["1", "0", "12", "AABB", "0O", "A"], "")?,
+        [1, 0, 0, 12, 2, 0xaa, 0xbb, 1, 0x06, 0, 1, 0x40]
+    );
+    Ok(())
+}
+
+/// Validate that record data survives the round trip through presentation format.
+#[test]
+fn test_rdata_presentation_round_trip() -> Result<(), DnsError> {
+    let examples = [
+        (RecordType::A, "192.0.2.1"),
+        (RecordType::AAAA, "2001:db8::1"),
+        (RecordType::NS, "ns1.example.com."),
+        (RecordType::CNAME, "."),
+        (
+            RecordType::SOA,
+            "ns1.example.com. hostmaster.example.com. 1 3600 900 604800 60",
+        ),
+        (RecordType::MX, "10 mail.example.com."),
+        (RecordType::SRV, "0 5 5060 sip.example.com."),
+        (
+            RecordType::DS,
+            "60485 5 1 2BB183AF5F22588179A53B0A98631FAD1A292118",
+        ),
+        (RecordType::DNSKEY, "256 3 8 AwEAAag="),
+        (RecordType::TLSA, "3 1 1 0C72AC70B745AC19998811B131D662C9"),
+        (RecordType::HTTPS, "0 example.com."),
+        (
+            RecordType::HTTPS,
+            "1 . alpn=h2,h3 no-default-alpn port=8443 ipv4hint=192.0.2.1,192.0.2.2 ech=AQID",
+        ),
+        (
+            RecordType::SVCB,
+            "16 svc.example.net. mandatory=alpn,ipv6hint alpn=dot ipv6hint=2001:db8::1 key667",
+        ),
+        (
+            RecordType::RRSIG,
+            "A 5 3 86400 20030322173103 20030220173103 2642 example.com. AQID",
+        ),
+        (RecordType::NSEC, "host.example.com. A MX RRSIG NSEC"),
+        (
+            RecordType::NSEC3,
+            "1 0 12 - 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR A RRSIG",
+        ),
+        (RecordType::OPT, r"\# 4 00030000"),
+    ];
+
+    for (record_type, presentation) in examples {
+        let fields: Vec<&str> = presentation.split(' ').collect();
+        let data = from_presentation(record_type, &fields, "")?;
+        assert_eq!(to_presentation(record_type, &data)?, presentation);
+    }
+
+    // Minimal responses to ANY queries, as specified in RFC 8482, section 4.2.
+    let data = from_presentation(RecordType::HINFO, &["RFC8482", ""], "")?;
+    assert_eq!(data, [7, b'R', b'F', b'C', b'8', b'4', b'8', b'2', 0]);
+    assert_eq!(
+        to_presentation(RecordType::HINFO, &data)?,
+        r#""RFC8482" """#
+    );
+    assert_eq!(
+        to_presentation(RecordType::Unknown(65), &[1, 2])?,
+        r"\# 2 0102"
+    );
+
+    let data = from_presentation(RecordType::TXT, &["v=spf1 -all", r#"\"\\ \007"#, ""], "")?;
+    assert_eq!(
+        to_presentation(RecordType::TXT, &data)?,
+        r#""v=spf1 -all" "\"\\ \007" """#
+    );
+
+    let data = from_presentation(
+        RecordType::NAPTR,
+        &[
+            "100",
+            "10",
+            "u",
+            "E2U+sip",
+            r"!^\\+1(.*)$!sip:\\1@example.com!",
+            ".",
+        ],
+        "",
+    )?;
+    assert_eq!(data[..7], [0, 100, 0, 10, 1, b'u', 7]);
+    assert_eq!(
+        to_presentation(RecordType::NAPTR, &data)?,
+        r#"100 10 "u" "E2U+sip" "!^\\+1(.*)$!sip:\\1@example.com!" ."#
+    );
+
+    // The value of CAA records is quoted but, unlike a <character-string>, not length-prefixed.
+    let data = from_presentation(
+        RecordType::CAA,
+        &["128", "issue", r"ca.example.net; a=\\"],
+        "",
+    )?;
+    assert_eq!(data[..8], [128, 5, b'i', b's', b's', b'u', b'e', b'c']);
+    assert_eq!(
+        to_presentation(RecordType::CAA, &data)?,
+        r#"128 issue "ca.example.net; a=\\""#
+    );
+
+    // Types toy_dns does not know are rendered by their value.
+    let data = [&[0][..], &[4, 2, 0, 0x20][..]].concat();
+    assert_eq!(to_presentation(RecordType::NSEC, &data)?, ". TYPE1034");
+    Ok(())
+}
+
+/// Validate that truncated or overlong record data cannot be rendered.
+#[test]
+fn test_rdata_to_presentation_malformed() {
+    assert!(to_presentation(RecordType::A, &[192, 0, 2]).is_err());
+    assert!(to_presentation(RecordType::MX, &[0, 10, 0, 1]).is_err());
+    assert!(to_presentation(RecordType::NS, &[0, 0]).is_err());
+    assert!(to_presentation(RecordType::TXT, &[]).is_err());
+    assert!(to_presentation(RecordType::CAA, &[0, 0, b'x']).is_err());
+    assert!(to_presentation(RecordType::CAA, &[0, 2, b'a', b'-']).is_err());
+    assert_eq!(generic_presentation(&[1, 255]), r"\# 2 01FF");
+}
+
+/// Validate that malformed record data is rejected.
+#[test]
+fn test_rdata_from_presentation_malformed() {
+    assert!(from_presentation(RecordType::A, &["192.0.2"], "").is_err());
+    assert!(from_presentation(RecordType::A, &["192.0.2.1", "extra"], "").is_err());
+    assert!(from_presentation(RecordType::MX, &["mail"], "").is_err());
+    assert!(from_presentation(RecordType::OPT, &[], "").is_err());
+    assert!(from_presentation(RecordType::NSEC, &["next.", "BOGUS"], "").is_err());
+    assert!(from_presentation(RecordType::CAA, &["0", "is-sue", "ca.example.net"], "").is_err());
+    assert!(from_presentation(RecordType::HTTPS, &["1", ".", "port=1", "port=2"], "").is_err());
+    assert!(from_presentation(RecordType::HTTPS, &["1", ".", "bogus=1"], "").is_err());
+    assert!(to_presentation(RecordType::HTTPS, &[0, 1, 0, 0, 3, 0, 1, 80]).is_err());
+}
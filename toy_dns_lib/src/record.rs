@@ -1,16 +1,46 @@
+use crate::dns_name::DnsName;
 use crate::errors::DnsError;
-use crate::record_name::RecordName;
-use byteorder::{BigEndian, ReadBytesExt};
+use crate::rdata;
+use crate::record_name::{CompressionOffsets, RecordName};
+use crate::ttl::Ttl;
+use crate::zone::class_name;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::cmp::Ordering;
 use std::fmt;
 use std::io::{Cursor, Read};
+use std::net::IpAddr;
 
 /// Types of DNS records supported by toy_dns.
-#[derive(PartialEq, Debug, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RecordType {
     Invalid,
     A,
     NS,
+    CNAME,
+    SOA,
+    PTR,
+    HINFO,
+    MX,
+    TXT,
     AAAA,
+    SRV,
+    NAPTR,
+    OPT,
+    DS,
+    RRSIG,
+    NSEC,
+    DNSKEY,
+    NSEC3,
+    TLSA,
+    SVCB,
+    HTTPS,
+    CAA,
+    ANY,
+
+    /// A type toy_dns does not know, kept by its value so that records of it can still be parsed
+    /// and passed along.
+    Unknown(u16),
 }
 
 impl fmt::Display for RecordType {
@@ -19,7 +49,28 @@ impl fmt::Display for RecordType {
             RecordType::Invalid => "INVALID",
             RecordType::A => "A",
             RecordType::NS => "NS",
+            RecordType::CNAME => "CNAME",
+            RecordType::SOA => "SOA",
+            RecordType::PTR => "PTR",
+            RecordType::HINFO => "HINFO",
+            RecordType::MX => "MX",
+            RecordType::TXT => "TXT",
             RecordType::AAAA => "AAAA",
+            RecordType::SRV => "SRV",
+            RecordType::NAPTR => "NAPTR",
+            RecordType::OPT => "OPT",
+            RecordType::DS => "DS",
+            RecordType::RRSIG => "RRSIG",
+            RecordType::NSEC => "NSEC",
+            RecordType::DNSKEY => "DNSKEY",
+            RecordType::NSEC3 => "NSEC3",
+            RecordType::TLSA => "TLSA",
+            RecordType::SVCB => "SVCB",
+            RecordType::HTTPS => "HTTPS",
+            RecordType::CAA => "CAA",
+            RecordType::ANY => "ANY",
+            // Unknown types are named after their value, as specified in RFC 3597, section 5.
+            RecordType::Unknown(value) => return write!(f, "TYPE{}", value),
         };
         write!(f, "{}", name)
     }
@@ -27,13 +78,36 @@ impl fmt::Display for RecordType {
 
 impl RecordType {
     /// The integer value of each record type. Record types with value <= 16 are defined in
-    /// RFC 1035. The AAAA record is specified in RFC 3596.
+    /// RFC 1035. The AAAA record is specified in RFC 3596, SRV in RFC 2782 and NAPTR in RFC 3403. The OPT pseudo-record is specified in
+    /// RFC 6891. The DNSSEC records (DS, RRSIG, NSEC, DNSKEY) are specified in RFC 4034 and NSEC3
+    /// in RFC 5155. ANY (255) is only valid in questions. TLSA is specified in RFC 6698, SVCB and
+    /// HTTPS in RFC 9460 and CAA in RFC 8659.
     pub fn value(record_type: RecordType) -> u16 {
         match record_type {
             RecordType::Invalid => 0,
             RecordType::A => 1,
             RecordType::NS => 2,
+            RecordType::CNAME => 5,
+            RecordType::SOA => 6,
+            RecordType::PTR => 12,
+            RecordType::HINFO => 13,
+            RecordType::MX => 15,
+            RecordType::TXT => 16,
             RecordType::AAAA => 28,
+            RecordType::SRV => 33,
+            RecordType::NAPTR => 35,
+            RecordType::OPT => 41,
+            RecordType::DS => 43,
+            RecordType::RRSIG => 46,
+            RecordType::NSEC => 47,
+            RecordType::DNSKEY => 48,
+            RecordType::NSEC3 => 50,
+            RecordType::TLSA => 52,
+            RecordType::SVCB => 64,
+            RecordType::HTTPS => 65,
+            RecordType::ANY => 255,
+            RecordType::CAA => 257,
+            RecordType::Unknown(value) => value,
         }
     }
 
@@ -42,16 +116,96 @@ impl RecordType {
             0 => Some(RecordType::Invalid),
             1 => Some(RecordType::A),
             2 => Some(RecordType::NS),
+            5 => Some(RecordType::CNAME),
+            6 => Some(RecordType::SOA),
+            12 => Some(RecordType::PTR),
+            13 => Some(RecordType::HINFO),
+            15 => Some(RecordType::MX),
+            16 => Some(RecordType::TXT),
             28 => Some(RecordType::AAAA),
+            33 => Some(RecordType::SRV),
+            35 => Some(RecordType::NAPTR),
+            41 => Some(RecordType::OPT),
+            43 => Some(RecordType::DS),
+            46 => Some(RecordType::RRSIG),
+            47 => Some(RecordType::NSEC),
+            48 => Some(RecordType::DNSKEY),
+            50 => Some(RecordType::NSEC3),
+            52 => Some(RecordType::TLSA),
+            64 => Some(RecordType::SVCB),
+            65 => Some(RecordType::HTTPS),
+            255 => Some(RecordType::ANY),
+            257 => Some(RecordType::CAA),
             _ => None,
         }
     }
+
+    /// The record type with the given value as found on the wire. Unlike `from()`, types toy_dns
+    /// does not know are returned as `Unknown` so that parsing can carry on past them.
+    ///
+    /// # Argument
+    /// * `record_type_value`: The value of the record type.
+    pub fn from_wire(record_type_value: u16) -> RecordType {
+        Self::from(record_type_value).unwrap_or(RecordType::Unknown(record_type_value))
+    }
+
+    /// The record type with the given mnemonic, as used in zone files. Matching is
+    /// case-insensitive. Types without a mnemonic are given as `TYPE` followed by their value.
+    ///
+    /// # Argument
+    /// * `name`: The mnemonic of the record type, e.g. "AAAA" or "TYPE65".
+    pub fn from_name(name: &str) -> Option<RecordType> {
+        let name = name.to_ascii_uppercase();
+        if let Some(value) = name.strip_prefix("TYPE") {
+            return value.parse::<u16>().ok().map(Self::from_wire);
+        }
+        ALL_RECORD_TYPES
+            .iter()
+            .find(|record_type| record_type.to_string() == name)
+            .copied()
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for RecordType {
+    /// The type of an arbitrary value, as parsed from the wire, so that known types are never
+    /// `Unknown`.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(RecordType::from_wire(u.arbitrary::<u16>()?))
+    }
 }
 
+/// Every record type that may appear in a zone file or on the wire, excluding `Invalid`.
+const ALL_RECORD_TYPES: [RecordType; 22] = [
+    RecordType::A,
+    RecordType::NS,
+    RecordType::CNAME,
+    RecordType::SOA,
+    RecordType::PTR,
+    RecordType::HINFO,
+    RecordType::MX,
+    RecordType::TXT,
+    RecordType::AAAA,
+    RecordType::SRV,
+    RecordType::NAPTR,
+    RecordType::OPT,
+    RecordType::DS,
+    RecordType::RRSIG,
+    RecordType::NSEC,
+    RecordType::DNSKEY,
+    RecordType::NSEC3,
+    RecordType::TLSA,
+    RecordType::SVCB,
+    RecordType::HTTPS,
+    RecordType::ANY,
+    RecordType::CAA,
+];
+
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Record {
     /// Name of the DNS Record.
-    pub name: Vec<u8>,
+    pub name: DnsName,
 
     /// Type of the DNS Record.
     pub r_type: RecordType,
@@ -59,8 +213,8 @@ pub struct Record {
     /// Class of the DNS Record.
     pub r_class: u16,
 
-    /// TTL for the DNS record.
-    pub ttl: u32,
+    /// TTL for the DNS record, along with when it was received.
+    pub ttl: Ttl,
 
     /// Data for the DNS record.
     pub data: Vec<u8>,
@@ -69,27 +223,100 @@ pub struct Record {
 impl Default for Record {
     fn default() -> Self {
         Self {
-            name: vec![],
+            name: DnsName::root(),
             r_type: RecordType::Invalid,
             r_class: 0,
-            ttl: 0,
+            ttl: Ttl::default(),
             data: vec![],
         }
     }
 }
 
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Record {
+    /// A record of class IN. Its data is of the right shape for a few common types, so that it
+    /// parses back the same, or arbitrary for a type in the private use range.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let name = u.arbitrary::<DnsName>()?;
+        let ttl = u.arbitrary::<Ttl>()?;
+        let encode = |name: DnsName| name.to_canonical_wire().expect("arbitrary names encode");
+        let (r_type, data) = match u.int_in_range(0..=5)? {
+            0 => (RecordType::A, u.bytes(4)?.to_vec()),
+            1 => (RecordType::AAAA, u.bytes(16)?.to_vec()),
+            2 => (RecordType::NS, encode(u.arbitrary::<DnsName>()?)),
+            3 => {
+                let mut data = u.arbitrary::<u16>()?.to_be_bytes().to_vec();
+                data.extend(encode(u.arbitrary::<DnsName>()?));
+                (RecordType::MX, data)
+            }
+            4 => {
+                let length = u.int_in_range(0..=u8::MAX)?;
+                let mut data = vec![length];
+                data.extend(u.bytes(length as usize)?);
+                (RecordType::TXT, data)
+            }
+            _ => {
+                let r_type = RecordType::from_wire(0xFF00 | u16::from(u.arbitrary::<u8>()?));
+                let length = u.int_in_range(0..=512)?;
+                (r_type, u.bytes(length)?.to_vec())
+            }
+        };
+        Ok(Record {
+            name,
+            r_type,
+            r_class: crate::query::CLASS_IN,
+            ttl,
+            data,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    /// Write the record in presentation format, with the data rendered according to its type:
+    /// addresses for A and AAAA records, names for NS, CNAME and PTR records, the preference and
+    /// exchange for MX records, quoted strings for TXT records, and so on, e.g.
+    /// `example.com. 60 IN MX 10 mail.example.com.`. Data of unknown types, or which cannot be
+    /// decoded, is written as hex in the generic format of RFC 3597.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}. {} {} {} {}",
+            self.name,
+            self.ttl,
+            class_name(self.r_class),
+            self.r_type,
+            self.data_to_presentation()
+        )
+    }
+}
+
 impl Record {
-    /// The IP address of the record as a string.
+    /// The IP address of the record as a string. Only meaningful for A records; see `Display` for
+    /// the data of other types.
     pub fn ip_address(&self) -> String {
         let mut address = String::new();
         let mut data_iterator = self.data.iter().peekable();
         while let Some(datum) = data_iterator.next() {
             address.push_str(&format!("{}", datum));
             if data_iterator.peek().is_some() {
-                address.push_str(".");
+                address.push('.');
             }
         }
-        return address;
+        address
+    }
+
+    /// The address held by an A or AAAA record. `None` for other records, and for data of the
+    /// wrong length.
+    pub fn to_ip_addr(&self) -> Option<IpAddr> {
+        match self.r_type {
+            RecordType::A => Some(IpAddr::from(
+                <[u8; 4]>::try_from(self.data.as_slice()).ok()?,
+            )),
+            RecordType::AAAA => Some(IpAddr::from(
+                <[u8; 16]>::try_from(self.data.as_slice()).ok()?,
+            )),
+            _ => None,
+        }
     }
 
     /// Read a DNS record at the given cursor. Cursor will advance (even if the function fails) up to the last
@@ -99,29 +326,277 @@ impl Record {
     /// * `cursor`: The byte buffer containing the full DNS message data.
     pub fn read_and_advance(cursor: &mut Cursor<&[u8]>) -> Result<Record, DnsError> {
         let record_name = RecordName::read_and_advance(cursor)?;
-        let Ok(parsed_type) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ReadRecordType) };
-        let Some(record_type) = RecordType::from(parsed_type) else { return Err(DnsError::UnrecognizedRecordType) };
-        let Ok(parsed_class) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ReadRecordClass) };
-        let Ok(parsed_ttl) = cursor.read_u32::<BigEndian>() else { return Err(DnsError::ReadRecordTTL) };
-        let Ok(parsed_data_length) = cursor.read_u16::<BigEndian>() else { return Err(DnsError::ReadRecordDataLength) };
+        let parsed_type = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ReadRecordType(Some(error)))?;
+        let record_type = RecordType::from_wire(parsed_type);
+        let parsed_class = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ReadRecordClass(Some(error)))?;
+        let parsed_ttl = cursor
+            .read_u32::<BigEndian>()
+            .map_err(|error| DnsError::ReadRecordTTL(Some(error)))?;
+        let parsed_data_length = cursor
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::ReadRecordDataLength(Some(error)))?;
 
-        let mut data = vec![0u8; parsed_data_length as usize];
-        let Ok(_) = cursor.read_exact(&mut data) else { return Err(DnsError::ReadRecordData) };
+        let data = Self::read_data_and_advance(record_type, parsed_data_length, cursor)?;
 
         Ok(Record {
-            name: record_name,
+            name: DnsName::from(record_name),
             r_type: record_type,
             r_class: parsed_class,
-            ttl: parsed_ttl,
-            data: data,
+            ttl: Ttl::from(parsed_ttl),
+            data,
         })
     }
+
+    /// Read the data of a record at the given cursor. Names within the data of the RFC 1035 types
+    /// (and SRV) may be compressed; they are decompressed so that the data stands on its own
+    /// outside of the message it was read from.
+    ///
+    /// # Arguments
+    /// * `record_type`: The type of the record.
+    /// * `data_length`: The length of the data on the wire.
+    /// * `cursor`: The byte buffer containing the full DNS message data.
+    fn read_data_and_advance(
+        record_type: RecordType,
+        data_length: u16,
+        cursor: &mut Cursor<&[u8]>,
+    ) -> Result<Vec<u8>, DnsError> {
+        // The number of octets preceding the names, and the number of names.
+        let (prefix_length, name_count) = match record_type {
+            RecordType::NS | RecordType::CNAME | RecordType::PTR => (0, 1),
+            RecordType::SOA => (0, 2),
+            RecordType::MX => (2, 1),
+            RecordType::SRV => (6, 1),
+            _ => (data_length as usize, 0),
+        };
+        let end = cursor.position() + data_length as u64;
+
+        let mut data = vec![0u8; prefix_length.min(data_length as usize)];
+        cursor
+            .read_exact(&mut data)
+            .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+        for _ in 0..name_count {
+            RecordName::decompress_and_advance_into(cursor, &mut data)?;
+        }
+
+        // Whatever follows the names is copied as is.
+        let Some(remaining) = end.checked_sub(cursor.position()) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let mut rest = vec![0u8; remaining as usize];
+        cursor
+            .read_exact(&mut rest)
+            .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+        data.extend(rest);
+        Ok(data)
+    }
+
+    /// Render the record in presentation format as used in zone files, e.g.
+    /// `example.com. 1800 IN A 93.184.216.34`. Data which cannot be decoded is rendered in the
+    /// generic format of RFC 3597.
+    pub fn to_presentation(&self) -> String {
+        format!("{}", self)
+    }
+
+    /// Render the data of the record in presentation format, e.g. `93.184.216.34` for an A
+    /// record. Data which cannot be decoded is rendered in the generic format of RFC 3597.
+    pub fn data_to_presentation(&self) -> String {
+        rdata::to_presentation(self.r_type, &self.data)
+            .unwrap_or_else(|_| rdata::generic_presentation(&self.data))
+    }
+
+    /// Serialize the record in wire format (without name compression) onto the given buffer.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized record to.
+    pub fn serialize(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        let Ok(name) = std::str::from_utf8(&self.name) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        bytes.extend(RecordName { name }.encode()?);
+
+        let Ok(data_length) = u16::try_from(self.data.len()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(self.r_type)) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(self.r_class) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u32::<BigEndian>(self.ttl.as_secs()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(data_length) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        bytes.extend(&self.data);
+        Ok(())
+    }
+
+    /// Serialize the record in wire format onto the given message, compressing the owner name and
+    /// the names within the data of the RFC 1035 types against the names already in the message.
+    /// Data whose names cannot be told apart is written as is.
+    ///
+    /// # Arguments
+    /// * `bytes`: The message to append the serialized record to.
+    /// * `offsets`: The names written to the message so far.
+    pub(crate) fn serialize_compressed(
+        &self,
+        bytes: &mut Vec<u8>,
+        offsets: &mut CompressionOffsets,
+    ) -> Result<(), DnsError> {
+        let Ok(name) = std::str::from_utf8(&self.name) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        RecordName::compress_into(&RecordName { name }.encode()?, bytes, offsets);
+
+        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(self.r_type)) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(self.r_class) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u32::<BigEndian>(self.ttl.as_secs()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let length_position = bytes.len();
+        let Ok(_) = bytes.write_u16::<BigEndian>(0) else {
+            return Err(DnsError::QuerySerialization);
+        };
+
+        // The number of octets preceding the names, and the number of names. Names in the data of
+        // later types, such as SRV, must not be compressed (RFC 3597, section 4).
+        let (prefix_length, name_count) = match self.r_type {
+            RecordType::NS | RecordType::CNAME | RecordType::PTR => (0, 1),
+            RecordType::SOA => (0, 2),
+            RecordType::MX => (2, 1),
+            _ => (self.data.len(), 0),
+        };
+        let mut names = Vec::with_capacity(name_count);
+        let mut position = prefix_length.min(self.data.len());
+        for _ in 0..name_count {
+            let Some(length) = RecordName::encoded_length(&self.data[position..]) else {
+                break;
+            };
+            names.push(position..position + length);
+            position += length;
+        }
+
+        if names.len() == name_count {
+            bytes.extend(&self.data[..prefix_length]);
+            for name in names {
+                RecordName::compress_into(&self.data[name], bytes, offsets);
+            }
+            bytes.extend(&self.data[position..]);
+        } else {
+            bytes.extend(&self.data);
+        }
+
+        let Ok(data_length) = u16::try_from(bytes.len() - length_position - 2) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        bytes[length_position..length_position + 2].copy_from_slice(&data_length.to_be_bytes());
+        Ok(())
+    }
+
+    /// Serialize the record in the canonical form of RFC 4034, section 6.2, onto the given
+    /// buffer, as covered by a signature: the owner name and the names within the data are
+    /// uncompressed and lowercased.
+    ///
+    /// # Argument
+    /// * `bytes`: The buffer to append the serialized record to.
+    pub fn serialize_canonical(&self, bytes: &mut Vec<u8>) -> Result<(), DnsError> {
+        bytes.extend(self.name.to_canonical_wire()?);
+
+        let data = self.canonical_data()?;
+        let Ok(data_length) = u16::try_from(data.len()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(RecordType::value(self.r_type)) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(self.r_class) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u32::<BigEndian>(self.ttl.as_secs()) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        let Ok(_) = bytes.write_u16::<BigEndian>(data_length) else {
+            return Err(DnsError::QuerySerialization);
+        };
+        bytes.extend(data);
+        Ok(())
+    }
+
+    /// The data of the record in canonical form: names within the data of the types listed in
+    /// RFC 4034, section 6.2, as amended by section 5.1 of RFC 6840, are lowercased. The data is
+    /// already uncompressed, as names are decompressed when records are read.
+    pub fn canonical_data(&self) -> Result<Vec<u8>, DnsError> {
+        // The number of octets preceding the names, and the number of names.
+        let (prefix_length, name_count) = match self.r_type {
+            RecordType::NS | RecordType::CNAME | RecordType::PTR => (0, 1),
+            RecordType::SOA => (0, 2),
+            RecordType::MX => (2, 1),
+            RecordType::SRV => (6, 1),
+            RecordType::RRSIG => (18, 1),
+            _ => (0, 0),
+        };
+
+        let mut data = self.data.clone();
+        let mut position = prefix_length;
+        for _ in 0..name_count {
+            loop {
+                let Some(&length) = data.get(position) else {
+                    return Err(DnsError::ReadRecordData(None));
+                };
+                position += 1;
+                if length == 0 {
+                    break;
+                }
+                let end = position + length as usize;
+                let Some(label) = data.get_mut(position..end) else {
+                    return Err(DnsError::ReadRecordData(None));
+                };
+                label.make_ascii_lowercase();
+                position = end;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Compare the record with another in canonical order: by owner name as specified in RFC
+    /// 4034, section 6.1, then by class and type, then by canonical data as specified in section
+    /// 6.3, as a left-justified octet string. Data which cannot be made canonical is compared as
+    /// is.
+    ///
+    /// # Argument
+    /// * `other`: The record to compare against.
+    pub fn cmp_canonical(&self, other: &Record) -> Ordering {
+        self.name
+            .cmp(&other.name)
+            .then(self.r_class.cmp(&other.r_class))
+            .then(RecordType::value(self.r_type).cmp(&RecordType::value(other.r_type)))
+            .then_with(|| {
+                let data = self.canonical_data().unwrap_or_else(|_| self.data.clone());
+                let other_data = other
+                    .canonical_data()
+                    .unwrap_or_else(|_| other.data.clone());
+                data.cmp(&other_data)
+            })
+    }
 }
 
 pub trait DnsRecordGetters {
     /// Get the first A record from the array of DNS records.
     fn get_first_a_record(&self) -> Option<&Record>;
 
+    /// Get the first AAAA record from the array of DNS records.
+    fn get_first_aaaa_record(&self) -> Option<&Record>;
+
     /// Get the first NS record from the array of DNS records.
     fn get_first_ns_record(&self) -> Option<&Record>;
 }
@@ -129,19 +604,38 @@ pub trait DnsRecordGetters {
 impl DnsRecordGetters for [Record] {
     /// Retrieve the first A record from an array of records.
     fn get_first_a_record(&self) -> Option<&Record> {
-        self.iter()
-            .filter(|record| record.r_type == RecordType::A)
-            .next()
+        self.iter().find(|record| record.r_type == RecordType::A)
+    }
+
+    /// Retrieve the first AAAA record from an array of records.
+    fn get_first_aaaa_record(&self) -> Option<&Record> {
+        self.iter().find(|record| record.r_type == RecordType::AAAA)
     }
 
     /// Retrieve the first NS record from an array of records.
     fn get_first_ns_record(&self) -> Option<&Record> {
-        self.iter()
-            .filter(|record| record.r_type == RecordType::NS)
-            .next()
+        self.iter().find(|record| record.r_type == RecordType::NS)
     }
 }
 
+/// Validate that record types are looked up by their mnemonic.
+#[test]
+fn test_record_type_from_name() {
+    assert_eq!(RecordType::from_name("aaaa"), Some(RecordType::AAAA));
+    assert_eq!(RecordType::from_name("NSEC3"), Some(RecordType::NSEC3));
+    assert_eq!(RecordType::from_name("any"), Some(RecordType::ANY));
+    assert_eq!(RecordType::from_name("TYPE13"), Some(RecordType::HINFO));
+    assert_eq!(
+        RecordType::from_name("type65280"),
+        Some(RecordType::Unknown(65280))
+    );
+    assert_eq!(RecordType::from_name("type65"), Some(RecordType::HTTPS));
+    assert_eq!(RecordType::Unknown(65280).to_string(), "TYPE65280");
+    assert_eq!(RecordType::from_name("INVALID"), None);
+    assert_eq!(RecordType::from_name("BOGUS"), None);
+    assert_eq!(RecordType::from_name("TYPE"), None);
+}
+
 /// Validate serialization of an IP address from a record
 #[test]
 fn test_query_serialization() {
@@ -154,6 +648,26 @@ fn test_query_serialization() {
     assert_eq!(record.ip_address(), "93.184.216.34");
 }
 
+/// Validate that a serialized record parses back into the same record.
+#[test]
+fn test_record_serialization_round_trip() -> Result<(), DnsError> {
+    let record = Record {
+        name: DnsName::from("www.example.com"),
+        r_type: RecordType::A,
+        r_class: 1,
+        ttl: Ttl::from(7658),
+        data: vec![93, 184, 216, 34],
+    };
+
+    let mut bytes = Vec::new();
+    record.serialize(&mut bytes)?;
+
+    let mut cursor = Cursor::new(bytes.as_slice());
+    assert_eq!(Record::read_and_advance(&mut cursor)?, record);
+    assert_eq!(cursor.position() as usize, bytes.len());
+    Ok(())
+}
+
 #[test]
 fn test_parsing_valid_record() {
     use crate::record::RecordType;
@@ -177,15 +691,43 @@ fn test_parsing_valid_record() {
     assert_eq!(
         result.unwrap_or_default(),
         Record {
-            name: "www.example.com".chars().map(|c| c as u8).collect(),
+            name: DnsName::from("www.example.com"),
             r_type: RecordType::A,
             r_class: 1,
-            ttl: 29 << 8 | 234,
+            ttl: Ttl::from(29 << 8 | 234),
             data: vec![93, 184, 216, 34]
         }
     )
 }
 
+/// Validate that compressed names within record data are decompressed while parsing.
+#[test]
+fn test_parsing_record_with_compressed_data() -> Result<(), DnsError> {
+    let data = [
+        // example.com at offset 0, followed by an MX record for it pointing at mail.example.com
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+        //   Type  Class TTL         Len    Pref   mail                    Ptr
+        192, 0, 0, 15, 0, 1, 0, 0, 0, 60, 0, 9, 0, 10, 4, b'm', b'a', b'i', b'l', 192, 0,
+    ];
+    let mut cursor = Cursor::new(data.as_slice());
+    cursor.set_position(13);
+
+    let record = Record::read_and_advance(&mut cursor)?;
+    assert_eq!(cursor.position() as usize, data.len());
+    assert_eq!(
+        record.data,
+        [
+            0, 10, 4, b'm', b'a', b'i', b'l', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c',
+            b'o', b'm', 0
+        ]
+    );
+    assert_eq!(
+        record.to_presentation(),
+        "example.com. 60 IN MX 10 mail.example.com."
+    );
+    Ok(())
+}
+
 /// Validate record parsing can handle a buffer too small to hold a record.
 #[test]
 fn test_parsing_incomplete_record_buffer() {
@@ -215,7 +757,7 @@ fn test_parsing_incomplete_record_data() {
     cursor.set_position(33);
 
     let result = Record::read_and_advance(&mut cursor);
-    assert_eq!(result, Err(DnsError::ReadRecordData));
+    assert_eq!(result, Err(DnsError::ReadRecordData(None)));
 }
 
 /// Validate that get_first_a_record() returns the correct record when it's the first in the array.
@@ -239,7 +781,7 @@ fn test_get_first_a_record_when_first_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_1.clone(), record_2, record_3];
+    let records = [record_1.clone(), record_2, record_3];
     assert_eq!(records.get_first_a_record(), Some(&record_1));
 }
 
@@ -265,7 +807,7 @@ fn test_get_first_a_record_when_middle_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_2, record_1.clone(), record_3];
+    let records = [record_2, record_1.clone(), record_3];
     assert_eq!(records.get_first_a_record(), Some(&record_1));
 }
 
@@ -290,7 +832,7 @@ fn test_get_first_a_record_when_last_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_2, record_3, record_1.clone()];
+    let records = [record_2, record_3, record_1.clone()];
     assert_eq!(records.get_first_a_record(), Some(&record_1));
 }
 
@@ -315,7 +857,7 @@ fn test_get_first_ns_record_when_first_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_1.clone(), record_2, record_3];
+    let records = [record_1.clone(), record_2, record_3];
     assert_eq!(records.get_first_ns_record(), Some(&record_1));
 }
 
@@ -341,7 +883,7 @@ fn test_get_first_ns_record_when_middle_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_2, record_1.clone(), record_3];
+    let records = [record_2, record_1.clone(), record_3];
     assert_eq!(records.get_first_ns_record(), Some(&record_1));
 }
 
@@ -366,6 +908,142 @@ fn test_get_first_ns_record_when_last_of_many() {
         ..Default::default()
     };
 
-    let records = vec![record_2, record_3, record_1.clone()];
+    let records = [record_2, record_3, record_1.clone()];
     assert_eq!(records.get_first_ns_record(), Some(&record_1));
 }
+
+/// Validate the canonical form of records, with the names in their data lowercased.
+#[test]
+fn test_serialize_canonical() {
+    let record = Record {
+        name: DnsName::from("Example.COM"),
+        r_type: RecordType::MX,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: b"\x00\x0a\x04Mail\x07Example\x03COM\x00".to_vec(),
+    };
+    assert_eq!(
+        record.canonical_data(),
+        Ok(b"\x00\x0a\x04mail\x07example\x03com\x00".to_vec())
+    );
+
+    let mut bytes = Vec::new();
+    record.serialize_canonical(&mut bytes).unwrap();
+    let mut expected = b"\x07example\x03com\x00\x00\x0f\x00\x01\x00\x00\x01\x2c\x00\x14".to_vec();
+    expected.extend(b"\x00\x0a\x04mail\x07example\x03com\x00");
+    assert_eq!(bytes, expected);
+
+    // The data of other types is left alone, and truncated names are rejected.
+    let txt = Record {
+        r_type: RecordType::TXT,
+        data: b"\x02AB".to_vec(),
+        ..Default::default()
+    };
+    assert_eq!(txt.canonical_data(), Ok(b"\x02AB".to_vec()));
+    let cname = Record {
+        r_type: RecordType::CNAME,
+        data: b"\x07Example".to_vec(),
+        ..Default::default()
+    };
+    assert_eq!(cname.canonical_data(), Err(DnsError::ReadRecordData(None)));
+}
+
+/// Validate that records are ordered by owner name, then class and type, then data.
+#[test]
+fn test_cmp_canonical() {
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: data.to_vec(),
+    };
+
+    let ordered = [
+        record("example", RecordType::NS, b"\x02ns\x07example\x00"),
+        record("a.example", RecordType::A, b"\x0a\x00\x00\x02"),
+        record("a.example", RecordType::A, b"\x0a\x00\x00\x0a"),
+        record("a.example", RecordType::MX, b"\x00\x0a\x01b\x07example\x00"),
+        record("a.example", RecordType::MX, b"\x00\x0a\x01C\x07example\x00"),
+        record("Z.a.example", RecordType::A, b"\x0a\x00\x00\x01"),
+    ];
+    let mut records = ordered.to_vec();
+    records.reverse();
+    records.sort_by(Record::cmp_canonical);
+    assert_eq!(records, ordered);
+
+    let upper = record("A.EXAMPLE", RecordType::CNAME, b"\x01B\x07EXAMPLE\x00");
+    let lower = record("a.example", RecordType::CNAME, b"\x01b\x07example\x00");
+    assert_eq!(upper.cmp_canonical(&lower), Ordering::Equal);
+}
+
+/// Validate that records display their data according to their type.
+#[test]
+fn test_record_display() {
+    let record = |r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from("example.com"),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(60),
+        data: data.to_vec(),
+    };
+
+    assert_eq!(
+        format!("{}", record(RecordType::A, &[93, 184, 216, 34])),
+        "example.com. 60 IN A 93.184.216.34"
+    );
+    let mut ipv6 = vec![0x20, 0x01, 0x0d, 0xb8];
+    ipv6.extend([0; 11]);
+    ipv6.push(1);
+    assert_eq!(
+        format!("{}", record(RecordType::AAAA, &ipv6)),
+        "example.com. 60 IN AAAA 2001:db8::1"
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            record(RecordType::CNAME, b"\x03www\x07example\x03com\x00")
+        ),
+        "example.com. 60 IN CNAME www.example.com."
+    );
+    assert_eq!(
+        format!(
+            "{}",
+            record(RecordType::MX, b"\x00\x0a\x04mail\x07example\x03com\x00")
+        ),
+        "example.com. 60 IN MX 10 mail.example.com."
+    );
+    assert_eq!(
+        format!("{}", record(RecordType::TXT, b"\x05hello\x05world")),
+        "example.com. 60 IN TXT \"hello\" \"world\""
+    );
+    assert_eq!(
+        format!("{}", record(RecordType::Unknown(65280), &[0xab, 0xcd])),
+        "example.com. 60 IN TYPE65280 \\# 2 ABCD"
+    );
+}
+
+/// Validate reading the address of A and AAAA records.
+#[test]
+fn test_record_to_ip_addr() {
+    let record = |r_type: RecordType, data: &[u8]| Record {
+        r_type,
+        data: data.to_vec(),
+        ..Default::default()
+    };
+    assert_eq!(
+        record(RecordType::A, &[192, 0, 2, 1]).to_ip_addr(),
+        Some(IpAddr::from([192, 0, 2, 1]))
+    );
+    let mut ipv6 = [0u8; 16];
+    ipv6[..4].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+    ipv6[15] = 1;
+    assert_eq!(
+        record(RecordType::AAAA, &ipv6)
+            .to_ip_addr()
+            .map(|address| address.to_string()),
+        Some("2001:db8::1".to_owned())
+    );
+    assert_eq!(record(RecordType::A, &ipv6).to_ip_addr(), None);
+    assert_eq!(record(RecordType::NS, &[192, 0, 2, 1]).to_ip_addr(), None);
+}
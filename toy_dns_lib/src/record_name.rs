@@ -1,7 +1,8 @@
 use crate::errors::DnsError;
-use byteorder::ReadBytesExt;
-use log::debug;
-use std::io::{Cursor, Seek, SeekFrom};
+#[cfg(feature = "idna")]
+use crate::idna;
+use std::collections::HashMap;
+use std::io::Cursor;
 
 /// Establish an underlying type for a name that has been encoded
 type EncodedName = Vec<u8>;
@@ -15,118 +16,268 @@ pub struct RecordName<'a> {
 /// compression pointer.
 const COMPRESSION_SIGNIFIER: u8 = 0b1100_0000;
 
+/// Longest label allowed by section 2.3.4 of RFC 1035, in octets.
+pub(crate) const MAX_LABEL_LENGTH: u8 = 63;
+
+/// Longest name allowed by section 2.3.4 of RFC 1035, in octets on the wire: the labels with
+/// their length bytes, and the terminating null byte.
+pub(crate) const MAX_NAME_LENGTH: usize = 255;
+
+/// Most compression pointers followed while reading a single name. A name is at most 255 octets
+/// long, so it has at most 127 labels, each of which may be reached through a pointer; a name
+/// needing more can only be following pointers in a loop.
+const MAX_COMPRESSION_POINTERS: usize = 127;
+
+/// Largest offset a compression pointer can hold, in the 14 bits below the signifier.
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
+
+/// Offsets within a message of the names written so far, by their labels from that offset on in
+/// wire format, so that names written later can point to them.
+pub(crate) type CompressionOffsets = HashMap<Vec<u8>, u16>;
+
 impl<'a> RecordName<'a> {
-    /// Encode the name into a format appropriate for queries over the wire.
+    /// Encode the name into a format appropriate for queries over the wire. Both the empty name
+    /// and "." encode the root. A trailing dot is otherwise optional. Empty labels, as in
+    /// "a..b" or ".a", labels longer than 63 octets and names longer than 255 octets are
+    /// rejected. With the `idna` feature, names holding Unicode labels are converted to their
+    /// ASCII form first, see `idna::to_ascii()`.
+    #[allow(
+        clippy::needless_character_iteration,
+        clippy::needless_return,
+        clippy::useless_conversion
+    )]
     pub fn encode(&'a self) -> Result<EncodedName, DnsError> {
-        if !self.name.chars().all(|c| c.is_ascii()) {
+        if !self.name.is_ascii() {
+            #[cfg(feature = "idna")]
+            return RecordName {
+                name: &idna::to_ascii(self.name)?,
+            }
+            .encode();
+            #[cfg(not(feature = "idna"))]
             return Err(DnsError::InvalidByteInName);
         }
 
-        let name_parts = self.name.split(".");
+        let name = self.name.strip_suffix('.').unwrap_or(self.name);
         let mut name_bytes = EncodedName::new();
+        if name.is_empty() {
+            name_bytes.push(0x0);
+            return Ok(name_bytes);
+        }
+
+        let name_parts = name.split(".");
         for part in name_parts {
+            if part.is_empty() {
+                return Err(DnsError::InvalidByteInName);
+            }
+            if part.len() > MAX_LABEL_LENGTH as usize {
+                return Err(DnsError::LabelTooLong);
+            }
             let mut part_as_bytes = vec![part.len() as u8];
-            part_as_bytes.extend(part.chars().into_iter().map(|c| c as u8));
+            part_as_bytes.extend(part.chars().map(|c| c as u8));
             name_bytes.extend(part_as_bytes)
         }
 
         // The name needs to be null-terminated which will not be done automatically
         name_bytes.push(0x0);
-        return Ok(name_bytes);
+        if name_bytes.len() > MAX_NAME_LENGTH {
+            return Err(DnsError::NameTooLong);
+        }
+        Ok(name_bytes)
     }
 
-    /// Read a DNS record name at the given cursor. Cursor will advance (even if the function fails)
-    /// up to the last successful byte read.
+    /// Read a DNS record name at the given cursor, in dotted form without a trailing dot. Cursor
+    /// will advance (even if the function fails) up to the last successful byte read.
     ///
     /// # Arguments
     /// * `cursor`: The byte buffer containing the full DNS message data.
     pub fn read_and_advance(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>, DnsError> {
-        let mut parts: Vec<String> = Vec::new();
+        let mut name = Vec::new();
+        Self::read_and_advance_into(cursor, &mut name)?;
+        Ok(name)
+    }
 
-        // Loop as long as we continue to see valid bytes
-        loop {
-            match cursor.read_u8() {
-                // If we encounter a null terminator, then we're done
-                Ok(0) => break,
-
-                // If we get a value, then this indicates length of the name-part.
-                // Processing within this scope advances the cursor so that next time this loops,
-                // the cursor should be at another length byte (if any).
-                Ok(length) => {
-                    // Section 4.1.4 of RFC 1035 specifies a compression scheme used to reduce
-                    // the data transmitted for verbose DNS messages. In this scheme, a "pointer"
-                    // is indicated by setting the first two bytes with 1s.
-                    if length & COMPRESSION_SIGNIFIER > 0 {
-                        // In this case, we need decompression.
-
-                        // Because length has the first two bits set, length cannot be taken
-                        // "literally" as a value. Before the actual length can be read from the
-                        // variable, we need to unset the first two bits.
-                        let length_without_compression_signifiers = length & !COMPRESSION_SIGNIFIER;
-                        let Ok(part_string) = std::string::String::from_utf8(
-                            Self::read_and_advance_compressed_bytes(
-                                length_without_compression_signifiers,
-                                cursor,
-                            )?,
-                        ) else {
-                            return Err(DnsError::InvalidByteInName)
-                        };
-                        parts.push(part_string);
-                        break;
-                    } else {
-                        let mut part_bytes = Vec::new();
-                        // In this case, we don't need to handle decompression.
-                        // Pick the number of bytes indicated by length. This advances the cursor.
-                        for _ in 0..length {
-                            match cursor.read_u8() {
-                                Ok(byte) => {
-                                    part_bytes.push(byte);
-                                }
-                                Err(_) => {
-                                    return Err(DnsError::ReadByte);
-                                }
-                            }
-                        }
-
-                        let Ok(part_string) = std::string::String::from_utf8(part_bytes) else {
-                            return Err(DnsError::InvalidByteInName)
-                         };
-                        parts.push(part_string);
-                    }
-                }
+    /// Read a DNS record name at the given cursor and append it to the given buffer in dotted
+    /// form, without a trailing dot. Unlike `read_and_advance()`, this lets a caller reading
+    /// many names reuse the same buffer.
+    ///
+    /// # Arguments
+    /// * `cursor`: The byte buffer containing the full DNS message data.
+    /// * `name`: The buffer to append the name to.
+    pub fn read_and_advance_into(
+        cursor: &mut Cursor<&[u8]>,
+        name: &mut Vec<u8>,
+    ) -> Result<(), DnsError> {
+        let start = name.len();
+        Self::read_labels_and_advance(cursor, |label| {
+            if name.len() > start {
+                name.push(b'.');
+            }
+            name.extend_from_slice(label);
+        })?;
+        if std::str::from_utf8(&name[start..]).is_err() {
+            return Err(DnsError::InvalidByteInName);
+        }
+        Ok(())
+    }
 
-                Err(_) => return Err(DnsError::ReadLength),
+    /// Read a DNS record name at the given cursor and append it to the given buffer in wire
+    /// format, with any compression pointers followed, so that it stands on its own outside of
+    /// the message it was read from.
+    ///
+    /// # Arguments
+    /// * `cursor`: The byte buffer containing the full DNS message data.
+    /// * `encoded`: The buffer to append the encoded name to.
+    pub fn decompress_and_advance_into(
+        cursor: &mut Cursor<&[u8]>,
+        encoded: &mut EncodedName,
+    ) -> Result<(), DnsError> {
+        let start = encoded.len();
+        Self::read_labels_and_advance(cursor, |label| {
+            encoded.push(label.len() as u8);
+            encoded.extend_from_slice(label);
+        })?;
+        if !encoded[start..].is_ascii() {
+            return Err(DnsError::InvalidByteInName);
+        }
+        encoded.push(0x0);
+        Ok(())
+    }
+
+    /// Append an encoded name to the given message, replacing the longest ending of the name
+    /// already in the message with a compression pointer, as described in section 4.1.4 of
+    /// RFC 1035. Endings are matched exactly, case included, so that the name reads back the same.
+    ///
+    /// # Arguments
+    /// * `encoded`: The name in wire format, uncompressed, e.g. as returned by `encode()`.
+    /// * `bytes`: The message to append the name to.
+    /// * `offsets`: The names written to the message so far, which the name is added to.
+    pub(crate) fn compress_into(
+        encoded: &[u8],
+        bytes: &mut Vec<u8>,
+        offsets: &mut CompressionOffsets,
+    ) {
+        let mut position = 0;
+        while let Some(&length) = encoded.get(position) {
+            if length == 0 {
+                break;
+            }
+            let ending = &encoded[position..];
+            if let Some(offset) = offsets.get(ending) {
+                bytes.push(COMPRESSION_SIGNIFIER | (offset >> 8) as u8);
+                bytes.push(*offset as u8);
+                return;
             }
+            if bytes.len() <= MAX_POINTER_OFFSET {
+                offsets.insert(ending.to_vec(), bytes.len() as u16);
+            }
+            let end = encoded.len().min(position + 1 + length as usize);
+            bytes.extend_from_slice(&encoded[position..end]);
+            position = end;
         }
+        bytes.push(0x0);
+    }
 
-        let bytes = parts.join(".");
-        return Ok(bytes.into_bytes());
+    /// The length of the uncompressed name in wire format at the start of the given data, or
+    /// `None` if the data does not start with one.
+    ///
+    /// # Argument
+    /// * `data`: The data starting with the name, e.g. the data of a record.
+    pub(crate) fn encoded_length(data: &[u8]) -> Option<usize> {
+        let mut position = 0;
+        loop {
+            let length = *data.get(position)?;
+            if length == 0 {
+                return Some(position + 1);
+            }
+            if length > MAX_LABEL_LENGTH {
+                return None;
+            }
+            position += 1 + length as usize;
+        }
     }
 
-    /// Read a compressed name byte sequence at the given cursor. This function assumes that the
-    /// cursor is at the position immediately following the length byte.
+    /// Walk the labels of a DNS record name at the given cursor, in order, following compression
+    /// pointers without recursing. The labels are borrowed from the message, so that nothing is
+    /// allocated here. The cursor ends up after the first pointer, or after the terminating null
+    /// byte when there is no pointer. Pointers which loop back on themselves, directly or
+    /// through other pointers, fail with `DnsError::CompressionLoop`, and names which exceed the
+    /// limits of RFC 1035 fail with `DnsError::LabelTooLong` or `DnsError::NameTooLong`.
     ///
     /// # Arguments
-    /// * `length`: The first byte of a name part. The function assumes that the first two bits have
-    ///             been zeroed even though it would have been set to 1 which signified that it is a
-    ///             compression pointer.
     /// * `cursor`: The byte buffer containing the full DNS message data.
-    fn read_and_advance_compressed_bytes(
-        length: u8,
+    /// * `on_label`: Called with each label, without its length byte.
+    fn read_labels_and_advance(
         cursor: &mut Cursor<&[u8]>,
-    ) -> Result<EncodedName, DnsError> {
-        let Ok(next_byte) = cursor.read_u8() else { return Err(DnsError::DecompressReadByte) };
-        let shifted_length = (length as u16) << 8;
-        let offset = (shifted_length | next_byte as u16) as u64;
-
-        let previous_position = cursor.position();
-        debug!("Saved previous position: {}", previous_position);
-        debug!("Seeking from beginning: {}", offset);
-        let Ok(_) = cursor.seek(SeekFrom::Start(offset)) else { return Err(DnsError::DecompressSkip); };
-        let result = RecordName::read_and_advance(cursor)?;
-        debug!("Restoring position of {}", previous_position);
-        let Ok(_) = cursor.seek(SeekFrom::Start(previous_position)) else { return Err(DnsError::DecompressRestore); };
-        Ok(result)
+        mut on_label: impl FnMut(&[u8]),
+    ) -> Result<(), DnsError> {
+        let message: &[u8] = cursor.get_ref();
+        let mut position = cursor.position() as usize;
+        // Where the cursor is left once a pointer has been followed.
+        let mut resume_at: Option<usize> = None;
+        let mut pointers_followed = 0;
+        // The length of the name on the wire so far, counting the terminating null byte.
+        let mut name_length = 1;
+
+        let result = loop {
+            let Some(&length) = message.get(position) else {
+                break Err(DnsError::ReadLength(None));
+            };
+            position += 1;
+
+            // If we encounter a null terminator, then we're done
+            if length == 0 {
+                break Ok(());
+            }
+
+            // Section 4.1.4 of RFC 1035 specifies a compression scheme used to reduce the data
+            // transmitted for verbose DNS messages. In this scheme, a "pointer" is indicated by
+            // setting the first two bits with 1s, and the remaining 14 bits hold the offset of
+            // the rest of the name from the start of the message. That offset must be of a
+            // prior occurrence of the name, so that a pointer may never point forward.
+            if length & COMPRESSION_SIGNIFIER == COMPRESSION_SIGNIFIER {
+                let Some(&next_byte) = message.get(position) else {
+                    break Err(DnsError::DecompressReadByte(None));
+                };
+                position += 1;
+                resume_at.get_or_insert(position);
+
+                pointers_followed += 1;
+                if pointers_followed > MAX_COMPRESSION_POINTERS {
+                    break Err(DnsError::CompressionLoop);
+                }
+
+                let offset =
+                    (((length & !COMPRESSION_SIGNIFIER) as usize) << 8) | next_byte as usize;
+                if offset >= message.len() {
+                    break Err(DnsError::DecompressSkip(None));
+                }
+                if offset >= position - 2 {
+                    break Err(DnsError::ForwardPointer);
+                }
+                position = offset;
+                continue;
+            }
+
+            // Otherwise, the byte is the length of the label which follows it. Lengths with only
+            // one of the upper two bits set are above the limit (RFC 6891 retired the extended
+            // label types they once introduced).
+            if length > MAX_LABEL_LENGTH {
+                break Err(DnsError::LabelTooLong);
+            }
+            name_length += 1 + length as usize;
+            if name_length > MAX_NAME_LENGTH {
+                break Err(DnsError::NameTooLong);
+            }
+            let Some(label) = message.get(position..position + length as usize) else {
+                position = message.len();
+                break Err(DnsError::ReadByte(None));
+            };
+            on_label(label);
+            position += label.len();
+        };
+
+        cursor.set_position(resume_at.unwrap_or(position) as u64);
+        result
     }
 }
 
@@ -273,9 +424,187 @@ fn test_encoding_record_name() -> Result<(), DnsError> {
     Ok(())
 }
 
+#[test]
+/// Validate encoding of the root name and of a name with a trailing dot
+fn test_encoding_root_and_absolute_record_name() -> Result<(), DnsError> {
+    assert_eq!(RecordName { name: "" }.encode()?, [0]);
+    assert_eq!(RecordName { name: "." }.encode()?, [0]);
+    assert_eq!(
+        RecordName {
+            name: "dns.project."
+        }
+        .encode()?,
+        [3, b'd', b'n', b's', 7, b'p', b'r', b'o', b'j', b'e', b'c', b't', 0]
+    );
+    Ok(())
+}
+
 #[test]
 /// Validate encoding of an invalid record name
 fn test_encoding_invalid_record_name() {
-    let invalid_name = RecordName { name: "👍" };
+    let invalid_name = RecordName { name: "\u{fffd}" };
     assert!(invalid_name.encode().is_err());
 }
+
+#[test]
+/// Validate that empty labels are rejected anywhere but as the trailing root
+fn test_encoding_record_name_with_empty_label() {
+    for name in ["a..b", ".a", "..", "a..", ".a."] {
+        assert_eq!(
+            RecordName { name }.encode(),
+            Err(DnsError::InvalidByteInName),
+            "{}",
+            name
+        );
+    }
+}
+
+#[cfg(feature = "idna")]
+#[test]
+/// Validate encoding of an internationalized record name into A-labels
+fn test_encoding_internationalized_record_name() -> Result<(), DnsError> {
+    let mut expected = vec![13];
+    expected.extend(b"xn--bcher-kva");
+    expected.extend([7]);
+    expected.extend(b"example");
+    expected.push(0);
+    assert_eq!(
+        RecordName {
+            name: "Bücher.example"
+        }
+        .encode()?,
+        expected
+    );
+    Ok(())
+}
+
+#[test]
+/// Validate reading several names into the same buffer, and reading a name back in wire format
+fn test_decode_names_into_buffer() -> Result<(), DnsError> {
+    let mut cursor = Cursor::new(RFC_1035_4_1_4_EXAMPLE.as_slice());
+    let mut names = Vec::new();
+    cursor.set_position(40);
+    RecordName::read_and_advance_into(&mut cursor, &mut names)?;
+    assert_eq!(cursor.position(), 46);
+    names.push(b' ');
+    cursor.set_position(64);
+    RecordName::read_and_advance_into(&mut cursor, &mut names)?;
+    assert_eq!(names, b"FOO.F.ISI.ARPA ARPA");
+
+    let mut encoded = vec![0xff];
+    cursor.set_position(40);
+    RecordName::decompress_and_advance_into(&mut cursor, &mut encoded)?;
+    assert_eq!(cursor.position(), 46);
+    assert_eq!(
+        encoded,
+        [0xff, 3, b'F', b'O', b'O', 1, b'F', 3, b'I', b'S', b'I', 4, b'A', b'R', b'P', b'A', 0]
+    );
+    Ok(())
+}
+
+#[test]
+/// Validate decoding of a compressed name whose pointer is out of bounds or cut short
+fn test_decode_compressed_name_with_invalid_pointer() {
+    let mut cursor = Cursor::new([3, b'F', b'O', b'O', 0b1100_0000, 200].as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::DecompressSkip(None))
+    );
+    let mut cursor = Cursor::new([0b1100_0000].as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::DecompressReadByte(None))
+    );
+}
+
+#[test]
+/// Validate that compression pointers which do not point to an earlier offset are rejected
+fn test_decode_compressed_name_with_forward_pointer() {
+    // A pointer to itself
+    let mut cursor = Cursor::new([0, 0, 0b1100_0000, 2].as_slice());
+    cursor.set_position(2);
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::ForwardPointer)
+    );
+
+    // A pointer to a name which follows it
+    let message = [0b1100_0000, 2, 1, b'A', 0];
+    let mut cursor = Cursor::new(message.as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::ForwardPointer)
+    );
+    assert_eq!(cursor.position(), 2);
+}
+
+#[test]
+/// Validate that compression pointers looping back on themselves fail instead of never ending
+fn test_decode_compressed_name_with_pointer_loop() {
+    // A label followed by a pointer back to the pointer hidden within the label, which points
+    // back to the label, so that two pointers are followed for each label
+    let message = [2, 0b1100_0000, 0, 0b1100_0000, 1];
+    let mut cursor = Cursor::new(message.as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::CompressionLoop)
+    );
+    let mut encoded = Vec::new();
+    cursor.set_position(0);
+    assert_eq!(
+        RecordName::decompress_and_advance_into(&mut cursor, &mut encoded),
+        Err(DnsError::CompressionLoop)
+    );
+    assert_eq!(cursor.position(), 5);
+
+    // A label followed by a pointer back to it, adding the label until the name is too long
+    let message = [1, b'A', 0b1100_0000, 0];
+    let mut cursor = Cursor::new(message.as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::NameTooLong)
+    );
+}
+
+#[test]
+/// Validate that labels longer than 63 octets and names longer than 255 octets are rejected
+fn test_name_length_limits() -> Result<(), DnsError> {
+    let label = "a".repeat(63);
+    let longest = [label.as_str(); 4].join(".");
+    let longest = &longest[..longest.len() - 2];
+    assert_eq!(RecordName { name: longest }.encode()?.len(), 255);
+    let name = format!("a.{}", longest);
+    assert_eq!(
+        RecordName { name: &name }.encode(),
+        Err(DnsError::NameTooLong)
+    );
+    let name = format!("a{}.com", label);
+    assert_eq!(
+        RecordName { name: &name }.encode(),
+        Err(DnsError::LabelTooLong)
+    );
+
+    let encoded = RecordName { name: longest }.encode()?;
+    let mut cursor = Cursor::new(encoded.as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor)?,
+        longest.as_bytes()
+    );
+
+    // The same name with one more octet in its last label, which still fits in 63 octets
+    let mut too_long = encoded.clone();
+    too_long[192] = 62;
+    too_long.insert(193, b'a');
+    let mut cursor = Cursor::new(too_long.as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::NameTooLong)
+    );
+
+    let mut cursor = Cursor::new([64, b'a'].as_slice());
+    assert_eq!(
+        RecordName::read_and_advance(&mut cursor),
+        Err(DnsError::LabelTooLong)
+    );
+    Ok(())
+}
@@ -0,0 +1,764 @@
+use crate::address_sort::{self, sort_destinations};
+use crate::cache::{Cache, CacheKey};
+use crate::errors::{DnsError, ErrorCategory};
+use crate::header::RCODE_NAME_ERROR;
+use crate::hosts::HostsFile;
+use crate::in_flight::InFlight;
+use crate::packet::Packet;
+use crate::query::{Query, QueryOptions};
+use crate::rdata;
+use crate::record::RecordType;
+use crate::resolver_config::ResolverConfig;
+use crate::socket::DnsTransport;
+use crate::stats::{ResolutionStats, StatsRecorder};
+use crate::trace::Trace;
+use crate::upstream_pool::UpstreamPool;
+use log::info;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The absolute name holding the PTR records of an address, as specified in RFC 1035, section
+/// 3.5 for IPv4 (`4.3.2.1.in-addr.arpa.`) and RFC 3596, section 2.5 for IPv6 (one label per
+/// nibble under `ip6.arpa.`).
+///
+/// # Argument
+/// * `address`: The address to look up.
+pub fn reverse_name(address: IpAddr) -> String {
+    let labels: Vec<String> = match address {
+        IpAddr::V4(address) => address
+            .octets()
+            .iter()
+            .rev()
+            .map(|octet| octet.to_string())
+            .chain(["in-addr".to_owned(), "arpa".to_owned()])
+            .collect(),
+        IpAddr::V6(address) => address
+            .octets()
+            .iter()
+            .rev()
+            .flat_map(|octet| [octet & 0xf, octet >> 4])
+            .map(|nibble| format!("{:x}", nibble))
+            .chain(["ip6".to_owned(), "arpa".to_owned()])
+            .collect(),
+    };
+    format!("{}.", labels.join("."))
+}
+
+/// Resolves names the way an application would expect: by consulting the hosts file and the cache
+/// (if any) and applying the configured search list before recursively resolving each candidate
+/// name with `Query`.
+pub struct Resolver {
+    /// Configuration of the resolver.
+    pub config: ResolverConfig,
+
+    /// Static mappings which are answered locally without touching the network.
+    pub hosts: Option<HostsFile>,
+
+    /// Cache of previous responses. It can be shared with other resolvers.
+    pub cache: Option<Arc<Cache>>,
+
+    /// Options applied to every query sent by the resolver.
+    pub query_options: QueryOptions,
+
+    /// Servers to send queries to directly, as addressed on the socket (e.g. `192.0.2.1:53`),
+    /// instead of resolving them from the root servers. They are tried in order until one of them
+    /// responds, the order being up to the pool if there is one. See `Query::ask()`.
+    pub servers: Vec<String>,
+
+    /// Health of the servers, to skip those which stopped responding and choose which one to ask
+    /// first. It can be shared with other resolvers asking the same servers.
+    pub pool: Option<Arc<UpstreamPool>>,
+
+    /// Queries on their way, so that identical queries made concurrently on a cache miss are sent
+    /// once. It can be shared with other resolvers.
+    pub in_flight: Option<Arc<InFlight>>,
+}
+
+impl Resolver {
+    /// Create a resolver with the given configuration.
+    ///
+    /// # Argument
+    /// * `config`: The configuration to resolve names with.
+    pub fn new(config: ResolverConfig) -> Resolver {
+        Resolver {
+            config,
+            hosts: None,
+            cache: None,
+            query_options: QueryOptions::default(),
+            servers: vec![],
+            pool: None,
+            in_flight: None,
+        }
+    }
+
+    /// Create a stub resolver, which sends queries with the RD bit set to the name servers of the
+    /// configuration and leaves the resolution to them, rather than walking the delegations from
+    /// the root servers itself. Without name servers, names are still resolved from the roots.
+    ///
+    /// # Arguments
+    /// * `config`: The configuration to resolve names with.
+    /// * `port`: The port the name servers listen on, normally `DEFAULT_UDP_PORT`.
+    pub fn stub(config: ResolverConfig, port: u16) -> Resolver {
+        let servers = config
+            .name_servers
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, port).to_string())
+            .collect();
+        let mut resolver = Resolver::new(config);
+        resolver.servers = servers;
+        resolver.query_options.recursion_desired = true;
+        resolver
+    }
+
+    /// Resolve the given name and record type. A name found in the hosts file is answered with a
+    /// synthetic packet. Otherwise, each candidate name produced by the search list is tried in
+    /// order until one of them is known to the DNS. A name unknown to the servers asked is an
+    /// `UnknownDomainName` error, unless they were asked directly, as with `stub()`: their NXDOMAIN
+    /// response is returned then.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to resolve, as given by the user.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn resolve(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        self.resolve_traced(socket, domain_name, record_type, rand_seed, None)
+    }
+
+    /// Like `resolve()`, but the exchanges made with servers are added to the given trace rather
+    /// than to the one of the query options. This lets resolvers shared between threads trace
+    /// each resolution on its own.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to resolve, as given by the user.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `trace`: Where to add the exchanges, or `None` for the trace of the query options.
+    pub fn resolve_traced(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+        trace: Option<&Trace>,
+    ) -> Result<Packet, DnsError> {
+        let options = match trace {
+            Some(trace) => QueryOptions {
+                trace: Some(trace.clone()),
+                ..self.query_options.clone()
+            },
+            None => self.query_options.clone(),
+        };
+        self.resolve_with_options(socket, domain_name, record_type, rand_seed, &options)
+    }
+
+    /// Like `resolve()`, but statistics about the resolution are returned along with its
+    /// outcome: the queries sent, their sizes, how long each server took to respond and how long
+    /// the resolution took.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to resolve, as given by the user.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn resolve_with_stats(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+    ) -> (Result<Packet, DnsError>, ResolutionStats) {
+        let recorder = StatsRecorder::default();
+        let options = QueryOptions {
+            stats: Some(recorder.clone()),
+            ..self.query_options.clone()
+        };
+        let started_at = Instant::now();
+        let result =
+            self.resolve_with_options(socket, domain_name, record_type, rand_seed, &options);
+        let mut stats = recorder.take();
+        stats.elapsed = started_at.elapsed();
+        (result, stats)
+    }
+
+    /// Resolve the given name and record type with the given query options rather than those of
+    /// the resolver. See `resolve()`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to resolve, as given by the user.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `options`: The options to send the queries with.
+    fn resolve_with_options(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+        options: &QueryOptions,
+    ) -> Result<Packet, DnsError> {
+        if let Some(packet) = self
+            .hosts
+            .as_ref()
+            .and_then(|hosts| hosts.answer(domain_name, record_type))
+        {
+            info!("Answering {} from the hosts file", domain_name);
+            return Ok(packet);
+        }
+
+        // Servers asked directly tell of a name that does not exist with a response, which is
+        // returned as is when no candidate exists, so that its RCODE can be shown.
+        let mut unknown = Err(DnsError::UnknownDomainName);
+        for candidate in self.config.search_candidates(domain_name) {
+            match self.resolve_candidate(socket, &candidate, record_type, rand_seed, options) {
+                // Only a name that does not exist moves on to the next candidate. Any other
+                // failure would most likely repeat itself for the remaining candidates as well.
+                Ok(packet) if packet.rcode() == RCODE_NAME_ERROR => {
+                    info!("{} is unknown, trying the next search candidate", candidate);
+                    unknown = Ok(packet);
+                }
+                Ok(packet) => return Ok(packet),
+                Err(DnsError::UnknownDomainName) => {
+                    info!("{} is unknown, trying the next search candidate", candidate);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        unknown
+    }
+
+    /// Look up the host names of an address with a PTR query for its reverse name, see
+    /// `reverse_name()`. The names are returned in presentation format, e.g. `host.example.com.`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `address`: The address to look up.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn reverse(
+        &self,
+        socket: &mut dyn DnsTransport,
+        address: IpAddr,
+        rand_seed: Option<usize>,
+    ) -> Result<Vec<String>, DnsError> {
+        let packet = self.resolve(socket, &reverse_name(address), RecordType::PTR, rand_seed)?;
+        Ok(packet
+            .answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::PTR)
+            .map(|record| record.data_to_presentation())
+            .collect())
+    }
+
+    /// Look up the IPv6 and IPv4 addresses of a name, sorted in the order they should be tried as
+    /// specified in RFC 6724, see `sort_destinations()`. The addresses are those of the AAAA and
+    /// A records of the answers, including those at the end of CNAME chains. A failure to resolve
+    /// either type is only returned when the other yields no address either.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to look up, as given by the user.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn lookup_ip(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        rand_seed: Option<usize>,
+    ) -> Result<Vec<IpAddr>, DnsError> {
+        self.lookup_ip_from(socket, domain_name, rand_seed, address_sort::source_address)
+    }
+
+    /// Like `lookup_ip()`, with the source addresses to reach each address given rather than
+    /// asked of the system.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to look up, as given by the user.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `source_address`: The source address to reach an address with, if reachable.
+    fn lookup_ip_from(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        rand_seed: Option<usize>,
+        source_address: impl Fn(IpAddr) -> Option<IpAddr>,
+    ) -> Result<Vec<IpAddr>, DnsError> {
+        let mut addresses = Vec::new();
+        let mut failure = None;
+        for record_type in [RecordType::AAAA, RecordType::A] {
+            match self.resolve(socket, domain_name, record_type, rand_seed) {
+                Ok(packet) => addresses.extend(
+                    packet
+                        .answers
+                        .iter()
+                        .filter(|record| record.r_type == record_type)
+                        .filter_map(|record| record.to_ip_addr()),
+                ),
+                Err(error) => failure = failure.or(Some(error)),
+            }
+        }
+        if let (true, Some(error)) = (addresses.is_empty(), failure) {
+            return Err(error);
+        }
+        sort_destinations(&mut addresses, source_address);
+        Ok(addresses)
+    }
+
+    /// Look up the TXT records of a name. Each record is returned as the concatenation of its
+    /// character strings, as SPF and DKIM records are meant to be read, e.g. `v=spf1 -all`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The name to look up, as given by the user.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn lookup_txt(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        rand_seed: Option<usize>,
+    ) -> Result<Vec<String>, DnsError> {
+        let packet = self.resolve(socket, domain_name, RecordType::TXT, rand_seed)?;
+        packet
+            .answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::TXT)
+            .map(|record| {
+                let strings = rdata::character_strings(&record.data)?;
+                Ok(String::from_utf8_lossy(&strings.concat()).into_owned())
+            })
+            .collect()
+    }
+
+    /// Refresh the cache entries which were queued for prefetching because they were accessed
+    /// close to their expiry. This is meant to be called off the request path (e.g. by a server
+    /// between requests or on a background thread) so that hot names never incur a cold lookup.
+    /// Returns the number of refreshed entries.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn prefetch(&self, socket: &mut dyn DnsTransport, rand_seed: Option<usize>) -> usize {
+        let Some(cache) = &self.cache else {
+            return 0;
+        };
+
+        let mut refreshed = 0;
+        for key in cache.take_prefetch_queue() {
+            let query = Query {
+                domain_name: key.domain_name.as_str().into(),
+                record_type: key.record_type,
+                options: self.query_options.clone(),
+            };
+
+            match self.send(socket, &query, rand_seed) {
+                Ok(packet) => {
+                    info!("Prefetched {} {}", key.record_type, key.domain_name);
+                    cache.insert(key, &packet, Instant::now());
+                    refreshed += 1;
+                }
+                Err(error) => info!("Could not prefetch {}: {}", key.domain_name, error),
+            }
+        }
+        refreshed
+    }
+
+    /// Ask the configured servers the query, or else resolve it from the root servers. A server
+    /// which does not respond moves on to the next one, but one which knows the name does not
+    /// exist settles the query, as does a query no server could be asked, see
+    /// `ErrorCategory::Input`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `query`: The query to send.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    fn send(
+        &self,
+        socket: &mut dyn DnsTransport,
+        query: &Query,
+        rand_seed: Option<usize>,
+    ) -> Result<Packet, DnsError> {
+        if self.servers.is_empty() {
+            return query.resolve(socket, rand_seed);
+        }
+
+        let servers = match &self.pool {
+            Some(pool) => pool.order(&self.servers),
+            None => self.servers.iter().collect(),
+        };
+        let mut result = Err(DnsError::SocketConnect(None));
+        for server in servers {
+            let sent_at = Instant::now();
+            result = query.ask(socket, server, rand_seed);
+            match (&result, &self.pool) {
+                (Ok(_), Some(pool)) => pool.record_response(server, sent_at.elapsed()),
+                (Err(error), Some(pool)) if error.category() != ErrorCategory::Input => {
+                    pool.record_failure(server)
+                }
+                _ => (),
+            }
+            match &result {
+                Ok(_) => return result,
+                Err(error) if error.category() == ErrorCategory::Input => return result,
+                Err(error) => info!("No response from {}: {}", server, error),
+            }
+        }
+        result
+    }
+
+    /// Resolve a single candidate name, consulting the cache first. When no server responds, an
+    /// expired answer may be served stale, see `CacheConfig::serve_stale`.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to perform the DNS queries.
+    /// * `domain_name`: The fully-qualified name to resolve.
+    /// * `record_type`: The record type to resolve.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    /// * `options`: The options to send the query with.
+    fn resolve_candidate(
+        &self,
+        socket: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        rand_seed: Option<usize>,
+        options: &QueryOptions,
+    ) -> Result<Packet, DnsError> {
+        let key = CacheKey::new(domain_name, record_type);
+        let cached = self
+            .cache
+            .as_ref()
+            .map(|cache| cache.get(&key, Instant::now()));
+        if let (Some(cached), Some(stats)) = (&cached, &options.stats) {
+            stats.record_cache_lookup(cached.is_some());
+        }
+        if let Some(Some(packet)) = cached {
+            info!("Answering {} from the cache", domain_name);
+            return Ok(packet);
+        }
+
+        let query = Query {
+            domain_name: domain_name.into(),
+            record_type,
+            options: options.clone(),
+        };
+        let result = match &self.in_flight {
+            Some(in_flight) => in_flight.run(&key, || self.send(socket, &query, rand_seed)),
+            None => self.send(socket, &query, rand_seed),
+        };
+
+        let Some(cache) = &self.cache else {
+            return result;
+        };
+        match result {
+            Ok(packet) => {
+                cache.insert(key, &packet, Instant::now());
+                Ok(packet)
+            }
+            result => cache.or_stale(&key, result, Instant::now()),
+        }
+    }
+}
+
+/// Validate that the search list is applied to short names.
+#[test]
+fn test_resolving_with_search_domain() -> Result<(), DnsError> {
+    use crate::mock_data;
+    use crate::record::DnsRecordGetters;
+    use crate::socket::MockSocket;
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let resolver = Resolver::new(ResolverConfig {
+        search_domains: vec!["com".to_owned()],
+        ..Default::default()
+    });
+
+    let packet = resolver.resolve(&mut socket, "twitter", RecordType::A, Some(0))?;
+    let a_record = packet.answers.get_first_a_record().unwrap();
+    assert_eq!(a_record.ip_address(), "104.244.42.193");
+    Ok(())
+}
+
+/// Validate that a search candidate unknown to the DNS falls through to the next candidate.
+#[test]
+fn test_resolving_falls_through_unknown_search_candidate() -> Result<(), DnsError> {
+    use crate::mock_data;
+    use crate::record::DnsRecordGetters;
+    use crate::socket::{MockData, MockKey, MockSocket};
+
+    // The root server knows nothing about "twitter.com.example" and returns an empty response.
+    #[rustfmt::skip]
+    static QUERY: [u8; 37] = [
+        59, 108, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
+        7, b't', b'w', b'i', b't', b't', b'e', b'r', 3, b'c', b'o', b'm',
+        7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 0,
+        0, 1, 0, 1,
+    ];
+    let mut response = QUERY.to_vec();
+    response[2..4].copy_from_slice(&[129, 131]);
+    response.resize(1024, 0);
+
+    let mut data = mock_data::captured_data_for_twitter();
+    data.push((
+        MockKey {
+            query_bytes: QUERY.to_vec(),
+            server_ip: "192.58.128.30:53".to_owned(),
+        },
+        MockData { data: response },
+    ));
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(data);
+
+    let resolver = Resolver::new(ResolverConfig {
+        search_domains: vec!["example".to_owned()],
+        ndots: 2,
+        ..Default::default()
+    });
+
+    let packet = resolver.resolve(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    let a_record = packet.answers.get_first_a_record().unwrap();
+    assert_eq!(a_record.ip_address(), "104.244.42.193");
+    Ok(())
+}
+
+/// Validate that names in the hosts file are answered without touching the network.
+#[test]
+fn test_resolving_from_hosts_file() -> Result<(), DnsError> {
+    use crate::socket::MockSocket;
+
+    // The mock socket has no preconfigured responses, so any network access would fail.
+    let mut socket = MockSocket::bind("")?;
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse("104.244.42.1 twitter.com\n"));
+
+    let packet = resolver.resolve(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    assert_eq!(packet.answers[0].ip_address(), "104.244.42.1");
+
+    // A record type the hosts file cannot answer falls through to the network.
+    assert!(resolver
+        .resolve(&mut socket, "twitter.com", RecordType::NS, Some(0))
+        .is_err());
+    Ok(())
+}
+
+/// Validate that the addresses of a name are sorted by reachability and precedence.
+#[test]
+fn test_looking_up_addresses() -> Result<(), DnsError> {
+    use crate::socket::MockSocket;
+
+    let mut socket = MockSocket::bind("")?;
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse(
+        "192.0.2.1 example.com\n2001:db8::1 example.com\n",
+    ));
+
+    let dual_stack = |destination: IpAddr| match destination {
+        IpAddr::V4(_) => "192.0.2.2".parse().ok(),
+        IpAddr::V6(_) => "2001:db8::2".parse().ok(),
+    };
+    let addresses = resolver.lookup_ip_from(&mut socket, "example.com", Some(0), dual_stack)?;
+    assert_eq!(
+        addresses,
+        [
+            "2001:db8::1".parse::<IpAddr>().unwrap(),
+            "192.0.2.1".parse().unwrap()
+        ]
+    );
+
+    let ipv4_only = |destination: IpAddr| match destination {
+        IpAddr::V4(_) => "192.0.2.2".parse().ok(),
+        IpAddr::V6(_) => None,
+    };
+    let addresses = resolver.lookup_ip_from(&mut socket, "example.com", Some(0), ipv4_only)?;
+    assert_eq!(
+        addresses,
+        [
+            "192.0.2.1".parse::<IpAddr>().unwrap(),
+            "2001:db8::1".parse().unwrap()
+        ]
+    );
+
+    // Neither type can be resolved without the network.
+    assert!(resolver
+        .lookup_ip(&mut socket, "example.org", Some(0))
+        .is_err());
+    Ok(())
+}
+
+/// Validate that near-expiry cache entries are served from the cache and refreshed by prefetch().
+#[test]
+fn test_resolving_with_cache_prefetch() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+    use crate::mock_data;
+    use crate::socket::MockSocket;
+    use std::time::Duration;
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.cache = Some(Arc::new(Cache::new(CacheConfig {
+        prefetch_percent: Some(10),
+        ..Default::default()
+    })));
+    let cache = resolver.cache.clone().unwrap();
+
+    // Populate the cache as if twitter.com had been resolved 1750 of its 1800 seconds ago.
+    let packet = resolver.resolve(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    let key = CacheKey::new("twitter.com", RecordType::A);
+    let Some(stored_at) = Instant::now().checked_sub(Duration::from_secs(1750)) else {
+        return Ok(());
+    };
+    cache.insert(key.clone(), &packet, stored_at);
+
+    let cached = resolver.resolve(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    assert!(cached.answers[0].ttl.as_secs() <= 50);
+
+    assert_eq!(resolver.prefetch(&mut socket, Some(0)), 1);
+    let refreshed = cache.get(&key, Instant::now()).unwrap();
+    assert!(refreshed.answers[0].ttl.as_secs() > 1700);
+    Ok(())
+}
+
+/// Validate that statistics are collected about each resolution, including cache lookups.
+#[test]
+fn test_resolving_with_stats() -> Result<(), DnsError> {
+    use crate::cache::CacheConfig;
+    use crate::mock_data;
+    use crate::socket::MockSocket;
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+
+    let (result, stats) =
+        resolver.resolve_with_stats(&mut socket, "twitter.com", RecordType::A, Some(0));
+    assert!(result.is_ok());
+    assert!(stats.queries_sent > 1);
+    assert!(stats.bytes_sent > 0 && stats.bytes_received > 0);
+    assert_eq!((stats.cache_hits, stats.cache_misses), (0, 1));
+    for server in &stats.servers {
+        assert_eq!(server.round_trips.len(), server.queries);
+        assert_eq!(server.timeouts, 0);
+    }
+    let queries: usize = stats.servers.iter().map(|server| server.queries).sum();
+    assert_eq!(queries, stats.queries_sent);
+
+    let (result, stats) =
+        resolver.resolve_with_stats(&mut socket, "twitter.com", RecordType::A, Some(0));
+    assert!(result.is_ok());
+    assert_eq!(stats.queries_sent, 0);
+    assert_eq!((stats.cache_hits, stats.cache_misses), (1, 0));
+    assert!(stats.servers.is_empty());
+    Ok(())
+}
+
+/// Validate that queries are sent once to the configured server, with the RD bit set.
+#[test]
+fn test_resolving_with_server() -> Result<(), DnsError> {
+    use crate::header::FLAG_RECURSION_DESIRED;
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let exchanges = MockResolution::new("twitter.com", RecordType::A, 0)
+        .recursion_desired()
+        .at("192.0.2.53")
+        .recursive_answer("twitter.com. 1800 IN A 104.244.42.193")
+        .build()?;
+    let query_bytes = exchanges[0].0.query_bytes.clone();
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.query_options.recursion_desired = true;
+    resolver.servers = vec!["192.0.2.53:53".to_owned()];
+    let packet = resolver.resolve(&mut socket, "twitter.com", RecordType::A, Some(0))?;
+    assert_eq!(packet.answers[0].ip_address(), "104.244.42.193");
+    assert!(query_bytes[2] & (FLAG_RECURSION_DESIRED >> 8) as u8 != 0);
+    Ok(())
+}
+
+/// Validate that a stub resolver moves on to the next name server when one does not respond, and
+/// returns the NXDOMAIN response of the one which does.
+#[test]
+fn test_resolving_as_stub() -> Result<(), DnsError> {
+    use crate::forwarder::DEFAULT_UDP_PORT;
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let resolver = Resolver::stub(
+        ResolverConfig {
+            name_servers: vec![
+                "192.0.2.1".parse().unwrap(),
+                "2001:db8::53".parse().unwrap(),
+            ],
+            ..Default::default()
+        },
+        DEFAULT_UDP_PORT,
+    );
+    assert_eq!(resolver.servers, ["192.0.2.1:53", "[2001:db8::53]:53"]);
+    assert!(resolver.query_options.recursion_desired);
+
+    let exchanges = MockResolution::new("missing.example", RecordType::A, 0)
+        .recursion_desired()
+        .at("2001:db8::53")
+        .name_error()
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let packet = resolver.resolve(&mut socket, "missing.example", RecordType::A, Some(0))?;
+    assert_eq!(packet.rcode(), RCODE_NAME_ERROR);
+    Ok(())
+}
+
+/// Validate building the reverse names of IPv4 and IPv6 addresses.
+#[test]
+fn test_reverse_name() {
+    assert_eq!(
+        reverse_name("192.0.2.1".parse().unwrap()),
+        "1.2.0.192.in-addr.arpa."
+    );
+    assert_eq!(
+        reverse_name("2001:db8::567:89ab".parse().unwrap()),
+        "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+    );
+}
+
+/// Validate looking up the host names of an address.
+#[test]
+fn test_resolving_reverse() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.query_options.recursion_desired = true;
+    resolver.servers = vec!["192.0.2.53:53".to_owned()];
+
+    let exchanges = MockResolution::new("1.2.0.192.in-addr.arpa", RecordType::PTR, 0)
+        .recursion_desired()
+        .at("192.0.2.53")
+        .recursive_answer("1.2.0.192.in-addr.arpa. 3600 IN PTR host.example.com.")
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    assert_eq!(
+        resolver.reverse(&mut socket, "192.0.2.1".parse().unwrap(), Some(0))?,
+        ["host.example.com."]
+    );
+    Ok(())
+}
@@ -0,0 +1,427 @@
+use crate::errors::DnsError;
+use std::net::IpAddr;
+
+/// The stub resolver configuration of the host: which recursive name servers to use and which
+/// domain suffixes to search.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResolverConfig {
+    /// Recursive name servers in order of preference.
+    pub name_servers: Vec<IpAddr>,
+
+    /// Domain suffixes to search, in order.
+    pub search_domains: Vec<String>,
+
+    /// The number of dots a name must contain before it is tried as-is ahead of the search list.
+    pub ndots: u8,
+}
+
+impl Default for ResolverConfig {
+    fn default() -> Self {
+        ResolverConfig {
+            name_servers: vec![],
+            search_domains: vec![],
+            ndots: DEFAULT_NDOTS,
+        }
+    }
+}
+
+/// The default value of `ndots` used by the libc resolver.
+const DEFAULT_NDOTS: u8 = 1;
+
+/// The libc resolver silently caps `ndots` at this value.
+const MAX_NDOTS: u8 = 15;
+
+/// Location of the resolver configuration file on Unix-like systems.
+#[cfg(not(windows))]
+const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Registry key under which Windows keeps its global TCP/IP settings, relative to
+/// `HKEY_LOCAL_MACHINE`.
+#[cfg(windows)]
+const WINDOWS_TCPIP_PARAMETERS: &str = r"SYSTEM\CurrentControlSet\Services\Tcpip\Parameters";
+
+/// The DNS settings of a network adapter, as reported by Windows.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AdapterDns {
+    /// Whether the adapter is operational, i.e. its `OperStatus` is `IfOperStatusUp`.
+    pub is_up: bool,
+
+    /// The name servers configured on the adapter, statically or by DHCP.
+    pub name_servers: Vec<IpAddr>,
+
+    /// The connection-specific DNS suffix of the adapter.
+    pub dns_suffix: String,
+}
+
+impl ResolverConfig {
+    /// Discover the resolver configuration of the running system. On Unix-like systems this reads
+    /// `/etc/resolv.conf`. On Windows, the network adapters are asked for their DNS settings.
+    pub fn from_system() -> Result<ResolverConfig, DnsError> {
+        #[cfg(windows)]
+        return Self::from_windows_adapters();
+
+        #[cfg(not(windows))]
+        return Self::from_resolv_conf(RESOLV_CONF_PATH);
+    }
+
+    /// Read the resolver configuration from a resolv.conf-formatted file.
+    ///
+    /// # Argument
+    /// * `path`: Path to the file to read.
+    pub fn from_resolv_conf(path: &str) -> Result<ResolverConfig, DnsError> {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Err(DnsError::SystemConfig);
+        };
+        Ok(Self::parse_resolv_conf(&contents))
+    }
+
+    /// Parse the contents of a resolv.conf file. Unknown or malformed lines are ignored, just as
+    /// the libc resolver does.
+    ///
+    /// # Argument
+    /// * `contents`: The contents of a resolv.conf file.
+    pub fn parse_resolv_conf(contents: &str) -> ResolverConfig {
+        let mut config = ResolverConfig::default();
+
+        for line in contents.lines() {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("nameserver") => {
+                    if let Some(Ok(address)) = tokens.next().map(|token| token.parse::<IpAddr>()) {
+                        config.name_servers.push(address);
+                    }
+                }
+                // "domain" and "search" are mutually exclusive. Whichever appears last wins.
+                Some("domain") | Some("search") => {
+                    config.search_domains = tokens.map(normalize_domain).collect();
+                }
+                Some("options") => {
+                    for option in tokens {
+                        let Some(value) = option.strip_prefix("ndots:") else {
+                            continue;
+                        };
+                        if let Ok(ndots) = value.parse::<u8>() {
+                            config.ndots = ndots.min(MAX_NDOTS);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        config
+    }
+
+    /// Read the resolver configuration of Windows from its network adapters, and the global
+    /// search list from the registry.
+    #[cfg(windows)]
+    fn from_windows_adapters() -> Result<ResolverConfig, DnsError> {
+        let adapters = windows_api::adapters()?;
+        Ok(Self::from_adapters(&adapters, &windows_api::search_list()))
+    }
+
+    /// Build the configuration of Windows from its network adapters. Only the adapters which are
+    /// up contribute, so that a disconnected or stale adapter does not leave behind the servers
+    /// of its last lease. A non-empty global search list takes precedence over the DNS suffixes
+    /// of the adapters.
+    ///
+    /// # Arguments
+    /// * `adapters`: The network adapters, in the order of preference of the system.
+    /// * `search_list`: The global search list.
+    pub fn from_adapters(adapters: &[AdapterDns], search_list: &[String]) -> ResolverConfig {
+        let mut config = ResolverConfig::default();
+        let mut domains: Vec<String> = Vec::new();
+
+        for adapter in adapters.iter().filter(|adapter| adapter.is_up) {
+            for address in &adapter.name_servers {
+                if !is_windows_placeholder_server(address) && !config.name_servers.contains(address)
+                {
+                    config.name_servers.push(*address);
+                }
+            }
+
+            if !adapter.dns_suffix.is_empty() {
+                domains.push(normalize_domain(&adapter.dns_suffix));
+            }
+        }
+
+        let mut search_domains = if search_list.is_empty() {
+            domains
+        } else {
+            search_list
+                .iter()
+                .map(|domain| normalize_domain(domain))
+                .collect()
+        };
+        let mut seen = Vec::new();
+        search_domains.retain(|domain| {
+            let is_new = !seen.contains(domain);
+            seen.push(domain.clone());
+            is_new
+        });
+        config.search_domains = search_domains;
+
+        config
+    }
+
+    /// The names to try, in order, when resolving the given name. This mirrors the libc resolver:
+    /// a name ending in a dot is absolute and is never extended. A name with at least `ndots` dots
+    /// is tried as-is before the search list. Any other name is tried with each search domain
+    /// appended first and as-is last.
+    ///
+    /// # Argument
+    /// * `name`: The name as given by the user.
+    pub fn search_candidates(&self, name: &str) -> Vec<String> {
+        if let Some(absolute_name) = name.strip_suffix('.') {
+            return vec![absolute_name.to_owned()];
+        }
+
+        let searched = self
+            .search_domains
+            .iter()
+            .map(|domain| format!("{}.{}", name, domain));
+
+        let mut candidates = Vec::with_capacity(self.search_domains.len() + 1);
+        if name.matches('.').count() >= self.ndots as usize {
+            candidates.push(name.to_owned());
+            candidates.extend(searched);
+        } else {
+            candidates.extend(searched);
+            candidates.push(name.to_owned());
+        }
+        candidates
+    }
+}
+
+/// Whether the address is one of the site-local placeholders `fec0:0:0:ffff::1` to `::3` which
+/// Windows reports as the IPv6 name servers of an adapter that was not given any.
+fn is_windows_placeholder_server(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V6(address) => {
+            let segments = address.segments();
+            segments[..7] == [0xfec0, 0, 0, 0xffff, 0, 0, 0] && (1..=3).contains(&segments[7])
+        }
+        IpAddr::V4(_) => false,
+    }
+}
+
+/// Strip the trailing dot of a fully-qualified domain so it can be appended to a relative name.
+fn normalize_domain(domain: &str) -> String {
+    domain.trim_end_matches('.').to_owned()
+}
+
+/// Reads the resolver configuration of Windows through the safe wrappers of `ipconfig` and
+/// `windows-registry`.
+#[cfg(windows)]
+mod windows_api {
+    use super::{AdapterDns, WINDOWS_TCPIP_PARAMETERS};
+    use crate::errors::DnsError;
+    use ipconfig::OperStatus;
+    use windows_registry::LOCAL_MACHINE;
+
+    /// List the network adapters of the host with their DNS settings.
+    pub fn adapters() -> Result<Vec<AdapterDns>, DnsError> {
+        let Ok(adapters) = ipconfig::get_adapters() else {
+            return Err(DnsError::SystemConfig);
+        };
+        Ok(adapters
+            .iter()
+            .map(|adapter| AdapterDns {
+                is_up: adapter.oper_status() == OperStatus::IfOperStatusUp,
+                name_servers: adapter.dns_servers().to_vec(),
+                dns_suffix: dns_suffix(adapter.adapter_name()),
+            })
+            .collect())
+    }
+
+    /// Read the global search list, the `SearchList` value of the TCP/IP parameters. A missing
+    /// value is an empty list.
+    pub fn search_list() -> Vec<String> {
+        let Ok(search_list) = read_string(WINDOWS_TCPIP_PARAMETERS, "SearchList") else {
+            return Vec::new();
+        };
+        search_list
+            .split([',', ' '])
+            .filter(|domain| !domain.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// The connection-specific DNS suffix of an adapter: the `Domain` value of its interface
+    /// parameters when set statically, or else the `DhcpDomain` value of its lease.
+    ///
+    /// # Argument
+    /// * `adapter_name`: The name of the adapter, the GUID its interface parameters are kept
+    ///   under.
+    fn dns_suffix(adapter_name: &str) -> String {
+        let interface = format!(r"{}\Interfaces\{}", WINDOWS_TCPIP_PARAMETERS, adapter_name);
+        ["Domain", "DhcpDomain"]
+            .into_iter()
+            .filter_map(|value| read_string(&interface, value).ok())
+            .find(|suffix| !suffix.is_empty())
+            .unwrap_or_default()
+    }
+
+    /// Read a string value of a key under `HKEY_LOCAL_MACHINE`.
+    ///
+    /// # Arguments
+    /// * `key`: The path of the key.
+    /// * `value`: The name of the value.
+    fn read_string(key: &str, value: &str) -> windows_registry::Result<String> {
+        LOCAL_MACHINE.open(key)?.get_string(value)
+    }
+}
+
+/// Validate parsing of a typical resolv.conf.
+#[test]
+fn test_parsing_resolv_conf() {
+    let contents = "
+# Generated by NetworkManager
+search corp.example.com example.com.
+nameserver 192.0.2.53
+nameserver 2001:db8::53
+nameserver not-an-address
+options edns0
+";
+
+    assert_eq!(
+        ResolverConfig::parse_resolv_conf(contents),
+        ResolverConfig {
+            name_servers: vec![
+                "192.0.2.53".parse().unwrap(),
+                "2001:db8::53".parse().unwrap()
+            ],
+            search_domains: vec!["corp.example.com".to_owned(), "example.com".to_owned()],
+            ndots: 1,
+        }
+    );
+}
+
+/// Validate parsing of the ndots option, including the libc cap.
+#[test]
+fn test_parsing_resolv_conf_ndots() {
+    let config = ResolverConfig::parse_resolv_conf("options rotate ndots:2 timeout:1\n");
+    assert_eq!(config.ndots, 2);
+
+    let config = ResolverConfig::parse_resolv_conf("options ndots:30\n");
+    assert_eq!(config.ndots, 15);
+}
+
+/// Validate that the last of "domain" and "search" in a resolv.conf wins.
+#[test]
+fn test_parsing_resolv_conf_domain_after_search() {
+    let contents = "search a.example b.example\ndomain c.example\n";
+    assert_eq!(
+        ResolverConfig::parse_resolv_conf(contents).search_domains,
+        vec!["c.example".to_owned()]
+    );
+}
+
+/// Validate the configuration built from Windows adapters: adapters which are down are skipped,
+/// as are the placeholder IPv6 servers, and the adapter suffixes are searched when there is no
+/// global search list.
+#[test]
+fn test_config_from_windows_adapters() {
+    let adapters = vec![
+        AdapterDns {
+            is_up: true,
+            name_servers: vec![
+                "192.168.1.1".parse().unwrap(),
+                "fec0:0:0:ffff::1".parse().unwrap(),
+                "192.168.1.1".parse().unwrap(),
+            ],
+            dns_suffix: "home.example".to_owned(),
+        },
+        AdapterDns {
+            is_up: false,
+            name_servers: vec!["10.8.0.1".parse().unwrap()],
+            dns_suffix: "vpn.example".to_owned(),
+        },
+        AdapterDns {
+            is_up: true,
+            name_servers: vec![
+                "10.0.0.53".parse().unwrap(),
+                "2001:db8::53".parse().unwrap(),
+            ],
+            dns_suffix: "corp.example.".to_owned(),
+        },
+        AdapterDns {
+            is_up: true,
+            name_servers: vec![],
+            dns_suffix: String::new(),
+        },
+    ];
+
+    assert_eq!(
+        ResolverConfig::from_adapters(&adapters, &[]),
+        ResolverConfig {
+            name_servers: vec![
+                "192.168.1.1".parse().unwrap(),
+                "10.0.0.53".parse().unwrap(),
+                "2001:db8::53".parse().unwrap(),
+            ],
+            search_domains: vec!["home.example".to_owned(), "corp.example".to_owned()],
+            ndots: 1,
+        }
+    );
+}
+
+/// Validate that a global search list on Windows overrides the adapter suffixes.
+#[test]
+fn test_config_from_windows_adapters_search_list() {
+    let adapters = vec![AdapterDns {
+        is_up: true,
+        name_servers: vec!["192.168.1.1".parse().unwrap()],
+        dns_suffix: "home.example".to_owned(),
+    }];
+    let search_list = vec!["a.example".to_owned(), "b.example.".to_owned()];
+
+    assert_eq!(
+        ResolverConfig::from_adapters(&adapters, &search_list).search_domains,
+        vec!["a.example".to_owned(), "b.example".to_owned()]
+    );
+}
+
+/// Validate that short names are tried with the search list before being tried as-is.
+#[test]
+fn test_search_candidates_below_ndots() {
+    let config = ResolverConfig {
+        search_domains: vec!["corp.example".to_owned(), "example".to_owned()],
+        ..Default::default()
+    };
+
+    assert_eq!(
+        config.search_candidates("intranet"),
+        vec!["intranet.corp.example", "intranet.example", "intranet"]
+    );
+}
+
+/// Validate that names with enough dots are tried as-is before the search list.
+#[test]
+fn test_search_candidates_at_ndots() {
+    let config = ResolverConfig {
+        search_domains: vec!["corp.example".to_owned()],
+        ndots: 2,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        config.search_candidates("www.example.com"),
+        vec!["www.example.com", "www.example.com.corp.example"]
+    );
+    assert_eq!(
+        config.search_candidates("example.com"),
+        vec!["example.com.corp.example", "example.com"]
+    );
+}
+
+/// Validate that absolute names are never extended with the search list.
+#[test]
+fn test_search_candidates_absolute_name() {
+    let config = ResolverConfig {
+        search_domains: vec!["corp.example".to_owned()],
+        ..Default::default()
+    };
+
+    assert_eq!(config.search_candidates("intranet."), vec!["intranet"]);
+}
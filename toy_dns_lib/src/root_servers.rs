@@ -1,4 +1,4 @@
-use phf::phf_ordered_map;
+use phf::{phf_map, phf_ordered_map};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 
@@ -20,6 +20,29 @@ const ROOT_SERVERS_AND_IPS: phf::OrderedMap<&'static str, RootServerName> = phf_
     "202.12.27.33" => RootServerName("m.root-servers.net"),
 };
 
+/// The IPv6 addresses of the root servers above, by name.
+const ROOT_SERVER_IPV6_ADDRESSES: phf::Map<&'static str, &'static str> = phf_map! {
+    "a.root-servers.net" => "2001:503:ba3e::2:30",
+    "c.root-servers.net" => "2001:500:2::c",
+    "d.root-servers.net" => "2001:500:2d::d",
+    "e.root-servers.net" => "2001:500:a8::e",
+    "f.root-servers.net" => "2001:500:2f::f",
+    "g.root-servers.net" => "2001:500:12::d0d",
+    "h.root-servers.net" => "2001:500:1::53",
+    "i.root-servers.net" => "2001:7fe::53",
+    "j.root-servers.net" => "2001:503:c27::2:30",
+    "k.root-servers.net" => "2001:7fd::1",
+    "l.root-servers.net" => "2001:500:9f::42",
+    "m.root-servers.net" => "2001:dc3::35",
+};
+
+impl RootServerName {
+    /// The IPv6 address of the root server.
+    pub fn ipv6_address(&self) -> Option<&'static str> {
+        ROOT_SERVER_IPV6_ADDRESSES.get(self.0).copied()
+    }
+}
+
 pub struct RootServer {}
 
 impl RootServer {
@@ -49,3 +72,16 @@ fn test_random_root_server_selection_with_seed_is_consistent() {
         assert_eq!(RootServer::random(Some(0)).0, &"192.58.128.30",);
     }
 }
+
+/// Ensure that every root server has an IPv6 address.
+#[test]
+fn test_root_server_ipv6_addresses() {
+    for name in ROOT_SERVERS_AND_IPS.values() {
+        let address = name.ipv6_address().unwrap();
+        assert!(address.parse::<std::net::Ipv6Addr>().is_ok());
+    }
+    assert_eq!(
+        RootServer::random(Some(0)).1.ipv6_address(),
+        Some("2001:503:c27::2:30")
+    );
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Number of rates tracked in a generation, above which a new one is started early.
+const MAX_TRACKED_RATES: usize = 100_000;
+
+/// Tunables of a `RateLimiter`. The defaults follow the recommendations for BIND's RRL.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RateLimitConfig {
+    /// Identical responses a client network may receive per second.
+    pub responses_per_second: u32,
+
+    /// Every this many limited responses, a truncated response is sent instead of nothing so that
+    /// legitimate clients retry over TCP. 0 drops all limited responses.
+    pub slip: u32,
+
+    /// Period over which rates are averaged: how long a client network which exceeded its rate
+    /// stays limited.
+    pub window: Duration,
+
+    /// Length of the prefix identifying IPv4 client networks.
+    pub ipv4_prefix_length: u8,
+
+    /// Length of the prefix identifying IPv6 client networks.
+    pub ipv6_prefix_length: u8,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            responses_per_second: 5,
+            slip: 2,
+            window: Duration::from_secs(15),
+            ipv4_prefix_length: 24,
+            ipv6_prefix_length: 56,
+        }
+    }
+}
+
+/// Responses are counted separately by kind, so that a flood of errors does not limit answers.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum ResponseKind {
+    /// A response with answers, or a referral.
+    Answer,
+
+    /// A response saying the name or the data does not exist.
+    Negative,
+
+    /// Any other error response.
+    Error,
+}
+
+/// What to do with a response.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RateLimitDecision {
+    /// Send the response as is.
+    Send,
+
+    /// Send a truncated response instead.
+    Slip,
+
+    /// Send nothing.
+    Drop,
+}
+
+/// Identifies a stream of identical responses.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+struct RateKey {
+    /// The client network.
+    prefix: IpAddr,
+
+    /// Lowercased name of the question.
+    domain_name: String,
+
+    /// The kind of response.
+    kind: ResponseKind,
+}
+
+/// The credit of a stream of responses, as in a token bucket.
+struct Rate {
+    /// Responses which may still be sent. Negative once the rate is exceeded.
+    balance: i64,
+
+    /// When the balance was last updated.
+    updated_at: Instant,
+
+    /// Limited responses since the last slip.
+    limited: u32,
+}
+
+/// The tracked rates, in two generations so that idle ones are forgotten without going through
+/// all of them: a new generation starts every window, and the rates left in the previous one when
+/// it does were idle for a whole window, long enough to be out of debt.
+#[derive(Default)]
+struct Rates {
+    /// Rates updated since the current generation started.
+    current: HashMap<RateKey, Rate>,
+
+    /// Rates of the previous generation not updated since. They move to the current one when
+    /// they are.
+    previous: HashMap<RateKey, Rate>,
+
+    /// When the current generation started, or `None` before the first response.
+    started_at: Option<Instant>,
+}
+
+/// Response Rate Limiting (RRL) which keeps the server from being used to amplify attacks with
+/// spoofed sources: identical responses to the same client network are limited to a number per
+/// second, and those above the limit are dropped or slipped (truncated).
+#[derive(Default)]
+pub struct RateLimiter {
+    /// Tunables of the limiter.
+    config: RateLimitConfig,
+
+    /// The tracked rates.
+    rates: Mutex<Rates>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Rates are always left
+/// consistent, so they remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl RateLimiter {
+    /// Create a rate limiter which is not tracking any client yet.
+    ///
+    /// # Argument
+    /// * `config`: Tunables of the limiter.
+    pub fn new(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Account for a response about to be sent and decide what to do with it.
+    ///
+    /// # Arguments
+    /// * `client`: The address of the client.
+    /// * `domain_name`: The name of the question, or for negative responses the zone they come
+    ///   from, i.e. the owner of their SOA record. As in BIND, negative responses to random names
+    ///   under a zone then share a rate.
+    /// * `kind`: The kind of response.
+    /// * `now`: The current time.
+    pub fn check(
+        &self,
+        client: IpAddr,
+        domain_name: &str,
+        kind: ResponseKind,
+        now: Instant,
+    ) -> RateLimitDecision {
+        let key = RateKey {
+            prefix: self.prefix(client),
+            domain_name: domain_name.trim_end_matches('.').to_ascii_lowercase(),
+            kind,
+        };
+        let per_second = self.config.responses_per_second as i64;
+        let max_debt = -per_second * self.config.window.as_secs() as i64;
+
+        let mut rates = lock(&self.rates);
+        let is_due = rates.started_at.is_none_or(|started_at| {
+            now.saturating_duration_since(started_at) >= self.config.window
+        });
+        if is_due || rates.current.len() >= MAX_TRACKED_RATES {
+            rates.previous = std::mem::take(&mut rates.current);
+            rates.started_at = Some(now);
+        }
+
+        let rate = rates.previous.remove(&key).unwrap_or(Rate {
+            balance: per_second,
+            updated_at: now,
+            limited: 0,
+        });
+        let rate = rates.current.entry(key).or_insert(rate);
+        let elapsed = now.saturating_duration_since(rate.updated_at).as_secs() as i64;
+        if elapsed > 0 {
+            rate.balance = rate
+                .balance
+                .saturating_add(elapsed.saturating_mul(per_second))
+                .min(per_second);
+            rate.updated_at = now;
+        }
+
+        rate.balance = (rate.balance - 1).max(max_debt);
+        if rate.balance >= 0 {
+            return RateLimitDecision::Send;
+        }
+
+        rate.limited += 1;
+        if self.config.slip > 0 && rate.limited >= self.config.slip {
+            rate.limited = 0;
+            return RateLimitDecision::Slip;
+        }
+        RateLimitDecision::Drop
+    }
+
+    /// The network of the client, as configured by the prefix lengths.
+    ///
+    /// # Argument
+    /// * `client`: The address of the client.
+    fn prefix(&self, client: IpAddr) -> IpAddr {
+        match client {
+            IpAddr::V4(address) => {
+                let length = self.config.ipv4_prefix_length.min(32) as u32;
+                let mask = u32::MAX.checked_shl(32 - length).unwrap_or(0);
+                IpAddr::V4((u32::from(address) & mask).into())
+            }
+            IpAddr::V6(address) => {
+                let length = self.config.ipv6_prefix_length.min(128) as u32;
+                let mask = u128::MAX.checked_shl(128 - length).unwrap_or(0);
+                IpAddr::V6((u128::from(address) & mask).into())
+            }
+        }
+    }
+}
+
+/// Validate that responses above the rate are slipped or dropped until credit accrues again.
+#[test]
+fn test_rate_limiting() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+        responses_per_second: 2,
+        slip: 2,
+        ..Default::default()
+    });
+    let client: IpAddr = "192.0.2.1".parse().unwrap();
+    let now = Instant::now();
+
+    let decisions: Vec<RateLimitDecision> = (0..5)
+        .map(|_| limiter.check(client, "example.com", ResponseKind::Answer, now))
+        .collect();
+    assert_eq!(
+        decisions,
+        [
+            RateLimitDecision::Send,
+            RateLimitDecision::Send,
+            RateLimitDecision::Drop,
+            RateLimitDecision::Slip,
+            RateLimitDecision::Drop,
+        ]
+    );
+
+    // Other names, kinds of responses and client networks are counted separately. Clients of the
+    // same network are not.
+    let neighbor: IpAddr = "192.0.2.200".parse().unwrap();
+    let stranger: IpAddr = "198.51.100.1".parse().unwrap();
+    let check = |client, domain_name, kind| limiter.check(client, domain_name, kind, now);
+    assert_eq!(
+        check(client, "example.net", ResponseKind::Answer),
+        RateLimitDecision::Send
+    );
+    assert_eq!(
+        check(client, "example.com", ResponseKind::Negative),
+        RateLimitDecision::Send
+    );
+    assert_eq!(
+        check(stranger, "example.com", ResponseKind::Answer),
+        RateLimitDecision::Send
+    );
+    assert_ne!(
+        check(neighbor, "EXAMPLE.com.", ResponseKind::Answer),
+        RateLimitDecision::Send
+    );
+
+    // Credit accrues again at 2 responses per second.
+    let later = now + Duration::from_secs(3);
+    assert_eq!(
+        limiter.check(client, "example.com", ResponseKind::Answer, later),
+        RateLimitDecision::Send
+    );
+}
+
+/// Validate that limited responses are all dropped without slip.
+#[test]
+fn test_rate_limiting_without_slip() {
+    let limiter = RateLimiter::new(RateLimitConfig {
+        responses_per_second: 1,
+        slip: 0,
+        ..Default::default()
+    });
+    let client: IpAddr = "2001:db8::1".parse().unwrap();
+    let now = Instant::now();
+
+    assert_eq!(
+        limiter.check(client, "example.com", ResponseKind::Error, now),
+        RateLimitDecision::Send
+    );
+    for _ in 0..4 {
+        assert_eq!(
+            limiter.check(client, "example.com", ResponseKind::Error, now),
+            RateLimitDecision::Drop
+        );
+    }
+}
+
+/// Validate that rates left idle for a whole window are forgotten, and that the others are kept.
+#[test]
+fn test_rate_limiting_forgets_idle_rates() {
+    let limiter = RateLimiter::new(RateLimitConfig::default());
+    let client: IpAddr = "192.0.2.1".parse().unwrap();
+    let now = Instant::now();
+    let window = limiter.config.window;
+
+    limiter.check(client, "idle.example.com", ResponseKind::Answer, now);
+    limiter.check(client, "busy.example.com", ResponseKind::Answer, now);
+    limiter.check(
+        client,
+        "busy.example.com",
+        ResponseKind::Answer,
+        now + window,
+    );
+    limiter.check(
+        client,
+        "busy.example.com",
+        ResponseKind::Answer,
+        now + window * 2,
+    );
+
+    let rates = lock(&limiter.rates);
+    let tracked: Vec<&str> = rates
+        .current
+        .keys()
+        .chain(rates.previous.keys())
+        .map(|key| key.domain_name.as_str())
+        .collect();
+    assert_eq!(tracked, ["busy.example.com"]);
+}
@@ -0,0 +1,1843 @@
+use crate::acl::ListenerAcl;
+use crate::blocklist::{modified, BlockAction, Blocklist};
+use crate::dns64::{needs_synthesis, synthesize, Nat64Prefix, IPV4ONLY_ARPA};
+use crate::edns::Edns;
+use crate::errors::DnsError;
+use crate::forwarder::{Forwarder, Upstream, UpstreamSocket};
+use crate::header::{
+    Header, FLAG_AUTHORITATIVE, FLAG_RECURSION_AVAILABLE, FLAG_RECURSION_DESIRED, FLAG_RESPONSE,
+    FLAG_TRUNCATED, OPCODE_MASK, RCODE_FORMAT_ERROR, RCODE_MASK, RCODE_NAME_ERROR,
+    RCODE_NOT_IMPLEMENTED, RCODE_NO_ERROR, RCODE_REFUSED, RCODE_SERVER_FAILURE,
+};
+use crate::metrics::Metrics;
+use crate::packet::Packet;
+use crate::query_log::{QueryLog, QueryLogEntry};
+use crate::record::{Record, RecordType};
+use crate::resolver::Resolver;
+use crate::rrl::{RateLimitDecision, RateLimiter, ResponseKind};
+use crate::socket::{local_address, DnsTransport};
+use crate::trace::Trace;
+use crate::ttl::Ttl;
+use crate::zone::{Zone, ZoneLookup};
+use byteorder::{BigEndian, ReadBytesExt};
+use log::{error, info, warn};
+use std::io::{Cursor, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Address the server listens on unless configured otherwise.
+pub const DEFAULT_LISTEN_ADDRESS: &str = "0.0.0.0:53";
+
+/// Largest response sent over UDP to clients which do not use EDNS, as specified in RFC 1035,
+/// section 2.3.4.
+const MAX_UDP_RESPONSE_SIZE: u16 = 512;
+
+/// Largest response sent over UDP whatever size the client advertises with EDNS. This avoids IP
+/// fragmentation on common networks (see https://dnsflagday.net/2020/).
+const MAX_EDNS_RESPONSE_SIZE: u16 = 1232;
+
+/// Largest request the server reads.
+const REQUEST_BUFFER_SIZE: usize = 4096;
+
+/// How often cache entries queued for prefetching are refreshed.
+const PREFETCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often upstreams are checked for being due a health probe.
+const PROBE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often zone and blocklist files are checked for changes.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Query type of incremental zone transfers, see RFC 1995.
+const QUERY_TYPE_IXFR: u16 = 251;
+
+/// Query type of full zone transfers, see RFC 5936.
+const QUERY_TYPE_AXFR: u16 = 252;
+
+/// Size of the header which starts every message.
+const HEADER_SIZE: usize = 12;
+
+/// Largest message sent over TCP, whose length prefix is 16 bits.
+const MAX_TCP_MESSAGE_SIZE: usize = u16::MAX as usize;
+
+/// How long a TCP connection may stay idle before the server closes it. RFC 7766, section 6.2.3
+/// recommends a few seconds.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many TCP connections are served at once. Further connections are closed right away.
+const MAX_TCP_CONNECTIONS: usize = 128;
+
+/// A socket to serve on, along with what its clients may do.
+pub struct Listener {
+    /// The bound socket.
+    pub socket: UdpSocket,
+
+    /// The socket listening for TCP connections on the same address, if any, over which clients
+    /// get the responses too large for UDP and transfer zones.
+    pub tcp: Option<TcpListener>,
+
+    /// What the clients of the socket may do.
+    pub acl: ListenerAcl,
+}
+
+/// The served zones along with where they were read from.
+#[derive(Default)]
+struct ZoneState {
+    /// The zones. Requests hold on to them while being answered, so that a reload does not pull
+    /// them from under their feet.
+    zones: Arc<Vec<Zone>>,
+
+    /// When each file was last modified at the time it was read.
+    modified: Vec<Option<SystemTime>>,
+}
+
+/// Lock the zones for reading even if another thread panicked while holding them. The state is
+/// only ever replaced as a whole, so it remains usable.
+fn read(lock: &RwLock<ZoneState>) -> RwLockReadGuard<'_, ZoneState> {
+    match lock.read() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Lock the zones for writing. See `read()`.
+fn write(lock: &RwLock<ZoneState>) -> RwLockWriteGuard<'_, ZoneState> {
+    match lock.write() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// A DNS server which answers questions about the zones it is authoritative for and, when
+/// configured with a resolver or a forwarder, resolves any other name for clients asking for
+/// recursion.
+pub struct Server {
+    /// The zones the server is authoritative for.
+    zones: RwLock<ZoneState>,
+
+    /// The files the zones are read from, if any. The zones are reloaded when they change.
+    zone_paths: Vec<String>,
+
+    /// The resolver for recursive queries. Recursion is unavailable without one.
+    pub recursion: Option<Resolver>,
+
+    /// The forwarder relaying queries to upstream resolvers. It takes precedence over recursion.
+    pub forwarding: Option<Forwarder>,
+
+    /// The NAT64 prefix AAAA records are synthesized in (DNS64, RFC 6147) for resolved and
+    /// forwarded names which only have A records.
+    pub dns64: Option<Nat64Prefix>,
+
+    /// Names which are not resolved for clients, whatever the zones or upstreams say.
+    pub blocklist: Option<Blocklist>,
+
+    /// Response Rate Limiting, to keep the server from being used in amplification attacks.
+    pub rate_limiter: Option<RateLimiter>,
+
+    /// Whether address records of the same name are rotated from one response to the next, so
+    /// that clients picking the first one spread the load. On by default.
+    pub rotate_answers: bool,
+
+    /// How many responses had their address records rotated so far.
+    rotation: AtomicUsize,
+
+    /// The seed for RNG of recursive queries, if desired. Only meant for testing.
+    pub rand_seed: Option<usize>,
+
+    /// Counters and histograms about the queries answered. See `set_metrics()`.
+    metrics: Option<Metrics>,
+
+    /// Where to log the queries answered, along with the upstreams contacted for them.
+    pub query_log: Option<QueryLog>,
+}
+
+impl Server {
+    /// Create a server for the given zones. Every zone needs an SOA record at its apex.
+    ///
+    /// # Argument
+    /// * `zones`: The zones to serve.
+    pub fn new(zones: Vec<Zone>) -> Result<Server, DnsError> {
+        check_soa(&zones)?;
+        Ok(Server {
+            zones: RwLock::new(ZoneState {
+                zones: Arc::new(zones),
+                modified: vec![],
+            }),
+            zone_paths: vec![],
+            recursion: None,
+            forwarding: None,
+            dns64: None,
+            blocklist: None,
+            rate_limiter: None,
+            rotate_answers: true,
+            rotation: AtomicUsize::new(0),
+            rand_seed: None,
+            metrics: None,
+            query_log: None,
+        })
+    }
+
+    /// Count the queries answered from now on, along with the cache lookups and upstream queries
+    /// of the resolver and forwarder. They must be set before calling this.
+    ///
+    /// # Argument
+    /// * `metrics`: Where to count. Clones share the same values, e.g. to serve them.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        if let Some(resolver) = &mut self.recursion {
+            resolver.query_options.metrics = Some(metrics.clone());
+            if let Some(cache) = &resolver.cache {
+                cache.set_metrics(metrics.clone());
+            }
+        }
+        if let Some(forwarder) = &mut self.forwarding {
+            forwarder.metrics = Some(metrics.clone());
+            if let Some(cache) = &forwarder.cache {
+                cache.set_metrics(metrics.clone());
+            }
+        }
+        self.metrics = Some(metrics);
+    }
+
+    /// Create a server for the zones read from the master files at the given paths. Each file
+    /// holds a zone with `$ORIGIN` set or absolute names. See `reload_zones_if_changed()`.
+    ///
+    /// # Argument
+    /// * `paths`: Paths to the master files.
+    pub fn from_files(paths: &[String]) -> Result<Server, DnsError> {
+        let mut server = Self::new(vec![])?;
+        server.zone_paths = paths.to_vec();
+        *write(&server.zones) = server.read_zone_files()?;
+        Ok(server)
+    }
+
+    /// The zones currently served.
+    pub fn zones(&self) -> Arc<Vec<Zone>> {
+        Arc::clone(&read(&self.zones).zones)
+    }
+
+    /// Read the zone files again if any of them was modified since they were last read, and swap
+    /// in the new zones at once. Requests being answered keep using the zones they started with.
+    /// On failure, the zones read before remain served. Returns whether the files were reloaded.
+    pub fn reload_zones_if_changed(&self) -> Result<bool, DnsError> {
+        let modified: Vec<Option<SystemTime>> =
+            self.zone_paths.iter().map(|path| modified(path)).collect();
+        if read(&self.zones).modified == modified {
+            return Ok(false);
+        }
+
+        let state = self.read_zone_files()?;
+        info!("Reloaded {} zones", state.zones.len());
+        *write(&self.zones) = state;
+        Ok(true)
+    }
+
+    /// Read all zone files.
+    fn read_zone_files(&self) -> Result<ZoneState, DnsError> {
+        let mut zones = Vec::with_capacity(self.zone_paths.len());
+        let mut state = ZoneState::default();
+        for path in &self.zone_paths {
+            // The modification time is taken first so that a change made while reading is picked
+            // up by the next reload.
+            state.modified.push(modified(path));
+            match Zone::from_file(path, "") {
+                Ok(zone) => zones.push(zone),
+                Err(parse_error) => {
+                    error!("Could not load the zone file {}", path);
+                    return Err(parse_error);
+                }
+            }
+        }
+        check_soa(&zones)?;
+        state.zones = Arc::new(zones);
+        Ok(state)
+    }
+
+    /// Answer requests arriving on the UDP sockets with the given number of worker threads per
+    /// socket until reading from one of them fails. Connections to the TCP sockets are each
+    /// served by a thread of their own. Each worker resolves and forwards queries on a
+    /// socket of its own, so slow upstreams only hold up the worker handling them. When there is a
+    /// cache, another thread refreshes the entries queued for prefetching. When upstreams are
+    /// forwarded to through a pool, another thread probes their health. When there are zone
+    /// files or a blocklist, yet another thread reloads them whenever they change.
+    ///
+    /// # Arguments
+    /// * `listeners`: The bound sockets to serve on.
+    /// * `workers`: The number of requests handled concurrently on each socket.
+    pub fn serve_concurrently(
+        self: Arc<Self>,
+        listeners: Vec<Listener>,
+        workers: usize,
+    ) -> Result<(), DnsError> {
+        let has_cache = self
+            .recursion
+            .as_ref()
+            .is_some_and(|resolver| resolver.cache.is_some())
+            || self
+                .forwarding
+                .as_ref()
+                .is_some_and(|forwarder| forwarder.cache.is_some());
+        if has_cache {
+            let server = Arc::clone(&self);
+            thread::spawn(move || {
+                let Ok(mut upstream) =
+                    UpstreamSocket::bind(local_address(server.upstream_address()))
+                else {
+                    error!("Could not bind a socket for prefetching");
+                    return;
+                };
+                loop {
+                    if let Some(resolver) = &server.recursion {
+                        resolver.prefetch(&mut upstream, server.rand_seed);
+                    }
+                    if let Some(forwarder) = &server.forwarding {
+                        forwarder.prefetch(&mut upstream, server.rand_seed);
+                    }
+                    thread::sleep(PREFETCH_INTERVAL);
+                }
+            });
+        }
+
+        if self
+            .forwarding
+            .as_ref()
+            .is_some_and(|forwarder| forwarder.pool.is_some())
+        {
+            let server = Arc::clone(&self);
+            thread::spawn(move || {
+                let Ok(mut upstream) =
+                    UpstreamSocket::bind(local_address(server.upstream_address()))
+                else {
+                    error!("Could not bind a socket for health probes");
+                    return;
+                };
+                loop {
+                    if let Some(forwarder) = &server.forwarding {
+                        forwarder.probe(&mut upstream, server.rand_seed);
+                    }
+                    thread::sleep(PROBE_CHECK_INTERVAL);
+                }
+            });
+        }
+
+        if !self.zone_paths.is_empty() || self.blocklist.is_some() {
+            let server = Arc::clone(&self);
+            thread::spawn(move || loop {
+                thread::sleep(RELOAD_INTERVAL);
+                if let Err(reload_error) = server.reload_zones_if_changed() {
+                    warn!("Could not reload the zones: {}", reload_error);
+                }
+                if let Some(Err(reload_error)) = server
+                    .blocklist
+                    .as_ref()
+                    .map(|blocklist| blocklist.reload_if_changed())
+                {
+                    warn!("Could not reload the blocklist: {}", reload_error);
+                }
+            });
+        }
+
+        let connections = Arc::new(AtomicUsize::new(0));
+        let mut handles = Vec::with_capacity(workers * listeners.len());
+        for listener in listeners {
+            let acl = Arc::new(listener.acl);
+            if let Some(tcp) = listener.tcp {
+                let server = Arc::clone(&self);
+                let acl = Arc::clone(&acl);
+                let connections = Arc::clone(&connections);
+                thread::spawn(move || server.accept_connections(tcp, acl, connections));
+            }
+            for _ in 0..workers.max(1) {
+                let Ok(mut socket) = listener.socket.try_clone() else {
+                    return Err(DnsError::SocketBind(None));
+                };
+                let mut upstream = UpstreamSocket::bind(local_address(self.upstream_address()))?;
+                let server = Arc::clone(&self);
+                let acl = Arc::clone(&acl);
+                handles.push(thread::spawn(move || {
+                    server.serve(&mut socket, &acl, &mut upstream)
+                }));
+            }
+        }
+
+        for handle in handles {
+            let Ok(result) = handle.join() else {
+                return Err(DnsError::SocketRead(None));
+            };
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Answer requests arriving on the socket one at a time until reading from it fails.
+    ///
+    /// # Arguments
+    /// * `socket`: The bound socket to serve on.
+    /// * `acl`: What the clients of the socket may do.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn serve(
+        &self,
+        socket: &mut dyn DnsTransport,
+        acl: &ListenerAcl,
+        upstream: &mut dyn DnsTransport,
+    ) -> Result<(), DnsError> {
+        let mut buf = [0u8; REQUEST_BUFFER_SIZE];
+        loop {
+            let (size, client) = socket.recv_from(&mut buf)?;
+            let Some(response) = self.handle(&buf[..size], client.ip(), acl, upstream) else {
+                continue;
+            };
+            if let Err(send_error) = socket.send(&response, &client.to_string()) {
+                warn!("Could not respond to {}: {}", client, send_error);
+            }
+        }
+    }
+
+    /// Accept connections on a TCP socket until accepting fails, serving each on a thread of its
+    /// own with `serve_tcp()`.
+    ///
+    /// # Arguments
+    /// * `listener`: The bound TCP socket.
+    /// * `acl`: What the clients of the socket may do.
+    /// * `connections`: How many connections are being served, across all TCP sockets.
+    fn accept_connections(
+        self: Arc<Self>,
+        listener: TcpListener,
+        acl: Arc<ListenerAcl>,
+        connections: Arc<AtomicUsize>,
+    ) {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(accept_error) => {
+                    warn!("Could not accept a TCP connection: {}", accept_error);
+                    continue;
+                }
+            };
+            if connections.fetch_add(1, Ordering::Relaxed) >= MAX_TCP_CONNECTIONS {
+                connections.fetch_sub(1, Ordering::Relaxed);
+                warn!(
+                    "Closed a TCP connection above the limit of {}",
+                    MAX_TCP_CONNECTIONS
+                );
+                continue;
+            }
+            let server = Arc::clone(&self);
+            let acl = Arc::clone(&acl);
+            let connections = Arc::clone(&connections);
+            thread::spawn(move || {
+                match UpstreamSocket::bind(local_address(server.upstream_address())) {
+                    Ok(mut upstream) => {
+                        if let Err(serve_error) = server.serve_tcp(stream, &acl, &mut upstream) {
+                            info!("Closed a TCP connection: {}", serve_error);
+                        }
+                    }
+                    Err(bind_error) => error!("Could not bind an upstream socket: {}", bind_error),
+                }
+                connections.fetch_sub(1, Ordering::Relaxed);
+            });
+        }
+    }
+
+    /// Answer the requests of a client arriving over a TCP connection, each prefixed with its
+    /// length as specified in RFC 1035, section 4.2.2, until the client closes the connection or
+    /// leaves it idle.
+    ///
+    /// # Arguments
+    /// * `stream`: The connection.
+    /// * `acl`: What the client may do.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn serve_tcp(
+        &self,
+        mut stream: TcpStream,
+        acl: &ListenerAcl,
+        upstream: &mut dyn DnsTransport,
+    ) -> Result<(), DnsError> {
+        let client = stream
+            .peer_addr()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        stream
+            .set_read_timeout(Some(TCP_IDLE_TIMEOUT))
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        loop {
+            // The client closing the connection or leaving it idle ends it.
+            let Ok(length) = stream.read_u16::<BigEndian>() else {
+                return Ok(());
+            };
+            let mut request = vec![0u8; length as usize];
+            stream
+                .read_exact(&mut request)
+                .map_err(|error| DnsError::SocketRead(Some(error)))?;
+            let Some(messages) = self.handle_tcp(&request, client.ip(), acl, upstream) else {
+                warn!(
+                    "Closed the connection of {} after a malformed request",
+                    client
+                );
+                return Ok(());
+            };
+            for message in messages {
+                let mut prefixed = Vec::with_capacity(message.len() + 2);
+                prefixed.extend((message.len() as u16).to_be_bytes());
+                prefixed.extend(message);
+                stream
+                    .write_all(&prefixed)
+                    .map_err(|error| DnsError::SocketSend(Some(error)))?;
+            }
+        }
+    }
+
+    /// Handle a request in wire format and build the response to send back over UDP. Requests
+    /// which are not even a DNS header, and responses, get no response. Clients the ACL does not
+    /// allow to query, or to transfer zones, are refused before the request is parsed. Responses
+    /// above the rate limit, if any, are slipped or dropped; see `limit_rate()`. Responses larger
+    /// than the client accepts over UDP are truncated, for the client to retry over TCP.
+    ///
+    /// # Arguments
+    /// * `request`: The request as received.
+    /// * `client`: The address of the client.
+    /// * `acl`: What the client may do.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn handle(
+        &self,
+        request: &[u8],
+        client: IpAddr,
+        acl: &ListenerAcl,
+        upstream: &mut dyn DnsTransport,
+    ) -> Option<Vec<u8>> {
+        let Some(response) = self.build_response(request, client, acl, upstream) else {
+            warn!("Dropped a malformed request from {}", client);
+            return None;
+        };
+        let Some(response) = self.limit_rate(client, response) else {
+            info!("Dropped a response to {} above the rate limit", client);
+            return None;
+        };
+        let Ok(mut bytes) = response.serialize() else {
+            return None;
+        };
+
+        let max_size = match response.edns() {
+            Ok(Some(edns)) => edns.udp_payload_size.max(MAX_UDP_RESPONSE_SIZE),
+            _ => MAX_UDP_RESPONSE_SIZE,
+        };
+        if bytes.len() > max_size as usize {
+            bytes = truncated(&response).serialize().ok()?;
+        }
+        Some(bytes)
+    }
+
+    /// Handle a request in wire format received over TCP and build the messages to send back:
+    /// a single response which is never truncated, or the records of a zone when a client the
+    /// ACL allows to transfer zones asks for its AXFR (RFC 5936).
+    ///
+    /// # Arguments
+    /// * `request`: The request as received, without its length prefix.
+    /// * `client`: The address of the client.
+    /// * `acl`: What the client may do.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn handle_tcp(
+        &self,
+        request: &[u8],
+        client: IpAddr,
+        acl: &ListenerAcl,
+        upstream: &mut dyn DnsTransport,
+    ) -> Option<Vec<Vec<u8>>> {
+        let is_axfr = first_question_type(request) == Some(QUERY_TYPE_AXFR);
+        if is_axfr && acl.query.permits(client) && acl.transfer.permits(client) {
+            if let Some(messages) = Packet::parse(request)
+                .ok()
+                .and_then(|request| self.transfer(&request))
+            {
+                info!("Transferring a zone to {}", client);
+                return Some(messages);
+            }
+        }
+
+        let response = self.build_response(request, client, acl, upstream)?;
+        let bytes = match response.serialize() {
+            Ok(bytes) if bytes.len() <= MAX_TCP_MESSAGE_SIZE => bytes,
+            _ => truncated(&response).serialize().ok()?,
+        };
+        Some(vec![bytes])
+    }
+
+    /// The messages transferring the zone a request asks for the AXFR of: its SOA record, its
+    /// other records and its SOA record again, in as many messages as they take. `None` when the
+    /// server is not authoritative for a zone of that name.
+    ///
+    /// # Argument
+    /// * `request`: The parsed request.
+    fn transfer(&self, request: &Packet) -> Option<Vec<Vec<u8>>> {
+        let [question] = request.questions.as_slice() else {
+            return None;
+        };
+        let zones = self.zones();
+        let zone = zones
+            .iter()
+            .find(|zone| question.name == zone.origin.as_str())?;
+        let soa = zone.soa()?;
+        let records = std::iter::once(soa)
+            .chain(
+                zone.records
+                    .iter()
+                    .filter(|record| record.r_type != RecordType::SOA),
+            )
+            .chain(std::iter::once(soa));
+
+        let message = |answers: Vec<Record>| Packet {
+            header: Header {
+                id: request.header.id,
+                flags: FLAG_RESPONSE | FLAG_AUTHORITATIVE,
+                ..Default::default()
+            },
+            questions: vec![question.clone()],
+            answers,
+            authorities: vec![],
+            additionals: vec![],
+        };
+        // Sizes are reckoned without name compression, which only makes messages smaller.
+        let empty_size = HEADER_SIZE + question.name.len() + 6;
+        let mut messages = Vec::new();
+        let mut answers: Vec<Record> = Vec::new();
+        let mut size = empty_size;
+        for record in records {
+            let record_size = record.name.len() + 12 + record.data.len();
+            if !answers.is_empty() && size + record_size > MAX_TCP_MESSAGE_SIZE {
+                messages.push(message(std::mem::take(&mut answers)).serialize().ok()?);
+                size = empty_size;
+            }
+            size += record_size;
+            answers.push(record.clone());
+        }
+        messages.push(message(answers).serialize().ok()?);
+        Some(messages)
+    }
+
+    /// Build the response to a request in wire format, recording it in the metrics and the query
+    /// log. See `handle()`.
+    ///
+    /// # Arguments
+    /// * `request`: The request as received.
+    /// * `client`: The address of the client.
+    /// * `acl`: What the client may do.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    fn build_response(
+        &self,
+        request: &[u8],
+        client: IpAddr,
+        acl: &ListenerAcl,
+        upstream: &mut dyn DnsTransport,
+    ) -> Option<Packet> {
+        let received_at = Instant::now();
+        let header = Header::read_and_advance(&mut Cursor::new(request)).ok()?;
+        if header.has_flag(FLAG_RESPONSE) {
+            return None;
+        }
+        let trace = self.query_log.as_ref().map(|_| Trace::default());
+
+        let is_transfer = header.num_questions > 0
+            && matches!(
+                first_question_type(request),
+                Some(QUERY_TYPE_AXFR | QUERY_TYPE_IXFR)
+            );
+        let mut response = if !acl.query.permits(client) {
+            info!("Refused a query from {}", client);
+            error_response(&header, RCODE_REFUSED)
+        } else if is_transfer && !acl.transfer.permits(client) {
+            info!("Refused a zone transfer to {}", client);
+            error_response(&header, RCODE_REFUSED)
+        } else {
+            match Packet::parse(request) {
+                Ok(request) => {
+                    let recursion_allowed = acl.recursion.permits(client);
+                    self.respond(&request, recursion_allowed, upstream, trace.as_ref())
+                }
+                Err(_) => error_response(&header, RCODE_FORMAT_ERROR),
+            }
+        };
+        if self.rotate_answers {
+            let offset = self.rotation.fetch_add(1, Ordering::Relaxed);
+            rotate_address_records(&mut response.answers, offset);
+        }
+        if let Some(metrics) = &self.metrics {
+            let record_type = match response.questions.first() {
+                Some(question) => question.q_type.to_string(),
+                None => String::new(),
+            };
+            metrics.record_query(&record_type, response.rcode(), received_at.elapsed());
+        }
+        if let (Some(query_log), Some(question)) = (&self.query_log, response.questions.first()) {
+            let exchanges = match &trace {
+                Some(trace) => trace.take(),
+                None => Vec::new(),
+            };
+            query_log.log(&QueryLogEntry::new(
+                &String::from_utf8_lossy(&question.name),
+                question.q_type,
+                Ok(&response),
+                received_at.elapsed(),
+                &exchanges,
+            ));
+        }
+        Some(response)
+    }
+
+    /// Apply Response Rate Limiting to a response about to be sent to a client over UDP. Returns
+    /// the response to send, which is truncated when slipped, or nothing when dropped. Negative
+    /// responses are counted by the zone they come from rather than by name.
+    ///
+    /// # Arguments
+    /// * `client`: The address of the client.
+    /// * `response`: The response.
+    pub fn limit_rate(&self, client: IpAddr, response: Packet) -> Option<Packet> {
+        let Some(limiter) = &self.rate_limiter else {
+            return Some(response);
+        };
+
+        let soa = response
+            .authorities
+            .iter()
+            .find(|record| record.r_type == RecordType::SOA);
+        let is_negative = response.answers.is_empty() && soa.is_some();
+        let kind = match response.rcode() {
+            RCODE_NO_ERROR if is_negative => ResponseKind::Negative,
+            RCODE_NO_ERROR => ResponseKind::Answer,
+            RCODE_NAME_ERROR => ResponseKind::Negative,
+            _ => ResponseKind::Error,
+        };
+        let domain_name = match (kind, soa, response.questions.first()) {
+            (ResponseKind::Negative, Some(soa), _) => String::from_utf8_lossy(&soa.name),
+            (_, _, Some(question)) => String::from_utf8_lossy(&question.name),
+            _ => "".into(),
+        };
+        match limiter.check(client, &domain_name, kind, Instant::now()) {
+            RateLimitDecision::Send => Some(response),
+            RateLimitDecision::Slip => Some(truncated(&response)),
+            RateLimitDecision::Drop => None,
+        }
+    }
+
+    /// Build the response to a parsed request from a client which may ask for recursion.
+    ///
+    /// # Arguments
+    /// * `request`: The parsed request.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn answer(&self, request: &Packet, upstream: &mut dyn DnsTransport) -> Packet {
+        self.respond(request, true, upstream, None)
+    }
+
+    /// Build the response to a parsed request.
+    ///
+    /// # Arguments
+    /// * `request`: The parsed request.
+    /// * `recursion_allowed`: Whether the ACL allows recursion for the client.
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    /// * `trace`: Where to add the exchanges with upstreams, if anywhere.
+    fn respond(
+        &self,
+        request: &Packet,
+        recursion_allowed: bool,
+        upstream: &mut dyn DnsTransport,
+        trace: Option<&Trace>,
+    ) -> Packet {
+        let mut response = Packet {
+            header: Header {
+                id: request.header.id,
+                flags: FLAG_RESPONSE
+                    | (request.header.flags & (OPCODE_MASK | FLAG_RECURSION_DESIRED)),
+                ..Default::default()
+            },
+            questions: request.questions.clone(),
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+        };
+        let recursion_available =
+            recursion_allowed && (self.recursion.is_some() || self.forwarding.is_some());
+        if recursion_available {
+            response.header.flags |= FLAG_RECURSION_AVAILABLE;
+        }
+
+        // Clients using EDNS are told how large a response this server is willing to send.
+        if let Ok(Some(edns)) = request.edns() {
+            let size = edns.udp_payload_size.min(MAX_EDNS_RESPONSE_SIZE);
+            if let Ok(record) = Edns::new(size).to_record() {
+                response.additionals.push(record);
+            }
+        }
+
+        if request.header.flags & OPCODE_MASK != 0 {
+            response.header.flags |= RCODE_NOT_IMPLEMENTED;
+            return response;
+        }
+        let [question] = request.questions.as_slice() else {
+            response.header.flags |= RCODE_FORMAT_ERROR;
+            return response;
+        };
+
+        let domain_name = String::from_utf8_lossy(&question.name);
+        info!("Query for {} {}", domain_name, question.q_type);
+
+        if let Some(blocklist) = &self.blocklist {
+            if let Some(entry) = blocklist.blocked_by(&domain_name) {
+                info!(
+                    "Blocked {} {} as listed under {}",
+                    domain_name, question.q_type, entry
+                );
+                response.answers = blocklist.answers(&domain_name, question.q_type);
+                if blocklist.action == BlockAction::NameError {
+                    response.header.flags |= RCODE_NAME_ERROR;
+                }
+                return response;
+            }
+        }
+
+        // The most specific zone containing the name is responsible for it.
+        let zones = self.zones();
+        let Some(zone) = zones
+            .iter()
+            .filter(|zone| zone.contains(&domain_name))
+            .max_by_key(|zone| zone.origin.len())
+        else {
+            if !recursion_available || !request.header.has_flag(FLAG_RECURSION_DESIRED) {
+                response.header.flags |= RCODE_REFUSED;
+                return response;
+            }
+            let mut result = self.query_upstream(upstream, &domain_name, question.q_type, trace);
+            if let (Some(prefix), Ok(packet)) = (&self.dns64, &result) {
+                if question.q_type == RecordType::AAAA && needs_synthesis(packet) {
+                    info!(
+                        "Synthesizing AAAA records for {} from {}",
+                        domain_name, prefix
+                    );
+                    let a_result =
+                        self.query_upstream(upstream, &domain_name, RecordType::A, trace);
+                    if let Ok(packet) = a_result {
+                        result = Ok(synthesize(prefix, packet));
+                    }
+                }
+            }
+            answer_from_upstream(result, &domain_name, &mut response);
+            return response;
+        };
+
+        match zone.lookup(&domain_name, question.q_type) {
+            ZoneLookup::Answer(records) => {
+                response.header.flags |= FLAG_AUTHORITATIVE;
+                response.answers = records;
+            }
+            ZoneLookup::Referral { name_servers, glue } => {
+                response.authorities = name_servers;
+                response.additionals.splice(0..0, glue);
+            }
+            ZoneLookup::NoData => {
+                response.header.flags |= FLAG_AUTHORITATIVE;
+                response.authorities = negative_soa(zone);
+            }
+            ZoneLookup::NameError => {
+                response.header.flags |= FLAG_AUTHORITATIVE | RCODE_NAME_ERROR;
+                response.authorities = negative_soa(zone);
+            }
+        }
+        response
+    }
+
+    /// The address of the first upstream forwarded to over plain DNS, if any. Sockets for
+    /// upstreams are bound on its address family, or on IPv4 for recursion.
+    pub fn upstream_address(&self) -> Option<SocketAddr> {
+        let forwarder = self.forwarding.as_ref()?;
+        forwarder
+            .upstreams
+            .iter()
+            .find_map(|upstream| match upstream {
+                Upstream::Udp(address) => Some(*address),
+                _ => None,
+            })
+    }
+
+    /// Discover the NAT64 prefix of the network by asking the upstream for the AAAA records of
+    /// `ipv4only.arpa`, as specified in RFC 7050, so that it can be used for DNS64.
+    ///
+    /// # Argument
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    pub fn discover_nat64_prefix(
+        &self,
+        upstream: &mut dyn DnsTransport,
+    ) -> Result<Nat64Prefix, DnsError> {
+        let packet = self.query_upstream(upstream, IPV4ONLY_ARPA, RecordType::AAAA, None)?;
+        let Some(prefix) = Nat64Prefix::discover(&packet.answers) else {
+            return Err(DnsError::InvalidNat64Prefix);
+        };
+        info!("Discovered the NAT64 prefix {}", prefix);
+        Ok(prefix)
+    }
+
+    /// Resolve or forward a question about a name outside of the served zones. Forwarding takes
+    /// precedence over recursion.
+    ///
+    /// # Arguments
+    /// * `upstream`: The socket to send recursive and forwarded queries on.
+    /// * `domain_name`: The name in question.
+    /// * `record_type`: The type in question.
+    /// * `trace`: Where to add the exchanges with upstreams, if anywhere.
+    fn query_upstream(
+        &self,
+        upstream: &mut dyn DnsTransport,
+        domain_name: &str,
+        record_type: RecordType,
+        trace: Option<&Trace>,
+    ) -> Result<Packet, DnsError> {
+        match (&self.forwarding, &self.recursion) {
+            (Some(forwarder), _) => {
+                forwarder.forward_traced(upstream, domain_name, record_type, self.rand_seed, trace)
+            }
+            (None, Some(resolver)) => {
+                resolver.resolve_traced(upstream, domain_name, record_type, self.rand_seed, trace)
+            }
+            (None, None) => Err(DnsError::InvalidUpstream),
+        }
+    }
+}
+
+/// Make sure that every zone has an SOA record at its apex.
+///
+/// # Argument
+/// * `zones`: The zones to check.
+fn check_soa(zones: &[Zone]) -> Result<(), DnsError> {
+    if let Some(zone) = zones.iter().find(|zone| zone.soa().is_none()) {
+        error!("The zone {} has no SOA record", zone.origin);
+        return Err(DnsError::ParseZone);
+    }
+    Ok(())
+}
+
+/// Fill in the response to a question from the outcome of resolving or forwarding it.
+///
+/// # Arguments
+/// * `result`: The response of the upstream, or the reason there is none.
+/// * `domain_name`: The name of the question. Only used for logging purposes.
+/// * `response`: The response to fill in.
+fn answer_from_upstream(
+    result: Result<Packet, DnsError>,
+    domain_name: &str,
+    response: &mut Packet,
+) {
+    match result {
+        // Extended response codes concern the EDNS exchange with the upstream, not the client.
+        Ok(packet) if packet.rcode() > RCODE_MASK => {
+            warn!(
+                "Upstream failed to answer {} with RCODE {}",
+                domain_name,
+                packet.rcode()
+            );
+            response.header.flags |= RCODE_SERVER_FAILURE;
+        }
+        Ok(packet) => {
+            // A response still truncated could not be retried over TCP, so the client is left to.
+            response.header.flags |= packet.header.rcode() | (packet.header.flags & FLAG_TRUNCATED);
+            response.answers = packet.answers;
+            response.authorities = packet.authorities;
+        }
+        Err(DnsError::UnknownDomainName) => response.header.flags |= RCODE_NAME_ERROR,
+        // The name may well exist; the servers delegating it are misconfigured.
+        Err(DnsError::TooManyReferrals) => {
+            warn!("Gave up resolving {}: too many referrals", domain_name);
+            response.header.flags |= RCODE_SERVER_FAILURE;
+        }
+        Err(resolve_error) => {
+            warn!("Could not resolve {}: {}", domain_name, resolve_error);
+            response.header.flags |= RCODE_SERVER_FAILURE;
+        }
+    }
+}
+
+/// Rotate the A and AAAA records of each name among the positions they hold, leaving any other
+/// record, such as the CNAME records leading to them, in place.
+///
+/// # Arguments
+/// * `records`: The records to rotate.
+/// * `offset`: How many positions to rotate by.
+fn rotate_address_records(records: &mut [Record], offset: usize) {
+    let mut rotated = vec![false; records.len()];
+    for first in 0..records.len() {
+        if rotated[first] || !matches!(records[first].r_type, RecordType::A | RecordType::AAAA) {
+            continue;
+        }
+        let positions: Vec<usize> = (first..records.len())
+            .filter(|index| {
+                records[*index].r_type == records[first].r_type
+                    && records[*index].name == records[first].name
+            })
+            .collect();
+        let mut rrset: Vec<Record> = positions
+            .iter()
+            .map(|index| records[*index].clone())
+            .collect();
+        let shift = offset % rrset.len();
+        rrset.rotate_left(shift);
+        for (index, record) in positions.into_iter().zip(rrset) {
+            records[index] = record;
+            rotated[index] = true;
+        }
+    }
+}
+
+/// A response without any question or record, for requests which cannot be processed.
+///
+/// # Arguments
+/// * `header`: The header of the request.
+/// * `rcode`: The response code.
+fn error_response(header: &Header, rcode: u16) -> Packet {
+    Packet {
+        header: Header {
+            id: header.id,
+            flags: FLAG_RESPONSE | (header.flags & OPCODE_MASK) | rcode,
+            ..Default::default()
+        },
+        questions: vec![],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    }
+}
+
+/// The type of the first question of a request in wire format, read without parsing the request.
+///
+/// # Argument
+/// * `request`: The request as received.
+fn first_question_type(request: &[u8]) -> Option<u16> {
+    let mut index = HEADER_SIZE;
+    loop {
+        let length = *request.get(index)? as usize;
+        index += 1;
+        if length == 0 {
+            break;
+        }
+        // The first name of a message has nothing to point back to.
+        if length > 63 {
+            return None;
+        }
+        index += length;
+    }
+    Some(u16::from_be_bytes([
+        *request.get(index)?,
+        *request.get(index + 1)?,
+    ]))
+}
+
+/// The response stripped of its records, with the TC bit set. The client is expected to retry
+/// over TCP once it sees the TC bit.
+///
+/// # Argument
+/// * `response`: The response to truncate.
+fn truncated(response: &Packet) -> Packet {
+    let mut truncated = response.clone();
+    truncated.header.flags |= FLAG_TRUNCATED;
+    truncated.answers.clear();
+    truncated.authorities.clear();
+    truncated
+        .additionals
+        .retain(|record| record.r_type == RecordType::OPT);
+    truncated
+}
+
+/// The SOA record to include with negative answers. Its TTL is the lower of its own TTL and its
+/// minimum field, as specified in RFC 2308, section 3.
+///
+/// # Argument
+/// * `zone`: The zone the negative answer is from.
+fn negative_soa(zone: &Zone) -> Vec<Record> {
+    let Some(soa) = zone.soa() else {
+        return vec![];
+    };
+    let mut soa = soa.clone();
+    if let Some(minimum) = soa.data.get(soa.data.len().saturating_sub(4)..) {
+        if let Ok(minimum) = <[u8; 4]>::try_from(minimum) {
+            soa.ttl = soa.ttl.min(Ttl::from(u32::from_be_bytes(minimum)));
+        }
+    }
+    vec![soa]
+}
+
+#[cfg(test)]
+fn test_server() -> Server {
+    let zone = Zone::parse(
+        "
+$ORIGIN example.com.
+$TTL 300
+@        SOA ns1 hostmaster 1 3600 900 604800 60
+         NS  ns1
+ns1      A   192.0.2.1
+www      A   192.0.2.2
+child    NS  ns.child
+ns.child A   192.0.2.4
+",
+        "",
+    )
+    .unwrap();
+    Server::new(vec![zone]).unwrap()
+}
+
+/// The address of the client of test requests.
+#[cfg(test)]
+const TEST_CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 100));
+
+/// A socket for servers which are not expected to send any queries.
+#[cfg(test)]
+fn no_upstream() -> crate::socket::MockSocket {
+    crate::socket::MockSocket::default()
+}
+
+/// An ACL allowing `TEST_CLIENT` recursion, which the default one does not.
+#[cfg(test)]
+fn recursive_acl() -> ListenerAcl {
+    ListenerAcl {
+        recursion: crate::acl::AccessList::default(),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+fn test_request(domain_name: &str, record_type: RecordType) -> Packet {
+    use crate::dns_name::DnsName;
+    use crate::question::Question;
+
+    Packet {
+        header: Header {
+            id: 4242,
+            flags: FLAG_RECURSION_DESIRED,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: DnsName::from(domain_name),
+            q_type: record_type,
+            q_class: 1,
+        }],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    }
+}
+
+/// Validate authoritative answers and negative responses.
+#[test]
+fn test_server_answers() {
+    use crate::header::RCODE_NO_ERROR;
+
+    let server = test_server();
+
+    let response = server.answer(
+        &test_request("www.example.com", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.id, 4242);
+    assert!(response.header.has_flag(FLAG_RESPONSE | FLAG_AUTHORITATIVE));
+    assert!(response.header.has_flag(FLAG_RECURSION_DESIRED));
+    assert_eq!(response.header.rcode(), RCODE_NO_ERROR);
+    assert_eq!(response.answers[0].data, [192, 0, 2, 2]);
+
+    let response = server.answer(
+        &test_request("www.example.com", RecordType::AAAA),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_NO_ERROR);
+    assert!(response.answers.is_empty());
+    assert_eq!(response.authorities[0].r_type, RecordType::SOA);
+
+    let response = server.answer(
+        &test_request("nope.example.com", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert!(response.header.has_flag(FLAG_AUTHORITATIVE));
+    assert_eq!(response.header.rcode(), RCODE_NAME_ERROR);
+    // The negative TTL is capped by the SOA minimum.
+    assert_eq!(response.authorities[0].ttl, 60);
+
+    let response = server.answer(
+        &test_request("example.net", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_REFUSED);
+}
+
+/// Validate referrals to delegated child zones.
+#[test]
+fn test_server_referral() {
+    let server = test_server();
+    let response = server.answer(
+        &test_request("host.child.example.com", RecordType::A),
+        &mut no_upstream(),
+    );
+
+    assert!(!response.header.has_flag(FLAG_AUTHORITATIVE));
+    assert!(response.answers.is_empty());
+    assert_eq!(response.authorities[0].r_type, RecordType::NS);
+    assert_eq!(response.additionals[0].data, [192, 0, 2, 4]);
+}
+
+/// Validate handling of requests in wire format, including malformed ones.
+#[test]
+fn test_server_handles_wire_format() -> Result<(), DnsError> {
+    let server = test_server();
+
+    let request = test_request("www.example.com", RecordType::A).serialize()?;
+    let response = Packet::parse(
+        &server
+            .handle(
+                &request,
+                TEST_CLIENT,
+                &ListenerAcl::default(),
+                &mut no_upstream(),
+            )
+            .unwrap(),
+    )?;
+    assert_eq!(response.answers.len(), 1);
+    assert_eq!(response.questions.len(), 1);
+
+    // A header followed by garbage is answered with FORMERR.
+    let mut garbage = request[..12].to_vec();
+    garbage.push(0xff);
+    let response = Packet::parse(
+        &server
+            .handle(
+                &garbage,
+                TEST_CLIENT,
+                &ListenerAcl::default(),
+                &mut no_upstream(),
+            )
+            .unwrap(),
+    )?;
+    assert_eq!(response.header.rcode(), RCODE_FORMAT_ERROR);
+
+    // Anything shorter than a header, and responses, are dropped.
+    assert!(server
+        .handle(
+            &request[..4],
+            TEST_CLIENT,
+            &ListenerAcl::default(),
+            &mut no_upstream()
+        )
+        .is_none());
+    let response = server
+        .handle(
+            &request,
+            TEST_CLIENT,
+            &ListenerAcl::default(),
+            &mut no_upstream(),
+        )
+        .unwrap();
+    assert!(server
+        .handle(
+            &response,
+            TEST_CLIENT,
+            &ListenerAcl::default(),
+            &mut no_upstream()
+        )
+        .is_none());
+    Ok(())
+}
+
+/// Validate that responses too large for UDP are truncated.
+#[test]
+fn test_server_truncates_large_responses() -> Result<(), DnsError> {
+    let mut zone_file = String::from("$TTL 60\n@ SOA ns hostmaster 1 1 1 1 1\n");
+    for index in 0..30 {
+        zone_file.push_str(&format!("big A 192.0.2.{}\n", index));
+    }
+    let server = Server::new(vec![Zone::parse(&zone_file, "example.com")?])?;
+
+    let request = test_request("big.example.com", RecordType::A).serialize()?;
+    let response = Packet::parse(
+        &server
+            .handle(
+                &request,
+                TEST_CLIENT,
+                &ListenerAcl::default(),
+                &mut no_upstream(),
+            )
+            .unwrap(),
+    )?;
+    assert!(response.header.has_flag(FLAG_TRUNCATED));
+    assert!(response.answers.is_empty());
+
+    // With EDNS, the client may accept the larger response.
+    let mut request = test_request("big.example.com", RecordType::A);
+    request.additionals.push(Edns::new(4096).to_record()?);
+    let response = Packet::parse(
+        &server
+            .handle(
+                &request.serialize()?,
+                TEST_CLIENT,
+                &ListenerAcl::default(),
+                &mut no_upstream(),
+            )
+            .unwrap(),
+    )?;
+    assert!(!response.header.has_flag(FLAG_TRUNCATED));
+    assert_eq!(response.answers.len(), 30);
+    Ok(())
+}
+
+/// Validate that responses too large for UDP are sent whole over TCP, and that zones are
+/// transferred over TCP to the clients allowed to.
+#[test]
+fn test_server_tcp() -> Result<(), DnsError> {
+    use crate::acl::{AccessList, Cidr};
+    use crate::tcp::TcpTransport;
+
+    let mut zone_file = String::from("$TTL 60\n@ SOA ns hostmaster 1 1 1 1 1\n");
+    for index in 0..59 {
+        zone_file.push_str(&format!("big A 192.0.2.{}\n", index));
+    }
+    let server = Server::new(vec![Zone::parse(&zone_file, "example.com")?])?;
+    let acl = ListenerAcl {
+        transfer: AccessList {
+            allow: vec![Cidr::parse("127.0.0.1/32")?],
+            deny: vec![],
+        },
+        ..Default::default()
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let serving = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        server.serve_tcp(stream, &acl, &mut no_upstream())
+    });
+
+    let mut transport = TcpTransport::bind("")?;
+    let request = test_request("big.example.com", RecordType::A).serialize()?;
+    transport.send(&request, &address)?;
+    let mut buf = [0u8; MAX_TCP_MESSAGE_SIZE];
+    let (size, _) = transport.recv_from(&mut buf)?;
+    let response = Packet::parse(&buf[..size])?;
+    assert!(!response.header.has_flag(FLAG_TRUNCATED));
+    assert_eq!(response.answers.len(), 59);
+    transport.set_idle_timeout(&address, Some(Duration::from_secs(60)));
+
+    // The transfer follows on the same connection.
+    let zone = crate::axfr::transfer_zone(&mut transport, &address, "example.com", Some(0))?;
+    assert_eq!(zone.records.len(), 60);
+    transport.set_idle_timeout(&address, None);
+    serving.join().unwrap()
+}
+
+/// Validate that names outside of the served zones are resolved for clients asking for recursion.
+#[test]
+fn test_server_recursion() -> Result<(), DnsError> {
+    use crate::mock_data;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let mut server = test_server();
+    server.recursion = Some(Resolver::new(ResolverConfig::default()));
+    server.rand_seed = Some(0);
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(mock_data::captured_data_for_twitter());
+
+    let response = server.answer(&test_request("twitter.com", RecordType::A), &mut socket);
+    assert!(response.header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert!(!response.header.has_flag(FLAG_AUTHORITATIVE));
+    assert_eq!(response.answers[0].data, [104, 244, 42, 193]);
+
+    // Without the RD bit, only the served zones are answered.
+    let mut request = test_request("twitter.com", RecordType::A);
+    request.header.flags = 0;
+    let response = server.answer(&request, &mut socket);
+    assert!(response.header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert_eq!(response.header.rcode(), RCODE_REFUSED);
+
+    // The served zones are still answered authoritatively.
+    let response = server.answer(&test_request("www.example.com", RecordType::A), &mut socket);
+    assert!(response.header.has_flag(FLAG_AUTHORITATIVE));
+    Ok(())
+}
+
+/// Validate that a resolution which gives up on a referral loop is a server failure.
+#[test]
+fn test_server_recursion_referral_loop() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let mut server = test_server();
+    server.recursion = Some(Resolver::new(ResolverConfig::default()));
+    server.rand_seed = Some(0);
+
+    let exchanges = MockResolution::new("twitter.com", RecordType::A, 0)
+        .referral("com", "a.gtld-servers.net", "192.0.2.30")
+        .referral("com", "a.gtld-servers.net", "192.0.2.30")
+        .build()?;
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let response = server.answer(&test_request("twitter.com", RecordType::A), &mut socket);
+    assert_eq!(response.header.rcode(), RCODE_SERVER_FAILURE);
+    assert!(response.answers.is_empty());
+    Ok(())
+}
+
+/// Validate that names outside of the served zones are forwarded for clients asking for
+/// recursion.
+#[test]
+fn test_server_forwarding() -> Result<(), DnsError> {
+    use crate::forwarder::{test_upstream, Upstream, TEST_UPSTREAM};
+
+    let mut server = test_server();
+    server.forwarding = Some(Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]));
+    server.rand_seed = Some(0);
+
+    let response = server.answer(
+        &test_request("twitter.com", RecordType::A),
+        &mut test_upstream(0),
+    );
+    assert!(response.header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert!(!response.header.has_flag(FLAG_AUTHORITATIVE));
+    assert_eq!(response.answers[0].data, [104, 244, 42, 193]);
+
+    // A truncated response which could not be retried over TCP is passed on as such.
+    let response = server.answer(
+        &test_request("twitter.com", RecordType::A),
+        &mut test_upstream(FLAG_TRUNCATED),
+    );
+    assert!(response.header.has_flag(FLAG_TRUNCATED));
+
+    // An unreachable upstream is a server failure.
+    let response = server.answer(
+        &test_request("twitter.com", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_SERVER_FAILURE);
+    Ok(())
+}
+
+/// Validate counting of the queries answered, the cache lookups and the upstream queries.
+#[test]
+fn test_server_metrics() -> Result<(), DnsError> {
+    use crate::cache::{Cache, CacheConfig};
+    use crate::forwarder::{test_upstream, Upstream, TEST_UPSTREAM};
+
+    let mut server = test_server();
+    let mut forwarder = Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]);
+    forwarder.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+    server.forwarding = Some(forwarder);
+    server.rand_seed = Some(0);
+    let metrics = Metrics::default();
+    server.set_metrics(metrics.clone());
+
+    let request = test_request("twitter.com", RecordType::A).serialize()?;
+    let mut upstream = test_upstream(0);
+    for _ in 0..2 {
+        let response = server.handle(&request, TEST_CLIENT, &recursive_acl(), &mut upstream);
+        assert!(response.is_some());
+    }
+    let request = test_request("nowhere.example.com", RecordType::AAAA).serialize()?;
+    server.handle(&request, TEST_CLIENT, &recursive_acl(), &mut upstream);
+
+    let output = metrics.render();
+    for line in [
+        "toy_dns_queries_total{type=\"A\",rcode=\"NOERROR\"} 2",
+        "toy_dns_queries_total{type=\"AAAA\",rcode=\"NXDOMAIN\"} 1",
+        "toy_dns_request_duration_seconds_count 3",
+        "toy_dns_cache_hits_total 1",
+        "toy_dns_cache_misses_total 1",
+        "toy_dns_upstream_rtt_seconds_count 1",
+        "toy_dns_upstream_timeouts_total 0",
+    ] {
+        assert!(output.lines().any(|rendered| rendered == line), "{}", line);
+    }
+    Ok(())
+}
+
+/// Validate logging of the queries answered, with the upstreams contacted for each of them.
+#[test]
+fn test_server_query_log() -> Result<(), DnsError> {
+    use crate::cache::{Cache, CacheConfig};
+    use crate::forwarder::{test_upstream, Upstream, TEST_UPSTREAM};
+    use crate::query_log::QueryLogFormat;
+
+    let path = std::env::temp_dir().join(format!("toy_dns_server_log_{}", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let mut server = test_server();
+    let mut forwarder = Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]);
+    forwarder.cache = Some(Arc::new(Cache::new(CacheConfig::default())));
+    server.forwarding = Some(forwarder);
+    server.rand_seed = Some(0);
+    server.query_log = Some(QueryLog::open(&path, QueryLogFormat::Text)?);
+
+    let request = test_request("twitter.com", RecordType::A).serialize()?;
+    let mut upstream = test_upstream(0);
+    for _ in 0..2 {
+        server.handle(&request, TEST_CLIENT, &recursive_acl(), &mut upstream);
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|_| DnsError::QueryLog)?;
+    _ = std::fs::remove_file(&path);
+
+    // The second query is answered from the cache without contacting the upstream.
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(" twitter.com A NOERROR answers=1 elapsed_ms="));
+    assert!(lines[0].ends_with(&format!(" servers={}", TEST_UPSTREAM)));
+    assert!(lines[1].ends_with(" servers=-"));
+    Ok(())
+}
+
+/// Validate that clients are refused queries, recursion and zone transfers as the ACL says.
+#[test]
+fn test_server_acl() -> Result<(), DnsError> {
+    use crate::acl::{AccessList, Cidr};
+    use crate::forwarder::{test_upstream, Upstream, TEST_UPSTREAM};
+
+    let mut server = test_server();
+    server.forwarding = Some(Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]));
+    server.rand_seed = Some(0);
+    let acl = ListenerAcl {
+        query: AccessList {
+            allow: vec![Cidr::parse("192.0.2.0/24")?],
+            deny: vec![],
+        },
+        recursion: AccessList {
+            allow: vec![Cidr::parse("192.0.2.200/32")?],
+            deny: vec![],
+        },
+        ..Default::default()
+    };
+    let handle = |request: &[u8], client: &str| {
+        let response = server.handle(
+            request,
+            client.parse().unwrap(),
+            &acl,
+            &mut test_upstream(0),
+        );
+        Packet::parse(&response.unwrap())
+    };
+
+    // Clients outside of the allowed blocks are refused without their request being parsed.
+    let request = test_request("www.example.com", RecordType::A).serialize()?;
+    let response = handle(&request, "198.51.100.1")?;
+    assert_eq!(response.header.id, 4242);
+    assert_eq!(response.header.rcode(), RCODE_REFUSED);
+    assert!(response.questions.is_empty());
+    assert_eq!(handle(&request, "192.0.2.1")?.answers.len(), 1);
+
+    // Names outside of the served zones are only forwarded for clients allowed recursion.
+    let request = test_request("twitter.com", RecordType::A).serialize()?;
+    let response = handle(&request, "192.0.2.1")?;
+    assert!(!response.header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert_eq!(response.header.rcode(), RCODE_REFUSED);
+    let response = handle(&request, "192.0.2.200")?;
+    assert!(response.header.has_flag(FLAG_RECURSION_AVAILABLE));
+    assert_eq!(response.answers[0].data, [104, 244, 42, 193]);
+
+    // Zone transfers are denied by default. The type of the question ends the request.
+    let mut request = test_request("example.com", RecordType::A).serialize()?;
+    let index = request.len() - 4;
+    request[index..index + 2].copy_from_slice(&QUERY_TYPE_AXFR.to_be_bytes());
+    assert_eq!(handle(&request, "192.0.2.1")?.header.rcode(), RCODE_REFUSED);
+    Ok(())
+}
+
+/// Validate that AAAA records are synthesized from A records for names without any.
+#[test]
+fn test_server_dns64() -> Result<(), DnsError> {
+    use crate::cache::CacheKey;
+    use crate::dns64::WELL_KNOWN_PREFIX;
+    use crate::dns_name::DnsName;
+    use crate::forwarder::{test_upstream_answering, Upstream, TEST_UPSTREAM};
+
+    let mut server = test_server();
+    server.forwarding = Some(Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]));
+    server.dns64 = Some(Nat64Prefix::parse(WELL_KNOWN_PREFIX)?);
+    server.rand_seed = Some(0);
+
+    let native = Record {
+        name: DnsName::from("native.example"),
+        r_type: RecordType::AAAA,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: "2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+            .to_vec(),
+    };
+    let legacy = Record {
+        name: DnsName::from("legacy.example"),
+        r_type: RecordType::A,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: vec![192, 0, 2, 1],
+    };
+    let upstream = || {
+        test_upstream_answering(vec![
+            (
+                CacheKey::new("native.example", RecordType::AAAA),
+                RCODE_NO_ERROR,
+                vec![native.clone()],
+            ),
+            (
+                CacheKey::new("legacy.example", RecordType::AAAA),
+                RCODE_NO_ERROR,
+                vec![],
+            ),
+            (
+                CacheKey::new("legacy.example", RecordType::A),
+                RCODE_NO_ERROR,
+                vec![legacy.clone()],
+            ),
+            (
+                CacheKey::new("missing.example", RecordType::AAAA),
+                RCODE_NAME_ERROR,
+                vec![],
+            ),
+        ])
+    };
+
+    let response = server.answer(
+        &test_request("native.example", RecordType::AAAA),
+        &mut upstream(),
+    );
+    assert_eq!(response.answers[0], native);
+
+    let response = server.answer(
+        &test_request("legacy.example", RecordType::AAAA),
+        &mut upstream(),
+    );
+    assert_eq!(response.answers[0].r_type, RecordType::AAAA);
+    assert_eq!(
+        response.answers[0].data,
+        "64:ff9b::c000:201"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+    );
+
+    // Names which do not exist are not synthesized.
+    let response = server.answer(
+        &test_request("missing.example", RecordType::AAAA),
+        &mut upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_NAME_ERROR);
+    assert!(response.answers.is_empty());
+    Ok(())
+}
+
+/// Validate discovery of the NAT64 prefix through the upstream.
+#[test]
+fn test_server_discovers_nat64_prefix() -> Result<(), DnsError> {
+    use crate::cache::CacheKey;
+    use crate::dns_name::DnsName;
+    use crate::forwarder::{test_upstream_answering, Upstream, TEST_UPSTREAM};
+
+    let mut server = test_server();
+    server.forwarding = Some(Forwarder::new(vec![Upstream::parse(TEST_UPSTREAM)?]));
+    server.rand_seed = Some(0);
+
+    let synthesized = Record {
+        name: DnsName::from(IPV4ONLY_ARPA),
+        r_type: RecordType::AAAA,
+        r_class: 1,
+        ttl: Ttl::from(300),
+        data: "2001:db8:64::c000:aa"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+            .to_vec(),
+    };
+    let mut upstream = test_upstream_answering(vec![(
+        CacheKey::new(IPV4ONLY_ARPA, RecordType::AAAA),
+        RCODE_NO_ERROR,
+        vec![synthesized],
+    )]);
+    assert_eq!(
+        server.discover_nat64_prefix(&mut upstream)?,
+        Nat64Prefix::parse("2001:db8:64::/96")?
+    );
+
+    // Without DNS64 on the path, ipv4only.arpa has no AAAA record.
+    let mut upstream = test_upstream_answering(vec![(
+        CacheKey::new(IPV4ONLY_ARPA, RecordType::AAAA),
+        RCODE_NO_ERROR,
+        vec![],
+    )]);
+    assert_eq!(
+        server.discover_nat64_prefix(&mut upstream),
+        Err(DnsError::InvalidNat64Prefix)
+    );
+    Ok(())
+}
+
+/// Validate that blocked names are answered according to the blocklist, even in served zones.
+#[test]
+fn test_server_blocklist() {
+    use crate::header::RCODE_NO_ERROR;
+
+    let mut server = test_server();
+    server.blocklist = Some(Blocklist::parse(
+        "www.example.com\n",
+        BlockAction::NameError,
+    ));
+
+    let response = server.answer(
+        &test_request("www.example.com", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_NAME_ERROR);
+    assert!(response.answers.is_empty());
+
+    server.blocklist = Some(Blocklist::parse("example.com\n", BlockAction::NullAddress));
+    let response = server.answer(
+        &test_request("www.example.com", RecordType::A),
+        &mut no_upstream(),
+    );
+    assert_eq!(response.header.rcode(), RCODE_NO_ERROR);
+    assert_eq!(response.answers[0].data, [0, 0, 0, 0]);
+}
+
+/// Validate that address records are rotated from one response to the next unless disabled.
+#[test]
+fn test_server_rotates_answers() -> Result<(), DnsError> {
+    let zone = Zone::parse(
+        "
+$ORIGIN example.com.
+$TTL 300
+@    SOA ns1 hostmaster 1 3600 900 604800 60
+www  CNAME pool
+pool A   192.0.2.1
+pool A   192.0.2.2
+pool A   192.0.2.3
+",
+        "",
+    )?;
+    let mut server = Server::new(vec![zone])?;
+    let request = test_request("www.example.com", RecordType::A).serialize()?;
+    let first_addresses = || -> Result<(RecordType, u8), DnsError> {
+        let response = server.handle(
+            &request,
+            TEST_CLIENT,
+            &ListenerAcl::default(),
+            &mut no_upstream(),
+        );
+        let response = Packet::parse(&response.unwrap())?;
+        Ok((response.answers[0].r_type, response.answers[1].data[3]))
+    };
+    assert_eq!(first_addresses()?, (RecordType::CNAME, 1));
+    assert_eq!(first_addresses()?, (RecordType::CNAME, 2));
+    assert_eq!(first_addresses()?, (RecordType::CNAME, 3));
+    assert_eq!(first_addresses()?, (RecordType::CNAME, 1));
+
+    server.rotate_answers = false;
+    let response = server.handle(
+        &request,
+        TEST_CLIENT,
+        &ListenerAcl::default(),
+        &mut no_upstream(),
+    );
+    let response = Packet::parse(&response.unwrap())?;
+    let last_bytes: Vec<u8> = response.answers[1..]
+        .iter()
+        .map(|record| record.data[3])
+        .collect();
+    assert_eq!(last_bytes, [1, 2, 3]);
+    Ok(())
+}
+
+/// Validate that responses above the rate limit are slipped or dropped.
+#[test]
+fn test_server_rate_limiting() -> Result<(), DnsError> {
+    use crate::rrl::RateLimitConfig;
+
+    let mut server = test_server();
+    server.rate_limiter = Some(RateLimiter::new(RateLimitConfig {
+        responses_per_second: 1,
+        slip: 2,
+        ..Default::default()
+    }));
+    let handle = |domain_name: &str, record_type: RecordType| {
+        let request = test_request(domain_name, record_type).serialize().unwrap();
+        let response = server.handle(
+            &request,
+            TEST_CLIENT,
+            &ListenerAcl::default(),
+            &mut no_upstream(),
+        );
+        response.map(|response| Packet::parse(&response).unwrap())
+    };
+
+    assert_eq!(
+        handle("www.example.com", RecordType::A)
+            .unwrap()
+            .answers
+            .len(),
+        1
+    );
+    assert!(handle("www.example.com", RecordType::A).is_none());
+    let slipped = handle("www.example.com", RecordType::A).unwrap();
+    assert!(slipped.header.has_flag(FLAG_TRUNCATED));
+    assert!(slipped.answers.is_empty());
+
+    // Negative responses are counted separately, by zone rather than by name.
+    let response = handle("www.example.com", RecordType::AAAA).unwrap();
+    assert!(!response.header.has_flag(FLAG_TRUNCATED));
+    assert!(handle("random1.example.com", RecordType::A).is_none());
+    assert!(handle("random2.example.com", RecordType::A)
+        .unwrap()
+        .header
+        .has_flag(FLAG_TRUNCATED));
+    Ok(())
+}
+
+/// Validate that zones without an SOA record are rejected.
+#[test]
+fn test_server_requires_soa() -> Result<(), DnsError> {
+    let zone = Zone::parse("$TTL 60\nwww A 192.0.2.1\n", "example.com")?;
+    assert!(Server::new(vec![zone]).is_err());
+    Ok(())
+}
+
+/// Validate that zone files are reloaded only once they change, and kept when they break.
+#[test]
+fn test_server_zone_reload() -> Result<(), DnsError> {
+    let path = std::env::temp_dir().join(format!("toy_dns_zone_{}", std::process::id()));
+    let path = path.to_string_lossy().into_owned();
+    let zone = "$ORIGIN example.com.\n$TTL 60\n@ SOA ns1 hostmaster 1 3600 900 604800 60\n";
+    std::fs::write(&path, format!("{}www A 192.0.2.1\n", zone)).unwrap();
+
+    let server = Server::from_files(std::slice::from_ref(&path))?;
+    let request = test_request("www.example.com", RecordType::A);
+    assert_eq!(
+        server.answer(&request, &mut no_upstream()).answers[0].data,
+        [192, 0, 2, 1]
+    );
+    assert!(!server.reload_zones_if_changed()?);
+
+    // Zones held by a request being answered are not affected by a reload.
+    let in_flight = server.zones();
+
+    // Modification times may be coarse, so the change is dated well into the future.
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    std::fs::write(&path, format!("{}www A 192.0.2.2\n", zone)).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    assert!(server.reload_zones_if_changed()?);
+    assert_eq!(
+        server.answer(&request, &mut no_upstream()).answers[0].data,
+        [192, 0, 2, 2]
+    );
+    assert_eq!(in_flight[0].records[1].data, [192, 0, 2, 1]);
+
+    // A zone which cannot be parsed leaves the zones read before served.
+    std::fs::write(&path, "www A 192.0.2.3\n").unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(120))
+        .unwrap();
+    assert_eq!(server.reload_zones_if_changed(), Err(DnsError::ParseZone));
+    assert_eq!(
+        server.answer(&request, &mut no_upstream()).answers[0].data,
+        [192, 0, 2, 2]
+    );
+    std::fs::remove_file(&path).unwrap();
+    Ok(())
+}
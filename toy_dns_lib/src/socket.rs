@@ -1,19 +1,18 @@
 use crate::errors::DnsError;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::net::IpAddr;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
 
-pub trait Socket<T> {
-    /// Bind the socket to the provided address
-    ///
-    /// # Argument
-    /// * `addr`: The (local) address to bind to.
-    fn bind(addr: &str) -> Result<T, DnsError>
-    where
-        Self: Sized;
-
+/// A transport on which DNS messages are exchanged, such as a UDP socket or a pool of TLS
+/// sessions. The trait is object safe, so that code can take any transport as
+/// `&mut dyn DnsTransport`; each transport is created with a `bind()` of its own.
+pub trait DnsTransport {
     /// Send the given buffer to the provided address. Upon success will return the size of the
     /// sent buffer.
     ///
@@ -27,114 +26,410 @@ pub trait Socket<T> {
     /// # Argument
     /// * `buf`: The buffer to populate when data is received.
     fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError>;
+
+    /// Whether the socket keeps connections to servers open, like TCP does. Connection-oriented
+    /// sockets can honor the server's idle timeout advertised with edns-tcp-keepalive.
+    fn is_connection_oriented(&self) -> bool {
+        false
+    }
+
+    /// Keep the connection to the given server open for reuse for the given duration. A timeout of
+    /// `None` closes the connection. Connectionless sockets ignore this.
+    ///
+    /// # Arguments
+    /// * `addr`: The address of the server.
+    /// * `timeout`: How long the connection may remain idle.
+    fn set_idle_timeout(&mut self, _addr: &str, _timeout: Option<Duration>) {}
+
+    /// Send plain DNS over TCP rather than UDP from now on, or over UDP again, e.g. to retry a
+    /// query whose response was truncated. Sockets which cannot switch ignore this, so that
+    /// `is_connection_oriented()` tells whether the switch happened.
+    ///
+    /// # Argument
+    /// * `_tcp`: Whether to use TCP.
+    fn set_tcp(&mut self, _tcp: bool) {}
 }
 
-impl Socket<UdpSocket> for UdpSocket {
-    fn bind(addr: &str) -> Result<UdpSocket, DnsError>
-    where
-        Self: Sized,
-    {
-        let new_socket = UdpSocket::bind(addr);
-        match new_socket {
-            Ok(socket) => return Ok(socket),
-            Err(_) => return Err(DnsError::SocketBind),
-        }
+/// The unspecified local address of the family of the given server, to bind the socket queries
+/// are sent to it from. IPv4 is assumed when the server is not known up front.
+///
+/// # Argument
+/// * `server`: The server queries are sent to, if known.
+pub fn local_address(server: Option<SocketAddr>) -> &'static str {
+    match server {
+        Some(SocketAddr::V6(_)) => "[::]:0",
+        _ => "0.0.0.0:0",
+    }
+}
+
+/// Bind a UDP socket to the provided address.
+///
+/// # Argument
+/// * `addr`: The (local) address to bind to.
+pub fn bind_udp(addr: &str) -> Result<UdpSocket, DnsError> {
+    let new_socket = UdpSocket::bind(addr);
+    match new_socket {
+        Ok(socket) => Ok(socket),
+        Err(error) => Err(DnsError::SocketBind(Some(error))),
+    }
+}
+
+/// Bind a UDP socket to a network interface with SO_BINDTODEVICE, so that its datagrams leave
+/// through that interface whatever the routing table says, e.g. to query through a VPN. Only
+/// supported on Linux, where older kernels require CAP_NET_RAW.
+///
+/// # Arguments
+/// * `socket`: The socket to bind.
+/// * `device`: The name of the interface, e.g. "eth0".
+#[cfg(target_os = "linux")]
+pub fn bind_to_device(socket: &UdpSocket, device: &str) -> Result<(), DnsError> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: The option value points at `device.len()` bytes which outlive the call.
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            device.as_ptr() as *const libc::c_void,
+            device.len() as libc::socklen_t,
+        )
+    };
+    match result {
+        0 => Ok(()),
+        _ => Err(DnsError::BindToDevice),
     }
+}
+
+/// Bind a UDP socket to a network interface. SO_BINDTODEVICE only exists on Linux, so this
+/// always fails elsewhere.
+///
+/// # Arguments
+/// * `socket`: The socket to bind.
+/// * `device`: The name of the interface.
+#[cfg(not(target_os = "linux"))]
+pub fn bind_to_device(_socket: &UdpSocket, _device: &str) -> Result<(), DnsError> {
+    Err(DnsError::BindToDevice)
+}
 
+impl DnsTransport for UdpSocket {
     fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        // A socket bound to an IPv6 address only reaches IPv4 servers at their IPv4-mapped
+        // addresses.
+        if let (Ok(SocketAddr::V4(target)), Ok(SocketAddr::V6(_))) =
+            (addr.parse::<SocketAddr>(), self.local_addr())
+        {
+            let mapped = SocketAddr::new(IpAddr::V6(target.ip().to_ipv6_mapped()), target.port());
+            return match self.send_to(buf, mapped) {
+                Ok(size) => Ok(size),
+                Err(error) => Err(DnsError::SocketSend(Some(error))),
+            };
+        }
         match self.send_to(buf, addr) {
             Ok(size) => Ok(size),
-            Err(_) => Err(DnsError::SocketSend),
+            Err(error) => Err(DnsError::SocketSend(Some(error))),
         }
     }
 
     fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
         match self.recv_from(buf) {
             Ok(size_and_addr) => Ok(size_and_addr),
-            Err(_) => Err(DnsError::SocketRead),
+            Err(error) => Err(DnsError::SocketRead(Some(error))),
         }
     }
 }
 
 /// Key used to match send calls with the right preconfigured response
-#[derive(Clone, Eq, PartialEq, Hash, Copy)]
-pub struct MockKey<'a> {
-    pub query_bytes: &'a [u8],
-    pub server_ip: &'a str,
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MockKey {
+    pub query_bytes: Vec<u8>,
+    pub server_ip: String,
+}
+
+/// Data with which to configure MockSocket. A response without data stands for a datagram which
+/// was lost: recv_from() fails as if it had timed out.
+#[derive(Clone, Debug)]
+pub struct MockData {
+    pub data: Vec<u8>,
+}
+
+/// A fault MockSocket injects into a response, to exercise how failures are handled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockFault {
+    /// Wait this long before delivering the response.
+    Latency(Duration),
+
+    /// Lose the response: recv_from() fails as if it had timed out.
+    Drop,
+
+    /// Deliver only the first given number of bytes of the response.
+    Truncate(usize),
+
+    /// Flip every bit of the byte at the given offset of the response, if it has one.
+    Corrupt(usize),
+
+    /// Deliver the response from the given address rather than from the server queried.
+    WrongSource(SocketAddr),
+}
+
+/// The responses preconfigured for a key, and how many times the key was sent.
+#[derive(Default)]
+struct MockResponses {
+    /// The responses along with their faults, served in turn. The last one is served again once
+    /// all have been.
+    responses: Vec<(MockData, Vec<MockFault>)>,
+
+    /// How many times the key was sent.
+    sends: usize,
+}
+
+/// A response on its way to recv_from(), with its faults applied.
+pub(crate) struct MockReply {
+    /// The response, empty if it was lost.
+    data: Vec<u8>,
+
+    /// Where the response comes from.
+    source: SocketAddr,
+
+    /// How long to wait before delivering the response.
+    pub(crate) latency: Duration,
 }
 
-/// Data with which to configure MockSocket.
-pub struct MockData<'a> {
-    pub data: &'a [u8],
+impl MockReply {
+    /// Copy the response into the buffer, as recv_from() returns it.
+    ///
+    /// # Argument
+    /// * `buf`: The buffer to populate with the response.
+    pub(crate) fn deliver(self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        // A lost datagram fails the read as a UDP socket whose read timed out does.
+        if self.data.is_empty() {
+            return Err(DnsError::SocketRead(Some(ErrorKind::WouldBlock.into())));
+        }
+
+        // Like a datagram, a response longer than the buffer is cut short.
+        let size = self.data.len().min(buf.len());
+        buf[..size].copy_from_slice(&self.data[..size]);
+        Ok((size, self.source))
+    }
 }
 
-/// A socket object that vendors preconfigured responses.
-pub struct MockSocket<'a> {
+/// A socket object that vendors preconfigured responses. The socket owns its responses, so that
+/// they can be built at runtime, e.g. by serializing packets. Each key may have a sequence of
+/// responses, e.g. to answer a retry differently from the first attempt, and faults may be
+/// injected into each response (see `MockFault`).
+#[derive(Default)]
+pub struct MockSocket {
     /// The map of all preconfigured responses for this mock socket.
-    response_data: HashMap<&'a MockKey<'a>, &'a MockData<'a>>,
+    response_data: HashMap<MockKey, MockResponses>,
 
-    /// The next response to serve when socket gets recv_from() called.
-    next_response: Option<&'a MockData<'a>>,
+    /// The next response to serve when socket gets recv_from() called. Like a datagram, it is
+    /// only received once.
+    next_response: RefCell<Option<MockReply>>,
 }
 
-impl<'a> MockSocket<'a> {
-    /// Preconfigure the mock socket with data
+impl MockSocket {
+    /// Create a mock socket without any preconfigured responses. The address is ignored.
+    ///
+    /// # Argument
+    /// * `_addr`: The (local) address to bind to.
+    pub fn bind(_addr: &str) -> Result<MockSocket, DnsError> {
+        Ok(MockSocket::default())
+    }
+
+    /// Preconfigure the mock socket with data, replacing any responses registered before. A key
+    /// given more than once is answered with each of its responses in turn.
     ///
     /// # Argument
     /// * `data`: The data with which to configure the mock socket.
-    pub fn register_response_data(&mut self, data: &'a [(MockKey, MockData)]) {
+    pub fn register_response_data(&mut self, data: impl IntoIterator<Item = (MockKey, MockData)>) {
         self.response_data = HashMap::new();
         for (key, value) in data {
-            self.response_data.insert(key, value);
+            self.register_response(key, value);
         }
     }
-}
 
-impl Default for MockSocket<'_> {
-    fn default() -> Self {
-        MockSocket {
-            response_data: HashMap::new(),
-            next_response: None,
+    /// Add a response to those the mock socket is preconfigured with. If the key already has
+    /// responses, this one is served after them.
+    ///
+    /// # Arguments
+    /// * `key`: The query and server to respond to.
+    /// * `data`: The response.
+    pub fn register_response(&mut self, key: MockKey, data: MockData) {
+        self.register_faulty_response(key, data, vec![]);
+    }
+
+    /// Add a response to those the mock socket is preconfigured with, injecting the given faults
+    /// into it when it is served. If the key already has responses, this one is served after
+    /// them.
+    ///
+    /// # Arguments
+    /// * `key`: The query and server to respond to.
+    /// * `data`: The response.
+    /// * `faults`: The faults to inject into the response, applied in order.
+    pub fn register_faulty_response(
+        &mut self,
+        key: MockKey,
+        data: MockData,
+        faults: Vec<MockFault>,
+    ) {
+        self.response_data
+            .entry(key)
+            .or_default()
+            .responses
+            .push((data, faults));
+    }
+
+    /// How many times the given query was sent to the given server, e.g. to assert how many
+    /// attempts a resolution took. Sends which matched no preconfigured response are not
+    /// counted.
+    ///
+    /// # Argument
+    /// * `key`: The query and server.
+    pub fn send_count(&self, key: &MockKey) -> usize {
+        match self.response_data.get(key) {
+            Some(responses) => responses.sends,
+            None => 0,
         }
     }
-}
 
-impl Socket<MockSocket<'_>> for MockSocket<'_> {
-    fn bind(_addr: &str) -> Result<MockSocket<'static>, DnsError>
-    where
-        Self: Sized,
-    {
-        Ok(MockSocket::default())
+    /// Take the response queued by the last send, for recv_from() to deliver once its latency
+    /// has elapsed. Fails if there is none.
+    pub(crate) fn take_reply(&self) -> Result<MockReply, DnsError> {
+        match self.next_response.take() {
+            Some(reply) => Ok(reply),
+            None => Err(DnsError::SocketRead(None)),
+        }
     }
+}
 
+impl DnsTransport for MockSocket {
     fn send<'a>(&'a mut self, buf: &[u8], addr: &'a str) -> Result<usize, DnsError> {
         let key = MockKey {
-            query_bytes: buf,
-            server_ip: addr,
+            query_bytes: buf.to_vec(),
+            server_ip: addr.to_owned(),
         };
 
         // Look up the request in the preconfigured data and get the associated response, if any.
-        let Some(response) = self.response_data.get(&key) else {
-            return Err(DnsError::SocketSend);
+        let Some(responses) = self.response_data.get_mut(&key) else {
+            return Err(DnsError::SocketSend(None));
+        };
+        let Some((response, faults)) = responses
+            .responses
+            .get(responses.sends)
+            .or(responses.responses.last())
+        else {
+            return Err(DnsError::SocketSend(None));
         };
 
+        // Responses come from the server queried, unless a fault says otherwise.
+        let zero_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+        let mut reply = MockReply {
+            data: response.data.clone(),
+            source: addr.parse().unwrap_or(zero_addr),
+            latency: Duration::ZERO,
+        };
+        for fault in faults {
+            match fault {
+                MockFault::Latency(latency) => reply.latency += *latency,
+                MockFault::Drop => reply.data.clear(),
+                MockFault::Truncate(size) => reply.data.truncate(*size),
+                MockFault::Corrupt(offset) => {
+                    if let Some(byte) = reply.data.get_mut(*offset) {
+                        *byte = !*byte;
+                    }
+                }
+                MockFault::WrongSource(source) => reply.source = *source,
+            }
+        }
+
         // Next time recv_from() is called on the mock socket, it will return the response from
         // the lookup above.
-        self.next_response = Some(*response);
+        self.next_response.replace(Some(reply));
+        responses.sends += 1;
 
         Ok(buf.len())
     }
 
     fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
-        let Some(response) = self.next_response else {
-            return Err(DnsError::SocketRead);
-        };
+        let reply = self.take_reply()?;
+        thread::sleep(reply.latency);
+        reply.deliver(buf)
+    }
+}
 
-        buf.copy_from_slice(response.data);
+/// A transport which passes everything on to another one, a UDP socket by default, while
+/// recording each exchange: the query, the server it was sent to and the response. Recorded
+/// exchanges serve as mock socket responses, e.g. written as a fixture with
+/// `mock_data::to_fixture()`. Responses which never arrive are recorded without data, so that
+/// mock sockets lose them too.
+pub struct RecordingSocket<T: DnsTransport = UdpSocket> {
+    /// The transport to pass everything on to.
+    inner: T,
 
-        // Address & port doesn't matter for the time being as the result is not used by toy_dns.
-        let zero_addr = IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0));
-        return Ok((0, SocketAddr::new(zero_addr, 0)));
+    /// The query last sent and where it was sent to, until its response is received.
+    pending: RefCell<Option<MockKey>>,
+
+    /// The exchanges recorded so far.
+    exchanges: RefCell<Vec<(MockKey, MockData)>>,
+}
+
+impl RecordingSocket {
+    /// Bind a UDP socket to the provided address, and record the exchanges on it.
+    ///
+    /// # Argument
+    /// * `addr`: The (local) address to bind to.
+    pub fn bind(addr: &str) -> Result<RecordingSocket, DnsError> {
+        Ok(RecordingSocket::new(bind_udp(addr)?))
+    }
+}
+
+impl<T: DnsTransport> RecordingSocket<T> {
+    /// Record the exchanges on the given transport.
+    ///
+    /// # Argument
+    /// * `inner`: The transport to pass everything on to.
+    pub fn new(inner: T) -> RecordingSocket<T> {
+        RecordingSocket {
+            inner,
+            pending: RefCell::new(None),
+            exchanges: RefCell::new(vec![]),
+        }
+    }
+
+    /// The exchanges recorded, in the order they took place.
+    pub fn into_exchanges(self) -> Vec<(MockKey, MockData)> {
+        self.exchanges.into_inner()
+    }
+}
+
+impl<T: DnsTransport> DnsTransport for RecordingSocket<T> {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        let size = self.inner.send(buf, addr)?;
+        self.pending.replace(Some(MockKey {
+            query_bytes: buf.to_vec(),
+            server_ip: addr.to_owned(),
+        }));
+        Ok(size)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        let received = self.inner.recv_from(buf);
+        if let Some(key) = self.pending.take() {
+            let data = match &received {
+                Ok((size, _)) => buf[..*size].to_vec(),
+                Err(_) => vec![],
+            };
+            self.exchanges.borrow_mut().push((key, MockData { data }));
+        }
+        received
+    }
+
+    fn is_connection_oriented(&self) -> bool {
+        self.inner.is_connection_oriented()
+    }
+
+    fn set_idle_timeout(&mut self, addr: &str, timeout: Option<Duration>) {
+        self.inner.set_idle_timeout(addr, timeout);
     }
 }
 
@@ -155,20 +450,24 @@ fn test_mock_socket_send_and_receive_preconfigured_data() -> Result<(), DnsError
 
     let mut socket = MockSocket::bind("")?;
 
-    let data = &[
+    let data = [
         (
             MockKey {
-                query_bytes: query_1,
-                server_ip: addr_1,
+                query_bytes: query_1.to_vec(),
+                server_ip: addr_1.to_string(),
+            },
+            MockData {
+                data: data_1.to_vec(),
             },
-            MockData { data: data_1 },
         ),
         (
             MockKey {
-                query_bytes: query_2,
-                server_ip: addr_2,
+                query_bytes: query_2.to_vec(),
+                server_ip: addr_2.to_string(),
+            },
+            MockData {
+                data: data_2.to_vec(),
             },
-            MockData { data: data_2 },
         ),
     ];
 
@@ -196,12 +495,14 @@ fn test_mock_socket_send_unrecognized_data() -> Result<(), DnsError> {
 
     let mut socket = MockSocket::bind("")?;
 
-    let data = &[(
+    let data = [(
         MockKey {
-            query_bytes: query_1,
-            server_ip: addr_1,
+            query_bytes: query_1.to_vec(),
+            server_ip: addr_1.to_string(),
+        },
+        MockData {
+            data: data_1.to_vec(),
         },
-        MockData { data: data_1 },
     )];
 
     socket.register_response_data(data);
@@ -223,12 +524,14 @@ fn test_mock_socket_send_unrecognized_query() -> Result<(), DnsError> {
 
     let mut socket = MockSocket::bind("")?;
 
-    let data = &[(
+    let data = [(
         MockKey {
-            query_bytes: query_1,
-            server_ip: addr_1,
+            query_bytes: query_1.to_vec(),
+            server_ip: addr_1.to_string(),
+        },
+        MockData {
+            data: data_1.to_vec(),
         },
-        MockData { data: data_1 },
     )];
 
     socket.register_response_data(data);
@@ -250,12 +553,14 @@ fn test_mock_socket_send_unrecognized_server_ip() -> Result<(), DnsError> {
 
     let mut socket = MockSocket::bind("")?;
 
-    let data = &[(
+    let data = [(
         MockKey {
-            query_bytes: query_1,
-            server_ip: addr_1,
+            query_bytes: query_1.to_vec(),
+            server_ip: addr_1.to_string(),
+        },
+        MockData {
+            data: data_1.to_vec(),
         },
-        MockData { data: data_1 },
     )];
 
     socket.register_response_data(data);
@@ -276,3 +581,225 @@ fn test_mock_socket_receive_without_preconfiguring() -> Result<(), DnsError> {
 
     Ok(())
 }
+
+/// Validate binding a UDP socket to the loopback interface, and that unknown interfaces are
+/// refused.
+#[cfg(target_os = "linux")]
+#[test]
+fn test_bind_to_device() -> Result<(), DnsError> {
+    let socket = bind_udp("127.0.0.1:0")?;
+    assert_eq!(
+        bind_to_device(&socket, "no-such-if0"),
+        Err(DnsError::BindToDevice)
+    );
+    // Older kernels only let privileged processes bind to an interface.
+    if bind_to_device(&socket, "lo").is_ok() {
+        let server = bind_udp("127.0.0.1:0")?;
+        let server_address = server
+            .local_addr()
+            .map_err(|error| DnsError::SocketBind(Some(error)))?;
+        let mut socket = socket;
+        DnsTransport::send(&mut socket, b"query", &server_address.to_string())?;
+        let mut buf = [0; 16];
+        let (size, _) = DnsTransport::recv_from(&server, &mut buf)?;
+        assert_eq!(&buf[..size], b"query");
+    }
+    Ok(())
+}
+
+/// Validate that socket failures keep the I/O error behind them, e.g. a receive timing out.
+#[test]
+fn test_socket_error_source() -> Result<(), DnsError> {
+    use std::error::Error;
+
+    let socket = bind_udp("127.0.0.1:0")?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(10)))
+        .map_err(|error| DnsError::SocketBind(Some(error)))?;
+    let mut buf = [0; 16];
+    let error = DnsTransport::recv_from(&socket, &mut buf).unwrap_err();
+    assert_eq!(error, DnsError::SocketRead(None));
+    let kind = error.io_error().map(|error| error.kind());
+    assert!(matches!(
+        kind,
+        Some(ErrorKind::WouldBlock | ErrorKind::TimedOut)
+    ));
+    assert!(error.source().is_some());
+    assert!(error
+        .to_string()
+        .starts_with("SocketRead: Could not read data from socket: "));
+    Ok(())
+}
+
+/// Ensure responses registered one at a time are added to those MockSocket already has.
+#[test]
+fn test_mock_socket_register_response() -> Result<(), DnsError> {
+    let response = |query: &[u8], server_ip: &str, data: u8| {
+        (
+            MockKey {
+                query_bytes: query.to_vec(),
+                server_ip: server_ip.to_owned(),
+            },
+            MockData {
+                data: vec![data; 1024],
+            },
+        )
+    };
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data([response(&[12, 34], "1.2.3.4:0", 0xAB)]);
+    let (key, data) = response(&[56, 78], "5.6.7.8:0", 0xEF);
+    socket.register_response(key, data);
+
+    let mut buf = [0; 1024];
+    socket.send(&[12, 34], "1.2.3.4:0")?;
+    socket.recv_from(&mut buf)?;
+    assert_eq!(buf, [0xAB; 1024]);
+    socket.send(&[56, 78], "5.6.7.8:0")?;
+    socket.recv_from(&mut buf)?;
+    assert_eq!(buf, [0xEF; 1024]);
+    Ok(())
+}
+
+/// Ensure MockSocket serves the responses of a key in turn, repeating the last one, and counts
+/// how many times the key was sent.
+#[test]
+fn test_mock_socket_sequenced_responses() -> Result<(), DnsError> {
+    let key = MockKey {
+        query_bytes: vec![12, 34],
+        server_ip: "1.2.3.4:0".to_owned(),
+    };
+    let response = |data: Vec<u8>| (key.clone(), MockData { data });
+
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data([
+        response(vec![]),
+        response(vec![0xAB; 1024]),
+        response(vec![0xEF; 1024]),
+    ]);
+    assert_eq!(socket.send_count(&key), 0);
+
+    // The first response is lost.
+    let mut buf = [0; 1024];
+    socket.send(&[12, 34], "1.2.3.4:0")?;
+    assert_eq!(socket.recv_from(&mut buf), Err(DnsError::SocketRead(None)));
+    socket.send(&[12, 34], "1.2.3.4:0")?;
+    socket.recv_from(&mut buf)?;
+    assert_eq!(buf, [0xAB; 1024]);
+    for _ in 0..2 {
+        socket.send(&[12, 34], "1.2.3.4:0")?;
+        socket.recv_from(&mut buf)?;
+        assert_eq!(buf, [0xEF; 1024]);
+    }
+    assert_eq!(socket.send_count(&key), 4);
+
+    assert!(socket.send(&[56, 78], "1.2.3.4:0").is_err());
+    let unknown = MockKey {
+        query_bytes: vec![56, 78],
+        ..key.clone()
+    };
+    assert_eq!(socket.send_count(&unknown), 0);
+    Ok(())
+}
+
+/// Ensure MockSocket injects the faults registered with a response.
+#[test]
+fn test_mock_socket_faults() -> Result<(), DnsError> {
+    use std::time::Instant;
+
+    let key = MockKey {
+        query_bytes: vec![12, 34],
+        server_ip: "1.2.3.4:53".to_owned(),
+    };
+    let data = MockData {
+        data: vec![0xAB; 16],
+    };
+    let wrong_source: SocketAddr = "5.6.7.8:53".parse().unwrap();
+
+    let mut socket = MockSocket::bind("")?;
+    let faults = [
+        vec![],
+        vec![MockFault::Drop],
+        vec![MockFault::Truncate(4)],
+        vec![MockFault::Corrupt(1), MockFault::Corrupt(64)],
+        vec![MockFault::WrongSource(wrong_source)],
+        vec![MockFault::Latency(Duration::from_millis(20))],
+    ];
+    for faults in faults {
+        socket.register_faulty_response(key.clone(), data.clone(), faults);
+    }
+
+    let mut buf = [0; 32];
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    let (size, source) = socket.recv_from(&mut buf)?;
+    assert_eq!((size, source), (16, "1.2.3.4:53".parse().unwrap()));
+
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    assert_eq!(socket.recv_from(&mut buf), Err(DnsError::SocketRead(None)));
+
+    buf = [0; 32];
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    assert_eq!(socket.recv_from(&mut buf)?.0, 4);
+    assert_eq!(buf[..5], [0xAB, 0xAB, 0xAB, 0xAB, 0]);
+
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    socket.recv_from(&mut buf)?;
+    assert_eq!(buf[..3], [0xAB, 0x54, 0xAB]);
+
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    assert_eq!(socket.recv_from(&mut buf)?.1, wrong_source);
+
+    socket.send(&[12, 34], "1.2.3.4:53")?;
+    let started_at = Instant::now();
+    socket.recv_from(&mut buf)?;
+    assert!(started_at.elapsed() >= Duration::from_millis(20));
+    Ok(())
+}
+
+/// Ensure RecordingSocket records what goes through it, and that its recording replays on a
+/// MockSocket.
+#[test]
+fn test_recording_socket() -> Result<(), DnsError> {
+    let key = MockKey {
+        query_bytes: vec![12, 34],
+        server_ip: "1.2.3.4:53".to_owned(),
+    };
+    let mut socket = MockSocket::bind("")?;
+    socket.register_faulty_response(
+        key.clone(),
+        MockData {
+            data: vec![0xAB; 4],
+        },
+        vec![MockFault::Drop],
+    );
+    socket.register_response(
+        key.clone(),
+        MockData {
+            data: vec![0xCD; 4],
+        },
+    );
+
+    let mut recording = RecordingSocket::new(socket);
+    let mut buf = [0; 8];
+    recording.send(&[12, 34], "1.2.3.4:53")?;
+    assert!(recording.recv_from(&mut buf).is_err());
+    recording.send(&[12, 34], "1.2.3.4:53")?;
+    assert_eq!(recording.recv_from(&mut buf)?.0, 4);
+    assert!(recording.send(&[56, 78], "1.2.3.4:53").is_err());
+
+    let exchanges = recording.into_exchanges();
+    assert_eq!(exchanges.len(), 2);
+    assert!(exchanges
+        .iter()
+        .all(|(recorded_key, _)| *recorded_key == key));
+    assert!(exchanges[0].1.data.is_empty());
+    assert_eq!(exchanges[1].1.data, [0xCD; 4]);
+
+    let mut replay = MockSocket::bind("")?;
+    replay.register_response_data(exchanges);
+    replay.send(&[12, 34], "1.2.3.4:53")?;
+    assert!(replay.recv_from(&mut buf).is_err());
+    replay.send(&[12, 34], "1.2.3.4:53")?;
+    assert_eq!(replay.recv_from(&mut buf)?.0, 4);
+    Ok(())
+}
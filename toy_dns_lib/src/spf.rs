@@ -0,0 +1,454 @@
+use crate::errors::DnsError;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use log::info;
+use std::fmt;
+
+/// The version term every SPF record starts with.
+const SPF_VERSION: &str = "v=spf1";
+
+/// Most terms causing DNS lookups a policy may hold, counting those of the policies it includes
+/// (RFC 7208, section 4.6.4).
+pub const MAX_SPF_LOOKUPS: usize = 10;
+
+/// The mechanisms of RFC 7208, section 5.
+const MECHANISMS: [&str; 8] = ["all", "include", "a", "mx", "ptr", "ip4", "ip6", "exists"];
+
+/// The mechanisms and modifiers which cause DNS lookups when evaluated.
+const LOOKUP_TERMS: [&str; 6] = ["include", "a", "mx", "ptr", "exists", "redirect"];
+
+/// What a mechanism yields when it matches (RFC 7208, section 4.6.2).
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Qualifier {
+    /// `+`, the default: the host is authorized.
+    Pass,
+
+    /// `-`: the host is not authorized.
+    Fail,
+
+    /// `~`: the host is probably not authorized.
+    SoftFail,
+
+    /// `?`: nothing is said about the host.
+    Neutral,
+}
+
+impl Qualifier {
+    /// The qualifier as written before a mechanism.
+    pub fn symbol(&self) -> char {
+        match self {
+            Self::Pass => '+',
+            Self::Fail => '-',
+            Self::SoftFail => '~',
+            Self::Neutral => '?',
+        }
+    }
+}
+
+/// A term of an SPF record.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SpfTerm {
+    /// A mechanism, matching hosts, e.g. `-all` or `ip4:192.0.2.0/24`.
+    Mechanism {
+        /// What the mechanism yields when it matches.
+        qualifier: Qualifier,
+
+        /// The name of the mechanism, lowercased, e.g. `include`.
+        name: String,
+
+        /// What follows the name: the domain after `:` and the prefix lengths after `/`, e.g.
+        /// `example.com` or `/24`.
+        value: Option<String>,
+    },
+
+    /// A modifier, e.g. `redirect=_spf.example.com`.
+    Modifier {
+        /// The name of the modifier, lowercased.
+        name: String,
+
+        /// The value after `=`.
+        value: String,
+    },
+}
+
+impl SpfTerm {
+    /// Parse a term.
+    ///
+    /// # Argument
+    /// * `term`: The term, e.g. `~all`.
+    fn parse(term: &str) -> Result<SpfTerm, DnsError> {
+        let name_end = term
+            .find(|character: char| {
+                !character.is_ascii_alphanumeric() && !"-_.".contains(character)
+            })
+            .unwrap_or(term.len());
+        if let Some(value) = term[name_end..].strip_prefix('=') {
+            if name_end == 0 {
+                return Err(DnsError::Spf);
+            }
+            return Ok(SpfTerm::Modifier {
+                name: term[..name_end].to_ascii_lowercase(),
+                value: value.to_owned(),
+            });
+        }
+
+        let (qualifier, mechanism) = match term.chars().next() {
+            Some('+') => (Qualifier::Pass, &term[1..]),
+            Some('-') => (Qualifier::Fail, &term[1..]),
+            Some('~') => (Qualifier::SoftFail, &term[1..]),
+            Some('?') => (Qualifier::Neutral, &term[1..]),
+            _ => (Qualifier::Pass, term),
+        };
+        let (name, value) = match mechanism.find([':', '/']) {
+            Some(position) => {
+                let value = mechanism[position..].strip_prefix(':');
+                (
+                    &mechanism[..position],
+                    Some(value.unwrap_or(&mechanism[position..]).to_owned()),
+                )
+            }
+            None => (mechanism, None),
+        };
+        let name = name.to_ascii_lowercase();
+        if !MECHANISMS.contains(&name.as_str()) || value.as_deref() == Some("") {
+            return Err(DnsError::Spf);
+        }
+        Ok(SpfTerm::Mechanism {
+            qualifier,
+            name,
+            value,
+        })
+    }
+
+    /// The name of the mechanism or modifier.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Mechanism { name, .. } | Self::Modifier { name, .. } => name,
+        }
+    }
+
+    /// Whether evaluating the term causes a DNS lookup, counting towards `MAX_SPF_LOOKUPS`.
+    pub fn needs_lookup(&self) -> bool {
+        LOOKUP_TERMS.contains(&self.name())
+    }
+}
+
+impl fmt::Display for SpfTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mechanism {
+                qualifier,
+                name,
+                value,
+            } => {
+                if *qualifier != Qualifier::Pass {
+                    write!(f, "{}", qualifier.symbol())?;
+                }
+                match value {
+                    Some(value) if value.starts_with('/') => write!(f, "{}{}", name, value),
+                    Some(value) => write!(f, "{}:{}", name, value),
+                    None => write!(f, "{}", name),
+                }
+            }
+            Self::Modifier { name, value } => write!(f, "{}={}", name, value),
+        }
+    }
+}
+
+/// An SPF record, which tells the hosts allowed to send mail for a domain (RFC 7208).
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpfRecord {
+    /// The terms after the version, in order.
+    pub terms: Vec<SpfTerm>,
+}
+
+impl SpfRecord {
+    /// Parse an SPF record.
+    ///
+    /// # Argument
+    /// * `text`: The text of the record, e.g. `v=spf1 mx -all`.
+    pub fn parse(text: &str) -> Result<SpfRecord, DnsError> {
+        let mut fields = text.split_ascii_whitespace();
+        if !fields
+            .next()
+            .is_some_and(|version| version.eq_ignore_ascii_case(SPF_VERSION))
+        {
+            return Err(DnsError::Spf);
+        }
+        let terms = fields.map(SpfTerm::parse).collect::<Result<Vec<_>, _>>()?;
+        Ok(SpfRecord { terms })
+    }
+
+    /// The SPF record among the TXT records of a domain, if any. More than one is an error.
+    ///
+    /// # Argument
+    /// * `texts`: The TXT records, e.g. from `Resolver::lookup_txt()`.
+    pub fn find(texts: &[String]) -> Result<Option<SpfRecord>, DnsError> {
+        let mut records = texts.iter().filter(|text| {
+            let version = text.split_ascii_whitespace().next().unwrap_or_default();
+            version.eq_ignore_ascii_case(SPF_VERSION)
+        });
+        match (records.next(), records.next()) {
+            (None, _) => Ok(None),
+            (Some(text), None) => Ok(Some(SpfRecord::parse(text)?)),
+            (Some(_), Some(_)) => Err(DnsError::Spf),
+        }
+    }
+
+    /// The domains whose policies this one includes, with `include:`.
+    pub fn includes(&self) -> Vec<&str> {
+        self.terms
+            .iter()
+            .filter_map(|term| match term {
+                SpfTerm::Mechanism {
+                    name,
+                    value: Some(value),
+                    ..
+                } if name == "include" => Some(value.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The domain whose policy applies when no mechanism matches, with `redirect=`.
+    pub fn redirect(&self) -> Option<&str> {
+        self.terms.iter().find_map(|term| match term {
+            SpfTerm::Modifier { name, value } if name == "redirect" => Some(value.as_str()),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for SpfRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", SPF_VERSION)?;
+        for term in &self.terms {
+            write!(f, " {}", term)?;
+        }
+        Ok(())
+    }
+}
+
+/// The SPF policy of a domain along with the policies it includes or redirects to, in the order
+/// of their terms.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpfPolicy {
+    /// The domain the policy was found at.
+    pub domain_name: String,
+
+    /// The SPF record of the domain.
+    pub record: SpfRecord,
+
+    /// The policies of the `include:` and `redirect=` targets. Targets with macros, e.g.
+    /// `%{i}._spf.example.com`, are left out as they depend on the mail being checked.
+    pub included: Vec<SpfPolicy>,
+}
+
+impl SpfPolicy {
+    /// Render the policy and those it includes as a tree, one record per line indented by depth.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        self.render_at(&mut output, 0);
+        output
+    }
+
+    /// Render the policy at the given depth.
+    ///
+    /// # Arguments
+    /// * `output`: Where to render the policy.
+    /// * `depth`: How many policies include this one on the way from the top.
+    fn render_at(&self, output: &mut String, depth: usize) {
+        output.push_str(&format!(
+            "{}{}: {}\n",
+            "  ".repeat(depth),
+            self.domain_name,
+            self.record
+        ));
+        for policy in &self.included {
+            policy.render_at(output, depth + 1);
+        }
+    }
+}
+
+/// Look up the SPF record of a domain.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TXT records of the domain with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_spf(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Option<SpfRecord>, DnsError> {
+    SpfRecord::find(&resolver.lookup_txt(socket, domain_name, rand_seed)?)
+}
+
+/// Look up the SPF policy of a domain and, recursively, those it includes or redirects to. A
+/// domain without an SPF record, or a policy needing more than `MAX_SPF_LOOKUPS` lookups in all,
+/// is an error, as it would be when checking mail.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TXT records of the domains with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn expand_spf(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<SpfPolicy, DnsError> {
+    let mut lookups = 0;
+    expand_counting(resolver, socket, domain_name, rand_seed, &mut lookups)
+}
+
+/// Expand the SPF policy of a domain, adding the lookups its terms cause to the given count.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the TXT records of the domains with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain.
+/// * `rand_seed`: The seed for RNG, if desired.
+/// * `lookups`: The lookups counted so far.
+fn expand_counting(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+    lookups: &mut usize,
+) -> Result<SpfPolicy, DnsError> {
+    let Some(record) = lookup_spf(resolver, socket, domain_name, rand_seed)? else {
+        info!("{} has no SPF record", domain_name);
+        return Err(DnsError::Spf);
+    };
+    *lookups += record
+        .terms
+        .iter()
+        .filter(|term| term.needs_lookup())
+        .count();
+    if *lookups > MAX_SPF_LOOKUPS {
+        info!("The SPF policy needs more than {} lookups", MAX_SPF_LOOKUPS);
+        return Err(DnsError::Spf);
+    }
+
+    let mut included = Vec::new();
+    for target in record.includes().into_iter().chain(record.redirect()) {
+        if target.contains('%') {
+            continue;
+        }
+        included.push(expand_counting(
+            resolver, socket, target, rand_seed, lookups,
+        )?);
+    }
+    Ok(SpfPolicy {
+        domain_name: domain_name.to_owned(),
+        record,
+        included,
+    })
+}
+
+/// Validate parsing of SPF records and of their terms.
+#[test]
+fn test_parsing_spf_record() -> Result<(), DnsError> {
+    let record =
+        SpfRecord::parse("v=spf1 +mx a/24 ip4:192.0.2.0/24 include:_spf.example.net ~all")?;
+    assert_eq!(
+        record.terms[1],
+        SpfTerm::Mechanism {
+            qualifier: Qualifier::Pass,
+            name: "a".to_owned(),
+            value: Some("/24".to_owned()),
+        }
+    );
+    assert_eq!(
+        record.terms[4],
+        SpfTerm::Mechanism {
+            qualifier: Qualifier::SoftFail,
+            name: "all".to_owned(),
+            value: None,
+        }
+    );
+    assert_eq!(record.includes(), ["_spf.example.net"]);
+    assert_eq!(record.redirect(), None);
+    assert_eq!(
+        record.to_string(),
+        "v=spf1 mx a/24 ip4:192.0.2.0/24 include:_spf.example.net ~all"
+    );
+    let lookups = record.terms.iter().filter(|term| term.needs_lookup());
+    assert_eq!(lookups.count(), 3);
+
+    let record = SpfRecord::parse("V=SPF1 redirect=_spf.example.com exp=explain.example.com")?;
+    assert_eq!(record.redirect(), Some("_spf.example.com"));
+    assert_eq!(record.terms[1].name(), "exp");
+
+    assert_eq!(SpfRecord::parse("v=spf10 -all"), Err(DnsError::Spf));
+    assert_eq!(SpfRecord::parse("v=spf1 -any"), Err(DnsError::Spf));
+    assert_eq!(SpfRecord::parse("v=spf1 include:"), Err(DnsError::Spf));
+    assert_eq!(SpfRecord::parse("v=spf1 =value"), Err(DnsError::Spf));
+    Ok(())
+}
+
+/// Validate finding the SPF record among the TXT records of a domain.
+#[test]
+fn test_finding_spf_record() -> Result<(), DnsError> {
+    let texts = [
+        "google-site-verification=abc".to_owned(),
+        "v=spf1 -all".to_owned(),
+    ];
+    assert_eq!(
+        SpfRecord::find(&texts)?,
+        Some(SpfRecord::parse("v=spf1 -all")?)
+    );
+    assert_eq!(SpfRecord::find(&texts[..1])?, None);
+    let texts = ["v=spf1 -all".to_owned(), "v=spf1 mx -all".to_owned()];
+    assert_eq!(SpfRecord::find(&texts), Err(DnsError::Spf));
+    Ok(())
+}
+
+/// Validate expanding the policies a domain includes, and the lookup limit.
+#[test]
+fn test_expanding_spf_policy() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let txt = |domain_name: &str, text: &str| {
+        MockResolution::new(domain_name, RecordType::TXT, 0)
+            .answer(&format!("{}. 300 IN TXT \"{}\"", domain_name, text))
+            .build()
+    };
+    let exchanges = [
+        txt(
+            "example.com",
+            "v=spf1 mx include:_spf.example.net redirect=_spf.example.com",
+        )?,
+        txt("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all")?,
+        txt(
+            "_spf.example.com",
+            "v=spf1 include:%{i}._ip.example.com ~all",
+        )?,
+        txt("loop.example.com", "v=spf1 include:loop.example.com")?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let policy = expand_spf(&resolver, &mut socket, "example.com", Some(0))?;
+    assert_eq!(
+        policy.render(),
+        "example.com: v=spf1 mx include:_spf.example.net redirect=_spf.example.com
+  _spf.example.net: v=spf1 ip4:192.0.2.0/24 -all
+  _spf.example.com: v=spf1 include:%{i}._ip.example.com ~all
+"
+    );
+
+    assert_eq!(
+        expand_spf(&resolver, &mut socket, "loop.example.com", Some(0)),
+        Err(DnsError::Spf)
+    );
+    Ok(())
+}
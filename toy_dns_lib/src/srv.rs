@@ -0,0 +1,259 @@
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::record_name::RecordName;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use log::warn;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::io::Cursor;
+use std::net::SocketAddr;
+
+/// An SRV record: a server of a service (RFC 2782).
+#[derive(Debug, PartialEq, Clone)]
+pub struct SrvRecord {
+    /// The priority of the server, lower ones being tried first.
+    pub priority: u16,
+
+    /// The share of the connections the server should get among those of the same priority.
+    pub weight: u16,
+
+    /// The port the service listens on.
+    pub port: u16,
+
+    /// The host name of the server, without a trailing dot. `.` stands for no server at all.
+    pub target: String,
+}
+
+impl SrvRecord {
+    /// Parse the data of an SRV record, as decompressed when the record was read.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<SrvRecord, DnsError> {
+        let Some(numbers) = data.get(..6) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let number = |index: usize| u16::from_be_bytes([numbers[index], numbers[index + 1]]);
+        let mut cursor = Cursor::new(&data[6..]);
+        let target = RecordName::read_and_advance(&mut cursor)?;
+        let Ok(target) = String::from_utf8(target) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+        Ok(SrvRecord {
+            priority: number(0),
+            weight: number(2),
+            port: number(4),
+            target: match target.is_empty() {
+                true => ".".to_owned(),
+                false => target,
+            },
+        })
+    }
+}
+
+/// The name holding the SRV records of a service: `_service._protocol.domain`.
+///
+/// # Arguments
+/// * `service`: The service, e.g. `xmpp-client`, with or without its leading underscore.
+/// * `protocol`: The protocol, e.g. `tcp`, with or without its leading underscore.
+/// * `domain_name`: The domain offering the service, e.g. `example.com`.
+pub fn srv_name(service: &str, protocol: &str, domain_name: &str) -> String {
+    format!(
+        "_{}._{}.{}",
+        service.trim_start_matches('_'),
+        protocol.trim_start_matches('_'),
+        domain_name.trim_end_matches('.')
+    )
+}
+
+/// Order SRV records the way clients should try them (RFC 2782, "Usage rules"): by ascending
+/// priority, and within a priority at random, each record being picked next in proportion to its
+/// weight. Records of weight 0 are only given a slim chance of being picked before the others.
+///
+/// # Arguments
+/// * `records`: The SRV records of a service.
+/// * `rng`: The random number generator picking among records of the same priority.
+pub fn order_srv_records(mut records: Vec<SrvRecord>, rng: &mut impl Rng) -> Vec<SrvRecord> {
+    records.sort_by_key(|record| (record.priority, record.weight != 0));
+
+    let mut ordered = Vec::with_capacity(records.len());
+    while let Some(priority) = records.first().map(|record| record.priority) {
+        let end = records
+            .iter()
+            .position(|record| record.priority != priority)
+            .unwrap_or(records.len());
+        let mut group: Vec<SrvRecord> = records.drain(..end).collect();
+        while !group.is_empty() {
+            let total: u32 = group.iter().map(|record| record.weight as u32).sum();
+            let pick = rng.gen_range(0..=total);
+            let mut running_sum = 0;
+            let index = group
+                .iter()
+                .position(|record| {
+                    running_sum += record.weight as u32;
+                    running_sum >= pick
+                })
+                .unwrap_or(0);
+            ordered.push(group.remove(index));
+        }
+    }
+    ordered
+}
+
+/// Resolve a service to the addresses to connect to, in order: the targets of its SRV records
+/// ordered by `order_srv_records()`, each followed by its addresses as sorted by
+/// `Resolver::lookup_ip()`. Targets which cannot be resolved are left out, unless none can. Empty
+/// when the name has no SRV records, or when the service is decidedly not available there (a
+/// single record with target `.`).
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the SRV records and the addresses with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `service`: The service, e.g. `xmpp-client`.
+/// * `protocol`: The protocol, e.g. `tcp`.
+/// * `domain_name`: The domain offering the service, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_service(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    service: &str,
+    protocol: &str,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Vec<SocketAddr>, DnsError> {
+    let name = srv_name(service, protocol, domain_name);
+    let packet = resolver.resolve(socket, &name, RecordType::SRV, rand_seed)?;
+    let records = packet
+        .answers
+        .iter()
+        .filter(|record| record.r_type == RecordType::SRV)
+        .map(|record| SrvRecord::from_data(&record.data))
+        .collect::<Result<Vec<SrvRecord>, DnsError>>()?;
+    if let [record] = records.as_slice() {
+        if record.target == "." {
+            return Ok(vec![]);
+        }
+    }
+
+    let records = match rand_seed {
+        None => order_srv_records(records, &mut rand::thread_rng()),
+        Some(value) => order_srv_records(records, &mut ChaCha8Rng::seed_from_u64(value as u64)),
+    };
+    let mut addresses = Vec::new();
+    let mut failure = None;
+    for record in records.iter().filter(|record| record.target != ".") {
+        match resolver.lookup_ip(socket, &record.target, rand_seed) {
+            Ok(target_addresses) => addresses.extend(
+                target_addresses
+                    .into_iter()
+                    .map(|address| SocketAddr::new(address, record.port)),
+            ),
+            Err(error) => {
+                warn!(
+                    "Could not resolve {}, the target of {}",
+                    record.target, name
+                );
+                failure = failure.or(Some(error));
+            }
+        }
+    }
+    if let (true, Some(error)) = (addresses.is_empty(), failure) {
+        return Err(error);
+    }
+    Ok(addresses)
+}
+
+/// An SRV record with the given priority, weight and target.
+#[cfg(test)]
+fn srv(priority: u16, weight: u16, target: &str) -> SrvRecord {
+    SrvRecord {
+        priority,
+        weight,
+        port: 5222,
+        target: target.to_owned(),
+    }
+}
+
+/// Validate that records are ordered by priority, and by weight at random within a priority.
+#[test]
+fn test_ordering_srv_records() {
+    let records = vec![
+        srv(20, 0, "backup"),
+        srv(10, 60, "heavy"),
+        srv(10, 0, "idle"),
+        srv(10, 20, "light"),
+    ];
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut heavy_first = 0;
+    for _ in 0..1000 {
+        let ordered = order_srv_records(records.clone(), &mut rng);
+        let targets: Vec<&str> = ordered
+            .iter()
+            .map(|record| record.target.as_str())
+            .collect();
+        assert_eq!(targets.len(), 4);
+        assert_eq!(targets[3], "backup");
+        if targets[0] == "heavy" {
+            heavy_first += 1;
+        }
+    }
+    // The heavy record comes first about three times as often as the light one, and the idle one
+    // almost never does.
+    assert!((700..800).contains(&heavy_first), "{}", heavy_first);
+
+    let ordered = order_srv_records(vec![srv(1, 0, "a"), srv(1, 0, "b")], &mut rng);
+    assert_eq!(ordered.len(), 2);
+}
+
+/// Validate resolving a service to addresses, through its SRV records and their targets.
+#[test]
+fn test_looking_up_service() -> Result<(), DnsError> {
+    use crate::hosts::HostsFile;
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    assert_eq!(
+        srv_name("_xmpp-client", "tcp", "example.com."),
+        "_xmpp-client._tcp.example.com"
+    );
+    let exchanges = vec![
+        MockResolution::new("_xmpp-client._tcp.example.com", RecordType::SRV, 0)
+            .answer(
+                "_xmpp-client._tcp.example.com. 300 IN SRV 20 0 5222 backup.example.com.
+                 _xmpp-client._tcp.example.com. 300 IN SRV 10 0 5223 xmpp.example.com.
+                 _xmpp-client._tcp.example.com. 300 IN SRV 10 0 5222 gone.example.com.",
+            )
+            .build()?,
+        MockResolution::new("_sip._udp.example.com", RecordType::SRV, 0)
+            .answer("_sip._udp.example.com. 300 IN SRV 0 0 0 .")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let mut resolver = Resolver::new(ResolverConfig::default());
+    resolver.hosts = Some(HostsFile::parse(
+        "192.0.2.1 xmpp.example.com\n192.0.2.2 backup.example.com\n",
+    ));
+    let addresses = lookup_service(
+        &resolver,
+        &mut socket,
+        "xmpp-client",
+        "tcp",
+        "example.com",
+        Some(0),
+    )?;
+    assert_eq!(
+        addresses,
+        [
+            "192.0.2.1:5223".parse::<SocketAddr>().unwrap(),
+            "192.0.2.2:5222".parse().unwrap()
+        ]
+    );
+
+    let addresses = lookup_service(&resolver, &mut socket, "sip", "udp", "example.com", Some(0))?;
+    assert!(addresses.is_empty());
+    Ok(())
+}
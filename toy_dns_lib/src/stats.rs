@@ -0,0 +1,217 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// How a server fared over the course of a resolution.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ServerStats {
+    /// The address the queries were sent to, e.g. `198.41.0.4:53`.
+    pub address: String,
+
+    /// How many queries were sent to the server, including those sent again.
+    pub queries: usize,
+
+    /// How long each response took to arrive, in order.
+    pub round_trips: Vec<Duration>,
+
+    /// How many queries the server left unanswered.
+    pub timeouts: usize,
+}
+
+/// Statistics about a resolution: the queries it took, the bytes they put on the wire, how each
+/// server fared and how long it all took.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ResolutionStats {
+    /// How many queries were sent, including those sent again.
+    pub queries_sent: usize,
+
+    /// The size of the queries sent, in octets.
+    pub bytes_sent: usize,
+
+    /// The size of the responses received, in octets.
+    pub bytes_received: usize,
+
+    /// The servers queried, in the order they were first contacted.
+    pub servers: Vec<ServerStats>,
+
+    /// How many names were answered from the cache.
+    pub cache_hits: usize,
+
+    /// How many names were looked up in the cache without success.
+    pub cache_misses: usize,
+
+    /// How long the whole resolution took.
+    pub elapsed: Duration,
+}
+
+impl ResolutionStats {
+    /// The statistics of the given server, added if it was not queried before.
+    ///
+    /// # Argument
+    /// * `address`: The address of the server.
+    fn server(&mut self, address: &str) -> &mut ServerStats {
+        let index = match self
+            .servers
+            .iter()
+            .position(|server| server.address == address)
+        {
+            Some(index) => index,
+            None => {
+                self.servers.push(ServerStats {
+                    address: address.to_owned(),
+                    ..Default::default()
+                });
+                self.servers.len() - 1
+            }
+        };
+        &mut self.servers[index]
+    }
+
+    /// Render the statistics as dig-style comment lines, with a line per server giving the
+    /// minimum, average and maximum round-trip time of its responses.
+    pub fn render(&self) -> String {
+        let unanswered: usize = self.servers.iter().map(|server| server.timeouts).sum();
+        let mut output = format!(
+            ";; Queries sent: {} ({} unanswered)\n\
+             ;; Bytes on the wire: {} sent, {} received\n\
+             ;; Cache: {} hits, {} misses\n\
+             ;; Total time: {} msec\n",
+            self.queries_sent,
+            unanswered,
+            self.bytes_sent,
+            self.bytes_received,
+            self.cache_hits,
+            self.cache_misses,
+            self.elapsed.as_millis()
+        );
+        for server in &self.servers {
+            output.push_str(&format!(
+                ";; {}: {} queries, {} responses, {} timeouts",
+                server.address,
+                server.queries,
+                server.round_trips.len(),
+                server.timeouts
+            ));
+            if let (Some(min), Some(max)) = (
+                server.round_trips.iter().min(),
+                server.round_trips.iter().max(),
+            ) {
+                let total: Duration = server.round_trips.iter().sum();
+                output.push_str(&format!(
+                    ", rtt min/avg/max {}/{}/{} msec",
+                    min.as_millis(),
+                    (total / server.round_trips.len() as u32).as_millis(),
+                    max.as_millis()
+                ));
+            }
+            output.push('\n');
+        }
+        output
+    }
+}
+
+/// Collects the statistics of a resolution as queries are sent. Clones share the same statistics,
+/// so that the queries a resolution spawns (e.g. to resolve the name of a name server) add to
+/// them as well. See `QueryOptions::stats`.
+#[derive(Debug, Clone, Default)]
+pub struct StatsRecorder {
+    stats: Arc<Mutex<ResolutionStats>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Statistics are only ever
+/// updated whole, so they remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl StatsRecorder {
+    /// Count a query sent to a server.
+    ///
+    /// # Arguments
+    /// * `address`: The address of the server.
+    /// * `size`: The size of the query in octets.
+    pub(crate) fn record_query(&self, address: &str, size: usize) {
+        let mut stats = lock(&self.stats);
+        stats.queries_sent += 1;
+        stats.bytes_sent += size;
+        stats.server(address).queries += 1;
+    }
+
+    /// Count a response received from a server.
+    ///
+    /// # Arguments
+    /// * `address`: The address of the server.
+    /// * `size`: The size of the response in octets.
+    /// * `round_trip`: How long the response took to arrive.
+    pub(crate) fn record_response(&self, address: &str, size: usize, round_trip: Duration) {
+        let mut stats = lock(&self.stats);
+        stats.bytes_received += size;
+        stats.server(address).round_trips.push(round_trip);
+    }
+
+    /// Count a query a server left unanswered.
+    ///
+    /// # Argument
+    /// * `address`: The address of the server.
+    pub(crate) fn record_timeout(&self, address: &str) {
+        lock(&self.stats).server(address).timeouts += 1;
+    }
+
+    /// Count a lookup in the cache.
+    ///
+    /// # Argument
+    /// * `hit`: Whether the name was answered from the cache.
+    pub(crate) fn record_cache_lookup(&self, hit: bool) {
+        let mut stats = lock(&self.stats);
+        match hit {
+            true => stats.cache_hits += 1,
+            false => stats.cache_misses += 1,
+        }
+    }
+
+    /// Take the statistics collected so far, leaving them empty for the next resolution.
+    pub fn take(&self) -> ResolutionStats {
+        std::mem::take(&mut *lock(&self.stats))
+    }
+}
+
+impl PartialEq for StatsRecorder {
+    /// Recorders are equal when they share the same statistics.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.stats, &other.stats)
+    }
+}
+
+/// Validate that statistics are collected per server and rendered.
+#[test]
+fn test_resolution_stats() {
+    let recorder = StatsRecorder::default();
+    recorder.clone().record_query("198.41.0.4:53", 40);
+    recorder.record_response("198.41.0.4:53", 500, Duration::from_millis(20));
+    recorder.record_query("192.5.6.30:53", 40);
+    recorder.record_timeout("192.5.6.30:53");
+    recorder.record_query("192.5.6.30:53", 40);
+    recorder.record_response("192.5.6.30:53", 300, Duration::from_millis(10));
+    recorder.record_query("198.41.0.4:53", 40);
+    recorder.record_response("198.41.0.4:53", 200, Duration::from_millis(30));
+    recorder.record_cache_lookup(false);
+
+    let mut stats = recorder.take();
+    assert_eq!(recorder.take(), ResolutionStats::default());
+    assert_eq!(stats.queries_sent, 4);
+    assert_eq!(stats.servers.len(), 2);
+    assert_eq!(stats.servers[1].timeouts, 1);
+
+    stats.elapsed = Duration::from_millis(1234);
+    assert_eq!(
+        stats.render(),
+        ";; Queries sent: 4 (1 unanswered)\n\
+         ;; Bytes on the wire: 160 sent, 1000 received\n\
+         ;; Cache: 0 hits, 1 misses\n\
+         ;; Total time: 1234 msec\n\
+         ;; 198.41.0.4:53: 2 queries, 2 responses, 0 timeouts, rtt min/avg/max 20/25/30 msec\n\
+         ;; 192.5.6.30:53: 2 queries, 1 responses, 1 timeouts, rtt min/avg/max 10/10/10 msec\n"
+    );
+}
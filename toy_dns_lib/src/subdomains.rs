@@ -0,0 +1,183 @@
+use crate::bulk::resolve_bulk;
+use crate::record::{Record, RecordType};
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use crate::wildcard::Wildcard;
+use log::info;
+use std::fmt;
+use std::net::IpAddr;
+
+/// A name found under a domain, with its records.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Subdomain {
+    /// The name, e.g. `www.example.com`.
+    pub name: String,
+
+    /// The A and AAAA records of the name, along with the CNAME records leading to them.
+    pub records: Vec<Record>,
+}
+
+impl fmt::Display for Subdomain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for record in &self.records {
+            writeln!(f, "{}", record)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a wordlist: one label per line, lowercased. Blank lines, comments starting with `#` and
+/// repeated labels are skipped.
+///
+/// # Argument
+/// * `contents`: The contents of the wordlist.
+pub fn parse_wordlist(contents: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for line in contents.lines() {
+        let word = line.split('#').next().unwrap_or_default().trim();
+        let word = word.trim_matches('.').to_ascii_lowercase();
+        if !word.is_empty() && !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// Find which of the given labels name something under a domain, by resolving their A and AAAA
+/// records concurrently with `resolve_bulk()`. Names without addresses are left out, and so are
+/// names whose addresses are all among those of the wildcard of the domain, if it has one, as
+/// they may just be synthesized.
+///
+/// # Arguments
+/// * `resolver`: The resolver to resolve the names with.
+/// * `sockets`: The sockets to resolve the names on, one per resolution in progress at once.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `words`: The labels to try under the domain, e.g. from `parse_wordlist()`.
+/// * `wildcard`: The wildcard of the domain, if any, e.g. from `detect_wildcard()`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn enumerate_subdomains<S: DnsTransport + Send>(
+    resolver: &Resolver,
+    sockets: Vec<S>,
+    domain_name: &str,
+    words: &[String],
+    wildcard: Option<&Wildcard>,
+    rand_seed: Option<usize>,
+) -> Vec<Subdomain> {
+    let domain_name = domain_name.trim_end_matches('.');
+    let questions: Vec<(String, RecordType)> = words
+        .iter()
+        .flat_map(|word| {
+            let name = format!("{}.{}", word, domain_name);
+            [(name.clone(), RecordType::A), (name, RecordType::AAAA)]
+        })
+        .collect();
+    let results = resolve_bulk(resolver, sockets, &questions, rand_seed);
+
+    let mut subdomains = Vec::new();
+    for (questions, results) in questions.chunks(2).zip(results.chunks(2)) {
+        let name = &questions[0].0;
+        let mut records: Vec<Record> = Vec::new();
+        for packet in results.iter().filter_map(|result| result.as_ref().ok()) {
+            for record in &packet.answers {
+                // A CNAME record leading to both the A and AAAA records is listed once.
+                let is_listed = records.iter().any(|listed| {
+                    listed.name == record.name
+                        && listed.r_type == record.r_type
+                        && listed.data == record.data
+                });
+                if !is_listed {
+                    records.push(record.clone());
+                }
+            }
+        }
+        let addresses: Vec<IpAddr> = records.iter().filter_map(Record::to_ip_addr).collect();
+        if addresses.is_empty() {
+            continue;
+        }
+        if wildcard.is_some_and(|wildcard| wildcard.matches(&addresses)) {
+            info!("{} only resolves to the addresses of the wildcard", name);
+            continue;
+        }
+        subdomains.push(Subdomain {
+            name: name.clone(),
+            records,
+        });
+    }
+    subdomains
+}
+
+/// Validate parsing of wordlists.
+#[test]
+fn test_parsing_wordlist() {
+    assert_eq!(
+        parse_wordlist("www\n\n# mail servers\nMail  # and smtp\nwww\n.ftp.\n"),
+        ["www", "mail", "ftp"]
+    );
+}
+
+/// Validate finding names under a domain, leaving out those which do not exist and those which
+/// only resolve to the addresses of its wildcard.
+#[test]
+fn test_enumerating_subdomains() -> Result<(), crate::errors::DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let mut sockets = Vec::new();
+    for _ in 0..2 {
+        let exchanges = vec![
+            MockResolution::new("www.example.com", RecordType::A, 0)
+                .answer(
+                    "www.example.com. 300 IN CNAME web.example.com.
+                     web.example.com. 300 IN A 192.0.2.1",
+                )
+                .build()?,
+            MockResolution::new("www.example.com", RecordType::AAAA, 0)
+                .answer(
+                    "www.example.com. 300 IN CNAME web.example.com.
+                     web.example.com. 300 IN AAAA 2001:db8::1",
+                )
+                .build()?,
+            MockResolution::new("mail.example.com", RecordType::A, 0)
+                .answer("mail.example.com. 300 IN A 192.0.2.80")
+                .build()?,
+            MockResolution::new("mail.example.com", RecordType::AAAA, 0)
+                .answer("")
+                .build()?,
+            MockResolution::new("ftp.example.com", RecordType::A, 0)
+                .name_error()
+                .build()?,
+            MockResolution::new("ftp.example.com", RecordType::AAAA, 0)
+                .name_error()
+                .build()?,
+        ];
+        let mut socket = MockSocket::bind("")?;
+        socket.register_response_data(exchanges.into_iter().flatten());
+        sockets.push(socket);
+    }
+
+    let wildcard = Wildcard {
+        domain_name: "example.com".to_owned(),
+        probe_names: vec!["q3vx0c7kd1m2a9ze.example.com".to_owned()],
+        addresses: vec!["192.0.2.80".parse().unwrap()],
+    };
+    let words = parse_wordlist("www\nmail\nftp\n");
+    let resolver = Resolver::new(ResolverConfig::default());
+    let subdomains = enumerate_subdomains(
+        &resolver,
+        sockets,
+        "example.com.",
+        &words,
+        Some(&wildcard),
+        Some(0),
+    );
+    assert_eq!(subdomains.len(), 1);
+    assert_eq!(subdomains[0].name, "www.example.com");
+    let types: Vec<RecordType> = subdomains[0]
+        .records
+        .iter()
+        .map(|record| record.r_type)
+        .collect();
+    assert_eq!(types, [RecordType::CNAME, RecordType::A, RecordType::AAAA]);
+    Ok(())
+}
@@ -0,0 +1,391 @@
+use crate::errors::DnsError;
+use crate::record::RecordType;
+use crate::record_name::RecordName;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use log::warn;
+use std::io::{Cursor, Read};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The keys of the SVCB parameters connection hints are made of (RFC 9460, section 14.3.2).
+pub const KEY_MANDATORY: u16 = 0;
+pub const KEY_ALPN: u16 = 1;
+pub const KEY_NO_DEFAULT_ALPN: u16 = 2;
+pub const KEY_PORT: u16 = 3;
+pub const KEY_IPV4_HINT: u16 = 4;
+pub const KEY_ECH: u16 = 5;
+pub const KEY_IPV6_HINT: u16 = 6;
+
+/// The protocol HTTPS clients support unless the record says otherwise (RFC 9460, section 7.1.2).
+const DEFAULT_ALPN: &str = "http/1.1";
+
+/// The most aliases followed before giving up on a name.
+const MAX_ALIAS_CHAIN_LENGTH: usize = 8;
+
+/// An SVCB or HTTPS record: an alias of a service, or an endpoint of it along with how to connect
+/// to it (RFC 9460, section 2).
+#[derive(Debug, PartialEq, Clone)]
+pub struct ServiceBinding {
+    /// The priority of the endpoint, lower ones being tried first. 0 for an alias.
+    pub priority: u16,
+
+    /// The name of the alias or the endpoint, without a trailing dot. `.` stands for the owner
+    /// of the record for an endpoint, and for no service at all for an alias.
+    pub target: String,
+
+    /// The parameters of the endpoint by key, in ascending order of key.
+    pub params: Vec<(u16, Vec<u8>)>,
+}
+
+impl ServiceBinding {
+    /// Parse the data of an SVCB or HTTPS record.
+    ///
+    /// # Argument
+    /// * `data`: The record data.
+    pub fn from_data(data: &[u8]) -> Result<ServiceBinding, DnsError> {
+        let Some(priority) = data.get(..2) else {
+            return Err(DnsError::ReadRecordData(None));
+        };
+        let mut cursor = Cursor::new(&data[2..]);
+        let target = RecordName::read_and_advance(&mut cursor)?;
+        let Ok(target) = String::from_utf8(target) else {
+            return Err(DnsError::InvalidByteInName);
+        };
+
+        let mut params = Vec::new();
+        while (cursor.position() as usize) < data.len() - 2 {
+            let mut header = [0u8; 4];
+            cursor
+                .read_exact(&mut header)
+                .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+            let mut value = vec![0u8; u16::from_be_bytes([header[2], header[3]]) as usize];
+            cursor
+                .read_exact(&mut value)
+                .map_err(|error| DnsError::ReadRecordData(Some(error)))?;
+            params.push((u16::from_be_bytes([header[0], header[1]]), value));
+        }
+        Ok(ServiceBinding {
+            priority: u16::from_be_bytes([priority[0], priority[1]]),
+            target: match target.is_empty() {
+                true => ".".to_owned(),
+                false => target,
+            },
+            params,
+        })
+    }
+
+    /// Whether the record is an alias of the service rather than an endpoint of it.
+    pub fn is_alias(&self) -> bool {
+        self.priority == 0
+    }
+
+    /// The value of a parameter, if the record has it.
+    ///
+    /// # Argument
+    /// * `key`: The key of the parameter, e.g. `KEY_ALPN`.
+    pub fn param(&self, key: u16) -> Option<&[u8]> {
+        self.params
+            .iter()
+            .find(|(param_key, _)| *param_key == key)
+            .map(|(_, value)| value.as_slice())
+    }
+}
+
+/// What an HTTP client needs to connect to an endpoint of a service, as given by its HTTPS record.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ConnectionHints {
+    /// The priority of the endpoint, lower ones being tried first.
+    pub priority: u16,
+
+    /// The host name to connect to, without a trailing dot.
+    pub target: String,
+
+    /// The port to connect to.
+    pub port: u16,
+
+    /// The protocols the endpoint supports, to offer with ALPN, e.g. `h2` and `h3`. The default
+    /// protocol is included unless the record leaves it out.
+    pub alpn: Vec<String>,
+
+    /// Addresses of the target, to connect to before its own address records are resolved.
+    pub ipv4_hints: Vec<Ipv4Addr>,
+
+    /// Addresses of the target, to connect to before its own address records are resolved.
+    pub ipv6_hints: Vec<Ipv6Addr>,
+
+    /// The Encrypted ClientHello configuration list of the endpoint, if it has one.
+    pub ech_config: Option<Vec<u8>>,
+}
+
+impl ConnectionHints {
+    /// The connection hints of an endpoint. `None` when the record makes parameters mandatory
+    /// which are not understood, as the endpoint cannot be used then (RFC 9460, section 8).
+    ///
+    /// # Arguments
+    /// * `binding`: The HTTPS record of the endpoint, in service mode.
+    /// * `owner`: The name the record was found at, which a target of `.` stands for.
+    /// * `port`: The port to connect to unless the record gives one.
+    pub fn from_binding(
+        binding: &ServiceBinding,
+        owner: &str,
+        port: u16,
+    ) -> Result<Option<ConnectionHints>, DnsError> {
+        let malformed = || DnsError::ReadRecordData(None);
+        let mandatory = binding.param(KEY_MANDATORY).unwrap_or_default();
+        if !mandatory.len().is_multiple_of(2) {
+            return Err(malformed());
+        }
+        if mandatory
+            .chunks(2)
+            .any(|key| u16::from_be_bytes([key[0], key[1]]) > KEY_IPV6_HINT)
+        {
+            return Ok(None);
+        }
+
+        let mut alpn = Vec::new();
+        let mut protocols = binding.param(KEY_ALPN).unwrap_or_default();
+        while let Some((&length, rest)) = protocols.split_first() {
+            let Some(protocol) = rest.get(..length as usize) else {
+                return Err(malformed());
+            };
+            alpn.push(String::from_utf8_lossy(protocol).into_owned());
+            protocols = &rest[length as usize..];
+        }
+        if binding.param(KEY_NO_DEFAULT_ALPN).is_none() && !alpn.iter().any(|p| p == DEFAULT_ALPN) {
+            alpn.push(DEFAULT_ALPN.to_owned());
+        }
+
+        let port = match binding.param(KEY_PORT) {
+            Some(value) => u16::from_be_bytes(value.try_into().map_err(|_| malformed())?),
+            None => port,
+        };
+        let ipv4_hints = binding.param(KEY_IPV4_HINT).unwrap_or_default();
+        let ipv6_hints = binding.param(KEY_IPV6_HINT).unwrap_or_default();
+        if !ipv4_hints.len().is_multiple_of(4) || !ipv6_hints.len().is_multiple_of(16) {
+            return Err(malformed());
+        }
+
+        Ok(Some(ConnectionHints {
+            priority: binding.priority,
+            target: match binding.target.as_str() {
+                "." => owner.trim_end_matches('.').to_owned(),
+                target => target.to_owned(),
+            },
+            port,
+            alpn,
+            ipv4_hints: ipv4_hints
+                .chunks(4)
+                .map(|octets| Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+                .collect(),
+            ipv6_hints: ipv6_hints
+                .chunks(16)
+                .map(|octets| Ipv6Addr::from(<[u8; 16]>::try_from(octets).unwrap_or_default()))
+                .collect(),
+            ech_config: binding.param(KEY_ECH).map(<[u8]>::to_vec),
+        }))
+    }
+}
+
+/// The name holding the HTTPS records of an origin: the host name itself for the default port,
+/// `_port._https.hostname` otherwise (RFC 9460, section 9.1).
+///
+/// # Arguments
+/// * `hostname`: The host name of the origin, e.g. `example.com`.
+/// * `port`: The port of the origin, e.g. 443.
+pub fn https_name(hostname: &str, port: u16) -> String {
+    let hostname = hostname.trim_end_matches('.');
+    match port {
+        443 => hostname.to_owned(),
+        port => format!("_{}._https.{}", port, hostname),
+    }
+}
+
+/// Look up how to connect to an HTTPS origin, as browsers bootstrap from HTTPS records: aliases
+/// are followed, and the endpoints found at the end are returned by ascending priority. Endpoints
+/// whose mandatory parameters are not understood are left out. Empty when the origin has no HTTPS
+/// records, in which case clients connect to it as they would without them, or when an alias says
+/// the service is not available.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the HTTPS records with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `hostname`: The host name of the origin, e.g. `example.com`.
+/// * `port`: The port of the origin, e.g. 443.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn lookup_https(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    hostname: &str,
+    port: u16,
+    rand_seed: Option<usize>,
+) -> Result<Vec<ConnectionHints>, DnsError> {
+    let mut name = https_name(hostname, port);
+    for _ in 0..=MAX_ALIAS_CHAIN_LENGTH {
+        let packet = match resolver.resolve(socket, &name, RecordType::HTTPS, rand_seed) {
+            Ok(packet) => packet,
+            Err(DnsError::UnknownDomainName) => return Ok(vec![]),
+            Err(error) => return Err(error),
+        };
+        let mut bindings = packet
+            .answers
+            .iter()
+            .filter(|record| record.r_type == RecordType::HTTPS)
+            .map(|record| ServiceBinding::from_data(&record.data))
+            .collect::<Result<Vec<ServiceBinding>, DnsError>>()?;
+
+        // Endpoints are ignored alongside an alias (RFC 9460, section 2.4.2).
+        if let Some(alias) = bindings.iter().find(|binding| binding.is_alias()) {
+            if alias.target == "." {
+                return Ok(vec![]);
+            }
+            name = alias.target.clone();
+            continue;
+        }
+
+        bindings.sort_by_key(|binding| binding.priority);
+        let mut endpoints = Vec::new();
+        for binding in &bindings {
+            match ConnectionHints::from_binding(binding, &name, port)? {
+                Some(hints) => endpoints.push(hints),
+                None => warn!(
+                    "Skipping an endpoint of {} with unknown mandatory parameters",
+                    name
+                ),
+            }
+        }
+        return Ok(endpoints);
+    }
+    Err(DnsError::Svcb)
+}
+
+/// Validate parsing of HTTPS records into connection hints.
+#[test]
+fn test_parsing_service_binding() -> Result<(), DnsError> {
+    use crate::rdata::from_presentation;
+
+    let data = from_presentation(
+        RecordType::HTTPS,
+        &[
+            "1",
+            ".",
+            "alpn=h3,h2",
+            "port=8443",
+            "ipv4hint=192.0.2.1",
+            "ech=AQID",
+            "ipv6hint=2001:db8::1,2001:db8::2",
+        ],
+        "",
+    )?;
+    let binding = ServiceBinding::from_data(&data)?;
+    assert_eq!(binding.target, ".");
+    assert!(!binding.is_alias());
+    assert_eq!(binding.param(KEY_PORT), Some(&[0x20, 0xfb][..]));
+    assert!(ServiceBinding::from_data(&data[..data.len() - 1]).is_err());
+
+    let hints = ConnectionHints::from_binding(&binding, "example.com.", 443)?;
+    assert_eq!(
+        hints,
+        Some(ConnectionHints {
+            priority: 1,
+            target: "example.com".to_owned(),
+            port: 8443,
+            alpn: vec!["h3".to_owned(), "h2".to_owned(), "http/1.1".to_owned()],
+            ipv4_hints: vec![Ipv4Addr::new(192, 0, 2, 1)],
+            ipv6_hints: vec![
+                "2001:db8::1".parse().unwrap(),
+                "2001:db8::2".parse().unwrap()
+            ],
+            ech_config: Some(vec![1, 2, 3]),
+        })
+    );
+
+    let data = from_presentation(
+        RecordType::HTTPS,
+        &["2", "cdn.example.net.", "alpn=h2", "no-default-alpn"],
+        "",
+    )?;
+    let binding = ServiceBinding::from_data(&data)?;
+    let hints = ConnectionHints::from_binding(&binding, "example.com", 443)?.unwrap();
+    assert_eq!(
+        (hints.target.as_str(), hints.port),
+        ("cdn.example.net", 443)
+    );
+    assert_eq!(hints.alpn, ["h2"]);
+    assert_eq!(hints.ech_config, None);
+
+    let data = from_presentation(
+        RecordType::HTTPS,
+        &["1", ".", "mandatory=key667", "key667=x"],
+        "",
+    )?;
+    let binding = ServiceBinding::from_data(&data)?;
+    assert_eq!(
+        ConnectionHints::from_binding(&binding, "example.com", 443)?,
+        None
+    );
+    Ok(())
+}
+
+/// Validate looking up connection hints, through aliases and by priority.
+#[test]
+fn test_looking_up_https() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    assert_eq!(https_name("example.com.", 443), "example.com");
+    assert_eq!(https_name("example.com", 8443), "_8443._https.example.com");
+    let exchanges = vec![
+        MockResolution::new("example.com", RecordType::HTTPS, 0)
+            .answer("example.com. 300 IN HTTPS 0 svc.example.net.")
+            .build()?,
+        MockResolution::new("svc.example.net", RecordType::HTTPS, 0)
+            .answer(
+                "svc.example.net. 300 IN HTTPS 2 . alpn=h2
+                 svc.example.net. 300 IN HTTPS 3 . mandatory=key9 key9=x
+                 svc.example.net. 300 IN HTTPS 1 h3.example.net. alpn=h3 port=8443",
+            )
+            .build()?,
+        MockResolution::new("_8443._https.example.com", RecordType::HTTPS, 0)
+            .answer("_8443._https.example.com. 300 IN HTTPS 0 .")
+            .build()?,
+        MockResolution::new("plain.example.com", RecordType::HTTPS, 0)
+            .answer("")
+            .build()?,
+        MockResolution::new("loop.example.com", RecordType::HTTPS, 0)
+            .answer("loop.example.com. 300 IN HTTPS 0 loop.example.com.")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let endpoints = lookup_https(&resolver, &mut socket, "example.com", 443, Some(0))?;
+    let endpoints: Vec<(&str, u16, Vec<String>)> = endpoints
+        .iter()
+        .map(|hints| (hints.target.as_str(), hints.port, hints.alpn.clone()))
+        .collect();
+    assert_eq!(
+        endpoints,
+        [
+            (
+                "h3.example.net",
+                8443,
+                vec!["h3".to_owned(), "http/1.1".to_owned()]
+            ),
+            (
+                "svc.example.net",
+                443,
+                vec!["h2".to_owned(), "http/1.1".to_owned()]
+            ),
+        ]
+    );
+
+    assert!(lookup_https(&resolver, &mut socket, "example.com", 8443, Some(0))?.is_empty());
+    assert!(lookup_https(&resolver, &mut socket, "plain.example.com", 443, Some(0))?.is_empty());
+    assert_eq!(
+        lookup_https(&resolver, &mut socket, "loop.example.com", 443, Some(0)),
+        Err(DnsError::Svcb)
+    );
+    Ok(())
+}
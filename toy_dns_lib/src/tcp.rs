@@ -0,0 +1,221 @@
+use crate::errors::DnsError;
+use crate::socket::DnsTransport;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::info;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+/// An open connection to a server.
+struct TcpConnection {
+    /// The connected stream.
+    stream: TcpStream,
+
+    /// Until when the server agreed to keep the connection open while idle.
+    idle_deadline: Instant,
+}
+
+/// A transport that sends DNS messages over TCP. Each message is prefixed with its length as
+/// specified in RFC 1035, section 4.2.2. Connections are kept open and reused for as long as the
+/// server's edns-tcp-keepalive idle timeout (RFC 7828) allows.
+#[derive(Default)]
+pub struct TcpTransport {
+    /// Open connections keyed by server address.
+    connections: HashMap<String, TcpConnection>,
+
+    /// Address of the server the last message was sent to.
+    last_server: Option<String>,
+
+    /// How long new connections wait for the server, or forever if `None`.
+    timeout: Option<Duration>,
+}
+
+impl TcpTransport {
+    /// Create the transport. Connections are made as messages are sent, so the address is
+    /// unused.
+    ///
+    /// # Argument
+    /// * `_addr`: The (local) address to bind to.
+    pub fn bind(_addr: &str) -> Result<TcpTransport, DnsError> {
+        Ok(TcpTransport::default())
+    }
+
+    /// Set how long new connections wait for the server to read or write anything.
+    ///
+    /// # Argument
+    /// * `timeout`: The timeout. It must not be zero.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// The number of connections currently kept open.
+    pub fn open_connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Write a length-prefixed message onto the stream.
+    ///
+    /// # Arguments
+    /// * `stream`: The stream to write to.
+    /// * `buf`: The message to write.
+    fn write_message(mut stream: &TcpStream, buf: &[u8]) -> Result<(), DnsError> {
+        let Ok(length) = u16::try_from(buf.len()) else {
+            return Err(DnsError::SocketSend(None));
+        };
+        let mut message = Vec::with_capacity(buf.len() + 2);
+        message
+            .write_u16::<BigEndian>(length)
+            .map_err(|error| DnsError::SocketSend(Some(error)))?;
+        message.extend(buf);
+        stream
+            .write_all(&message)
+            .map_err(|error| DnsError::SocketSend(Some(error)))?;
+        Ok(())
+    }
+}
+
+impl DnsTransport for TcpTransport {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        let now = Instant::now();
+        self.connections
+            .retain(|_, connection| connection.idle_deadline > now);
+
+        // Reuse the open connection if there is one. The server may have closed it in the
+        // meantime, in which case a fresh connection is attempted.
+        if let Some(connection) = self.connections.get(addr) {
+            if Self::write_message(&connection.stream, buf).is_ok() {
+                info!("Reusing open TCP connection to {}", addr);
+                self.last_server = Some(addr.to_owned());
+                return Ok(buf.len());
+            }
+            self.connections.remove(addr);
+        }
+
+        let stream =
+            TcpStream::connect(addr).map_err(|error| DnsError::SocketConnect(Some(error)))?;
+        let (Ok(_), Ok(_)) = (
+            stream.set_read_timeout(self.timeout),
+            stream.set_write_timeout(self.timeout),
+        ) else {
+            return Err(DnsError::SocketConnect(None));
+        };
+        Self::write_message(&stream, buf)?;
+        self.connections.insert(
+            addr.to_owned(),
+            TcpConnection {
+                stream,
+                idle_deadline: now,
+            },
+        );
+        self.last_server = Some(addr.to_owned());
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        let Some(connection) = self
+            .last_server
+            .as_ref()
+            .and_then(|addr| self.connections.get(addr))
+        else {
+            return Err(DnsError::SocketRead(None));
+        };
+
+        let mut stream = &connection.stream;
+        let length = stream
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        let mut message = vec![0u8; length as usize];
+        stream
+            .read_exact(&mut message)
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+
+        // Messages larger than the buffer are truncated, just as a datagram would be.
+        let size = message.len().min(buf.len());
+        buf[..size].copy_from_slice(&message[..size]);
+
+        let peer = connection
+            .stream
+            .peer_addr()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        Ok((size, peer))
+    }
+
+    fn is_connection_oriented(&self) -> bool {
+        true
+    }
+
+    fn set_idle_timeout(&mut self, addr: &str, timeout: Option<Duration>) {
+        match timeout {
+            Some(timeout) if !timeout.is_zero() => {
+                if let Some(connection) = self.connections.get_mut(addr) {
+                    connection.idle_deadline = Instant::now() + timeout;
+                }
+            }
+            // Without an agreed idle timeout, the connection is closed as soon as the response
+            // has been read so that the server doesn't have to.
+            _ => {
+                self.connections.remove(addr);
+            }
+        }
+    }
+}
+
+/// Validate that messages are framed with a length prefix and that connections are reused only
+/// while the idle timeout allows.
+#[test]
+fn test_tcp_transport_reuses_connection() -> Result<(), DnsError> {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    // The server accepts a single connection and echoes every message back reversed.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut received = Vec::new();
+        while let Ok(length) = stream.read_u16::<BigEndian>() {
+            let mut message = vec![0u8; length as usize];
+            stream.read_exact(&mut message).unwrap();
+            received.push(message.clone());
+            message.reverse();
+            stream.write_u16::<BigEndian>(message.len() as u16).unwrap();
+            stream.write_all(&message).unwrap();
+        }
+        received
+    });
+
+    let mut transport = TcpTransport::bind("")?;
+    let mut buf = [0u8; 16];
+
+    transport.send(&[1, 2, 3], &addr)?;
+    let (size, _) = transport.recv_from(&mut buf)?;
+    assert_eq!(buf[..size], [3, 2, 1]);
+    transport.set_idle_timeout(&addr, Some(Duration::from_secs(60)));
+    assert_eq!(transport.open_connections(), 1);
+
+    // The second message travels over the same connection as the first. The server would not
+    // accept another one.
+    transport.send(&[4, 5], &addr)?;
+    let (size, _) = transport.recv_from(&mut buf)?;
+    assert_eq!(buf[..size], [5, 4]);
+
+    // Without an idle timeout, the connection is closed.
+    transport.set_idle_timeout(&addr, None);
+    assert_eq!(transport.open_connections(), 0);
+
+    assert_eq!(server.join().unwrap(), vec![vec![1, 2, 3], vec![4, 5]]);
+    Ok(())
+}
+
+/// Validate that reading without having sent anything fails.
+#[test]
+fn test_tcp_transport_receive_without_sending() -> Result<(), DnsError> {
+    let transport = TcpTransport::bind("")?;
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        transport.recv_from(&mut buf),
+        Err(DnsError::SocketRead(None))
+    );
+    Ok(())
+}
@@ -0,0 +1,252 @@
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of servers tracked in a generation of buckets, above which a new one is started.
+const MAX_TRACKED_SERVERS: usize = 10_000;
+
+/// Tunables of a `QueryThrottle`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ThrottleConfig {
+    /// Queries which may be sent to each server per second, on average. At least 1.
+    pub queries_per_second: u32,
+
+    /// Queries which may be sent to a server at once after it was left alone for a while.
+    pub burst: u32,
+}
+
+impl ThrottleConfig {
+    /// Tunables allowing the given rate, with bursts of up to one second's worth of queries.
+    ///
+    /// # Argument
+    /// * `queries_per_second`: Queries which may be sent to each server per second.
+    pub fn new(queries_per_second: u32) -> ThrottleConfig {
+        ThrottleConfig {
+            queries_per_second,
+            burst: queries_per_second,
+        }
+    }
+}
+
+/// The queries which may be sent to a server, as in a token bucket.
+#[derive(Debug)]
+struct Bucket {
+    /// Queries which may still be sent. Negative when queries are waiting for their turn.
+    tokens: f64,
+
+    /// When the tokens were last updated.
+    updated_at: Instant,
+}
+
+/// The buckets of the servers queried so far, in two generations so that full ones are forgotten
+/// without going through all of them on every query: once the current generation is full, a new
+/// one starts and only the buckets still refilling are kept in the previous one.
+#[derive(Debug, Default)]
+struct Buckets {
+    /// Buckets updated since the current generation started.
+    current: HashMap<String, Bucket>,
+
+    /// Buckets of earlier generations still refilling when the current one started. They move to
+    /// the current one when updated.
+    previous: HashMap<String, Bucket>,
+}
+
+/// Limits the rate at which queries are sent to each server with a token bucket, so that batch
+/// jobs and busy forwarders do not flood the servers they ask. Queries above the rate wait for
+/// their turn rather than being dropped. Clones share the same buckets, so that every socket and
+/// every query they are given to is accounted for. See `QueryOptions::throttle`.
+#[derive(Debug, Clone)]
+pub struct QueryThrottle {
+    /// Tunables of the throttle.
+    config: ThrottleConfig,
+
+    /// The buckets of the servers queried so far, by address.
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Buckets are only ever updated
+/// whole, so they remain usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl QueryThrottle {
+    /// Create a throttle which has not seen any query yet.
+    ///
+    /// # Argument
+    /// * `config`: Tunables of the throttle.
+    pub fn new(config: ThrottleConfig) -> QueryThrottle {
+        QueryThrottle {
+            config,
+            buckets: Arc::default(),
+        }
+    }
+
+    /// Take the turn of a query to the given server and tell how long to wait before sending it.
+    /// Turns are handed out ahead of time, so that queries waiting on the same server are spaced
+    /// out rather than all sent at once when it is their turn.
+    ///
+    /// # Arguments
+    /// * `server`: The address of the server.
+    /// * `now`: The current time.
+    pub fn reserve(&self, server: &str, now: Instant) -> Duration {
+        let rate = self.config.queries_per_second.max(1) as f64;
+        let burst = self.config.burst.max(1) as f64;
+
+        // The tokens of a bucket, refilled for the time elapsed since it was last updated.
+        let refilled = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated_at);
+            (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst)
+        };
+
+        let mut buckets = lock(&self.buckets);
+        if buckets.current.len() >= MAX_TRACKED_SERVERS {
+            let mut previous = std::mem::take(&mut buckets.current);
+            previous.extend(std::mem::take(&mut buckets.previous));
+            previous.retain(|_, bucket| refilled(bucket) < burst);
+            buckets.previous = previous;
+        }
+        let bucket = buckets.previous.remove(server).unwrap_or(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+        let bucket = buckets.current.entry(server.to_owned()).or_insert(bucket);
+        bucket.tokens = refilled(bucket) - 1.0;
+        bucket.updated_at = bucket.updated_at.max(now);
+        match bucket.tokens < 0.0 {
+            true => Duration::from_secs_f64(-bucket.tokens / rate),
+            false => Duration::ZERO,
+        }
+    }
+
+    /// Wait for the turn of a query to the given server.
+    ///
+    /// # Argument
+    /// * `server`: The address of the server.
+    pub fn wait(&self, server: &str) {
+        let delay = self.reserve(server, Instant::now());
+        if !delay.is_zero() {
+            info!("Holding a query to {} back for {:?}", server, delay);
+            thread::sleep(delay);
+        }
+    }
+}
+
+impl PartialEq for QueryThrottle {
+    /// Throttles are equal when they share the same buckets.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.buckets, &other.buckets)
+    }
+}
+
+/// Validate that queries to a server beyond its burst are spaced out at the configured rate, and
+/// that servers are throttled independently.
+#[test]
+fn test_query_throttle() {
+    let throttle = QueryThrottle::new(ThrottleConfig::new(2));
+    let now = Instant::now();
+
+    let delays: Vec<Duration> = (0..4)
+        .map(|_| throttle.reserve("192.0.2.1:53", now))
+        .collect();
+    assert_eq!(
+        delays,
+        [
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::from_millis(500),
+            Duration::from_secs(1)
+        ]
+    );
+    assert_eq!(
+        throttle.clone().reserve("192.0.2.2:53", now),
+        Duration::ZERO
+    );
+
+    // The queries waiting for their turn use up the tokens which came in meanwhile.
+    let later = now + Duration::from_secs(1);
+    assert_eq!(
+        throttle.reserve("192.0.2.1:53", later),
+        Duration::from_millis(500)
+    );
+    let much_later = now + Duration::from_secs(60);
+    assert_eq!(throttle.reserve("192.0.2.1:53", much_later), Duration::ZERO);
+    assert_eq!(throttle.reserve("192.0.2.1:53", much_later), Duration::ZERO);
+    assert_eq!(
+        throttle.reserve("192.0.2.1:53", much_later),
+        Duration::from_millis(500)
+    );
+}
+
+/// Validate that a bucket refills at the configured rate over time, including partially, so that
+/// a query only waits for the part of a token still missing.
+#[test]
+fn test_query_throttle_refill() {
+    let throttle = QueryThrottle::new(ThrottleConfig::new(10));
+    let now = Instant::now();
+    for _ in 0..10 {
+        assert_eq!(throttle.reserve("192.0.2.1:53", now), Duration::ZERO);
+    }
+
+    // A tenth of a second brings back one token.
+    let refilled = now + Duration::from_millis(100);
+    assert_eq!(throttle.reserve("192.0.2.1:53", refilled), Duration::ZERO);
+
+    // Half of a token came back since, so the next query waits for the other half.
+    let half = refilled + Duration::from_millis(50);
+    assert_eq!(
+        throttle.reserve("192.0.2.1:53", half),
+        Duration::from_millis(50)
+    );
+}
+
+/// Validate that a bucket refills up to the burst and no further, however long the server is
+/// left alone.
+#[test]
+fn test_query_throttle_burst_cap() {
+    let throttle = QueryThrottle::new(ThrottleConfig {
+        queries_per_second: 1,
+        burst: 3,
+    });
+    let now = Instant::now();
+    let reserve_four = |at: Instant| -> Vec<Duration> {
+        (0..4)
+            .map(|_| throttle.reserve("192.0.2.1:53", at))
+            .collect()
+    };
+    let burst = [
+        Duration::ZERO,
+        Duration::ZERO,
+        Duration::ZERO,
+        Duration::from_secs(1),
+    ];
+    assert_eq!(reserve_four(now), burst);
+
+    // An hour refills far more tokens than the burst, but only the burst may be sent at once.
+    assert_eq!(reserve_four(now + Duration::from_secs(3600)), burst);
+}
+
+/// Validate that a rate or burst of zero, which the command line rejects, is taken as one query
+/// per second rather than blocking queries forever or dividing by zero.
+#[test]
+fn test_query_throttle_zero_rate() {
+    let throttle = QueryThrottle::new(ThrottleConfig {
+        queries_per_second: 0,
+        burst: 0,
+    });
+    let now = Instant::now();
+    assert_eq!(throttle.reserve("192.0.2.1:53", now), Duration::ZERO);
+    assert_eq!(
+        throttle.reserve("192.0.2.1:53", now),
+        Duration::from_secs(1)
+    );
+    assert_eq!(
+        throttle.reserve("192.0.2.1:53", now + Duration::from_secs(2)),
+        Duration::ZERO
+    );
+}
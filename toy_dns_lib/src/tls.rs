@@ -0,0 +1,226 @@
+use crate::errors::DnsError;
+use crate::socket::DnsTransport;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use log::info;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Port DNS-over-TLS servers listen on, as specified in RFC 7858, section 3.1.
+pub const DEFAULT_TLS_PORT: u16 = 853;
+
+/// How long to wait for a server over TLS before giving up on it, unless set otherwise.
+pub(crate) const TLS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A TLS session to a server over TCP.
+pub(crate) type TlsStream = StreamOwned<ClientConnection, TcpStream>;
+
+/// Build the TLS configuration shared by the encrypted transports. Servers are authenticated
+/// against the Mozilla root certificates.
+///
+/// # Argument
+/// * `alpn_protocols`: The application protocols to offer during the handshake.
+pub(crate) fn client_config(alpn_protocols: &[&[u8]]) -> Result<Arc<ClientConfig>, DnsError> {
+    let roots = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let Ok(builder) =
+        ClientConfig::builder_with_provider(provider).with_safe_default_protocol_versions()
+    else {
+        return Err(DnsError::SocketConnect(None));
+    };
+    let mut config = builder.with_root_certificates(roots).with_no_client_auth();
+    config.alpn_protocols = alpn_protocols
+        .iter()
+        .map(|protocol| protocol.to_vec())
+        .collect();
+    Ok(Arc::new(config))
+}
+
+/// Open a TLS session to the given server. The server's certificate must be valid for the host,
+/// which may be a name or an IP address.
+///
+/// # Arguments
+/// * `config`: The TLS configuration to use.
+/// * `addr`: The host and port of the server, e.g. `dns.quad9.net:853` or `[2620:fe::fe]:853`.
+/// * `timeout`: How long to wait for the server to read or write anything.
+pub(crate) fn connect(
+    config: &Arc<ClientConfig>,
+    addr: &str,
+    timeout: Duration,
+) -> Result<TlsStream, DnsError> {
+    let Some((host, _)) = addr.rsplit_once(':') else {
+        return Err(DnsError::SocketConnect(None));
+    };
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let Ok(server_name) = ServerName::try_from(host.to_owned()) else {
+        return Err(DnsError::SocketConnect(None));
+    };
+    let Ok(connection) = ClientConnection::new(Arc::clone(config), server_name) else {
+        return Err(DnsError::SocketConnect(None));
+    };
+
+    let stream = TcpStream::connect(addr).map_err(|error| DnsError::SocketConnect(Some(error)))?;
+    let (Ok(_), Ok(_)) = (
+        stream.set_read_timeout(Some(timeout)),
+        stream.set_write_timeout(Some(timeout)),
+    ) else {
+        return Err(DnsError::SocketConnect(None));
+    };
+    Ok(StreamOwned::new(connection, stream))
+}
+
+/// A transport that sends DNS messages over TLS (DoT) as specified in RFC 7858. Messages are
+/// framed with a length prefix just as over TCP. Sessions are kept open and reused for subsequent
+/// messages to the same server.
+pub struct TlsTransport {
+    /// The TLS configuration of new sessions.
+    config: Arc<ClientConfig>,
+
+    /// Open sessions keyed by server address.
+    sessions: HashMap<String, TlsStream>,
+
+    /// The response to the last message sent, waiting to be read.
+    response: RefCell<Option<(Vec<u8>, SocketAddr)>>,
+
+    /// How long new sessions wait for the server.
+    timeout: Duration,
+}
+
+impl TlsTransport {
+    /// Create the transport. Connections are made as messages are sent, so the address is
+    /// unused.
+    ///
+    /// # Argument
+    /// * `_addr`: The (local) address to bind to.
+    pub fn bind(_addr: &str) -> Result<TlsTransport, DnsError> {
+        Ok(TlsTransport {
+            config: client_config(&[])?,
+            sessions: HashMap::new(),
+            response: RefCell::new(None),
+            timeout: TLS_TIMEOUT,
+        })
+    }
+
+    /// Set how long new sessions wait for the server to read or write anything.
+    ///
+    /// # Argument
+    /// * `timeout`: The timeout. It must not be zero.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Send a message over the session and read the response to it.
+    ///
+    /// # Arguments
+    /// * `session`: The session to exchange the message over.
+    /// * `buf`: The message to send.
+    fn exchange(session: &mut TlsStream, buf: &[u8]) -> Result<Vec<u8>, DnsError> {
+        let Ok(length) = u16::try_from(buf.len()) else {
+            return Err(DnsError::SocketSend(None));
+        };
+        let mut message = Vec::with_capacity(buf.len() + 2);
+        message
+            .write_u16::<BigEndian>(length)
+            .map_err(|error| DnsError::SocketSend(Some(error)))?;
+        message.extend(buf);
+        let (Ok(_), Ok(_)) = (session.write_all(&message), session.flush()) else {
+            return Err(DnsError::SocketSend(None));
+        };
+
+        let length = session
+            .read_u16::<BigEndian>()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        let mut response = vec![0u8; length as usize];
+        session
+            .read_exact(&mut response)
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        Ok(response)
+    }
+}
+
+impl DnsTransport for TlsTransport {
+    fn send<'a>(&'a mut self, buf: &'a [u8], addr: &str) -> Result<usize, DnsError> {
+        // The server may have closed an idle session in the meantime, in which case a fresh
+        // session is attempted.
+        if let Some(mut session) = self.sessions.remove(addr) {
+            if let Ok(response) = Self::exchange(&mut session, buf) {
+                info!("Reusing open TLS session to {}", addr);
+                let peer = session
+                    .sock
+                    .peer_addr()
+                    .map_err(|error| DnsError::SocketRead(Some(error)))?;
+                self.response.replace(Some((response, peer)));
+                self.sessions.insert(addr.to_owned(), session);
+                return Ok(buf.len());
+            }
+        }
+
+        let mut session = connect(&self.config, addr, self.timeout)?;
+        let response = Self::exchange(&mut session, buf)?;
+        let peer = session
+            .sock
+            .peer_addr()
+            .map_err(|error| DnsError::SocketRead(Some(error)))?;
+        self.response.replace(Some((response, peer)));
+        self.sessions.insert(addr.to_owned(), session);
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), DnsError> {
+        let Some((message, peer)) = self.response.take() else {
+            return Err(DnsError::SocketRead(None));
+        };
+
+        // Messages larger than the buffer are truncated, just as a datagram would be.
+        let size = message.len().min(buf.len());
+        buf[..size].copy_from_slice(&message[..size]);
+        Ok((size, peer))
+    }
+
+    fn is_connection_oriented(&self) -> bool {
+        true
+    }
+}
+
+/// Validate that reading without having sent anything fails.
+#[test]
+fn test_tls_transport_receive_without_sending() -> Result<(), DnsError> {
+    let transport = TlsTransport::bind("")?;
+    let mut buf = [0u8; 16];
+    assert_eq!(
+        transport.recv_from(&mut buf),
+        Err(DnsError::SocketRead(None))
+    );
+    Ok(())
+}
+
+/// Validate that a server which does not speak TLS is not trusted with the message.
+#[test]
+fn test_tls_transport_requires_handshake() -> Result<(), DnsError> {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+
+    // The server answers the ClientHello with a plain DNS message.
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut hello = [0u8; 5];
+        stream.read_exact(&mut hello).unwrap();
+        stream.write_all(&[0, 2, 1, 2]).unwrap();
+        hello
+    });
+
+    let mut transport = TlsTransport::bind("")?;
+    assert!(transport.send(&[1, 2, 3], &addr).is_err());
+    // The first record sent is a TLS handshake.
+    assert_eq!(server.join().unwrap()[0], 0x16);
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use crate::header::{RCODE_NAME_ERROR, RCODE_NO_ERROR};
+use crate::packet::Packet;
+use crate::record::RecordType;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+/// A query sent to a server while resolving a name, along with the response it received.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Exchange {
+    /// The address the query was sent to, e.g. `198.41.0.4:53`.
+    pub server_address: String,
+
+    /// The name of the server, if known.
+    pub server_name: String,
+
+    /// The name in question.
+    pub domain_name: String,
+
+    /// The type in question.
+    pub record_type: RecordType,
+
+    /// The response of the server.
+    pub response: Packet,
+
+    /// The size of the response in octets.
+    pub received_bytes: usize,
+
+    /// How long the server took to respond.
+    pub elapsed: Duration,
+}
+
+/// A name server of a referral whose address the referring server gave along with it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Glue {
+    /// The name of the name server, e.g. `a.gtld-servers.net`.
+    pub name: String,
+
+    /// The address of the name server in presentation format, e.g. `192.5.6.30`.
+    pub address: String,
+}
+
+/// What a server told the resolver in response to a query.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Outcome {
+    /// The server answered the question.
+    Answer,
+
+    /// The server referred the resolver to the name servers of a zone closer to the name.
+    Referral {
+        /// The zone the name servers are authoritative for, e.g. `com`.
+        zone: String,
+
+        /// The names of the name servers, in presentation format.
+        name_servers: Vec<String>,
+
+        /// The addresses of the name servers which the server knew, if any.
+        glue: Vec<Glue>,
+    },
+
+    /// The name does not exist.
+    NameError,
+
+    /// The name exists, but has no records of the type in question.
+    NoData,
+
+    /// The server failed to answer with the given RCODE, e.g. SERVFAIL or REFUSED.
+    Failure(u16),
+}
+
+impl Exchange {
+    /// Classify the response of the server: an answer, a referral to the name servers of
+    /// another zone, or neither.
+    pub fn outcome(&self) -> Outcome {
+        let response = &self.response;
+        match response.rcode() {
+            RCODE_NO_ERROR => (),
+            RCODE_NAME_ERROR => return Outcome::NameError,
+            rcode => return Outcome::Failure(rcode),
+        }
+        if !response.answers.is_empty() {
+            return Outcome::Answer;
+        }
+
+        let referral: Vec<_> = response
+            .authorities
+            .iter()
+            .filter(|record| record.r_type == RecordType::NS)
+            .collect();
+        let Some(first) = referral.first() else {
+            return Outcome::NoData;
+        };
+        let name_servers: Vec<String> = referral
+            .iter()
+            .map(|record| record.data_to_presentation())
+            .collect();
+        let glue = response
+            .additionals
+            .iter()
+            .filter(|record| matches!(record.r_type, RecordType::A | RecordType::AAAA))
+            .filter(|record| {
+                let name = String::from_utf8_lossy(&record.name);
+                name_servers.iter().any(|name_server| {
+                    name_server
+                        .trim_end_matches('.')
+                        .eq_ignore_ascii_case(&name)
+                })
+            })
+            .map(|record| Glue {
+                name: String::from_utf8_lossy(&record.name).into_owned(),
+                address: record.data_to_presentation(),
+            })
+            .collect();
+        Outcome::Referral {
+            zone: String::from_utf8_lossy(&first.name).into_owned(),
+            name_servers,
+            glue,
+        }
+    }
+}
+
+/// The exchanges made with servers over the course of a resolution, in order. Clones share the
+/// same record, so that the queries a resolution spawns (e.g. to resolve the name of a name
+/// server) add to it as well. See `QueryOptions::trace`.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    exchanges: Arc<Mutex<Vec<Exchange>>>,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Exchanges are only ever added
+/// whole, so the record remains usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl Trace {
+    /// Add an exchange to the record.
+    ///
+    /// # Argument
+    /// * `exchange`: The exchange to add.
+    pub(crate) fn push(&self, exchange: Exchange) {
+        lock(&self.exchanges).push(exchange);
+    }
+
+    /// Take the exchanges recorded so far, leaving the record empty for the next resolution.
+    pub fn take(&self) -> Vec<Exchange> {
+        std::mem::take(&mut *lock(&self.exchanges))
+    }
+}
+
+impl PartialEq for Trace {
+    /// Traces are equal when they share the same record.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.exchanges, &other.exchanges)
+    }
+}
+
+/// Validate that clones of a trace share the same record.
+#[test]
+fn test_trace_shared_by_clones() {
+    use crate::header::Header;
+
+    let trace = Trace::default();
+    let exchange = Exchange {
+        server_address: "198.41.0.4:53".to_owned(),
+        server_name: "a.root-servers.net".to_owned(),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::A,
+        response: Packet {
+            header: Header::default(),
+            questions: vec![],
+            answers: vec![],
+            authorities: vec![],
+            additionals: vec![],
+        },
+        received_bytes: 12,
+        elapsed: Duration::from_millis(12),
+    };
+    trace.clone().push(exchange.clone());
+    assert_eq!(trace, trace.clone());
+    assert_ne!(trace, Trace::default());
+
+    assert_eq!(trace.take(), [exchange]);
+    assert!(trace.take().is_empty());
+}
+
+/// Validate telling answers, referrals and failures apart.
+#[test]
+fn test_exchange_outcome() {
+    use crate::dns_name::DnsName;
+    use crate::header::{Header, RCODE_SERVER_FAILURE};
+    use crate::record::Record;
+    use crate::ttl::Ttl;
+
+    let record = |name: &str, r_type: RecordType, data: &[u8]| Record {
+        name: DnsName::from(name),
+        r_type,
+        r_class: 1,
+        ttl: Ttl::from(172800),
+        data: data.to_vec(),
+    };
+    let mut exchange = Exchange {
+        server_address: "198.41.0.4:53".to_owned(),
+        server_name: "a.root-servers.net".to_owned(),
+        domain_name: "example.com".to_owned(),
+        record_type: RecordType::A,
+        response: Packet {
+            header: Header::default(),
+            questions: vec![],
+            answers: vec![],
+            authorities: vec![
+                record("com", RecordType::NS, b"\x01a\x0cgtld-servers\x03net\x00"),
+                record("com", RecordType::NS, b"\x01b\x0cgtld-servers\x03net\x00"),
+            ],
+            additionals: vec![
+                record("a.gtld-servers.net", RecordType::A, &[192, 5, 6, 30]),
+                record("elsewhere.example", RecordType::A, &[192, 0, 2, 1]),
+            ],
+        },
+        received_bytes: 512,
+        elapsed: Duration::from_millis(12),
+    };
+    assert_eq!(
+        exchange.outcome(),
+        Outcome::Referral {
+            zone: "com".to_owned(),
+            name_servers: vec![
+                "a.gtld-servers.net.".to_owned(),
+                "b.gtld-servers.net.".to_owned()
+            ],
+            glue: vec![Glue {
+                name: "a.gtld-servers.net".to_owned(),
+                address: "192.5.6.30".to_owned(),
+            }],
+        }
+    );
+
+    exchange.response.authorities.clear();
+    assert_eq!(exchange.outcome(), Outcome::NoData);
+    exchange.response.answers = vec![record("example.com", RecordType::A, &[192, 0, 2, 1])];
+    assert_eq!(exchange.outcome(), Outcome::Answer);
+    exchange.response.header.flags = RCODE_NAME_ERROR;
+    assert_eq!(exchange.outcome(), Outcome::NameError);
+    exchange.response.header.flags = RCODE_SERVER_FAILURE;
+    assert_eq!(exchange.outcome(), Outcome::Failure(RCODE_SERVER_FAILURE));
+}
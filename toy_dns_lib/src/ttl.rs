@@ -0,0 +1,213 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The TTL of a record along with when it was received, so that its expiry can be told without
+/// keeping track of when the record arrived. TTLs compare and hash by their number of seconds
+/// alone, so that records read at different times are still equal. With the `serde` feature, TTLs
+/// are serialized as their number of seconds, and deserialized as received then.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "u32", into = "u32")
+)]
+pub struct Ttl {
+    /// The TTL in seconds, as on the wire.
+    seconds: u32,
+
+    /// When the record was received, on the monotonic clock.
+    received: Instant,
+
+    /// When the record was received, on the system clock.
+    received_at: SystemTime,
+}
+
+impl Ttl {
+    /// A TTL of the given number of seconds, received at the given time.
+    ///
+    /// # Arguments
+    /// * `seconds`: The TTL in seconds.
+    /// * `received`: When the record was received.
+    pub fn new(seconds: u32, received: Instant) -> Ttl {
+        let since = Instant::now().saturating_duration_since(received);
+        Ttl {
+            seconds,
+            received,
+            received_at: SystemTime::now() - since,
+        }
+    }
+
+    /// The TTL in seconds, as on the wire.
+    pub fn as_secs(&self) -> u32 {
+        self.seconds
+    }
+
+    /// The TTL as a duration.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.seconds as u64)
+    }
+
+    /// When the record expires, on the monotonic clock.
+    pub fn expires(&self) -> Instant {
+        self.received + self.duration()
+    }
+
+    /// When the record expires, on the system clock, e.g. for display.
+    pub fn expires_at(&self) -> SystemTime {
+        self.received_at + self.duration()
+    }
+
+    /// How long the record remains valid from the given time on, zero once it has expired.
+    ///
+    /// # Argument
+    /// * `now`: The current time.
+    pub fn remaining(&self, now: Instant) -> Duration {
+        self.expires().saturating_duration_since(now)
+    }
+
+    /// Whether the record has expired by the given time.
+    ///
+    /// # Argument
+    /// * `now`: The current time.
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires()
+    }
+
+    /// The TTL as it stands the given number of seconds after the record was received, e.g. to
+    /// hand out a cached record. The expiry stays the same.
+    ///
+    /// # Argument
+    /// * `seconds`: The number of seconds that have passed.
+    pub fn saturating_sub(&self, seconds: u32) -> Ttl {
+        let seconds = seconds.min(self.seconds);
+        let elapsed = Duration::from_secs(seconds as u64);
+        Ttl {
+            seconds: self.seconds - seconds,
+            received: self.received + elapsed,
+            received_at: self.received_at + elapsed,
+        }
+    }
+
+    /// The TTL brought within the given bounds, received at the same time. The upper bound wins
+    /// should the bounds cross.
+    ///
+    /// # Arguments
+    /// * `min`: The lowest TTL in seconds.
+    /// * `max`: The highest TTL in seconds.
+    pub fn clamped(&self, min: u32, max: u32) -> Ttl {
+        Ttl {
+            seconds: self.seconds.max(min).min(max),
+            ..*self
+        }
+    }
+}
+
+impl From<u32> for Ttl {
+    /// A TTL of the given number of seconds, received now.
+    fn from(seconds: u32) -> Self {
+        Ttl {
+            seconds,
+            received: Instant::now(),
+            received_at: SystemTime::now(),
+        }
+    }
+}
+
+impl From<Ttl> for u32 {
+    /// The TTL in seconds, as on the wire.
+    fn from(ttl: Ttl) -> Self {
+        ttl.seconds
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Ttl {
+    /// A TTL of an arbitrary number of seconds, received now.
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Ttl::from(u.arbitrary::<u32>()?))
+    }
+}
+
+impl Default for Ttl {
+    fn default() -> Self {
+        Ttl::from(0)
+    }
+}
+
+impl PartialEq for Ttl {
+    fn eq(&self, other: &Self) -> bool {
+        self.seconds == other.seconds
+    }
+}
+
+impl Eq for Ttl {}
+
+impl PartialEq<u32> for Ttl {
+    fn eq(&self, other: &u32) -> bool {
+        self.seconds == *other
+    }
+}
+
+impl Ord for Ttl {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.seconds.cmp(&other.seconds)
+    }
+}
+
+impl PartialOrd for Ttl {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for Ttl {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.seconds.hash(state);
+    }
+}
+
+impl fmt::Display for Ttl {
+    /// Write the TTL in seconds, as in presentation format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.seconds)
+    }
+}
+
+/// Validate the expiry of a TTL, and that it remains the same as the TTL counts down.
+#[test]
+fn test_ttl_expiry() {
+    let received = Instant::now();
+    let ttl = Ttl::new(300, received);
+    assert_eq!(ttl.duration(), Duration::from_secs(300));
+    assert_eq!(ttl.expires(), received + Duration::from_secs(300));
+    assert_eq!(
+        ttl.remaining(received + Duration::from_secs(100)),
+        Duration::from_secs(200)
+    );
+    assert!(!ttl.is_expired(received + Duration::from_secs(299)));
+    assert!(ttl.is_expired(received + Duration::from_secs(300)));
+    assert_eq!(
+        ttl.remaining(received + Duration::from_secs(400)),
+        Duration::ZERO
+    );
+
+    let decayed = ttl.saturating_sub(100);
+    assert_eq!(decayed.as_secs(), 200);
+    assert_eq!(decayed.expires(), ttl.expires());
+    assert_eq!(decayed.expires_at(), ttl.expires_at());
+    assert_eq!(ttl.saturating_sub(400).as_secs(), 0);
+    assert_eq!(ttl.saturating_sub(400).expires(), ttl.expires());
+}
+
+/// Validate that TTLs compare by their number of seconds alone.
+#[test]
+fn test_ttl_comparison() {
+    let earlier = Ttl::new(60, Instant::now());
+    let later = Ttl::new(60, Instant::now() + Duration::from_secs(5));
+    assert_eq!(earlier, later);
+    assert_eq!(earlier, 60);
+    assert!(Ttl::from(30) < earlier);
+    assert_eq!(format!("{}", earlier), "60");
+}
@@ -0,0 +1,380 @@
+use crate::header::{RCODE_REFUSED, RCODE_SERVER_FAILURE};
+use crate::query::{Query, QueryOptions};
+use crate::record::RecordType;
+use crate::socket::DnsTransport;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// How the upstream asked first is chosen among the healthy ones. The others are asked in turn
+/// when it does not respond.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum PoolStrategy {
+    /// Always start with the first healthy upstream, in the order they were given.
+    #[default]
+    Failover,
+
+    /// Start with the next healthy upstream from one query to the next, spreading the load.
+    RoundRobin,
+
+    /// Start with the healthy upstream which has been responding the fastest.
+    Fastest,
+}
+
+/// Tunables of an `UpstreamPool`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PoolConfig {
+    /// Failures in a row after which an upstream is ejected from the pool.
+    pub max_failures: u32,
+
+    /// How often each upstream is probed, see `UpstreamPool::probe()`.
+    pub probe_interval: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_failures: 3,
+            probe_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What is known of the health of an upstream.
+#[derive(Debug, Default, Clone)]
+struct Health {
+    /// Queries and probes left unanswered since the last response.
+    failures: u32,
+
+    /// Whether the upstream is skipped until it responds to a probe.
+    ejected: bool,
+
+    /// Smoothed round-trip time, as TCP estimates it (RFC 6298). None until the first response.
+    rtt: Option<Duration>,
+
+    /// When the upstream was last probed.
+    probed_at: Option<Instant>,
+}
+
+/// Keeps track of the health of a set of upstream servers so that those which stopped responding
+/// are ejected until a health probe finds them responding again, and decides in which order the
+/// others are asked. Upstreams are told apart by the address they are reached at, so the pool can
+/// be shared by anything asking the same servers.
+#[derive(Default)]
+pub struct UpstreamPool {
+    /// How the upstream asked first is chosen.
+    pub strategy: PoolStrategy,
+
+    /// Tunables of the pool.
+    pub config: PoolConfig,
+
+    /// The health of the upstreams, by address.
+    health: Mutex<HashMap<String, Health>>,
+
+    /// Where the next round-robin query starts.
+    next: AtomicUsize,
+}
+
+/// Lock the mutex even if another thread panicked while holding it. Health is always left
+/// consistent, so it remains usable.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl UpstreamPool {
+    /// Create a pool choosing upstreams with the given strategy, which knows nothing of their
+    /// health yet.
+    ///
+    /// # Argument
+    /// * `strategy`: How the upstream asked first is chosen.
+    pub fn new(strategy: PoolStrategy) -> UpstreamPool {
+        UpstreamPool {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    /// The order in which to ask the given upstreams: the healthy ones, starting with the one
+    /// picked by the strategy. When every upstream is ejected, all of them are asked in the order
+    /// given, as an upstream which might respond beats none at all.
+    ///
+    /// # Argument
+    /// * `upstreams`: The upstreams to choose from, in order of preference.
+    pub fn order<'a, T: fmt::Display>(&self, upstreams: &'a [T]) -> Vec<&'a T> {
+        let health = lock(&self.health);
+        let mut healthy: Vec<(&T, Option<Duration>)> = upstreams
+            .iter()
+            .filter_map(|upstream| match health.get(&upstream.to_string()) {
+                Some(health) if health.ejected => None,
+                Some(health) => Some((upstream, health.rtt)),
+                None => Some((upstream, None)),
+            })
+            .collect();
+        drop(health);
+        if healthy.is_empty() {
+            return upstreams.iter().collect();
+        }
+
+        match self.strategy {
+            PoolStrategy::Failover => (),
+            PoolStrategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed) % healthy.len();
+                healthy.rotate_left(start);
+            }
+            // Upstreams which never responded go first, so that they get measured.
+            PoolStrategy::Fastest => healthy.sort_by_key(|(_, rtt)| *rtt),
+        }
+        healthy.into_iter().map(|(upstream, _)| upstream).collect()
+    }
+
+    /// Account for a response from an upstream, which is back in the pool if it was ejected.
+    ///
+    /// # Arguments
+    /// * `upstream`: The address of the upstream.
+    /// * `rtt`: How long the upstream took to respond.
+    pub fn record_response(&self, upstream: &str, rtt: Duration) {
+        let mut health = lock(&self.health);
+        let health = health.entry(upstream.to_owned()).or_default();
+        if health.ejected {
+            info!("{} is responding again, back in the pool", upstream);
+        }
+        health.failures = 0;
+        health.ejected = false;
+        health.rtt = Some(match health.rtt {
+            Some(rtt_estimate) => (rtt_estimate * 7 + rtt) / 8,
+            None => rtt,
+        });
+    }
+
+    /// Account for a query or probe an upstream left unanswered, ejecting it after too many in a
+    /// row.
+    ///
+    /// # Argument
+    /// * `upstream`: The address of the upstream.
+    pub fn record_failure(&self, upstream: &str) {
+        let mut health = lock(&self.health);
+        let health = health.entry(upstream.to_owned()).or_default();
+        health.failures += 1;
+        if !health.ejected && health.failures >= self.config.max_failures {
+            warn!(
+                "{} failed to respond {} times in a row, ejected from the pool",
+                upstream, health.failures
+            );
+            health.ejected = true;
+        }
+    }
+
+    /// Whether the upstream is ejected from the pool.
+    ///
+    /// # Argument
+    /// * `upstream`: The address of the upstream.
+    pub fn is_ejected(&self, upstream: &str) -> bool {
+        lock(&self.health)
+            .get(upstream)
+            .is_some_and(|health| health.ejected)
+    }
+
+    /// Probe the upstreams which were not probed for `probe_interval` by asking them for the name
+    /// servers of the root zone. A server failure or a refusal counts as no response. Meant to be
+    /// called periodically, so that ejected upstreams return to the pool once they recover.
+    /// Returns the number of upstreams which responded.
+    ///
+    /// # Arguments
+    /// * `socket`: The socket on which to reach the upstreams.
+    /// * `upstreams`: The upstreams to probe.
+    /// * `rand_seed`: The seed for RNG, if desired.
+    pub fn probe<T: fmt::Display>(
+        &self,
+        socket: &mut dyn DnsTransport,
+        upstreams: &[T],
+        rand_seed: Option<usize>,
+    ) -> usize {
+        let probe = Query {
+            domain_name: ".".into(),
+            record_type: RecordType::NS,
+            options: QueryOptions {
+                recursion_desired: true,
+                ..Default::default()
+            },
+        };
+
+        let mut responded = 0;
+        for upstream in upstreams {
+            let upstream = upstream.to_string();
+            let now = Instant::now();
+            let is_due = lock(&self.health)
+                .get(&upstream)
+                .and_then(|health| health.probed_at)
+                .is_none_or(|probed_at| {
+                    now.duration_since(probed_at) >= self.config.probe_interval
+                });
+            if !is_due {
+                continue;
+            }
+            lock(&self.health)
+                .entry(upstream.clone())
+                .or_default()
+                .probed_at = Some(now);
+
+            match probe.ask(socket, &upstream, rand_seed) {
+                Ok(packet)
+                    if packet.rcode() != RCODE_SERVER_FAILURE
+                        && packet.rcode() != RCODE_REFUSED =>
+                {
+                    self.record_response(&upstream, now.elapsed());
+                    responded += 1;
+                }
+                Ok(packet) => {
+                    info!(
+                        "{} failed its health probe with RCODE {}",
+                        upstream,
+                        packet.rcode()
+                    );
+                    self.record_failure(&upstream);
+                }
+                Err(error) => {
+                    info!("{} failed its health probe: {}", upstream, error);
+                    self.record_failure(&upstream);
+                }
+            }
+        }
+        responded
+    }
+}
+
+/// Validate the order upstreams are asked in with each strategy.
+#[test]
+fn test_upstream_pool_strategies() {
+    let upstreams = ["192.0.2.1:53", "192.0.2.2:53", "192.0.2.3:53"];
+
+    let pool = UpstreamPool::new(PoolStrategy::Failover);
+    assert_eq!(pool.order(&upstreams), upstreams.iter().collect::<Vec<_>>());
+    assert_eq!(pool.order(&upstreams), upstreams.iter().collect::<Vec<_>>());
+
+    let pool = UpstreamPool::new(PoolStrategy::RoundRobin);
+    let first: Vec<&str> = (0..4).map(|_| *pool.order(&upstreams)[0]).collect();
+    assert_eq!(
+        first,
+        [
+            "192.0.2.1:53",
+            "192.0.2.2:53",
+            "192.0.2.3:53",
+            "192.0.2.1:53"
+        ]
+    );
+
+    let pool = UpstreamPool::new(PoolStrategy::Fastest);
+    pool.record_response("192.0.2.1:53", Duration::from_millis(80));
+    pool.record_response("192.0.2.2:53", Duration::from_millis(20));
+    // The upstream which never responded is measured first.
+    assert_eq!(
+        pool.order(&upstreams),
+        [&"192.0.2.3:53", &"192.0.2.2:53", &"192.0.2.1:53"]
+    );
+    pool.record_response("192.0.2.3:53", Duration::from_millis(50));
+    assert_eq!(
+        pool.order(&upstreams),
+        [&"192.0.2.2:53", &"192.0.2.3:53", &"192.0.2.1:53"]
+    );
+}
+
+/// Validate that upstreams failing repeatedly are ejected until they respond again, and that
+/// all upstreams are asked when every one of them is ejected.
+#[test]
+fn test_upstream_pool_ejection() {
+    let upstreams = ["192.0.2.1:53", "192.0.2.2:53"];
+    let pool = UpstreamPool::new(PoolStrategy::Failover);
+
+    pool.record_failure("192.0.2.1:53");
+    pool.record_failure("192.0.2.1:53");
+    assert!(!pool.is_ejected("192.0.2.1:53"));
+    pool.record_failure("192.0.2.1:53");
+    assert!(pool.is_ejected("192.0.2.1:53"));
+    assert_eq!(pool.order(&upstreams), [&"192.0.2.2:53"]);
+
+    for _ in 0..3 {
+        pool.record_failure("192.0.2.2:53");
+    }
+    assert_eq!(pool.order(&upstreams), upstreams.iter().collect::<Vec<_>>());
+
+    pool.record_response("192.0.2.1:53", Duration::from_millis(10));
+    assert!(!pool.is_ejected("192.0.2.1:53"));
+    assert_eq!(pool.order(&upstreams), [&"192.0.2.1:53"]);
+}
+
+/// Validate that probes eject upstreams which do not respond and bring them back once they do.
+#[test]
+fn test_upstream_pool_probes() -> Result<(), crate::errors::DnsError> {
+    use crate::header::{Header, FLAG_RESPONSE};
+    use crate::packet::Packet;
+    use crate::query::CLASS_IN;
+    use crate::question::Question;
+    use crate::socket::{MockData, MockKey, MockSocket};
+
+    let probe = Query {
+        domain_name: ".".into(),
+        record_type: RecordType::NS,
+        options: QueryOptions {
+            recursion_desired: true,
+            ..Default::default()
+        },
+    };
+    let query_bytes = probe.serialize(Some(0))?;
+    let mut response = Packet {
+        header: Header {
+            id: u16::from_be_bytes([query_bytes[0], query_bytes[1]]),
+            flags: FLAG_RESPONSE,
+            num_questions: 1,
+            ..Default::default()
+        },
+        questions: vec![Question {
+            name: ".".into(),
+            q_type: RecordType::NS,
+            q_class: CLASS_IN,
+        }],
+        answers: vec![],
+        authorities: vec![],
+        additionals: vec![],
+    }
+    .serialize()?;
+    response.resize(4096, 0);
+    let responding = |server_ip: &str| {
+        let mut socket = MockSocket::default();
+        socket.register_response_data([(
+            MockKey {
+                query_bytes: query_bytes.clone(),
+                server_ip: server_ip.to_owned(),
+            },
+            MockData {
+                data: response.clone(),
+            },
+        )]);
+        socket
+    };
+
+    let upstreams = ["192.0.2.1:53", "192.0.2.2:53"];
+    let mut pool = UpstreamPool::new(PoolStrategy::Failover);
+    pool.config.probe_interval = Duration::ZERO;
+
+    let mut socket = responding("192.0.2.2:53");
+    for _ in 0..3 {
+        assert_eq!(pool.probe(&mut socket, &upstreams, Some(0)), 1);
+    }
+    assert!(pool.is_ejected("192.0.2.1:53"));
+    assert_eq!(pool.order(&upstreams), [&"192.0.2.2:53"]);
+
+    let mut socket = responding("192.0.2.1:53");
+    assert_eq!(pool.probe(&mut socket, &upstreams, Some(0)), 1);
+    assert!(!pool.is_ejected("192.0.2.1:53"));
+
+    // Upstreams probed recently are left alone.
+    pool.config.probe_interval = Duration::from_secs(3600);
+    assert_eq!(pool.probe(&mut socket, &upstreams, Some(0)), 0);
+    Ok(())
+}
@@ -0,0 +1,289 @@
+use crate::errors::DnsError;
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use log::info;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+use std::net::IpAddr;
+
+/// How many random names to probe. Wildcards answering with a rotating set of addresses show
+/// more of them with each probe.
+const PROBES: usize = 3;
+
+/// The length of the random labels probed, long enough for no zone to hold them by chance.
+const PROBE_LABEL_LENGTH: usize = 16;
+
+/// The characters random labels are made of.
+const PROBE_LABEL_CHARACTERS: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+
+/// Addresses synthesized for names which do not exist under a domain, whether by a wildcard
+/// record (RFC 4592) or by a server answering every name (catch-all answers, e.g. from a
+/// resolver rewriting NXDOMAIN responses).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Wildcard {
+    /// The domain the names were probed under, e.g. `example.com`.
+    pub domain_name: String,
+
+    /// The random names probed, e.g. `q3vx0c7kd1m2a9ze.example.com`.
+    pub probe_names: Vec<String>,
+
+    /// The addresses the probed names resolved to, sorted and without duplicates.
+    pub addresses: Vec<IpAddr>,
+}
+
+impl Wildcard {
+    /// Whether the given addresses of a name are the synthesized ones, so that the name need not
+    /// exist: all of them are among the addresses of the wildcard.
+    ///
+    /// # Argument
+    /// * `addresses`: The addresses of the name.
+    pub fn matches(&self, addresses: &[IpAddr]) -> bool {
+        !addresses.is_empty()
+            && addresses
+                .iter()
+                .all(|address| self.addresses.contains(address))
+    }
+}
+
+impl fmt::Display for Wildcard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addresses: Vec<String> = self.addresses.iter().map(IpAddr::to_string).collect();
+        write!(f, "*.{}: {}", self.domain_name, addresses.join(", "))
+    }
+}
+
+/// Random names under a domain, which should not exist.
+///
+/// # Arguments
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `count`: How many names to make up.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub(crate) fn random_names(
+    domain_name: &str,
+    count: usize,
+    rand_seed: Option<usize>,
+) -> Vec<String> {
+    let mut rng = match rand_seed {
+        None => ChaCha8Rng::from_entropy(),
+        Some(value) => ChaCha8Rng::seed_from_u64(value as u64),
+    };
+    (0..count)
+        .map(|_| {
+            let label: String = (0..PROBE_LABEL_LENGTH)
+                .map(|_| {
+                    let index = rng.gen_range(0..PROBE_LABEL_CHARACTERS.len());
+                    PROBE_LABEL_CHARACTERS[index] as char
+                })
+                .collect();
+            format!("{}.{}", label, domain_name)
+        })
+        .collect()
+}
+
+/// Detect whether names which do not exist under a domain resolve anyway, by looking up the
+/// addresses of random names under it. `None` when the first name does not exist, as it should.
+///
+/// # Arguments
+/// * `resolver`: The resolver to look up the addresses with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `domain_name`: The domain, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn detect_wildcard(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    domain_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<Option<Wildcard>, DnsError> {
+    let domain_name = domain_name.trim_end_matches('.');
+    let mut wildcard = Wildcard {
+        domain_name: domain_name.to_owned(),
+        probe_names: Vec::new(),
+        addresses: Vec::new(),
+    };
+    for name in random_names(domain_name, PROBES, rand_seed) {
+        let addresses = match resolver.lookup_ip(socket, &name, rand_seed) {
+            Ok(addresses) => addresses,
+            Err(DnsError::UnknownDomainName) => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        // A wildcard with records of other types only still leaves no addresses to mistake.
+        if addresses.is_empty() {
+            if wildcard.probe_names.is_empty() {
+                return Ok(None);
+            }
+            continue;
+        }
+        info!("{} resolved to {:?}", name, addresses);
+        wildcard.probe_names.push(name);
+        wildcard.addresses.extend(addresses);
+    }
+    wildcard.addresses.sort();
+    wildcard.addresses.dedup();
+    Ok(Some(wildcard))
+}
+
+/// Validate detecting a wildcard from the addresses of random names, and its absence from a name
+/// error.
+#[test]
+fn test_detecting_wildcard() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let names = random_names("example.com", PROBES, Some(0));
+    assert_eq!(names.len(), PROBES);
+    assert!(names
+        .iter()
+        .all(|name| name.len() == PROBE_LABEL_LENGTH + 12));
+    assert_ne!(names[0], names[1]);
+    assert_eq!(names, random_names("example.com", PROBES, Some(0)));
+
+    let mut exchanges = Vec::new();
+    for (name, address) in names.iter().zip(["192.0.2.80", "192.0.2.81", "192.0.2.80"]) {
+        exchanges.push(
+            MockResolution::new(name, RecordType::AAAA, 0)
+                .answer("")
+                .build()?,
+        );
+        exchanges.push(
+            MockResolution::new(name, RecordType::A, 0)
+                .answer(&format!("{}. 300 IN A {}", name, address))
+                .build()?,
+        );
+    }
+    let names = random_names("example.org", PROBES, Some(0));
+    for record_type in [RecordType::AAAA, RecordType::A] {
+        exchanges.push(
+            MockResolution::new(&names[0], record_type, 0)
+                .name_error()
+                .build()?,
+        );
+    }
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let wildcard = detect_wildcard(&resolver, &mut socket, "example.com.", Some(0))?.unwrap();
+    assert_eq!(wildcard.probe_names.len(), PROBES);
+    assert_eq!(
+        wildcard.to_string(),
+        "*.example.com: 192.0.2.80, 192.0.2.81"
+    );
+    assert!(wildcard.matches(&["192.0.2.81".parse().unwrap()]));
+    assert!(!wildcard.matches(&["192.0.2.81".parse().unwrap(), "192.0.2.1".parse().unwrap()]));
+    assert!(!wildcard.matches(&[]));
+
+    assert_eq!(
+        detect_wildcard(&resolver, &mut socket, "example.org", Some(0))?,
+        None
+    );
+    Ok(())
+}
+
+/// Validate that a first probe name which does not exist means there is no wildcard, whether the
+/// resolution from the root or a server asked directly tells so, and that other failures are
+/// returned rather than taken for the absence of a wildcard.
+#[test]
+fn test_detecting_no_wildcard_from_name_error() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let names = random_names("example.com", PROBES, Some(0));
+    let mut exchanges = Vec::new();
+    for record_type in [RecordType::AAAA, RecordType::A] {
+        let resolution = MockResolution::new(&names[0], record_type, 0)
+            .name_error()
+            .build()?;
+        exchanges.extend(resolution);
+    }
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+    let resolver = Resolver::new(ResolverConfig::default());
+    assert_eq!(
+        detect_wildcard(&resolver, &mut socket, "example.com", Some(0))?,
+        None
+    );
+    assert_eq!(socket.take_reply().err(), Some(DnsError::SocketRead(None)));
+
+    // A server asked directly returns its NXDOMAIN response as is, without any address.
+    let mut exchanges = Vec::new();
+    for record_type in [RecordType::AAAA, RecordType::A] {
+        let resolution = MockResolution::new(&names[0], record_type, 0)
+            .recursion_desired()
+            .at("192.0.2.53")
+            .name_error()
+            .build()?;
+        exchanges.extend(resolution);
+    }
+    socket.register_response_data(exchanges);
+    let mut stub = Resolver::new(ResolverConfig::default());
+    stub.query_options.recursion_desired = true;
+    stub.servers = vec!["192.0.2.53:53".to_owned()];
+    assert_eq!(
+        detect_wildcard(&stub, &mut socket, "example.com", Some(0))?,
+        None
+    );
+
+    // Without any response, whether there is a wildcard is unknown.
+    socket.register_response_data([]);
+    assert!(detect_wildcard(&stub, &mut socket, "example.com", Some(0)).is_err());
+    Ok(())
+}
+
+/// Validate that the addresses of every probe name which resolves make up the wildcard, and that
+/// a later probe name which does not exist is passed over rather than ending the detection.
+#[test]
+fn test_detecting_wildcard_across_probes() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::record::RecordType;
+    use crate::resolver_config::ResolverConfig;
+    use crate::socket::MockSocket;
+
+    let names = random_names("example.com", PROBES, Some(0));
+    let mut exchanges = Vec::new();
+    let answers = [
+        Some("192.0.2.81\n192.0.2.80"),
+        None,
+        Some("192.0.2.82\n192.0.2.81"),
+    ];
+    for (name, addresses) in names.iter().zip(answers) {
+        let Some(addresses) = addresses else {
+            for record_type in [RecordType::AAAA, RecordType::A] {
+                let resolution = MockResolution::new(name, record_type, 0)
+                    .name_error()
+                    .build()?;
+                exchanges.extend(resolution);
+            }
+            continue;
+        };
+        let records: Vec<String> = addresses
+            .lines()
+            .map(|address| format!("{}. 300 IN A {}", name, address))
+            .collect();
+        exchanges.extend(
+            MockResolution::new(name, RecordType::AAAA, 0)
+                .answer("")
+                .build()?,
+        );
+        exchanges.extend(
+            MockResolution::new(name, RecordType::A, 0)
+                .answer(&records.join("\n"))
+                .build()?,
+        );
+    }
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges);
+
+    let resolver = Resolver::new(ResolverConfig::default());
+    let wildcard = detect_wildcard(&resolver, &mut socket, "example.com", Some(0))?.unwrap();
+    assert_eq!(wildcard.probe_names, [names[0].clone(), names[2].clone()]);
+    assert_eq!(
+        wildcard.to_string(),
+        "*.example.com: 192.0.2.80, 192.0.2.81, 192.0.2.82"
+    );
+    Ok(())
+}
@@ -0,0 +1,1038 @@
+use crate::dns_name::DnsName;
+use crate::errors::DnsError;
+use crate::rdata;
+use crate::record::{Record, RecordType};
+use crate::record_name::RecordName;
+use crate::ttl::Ttl;
+use log::error;
+use std::collections::BTreeMap;
+use std::io::Cursor;
+
+/// How many CNAME records are followed within a zone before giving up on a chain.
+const MAX_CNAME_CHAIN_LENGTH: usize = 8;
+
+/// Class values which may be given in a zone file, as specified in RFC 1035, section 3.2.4.
+const CLASSES: [(&str, u16); 3] = [("IN", 1), ("CH", 3), ("HS", 4)];
+
+/// The records of a zone, read from a master file as specified in RFC 1035, section 5.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Zone {
+    /// The name of the zone without a trailing dot: the owner of its SOA record, or else the
+    /// origin the file was parsed with. Empty for the root zone.
+    pub origin: String,
+
+    /// The records of the zone in the order they appear in the file. Names are absolute and
+    /// record data is in uncompressed wire format.
+    pub records: Vec<Record>,
+
+    /// The positions in `records` of the records owned by each name, keyed by `index_key()` so
+    /// that the names below a name sort right after it.
+    names: BTreeMap<String, Vec<usize>>,
+}
+
+/// The outcome of looking up a name within a zone, following RFC 1034, section 4.3.2.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ZoneLookup {
+    /// Records answering the question, possibly preceded by the CNAME records leading to them.
+    Answer(Vec<Record>),
+
+    /// The name lies in a delegated child zone. Holds the NS records of the delegation and the
+    /// address records of name servers which lie within the zone (glue).
+    Referral {
+        name_servers: Vec<Record>,
+        glue: Vec<Record>,
+    },
+
+    /// The name exists but has no records of the type in question.
+    NoData,
+
+    /// The name does not exist.
+    NameError,
+}
+
+/// One entry of a master file, which may span several lines within parentheses.
+struct Entry {
+    /// The line the entry starts on, for error messages.
+    line: usize,
+
+    /// Whether the entry starts with whitespace, meaning that it has no owner name of its own.
+    inherits_owner: bool,
+
+    /// The fields of the entry. Quoted strings are a single field without the quotes.
+    fields: Vec<String>,
+}
+
+impl Zone {
+    /// Create a zone out of the given records.
+    ///
+    /// # Arguments
+    /// * `origin`: The name of the zone, with or without a trailing dot.
+    /// * `records`: The records of the zone, with absolute names.
+    pub fn new(origin: &str, records: Vec<Record>) -> Zone {
+        let mut names: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for (position, record) in records.iter().enumerate() {
+            let key = index_key(&normalize_name(&record.name.to_string()));
+            names.entry(key).or_default().push(position);
+        }
+        Zone {
+            origin: origin.strip_suffix('.').unwrap_or(origin).to_owned(),
+            records,
+            names,
+        }
+    }
+
+    /// Read the master file at the given path.
+    ///
+    /// # Arguments
+    /// * `path`: Path to the master file.
+    /// * `origin`: The origin of the zone until a `$ORIGIN` directive says otherwise.
+    pub fn from_file(path: &str, origin: &str) -> Result<Zone, DnsError> {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Err(DnsError::ParseZone);
+        };
+        Self::parse(&contents, origin)
+    }
+
+    /// Parse the contents of a master file. `$ORIGIN` and `$TTL` directives, relative names,
+    /// `@`, omitted owners, TTLs and classes, comments and parenthesized multi-line records are
+    /// supported. Records without a TTL use the one given by `$TTL`, or else the last TTL given
+    /// explicitly.
+    ///
+    /// # Arguments
+    /// * `contents`: The contents of a master file.
+    /// * `origin`: The origin of the zone until a `$ORIGIN` directive says otherwise.
+    pub fn parse(contents: &str, origin: &str) -> Result<Zone, DnsError> {
+        let mut records = vec![];
+        let mut current_origin = origin.strip_suffix('.').unwrap_or(origin).to_owned();
+        let mut default_ttl: Option<u32> = None;
+        let mut last_ttl: Option<u32> = None;
+        let mut last_owner: Option<String> = None;
+
+        for entry in tokenize(contents)? {
+            let result = Self::parse_entry(
+                &entry,
+                &mut current_origin,
+                &mut default_ttl,
+                &mut last_ttl,
+                &mut last_owner,
+            );
+            match result {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => {}
+                Err(parse_error) => {
+                    error!("Invalid entry on line {} of the zone file", entry.line);
+                    return Err(parse_error);
+                }
+            }
+        }
+
+        let mut zone = Zone::new(origin, records);
+        if let Some(soa) = zone
+            .records
+            .iter()
+            .find(|record| record.r_type == RecordType::SOA)
+        {
+            if let Ok(name) = String::from_utf8(soa.name.to_vec()) {
+                zone.origin = name;
+            }
+        }
+        Ok(zone)
+    }
+
+    /// Render the zone in presentation format with absolute names. Parsing the output yields the
+    /// same records.
+    pub fn to_presentation(&self) -> String {
+        write_records(&self.records)
+    }
+
+    /// The SOA record at the apex of the zone, if the zone has one.
+    pub fn soa(&self) -> Option<&Record> {
+        self.records
+            .iter()
+            .find(|record| record.r_type == RecordType::SOA && record.name == self.origin.as_str())
+    }
+
+    /// Whether the given name lies within the zone, including names delegated to child zones.
+    ///
+    /// # Argument
+    /// * `domain_name`: The name to check.
+    pub fn contains(&self, domain_name: &str) -> bool {
+        is_subdomain(
+            &normalize_name(domain_name),
+            &self.origin.to_ascii_lowercase(),
+        )
+    }
+
+    /// Look up the records answering a question about a name within the zone. Delegations to
+    /// child zones are honored, answers are synthesized from wildcard records as specified in
+    /// RFC 4592, and CNAME chains are followed as far as they stay within the zone.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The name in question, which must lie within the zone.
+    /// * `record_type`: The type in question.
+    pub fn lookup(&self, domain_name: &str, record_type: RecordType) -> ZoneLookup {
+        let mut answers = Vec::new();
+        let mut domain_name = normalize_name(domain_name);
+
+        for _ in 0..MAX_CNAME_CHAIN_LENGTH {
+            // A delegation anywhere between the apex and the name takes precedence over the
+            // records at the name itself, except for the DS records held by the parent.
+            if let Some(referral) = self.referral(&domain_name, record_type) {
+                return match answers.is_empty() {
+                    true => referral,
+                    false => ZoneLookup::Answer(answers),
+                };
+            }
+
+            let mut records_at_name: Vec<Record> = self.records_at(&domain_name).cloned().collect();
+            // Names without records of their own which have descendants (empty non-terminals)
+            // still exist, so only names which do not exist at all match wildcards.
+            let has_descendants = self.has_descendants(&domain_name);
+            if records_at_name.is_empty() && !has_descendants {
+                records_at_name = self.synthesize_from_wildcard(&domain_name);
+            }
+            if records_at_name.is_empty() {
+                if !answers.is_empty() {
+                    return ZoneLookup::Answer(answers);
+                }
+                return match has_descendants {
+                    true => ZoneLookup::NoData,
+                    false => ZoneLookup::NameError,
+                };
+            }
+
+            // ANY queries are answered with every record at the name.
+            let matching: Vec<Record> = records_at_name
+                .iter()
+                .filter(|record| record_type == RecordType::ANY || record.r_type == record_type)
+                .cloned()
+                .collect();
+            if !matching.is_empty() {
+                answers.extend(matching);
+                return ZoneLookup::Answer(answers);
+            }
+
+            let Some(cname) = records_at_name
+                .iter()
+                .find(|record| record.r_type == RecordType::CNAME)
+            else {
+                return match answers.is_empty() {
+                    true => ZoneLookup::NoData,
+                    false => ZoneLookup::Answer(answers),
+                };
+            };
+            answers.push(cname.clone());
+
+            let Some(target) = name_in_data(&cname.data) else {
+                return ZoneLookup::Answer(answers);
+            };
+            if !self.contains(&target) {
+                return ZoneLookup::Answer(answers);
+            }
+            domain_name = target;
+        }
+
+        ZoneLookup::Answer(answers)
+    }
+
+    /// The referral to a child zone for the given name, if the name lies at or below a delegation.
+    ///
+    /// # Arguments
+    /// * `domain_name`: The normalized name in question.
+    /// * `record_type`: The type in question.
+    fn referral(&self, domain_name: &str, record_type: RecordType) -> Option<ZoneLookup> {
+        let origin = self.origin.to_ascii_lowercase();
+        let labels: Vec<&str> = match domain_name.is_empty() {
+            true => vec![],
+            false => domain_name.split('.').collect(),
+        };
+        let origin_label_count = match origin.is_empty() {
+            true => 0,
+            false => origin.split('.').count(),
+        };
+
+        // Walk from just below the apex down to the name itself.
+        for label_count in (origin_label_count + 1)..=labels.len() {
+            let cut = labels[labels.len() - label_count..].join(".");
+            if cut == domain_name && record_type == RecordType::DS {
+                continue;
+            }
+
+            let name_servers: Vec<Record> = self
+                .records_at(&cut)
+                .filter(|record| record.r_type == RecordType::NS)
+                .cloned()
+                .collect();
+            if name_servers.is_empty() {
+                continue;
+            }
+
+            let glue = name_servers
+                .iter()
+                .filter_map(|record| name_in_data(&record.data))
+                .flat_map(|name_server| {
+                    self.records_at(&name_server)
+                        .filter(|record| matches!(record.r_type, RecordType::A | RecordType::AAAA))
+                        .cloned()
+                        .collect::<Vec<Record>>()
+                })
+                .collect();
+            return Some(ZoneLookup::Referral { name_servers, glue });
+        }
+        None
+    }
+
+    /// Whether any record is owned by a name below the given normalized name.
+    ///
+    /// # Argument
+    /// * `domain_name`: The normalized name to check.
+    fn has_descendants(&self, domain_name: &str) -> bool {
+        let key = index_key(domain_name);
+        if key.is_empty() {
+            return self.names.keys().any(|name| !name.is_empty());
+        }
+        let prefix = format!("{}.", key);
+        self.names
+            .range(prefix.clone()..)
+            .next()
+            .is_some_and(|(name, _)| name.starts_with(&prefix))
+    }
+
+    /// The records synthesized for a name which does not exist from the wildcard at its closest
+    /// encloser, as specified in RFC 4592, section 3.3.1. The closest encloser is the nearest
+    /// ancestor which exists. Only the wildcard directly below it matches, so none is used when a
+    /// closer name exists without a wildcard of its own.
+    ///
+    /// # Argument
+    /// * `domain_name`: The normalized name which does not exist.
+    fn synthesize_from_wildcard(&self, domain_name: &str) -> Vec<Record> {
+        let origin = self.origin.to_ascii_lowercase();
+        let mut encloser = domain_name;
+        while let Some((_, parent)) = encloser.split_once('.') {
+            encloser = parent;
+            if !is_subdomain(encloser, &origin) {
+                break;
+            }
+            if self.records_at(encloser).next().is_none() && !self.has_descendants(encloser) {
+                continue;
+            }
+
+            let source = format!("*.{}", encloser);
+            return self
+                .records_at(&source)
+                .map(|record| Record {
+                    name: DnsName::from(domain_name),
+                    ..record.clone()
+                })
+                .collect();
+        }
+        vec![]
+    }
+
+    /// The records owned by the given normalized name.
+    fn records_at(&self, domain_name: &str) -> impl Iterator<Item = &Record> + '_ {
+        self.names
+            .get(&index_key(domain_name))
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.records[position])
+    }
+
+    /// Parse a single entry, which is either a directive or a record.
+    ///
+    /// # Arguments
+    /// * `entry`: The entry to parse.
+    /// * `origin`: The current origin, updated by `$ORIGIN`.
+    /// * `default_ttl`: The TTL set by `$TTL`, if any.
+    /// * `last_ttl`: The last TTL given explicitly in a record.
+    /// * `last_owner`: The owner of the previous record.
+    fn parse_entry(
+        entry: &Entry,
+        origin: &mut String,
+        default_ttl: &mut Option<u32>,
+        last_ttl: &mut Option<u32>,
+        last_owner: &mut Option<String>,
+    ) -> Result<Option<Record>, DnsError> {
+        let mut fields = entry.fields.iter().map(String::as_str).peekable();
+
+        if !entry.inherits_owner {
+            match fields
+                .peek()
+                .map(|field| field.to_ascii_uppercase())
+                .as_deref()
+            {
+                Some("$ORIGIN") => {
+                    let [_, name] = entry.fields.as_slice() else {
+                        return Err(DnsError::ParseZone);
+                    };
+                    *origin = absolute_name(name, origin)?;
+                    return Ok(None);
+                }
+                Some("$TTL") => {
+                    let [_, ttl] = entry.fields.as_slice() else {
+                        return Err(DnsError::ParseZone);
+                    };
+                    let Some(ttl) = parse_ttl(ttl) else {
+                        return Err(DnsError::ParseZone);
+                    };
+                    *default_ttl = Some(ttl);
+                    return Ok(None);
+                }
+                Some(directive) if directive.starts_with('$') => {
+                    return Err(DnsError::ParseZone);
+                }
+                _ => {}
+            }
+        }
+
+        let owner = match entry.inherits_owner {
+            true => last_owner.clone(),
+            false => fields
+                .next()
+                .map(|name| absolute_name(name, origin))
+                .transpose()?,
+        };
+        let Some(owner) = owner else {
+            return Err(DnsError::ParseZone);
+        };
+
+        // The TTL and class may each be omitted and appear in either order.
+        let mut ttl = None;
+        let mut class = None;
+        for _ in 0..2 {
+            let Some(field) = fields.peek() else { break };
+            if let Some(value) = parse_class(field) {
+                class = Some(value);
+            } else if let Some(value) = parse_ttl(field) {
+                ttl = Some(value);
+            } else {
+                break;
+            }
+            fields.next();
+        }
+
+        let Some(record_type) = fields.next().and_then(RecordType::from_name) else {
+            return Err(DnsError::ParseZone);
+        };
+
+        if ttl.is_some() {
+            *last_ttl = ttl;
+        }
+        let Some(ttl) = ttl.or(*default_ttl).or(*last_ttl) else {
+            return Err(DnsError::ParseZone);
+        };
+
+        let data_fields: Vec<&str> = fields.collect();
+        let data = rdata::from_presentation(record_type, &data_fields, origin)?;
+
+        *last_owner = Some(owner.clone());
+        Ok(Some(Record {
+            name: DnsName::from(owner),
+            r_type: record_type,
+            r_class: class.unwrap_or(CLASSES[0].1),
+            ttl: Ttl::from(ttl),
+            data,
+        }))
+    }
+}
+
+/// Split a master file into entries. Comments are dropped, and newlines within parentheses do not
+/// end an entry.
+///
+/// # Argument
+/// * `contents`: The contents of a master file.
+fn tokenize(contents: &str) -> Result<Vec<Entry>, DnsError> {
+    let mut entries = Vec::new();
+    let mut current = Entry {
+        line: 1,
+        inherits_owner: false,
+        fields: vec![],
+    };
+    let mut line = 1;
+    let mut depth = 0;
+    let mut at_entry_start = true;
+    let mut chars = contents.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if at_entry_start {
+            at_entry_start = false;
+            current.line = line;
+            current.inherits_owner = c == ' ' || c == '\t';
+        }
+
+        match c {
+            '\n' => {
+                line += 1;
+                if depth == 0 {
+                    if !current.fields.is_empty() {
+                        entries.push(std::mem::replace(
+                            &mut current,
+                            Entry {
+                                line,
+                                inherits_owner: false,
+                                fields: vec![],
+                            },
+                        ));
+                    }
+                    at_entry_start = true;
+                }
+            }
+            ' ' | '\t' | '\r' => {}
+            ';' => while chars.next_if(|&next| next != '\n').is_some() {},
+            '(' => depth += 1,
+            ')' => {
+                if depth == 0 {
+                    error!("Unbalanced parenthesis on line {} of the zone file", line);
+                    return Err(DnsError::ParseZone);
+                }
+                depth -= 1;
+            }
+            '"' => {
+                let mut field = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => {
+                            field.push('\\');
+                            if let Some(escaped) = chars.next() {
+                                field.push(escaped);
+                            }
+                        }
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                        None => {
+                            error!("Unterminated string on line {} of the zone file", line);
+                            return Err(DnsError::ParseZone);
+                        }
+                    }
+                }
+                current.fields.push(field);
+            }
+            _ => {
+                let mut field = String::from(c);
+                if c == '\\' {
+                    field.extend(chars.next());
+                }
+                while let Some(next) = chars.next_if(|&next| {
+                    !matches!(next, ' ' | '\t' | '\r' | '\n' | ';' | '(' | ')' | '"')
+                }) {
+                    field.push(next);
+                    if next == '\\' {
+                        field.extend(chars.next());
+                    }
+                }
+                current.fields.push(field);
+            }
+        }
+    }
+
+    if depth != 0 {
+        error!("Unbalanced parenthesis on line {} of the zone file", line);
+        return Err(DnsError::ParseZone);
+    }
+    if !current.fields.is_empty() {
+        entries.push(current);
+    }
+    Ok(entries)
+}
+
+/// Make a name from a master file absolute, without a trailing dot. `@` stands for the origin
+/// and names not ending in a dot are relative to it.
+///
+/// # Arguments
+/// * `name`: The name as written in the master file.
+/// * `origin`: The current origin.
+pub(crate) fn absolute_name(name: &str, origin: &str) -> Result<String, DnsError> {
+    // Escaped characters within labels are not supported by the dotted representation of names.
+    if name.contains('\\') || name.is_empty() {
+        return Err(DnsError::ParseZone);
+    }
+
+    if name == "@" {
+        return Ok(origin.to_owned());
+    }
+    if let Some(name) = name.strip_suffix('.') {
+        return Ok(name.to_owned());
+    }
+    if origin.is_empty() {
+        return Ok(name.to_owned());
+    }
+    Ok(format!("{}.{}", name, origin))
+}
+
+/// Parse a TTL given either in seconds or with the unit suffixes `w`, `d`, `h`, `m` and `s`, such
+/// as `1h30m`.
+///
+/// # Argument
+/// * `field`: The TTL as written in the master file.
+pub(crate) fn parse_ttl(field: &str) -> Option<u32> {
+    if let Ok(seconds) = field.parse::<u32>() {
+        return Some(seconds);
+    }
+    if field.is_empty() {
+        return None;
+    }
+
+    let mut total: u32 = 0;
+    let mut value: Option<u32> = None;
+    for c in field.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            value = Some(value.unwrap_or(0).checked_mul(10)?.checked_add(digit)?);
+            continue;
+        }
+        let multiplier = match c.to_ascii_lowercase() {
+            'w' => 604_800,
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(value.take()?.checked_mul(multiplier)?)?;
+    }
+
+    // Every number needs a unit once units are used.
+    match value {
+        Some(_) => None,
+        None => Some(total),
+    }
+}
+
+/// Names are compared case-insensitively and without a trailing dot.
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// The key of a normalized name in the index of a zone: its labels from the root down, e.g.
+/// `com.example.www` for `www.example.com`.
+fn index_key(domain_name: &str) -> String {
+    domain_name.rsplit('.').collect::<Vec<&str>>().join(".")
+}
+
+/// Whether the normalized name equals or lies below the normalized ancestor.
+fn is_subdomain(domain_name: &str, ancestor: &str) -> bool {
+    ancestor.is_empty()
+        || domain_name == ancestor
+        || domain_name
+            .strip_suffix(ancestor)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// The normalized name at the start of uncompressed record data, such as that of NS and CNAME
+/// records.
+fn name_in_data(data: &[u8]) -> Option<String> {
+    let name = RecordName::read_and_advance(&mut Cursor::new(data)).ok()?;
+    Some(normalize_name(&String::from_utf8(name).ok()?))
+}
+
+/// Look up a class by its mnemonic.
+fn parse_class(field: &str) -> Option<u16> {
+    if let Some(value) = CLASSES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(field))
+        .map(|(_, value)| *value)
+    {
+        return Some(value);
+    }
+
+    // Classes without a mnemonic are written as `CLASS` followed by their value.
+    let value = field
+        .get(..5)?
+        .eq_ignore_ascii_case("CLASS")
+        .then(|| &field[5..])?;
+    value.parse().ok()
+}
+
+/// The mnemonic of a class value, or `CLASS` followed by the value for classes without one, as
+/// specified in RFC 3597, section 5.
+///
+/// # Argument
+/// * `value`: The class value.
+pub(crate) fn class_name(value: u16) -> String {
+    match CLASSES.iter().find(|(_, class)| *class == value) {
+        Some((name, _)) => (*name).to_owned(),
+        None => format!("CLASS{}", value),
+    }
+}
+
+/// Render records in presentation format, one per line, so that they can be saved as a zone
+/// snippet or passed on to other DNS tools.
+///
+/// # Argument
+/// * `records`: The records to render, e.g. a section of a `Packet`.
+pub fn write_records(records: &[Record]) -> String {
+    let mut output = String::new();
+    for record in records {
+        output.push_str(&record.to_presentation());
+        output.push('\n');
+    }
+    output
+}
+
+#[cfg(test)]
+const EXAMPLE_ZONE: &str = r#"
+$ORIGIN example.com.
+$TTL 1h
+@   IN  SOA ns1 hostmaster (
+            2024010101 ; serial
+            1d         ; refresh
+            2h         ; retry
+            4w         ; expire
+            1h )       ; minimum
+        IN  NS  ns1
+        IN  NS  ns2.example.net.
+        IN  MX  10 mail
+ns1     300 IN A 192.0.2.1
+        IN  AAAA 2001:db8::1
+mail    IN  600 A 192.0.2.2
+www     CNAME @
+txt     TXT "v=spf1 -all" "second ; not a comment"
+$ORIGIN sub
+host    A   192.0.2.3
+"#;
+
+/// Validate parsing of a master file with directives, relative names and multi-line records.
+#[test]
+fn test_parsing_zone() -> Result<(), DnsError> {
+    let zone = Zone::parse(EXAMPLE_ZONE, "ignored.test")?;
+    assert_eq!(zone.origin, "example.com");
+
+    let summary: Vec<(String, RecordType, u32)> = zone
+        .records
+        .iter()
+        .map(|record| (record.name.to_string(), record.r_type, record.ttl.as_secs()))
+        .collect();
+    let expected = [
+        ("example.com", RecordType::SOA, 3600),
+        ("example.com", RecordType::NS, 3600),
+        ("example.com", RecordType::NS, 3600),
+        ("example.com", RecordType::MX, 3600),
+        ("ns1.example.com", RecordType::A, 300),
+        ("ns1.example.com", RecordType::AAAA, 3600),
+        ("mail.example.com", RecordType::A, 600),
+        ("www.example.com", RecordType::CNAME, 3600),
+        ("txt.example.com", RecordType::TXT, 3600),
+        ("host.sub.example.com", RecordType::A, 3600),
+    ];
+    assert_eq!(
+        summary,
+        expected.map(|(name, record_type, ttl)| (name.to_owned(), record_type, ttl))
+    );
+    assert!(zone.records.iter().all(|record| record.r_class == 1));
+
+    let soa = &zone.records[0];
+    assert_eq!(
+        soa.data[..30],
+        [
+            3, b'n', b's', b'1', 7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm',
+            0, 10, b'h', b'o', b's', b't', b'm', b'a', b's', b't', b'e', b'r', 7, b'e'
+        ]
+    );
+    assert_eq!(
+        soa.data[soa.data.len() - 20..],
+        [120, 163, 241, 117, 0, 1, 81, 128, 0, 0, 28, 32, 0, 36, 234, 0, 0, 0, 14, 16]
+    );
+    assert_eq!(
+        zone.records[8].data,
+        [
+            [&[11][..], b"v=spf1 -all"].concat(),
+            [&[22][..], b"second ; not a comment"].concat()
+        ]
+        .concat()
+    );
+    Ok(())
+}
+
+/// Validate that records without any applicable TTL inherit the last explicit one.
+#[test]
+fn test_parsing_zone_without_default_ttl() -> Result<(), DnsError> {
+    let zone = Zone::parse("a 120 A 192.0.2.1\nb A 192.0.2.2\n", "example.com")?;
+    assert_eq!(zone.records[1].name, "b.example.com");
+    assert_eq!(zone.records[1].ttl, 120);
+
+    assert_eq!(
+        Zone::parse("a A 192.0.2.1\n", "example.com"),
+        Err(DnsError::ParseZone)
+    );
+    Ok(())
+}
+
+/// Validate that malformed master files are rejected.
+#[test]
+fn test_parsing_invalid_zones() {
+    let invalid_zones = [
+        "$TTL 60\n  A 192.0.2.1\n",
+        "$TTL 60\na ( A 192.0.2.1\n",
+        "$TTL 60\na A 192.0.2.1 )\n",
+        "$TTL 60\na TXT \"unterminated\n",
+        "$TTL 60\na BOGUS data\n",
+        "$TTL 60\n$INCLUDE other.zone\n",
+        "$TTL 1x\n",
+    ];
+    for zone in invalid_zones {
+        assert_eq!(Zone::parse(zone, "example.com"), Err(DnsError::ParseZone));
+    }
+}
+
+/// Validate parsing of TTLs with and without units.
+#[test]
+fn test_parse_ttl() {
+    assert_eq!(parse_ttl("3600"), Some(3600));
+    assert_eq!(parse_ttl("1h30m"), Some(5400));
+    assert_eq!(parse_ttl("1W2D"), Some(777600));
+    assert_eq!(parse_ttl("1h30"), None);
+    assert_eq!(parse_ttl("IN"), None);
+    assert_eq!(parse_ttl(""), None);
+}
+
+/// Validate completion of relative names.
+#[test]
+fn test_absolute_name() -> Result<(), DnsError> {
+    assert_eq!(absolute_name("www", "example.com")?, "www.example.com");
+    assert_eq!(absolute_name("www.test.", "example.com")?, "www.test");
+    assert_eq!(absolute_name("@", "example.com")?, "example.com");
+    assert_eq!(absolute_name(".", "example.com")?, "");
+    assert_eq!(absolute_name("com", "")?, "com");
+    assert!(absolute_name(r"a\.b", "example.com").is_err());
+    Ok(())
+}
+
+/// Validate that a zone rendered in presentation format parses back into the same records.
+#[test]
+fn test_zone_presentation_round_trip() -> Result<(), DnsError> {
+    let zone = Zone::parse(EXAMPLE_ZONE, "")?;
+    let written = zone.to_presentation();
+    assert!(written.starts_with(
+        "example.com. 3600 IN SOA ns1.example.com. hostmaster.example.com. 2024010101 86400 7200 \
+         2419200 3600\nexample.com. 3600 IN NS ns1.example.com.\n"
+    ));
+    assert!(written.contains("\nwww.example.com. 3600 IN CNAME example.com.\n"));
+
+    assert_eq!(Zone::parse(&written, "")?, zone);
+    Ok(())
+}
+
+/// Validate rendering of individual records, including ones read from the wire.
+#[test]
+fn test_write_records() {
+    let records = [
+        Record {
+            name: DnsName::from("twitter.com"),
+            r_type: RecordType::A,
+            r_class: 1,
+            ttl: Ttl::from(1800),
+            data: vec![104, 244, 42, 193],
+        },
+        Record {
+            name: DnsName::root(),
+            r_type: RecordType::NS,
+            r_class: 3,
+            ttl: Ttl::from(0),
+            // Truncated data falls back to the generic format.
+            data: vec![1, b'a'],
+        },
+        Record {
+            name: DnsName::from("example.com"),
+            r_type: RecordType::TXT,
+            r_class: 255,
+            ttl: Ttl::from(60),
+            data: vec![2, b'h', b'i'],
+        },
+    ];
+
+    assert_eq!(
+        write_records(&records),
+        "twitter.com. 1800 IN A 104.244.42.193\n\
+         . 0 CH NS \\# 2 0161\n\
+         example.com. 60 CLASS255 TXT \"hi\"\n"
+    );
+    assert_eq!(parse_class("class255"), Some(255));
+    assert_eq!(parse_class("CLASS"), None);
+}
+
+#[cfg(test)]
+const LOOKUP_ZONE: &str = "
+$ORIGIN example.com.
+$TTL 300
+@        SOA ns1 hostmaster 1 3600 900 604800 60
+         NS  ns1
+ns1      A   192.0.2.1
+www      A   192.0.2.2
+         A   192.0.2.3
+alias    CNAME www
+outside  CNAME www.example.net.
+a.b.c    TXT \"deep\"
+child    NS  ns.child
+         DS  60485 5 1 2BB183AF
+ns.child A   192.0.2.4
+";
+
+/// Validate answers, CNAME chains, NODATA and NXDOMAIN outcomes of zone lookups.
+#[test]
+fn test_zone_lookup_answers() -> Result<(), DnsError> {
+    let zone = Zone::parse(LOOKUP_ZONE, "")?;
+    assert_eq!(zone.origin, "example.com");
+    assert_eq!(zone.soa().map(|soa| soa.r_type), Some(RecordType::SOA));
+    assert!(zone.contains("WWW.Example.com."));
+    assert!(!zone.contains("badexample.com"));
+
+    let ZoneLookup::Answer(answers) = zone.lookup("WWW.example.com", RecordType::A) else {
+        panic!("Expected an answer");
+    };
+    assert_eq!(answers.len(), 2);
+
+    let ZoneLookup::Answer(answers) = zone.lookup("alias.example.com", RecordType::A) else {
+        panic!("Expected an answer");
+    };
+    let types: Vec<RecordType> = answers.iter().map(|record| record.r_type).collect();
+    assert_eq!(types, [RecordType::CNAME, RecordType::A, RecordType::A]);
+
+    // Chains leaving the zone end with the CNAME record.
+    let ZoneLookup::Answer(answers) = zone.lookup("outside.example.com", RecordType::A) else {
+        panic!("Expected an answer");
+    };
+    assert_eq!(answers.len(), 1);
+
+    assert_eq!(
+        zone.lookup("www.example.com", RecordType::MX),
+        ZoneLookup::NoData
+    );
+    assert_eq!(
+        zone.lookup("b.c.example.com", RecordType::A),
+        ZoneLookup::NoData
+    );
+    assert_eq!(
+        zone.lookup("nope.example.com", RecordType::A),
+        ZoneLookup::NameError
+    );
+    Ok(())
+}
+
+/// Validate referrals to delegated child zones.
+#[test]
+fn test_zone_lookup_referrals() -> Result<(), DnsError> {
+    let zone = Zone::parse(LOOKUP_ZONE, "")?;
+
+    let ZoneLookup::Referral { name_servers, glue } =
+        zone.lookup("host.child.example.com", RecordType::A)
+    else {
+        panic!("Expected a referral");
+    };
+    assert_eq!(name_servers.len(), 1);
+    assert_eq!(glue.len(), 1);
+    assert_eq!(glue[0].data, [192, 0, 2, 4]);
+
+    // The parent answers for the DS records of the delegation.
+    assert!(matches!(
+        zone.lookup("child.example.com", RecordType::DS),
+        ZoneLookup::Answer(_)
+    ));
+    assert!(matches!(
+        zone.lookup("child.example.com", RecordType::NS),
+        ZoneLookup::Referral { .. }
+    ));
+    Ok(())
+}
+
+/// Validate answers synthesized from wildcards, and names which wildcards do not match.
+#[test]
+fn test_zone_lookup_wildcards() -> Result<(), DnsError> {
+    let zone = Zone::parse(
+        "
+$ORIGIN example.com.
+$TTL 300
+@          SOA ns1 hostmaster 1 3600 900 604800 60
+*          TXT \"wildcard\"
+*.alias    CNAME www
+www        A   192.0.2.1
+host.sub   A   192.0.2.2
+*.child    A   192.0.2.3
+child      NS  ns.elsewhere.net.
+",
+        "",
+    )?;
+
+    let ZoneLookup::Answer(answers) = zone.lookup("Any.Thing.example.com", RecordType::TXT) else {
+        panic!("Expected an answer");
+    };
+    assert_eq!(answers.len(), 1);
+    assert_eq!(answers[0].name, "any.thing.example.com");
+    assert_eq!(
+        zone.lookup("nope.example.com", RecordType::A),
+        ZoneLookup::NoData
+    );
+
+    // Synthesized CNAME records are followed.
+    let ZoneLookup::Answer(answers) = zone.lookup("x.alias.example.com", RecordType::A) else {
+        panic!("Expected an answer");
+    };
+    assert_eq!(answers[0].name, "x.alias.example.com");
+    assert_eq!(answers[1].data, [192, 0, 2, 1]);
+
+    // Existing names, empty non-terminals included, and names below them do not match the
+    // wildcard at the apex.
+    assert_eq!(
+        zone.lookup("www.example.com", RecordType::TXT),
+        ZoneLookup::NoData
+    );
+    assert_eq!(
+        zone.lookup("sub.example.com", RecordType::TXT),
+        ZoneLookup::NoData
+    );
+    assert_eq!(
+        zone.lookup("other.sub.example.com", RecordType::TXT),
+        ZoneLookup::NameError
+    );
+
+    // Wildcards below a delegation belong to the child zone.
+    assert!(matches!(
+        zone.lookup("x.child.example.com", RecordType::A),
+        ZoneLookup::Referral { .. }
+    ));
+
+    // A literal wildcard query matches the wildcard records.
+    assert!(matches!(
+        zone.lookup("*.example.com", RecordType::TXT),
+        ZoneLookup::Answer(_)
+    ));
+    Ok(())
+}
+
+/// Validate that the root zone answers for its apex and refers names below it to their zones.
+#[test]
+fn test_zone_lookup_at_root() -> Result<(), DnsError> {
+    let zone = Zone::parse(
+        "
+$TTL 86400
+.          SOA a.root-servers.net. nstld.verisign-grs.com. 1 1800 900 604800 86400
+.          NS  a.root-servers.net.
+com.       NS  a.gtld-servers.net.
+a.gtld-servers.net. A 192.5.6.30
+",
+        ".",
+    )?;
+    assert_eq!(zone.origin, "");
+
+    let ZoneLookup::Answer(answers) = zone.lookup("", RecordType::NS) else {
+        panic!("Expected an answer");
+    };
+    assert_eq!(answers.len(), 1);
+    assert!(matches!(
+        zone.lookup(".", RecordType::SOA),
+        ZoneLookup::Answer(_)
+    ));
+    assert_eq!(zone.lookup("", RecordType::MX), ZoneLookup::NoData);
+
+    let ZoneLookup::Referral { name_servers, glue } = zone.lookup("example.com", RecordType::A)
+    else {
+        panic!("Expected a referral");
+    };
+    assert_eq!(name_servers.len(), 1);
+    assert_eq!(glue.len(), 1);
+    assert_eq!(zone.lookup("nope", RecordType::A), ZoneLookup::NameError);
+    Ok(())
+}
@@ -0,0 +1,591 @@
+use crate::errors::DnsError;
+use crate::forwarder::DEFAULT_UDP_PORT;
+use crate::packet::Packet;
+use crate::query::{Query, QueryOptions};
+use crate::record::{Record, RecordType};
+use crate::resolver::Resolver;
+use crate::socket::DnsTransport;
+use crate::wildcard::random_names;
+use log::{info, warn};
+use std::fmt;
+use std::net::SocketAddr;
+
+/// How many queries a walk sends at most, unless told otherwise.
+pub const DEFAULT_MAX_QUERIES: usize = 1000;
+
+/// A name of a zone, as listed by the NSEC chain, along with the types of the records it owns.
+#[derive(Debug, PartialEq, Clone)]
+pub struct WalkedName {
+    /// The name, e.g. `www.example.com`.
+    pub name: String,
+
+    /// The types listed in the type bitmap of its NSEC record, e.g. `A` and `RRSIG`.
+    pub types: Vec<String>,
+}
+
+impl fmt::Display for WalkedName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.types.join(" "))
+    }
+}
+
+/// A link of an NSEC3 chain: the hash of a name of the zone and the hash following it. The names
+/// themselves are not revealed, only their hashes, which may be cracked offline.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HashedName {
+    /// The hash of the name, in base32hex as in the owner name of the NSEC3 record.
+    pub hash: String,
+
+    /// The next hash of the chain, in base32hex.
+    pub next_hash: String,
+
+    /// The types listed in the type bitmap of the NSEC3 record.
+    pub types: Vec<String>,
+}
+
+impl fmt::Display for HashedName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} {}",
+            self.hash,
+            self.next_hash,
+            self.types.join(" ")
+        )
+    }
+}
+
+/// How the names of a zone are hashed for its NSEC3 records (RFC 5155, section 3.1).
+#[derive(Debug, PartialEq, Clone)]
+pub struct Nsec3Parameters {
+    /// The hash algorithm, 1 for SHA-1.
+    pub algorithm: u8,
+
+    /// How many more times names are hashed.
+    pub iterations: u16,
+
+    /// The salt in hex, or `-` for none.
+    pub salt: String,
+}
+
+/// The chain of denial-of-existence records a zone is signed with, as far as it was followed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum WalkedChain {
+    /// The names of the zone, in the order of the NSEC chain starting at the apex.
+    Nsec(Vec<WalkedName>),
+
+    /// The hashes of the names of the zone collected from NSEC3 records, sorted.
+    Nsec3 {
+        /// How the names are hashed.
+        parameters: Nsec3Parameters,
+
+        /// The links of the chain.
+        hashes: Vec<HashedName>,
+    },
+}
+
+/// The result of walking a zone.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ZoneWalk {
+    /// The zone walked, e.g. `example.com`.
+    pub zone_name: String,
+
+    /// The names or hashes collected.
+    pub chain: WalkedChain,
+
+    /// Whether the chain was followed all the way back to where it started, so that every name
+    /// of the zone is known, or its hash.
+    pub complete: bool,
+
+    /// How many queries the walk sent.
+    pub queries: usize,
+}
+
+impl ZoneWalk {
+    /// Render the walk: the names or hashes collected, one per line, then a summary.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let summary = match &self.chain {
+            WalkedChain::Nsec(names) => {
+                for name in names {
+                    output.push_str(&format!("{}\n", name));
+                }
+                format!("{} names", names.len())
+            }
+            WalkedChain::Nsec3 { parameters, hashes } => {
+                for hash in hashes {
+                    output.push_str(&format!("{}\n", hash));
+                }
+                format!(
+                    "{} NSEC3 hashes (algorithm {}, {} iterations, salt {})",
+                    hashes.len(),
+                    parameters.algorithm,
+                    parameters.iterations,
+                    parameters.salt
+                )
+            }
+        };
+        output.push_str(&format!(
+            "{}: {}, chain {} after {} queries\n",
+            self.zone_name,
+            summary,
+            match self.complete {
+                true => "complete",
+                false => "incomplete",
+            },
+            self.queries
+        ));
+        output
+    }
+}
+
+/// A name as names are compared: in lowercase, without a trailing dot.
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Whether a name lies in a zone, the apex included.
+///
+/// # Arguments
+/// * `name`: The name, normalized.
+/// * `zone_name`: The name of the zone, normalized.
+fn is_in_zone(name: &str, zone_name: &str) -> bool {
+    name == zone_name || name.ends_with(&format!(".{}", zone_name))
+}
+
+/// Ask a server for records with the DO bit set and without recursion, keeping its response
+/// even when the name does not exist, as the NSEC3 records proving it are what is sought.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS query.
+/// * `server_address`: The address of the server, e.g. `192.0.2.53:53`.
+/// * `domain_name`: The name to ask for.
+/// * `record_type`: The type of records to ask for.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn ask_signed(
+    socket: &mut dyn DnsTransport,
+    server_address: &str,
+    domain_name: &str,
+    record_type: RecordType,
+    rand_seed: Option<usize>,
+) -> Result<Packet, DnsError> {
+    let query = Query {
+        domain_name: domain_name.into(),
+        record_type,
+        options: QueryOptions {
+            dnssec_ok: true,
+            ..Default::default()
+        },
+    };
+    query.perform(socket, server_address, "", rand_seed)
+}
+
+/// The NSEC record owned by a name in a response, as the name it owns and its fields: the next
+/// name of the chain and the types of the name. Looked for in the answer, then in the authority
+/// section where servers put it along with referrals.
+///
+/// # Arguments
+/// * `packet`: The response.
+/// * `name`: The owner of the NSEC record, normalized.
+fn find_nsec(packet: &Packet, name: &str) -> Option<(String, Vec<String>)> {
+    let record = packet
+        .answers
+        .iter()
+        .chain(packet.authorities.iter())
+        .find(|record| {
+            record.r_type == RecordType::NSEC && normalize(&record.name.to_string()) == name
+        })?;
+    let data = record.data_to_presentation();
+    let mut fields = data.split_whitespace();
+    let next_name = normalize(fields.next()?);
+    Some((next_name, fields.map(str::to_owned).collect()))
+}
+
+/// The link of the chain and the hashing parameters of an NSEC3 record.
+///
+/// # Argument
+/// * `record`: The NSEC3 record.
+fn read_nsec3(record: &Record) -> Option<(HashedName, Nsec3Parameters)> {
+    let data = record.data_to_presentation();
+    let fields: Vec<&str> = data.split_whitespace().collect();
+    let [algorithm, _flags, iterations, salt, next_hash, types @ ..] = fields.as_slice() else {
+        return None;
+    };
+    let name = record.name.to_string();
+    let hash = name.split('.').next().unwrap_or_default();
+    let link = HashedName {
+        hash: hash.to_ascii_uppercase(),
+        next_hash: next_hash.to_ascii_uppercase(),
+        types: types.iter().map(|field| field.to_string()).collect(),
+    };
+    let parameters = Nsec3Parameters {
+        algorithm: algorithm.parse().ok()?,
+        iterations: iterations.parse().ok()?,
+        salt: salt.to_string(),
+    };
+    Some((link, parameters))
+}
+
+/// Add the NSEC3 records of a response for names of the zone to the links collected so far,
+/// skipping those already known.
+///
+/// # Arguments
+/// * `packet`: The response.
+/// * `zone_name`: The name of the zone, normalized.
+/// * `hashes`: The links collected so far.
+fn collect_nsec3(packet: &Packet, zone_name: &str, hashes: &mut Vec<HashedName>) {
+    for record in packet.answers.iter().chain(packet.authorities.iter()) {
+        if record.r_type != RecordType::NSEC3
+            || !is_in_zone(&normalize(&record.name.to_string()), zone_name)
+        {
+            continue;
+        }
+        if let Some((link, _)) = read_nsec3(record) {
+            if !hashes.iter().any(|known| known.hash == link.hash) {
+                info!("Found NSEC3 hash {}", link.hash);
+                hashes.push(link);
+            }
+        }
+    }
+}
+
+/// Whether the links collected close the NSEC3 chain: each next hash is a hash collected.
+///
+/// # Argument
+/// * `hashes`: The links collected.
+fn is_closed(hashes: &[HashedName]) -> bool {
+    !hashes.is_empty()
+        && hashes
+            .iter()
+            .all(|link| hashes.iter().any(|known| known.hash == link.next_hash))
+}
+
+/// Follow an NSEC chain from the record of the apex, asking for the NSEC record of each next
+/// name in turn until the chain leads back to the apex.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `server_address`: The address of the server.
+/// * `zone_name`: The name of the zone, normalized.
+/// * `apex`: The next name and types of the NSEC record of the apex.
+/// * `max_queries`: How many queries to send at most, the one for the apex included.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn walk_nsec(
+    socket: &mut dyn DnsTransport,
+    server_address: &str,
+    zone_name: &str,
+    apex: (String, Vec<String>),
+    max_queries: usize,
+    rand_seed: Option<usize>,
+) -> ZoneWalk {
+    let mut names: Vec<WalkedName> = Vec::new();
+    let mut queries = 1;
+    let mut complete = false;
+    let (mut name, mut nsec) = (zone_name.to_owned(), apex);
+    loop {
+        let (next_name, types) = nsec;
+        names.push(WalkedName { name, types });
+        if next_name == zone_name {
+            complete = true;
+            break;
+        }
+        // A chain looping elsewhere than the apex or leaving the zone is broken.
+        if names.iter().any(|walked| walked.name == next_name) || !is_in_zone(&next_name, zone_name)
+        {
+            warn!("The NSEC chain of {} is broken at {}", zone_name, next_name);
+            break;
+        }
+        if queries >= max_queries {
+            break;
+        }
+        queries += 1;
+        let packet = match ask_signed(
+            socket,
+            server_address,
+            &next_name,
+            RecordType::NSEC,
+            rand_seed,
+        ) {
+            Ok(packet) => packet,
+            Err(error) => {
+                warn!(
+                    "Could not ask for the NSEC record of {}: {}",
+                    next_name, error
+                );
+                break;
+            }
+        };
+        let Some(next_nsec) = find_nsec(&packet, &next_name) else {
+            warn!("{} has no NSEC record", next_name);
+            break;
+        };
+        (name, nsec) = (next_name, next_nsec);
+    }
+    ZoneWalk {
+        zone_name: zone_name.to_owned(),
+        chain: WalkedChain::Nsec(names),
+        complete,
+        queries,
+    }
+}
+
+/// Collect the links of an NSEC3 chain from the NSEC3 records proving that random names do not
+/// exist, until the links close the chain. Unlike NSEC records, NSEC3 records cannot be asked
+/// for by name, so each query only reveals the link covering a random hash.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `server_address`: The address of the server.
+/// * `zone_name`: The name of the zone, normalized.
+/// * `apex`: The response to the query for the NSEC record of the apex.
+/// * `max_queries`: How many queries to send at most, the one for the apex included.
+/// * `rand_seed`: The seed for RNG, if desired.
+fn walk_nsec3(
+    socket: &mut dyn DnsTransport,
+    server_address: &str,
+    zone_name: &str,
+    apex: &Packet,
+    max_queries: usize,
+    rand_seed: Option<usize>,
+) -> Result<ZoneWalk, DnsError> {
+    let Some(parameters) = apex
+        .authorities
+        .iter()
+        .filter(|record| record.r_type == RecordType::NSEC3)
+        .find_map(|record| read_nsec3(record).map(|(_, parameters)| parameters))
+    else {
+        return Err(DnsError::ZoneWalk);
+    };
+    let mut hashes: Vec<HashedName> = Vec::new();
+    collect_nsec3(apex, zone_name, &mut hashes);
+    let mut queries = 1;
+    for name in random_names(zone_name, max_queries.saturating_sub(queries), rand_seed) {
+        if is_closed(&hashes) {
+            break;
+        }
+        queries += 1;
+        match ask_signed(socket, server_address, &name, RecordType::A, rand_seed) {
+            Ok(packet) => collect_nsec3(&packet, zone_name, &mut hashes),
+            Err(error) => {
+                warn!("Could not ask for {}: {}", name, error);
+                break;
+            }
+        }
+    }
+    hashes.sort_by(|a, b| a.hash.cmp(&b.hash));
+    Ok(ZoneWalk {
+        zone_name: zone_name.to_owned(),
+        complete: is_closed(&hashes),
+        chain: WalkedChain::Nsec3 { parameters, hashes },
+        queries,
+    })
+}
+
+/// Enumerate the names of a DNSSEC-signed zone by walking its chain of denial-of-existence
+/// records at one of its authoritative servers (RFC 4034, section 4, and RFC 5155). With NSEC,
+/// each record names the next name of the zone, so following the chain lists every name along
+/// with the types it owns. With NSEC3 the names are hashed, so only the hashes are collected.
+/// The walk stops early at a broken chain or after the given number of queries, in which case it
+/// is incomplete.
+///
+/// # Arguments
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `server_address`: The address of an authoritative server of the zone, e.g.
+///   `192.0.2.53:53`.
+/// * `zone_name`: The name of the zone, e.g. `example.com`.
+/// * `max_queries`: How many queries to send at most, e.g. `DEFAULT_MAX_QUERIES`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn walk_zone(
+    socket: &mut dyn DnsTransport,
+    server_address: &str,
+    zone_name: &str,
+    max_queries: usize,
+    rand_seed: Option<usize>,
+) -> Result<ZoneWalk, DnsError> {
+    let zone_name = normalize(zone_name);
+    let packet = ask_signed(
+        socket,
+        server_address,
+        &zone_name,
+        RecordType::NSEC,
+        rand_seed,
+    )?;
+    if let Some(apex) = find_nsec(&packet, &zone_name) {
+        return Ok(walk_nsec(
+            socket,
+            server_address,
+            &zone_name,
+            apex,
+            max_queries,
+            rand_seed,
+        ));
+    }
+    walk_nsec3(
+        socket,
+        server_address,
+        &zone_name,
+        &packet,
+        max_queries,
+        rand_seed,
+    )
+}
+
+/// The address of a name server of a zone to walk it at: the first address found among its NS
+/// records.
+///
+/// # Arguments
+/// * `resolver`: The resolver to resolve the zone and the names of its name servers with.
+/// * `socket`: The socket on which to perform the DNS queries.
+/// * `zone_name`: The name of the zone, e.g. `example.com`.
+/// * `rand_seed`: The seed for RNG, if desired.
+pub fn find_name_server(
+    resolver: &Resolver,
+    socket: &mut dyn DnsTransport,
+    zone_name: &str,
+    rand_seed: Option<usize>,
+) -> Result<SocketAddr, DnsError> {
+    let packet = resolver.resolve(socket, zone_name, RecordType::NS, rand_seed)?;
+    let mut last_error = DnsError::Delegation;
+    for record in packet
+        .answers
+        .iter()
+        .filter(|record| record.r_type == RecordType::NS)
+    {
+        let name_server = normalize(&record.data_to_presentation());
+        match resolver.lookup_ip(socket, &name_server, rand_seed) {
+            Ok(addresses) => {
+                if let Some(address) = addresses.first() {
+                    return Ok(SocketAddr::new(*address, DEFAULT_UDP_PORT));
+                }
+            }
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+/// Validate listing the names of a zone by following its NSEC chain, and stopping after the
+/// given number of queries.
+#[test]
+fn test_walking_nsec_chain() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let nsec = |name: &str, records: &str| {
+        MockResolution::new(name, RecordType::NSEC, 0)
+            .dnssec_ok()
+            .at("192.0.2.53")
+            .answer(records)
+            .build()
+    };
+    let exchanges = vec![
+        nsec(
+            "example.com",
+            "example.com. 3600 IN NSEC mail.example.com. NS SOA RRSIG NSEC DNSKEY",
+        )?,
+        nsec(
+            "mail.example.com",
+            "mail.example.com. 3600 IN NSEC www.example.com. A MX RRSIG NSEC",
+        )?,
+        nsec(
+            "www.example.com",
+            "www.example.com. 3600 IN NSEC example.com. A AAAA RRSIG NSEC",
+        )?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let walk = walk_zone(&mut socket, "192.0.2.53:53", "Example.com.", 100, Some(0))?;
+    assert!(walk.complete);
+    assert_eq!(
+        walk.render(),
+        "example.com NS SOA RRSIG NSEC DNSKEY
+mail.example.com A MX RRSIG NSEC
+www.example.com A AAAA RRSIG NSEC
+example.com: 3 names, chain complete after 3 queries
+"
+    );
+
+    let walk = walk_zone(&mut socket, "192.0.2.53:53", "example.com", 2, Some(0))?;
+    assert!(!walk.complete);
+    let WalkedChain::Nsec(names) = walk.chain else {
+        panic!("Expected an NSEC chain");
+    };
+    assert_eq!(names.len(), 2);
+    assert_eq!(names[1].name, "mail.example.com");
+    Ok(())
+}
+
+/// Validate collecting the hashes of a zone signed with NSEC3 from the denials of random names,
+/// and refusing to walk a zone without NSEC or NSEC3 records.
+#[test]
+fn test_walking_nsec3_chain() -> Result<(), DnsError> {
+    use crate::mock_data::MockResolution;
+    use crate::socket::MockSocket;
+
+    let link = |hash: &str, next_hash: &str, types: &str| {
+        format!(
+            "{}.example.org. 3600 IN NSEC3 1 0 10 AABBCCDD {} {}",
+            hash, next_hash, types
+        )
+    };
+    let apex = link(
+        "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM",
+        "2T7B4G4VSA5SMI47K61MV5BV1A22BOJR",
+        "NS SOA RRSIG DNSKEY",
+    );
+    let names = random_names("example.org", 2, Some(0));
+    let exchanges = vec![
+        MockResolution::new("example.org", RecordType::NSEC, 0)
+            .dnssec_ok()
+            .at("192.0.2.53")
+            .no_data_with(&apex)
+            .build()?,
+        MockResolution::new(&names[0], RecordType::A, 0)
+            .dnssec_ok()
+            .at("192.0.2.53")
+            .name_error_with(&format!(
+                "{}\n{}",
+                apex,
+                link(
+                    "2T7B4G4VSA5SMI47K61MV5BV1A22BOJR",
+                    "35MTHGPGCU1QG68FAB165KLNSNK3DPVL",
+                    "A RRSIG"
+                )
+            ))
+            .build()?,
+        MockResolution::new(&names[1], RecordType::A, 0)
+            .dnssec_ok()
+            .at("192.0.2.53")
+            .name_error_with(&link(
+                "35MTHGPGCU1QG68FAB165KLNSNK3DPVL",
+                "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM",
+                "MX RRSIG",
+            ))
+            .build()?,
+        MockResolution::new("example.net", RecordType::NSEC, 0)
+            .dnssec_ok()
+            .at("192.0.2.53")
+            .answer("")
+            .build()?,
+    ];
+    let mut socket = MockSocket::bind("")?;
+    socket.register_response_data(exchanges.into_iter().flatten());
+
+    let walk = walk_zone(&mut socket, "192.0.2.53:53", "example.org", 100, Some(0))?;
+    assert!(walk.complete);
+    assert_eq!(
+        walk.render(),
+        "0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM -> 2T7B4G4VSA5SMI47K61MV5BV1A22BOJR NS SOA RRSIG DNSKEY
+2T7B4G4VSA5SMI47K61MV5BV1A22BOJR -> 35MTHGPGCU1QG68FAB165KLNSNK3DPVL A RRSIG
+35MTHGPGCU1QG68FAB165KLNSNK3DPVL -> 0P9MHAVEQVM6T7VBL5LOP2U3T2RP3TOM MX RRSIG
+example.org: 3 NSEC3 hashes (algorithm 1, 10 iterations, salt AABBCCDD), chain complete after 3 queries
+"
+    );
+
+    assert_eq!(
+        walk_zone(&mut socket, "192.0.2.53:53", "example.net", 100, Some(0)).err(),
+        Some(DnsError::ZoneWalk)
+    );
+    Ok(())
+}